@@ -0,0 +1,276 @@
+//!
+//! Procedural macros for `async-os`, used through the `async_os` crate's
+//! re-exports
+//!
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{
+    Error, Expr, FnArg, Ident, ItemFn, LitStr, Pat, Path, ReturnType, meta, parse_macro_input,
+    parse_quote, spanned::Spanned,
+};
+
+/// Run `async fn main` as a task, next to the standard ones:
+///
+/// ```ignore
+/// #[async_os::main]
+/// async fn main() {
+///     shell::run_shell(Tty::console()).await;
+/// }
+/// ```
+///
+/// Generates the counting global allocator and a `fn main` that sets up
+/// logging, the filesystem, drivers, services, the watchdog and the trace,
+/// spawns the body as the task `main` and runs the executor. The body can
+/// start more tasks with `task::executor::spawn` or `spawn_local`.
+///
+/// - `executor`: an `async_os::ExecutorKind` expression, evaluated before
+///   anything is set up. `ExecutorKind::Simple` by default.
+#[proc_macro_attribute]
+pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut options = MainOptions::default();
+    let parser = meta::parser(|meta| options.parse(meta));
+    parse_macro_input!(args with parser);
+    let function = parse_macro_input!(item as ItemFn);
+    match expand(options, function) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+#[derive(Default)]
+struct MainOptions {
+    executor: Option<Expr>,
+}
+
+impl MainOptions {
+    fn parse(&mut self, meta: meta::ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("executor") {
+            self.executor = Some(meta.value()?.parse()?);
+        } else {
+            return Err(meta.error("expected `executor`"));
+        }
+        Ok(())
+    }
+}
+
+fn expand(options: MainOptions, function: ItemFn) -> Result<proc_macro2::TokenStream, Error> {
+    let signature = &function.sig;
+    if signature.ident != "main" {
+        return Err(Error::new(
+            signature.ident.span(),
+            "#[async_os::main] goes on `fn main`",
+        ));
+    }
+    if signature.asyncness.is_none() {
+        return Err(Error::new(
+            signature.fn_token.span,
+            "#[async_os::main] needs an `async fn`",
+        ));
+    }
+    if !signature.inputs.is_empty() {
+        return Err(Error::new(
+            signature.inputs.span(),
+            "#[async_os::main] takes no parameters",
+        ));
+    }
+    if !signature.generics.params.is_empty() {
+        return Err(Error::new(
+            signature.generics.span(),
+            "#[async_os::main] can't be generic",
+        ));
+    }
+    if let ReturnType::Type(_, output) = &signature.output {
+        return Err(Error::new(
+            output.span(),
+            "#[async_os::main] returns nothing, the task's output is dropped",
+        ));
+    }
+
+    let executor = options
+        .executor
+        .unwrap_or_else(|| parse_quote!(::async_os::ExecutorKind::Simple));
+    let attributes = &function.attrs;
+    let visibility = &function.vis;
+    let body = &function.block;
+    Ok(quote! {
+        #[global_allocator]
+        static ALLOCATOR: ::async_os::__private::CountingAllocator<::std::alloc::System> =
+            ::async_os::__private::CountingAllocator::new(::std::alloc::System);
+
+        #(#attributes)*
+        #visibility fn main() {
+            let executor = #executor;
+            async fn main() #body
+            ::async_os::__private::start(executor, main)
+        }
+    })
+}
+
+/// Declare a task: the `async fn` becomes a function taking the same
+/// arguments and returning a ready to spawn `Task`:
+///
+/// ```ignore
+/// #[async_os::task(name = "blinker", priority = High)]
+/// async fn blink(port: u16) { ... }
+///
+/// executor.spawn(blink(0x61));
+/// ```
+///
+/// - `name`: what the task is listed as, the function's name by default
+/// - `priority`: a `Priority` variant, `Normal` by default
+/// - `local`: the future isn't `Send`, make it a `LocalTask` for
+///   `spawn_local`
+/// - `static`: keep the future in a static `TaskStorage` instead of the
+///   heap, for no_std. The function then returns `Option<StaticTask>`,
+///   `None` while the previous one runs.
+///
+/// An `async fn` returning `Result<(), E>` is fallible, its error goes to
+/// the `fallible` handler.
+/// - `crate`: where the `task` crate is, found through `async_os` by
+///   default. Kernels depending on `task` directly give `crate = ::task`.
+#[proc_macro_attribute]
+pub fn task(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut options = TaskOptions::default();
+    let parser = meta::parser(|meta| options.parse(meta));
+    parse_macro_input!(args with parser);
+    let function = parse_macro_input!(item as ItemFn);
+    match expand_task(options, function) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+#[derive(Default)]
+struct TaskOptions {
+    name: Option<LitStr>,
+    priority: Option<Ident>,
+    local: bool,
+    in_static: bool,
+    krate: Option<Path>,
+}
+
+impl TaskOptions {
+    fn parse(&mut self, meta: meta::ParseNestedMeta) -> syn::Result<()> {
+        if meta.path.is_ident("name") {
+            self.name = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("priority") {
+            self.priority = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("local") {
+            self.local = true;
+        } else if meta.path.is_ident("static") {
+            self.in_static = true;
+        } else if meta.path.is_ident("crate") {
+            self.krate = Some(meta.value()?.parse()?);
+        } else {
+            return Err(meta.error("expected `name`, `priority`, `local`, `static` or `crate`"));
+        }
+        Ok(())
+    }
+}
+
+fn expand_task(options: TaskOptions, function: ItemFn) -> Result<proc_macro2::TokenStream, Error> {
+    let signature = &function.sig;
+    if signature.asyncness.is_none() {
+        return Err(Error::new(
+            signature.fn_token.span,
+            "#[async_os::task] needs an `async fn`",
+        ));
+    }
+    if !signature.generics.params.is_empty() {
+        return Err(Error::new(
+            signature.generics.span(),
+            "#[async_os::task] can't be generic",
+        ));
+    }
+    if options.local && options.in_static {
+        return Err(Error::new(
+            signature.fn_token.span,
+            "a static task's future is `Send`, it can't be `local`",
+        ));
+    }
+    let fallible = matches!(signature.output, ReturnType::Type(..));
+    if fallible && options.in_static {
+        return Err(Error::new(
+            signature.output.span(),
+            "a static task returns nothing, only heap tasks can be fallible",
+        ));
+    }
+    // Without `mut`, which only matters to the body
+    let mut arguments = Vec::new();
+    let mut parameters = Vec::new();
+    for input in &signature.inputs {
+        let FnArg::Typed(input) = input else {
+            return Err(Error::new(input.span(), "a task can't take `self`"));
+        };
+        match &*input.pat {
+            Pat::Ident(pattern) if pattern.subpat.is_none() && pattern.by_ref.is_none() => {
+                let (ident, ty) = (&pattern.ident, &input.ty);
+                arguments.push(ident);
+                parameters.push(quote!(#ident: #ty));
+            }
+            pattern => {
+                return Err(Error::new(
+                    pattern.span(),
+                    "task arguments need plain names",
+                ));
+            }
+        }
+    }
+
+    let krate = options
+        .krate
+        .unwrap_or_else(|| parse_quote!(::async_os::__private::task));
+    let ident = &signature.ident;
+    let name = options
+        .name
+        .unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+    let priority = options
+        .priority
+        .unwrap_or_else(|| Ident::new("Normal", ident.span()));
+    let attributes = &function.attrs;
+    let visibility = &function.vis;
+    let inputs = &signature.inputs;
+    let output = &signature.output;
+    let body = &function.block;
+
+    let future = quote! {
+        async fn #ident(#inputs) #output #body
+    };
+    let task = if options.local {
+        quote!(#krate::LocalTask)
+    } else {
+        quote!(#krate::Task)
+    };
+    let mut spawned = quote!(#ident(#(#arguments),*));
+    if fallible {
+        spawned = quote!(#krate::fallible::handle_errors(#spawned));
+    }
+    Ok(if options.in_static {
+        quote! {
+            #(#attributes)*
+            #visibility fn #ident(#(#parameters),*) -> ::core::option::Option<#krate::executor::StaticTask> {
+                #future
+                static STORAGE: #krate::executor::TaskStorage<
+                    { #krate::executor::future_size(&#ident) },
+                > = #krate::executor::TaskStorage::new();
+                STORAGE.spawn(#ident(#(#arguments),*)).map(|task| {
+                    task.with_name(#name)
+                        .with_priority(#krate::priority::Priority::#priority)
+                })
+            }
+        }
+    } else {
+        quote! {
+            #(#attributes)*
+            #visibility fn #ident(#(#parameters),*) -> #task {
+                #future
+                #task::with_priority(
+                    #spawned,
+                    #krate::priority::Priority::#priority,
+                )
+                .with_name(#name)
+            }
+        }
+    })
+}
@@ -0,0 +1,141 @@
+//!
+//! The standard setup of a hosted async-os program, behind
+//! [`#[async_os::main]`](main)
+//!
+
+use std::{fmt, str::FromStr, thread, time::Duration};
+
+pub use async_os_macros::{main, task};
+
+use task::{
+    LocalTask, Task,
+    config::Config,
+    drivers,
+    executor::{self, Executor, SimpleExecutor, ThreadPool},
+    fs::{self, RamFs},
+    keyboard::{self, KeyboardDriver},
+    log,
+    net::{self, Loopback, NetDriver},
+    panic, rng,
+    services::{self, console::ConsoleService, fs::FsService, net::NetService},
+    trace, watchdog,
+};
+
+/// How long a task may block the executor before the watchdog names it
+pub const WATCHDOG_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Which executor `#[async_os::main]` runs the tasks on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExecutorKind {
+    /// [`SimpleExecutor`], polling every task in turn
+    #[default]
+    Simple,
+    /// [`Executor`], by priority and only the woken ones
+    Priority,
+    /// [`ThreadPool`] with that many workers
+    Pool(usize),
+}
+
+/// `simple`, `priority`, `pool` with a worker per CPU or `pool:WORKERS`
+impl FromStr for ExecutorKind {
+    type Err = UnknownExecutor;
+
+    fn from_str(name: &str) -> Result<Self, UnknownExecutor> {
+        match name.split_once(':') {
+            None if name == "simple" => Ok(ExecutorKind::Simple),
+            None if name == "priority" => Ok(ExecutorKind::Priority),
+            None if name == "pool" => Ok(ExecutorKind::Pool(
+                thread::available_parallelism().map_or(1, |workers| workers.get()),
+            )),
+            Some(("pool", workers)) => match workers.parse() {
+                Ok(workers) if workers > 0 => Ok(ExecutorKind::Pool(workers)),
+                _ => Err(UnknownExecutor),
+            },
+            _ => Err(UnknownExecutor),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownExecutor;
+
+impl fmt::Display for UnknownExecutor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("expected `simple`, `priority`, `pool` or `pool:WORKERS`")
+    }
+}
+
+/// What the macro expands to, not for use otherwise
+#[doc(hidden)]
+pub mod __private {
+    pub use ::task;
+    pub use task::memory::CountingAllocator;
+
+    use super::*;
+
+    /// Set up, spawn `main` as the task "main" and run `executor`.
+    ///
+    /// Takes the `async fn` rather than its future, which needs not be
+    /// `Send`: a pool worker calls it and keeps the task.
+    pub fn start<F: Future<Output = ()> + 'static>(
+        executor: ExecutorKind,
+        main: impl FnOnce() -> F + Send + 'static,
+    ) {
+        // Panics name the task that was running
+        panic::install_hook();
+        // Capacities, layout, log levels and addresses, before anything uses them
+        let config: Config = include_str!("../async-os.conf")
+            .parse()
+            .unwrap_or_else(|err| panic!("async-os.conf: {}", err));
+        config.apply();
+        log::add_sink(log::ConsoleSink);
+        fs::mount("/", RamFs::new()).expect("nothing is mounted yet");
+
+        // Running hosted, so keys come from the terminal instead of an interrupt
+        keyboard::set_keymap_source(|| Some(include_str!("../keymap.conf").into()));
+        drivers::register(KeyboardDriver::new(keyboard::TerminalSource));
+        // No network card on the host, sockets still work on 127.0.0.1
+        drivers::register(NetDriver::new(
+            Loopback::new(),
+            config.net(net::Config::ip()),
+        ));
+
+        // A task blocking the executor for this long gets named on stderr
+        watchdog::enable(WATCHDOG_TIMEOUT, watchdog::report);
+        watchdog::spawn_checker();
+        // The last scheduler events go into panic reports and `trace`
+        trace::enable();
+
+        // Spawns the rest from inside, on whichever thread polls it
+        let boot = Task::new(async move {
+            executor::spawn(Task::new(log::run_logger()).with_name("logger"));
+            executor::spawn(Task::new(rng::run_reseeder()).with_name("rng"));
+            executor::spawn_local(services::serve(ConsoleService));
+            executor::spawn_local(services::serve(FsService));
+            executor::spawn_local(services::serve(NetService));
+            executor::spawn_local(LocalTask::new(drivers::run()).with_name("drivers"));
+            executor::spawn_local(LocalTask::new(main()).with_name("main"));
+        })
+        .with_name("boot");
+        let run = match executor {
+            ExecutorKind::Simple => {
+                let mut executor = SimpleExecutor::new();
+                executor.spawn(boot);
+                executor.run()
+            }
+            ExecutorKind::Priority => {
+                let mut executor = Executor::new();
+                executor.spawn(boot);
+                executor.run()
+            }
+            ExecutorKind::Pool(workers) => {
+                let mut pool = ThreadPool::new(workers);
+                pool.spawn(boot);
+                pool.run()
+            }
+        };
+        if let Err(err) = run {
+            panic!("{}", err);
+        }
+    }
+}
@@ -2,7 +2,18 @@
 //! Async tutorial entry point
 //!
 
-use task::{self, Task, executor::SimpleExecutor, keyboard};
+mod snake;
+
+use std::{process, sync::OnceLock};
+
+use async_os::ExecutorKind;
+use task::{
+    capability::{Capabilities, Capability},
+    demo, log,
+    net::{self, http},
+    services, shell,
+    tty::{Tty, TtyMode},
+};
 
 // #![allow(dead_code)]
 
@@ -10,14 +21,141 @@ async fn async_number() -> u32 {
     42
 }
 
+/// Runs with nothing but the console, see `main`
+#[async_os::task(name = "example")]
 async fn example_task() {
     let number = async_number().await;
-    println!("async number: {}", number);
+    if let Err(err) = services::console::write(format!("async number: {}\n", number)).await {
+        log::warn!("example: {}", err);
+    }
+    // Outside what the task was given
+    if let Err(err) = services::fs::read("/etc/motd").await {
+        log::info!("example: reading /etc/motd: {}", err);
+    }
+}
+
+fn console_tty() -> Tty {
+    let mut tty = Tty::console();
+    // The host terminal echoes already
+    tty.set_mode(TtyMode {
+        echo: false,
+        ..TtyMode::COOKED
+    });
+    tty
+}
+
+/// `fetch http://localhost/` from the shell shows it, `/metrics` is for
+/// Prometheus
+#[async_os::task(name = "httpd", local)]
+async fn status_server() -> net::Result<()> {
+    let router = http::Router::new()
+        .route("/", http::status_page)
+        .route("/metrics", http::metrics_page);
+    http::serve(80, router).await
+}
+
+/// `telnet localhost` gets a shell next to the console one
+#[async_os::task(name = "telnetd", local)]
+async fn telnet_server() -> net::Result<()> {
+    shell::serve_telnet(23).await
+}
+
+/// What the binary adds to the crate's own demos
+fn register_demos() {
+    demo::register(
+        "example",
+        "writes to the console, with nothing else allowed",
+        || {
+            example_task()
+                .with_capabilities(Capabilities::none().with(Capability::ConsoleWrite))
+                .into()
+        },
+    );
+    demo::register("httpd", "status page on port 80", status_server);
+    demo::register("telnetd", "shell over telnet on port 23", telnet_server);
+    demo::register("snake", "the game, on the console", snake::snake);
+}
+
+/// Not a demo, the body of `main` runs it
+const SHELL: &str = "shell";
+
+/// Without any demos named
+const DEFAULT_DEMOS: &[&str] = &["example", "httpd", "telnetd", SHELL];
+
+struct Args {
+    executor: ExecutorKind,
+    demos: Vec<String>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut parsed = Args {
+            executor: ExecutorKind::default(),
+            demos: Vec::new(),
+        };
+        while let Some(arg) = args.next() {
+            let executor = match arg.strip_prefix("--executor") {
+                Some("") => args.next(),
+                Some(value) => value.strip_prefix('=').map(String::from),
+                None => None,
+            };
+            if let Some(executor) = executor {
+                parsed.executor = executor
+                    .parse()
+                    .map_err(|err| format!("--executor: {}", err))?;
+            } else if arg.starts_with('-') {
+                return Err(format!("unknown option `{}`", arg));
+            } else if arg == SHELL || demo::find(&arg).is_some() {
+                parsed.demos.push(arg);
+            } else {
+                return Err(format!("no demo `{}`", arg));
+            }
+        }
+        if parsed.demos.is_empty() {
+            parsed.demos = DEFAULT_DEMOS.iter().map(|name| name.to_string()).collect();
+        }
+        Ok(parsed)
+    }
+}
+
+fn usage() -> String {
+    let mut usage = String::from(
+        "usage: async-os [--executor simple|priority|pool|pool:WORKERS] [DEMO...]\n\ndemos:\n",
+    );
+    for demo in demo::demos() {
+        usage += &format!("  {:<10} {}\n", demo.name, demo.description);
+    }
+    usage += &format!("  {:<10} {}\n", SHELL, "the shell on the console");
+    usage += "\nwithout any, example, httpd, telnetd and shell\n";
+    usage
+}
+
+/// The command line, exits on `--help` or a mistake
+fn args() -> &'static Args {
+    static ARGS: OnceLock<Args> = OnceLock::new();
+    ARGS.get_or_init(|| {
+        register_demos();
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+            print!("{}", usage());
+            process::exit(0);
+        }
+        Args::parse(args.into_iter()).unwrap_or_else(|err| {
+            eprint!("async-os: {}\n\n{}", err, usage());
+            process::exit(2);
+        })
+    })
 }
 
-fn main() {
-    let mut executor = SimpleExecutor::new();
-    executor.spawn(Task::new(example_task()));
-    executor.spawn(Task::new(keyboard::print_keypresses()));
-    executor.run();
+/// Runs next to the standard tasks, see `async_os::main`
+#[async_os::main(executor = args().executor)]
+async fn main() {
+    let demos = &args().demos;
+    for name in demos.iter().filter(|name| *name != SHELL) {
+        // Checked while parsing the arguments
+        demo::run(name).expect("a registered demo");
+    }
+    if demos.iter().any(|name| name == SHELL) {
+        shell::run_shell(console_tty()).await;
+    }
 }
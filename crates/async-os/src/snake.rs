@@ -0,0 +1,181 @@
+//!
+//! Snake on the console
+//!
+//! Built from the crate's public pieces only: a timer interval moves the
+//! snake, key events steer it, the two merged into one stream, and the
+//! console draws the board with ANSI escapes.
+//!
+
+use std::{collections::VecDeque, time::Duration};
+
+use futures_util::StreamExt;
+use pc_keyboard::{KeyCode, KeyEvent, KeyState};
+use task::{console, future::Either, keyboard::KeyEventStream, rng, stream, timer};
+
+const WIDTH: i16 = 30;
+const HEIGHT: i16 = 15;
+
+/// Time between moves
+const TICK: Duration = Duration::from_millis(150);
+
+/// Home the cursor and clear the screen
+const CLEAR: &str = "\x1b[H\x1b[2J";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    /// Arrows on the PC keyboard, WASD in a terminal that has no scancodes
+    /// for them
+    fn from_key(code: KeyCode) -> Option<Direction> {
+        match code {
+            KeyCode::ArrowUp | KeyCode::W => Some(Direction::Up),
+            KeyCode::ArrowDown | KeyCode::S => Some(Direction::Down),
+            KeyCode::ArrowLeft | KeyCode::A => Some(Direction::Left),
+            KeyCode::ArrowRight | KeyCode::D => Some(Direction::Right),
+            _ => None,
+        }
+    }
+
+    fn opposite(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+}
+
+type Cell = (i16, i16);
+
+struct Game {
+    // Head first
+    snake: VecDeque<Cell>,
+    direction: Direction,
+    // Where the next tick goes, several keys within one tick can't turn
+    // the snake back onto itself
+    turn: Direction,
+    food: Cell,
+    score: u32,
+}
+
+impl Game {
+    fn new() -> Game {
+        let (x, y) = (WIDTH / 2, HEIGHT / 2);
+        let mut game = Game {
+            snake: VecDeque::from([(x, y), (x - 1, y), (x - 2, y)]),
+            direction: Direction::Right,
+            turn: Direction::Right,
+            food: (0, 0),
+            score: 0,
+        };
+        game.place_food();
+        game
+    }
+
+    fn steer(&mut self, direction: Direction) {
+        if direction != self.direction.opposite() {
+            self.turn = direction;
+        }
+    }
+
+    /// Move one cell, `false` if the snake ran into a wall or itself
+    fn step(&mut self) -> bool {
+        self.direction = self.turn;
+        let (x, y) = self.snake[0];
+        let head = match self.direction {
+            Direction::Up => (x, y - 1),
+            Direction::Down => (x, y + 1),
+            Direction::Left => (x - 1, y),
+            Direction::Right => (x + 1, y),
+        };
+        let eating = head == self.food;
+        if !eating {
+            self.snake.pop_back();
+        }
+        let (x, y) = head;
+        if !(0..WIDTH).contains(&x) || !(0..HEIGHT).contains(&y) || self.snake.contains(&head) {
+            return false;
+        }
+        self.snake.push_front(head);
+        if eating {
+            self.score += 1;
+            self.place_food();
+        }
+        true
+    }
+
+    fn place_food(&mut self) {
+        let cells = (WIDTH * HEIGHT) as u64;
+        if self.snake.len() as u64 == cells {
+            // Won, the food stays under the snake
+            return;
+        }
+        loop {
+            // Unseeded only this early, any cell does then
+            let cell = rng::try_next_u64().unwrap_or(u64::from(self.score) * 7919) % cells;
+            let food = ((cell % WIDTH as u64) as i16, (cell / WIDTH as u64) as i16);
+            if !self.snake.contains(&food) {
+                self.food = food;
+                return;
+            }
+        }
+    }
+
+    /// The board with its frame, drawn over the last one
+    fn render(&self) -> String {
+        let border = format!("+{}+\n", "-".repeat(WIDTH as usize));
+        let mut text = format!("\x1b[H{}", border);
+        for y in 0..HEIGHT {
+            text.push('|');
+            for x in 0..WIDTH {
+                text.push(match (x, y) {
+                    cell if cell == self.snake[0] => '@',
+                    cell if self.snake.contains(&cell) => 'o',
+                    cell if cell == self.food => '*',
+                    _ => ' ',
+                });
+            }
+            text.push_str("|\n");
+        }
+        text + &border + &format!("score {}  arrows/WASD steer, Esc quits\n", self.score)
+    }
+}
+
+/// Play until the snake crashes or Esc is pressed
+#[async_os::task(name = "snake", local)]
+pub async fn snake() {
+    let mut game = Game::new();
+    let mut input = stream::merge(KeyEventStream::new(), timer::interval(TICK));
+    console::write_str(CLEAR).await;
+    console::write_string(game.render()).await;
+    while let Some(input) = input.next().await {
+        match input {
+            Either::Left(KeyEvent {
+                code,
+                state: KeyState::Down,
+            }) => {
+                if code == KeyCode::Escape {
+                    break;
+                }
+                if let Some(direction) = Direction::from_key(code) {
+                    game.steer(direction);
+                }
+            }
+            Either::Left(_) => {}
+            Either::Right(_) => {
+                if !game.step() {
+                    break;
+                }
+                console::write_string(game.render()).await;
+            }
+        }
+    }
+    console::println!("game over, score {}", game.score).await;
+}
@@ -0,0 +1,124 @@
+//!
+//! Minimal actors on top of tasks and channels
+//!
+//! An actor owns its state and handles one message at a time in its own
+//! mailbox task, so no locking is needed around the state. Other tasks talk
+//! to it through an [`Addr`].
+//!
+
+use core::future::Future;
+
+use crate::{
+    LocalTask,
+    channel::{SendError, TrySendError, mpmc},
+    executor::Executor,
+    future::{Either, race},
+    sync::CancellationToken,
+};
+
+/// Default number of messages queued before `Addr::send` waits
+pub const MAILBOX_CAPACITY: usize = 32;
+
+pub trait Actor: Sized + 'static {
+    type Message: 'static;
+
+    fn handle(&mut self, message: Self::Message) -> impl Future<Output = ()>;
+
+    /// Runs in the mailbox task before the first message
+    fn started(&mut self) -> impl Future<Output = ()> {
+        async {}
+    }
+
+    /// Runs once the mailbox closes or the actor is stopped
+    fn stopped(&mut self) -> impl Future<Output = ()> {
+        async {}
+    }
+}
+
+/// Handle for sending messages to an actor, cheap to clone
+pub struct Addr<A: Actor> {
+    mailbox: mpmc::Sender<A::Message>,
+    stop: CancellationToken,
+    done: CancellationToken,
+}
+
+impl<A: Actor> Clone for Addr<A> {
+    fn clone(&self) -> Self {
+        Addr {
+            mailbox: self.mailbox.clone(),
+            stop: self.stop.clone(),
+            done: self.done.clone(),
+        }
+    }
+}
+
+impl<A: Actor> Addr<A> {
+    /// Waits while the mailbox is full, fails once the actor has stopped
+    pub async fn send(&self, message: A::Message) -> Result<(), SendError<A::Message>> {
+        self.mailbox.send(message).await
+    }
+
+    pub fn try_send(&self, message: A::Message) -> Result<(), TrySendError<A::Message>> {
+        self.mailbox.try_send(message)
+    }
+
+    /// Ask the actor to stop after the message it is currently handling
+    pub fn stop(&self) {
+        self.stop.cancel();
+    }
+
+    pub fn is_alive(&self) -> bool {
+        !self.done.is_cancelled()
+    }
+
+    /// Resolves once the mailbox task has finished, for whatever reason
+    pub async fn stopped(&self) {
+        self.done.cancelled().await
+    }
+}
+
+/// Create the actor's mailbox task without spawning it
+pub fn start<A: Actor>(actor: A, capacity: usize) -> (Addr<A>, LocalTask) {
+    start_with_token(actor, capacity, CancellationToken::new())
+}
+
+/// Like [`start`], stopping the actor when `parent` is cancelled
+pub fn start_with_token<A: Actor>(
+    actor: A,
+    capacity: usize,
+    parent: CancellationToken,
+) -> (Addr<A>, LocalTask) {
+    let (tx, rx) = mpmc::channel(capacity);
+    let stop = parent.child_token();
+    let done = CancellationToken::new();
+    let addr = Addr {
+        mailbox: tx,
+        stop: stop.clone(),
+        done: done.clone(),
+    };
+    let task = LocalTask::new(mailbox(actor, rx, stop, done));
+    (addr, task)
+}
+
+pub fn spawn<A: Actor>(executor: &mut Executor, actor: A) -> Addr<A> {
+    let (addr, task) = start(actor, MAILBOX_CAPACITY);
+    executor.spawn_local(task);
+    addr
+}
+
+async fn mailbox<A: Actor>(
+    mut actor: A,
+    mut rx: mpmc::Receiver<A::Message>,
+    stop: CancellationToken,
+    done: CancellationToken,
+) {
+    // Marks the actor dead however the task ends, even if handle() panics
+    let _done = done.drop_guard();
+
+    actor.started().await;
+    // Ends once every Addr is dropped or stop() is called
+    while let Either::Left(Some(message)) = race(rx.recv(), stop.cancelled()).await {
+        actor.handle(message).await;
+    }
+    actor.stopped().await;
+}
@@ -0,0 +1,152 @@
+//!
+//! Batched requests
+//!
+//! After io_uring: rather than one request at a time, a task queues up
+//! several in a [`Batch`], submits them together and takes the results as
+//! a stream of [`Completion`]s, in whatever order they finish. The driver
+//! gets to hand the whole batch to its device with one doorbell, and one
+//! interrupt can complete many requests.
+//!
+//! ```ignore
+//! let mut batch = Batch::new();
+//! for (tag, &block) in wanted.iter().enumerate() {
+//!     batch.push(tag as u64, BlockRequest::Read { start: block, count: 1 });
+//! }
+//! let mut completions = device.submit(batch).await;
+//! while let Some(Completion { tag, result }) = completions.next().await {
+//!     blocks[tag as usize] = result?;
+//! }
+//! ```
+//!
+//! [`BlockDevice::submit`](crate::block::BlockDevice::submit) takes
+//! batches of block requests.
+//!
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::{Stream, stream::FuturesUnordered};
+
+/// Requests to submit together, each with a tag of the submitter's
+/// choosing that comes back with its [`Completion`]
+pub struct Batch<R> {
+    entries: Vec<(u64, R)>,
+}
+
+impl<R> Batch<R> {
+    pub fn new() -> Self {
+        Batch {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, tag: u64, request: R) {
+        self.entries.push((tag, request));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<R> Default for Batch<R> {
+    fn default() -> Self {
+        Batch::new()
+    }
+}
+
+impl<R> IntoIterator for Batch<R> {
+    type Item = (u64, R);
+    type IntoIter = alloc::vec::IntoIter<(u64, R)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<R> FromIterator<(u64, R)> for Batch<R> {
+    fn from_iter<I: IntoIterator<Item = (u64, R)>>(entries: I) -> Self {
+        Batch {
+            entries: entries.into_iter().collect(),
+        }
+    }
+}
+
+/// The result of one request, with the tag it was submitted with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion<T> {
+    pub tag: u64,
+    pub result: T,
+}
+
+/// One submitted request, for drivers building [`Completions`]
+pub type CompletionFuture<'a, T> = Pin<Box<dyn Future<Output = Completion<T>> + Send + 'a>>;
+
+/// The requests of a submitted batch, a stream yielding each as it
+/// completes. It ends after the last one. Dropping it drops the requests
+/// still in flight, which the driver may or may not have carried out.
+pub struct Completions<'a, T> {
+    pending: FuturesUnordered<CompletionFuture<'a, T>>,
+}
+
+impl<'a, T> Completions<'a, T> {
+    pub fn new() -> Self {
+        Completions {
+            pending: FuturesUnordered::new(),
+        }
+    }
+
+    /// Add a request the driver submitted
+    pub fn push(&mut self, request: CompletionFuture<'a, T>) {
+        self.pending.push(request);
+    }
+
+    /// Add a request that's done already, one rejected before reaching
+    /// the device for instance
+    pub fn push_ready(&mut self, tag: u64, result: T)
+    where
+        T: Send + 'a,
+    {
+        self.pending
+            .push(Box::pin(async move { Completion { tag, result } }));
+    }
+
+    /// Requests not completed yet
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+impl<T> Default for Completions<'_, T> {
+    fn default() -> Self {
+        Completions::new()
+    }
+}
+
+impl<'a, T> FromIterator<CompletionFuture<'a, T>> for Completions<'a, T> {
+    fn from_iter<I: IntoIterator<Item = CompletionFuture<'a, T>>>(requests: I) -> Self {
+        Completions {
+            pending: requests.into_iter().collect(),
+        }
+    }
+}
+
+impl<T> Stream for Completions<'_, T> {
+    type Item = Completion<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Completion<T>>> {
+        Pin::new(&mut self.pending).poll_next(cx)
+    }
+}
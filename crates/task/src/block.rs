@@ -0,0 +1,171 @@
+//!
+//! Block devices
+//!
+//! Disks are read and written in fixed-size blocks rather than bytes. A
+//! [`BlockDevice`] is what a filesystem needs from one, whatever the
+//! hardware behind it, a ramdisk, an ATA or a VirtIO disk:
+//!
+//! ```ignore
+//! let mut buf = vec![0; device.block_size()];
+//! device.read_blocks(0, &mut buf).await?;
+//! buf[510..512].copy_from_slice(&[0x55, 0xAA]);
+//! device.write_blocks(0, &buf).await?;
+//! device.flush().await?;
+//! ```
+//!
+//! Writes may sit in a device's own cache until [`BlockDevice::flush`].
+//! A [`BlockCache`] in front of a device keeps blocks in memory for the
+//! filesystem above, reads and writes hit the disk far less often.
+//!
+//! [`BlockDevice::read_bytes`] and [`BlockDevice::write_bytes`] move
+//! [`Bytes`] instead: a VirtIO disk reads into and writes from them
+//! directly, and the cache keeps the blocks of one read in one allocation.
+//! [`BlockDevice::submit`] takes a [`Batch`] of requests at once.
+//!
+
+mod cache;
+mod ramdisk;
+mod virtio;
+
+use std::{fmt, future::Future, pin::Pin};
+
+use crate::{
+    batch::{Batch, Completion, CompletionFuture, Completions},
+    bytes::{Bytes, BytesMut},
+};
+
+pub use cache::{BlockCache, DEFAULT_READ_AHEAD, run_flusher};
+pub use ramdisk::{RamDisk, SECTOR_SIZE};
+pub use virtio::VirtioBlock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// Blocks past the end of the device
+    OutOfRange,
+    /// A buffer that isn't a whole number of blocks
+    Misaligned,
+    /// Writing a device that can only be read
+    ReadOnly,
+    /// The device reported a failure
+    Device,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::OutOfRange => f.write_str("block out of range"),
+            Error::Misaligned => f.write_str("buffer isn't a whole number of blocks"),
+            Error::ReadOnly => f.write_str("read-only device"),
+            Error::Device => f.write_str("device error"),
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Future returned by the [`BlockDevice`] methods
+pub type BlockFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// One request of a [`Batch`] for [`BlockDevice::submit`]
+#[derive(Debug, Clone)]
+pub enum BlockRequest {
+    Read { start: u64, count: u64 },
+    Write { start: u64, bytes: Bytes },
+    Flush,
+}
+
+/// Requests of a batch as they complete, with the bytes read, empty for
+/// writes and flushes
+pub type BlockCompletions<'a> = Completions<'a, Result<Bytes>>;
+
+/// Future returned by [`BlockDevice::submit`]
+pub type SubmitFuture<'a> = Pin<Box<dyn Future<Output = BlockCompletions<'a>> + Send + 'a>>;
+
+/// A disk or anything else addressed in blocks. Shared between tasks, so
+/// the methods take `&self`.
+pub trait BlockDevice: Send + Sync {
+    /// Bytes in a block
+    fn block_size(&self) -> usize;
+
+    fn block_count(&self) -> u64;
+
+    /// Bytes on the device
+    fn capacity(&self) -> u64 {
+        self.block_count() * self.block_size() as u64
+    }
+
+    fn read_only(&self) -> bool {
+        false
+    }
+
+    /// Fill `buf` from consecutive blocks starting at `start`
+    fn read_blocks<'a>(&'a self, start: u64, buf: &'a mut [u8]) -> BlockFuture<'a, ()>;
+
+    /// Write `bytes` to consecutive blocks starting at `start`
+    fn write_blocks<'a>(&'a self, start: u64, bytes: &'a [u8]) -> BlockFuture<'a, ()>;
+
+    /// `count` blocks from `start` in a buffer of their own. Devices that
+    /// can fill one directly hand it over without a copy.
+    fn read_bytes(&self, start: u64, count: u64) -> BlockFuture<'_, Bytes> {
+        Box::pin(async move {
+            let len = usize::try_from(count)
+                .ok()
+                .and_then(|count| count.checked_mul(self.block_size()))
+                .ok_or(Error::OutOfRange)?;
+            let mut buf = BytesMut::zeroed(len);
+            self.read_blocks(start, &mut buf).await?;
+            Ok(buf.freeze())
+        })
+    }
+
+    /// Like [`write_blocks`](BlockDevice::write_blocks), for devices and
+    /// caches that keep `bytes` instead of copying them
+    fn write_bytes(&self, start: u64, bytes: Bytes) -> BlockFuture<'_, ()> {
+        Box::pin(async move { self.write_blocks(start, &bytes).await })
+    }
+
+    /// Wait until everything written is stored for good
+    fn flush(&self) -> BlockFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Start every request in `batch`, ready once the device has them all.
+    /// They run in no particular order: a flush doesn't wait for the writes
+    /// of its own batch. By default they're the other methods run at once,
+    /// devices with a queue put the whole batch in it before telling the
+    /// device.
+    fn submit(&self, batch: Batch<BlockRequest>) -> SubmitFuture<'_> {
+        let completions = batch
+            .into_iter()
+            .map(|(tag, request)| -> CompletionFuture<'_, Result<Bytes>> {
+                Box::pin(async move {
+                    let result = match request {
+                        BlockRequest::Read { start, count } => self.read_bytes(start, count).await,
+                        BlockRequest::Write { start, bytes } => {
+                            self.write_bytes(start, bytes).await.map(|()| Bytes::new())
+                        }
+                        BlockRequest::Flush => self.flush().await.map(|()| Bytes::new()),
+                    };
+                    Completion { tag, result }
+                })
+            })
+            .collect();
+        Box::pin(async move { completions })
+    }
+}
+
+/// Check a request for `len` bytes from block `start` against `device`,
+/// for implementations to call first
+pub fn check_range(device: &dyn BlockDevice, start: u64, len: usize) -> Result<()> {
+    let block_size = device.block_size();
+    if !len.is_multiple_of(block_size) {
+        return Err(Error::Misaligned);
+    }
+    let end = start
+        .checked_add((len / block_size) as u64)
+        .ok_or(Error::OutOfRange)?;
+    if end > device.block_count() {
+        return Err(Error::OutOfRange);
+    }
+    Ok(())
+}
@@ -0,0 +1,486 @@
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use futures_util::StreamExt;
+
+use super::{BlockDevice, BlockFuture, BlockRequest, Error, Result, check_range};
+use crate::{
+    batch::{Batch, Completion},
+    bytes::{Bytes, BytesMut},
+    log,
+    metrics::Counter,
+    timer,
+};
+
+/// Blocks read past a miss by [`BlockCache::new`], files are mostly read in
+/// order
+pub const DEFAULT_READ_AHEAD: u64 = 8;
+
+static HITS: Counter = Counter::new("block_cache_hits_total", "Blocks read from a block cache");
+static MISSES: Counter = Counter::new(
+    "block_cache_misses_total",
+    "Blocks a block cache had to read from its device",
+);
+static EVICTIONS: Counter = Counter::new(
+    "block_cache_evictions_total",
+    "Blocks dropped from a block cache to make room",
+);
+static WRITE_BACKS: Counter = Counter::new(
+    "block_cache_write_backs_total",
+    "Dirty blocks a block cache wrote to its device",
+);
+
+/// Keeps recently used blocks of a device in memory and holds writes back
+/// until [`BlockDevice::flush`], so filesystems can read the same metadata
+/// blocks over and over without going to the disk.
+///
+/// It's a [`BlockDevice`] itself, put it between a disk and a filesystem.
+/// The least recently used clean blocks make room for new ones, dirty
+/// blocks are written back first when there's nothing clean to drop.
+/// [`run_flusher`] bounds how long a write can sit in memory.
+///
+/// Blocks are [`Bytes`]: the ones read together share an allocation, which
+/// is freed once the last of them is dropped, and a block on its own is
+/// handed out by [`BlockDevice::read_bytes`] without a copy.
+pub struct BlockCache {
+    device: Arc<dyn BlockDevice>,
+    capacity: usize,
+    read_ahead: u64,
+    state: spin::Mutex<State>,
+}
+
+struct State {
+    blocks: BTreeMap<u64, Entry>,
+    // Block numbers by last use, oldest first
+    recent: BTreeMap<u64, u64>,
+    clock: u64,
+}
+
+struct Entry {
+    data: Bytes,
+    dirty: bool,
+    // The clock at the last write, so a write back only cleans what it
+    // wrote. Never reused, not even for a block evicted and written again.
+    version: u64,
+    used: u64,
+}
+
+impl State {
+    fn touch(&mut self, block: u64) {
+        self.clock += 1;
+        let clock = self.clock;
+        if let Some(entry) = self.blocks.get_mut(&block) {
+            self.recent.remove(&entry.used);
+            entry.used = clock;
+            self.recent.insert(clock, block);
+        }
+    }
+
+    fn insert(&mut self, block: u64, data: Bytes, dirty: bool) {
+        self.clock += 1;
+        let used = self.clock;
+        if let Some(old) = self.blocks.remove(&block) {
+            self.recent.remove(&old.used);
+        }
+        self.blocks.insert(
+            block,
+            Entry {
+                data,
+                dirty,
+                version: used,
+                used,
+            },
+        );
+        self.recent.insert(used, block);
+    }
+
+    /// Drop clean blocks, oldest first, until at most `capacity` are left.
+    /// `false` if that takes dropping dirty ones.
+    fn evict(&mut self, capacity: usize) -> bool {
+        while self.blocks.len() > capacity {
+            let oldest_clean = self
+                .recent
+                .iter()
+                .find(|(_, block)| !self.blocks[block].dirty)
+                .map(|(&used, &block)| (used, block));
+            let Some((used, block)) = oldest_clean else {
+                return false;
+            };
+            self.recent.remove(&used);
+            self.blocks.remove(&block);
+            EVICTIONS.inc();
+        }
+        true
+    }
+}
+
+impl BlockCache {
+    /// Cache up to `capacity` blocks of `device`
+    pub fn new(device: Arc<dyn BlockDevice>, capacity: usize) -> Self {
+        BlockCache::with_read_ahead(device, capacity, DEFAULT_READ_AHEAD)
+    }
+
+    /// Like [`BlockCache::new`], reading `read_ahead` blocks more than
+    /// asked for on a miss
+    pub fn with_read_ahead(device: Arc<dyn BlockDevice>, capacity: usize, read_ahead: u64) -> Self {
+        assert!(capacity > 0, "a cache needs room for a block");
+        BlockCache {
+            device,
+            capacity,
+            read_ahead,
+            state: spin::Mutex::new(State {
+                blocks: BTreeMap::new(),
+                recent: BTreeMap::new(),
+                clock: 0,
+            }),
+        }
+    }
+
+    pub fn device(&self) -> &Arc<dyn BlockDevice> {
+        &self.device
+    }
+
+    /// Blocks written but not on the device yet
+    pub fn dirty_blocks(&self) -> usize {
+        let state = self.state.lock();
+        state.blocks.values().filter(|entry| entry.dirty).count()
+    }
+
+    /// Forget every clean block, for when the device changed underneath
+    pub fn invalidate(&self) {
+        let mut state = self.state.lock();
+        let State { blocks, recent, .. } = &mut *state;
+        blocks.retain(|_, entry| {
+            if !entry.dirty {
+                recent.remove(&entry.used);
+            }
+            entry.dirty
+        });
+    }
+
+    /// Drop every clean block to free memory, the bytes freed. Gives up if
+    /// the cache is busy, so it can be a [`memory::add_reclaimer`] hook.
+    ///
+    /// [`memory::add_reclaimer`]: crate::memory::add_reclaimer
+    pub fn shrink(&self) -> usize {
+        let Some(mut state) = self.state.try_lock() else {
+            return 0;
+        };
+        let State { blocks, recent, .. } = &mut *state;
+        let mut freed = 0;
+        blocks.retain(|_, entry| {
+            if !entry.dirty {
+                recent.remove(&entry.used);
+                freed += entry.data.len();
+            }
+            entry.dirty
+        });
+        freed
+    }
+
+    /// Write every dirty block to the device, without flushing the device
+    /// itself. Runs of neighbouring blocks go out in one request, and the
+    /// requests in one [`Batch`]. The first error comes back once every
+    /// request is done, blocks written fine are clean by then.
+    pub async fn write_back(&self) -> Result<()> {
+        let dirty: Vec<(u64, Bytes, u64)> = {
+            let state = self.state.lock();
+            state
+                .blocks
+                .iter()
+                .filter(|(_, entry)| entry.dirty)
+                .map(|(&block, entry)| (block, entry.data.clone(), entry.version))
+                .collect()
+        };
+        // Each run of `dirty` as its start and end, tagged by position
+        let mut runs = Vec::new();
+        let mut batch = Batch::new();
+        let mut at = 0;
+        while at < dirty.len() {
+            let start = dirty[at].0;
+            let mut end = at + 1;
+            while end < dirty.len() && dirty[end].0 == start + (end - at) as u64 {
+                end += 1;
+            }
+            let run = &dirty[at..end];
+            let bytes = match run {
+                [(_, data, _)] => data.clone(),
+                _ => {
+                    let mut bytes = BytesMut::with_capacity(run.len() * self.block_size());
+                    run.iter()
+                        .for_each(|(_, data, _)| bytes.extend_from_slice(data));
+                    bytes.freeze()
+                }
+            };
+            batch.push(runs.len() as u64, BlockRequest::Write { start, bytes });
+            runs.push(at..end);
+            at = end;
+        }
+
+        let mut completions = self.device.submit(batch).await;
+        let mut failed = Ok(());
+        while let Some(Completion { tag, result }) = completions.next().await {
+            if let Err(err) = result {
+                failed = failed.and(Err(err));
+                continue;
+            }
+            let run = &dirty[runs[tag as usize].clone()];
+            WRITE_BACKS.add(run.len() as u64);
+            let mut state = self.state.lock();
+            for (block, _, version) in run {
+                // Written again meanwhile, that write is still to go out
+                if let Some(entry) = state.blocks.get_mut(block)
+                    && entry.version == *version
+                {
+                    entry.dirty = false;
+                }
+            }
+        }
+        failed
+    }
+
+    /// Get back under capacity, writing dirty blocks back if that's the
+    /// only way
+    async fn make_room(&self) -> Result<()> {
+        if self.state.lock().evict(self.capacity) {
+            return Ok(());
+        }
+        self.write_back().await?;
+        self.state.lock().evict(self.capacity);
+        Ok(())
+    }
+
+    /// Read `count` blocks from `start` into the cache, leaving out ones
+    /// that got there meanwhile
+    async fn fill(&self, start: u64, count: u64) -> Result<()> {
+        let block_size = self.device.block_size();
+        let bytes = self.device.read_bytes(start, count).await?;
+        MISSES.add(count);
+        let mut state = self.state.lock();
+        for (block, at) in (start..start + count).zip((0..).step_by(block_size)) {
+            if !state.blocks.contains_key(&block) {
+                state.insert(block, bytes.slice(at..at + block_size), false);
+            }
+        }
+        Ok(())
+    }
+
+    /// Block `block` if it's cached, counting it as used
+    fn cached(&self, block: u64) -> Option<Bytes> {
+        let mut state = self.state.lock();
+        let data = state.blocks.get(&block)?.data.clone();
+        state.touch(block);
+        Some(data)
+    }
+}
+
+impl BlockDevice for BlockCache {
+    fn block_size(&self) -> usize {
+        self.device.block_size()
+    }
+
+    fn block_count(&self) -> u64 {
+        self.device.block_count()
+    }
+
+    fn read_only(&self) -> bool {
+        self.device.read_only()
+    }
+
+    fn read_blocks<'a>(&'a self, start: u64, buf: &'a mut [u8]) -> BlockFuture<'a, ()> {
+        Box::pin(async move {
+            check_range(self, start, buf.len())?;
+            let block_size = self.block_size();
+            let count = (buf.len() / block_size) as u64;
+            let mut block = start;
+            while block < start + count {
+                let at = (block - start) as usize * block_size;
+                if let Some(data) = self.cached(block) {
+                    buf[at..at + block_size].copy_from_slice(&data);
+                    HITS.inc();
+                    block += 1;
+                    continue;
+                }
+                // The missing run, and some more after the request
+                let limit = (start + count + self.read_ahead).min(self.block_count());
+                let end = {
+                    let state = self.state.lock();
+                    (block + 1..limit)
+                        .find(|next| state.blocks.contains_key(next))
+                        .unwrap_or(limit)
+                };
+                self.fill(block, end - block).await?;
+                self.make_room().await?;
+                // Copied out on the next round, unless evicted already
+                if !self.state.lock().blocks.contains_key(&block) {
+                    let data = self.device.read_bytes(block, 1).await?;
+                    buf[at..at + block_size].copy_from_slice(&data);
+                    block += 1;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn write_blocks<'a>(&'a self, start: u64, bytes: &'a [u8]) -> BlockFuture<'a, ()> {
+        self.write_bytes(start, Bytes::copy_from_slice(bytes))
+    }
+
+    /// A cached block comes back as it's kept, more are copied together
+    fn read_bytes(&self, start: u64, count: u64) -> BlockFuture<'_, Bytes> {
+        Box::pin(async move {
+            if count == 1
+                && let Some(data) = self.cached(start)
+            {
+                HITS.inc();
+                return Ok(data);
+            }
+            let len = usize::try_from(count)
+                .ok()
+                .and_then(|count| count.checked_mul(self.block_size()))
+                .ok_or(Error::OutOfRange)?;
+            let mut buf = BytesMut::zeroed(len);
+            self.read_blocks(start, &mut buf).await?;
+            Ok(buf.freeze())
+        })
+    }
+
+    /// Keeps `bytes`, each block a slice of them
+    fn write_bytes(&self, start: u64, bytes: Bytes) -> BlockFuture<'_, ()> {
+        Box::pin(async move {
+            if self.read_only() {
+                return Err(Error::ReadOnly);
+            }
+            check_range(self, start, bytes.len())?;
+            let block_size = self.block_size();
+            {
+                let mut state = self.state.lock();
+                for (block, at) in (start..).zip((0..bytes.len()).step_by(block_size)) {
+                    state.insert(block, bytes.slice(at..at + block_size), true);
+                }
+            }
+            self.make_room().await
+        })
+    }
+
+    /// Write back every dirty block and flush the device, like `fsync`
+    fn flush(&self) -> BlockFuture<'_, ()> {
+        Box::pin(async move {
+            self.write_back().await?;
+            self.device.flush().await
+        })
+    }
+}
+
+/// Flush `cache` every `period` until the end, so writes reach the disk
+/// even if nobody flushes. Spawn it as a task next to the filesystem.
+pub async fn run_flusher(cache: Arc<BlockCache>, period: Duration) {
+    let mut interval = timer::interval(period);
+    loop {
+        interval.tick().await;
+        if cache.dirty_blocks() == 0 {
+            continue;
+        }
+        if let Err(err) = cache.flush().await {
+            log::warn!("block cache flush failed: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::{Future, poll_fn},
+        pin::{Pin, pin},
+        sync::atomic::{AtomicBool, Ordering},
+        task::{Context, Poll, Waker},
+    };
+
+    use super::*;
+    use crate::block::RamDisk;
+
+    /// Holds writes back until opened
+    struct Gated {
+        disk: RamDisk,
+        open: AtomicBool,
+        // Writes waiting for it
+        waiting: spin::Mutex<Vec<Waker>>,
+    }
+
+    impl Gated {
+        fn open(&self) {
+            self.open.store(true, Ordering::Release);
+            self.waiting.lock().drain(..).for_each(Waker::wake);
+        }
+    }
+
+    impl BlockDevice for Gated {
+        fn block_size(&self) -> usize {
+            self.disk.block_size()
+        }
+
+        fn block_count(&self) -> u64 {
+            self.disk.block_count()
+        }
+
+        fn read_blocks<'a>(&'a self, start: u64, buf: &'a mut [u8]) -> BlockFuture<'a, ()> {
+            self.disk.read_blocks(start, buf)
+        }
+
+        fn write_blocks<'a>(&'a self, start: u64, bytes: &'a [u8]) -> BlockFuture<'a, ()> {
+            Box::pin(async move {
+                poll_fn(|cx| {
+                    if self.open.load(Ordering::Acquire) {
+                        return Poll::Ready(());
+                    }
+                    self.waiting.lock().push(cx.waker().clone());
+                    Poll::Pending
+                })
+                .await;
+                self.disk.write_blocks(start, bytes).await
+            })
+        }
+    }
+
+    fn poll<F: Future>(future: Pin<&mut F>) -> Poll<F::Output> {
+        future.poll(&mut Context::from_waker(Waker::noop()))
+    }
+
+    fn ready<F: Future>(future: F) -> F::Output {
+        match poll(pin!(future)) {
+            Poll::Ready(output) => output,
+            Poll::Pending => panic!("expected to be ready"),
+        }
+    }
+
+    /// A write back that ends late doesn't clean a block evicted and written
+    /// again since it looked
+    #[test]
+    fn overlapping_write_backs_keep_a_rewritten_block_dirty() {
+        let gated = Arc::new(Gated {
+            disk: RamDisk::new(4, 2),
+            open: AtomicBool::new(false),
+            waiting: spin::Mutex::new(Vec::new()),
+        });
+        let cache = BlockCache::with_read_ahead(gated.clone(), 1, 0);
+        ready(cache.write_blocks(0, b"AAAA")).unwrap();
+
+        // Both see block 0 as written above
+        let mut first = pin!(cache.write_back());
+        let mut second = pin!(cache.write_back());
+        assert!(poll(first.as_mut()).is_pending());
+        assert!(poll(second.as_mut()).is_pending());
+        gated.open();
+        assert!(matches!(poll(first.as_mut()), Poll::Ready(Ok(()))));
+
+        // Reading block 1 evicts the clean block 0, then it's written again
+        let mut buf = [0; 4];
+        ready(cache.read_blocks(1, &mut buf)).unwrap();
+        ready(cache.write_blocks(0, b"BBBB")).unwrap();
+        assert!(matches!(poll(second.as_mut()), Poll::Ready(Ok(()))));
+        assert_eq!(cache.dirty_blocks(), 1);
+
+        ready(cache.flush()).unwrap();
+        ready(gated.disk.read_blocks(0, &mut buf)).unwrap();
+        assert_eq!(&buf, b"BBBB");
+    }
+}
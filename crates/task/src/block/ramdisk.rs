@@ -0,0 +1,76 @@
+use super::{BlockDevice, BlockFuture, Error, check_range};
+
+/// Block size of [`RamDisk::from_image`], a disk sector
+pub const SECTOR_SIZE: usize = 512;
+
+/// A block device in memory, for trying filesystems without a disk
+pub struct RamDisk {
+    block_size: usize,
+    data: spin::Mutex<Vec<u8>>,
+    read_only: bool,
+}
+
+impl RamDisk {
+    /// A zeroed disk of `block_count` blocks
+    pub fn new(block_size: usize, block_count: u64) -> Self {
+        assert!(block_size > 0, "blocks can't be empty");
+        RamDisk {
+            block_size,
+            data: spin::Mutex::new(vec![0; block_size * block_count as usize]),
+            read_only: false,
+        }
+    }
+
+    /// A disk holding a copy of `image`, such as one from `include_bytes!`.
+    /// A partial last sector is padded with zeros.
+    pub fn from_image(image: &[u8]) -> Self {
+        let mut data = image.to_vec();
+        data.resize(image.len().next_multiple_of(SECTOR_SIZE), 0);
+        RamDisk {
+            block_size: SECTOR_SIZE,
+            data: spin::Mutex::new(data),
+            read_only: false,
+        }
+    }
+
+    /// Make writes fail with [`Error::ReadOnly`]
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> u64 {
+        (self.data.lock().len() / self.block_size) as u64
+    }
+
+    fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn read_blocks<'a>(&'a self, start: u64, buf: &'a mut [u8]) -> BlockFuture<'a, ()> {
+        Box::pin(async move {
+            check_range(self, start, buf.len())?;
+            let offset = start as usize * self.block_size;
+            buf.copy_from_slice(&self.data.lock()[offset..offset + buf.len()]);
+            Ok(())
+        })
+    }
+
+    fn write_blocks<'a>(&'a self, start: u64, bytes: &'a [u8]) -> BlockFuture<'a, ()> {
+        Box::pin(async move {
+            if self.read_only {
+                return Err(Error::ReadOnly);
+            }
+            check_range(self, start, bytes.len())?;
+            let offset = start as usize * self.block_size;
+            self.data.lock()[offset..offset + bytes.len()].copy_from_slice(bytes);
+            Ok(())
+        })
+    }
+}
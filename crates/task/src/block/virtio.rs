@@ -0,0 +1,234 @@
+use std::sync::Arc;
+
+use super::{BlockDevice, BlockFuture, BlockRequest, Error, Result, SubmitFuture, check_range};
+use crate::{
+    batch::{Batch, Completion, Completions},
+    bytes::{BufMut, Bytes},
+    virtio::{self, Buffer, Completed, Transport, VirtQueue},
+};
+
+/// virtio-blk addresses the disk in 512 byte sectors, whatever the
+/// device's own block size
+const SECTOR_SIZE: usize = 512;
+
+const FEATURE_READ_ONLY: u64 = 1 << 5;
+const FEATURE_FLUSH: u64 = 1 << 9;
+
+const REQUEST_READ: u32 = 0;
+const REQUEST_WRITE: u32 = 1;
+const REQUEST_FLUSH: u32 = 4;
+
+const STATUS_OK: u8 = 0;
+
+/// A virtio-blk disk, as QEMU gives with `-drive if=virtio`.
+///
+/// Requests run concurrently, as many as the queue has room for, each
+/// waiting for the device's interrupt instead of polling a status port.
+pub struct VirtioBlock {
+    transport: spin::Mutex<Box<dyn Transport>>,
+    queue: Arc<VirtQueue>,
+    sectors: u64,
+    read_only: bool,
+    // The device has a write cache that needs flushing
+    flush: bool,
+}
+
+impl VirtioBlock {
+    /// Start the block device behind `transport`.
+    ///
+    /// Route its interrupt to [`handle_interrupt`].
+    ///
+    /// [`handle_interrupt`]: VirtioBlock::handle_interrupt
+    pub fn new(
+        mut transport: impl Transport + 'static,
+    ) -> core::result::Result<Self, virtio::Error> {
+        let features = virtio::negotiate(
+            &mut transport,
+            virtio::DEVICE_BLOCK,
+            FEATURE_READ_ONLY | FEATURE_FLUSH,
+        )?;
+        let queue = VirtQueue::new(&mut transport, 0)?;
+        virtio::start(&mut transport);
+
+        let mut capacity = [0; 8];
+        transport.read_config(0, &mut capacity);
+        Ok(VirtioBlock {
+            transport: spin::Mutex::new(Box::new(transport)),
+            queue,
+            sectors: u64::from_le_bytes(capacity),
+            read_only: features & FEATURE_READ_ONLY != 0,
+            flush: features & FEATURE_FLUSH != 0,
+        })
+    }
+
+    /// Call from the device's interrupt handler
+    pub fn handle_interrupt(&self) {
+        if self.transport.lock().acknowledge_interrupt() {
+            self.queue.handle_used();
+        }
+    }
+
+    /// Send a request with `data` between its header and status byte
+    async fn request(&self, kind: u32, sector: u64, data: Option<Buffer>) -> Result<Completed> {
+        let request = self.queue.submit(buffers(kind, sector, data)).await;
+        self.notify();
+        status(request.await)
+    }
+
+    fn notify(&self) {
+        self.queue.notify(&mut **self.transport.lock());
+    }
+
+    /// What `request` asks of the device, checked against the disk
+    fn prepare(&self, request: BlockRequest) -> Result<Option<(u32, u64, Option<Buffer>)>> {
+        match request {
+            BlockRequest::Read { start, count } => {
+                let len = usize::try_from(count)
+                    .ok()
+                    .and_then(|count| count.checked_mul(SECTOR_SIZE))
+                    .ok_or(Error::OutOfRange)?;
+                check_range(self, start, len)?;
+                Ok(Some((REQUEST_READ, start, Some(Buffer::writable(len)))))
+            }
+            BlockRequest::Write { start, bytes } => {
+                if self.read_only {
+                    return Err(Error::ReadOnly);
+                }
+                check_range(self, start, bytes.len())?;
+                Ok(Some((REQUEST_WRITE, start, Some(Buffer::readable(bytes)))))
+            }
+            // Without the feature writes go straight to the disk
+            BlockRequest::Flush if !self.flush => Ok(None),
+            BlockRequest::Flush => Ok(Some((REQUEST_FLUSH, 0, None))),
+        }
+    }
+}
+
+/// The header, `data` and the status byte the device writes back
+fn buffers(kind: u32, sector: u64, data: Option<Buffer>) -> Vec<Buffer> {
+    let mut header = Vec::with_capacity(16);
+    header.put_u32_le(kind);
+    header.put_u32_le(0);
+    header.put_u64_le(sector);
+
+    let mut buffers = vec![Buffer::readable(header)];
+    buffers.extend(data);
+    buffers.push(Buffer::writable(1));
+    buffers
+}
+
+/// `completed` if the device says it went well
+fn status(completed: Completed) -> Result<Completed> {
+    match completed.buffers.last().map(|status| status[0]) {
+        Some(STATUS_OK) => Ok(completed),
+        _ => Err(Error::Device),
+    }
+}
+
+impl BlockDevice for VirtioBlock {
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn read_blocks<'a>(&'a self, start: u64, buf: &'a mut [u8]) -> BlockFuture<'a, ()> {
+        Box::pin(async move {
+            let count = (buf.len() / SECTOR_SIZE) as u64;
+            check_range(self, start, buf.len())?;
+            buf.copy_from_slice(&self.read_bytes(start, count).await?);
+            Ok(())
+        })
+    }
+
+    fn write_blocks<'a>(&'a self, start: u64, bytes: &'a [u8]) -> BlockFuture<'a, ()> {
+        self.write_bytes(start, Bytes::copy_from_slice(bytes))
+    }
+
+    /// The device writes straight into the buffer handed back
+    fn read_bytes(&self, start: u64, count: u64) -> BlockFuture<'_, Bytes> {
+        Box::pin(async move {
+            let len = usize::try_from(count)
+                .ok()
+                .and_then(|count| count.checked_mul(SECTOR_SIZE))
+                .ok_or(Error::OutOfRange)?;
+            check_range(self, start, len)?;
+            let data = Buffer::writable(len);
+            let mut completed = self.request(REQUEST_READ, start, Some(data)).await?;
+            Ok(completed.buffers.swap_remove(1).into_bytes())
+        })
+    }
+
+    /// The device reads `bytes` where they are
+    fn write_bytes(&self, start: u64, bytes: Bytes) -> BlockFuture<'_, ()> {
+        Box::pin(async move {
+            if self.read_only {
+                return Err(Error::ReadOnly);
+            }
+            check_range(self, start, bytes.len())?;
+            self.request(REQUEST_WRITE, start, Some(Buffer::readable(bytes)))
+                .await?;
+            Ok(())
+        })
+    }
+
+    fn flush(&self) -> BlockFuture<'_, ()> {
+        Box::pin(async move {
+            // Without the feature writes go straight to the disk
+            if self.flush {
+                self.request(REQUEST_FLUSH, 0, None).await?;
+            }
+            Ok(())
+        })
+    }
+
+    /// The whole batch goes in the queue before the doorbell rings, unless
+    /// the queue fills up first
+    fn submit(&self, batch: Batch<BlockRequest>) -> SubmitFuture<'_> {
+        Box::pin(async move {
+            let mut completions = Completions::new();
+            let mut unnotified = false;
+            for (tag, request) in batch {
+                let (kind, sector, data) = match self.prepare(request) {
+                    Ok(Some(prepared)) => prepared,
+                    Ok(None) => {
+                        completions.push_ready(tag, Ok(Bytes::new()));
+                        continue;
+                    }
+                    Err(err) => {
+                        completions.push_ready(tag, Err(err));
+                        continue;
+                    }
+                };
+                let request = match self.queue.try_submit(buffers(kind, sector, data)) {
+                    Ok(request) => request,
+                    Err(buffers) => {
+                        // Let the device at what's queued, freeing room
+                        if unnotified {
+                            self.notify();
+                        }
+                        self.queue.submit(buffers).await
+                    }
+                };
+                unnotified = true;
+                completions.push(Box::pin(async move {
+                    let result = status(request.await).map(|mut completed| match kind {
+                        REQUEST_READ => completed.buffers.swap_remove(1).into_bytes(),
+                        _ => Bytes::new(),
+                    });
+                    Completion { tag, result }
+                }));
+            }
+            if unnotified {
+                self.notify();
+            }
+            completions
+        })
+    }
+}
@@ -0,0 +1,435 @@
+//!
+//! Shared byte buffers
+//!
+//! A payload goes from a device through its driver to a socket, a file or
+//! a terminal without being copied at every step. [`Bytes`] is a view into
+//! a reference-counted allocation: cloning and slicing one only bumps the
+//! count. [`BytesMut`] is the buffer it's filled in first, handed on with
+//! [`BytesMut::freeze`], which doesn't copy either.
+//!
+//! ```ignore
+//! let mut frame = BytesMut::zeroed(MAX_FRAME);
+//! let len = device.receive(&mut frame)?;
+//! let frame = frame.freeze().slice(..len);
+//! let payload = frame.slice(ETHERNET_HEADER_LEN..);
+//! ```
+//!
+//! [`Buf`] and [`BufMut`] read and write them a field at a time, for
+//! headers and other wire formats.
+//!
+
+use alloc::{string::String, sync::Arc, vec::Vec};
+use core::{
+    fmt,
+    ops::{Bound, Deref, DerefMut, RangeBounds},
+};
+
+/// Part of a shared, immutable allocation
+#[derive(Clone, Default)]
+pub struct Bytes {
+    data: Arc<Vec<u8>>,
+    start: usize,
+    end: usize,
+}
+
+impl Bytes {
+    pub fn new() -> Bytes {
+        Bytes::default()
+    }
+
+    /// A copy of `bytes` in an allocation of its own
+    pub fn copy_from_slice(bytes: &[u8]) -> Bytes {
+        Bytes::from(bytes.to_vec())
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// `range` of these bytes, sharing the allocation. Panics past the end.
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Bytes {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => self.len(),
+        };
+        assert!(
+            start <= end && end <= self.len(),
+            "range {}..{} out of {} bytes",
+            start,
+            end,
+            self.len()
+        );
+        Bytes {
+            data: self.data.clone(),
+            start: self.start + start,
+            end: self.start + end,
+        }
+    }
+
+    /// Take the first `at` bytes off, the rest stays
+    pub fn split_to(&mut self, at: usize) -> Bytes {
+        let front = self.slice(..at);
+        self.start += at;
+        front
+    }
+
+    /// Take the bytes from `at` on off, the first `at` stay
+    pub fn split_off(&mut self, at: usize) -> Bytes {
+        let back = self.slice(at..);
+        self.end = self.start + at;
+        back
+    }
+
+    /// Keep the first `len` bytes, if there are more
+    pub fn truncate(&mut self, len: usize) {
+        self.end = self.end.min(self.start + len);
+    }
+
+    /// These bytes to change, without copying if nothing else shares them
+    pub fn try_into_mut(self) -> Result<BytesMut, Bytes> {
+        let Bytes { data, start, end } = self;
+        match Arc::try_unwrap(data) {
+            Ok(mut data) => {
+                data.truncate(end);
+                data.drain(..start);
+                Ok(BytesMut { data })
+            }
+            Err(data) => Err(Bytes { data, start, end }),
+        }
+    }
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data[self.start..self.end]
+    }
+}
+
+impl AsRef<[u8]> for Bytes {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl PartialEq for Bytes {
+    fn eq(&self, other: &Bytes) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for Bytes {}
+
+impl PartialEq<[u8]> for Bytes {
+    fn eq(&self, other: &[u8]) -> bool {
+        **self == *other
+    }
+}
+
+impl fmt::Debug for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+/// Takes the vector over, no copy
+impl From<Vec<u8>> for Bytes {
+    fn from(data: Vec<u8>) -> Bytes {
+        let end = data.len();
+        Bytes {
+            data: Arc::new(data),
+            start: 0,
+            end,
+        }
+    }
+}
+
+impl From<&[u8]> for Bytes {
+    fn from(bytes: &[u8]) -> Bytes {
+        Bytes::copy_from_slice(bytes)
+    }
+}
+
+impl From<String> for Bytes {
+    fn from(text: String) -> Bytes {
+        Bytes::from(text.into_bytes())
+    }
+}
+
+impl From<BytesMut> for Bytes {
+    fn from(bytes: BytesMut) -> Bytes {
+        bytes.freeze()
+    }
+}
+
+/// A buffer owned by one task, to fill in before it's shared
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct BytesMut {
+    data: Vec<u8>,
+}
+
+impl BytesMut {
+    pub fn new() -> BytesMut {
+        BytesMut::default()
+    }
+
+    /// Empty, with room for `capacity` bytes
+    pub fn with_capacity(capacity: usize) -> BytesMut {
+        BytesMut {
+            data: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// `len` zeros, for a device or a read to fill in
+    pub fn zeroed(len: usize) -> BytesMut {
+        BytesMut {
+            data: alloc::vec![0; len],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    /// Room for `additional` more bytes without reallocating
+    pub fn reserve(&mut self, additional: usize) {
+        self.data.reserve(additional);
+    }
+
+    pub fn extend_from_slice(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    /// Grow with `value` or cut back to `len` bytes
+    pub fn resize(&mut self, len: usize, value: u8) {
+        self.data.resize(len, value);
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.data.truncate(len);
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    /// Share the bytes from now on, the allocation stays where it is
+    pub fn freeze(self) -> Bytes {
+        Bytes::from(self.data)
+    }
+}
+
+impl Deref for BytesMut {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl DerefMut for BytesMut {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+}
+
+impl AsRef<[u8]> for BytesMut {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl fmt::Debug for BytesMut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.data, f)
+    }
+}
+
+impl From<Vec<u8>> for BytesMut {
+    fn from(data: Vec<u8>) -> BytesMut {
+        BytesMut { data }
+    }
+}
+
+impl From<&[u8]> for BytesMut {
+    fn from(bytes: &[u8]) -> BytesMut {
+        BytesMut {
+            data: bytes.to_vec(),
+        }
+    }
+}
+
+impl From<BytesMut> for Vec<u8> {
+    fn from(bytes: BytesMut) -> Vec<u8> {
+        bytes.data
+    }
+}
+
+/// Bytes read from the front, a field at a time. Numbers without a suffix
+/// are big-endian, as on the wire.
+pub trait Buf {
+    /// Bytes left to read
+    fn remaining(&self) -> usize;
+
+    /// The bytes from the current position on, maybe not all of them
+    fn chunk(&self) -> &[u8];
+
+    /// Skip `count` bytes. Panics past the end.
+    fn advance(&mut self, count: usize);
+
+    fn has_remaining(&self) -> bool {
+        self.remaining() > 0
+    }
+
+    /// Fill `target`. Panics if fewer bytes are left.
+    fn copy_to_slice(&mut self, target: &mut [u8]) {
+        assert!(self.remaining() >= target.len(), "buffer too short");
+        let mut at = 0;
+        while at < target.len() {
+            let chunk = self.chunk();
+            let count = chunk.len().min(target.len() - at);
+            target[at..at + count].copy_from_slice(&chunk[..count]);
+            self.advance(count);
+            at += count;
+        }
+    }
+
+    /// The next `len` bytes, shared instead of copied where the buffer is
+    /// [`Bytes`] already
+    fn copy_to_bytes(&mut self, len: usize) -> Bytes {
+        let mut bytes = BytesMut::zeroed(len);
+        self.copy_to_slice(&mut bytes);
+        bytes.freeze()
+    }
+
+    fn get_u8(&mut self) -> u8 {
+        u8::from_be_bytes(take(self))
+    }
+
+    fn get_u16(&mut self) -> u16 {
+        u16::from_be_bytes(take(self))
+    }
+
+    fn get_u16_le(&mut self) -> u16 {
+        u16::from_le_bytes(take(self))
+    }
+
+    fn get_u32(&mut self) -> u32 {
+        u32::from_be_bytes(take(self))
+    }
+
+    fn get_u32_le(&mut self) -> u32 {
+        u32::from_le_bytes(take(self))
+    }
+
+    fn get_u64(&mut self) -> u64 {
+        u64::from_be_bytes(take(self))
+    }
+
+    fn get_u64_le(&mut self) -> u64 {
+        u64::from_le_bytes(take(self))
+    }
+}
+
+fn take<const N: usize>(buf: &mut (impl Buf + ?Sized)) -> [u8; N] {
+    let mut bytes = [0; N];
+    buf.copy_to_slice(&mut bytes);
+    bytes
+}
+
+impl Buf for Bytes {
+    fn remaining(&self) -> usize {
+        self.len()
+    }
+
+    fn chunk(&self) -> &[u8] {
+        self
+    }
+
+    fn advance(&mut self, count: usize) {
+        self.split_to(count);
+    }
+
+    fn copy_to_bytes(&mut self, len: usize) -> Bytes {
+        self.split_to(len)
+    }
+}
+
+impl Buf for &[u8] {
+    fn remaining(&self) -> usize {
+        self.len()
+    }
+
+    fn chunk(&self) -> &[u8] {
+        self
+    }
+
+    fn advance(&mut self, count: usize) {
+        *self = &self[count..];
+    }
+}
+
+/// Bytes appended a field at a time, big-endian unless the name says
+/// otherwise
+pub trait BufMut {
+    fn put_slice(&mut self, bytes: &[u8]);
+
+    fn put_u8(&mut self, value: u8) {
+        self.put_slice(&[value]);
+    }
+
+    fn put_u16(&mut self, value: u16) {
+        self.put_slice(&value.to_be_bytes());
+    }
+
+    fn put_u16_le(&mut self, value: u16) {
+        self.put_slice(&value.to_le_bytes());
+    }
+
+    fn put_u32(&mut self, value: u32) {
+        self.put_slice(&value.to_be_bytes());
+    }
+
+    fn put_u32_le(&mut self, value: u32) {
+        self.put_slice(&value.to_le_bytes());
+    }
+
+    fn put_u64(&mut self, value: u64) {
+        self.put_slice(&value.to_be_bytes());
+    }
+
+    fn put_u64_le(&mut self, value: u64) {
+        self.put_slice(&value.to_le_bytes());
+    }
+}
+
+impl BufMut for BytesMut {
+    fn put_slice(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+}
+
+impl BufMut for Vec<u8> {
+    fn put_slice(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes);
+    }
+}
@@ -0,0 +1,183 @@
+//!
+//! Capabilities
+//!
+//! A task given [`Capabilities`] with [`Task::with_capabilities`] can only
+//! do what they allow where it's checked: the [`services`](crate::services)
+//! look at the capabilities of the task sending each request, raw
+//! scancodes need [`Capability::RawInput`]. Tasks created by a task get its
+//! capabilities, and never more than it has:
+//!
+//! ```ignore
+//! let logger = Task::new(write_logs()).with_capabilities(
+//!     Capabilities::none()
+//!         .with(Capability::ConsoleWrite)
+//!         .with(Capability::Fs("/var/log".into())),
+//! );
+//! ```
+//!
+//! A task without any is unrestricted, as is code outside a task.
+//!
+
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
+use core::fmt;
+
+use crate::{TaskId, context};
+
+/// Something a task may do
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Capability {
+    /// Write to the console and scroll it
+    ConsoleWrite,
+    /// Anything in the files and directories under this absolute path
+    Fs(String),
+    /// Scancodes as they come from the keyboard, see
+    /// [`ScancodeStream`](crate::keyboard::ScancodeStream)
+    RawInput,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Capability::ConsoleWrite => f.write_str("console write"),
+            Capability::Fs(path) => write!(f, "fs {}", path),
+            Capability::RawInput => f.write_str("raw input"),
+        }
+    }
+}
+
+impl Capability {
+    /// Holding `self` is enough for `needed`
+    fn covers(&self, needed: &Capability) -> bool {
+        match (self, needed) {
+            (Capability::Fs(root), Capability::Fs(path)) => is_within(root, path),
+            (granted, needed) => granted == needed,
+        }
+    }
+}
+
+/// The names in `path` with `.` and `..` worked out
+fn components(path: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts
+}
+
+/// `path` is `root` or something in it, relative paths are in nothing
+fn is_within(root: &str, path: &str) -> bool {
+    if !root.starts_with('/') || !path.starts_with('/') {
+        return false;
+    }
+    let root = components(root);
+    let path = components(path);
+    path.starts_with(&root)
+}
+
+/// What a task may do
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    granted: Vec<Capability>,
+}
+
+impl Capabilities {
+    /// Nothing at all, add to it with [`with`](Capabilities::with)
+    pub fn none() -> Capabilities {
+        Capabilities::default()
+    }
+
+    pub fn with(mut self, capability: Capability) -> Capabilities {
+        if !self.granted.contains(&capability) {
+            self.granted.push(capability);
+        }
+        self
+    }
+
+    pub fn allows(&self, needed: &Capability) -> bool {
+        self.granted.iter().any(|granted| granted.covers(needed))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Capability> {
+        self.granted.iter()
+    }
+
+    /// Only the ones `held` allows as well
+    fn within(self, held: &Capabilities) -> Capabilities {
+        Capabilities {
+            granted: self
+                .granted
+                .into_iter()
+                .filter(|capability| held.allows(capability))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CapabilityError {
+    /// The task doesn't have this
+    Missing(Capability),
+}
+
+impl fmt::Display for CapabilityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CapabilityError::Missing(capability) => {
+                write!(f, "missing capability: {}", capability)
+            }
+        }
+    }
+}
+
+/// Capabilities of a task, `None` for an unrestricted one
+pub type Grant = Option<Arc<Capabilities>>;
+
+// Tasks spawned with capabilities and not finished yet
+static GRANTS: spin::Mutex<BTreeMap<TaskId, Arc<Capabilities>>> = spin::Mutex::new(BTreeMap::new());
+
+/// What the task being polled may do, `None` if anything
+pub fn current() -> Grant {
+    let (task, _) = context::current()?;
+    GRANTS.lock().get(&task).cloned()
+}
+
+/// `Ok` if `grant` allows `needed`
+pub fn check_grant(grant: &Grant, needed: &Capability) -> Result<(), CapabilityError> {
+    match grant {
+        Some(capabilities) if !capabilities.allows(needed) => {
+            Err(CapabilityError::Missing(needed.clone()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// `Ok` if the task being polled may do `needed`
+pub fn check(needed: &Capability) -> Result<(), CapabilityError> {
+    check_grant(&current(), needed)
+}
+
+/// `requested` cut down to what the creating task has
+pub(crate) fn narrow(requested: Capabilities, held: &Grant) -> Arc<Capabilities> {
+    Arc::new(match held {
+        Some(held) => requested.within(held),
+        None => requested,
+    })
+}
+
+/// Called by the executor when `task` is spawned
+pub(crate) fn grant(task: TaskId, grant: &Grant) {
+    if let Some(capabilities) = grant {
+        GRANTS.lock().insert(task, capabilities.clone());
+    }
+}
+
+/// `task` finished, drop its capabilities
+pub(crate) fn forget(task: TaskId) {
+    GRANTS.lock().remove(&task);
+}
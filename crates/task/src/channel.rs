@@ -0,0 +1,75 @@
+//!
+//! Async channels
+//!
+//! Each flavour lives in its own module with a `channel()` constructor
+//! returning the sender and receiver halves.
+//!
+
+pub mod broadcast;
+pub mod mpmc;
+pub mod oneshot;
+pub mod rendezvous;
+mod select;
+pub mod watch;
+
+pub use select::{Receive, Select, recv_any};
+
+use core::fmt;
+
+use crate::metrics::Counter;
+
+// Shared by every flavour
+static SENT: Counter = Counter::new("channel_sent_total", "Values sent on any channel");
+static SEND_WAITS: Counter = Counter::new(
+    "channel_send_waits_total",
+    "Sends that had to wait for room in a full channel",
+);
+static LAGGED: Counter = Counter::new(
+    "channel_lagged_total",
+    "Broadcast values overwritten before a receiver got to them",
+);
+
+/// Returned by `send` when every receiver is gone, hands the value back
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SendError(..)")
+    }
+}
+
+impl<T> fmt::Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("channel closed")
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum TrySendError<T> {
+    Full(T),
+    Closed(T),
+}
+
+impl<T> TrySendError<T> {
+    pub fn into_inner(self) -> T {
+        match self {
+            TrySendError::Full(value) | TrySendError::Closed(value) => value,
+        }
+    }
+}
+
+impl<T> fmt::Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("Full(..)"),
+            TrySendError::Closed(_) => f.write_str("Closed(..)"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TryRecvError {
+    Empty,
+    Closed,
+}
@@ -0,0 +1,246 @@
+//! Broadcast channel: every receiver sees every value.
+//!
+//! Values are kept in a ring of `capacity` slots. Sending never waits, the
+//! oldest value is overwritten instead, and a receiver that fell that far
+//! behind gets [`RecvError::Lagged`] once before catching up.
+
+use alloc::collections::VecDeque;
+use core::{
+    future::poll_fn,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::Stream;
+
+use super::{Receive, SendError};
+use crate::sync::{
+    loom::{Arc, Mutex},
+    waiters::WaitList,
+};
+
+pub fn channel<T: Clone>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "broadcast capacity must be non-zero");
+    let shared = Arc::new(Mutex::new(State {
+        buffer: VecDeque::with_capacity(capacity),
+        head: 0,
+        capacity,
+        waiters: WaitList::new(),
+        senders: 1,
+        receivers: 1,
+        closed: false,
+    }));
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver {
+            shared,
+            next: 0,
+            key: None,
+        },
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// All senders are gone and every value was received
+    Closed,
+    /// This many values were overwritten before we got to them
+    Lagged(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError {
+    Empty,
+    Closed,
+    Lagged(u64),
+}
+
+struct State<T> {
+    buffer: VecDeque<T>,
+    // Sequence number of buffer[0]
+    head: u64,
+    capacity: usize,
+    waiters: WaitList,
+    senders: usize,
+    receivers: usize,
+    // Set by `Sender::close`, acts as if every sender was dropped
+    closed: bool,
+}
+
+impl<T: Clone> State<T> {
+    fn tail(&self) -> u64 {
+        self.head + self.buffer.len() as u64
+    }
+
+    /// Next value for a receiver that wants sequence number `next`
+    fn take(&self, next: &mut u64) -> Option<Result<T, RecvError>> {
+        if *next < self.head {
+            let missed = self.head - *next;
+            *next = self.head;
+            super::LAGGED.add(missed);
+            return Some(Err(RecvError::Lagged(missed)));
+        }
+        if *next < self.tail() {
+            let value = self.buffer[(*next - self.head) as usize].clone();
+            *next += 1;
+            return Some(Ok(value));
+        }
+        if self.senders == 0 || self.closed {
+            return Some(Err(RecvError::Closed));
+        }
+        None
+    }
+}
+
+pub struct Sender<T> {
+    shared: Arc<Mutex<State<T>>>,
+}
+
+impl<T: Clone> Sender<T> {
+    /// Returns how many receivers will see the value
+    pub fn send(&self, value: T) -> Result<usize, SendError<T>> {
+        let mut state = self.shared.lock();
+        if state.receivers == 0 || state.closed {
+            return Err(SendError(value));
+        }
+        if state.buffer.len() == state.capacity {
+            state.buffer.pop_front();
+            state.head += 1;
+        }
+        state.buffer.push_back(value);
+        state.waiters.notify_all();
+        super::SENT.inc();
+        Ok(state.receivers)
+    }
+
+    /// New receiver that sees values sent from now on
+    pub fn subscribe(&self) -> Receiver<T> {
+        let mut state = self.shared.lock();
+        state.receivers += 1;
+        Receiver {
+            shared: self.shared.clone(),
+            next: state.tail(),
+            key: None,
+        }
+    }
+
+    pub fn receiver_count(&self) -> usize {
+        self.shared.lock().receivers
+    }
+
+    /// Close the channel for all senders, for when they can't be dropped.
+    ///
+    /// Receivers still get the values sent before.
+    pub fn close(&self) {
+        let mut state = self.shared.lock();
+        state.closed = true;
+        state.waiters.notify_all();
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            state.waiters.notify_all();
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Mutex<State<T>>>,
+    // Sequence number of the next value we want
+    next: u64,
+    key: Option<u64>,
+}
+
+impl<T: Clone> Receiver<T> {
+    pub async fn recv(&mut self) -> Result<T, RecvError> {
+        poll_fn(|cx| self.poll_recv_lagged(cx)).await
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let state = self.shared.lock();
+        match state.take(&mut self.next) {
+            Some(Ok(value)) => Ok(value),
+            Some(Err(RecvError::Lagged(missed))) => Err(TryRecvError::Lagged(missed)),
+            Some(Err(RecvError::Closed)) => Err(TryRecvError::Closed),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    pub fn poll_recv_lagged(&mut self, cx: &mut Context) -> Poll<Result<T, RecvError>> {
+        let mut state = self.shared.lock();
+        if let Some(result) = state.take(&mut self.next) {
+            if let Some(key) = self.key.take() {
+                state.waiters.remove(key);
+            }
+            return Poll::Ready(result);
+        }
+        state.waiters.register(&mut self.key, cx.waker());
+        Poll::Pending
+    }
+
+    /// Skip everything sent so far
+    pub fn resubscribe(&self) -> Receiver<T> {
+        let mut state = self.shared.lock();
+        state.receivers += 1;
+        Receiver {
+            shared: self.shared.clone(),
+            next: state.tail(),
+            key: None,
+        }
+    }
+}
+
+impl<T: Clone> Receive for Receiver<T> {
+    type Item = T;
+
+    /// Lag is skipped over silently
+    fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<T>> {
+        loop {
+            match self.poll_recv_lagged(cx) {
+                Poll::Ready(Ok(value)) => return Poll::Ready(Some(value)),
+                Poll::Ready(Err(RecvError::Lagged(_))) => continue,
+                Poll::Ready(Err(RecvError::Closed)) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn cancel_recv(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.shared.lock().waiters.remove(key);
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        if let Some(key) = self.key.take() {
+            state.waiters.remove(key);
+        }
+        state.receivers -= 1;
+    }
+}
+
+impl<T: Clone> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        Receive::poll_recv(self.get_mut(), cx)
+    }
+}
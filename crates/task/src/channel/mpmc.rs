@@ -0,0 +1,336 @@
+//! Multi-producer multi-consumer queue.
+//!
+//! Both halves are cloneable. Every value goes to exactly one receiver, so a
+//! pool of worker tasks can pull jobs from one shared queue.
+
+use alloc::collections::VecDeque;
+use core::{
+    future::{Future, poll_fn},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::Stream;
+
+use super::{Receive, SendError, TryRecvError, TrySendError};
+use crate::sync::{
+    loom::{Arc, Mutex},
+    waiters::WaitList,
+};
+
+/// Bounded channel, `send` waits while `capacity` values are queued
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "use channel::rendezvous for zero capacity");
+    with_capacity(Some(capacity))
+}
+
+/// `send` never waits
+pub fn unbounded<T>() -> (Sender<T>, Receiver<T>) {
+    with_capacity(None)
+}
+
+fn with_capacity<T>(capacity: Option<usize>) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Mutex::new(State {
+        queue: VecDeque::new(),
+        capacity,
+        send_waiters: WaitList::new(),
+        recv_waiters: WaitList::new(),
+        senders: 1,
+        receivers: 1,
+    }));
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared, key: None },
+    )
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    capacity: Option<usize>,
+    send_waiters: WaitList,
+    recv_waiters: WaitList,
+    senders: usize,
+    receivers: usize,
+}
+
+impl<T> State<T> {
+    fn is_full(&self) -> bool {
+        self.capacity.is_some_and(|cap| self.queue.len() >= cap)
+    }
+}
+
+pub struct Sender<T> {
+    shared: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Sender<T> {
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        SendFuture {
+            shared: &self.shared,
+            value: Some(value),
+            key: None,
+        }
+        .await
+    }
+
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let mut state = self.shared.lock();
+        if state.receivers == 0 {
+            return Err(TrySendError::Closed(value));
+        }
+        // Parked senders were first, notified ones are about to send
+        if state.is_full() || !state.send_waiters.is_empty() {
+            return Err(TrySendError::Full(value));
+        }
+        state.queue.push_back(value);
+        state.recv_waiters.notify_one();
+        super::SENT.inc();
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.lock().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.lock().receivers == 0
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            state.recv_waiters.notify_all();
+        }
+    }
+}
+
+struct SendFuture<'a, T> {
+    shared: &'a Mutex<State<T>>,
+    value: Option<T>,
+    key: Option<u64>,
+}
+
+impl<T> Unpin for SendFuture<'_, T> {}
+
+impl<T> Future for SendFuture<'_, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let shared = self.shared;
+        let mut state = shared.lock();
+        let this = &mut *self;
+
+        // Parked senders were first, of those the notified ones. A new one
+        // waits behind both kinds.
+        let turn = match this.key {
+            None => state.send_waiters.is_empty(),
+            Some(key) => state.send_waiters.is_notified(key),
+        };
+        if state.receivers == 0 || (turn && !state.is_full()) {
+            if let Some(key) = this.key.take() {
+                state.send_waiters.remove(key);
+            }
+            let value = this.value.take().expect("polled after completion");
+            if state.receivers == 0 {
+                return Poll::Ready(Err(SendError(value)));
+            }
+            state.queue.push_back(value);
+            state.recv_waiters.notify_one();
+            super::SENT.inc();
+            return Poll::Ready(Ok(()));
+        }
+
+        if this.key.is_none() {
+            super::SEND_WAITS.inc();
+        }
+        state.send_waiters.register(&mut this.key, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for SendFuture<'_, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            let mut state = self.shared.lock();
+            if state.send_waiters.remove(key) == Some(true) {
+                state.send_waiters.notify_one();
+            }
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Mutex<State<T>>>,
+    key: Option<u64>,
+}
+
+impl<T> Receiver<T> {
+    /// `None` once all senders are gone and the queue is drained
+    pub async fn recv(&mut self) -> Option<T> {
+        poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.lock();
+        match state.queue.pop_front() {
+            Some(value) => {
+                state.send_waiters.notify_one();
+                Ok(value)
+            }
+            None if state.senders == 0 => Err(TryRecvError::Closed),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    pub fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<T>> {
+        let mut state = self.shared.lock();
+        if let Some(value) = state.queue.pop_front() {
+            if let Some(key) = self.key.take() {
+                state.recv_waiters.remove(key);
+            }
+            state.send_waiters.notify_one();
+            return Poll::Ready(Some(value));
+        }
+        if state.senders == 0 {
+            if let Some(key) = self.key.take() {
+                state.recv_waiters.remove(key);
+            }
+            return Poll::Ready(None);
+        }
+        state.recv_waiters.register(&mut self.key, cx.waker());
+        Poll::Pending
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.lock().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Receive for Receiver<T> {
+    type Item = T;
+
+    fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<T>> {
+        Receiver::poll_recv(self, cx)
+    }
+
+    fn cancel_recv(&mut self) {
+        let mut state = self.shared.lock();
+        if let Some(key) = self.key.take()
+            && state.recv_waiters.remove(key) == Some(true)
+        {
+            state.recv_waiters.notify_one();
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().receivers += 1;
+        Receiver {
+            shared: self.shared.clone(),
+            key: None,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.cancel_recv();
+        let mut state = self.shared.lock();
+        state.receivers -= 1;
+        if state.receivers == 0 {
+            state.send_waiters.notify_all();
+        }
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        self.get_mut().poll_recv(cx)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use core::pin::pin;
+
+    use super::*;
+    use crate::waker::TestWaker;
+
+    /// A sender woken for a free slot gets it, new senders don't slip in
+    /// between the wake and its next poll
+    #[test]
+    fn notified_sender_keeps_its_slot() {
+        let (sender, mut receiver) = channel(1);
+        let (parked_waker, new_waker) = (TestWaker::new(), TestWaker::new());
+        let (parked_handle, new_handle) = (parked_waker.waker(), new_waker.waker());
+        let mut parked_cx = Context::from_waker(&parked_handle);
+        let mut new_cx = Context::from_waker(&new_handle);
+
+        sender.try_send(1).unwrap();
+        let mut parked = pin!(sender.send(2));
+        assert!(parked.as_mut().poll(&mut parked_cx).is_pending());
+        assert_eq!(receiver.try_recv(), Ok(1));
+        assert_eq!(parked_waker.wakes(), 1);
+
+        let mut new = pin!(sender.send(3));
+        assert!(new.as_mut().poll(&mut new_cx).is_pending());
+        assert_eq!(sender.try_send(4), Err(TrySendError::Full(4)));
+        assert_eq!(parked.as_mut().poll(&mut parked_cx), Poll::Ready(Ok(())));
+
+        assert_eq!(receiver.try_recv(), Ok(2));
+        assert_eq!(new_waker.wakes(), 1);
+        assert_eq!(new.as_mut().poll(&mut new_cx), Poll::Ready(Ok(())));
+        assert_eq!(receiver.try_recv(), Ok(3));
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use ::loom::{future::block_on, model, thread};
+
+    use super::*;
+
+    /// A sender parked on a full queue gets through once the receiver takes
+    /// a value, and values arrive in order
+    #[test]
+    fn send_waits_for_recv() {
+        model(|| {
+            let (sender, mut receiver) = channel(1);
+            let sending = thread::spawn(move || {
+                block_on(async {
+                    sender.send(1).await.unwrap();
+                    sender.send(2).await.unwrap();
+                });
+            });
+            assert_eq!(block_on(receiver.recv()), Some(1));
+            assert_eq!(block_on(receiver.recv()), Some(2));
+            assert_eq!(block_on(receiver.recv()), None);
+            sending.join().unwrap();
+        });
+    }
+}
@@ -0,0 +1,105 @@
+//! Channel for a single value, a reply to a request for instance.
+//!
+//! The [`Receiver`] is a future resolving to the value, or to `None` if the
+//! sender was dropped without sending.
+
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use super::SendError;
+use crate::sync::loom::{Arc, Mutex};
+
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Mutex::new(State {
+        value: None,
+        waker: None,
+        sender: true,
+        receiver: true,
+    }));
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+struct State<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+    sender: bool,
+    receiver: bool,
+}
+
+pub struct Sender<T> {
+    shared: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// Fails if the receiver is gone
+    pub fn send(self, value: T) -> Result<(), SendError<T>> {
+        let mut state = self.shared.lock();
+        if !state.receiver {
+            return Err(SendError(value));
+        }
+        state.value = Some(value);
+        super::SENT.inc();
+        Ok(())
+        // Dropping `self` wakes the receiver
+    }
+
+    pub fn is_closed(&self) -> bool {
+        !self.shared.lock().receiver
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.sender = false;
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Receiver<T> {
+    /// `None` if nothing was sent yet, see [`is_closed`](Receiver::is_closed)
+    pub fn try_recv(&mut self) -> Option<T> {
+        self.shared.lock().value.take()
+    }
+
+    /// The sender is gone, with or without sending
+    pub fn is_closed(&self) -> bool {
+        !self.shared.lock().sender
+    }
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        let mut state = self.shared.lock();
+        if let Some(value) = state.value.take() {
+            return Poll::Ready(Some(value));
+        }
+        if !state.sender {
+            return Poll::Ready(None);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.lock().receiver = false;
+    }
+}
@@ -0,0 +1,315 @@
+//! Zero-capacity channel: `send` completes only once a receiver has taken
+//! the value, so sender and receiver meet in lock-step (CSP style).
+
+use core::{
+    future::{Future, poll_fn},
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use futures_util::Stream;
+
+use super::{Receive, SendError, TryRecvError};
+use crate::sync::{
+    loom::{Arc, Mutex},
+    waiters::WaitList,
+};
+
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Mutex::new(State {
+        offer: None,
+        next_offer: 0,
+        taken: None,
+        send_waiters: WaitList::new(),
+        recv_waiters: WaitList::new(),
+        senders: 1,
+        receivers: 1,
+    }));
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared, key: None },
+    )
+}
+
+struct State<T> {
+    // The one value currently on offer and the sender waiting on it
+    offer: Option<Offer<T>>,
+    next_offer: u64,
+    // Offer id taken by a receiver but not yet seen by its sender
+    taken: Option<u64>,
+    send_waiters: WaitList,
+    recv_waiters: WaitList,
+    senders: usize,
+    receivers: usize,
+}
+
+struct Offer<T> {
+    id: u64,
+    value: T,
+    sender: Waker,
+}
+
+impl<T> State<T> {
+    fn take(&mut self) -> Option<T> {
+        let offer = self.offer.take()?;
+        self.taken = Some(offer.id);
+        offer.sender.wake();
+        Some(offer.value)
+    }
+}
+
+pub struct Sender<T> {
+    shared: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// Resolves once a receiver took the value
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        SendFuture {
+            shared: &self.shared,
+            value: Some(value),
+            key: None,
+            offer: None,
+        }
+        .await
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.shared.lock().receivers == 0
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            state.recv_waiters.notify_all();
+        }
+    }
+}
+
+struct SendFuture<'a, T> {
+    shared: &'a Mutex<State<T>>,
+    value: Option<T>,
+    // Place in the queue of senders waiting for the slot
+    key: Option<u64>,
+    // Id of our value once it sits in the slot
+    offer: Option<u64>,
+}
+
+impl<T> Unpin for SendFuture<'_, T> {}
+
+impl<T> Future for SendFuture<'_, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let shared = self.shared;
+        let mut state = shared.lock();
+
+        if let Some(id) = self.offer {
+            if state.taken == Some(id) {
+                // Only now is the slot free for the next sender
+                state.taken = None;
+                self.offer = None;
+                state.send_waiters.notify_one();
+                super::SENT.inc();
+                return Poll::Ready(Ok(()));
+            }
+            if state.receivers == 0 {
+                let offer = state.offer.take().expect("offer vanished");
+                self.offer = None;
+                state.send_waiters.notify_all();
+                return Poll::Ready(Err(SendError(offer.value)));
+            }
+            if let Some(offer) = state.offer.as_mut() {
+                offer.sender.clone_from(cx.waker());
+            }
+            return Poll::Pending;
+        }
+
+        if state.receivers == 0 {
+            if let Some(key) = self.key.take() {
+                state.send_waiters.remove(key);
+            }
+            return Poll::Ready(Err(SendError(
+                self.value.take().expect("polled after completion"),
+            )));
+        }
+
+        // Slot busy with another sender's offer
+        if state.offer.is_some() || state.taken.is_some() {
+            let mut key = self.key;
+            state.send_waiters.register(&mut key, cx.waker());
+            self.key = key;
+            return Poll::Pending;
+        }
+
+        if let Some(key) = self.key.take() {
+            state.send_waiters.remove(key);
+        }
+        let id = state.next_offer;
+        state.next_offer += 1;
+        state.offer = Some(Offer {
+            id,
+            value: self.value.take().expect("polled after completion"),
+            sender: cx.waker().clone(),
+        });
+        self.offer = Some(id);
+        state.recv_waiters.notify_one();
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for SendFuture<'_, T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        if let Some(key) = self.key
+            && state.send_waiters.remove(key) == Some(true)
+        {
+            state.send_waiters.notify_one();
+        }
+        if let Some(id) = self.offer {
+            // Withdraw a value nobody took yet, then let the next sender in
+            if state.offer.as_ref().is_some_and(|offer| offer.id == id) {
+                state.offer = None;
+            }
+            if state.taken == Some(id) {
+                state.taken = None;
+            }
+            state.send_waiters.notify_one();
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Mutex<State<T>>>,
+    key: Option<u64>,
+}
+
+impl<T> Receiver<T> {
+    /// `None` once all senders are gone
+    pub async fn recv(&mut self) -> Option<T> {
+        poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    /// Only succeeds if a sender is already waiting
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.lock();
+        match state.take() {
+            Some(value) => Ok(value),
+            None if state.senders == 0 => Err(TryRecvError::Closed),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    pub fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<T>> {
+        let mut state = self.shared.lock();
+        if let Some(value) = state.take() {
+            if let Some(key) = self.key.take() {
+                state.recv_waiters.remove(key);
+            }
+            return Poll::Ready(Some(value));
+        }
+        if state.senders == 0 {
+            if let Some(key) = self.key.take() {
+                state.recv_waiters.remove(key);
+            }
+            return Poll::Ready(None);
+        }
+        state.recv_waiters.register(&mut self.key, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl<T> Receive for Receiver<T> {
+    type Item = T;
+
+    fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<T>> {
+        Receiver::poll_recv(self, cx)
+    }
+
+    fn cancel_recv(&mut self) {
+        let mut state = self.shared.lock();
+        if let Some(key) = self.key.take()
+            && state.recv_waiters.remove(key) == Some(true)
+        {
+            state.recv_waiters.notify_one();
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().receivers += 1;
+        Receiver {
+            shared: self.shared.clone(),
+            key: None,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.cancel_recv();
+        let mut state = self.shared.lock();
+        state.receivers -= 1;
+        if state.receivers == 0 {
+            state.send_waiters.notify_all();
+            if let Some(offer) = state.offer.as_ref() {
+                offer.sender.wake_by_ref();
+            }
+        }
+    }
+}
+
+impl<T> Stream for Receiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        self.get_mut().poll_recv(cx)
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use core::pin::pin;
+
+    use super::*;
+    use crate::waker::TestWaker;
+
+    /// A sender finding the slot taken but not yet seen by its sender is
+    /// woken once that sender is done
+    #[test]
+    fn sender_polled_before_the_taken_one_is_woken() {
+        let (sender, mut receiver) = channel();
+        let (first_waker, second_waker) = (TestWaker::new(), TestWaker::new());
+        let (first_waker, second_waker_handle) = (first_waker.waker(), second_waker.waker());
+        let mut first_cx = Context::from_waker(&first_waker);
+        let mut second_cx = Context::from_waker(&second_waker_handle);
+        let mut recv_cx = Context::from_waker(Waker::noop());
+        let mut first = pin!(sender.send(1));
+        let mut second = pin!(sender.send(2));
+
+        assert!(first.as_mut().poll(&mut first_cx).is_pending());
+        assert_eq!(receiver.poll_recv(&mut recv_cx), Poll::Ready(Some(1)));
+        assert!(second.as_mut().poll(&mut second_cx).is_pending());
+        assert_eq!(first.as_mut().poll(&mut first_cx), Poll::Ready(Ok(())));
+        assert_eq!(second_waker.wakes(), 1);
+
+        assert!(second.as_mut().poll(&mut second_cx).is_pending());
+        assert_eq!(receiver.poll_recv(&mut recv_cx), Poll::Ready(Some(2)));
+        assert_eq!(second.as_mut().poll(&mut second_cx), Poll::Ready(Ok(())));
+    }
+}
@@ -0,0 +1,110 @@
+use alloc::vec::Vec;
+use core::{
+    future::poll_fn,
+    task::{Context, Poll},
+};
+
+/// Receiving half that can take part in a [`Select`]
+pub trait Receive {
+    type Item;
+
+    fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<Self::Item>>;
+
+    /// Drop any waker registration left over from a `poll_recv` that lost
+    /// the select, handing a wake we got on to the next receiver.
+    fn cancel_recv(&mut self);
+}
+
+/// Waits on several receivers at once, e.g. for a router task.
+///
+/// Polling starts one past the receiver that delivered last time, so a busy
+/// source can't starve the others. Receivers that report closed are skipped
+/// from then on.
+///
+/// ```ignore
+/// let mut select = Select::new();
+/// let keys = select.add(&mut key_rx);
+/// let net = select.add(&mut net_rx);
+/// while let Some((index, event)) = select.recv().await { ... }
+/// ```
+pub struct Select<'a, T> {
+    receivers: Vec<Entry<'a, T>>,
+    next: usize,
+}
+
+struct Entry<'a, T> {
+    receiver: &'a mut dyn Receive<Item = T>,
+    closed: bool,
+}
+
+impl<'a, T> Select<'a, T> {
+    pub fn new() -> Self {
+        Select {
+            receivers: Vec::new(),
+            next: 0,
+        }
+    }
+
+    /// Returns the index reported alongside values from this receiver
+    pub fn add(&mut self, receiver: &'a mut dyn Receive<Item = T>) -> usize {
+        self.receivers.push(Entry {
+            receiver,
+            closed: false,
+        });
+        self.receivers.len() - 1
+    }
+
+    /// Next value from any receiver, `None` once all of them are closed
+    pub async fn recv(&mut self) -> Option<(usize, T)> {
+        poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    pub fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<(usize, T)>> {
+        let len = self.receivers.len();
+        for offset in 0..len {
+            let index = (self.next + offset) % len;
+            let entry = &mut self.receivers[index];
+            if entry.closed {
+                continue;
+            }
+            match entry.receiver.poll_recv(cx) {
+                Poll::Ready(Some(value)) => {
+                    self.next = index + 1;
+                    self.cancel_others(index);
+                    return Poll::Ready(Some((index, value)));
+                }
+                Poll::Ready(None) => entry.closed = true,
+                Poll::Pending => {}
+            }
+        }
+
+        if self.receivers.iter().all(|entry| entry.closed) {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn cancel_others(&mut self, winner: usize) {
+        for (index, entry) in self.receivers.iter_mut().enumerate() {
+            if index != winner && !entry.closed {
+                entry.receiver.cancel_recv();
+            }
+        }
+    }
+}
+
+impl<T> Default for Select<'_, T> {
+    fn default() -> Self {
+        Select::new()
+    }
+}
+
+/// One-shot select over a slice of receivers, see [`Select`]
+pub async fn recv_any<T>(receivers: &mut [&mut dyn Receive<Item = T>]) -> Option<(usize, T)> {
+    let mut select = Select::new();
+    for receiver in receivers.iter_mut() {
+        select.add(&mut **receiver);
+    }
+    select.recv().await
+}
@@ -0,0 +1,226 @@
+//! Single-value channel: receivers see the latest value.
+//!
+//! Sending replaces the value instead of queueing it, so a receiver that is
+//! slow only misses intermediate states. Good for sharing state like
+//! configuration or which keys are held down.
+
+use core::{
+    future::poll_fn,
+    ops::Deref,
+    task::{Context, Poll},
+};
+
+use super::SendError;
+use crate::sync::{
+    loom::{Arc, Mutex, MutexGuard},
+    waiters::WaitList,
+};
+
+pub fn channel<T>(value: T) -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Mutex::new(State {
+        value,
+        version: 0,
+        waiters: WaitList::new(),
+        senders: 1,
+        receivers: 1,
+        closed: false,
+    }));
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver {
+            shared,
+            seen: 0,
+            key: None,
+        },
+    )
+}
+
+struct State<T> {
+    value: T,
+    // Bumped on every change
+    version: u64,
+    waiters: WaitList,
+    senders: usize,
+    receivers: usize,
+    // Set by `Sender::close`, acts as if every sender was dropped
+    closed: bool,
+}
+
+/// Read access to the current value, holds the channel lock so keep it short
+pub struct Ref<'a, T> {
+    state: MutexGuard<'a, State<T>>,
+}
+
+impl<T> Deref for Ref<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.state.value
+    }
+}
+
+pub struct Sender<T> {
+    shared: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Sender<T> {
+    /// Replace the value, fails if every receiver is gone
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut state = self.shared.lock();
+        if state.receivers == 0 {
+            return Err(SendError(value));
+        }
+        state.value = value;
+        Self::changed(&mut state);
+        Ok(())
+    }
+
+    /// Replace the value even if nobody is listening
+    pub fn send_replace(&self, value: T) -> T {
+        let mut state = self.shared.lock();
+        let old = core::mem::replace(&mut state.value, value);
+        Self::changed(&mut state);
+        old
+    }
+
+    /// Change the value in place, receivers are only woken if `modify`
+    /// returns `true`
+    pub fn send_if_modified(&self, modify: impl FnOnce(&mut T) -> bool) -> bool {
+        let mut state = self.shared.lock();
+        let modified = modify(&mut state.value);
+        if modified {
+            Self::changed(&mut state);
+        }
+        modified
+    }
+
+    pub fn borrow(&self) -> Ref<'_, T> {
+        Ref {
+            state: self.shared.lock(),
+        }
+    }
+
+    /// New receiver, the current value counts as already seen
+    pub fn subscribe(&self) -> Receiver<T> {
+        let mut state = self.shared.lock();
+        state.receivers += 1;
+        Receiver {
+            shared: self.shared.clone(),
+            seen: state.version,
+            key: None,
+        }
+    }
+
+    pub fn receiver_count(&self) -> usize {
+        self.shared.lock().receivers
+    }
+
+    /// Make `changed` fail from now on, for senders that can't be dropped.
+    ///
+    /// The value can still be read and replaced.
+    pub fn close(&self) {
+        let mut state = self.shared.lock();
+        state.closed = true;
+        state.waiters.notify_all();
+    }
+
+    fn changed(state: &mut State<T>) {
+        state.version += 1;
+        state.waiters.notify_all();
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Sender {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            state.waiters.notify_all();
+        }
+    }
+}
+
+/// Returned by [`Receiver::changed`] once every sender is gone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError;
+
+pub struct Receiver<T> {
+    shared: Arc<Mutex<State<T>>>,
+    // Version of the value we last looked at
+    seen: u64,
+    key: Option<u64>,
+}
+
+impl<T> Receiver<T> {
+    /// Current value, doesn't mark it as seen
+    pub fn borrow(&self) -> Ref<'_, T> {
+        Ref {
+            state: self.shared.lock(),
+        }
+    }
+
+    /// Current value, marking it as seen
+    pub fn borrow_and_update(&mut self) -> Ref<'_, T> {
+        let state = self.shared.lock();
+        self.seen = state.version;
+        Ref { state }
+    }
+
+    pub fn has_changed(&self) -> bool {
+        self.shared.lock().version != self.seen
+    }
+
+    /// Wait for a value newer than the last one seen
+    pub async fn changed(&mut self) -> Result<(), RecvError> {
+        poll_fn(|cx| self.poll_changed(cx)).await
+    }
+
+    pub fn poll_changed(&mut self, cx: &mut Context) -> Poll<Result<(), RecvError>> {
+        let mut state = self.shared.lock();
+        let result = if state.version != self.seen {
+            self.seen = state.version;
+            Ok(())
+        } else if state.senders == 0 || state.closed {
+            Err(RecvError)
+        } else {
+            state.waiters.register(&mut self.key, cx.waker());
+            return Poll::Pending;
+        };
+        if let Some(key) = self.key.take() {
+            state.waiters.remove(key);
+        }
+        Poll::Ready(result)
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().receivers += 1;
+        Receiver {
+            shared: self.shared.clone(),
+            seen: self.seen,
+            key: None,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        if let Some(key) = self.key.take() {
+            state.waiters.remove(key);
+        }
+        state.receivers -= 1;
+    }
+}
@@ -0,0 +1,74 @@
+//!
+//! Clipboard
+//!
+//! One piece of text shared by every task. The [`LineEditor`] copies its
+//! line on Ctrl+C and pastes on Ctrl+V, a [`Ui`] passes the [`COPY`] and
+//! [`PASTE`] hotkeys on to the focused widget. Tasks can use it directly:
+//!
+//! ```ignore
+//! clipboard::copy("hello");
+//! assert_eq!(clipboard::paste(), "hello");
+//!
+//! let mut changes = clipboard::watch();
+//! while changes.changed().await.is_ok() {
+//!     info!("copied {:?}", *changes.borrow());
+//! }
+//! ```
+//!
+//! [`LineEditor`]: crate::editor::LineEditor
+//! [`Ui`]: crate::tui::Ui
+//!
+
+use alloc::string::String;
+
+use pc_keyboard::KeyCode;
+
+use crate::{channel::watch, keyboard::Hotkey};
+
+/// Copies what has the focus
+pub const COPY: Hotkey = Hotkey::new(KeyCode::C).ctrl();
+/// Inserts the clipboard where the focus is
+pub const PASTE: Hotkey = Hotkey::new(KeyCode::V).ctrl();
+
+/// Text longer than this is cut short when copied, in bytes
+pub const MAX_LEN: usize = 64 * 1024;
+
+static CLIPBOARD: spin::Once<watch::Sender<String>> = spin::Once::new();
+
+fn sender() -> &'static watch::Sender<String> {
+    CLIPBOARD.call_once(|| watch::channel(String::new()).0)
+}
+
+/// Put `text` on the clipboard, replacing what was there
+pub fn copy(text: impl Into<String>) {
+    let mut text = text.into();
+    if text.len() > MAX_LEN {
+        let end = text.floor_char_boundary(MAX_LEN);
+        text.truncate(end);
+    }
+    sender().send_if_modified(|current| {
+        if *current == text {
+            return false;
+        }
+        *current = text;
+        true
+    });
+}
+
+/// What was copied last, empty if nothing was
+pub fn paste() -> String {
+    sender().borrow().clone()
+}
+
+pub fn is_empty() -> bool {
+    sender().borrow().is_empty()
+}
+
+pub fn clear() {
+    copy(String::new());
+}
+
+/// Receiver for waiting on the next copy with `changed().await`
+pub fn watch() -> watch::Receiver<String> {
+    sender().subscribe()
+}
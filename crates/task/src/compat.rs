@@ -0,0 +1,25 @@
+//!
+//! Stand-ins for the timer types executor-agnostic crates use, on this
+//! crate's timers
+//!
+//! [`futures_timer::Delay`] and [`async_io::Timer`] have the API of the
+//! crates they're named after, so code written against those builds here,
+//! without their helper threads or reactors, by swapping the `use`:
+//!
+//! ```ignore
+//! use task::compat::futures_timer::Delay;
+//!
+//! Delay::new(Duration::from_millis(100)).await;
+//! ```
+//!
+//! Instants are the crate's [`time::Instant`](crate::time::Instant), which
+//! is there without std too.
+//!
+//! With the `tokio-compat` feature, [`tokio`] goes further: spawning, sleeps,
+//! `mpsc` and `Mutex`, enough for small tokio examples to run as they are.
+//!
+
+pub mod async_io;
+pub mod futures_timer;
+#[cfg(feature = "tokio-compat")]
+pub mod tokio;
@@ -0,0 +1,128 @@
+//! `async_io`'s timer API
+
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_util::Stream;
+
+use crate::{
+    time::Instant,
+    timer::{self, Sleep},
+};
+
+/// Fires once at a deadline, or every period, `async_io::Timer`.
+///
+/// As a future it gives the instant it fired at. A timer that has fired
+/// and has no period never fires again, as a stream it then ends.
+pub struct Timer {
+    // `None` once fired, or for a timer that never fires
+    sleep: Option<Sleep>,
+    period: Option<Duration>,
+}
+
+impl Timer {
+    /// A timer that never fires
+    pub fn never() -> Timer {
+        Timer {
+            sleep: None,
+            period: None,
+        }
+    }
+
+    pub fn after(duration: Duration) -> Timer {
+        Timer::at(Instant::now() + duration)
+    }
+
+    pub fn at(instant: Instant) -> Timer {
+        Timer {
+            sleep: Some(timer::sleep_until(instant)),
+            period: None,
+        }
+    }
+
+    /// Fire every `period`, the first time one `period` from now
+    pub fn interval(period: Duration) -> Timer {
+        Timer::interval_at(Instant::now() + period, period)
+    }
+
+    /// Fire at `start`, then every `period`. Ticks missed while the task
+    /// was busy come in a burst, as in `async_io`.
+    pub fn interval_at(start: Instant, period: Duration) -> Timer {
+        Timer {
+            sleep: Some(timer::sleep_until(start)),
+            period: Some(period),
+        }
+    }
+
+    /// `false` if it has fired and has no period, or was made with [`never`](Timer::never)
+    pub fn will_fire(&self) -> bool {
+        self.sleep.is_some()
+    }
+
+    /// Fire once, `duration` from now. Drops any period.
+    pub fn set_after(&mut self, duration: Duration) {
+        self.set_at(Instant::now() + duration);
+    }
+
+    /// Fire once, at `instant`. Drops any period.
+    pub fn set_at(&mut self, instant: Instant) {
+        *self = Timer::at(instant);
+    }
+
+    pub fn set_interval(&mut self, period: Duration) {
+        *self = Timer::interval(period);
+    }
+
+    pub fn set_interval_at(&mut self, start: Instant, period: Duration) {
+        *self = Timer::interval_at(start, period);
+    }
+
+    fn poll_fire(&mut self, cx: &mut Context) -> Poll<Option<Instant>> {
+        let Some(sleep) = &mut self.sleep else {
+            return Poll::Ready(None);
+        };
+        if Pin::new(&mut *sleep).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let fired = sleep.deadline();
+        match self.period {
+            Some(period) => sleep.reset(fired + period),
+            None => self.sleep = None,
+        }
+        Poll::Ready(Some(fired))
+    }
+}
+
+impl Future for Timer {
+    type Output = Instant;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Instant> {
+        match self.get_mut().poll_fire(cx) {
+            Poll::Ready(Some(fired)) => Poll::Ready(fired),
+            // Never fires again
+            Poll::Ready(None) | Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Stream for Timer {
+    type Item = Instant;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Instant>> {
+        self.get_mut().poll_fire(cx)
+    }
+}
+
+impl fmt::Debug for Timer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Timer")
+            .field("deadline", &self.sleep.as_ref().map(Sleep::deadline))
+            .field("period", &self.period)
+            .finish()
+    }
+}
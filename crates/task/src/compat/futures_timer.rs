@@ -0,0 +1,48 @@
+//! `futures_timer`'s API
+
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::{
+    time::Instant,
+    timer::{self, Sleep},
+};
+
+/// Completes once its duration has passed, `futures_timer::Delay`
+pub struct Delay {
+    sleep: Sleep,
+}
+
+impl Delay {
+    pub fn new(duration: Duration) -> Delay {
+        Delay {
+            sleep: timer::sleep(duration),
+        }
+    }
+
+    /// Complete `duration` from now instead, even if it already has
+    pub fn reset(&mut self, duration: Duration) {
+        self.sleep.reset(Instant::now() + duration);
+    }
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        Pin::new(&mut self.sleep).poll(cx)
+    }
+}
+
+impl fmt::Debug for Delay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Delay")
+            .field("deadline", &self.sleep.deadline())
+            .finish()
+    }
+}
@@ -0,0 +1,27 @@
+//! A few of tokio's APIs, under the same paths:
+//!
+//! ```ignore
+//! use task::compat::tokio::{self, sync::mpsc, time};
+//!
+//! let (sender, mut receiver) = mpsc::channel(8);
+//! let producer = tokio::spawn(async move {
+//!     for n in 0..3 {
+//!         sender.send(n).await.unwrap();
+//!         time::sleep(Duration::from_millis(10)).await;
+//!     }
+//! });
+//! while let Some(n) = receiver.recv().await {
+//!     println!("{}", n);
+//! }
+//! producer.await.unwrap();
+//! ```
+//!
+//! Only what maps onto something here is covered. There's no runtime to
+//! build, tasks go to the executor polling the caller, see
+//! [`executor::spawn`](crate::executor::spawn).
+
+pub mod sync;
+pub mod task;
+pub mod time;
+
+pub use task::{spawn, spawn_local};
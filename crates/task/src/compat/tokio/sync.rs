@@ -0,0 +1,5 @@
+//! `tokio::sync`
+
+pub mod mpsc;
+
+pub use crate::sync::{Mutex, MutexGuard, Notify, Semaphore};
@@ -0,0 +1,40 @@
+//! `tokio::sync::mpsc`, on the crate's [`mpmc`] channel
+
+use crate::channel::mpmc;
+pub use crate::channel::{
+    SendError,
+    mpmc::{Receiver, Sender, channel},
+};
+
+pub type UnboundedReceiver<T> = Receiver<T>;
+
+pub fn unbounded_channel<T>() -> (UnboundedSender<T>, UnboundedReceiver<T>) {
+    let (sender, receiver) = mpmc::unbounded();
+    (UnboundedSender { sender }, receiver)
+}
+
+/// Sends without waiting, there's always room
+pub struct UnboundedSender<T> {
+    sender: Sender<T>,
+}
+
+impl<T> UnboundedSender<T> {
+    /// Fails if every receiver is gone
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.sender
+            .try_send(value)
+            .map_err(|err| SendError(err.into_inner()))
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+}
+
+impl<T> Clone for UnboundedSender<T> {
+    fn clone(&self) -> Self {
+        UnboundedSender {
+            sender: self.sender.clone(),
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! `tokio::task`
+
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pub use crate::future::yield_now;
+use crate::{
+    LocalTask, Task, TaskId,
+    channel::oneshot,
+    executor::{self, TaskStatus},
+};
+
+/// Run `future` as a task of its own, on the executor polling the caller
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    let task = Task::new(async move {
+        let _ = sender.send(future.await);
+    });
+    let id = task.id();
+    executor::spawn(task);
+    JoinHandle { id, receiver }
+}
+
+/// [`spawn`] for a future that isn't `Send`
+pub fn spawn_local<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + 'static,
+    F::Output: 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    let task = LocalTask::new(async move {
+        let _ = sender.send(future.await);
+    });
+    let id = task.id();
+    executor::spawn_local(task);
+    JoinHandle { id, receiver }
+}
+
+/// Awaits a spawned task's output. Dropping it leaves the task running.
+pub struct JoinHandle<T> {
+    id: TaskId,
+    receiver: oneshot::Receiver<T>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// Drop the task the next time its executor gets to it
+    pub fn abort(&self) {
+        executor::abort(self.id);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.receiver.is_closed()
+    }
+
+    /// See [`executor::status`]
+    pub fn status(&self) -> Option<TaskStatus> {
+        executor::status(self.id)
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let id = self.id;
+        Pin::new(&mut self.receiver)
+            .poll(cx)
+            .map(|output| output.ok_or(JoinError { id }))
+    }
+}
+
+impl<T> fmt::Debug for JoinHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JoinHandle").field("id", &self.id).finish()
+    }
+}
+
+/// The task ended without an output: it was aborted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JoinError {
+    id: TaskId,
+}
+
+impl JoinError {
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    /// Always, tasks here can't end any other way without their output
+    pub fn is_cancelled(&self) -> bool {
+        true
+    }
+
+    pub fn is_panic(&self) -> bool {
+        false
+    }
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "task {} was cancelled", self.id)
+    }
+}
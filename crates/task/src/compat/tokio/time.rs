@@ -0,0 +1,49 @@
+//! `tokio::time`
+
+pub use core::time::Duration;
+
+use crate::compat::async_io::Timer;
+pub use crate::{
+    time::Instant,
+    timer::{Sleep, Timeout, sleep, sleep_until, timeout},
+};
+
+pub mod error {
+    pub use crate::timer::Elapsed;
+}
+
+/// Ticks every `period`, the first one right away
+pub fn interval(period: Duration) -> Interval {
+    interval_at(Instant::now(), period)
+}
+
+/// Ticks at `start`, then every `period`
+pub fn interval_at(start: Instant, period: Duration) -> Interval {
+    assert!(!period.is_zero(), "interval period must be non-zero");
+    Interval {
+        timer: Timer::interval_at(start, period),
+        period,
+    }
+}
+
+/// Returned by [`interval`]. Missed ticks come in a burst, tokio's default.
+#[derive(Debug)]
+pub struct Interval {
+    timer: Timer,
+    period: Duration,
+}
+
+impl Interval {
+    pub async fn tick(&mut self) -> Instant {
+        (&mut self.timer).await
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// Tick next one `period` from now
+    pub fn reset(&mut self) {
+        self.timer = Timer::interval(self.period);
+    }
+}
@@ -0,0 +1,237 @@
+//! Boot settings, read from a text config rather than constants in the code.
+//!
+//! A subset of TOML: `[section]` headers, `key = value` lines, `#` comments.
+//! Values are integers or strings, quoted or not:
+//!
+//! ```text
+//! [executor]
+//! # Woken tasks each executor holds at once
+//! queue_capacity = 256
+//!
+//! [keyboard]
+//! layout = "Uk105Key"
+//! queue_capacity = 64
+//! overflow = "drop-oldest"
+//!
+//! [log]
+//! level = "info"
+//!
+//! [log.targets]
+//! "task::net" = "trace"
+//!
+//! [net]
+//! address = "10.0.2.15/24"
+//! gateway = "10.0.2.2"
+//! ```
+//!
+//! The `[keyboard]` and `[net]` sections need their features, without them
+//! their keys are unknown. Whatever is left out keeps its default. Parse
+//! the text, then
+//! [`apply`](Config::apply) it before spawning anything:
+//!
+//! ```ignore
+//! let config: Config = include_str!("../boot.conf").parse()?;
+//! config.apply();
+//! ```
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt, str::FromStr};
+
+#[cfg(feature = "net")]
+use smoltcp::wire::{Ipv4Address, Ipv4Cidr};
+
+#[cfg(feature = "keyboard")]
+use crate::keyboard::{self, Layout, Overflow, QueueConfig};
+use crate::{
+    executor,
+    log::{self, Level},
+};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    /// See [`executor::set_queue_capacity`]
+    pub executor_queue_capacity: Option<usize>,
+    #[cfg(feature = "keyboard")]
+    pub keyboard_layout: Option<Layout>,
+    #[cfg(feature = "keyboard")]
+    pub keyboard_queue_capacity: Option<usize>,
+    #[cfg(feature = "keyboard")]
+    pub keyboard_overflow: Option<Overflow>,
+    pub log_level: Option<Level>,
+    /// Module path prefixes and their levels, see [`log::set_target_level`]
+    pub log_targets: Vec<(String, Level)>,
+    #[cfg(feature = "net")]
+    pub net_address: Option<Ipv4Cidr>,
+    #[cfg(feature = "net")]
+    pub net_gateway: Option<Ipv4Address>,
+}
+
+impl Config {
+    /// Hand the settings to the subsystems. The keyboard queue can only be
+    /// set up before it's first used, past that its settings are ignored
+    /// with a warning.
+    ///
+    /// The network ones go through [`net`](Config::net) instead, when the
+    /// interface is made.
+    pub fn apply(&self) {
+        if let Some(capacity) = self.executor_queue_capacity {
+            executor::set_queue_capacity(capacity);
+        }
+        #[cfg(feature = "keyboard")]
+        self.apply_keyboard();
+        if let Some(level) = self.log_level {
+            log::set_max_level(level);
+        }
+        for (prefix, level) in &self.log_targets {
+            log::set_target_level(prefix, *level);
+        }
+    }
+
+    #[cfg(feature = "keyboard")]
+    fn apply_keyboard(&self) {
+        if let Some(layout) = self.keyboard_layout {
+            keyboard::set_layout(layout);
+        }
+        if self.keyboard_queue_capacity.is_some() || self.keyboard_overflow.is_some() {
+            let default = QueueConfig::new();
+            let queue = QueueConfig {
+                capacity: self.keyboard_queue_capacity.unwrap_or(default.capacity),
+                overflow: self.keyboard_overflow.unwrap_or(default.overflow),
+            };
+            if keyboard::set_queue_config(queue).is_err() {
+                log::warn!("config: the scancode queue is in use already");
+            }
+        }
+    }
+
+    /// `base` with the addresses the config gives
+    #[cfg(feature = "net")]
+    pub fn net(&self, mut base: crate::net::Config) -> crate::net::Config {
+        if let Some(address) = self.net_address {
+            base = base.with_address(address);
+        }
+        if let Some(gateway) = self.net_gateway {
+            base = base.with_gateway(gateway);
+        }
+        base
+    }
+
+    fn set(&mut self, section: &str, key: &str, value: &str) -> Result<(), Problem> {
+        fn parse<T: FromStr>(value: &str) -> Result<T, Problem> {
+            value.parse().map_err(|_| Problem::BadValue)
+        }
+        fn level(value: &str) -> Result<Level, Problem> {
+            Level::from_name(value).ok_or(Problem::BadValue)
+        }
+        match (section, key) {
+            ("executor", "queue_capacity") => self.executor_queue_capacity = Some(parse(value)?),
+            #[cfg(feature = "keyboard")]
+            ("keyboard", "layout") => {
+                self.keyboard_layout = Some(Layout::from_name(value).ok_or(Problem::BadValue)?)
+            }
+            #[cfg(feature = "keyboard")]
+            ("keyboard", "queue_capacity") => self.keyboard_queue_capacity = Some(parse(value)?),
+            #[cfg(feature = "keyboard")]
+            ("keyboard", "overflow") => {
+                self.keyboard_overflow = Some(match value {
+                    "drop-newest" => Overflow::DropNewest,
+                    "drop-oldest" => Overflow::DropOldest,
+                    "grow" => Overflow::Grow,
+                    _ => return Err(Problem::BadValue),
+                })
+            }
+            ("log", "level") => self.log_level = Some(level(value)?),
+            ("log.targets", prefix) => self.log_targets.push((prefix.to_string(), level(value)?)),
+            #[cfg(feature = "net")]
+            ("net", "address") => self.net_address = Some(parse(value)?),
+            #[cfg(feature = "net")]
+            ("net", "gateway") => self.net_gateway = Some(parse(value)?),
+            _ => return Err(Problem::UnknownKey),
+        }
+        Ok(())
+    }
+}
+
+// What's wrong with a line, before its number is known
+enum Problem {
+    UnknownKey,
+    BadValue,
+}
+
+impl FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(text: &str) -> Result<Self, ConfigError> {
+        let mut config = Config::default();
+        let mut section = "";
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(header) = line.strip_prefix('[') {
+                section = header
+                    .strip_suffix(']')
+                    .ok_or(ConfigError::Syntax(line_number))?
+                    .trim();
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(ConfigError::Syntax(line_number))?;
+            let (key, value) = (unquote(key.trim()), unquote(value.trim()));
+            config
+                .set(section, key, value)
+                .map_err(|problem| match problem {
+                    Problem::UnknownKey => ConfigError::UnknownKey(line_number),
+                    Problem::BadValue => ConfigError::BadValue(line_number),
+                })?;
+        }
+        Ok(config)
+    }
+}
+
+fn unquote(text: &str) -> &str {
+    text.strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .unwrap_or(text)
+}
+
+/// Read and parse the config at `path`, from the ramfs for instance
+#[cfg(feature = "fs")]
+pub async fn read(path: &str) -> Result<Config, ConfigError> {
+    let text = crate::fs::read_to_string(path)
+        .await
+        .map_err(ConfigError::Fs)?;
+    text.parse()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigError {
+    /// Line isn't `[section]` or `key = value`
+    Syntax(usize),
+    /// No such setting in that section
+    UnknownKey(usize),
+    /// The setting can't take that value
+    BadValue(usize),
+    #[cfg(feature = "fs")]
+    Fs(crate::fs::Error),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Syntax(line) => {
+                write!(f, "line {line}: expected `[section]` or `key = value`")
+            }
+            ConfigError::UnknownKey(line) => write!(f, "line {line}: unknown setting"),
+            ConfigError::BadValue(line) => write!(f, "line {line}: bad value"),
+            #[cfg(feature = "fs")]
+            ConfigError::Fs(err) => write!(f, "reading the config: {}", err),
+        }
+    }
+}
@@ -0,0 +1,256 @@
+//!
+//! Text console shared by all tasks
+//!
+//! Output goes through one [`TextOutput`], stdout until another one is set
+//! with [`set_output`]. Without std it's dropped until then, so set one
+//! early. The output sits behind an async [`Mutex`], so each
+//! write lands in one piece even when many tasks print at once, and a task
+//! waiting for the console is parked instead of spinning.
+//!
+//! ```ignore
+//! console::println!("{} tasks running", count).await;
+//! ```
+//!
+//! Write ANSI escapes for colors and cursor movement, [`VgaText`] interprets
+//! them and a terminal on stdout does anyway.
+//!
+//! Spawn [`scroll_keys`] to page through the console's scrollback with
+//! Shift+PageUp and Shift+PageDown.
+//!
+//! The output shows one of several [virtual terminals](vt), each task
+//! writes to its own.
+//!
+
+mod ansi;
+mod vga;
+pub mod vt;
+
+use alloc::{boxed::Box, string::String};
+use core::fmt;
+
+#[cfg(feature = "keyboard")]
+use pc_keyboard::KeyCode;
+
+use crate::sync::{Mutex, MutexGuard};
+#[cfg(feature = "keyboard")]
+use crate::{
+    future::race,
+    keyboard::{Hotkey, on_hotkey},
+};
+
+pub use vga::{Color, DEFAULT_SCROLLBACK, VgaText};
+
+/// Somewhere text can be shown
+pub trait TextOutput: Send {
+    fn write_str(&mut self, text: &str);
+
+    /// Show buffered text, called at the end of every console write and by
+    /// [`Console::present`]
+    fn flush(&mut self) {}
+
+    /// Move the view `lines` back into scrollback, forward if negative.
+    ///
+    /// Outputs without their own scrollback ignore it.
+    fn scroll(&mut self, _lines: isize) {}
+
+    /// Blank the screen and forget the scrollback, before another
+    /// [virtual terminal](vt)'s output is replayed
+    fn reset(&mut self) {
+        self.write_str("\x1b[0m\x1b[H\x1b[2J\x1b[3J");
+    }
+}
+
+/// The process's standard output, for the hosted build
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stdout;
+
+#[cfg(feature = "std")]
+impl TextOutput for Stdout {
+    fn write_str(&mut self, text: &str) {
+        use std::io::Write;
+        // Nowhere to report a failing stdout
+        let _ = std::io::stdout().write_all(text.as_bytes());
+    }
+
+    fn flush(&mut self) {
+        use std::io::Write;
+        let _ = std::io::stdout().flush();
+    }
+}
+
+/// Where output goes before [`set_output`] without std: nowhere
+#[cfg(not(feature = "std"))]
+struct Discard;
+
+#[cfg(not(feature = "std"))]
+impl TextOutput for Discard {
+    fn write_str(&mut self, _text: &str) {}
+}
+
+/// The output and what each virtual terminal wrote to it
+struct Screen {
+    // `None` until the first write or `set_output`
+    output: Option<Box<dyn TextOutput>>,
+    transcripts: [vt::Transcript; vt::COUNT],
+}
+
+static CONSOLE: Mutex<Screen> = Mutex::new(Screen {
+    output: None,
+    transcripts: [const { vt::Transcript::new() }; vt::COUNT],
+});
+
+/// Send console output to `output` from now on
+pub async fn set_output(output: impl TextOutput + 'static) {
+    let mut console = CONSOLE.lock().await;
+    if let Some(previous) = console.output.as_mut() {
+        previous.flush();
+    }
+    console.output = Some(Box::new(output));
+}
+
+/// Exclusive access to the console for several writes in a row.
+///
+/// Implements [`fmt::Write`], so `write!` works on it. Output is flushed when
+/// the guard is dropped.
+pub struct Console {
+    screen: MutexGuard<'static, Screen>,
+    // The virtual terminal written to
+    vt: usize,
+}
+
+/// Wait for the console, other tasks' writes queue up behind the guard.
+/// Writes go to the current task's [virtual terminal](vt).
+pub async fn lock() -> Console {
+    lock_vt(vt::current()).await
+}
+
+/// [`lock`] for writing to virtual terminal `vt`, whichever task writes.
+/// Panics if it isn't below [`vt::COUNT`].
+pub async fn lock_vt(vt: usize) -> Console {
+    assert!(vt < vt::COUNT, "no virtual terminal {}", vt);
+    Console::new(CONSOLE.lock().await, vt)
+}
+
+/// The console if nobody holds it, for writing where waiting isn't an
+/// option
+pub fn try_lock() -> Option<Console> {
+    Some(Console::new(CONSOLE.try_lock()?, vt::current()))
+}
+
+/// Show `vt` instead of the terminal on screen, see [`vt::switch`]
+async fn show(vt: usize) {
+    let mut console = Console::new(CONSOLE.lock().await, vt);
+    if vt::active() == vt {
+        return;
+    }
+    vt::set_active(vt);
+    let Screen {
+        output,
+        transcripts,
+    } = &mut *console.screen;
+    let output = output.as_deref_mut().expect("console output set in new()");
+    output.reset();
+    output.write_str(transcripts[vt].as_str());
+}
+
+impl Console {
+    fn new(mut screen: MutexGuard<'static, Screen>, vt: usize) -> Console {
+        #[cfg(feature = "std")]
+        screen.output.get_or_insert_with(|| Box::new(Stdout));
+        #[cfg(not(feature = "std"))]
+        screen.output.get_or_insert_with(|| Box::new(Discard));
+        Console { screen, vt }
+    }
+
+    /// Show what was written so far without letting go of the console, for
+    /// redrawing a screen repeatedly. Dropping the guard does this too.
+    pub fn present(&mut self) {
+        self.output().flush();
+    }
+
+    /// The terminal this guard writes to
+    pub fn vt(&self) -> usize {
+        self.vt
+    }
+
+    fn output(&mut self) -> &mut dyn TextOutput {
+        self.screen
+            .output
+            .as_deref_mut()
+            .expect("console output set in new()")
+    }
+}
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        self.screen.transcripts[self.vt].push(text);
+        // The others are replayed when switched to
+        if self.vt == vt::active() {
+            self.output().write_str(text);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Console {
+    fn drop(&mut self) {
+        self.output().flush();
+    }
+}
+
+/// Write `text` in one piece
+pub async fn write_str(text: &str) {
+    fmt::Write::write_str(&mut lock().await, text).expect("console writes don't fail");
+}
+
+/// Scroll the view of the terminal on screen, see [`TextOutput::scroll`]
+pub async fn scroll(lines: isize) {
+    lock().await.output().scroll(lines);
+}
+
+/// Lines moved per Shift+PageUp/PageDown, half a VGA screen
+#[cfg(feature = "keyboard")]
+const PAGE: isize = 12;
+
+/// Scroll the console with Shift+PageUp and Shift+PageDown, runs until the
+/// keyboard dispatcher stops.
+///
+/// Shifted so plain PageUp/PageDown still reach applications.
+#[cfg(feature = "keyboard")]
+pub async fn scroll_keys() {
+    race(
+        on_hotkey(Hotkey::new(KeyCode::PageUp).shift(), || scroll(PAGE)),
+        on_hotkey(Hotkey::new(KeyCode::PageDown).shift(), || scroll(-PAGE)),
+    )
+    .await;
+}
+
+/// Used by [`print!`], the text is formatted before waiting for the console
+#[doc(hidden)]
+pub async fn write_string(text: String) {
+    write_str(&text).await;
+}
+
+/// Like `std::print!` but through the console, returns a future to `.await`
+#[macro_export]
+macro_rules! __console_print {
+    ($($arg:tt)*) => {
+        $crate::console::write_string($crate::__private::format!($($arg)*))
+    };
+}
+
+/// Like `std::println!` but through the console, returns a future to `.await`
+#[macro_export]
+macro_rules! __console_println {
+    () => {
+        $crate::console::write_str("\n")
+    };
+    ($($arg:tt)*) => {{
+        let mut text = $crate::__private::format!($($arg)*);
+        text.push('\n');
+        $crate::console::write_string(text)
+    }};
+}
+
+pub use crate::{__console_print as print, __console_println as println};
@@ -0,0 +1,103 @@
+//! Parser for the ANSI escape sequences the VGA console understands
+
+/// Most parameters any supported sequence takes, extras are dropped
+const MAX_PARAMS: usize = 8;
+
+/// What a byte of output asks for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Action {
+    Print(u8),
+    /// `\n`, `\r`, backspace and the like
+    Control(u8),
+    Csi(Csi),
+}
+
+/// A finished `ESC [ params final` sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Csi {
+    params: [u16; MAX_PARAMS],
+    len: usize,
+    pub(crate) action: u8,
+}
+
+impl Csi {
+    /// Parameter `index`, `default` if it was left out or given as 0
+    pub(crate) fn param(&self, index: usize, default: u16) -> u16 {
+        match self.params().get(index) {
+            Some(&value) if value != 0 => value,
+            _ => default,
+        }
+    }
+
+    pub(crate) fn params(&self) -> &[u16] {
+        &self.params[..self.len]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    // `private` sequences (`ESC [ ?`) are parsed but dropped
+    Csi { private: bool },
+}
+
+pub(crate) struct Parser {
+    state: State,
+    params: [u16; MAX_PARAMS],
+    len: usize,
+}
+
+impl Parser {
+    pub(crate) const fn new() -> Self {
+        Parser {
+            state: State::Ground,
+            params: [0; MAX_PARAMS],
+            len: 0,
+        }
+    }
+
+    /// Feed one byte, `None` while in the middle of a sequence
+    pub(crate) fn advance(&mut self, byte: u8) -> Option<Action> {
+        match (self.state, byte) {
+            (State::Ground, 0x1b) => self.state = State::Escape,
+            (State::Ground, 0x00..=0x1f | 0x7f) => return Some(Action::Control(byte)),
+            (State::Ground, _) => return Some(Action::Print(byte)),
+            (State::Escape, b'[') => {
+                self.params = [0; MAX_PARAMS];
+                self.len = 0;
+                self.state = State::Csi { private: false };
+            }
+            // Other escapes aren't supported, skip the byte after ESC
+            (State::Escape, _) => self.state = State::Ground,
+            (State::Csi { .. }, b'?') => self.state = State::Csi { private: true },
+            (State::Csi { .. }, b'0'..=b'9') => {
+                if self.len == 0 {
+                    self.len = 1;
+                }
+                if let Some(param) = self.params.get_mut(self.len - 1) {
+                    *param = param
+                        .saturating_mul(10)
+                        .saturating_add(u16::from(byte - b'0'));
+                }
+            }
+            (State::Csi { .. }, b';') => {
+                // An empty first parameter still counts
+                self.len = (self.len.max(1) + 1).min(MAX_PARAMS + 1);
+            }
+            (State::Csi { private }, 0x40..=0x7e) => {
+                self.state = State::Ground;
+                if !private {
+                    return Some(Action::Csi(Csi {
+                        params: self.params,
+                        len: self.len.min(MAX_PARAMS),
+                        action: byte,
+                    }));
+                }
+            }
+            // Intermediate bytes, nothing supported uses them
+            (State::Csi { .. }, _) => {}
+        }
+        None
+    }
+}
@@ -0,0 +1,319 @@
+use alloc::{collections::VecDeque, vec, vec::Vec};
+use core::ptr;
+
+use super::{
+    TextOutput,
+    ansi::{Action, Csi, Parser},
+};
+
+const WIDTH: usize = 80;
+const HEIGHT: usize = 25;
+/// Lines kept after scrolling off the top, a few screens' worth
+pub const DEFAULT_SCROLLBACK: usize = 500;
+
+/// The 16 colors of VGA text mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Color {
+    Black = 0,
+    Blue = 1,
+    Green = 2,
+    Cyan = 3,
+    Red = 4,
+    Magenta = 5,
+    Brown = 6,
+    LightGray = 7,
+    DarkGray = 8,
+    LightBlue = 9,
+    LightGreen = 10,
+    LightCyan = 11,
+    LightRed = 12,
+    Pink = 13,
+    Yellow = 14,
+    White = 15,
+}
+
+/// VGA colors in ANSI order: black, red, green, yellow, blue, magenta, cyan,
+/// white
+const ANSI_COLORS: [Color; 8] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Brown,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::LightGray,
+];
+
+/// The 80x25 VGA text buffer.
+///
+/// Understands the ANSI escapes a terminal program needs most: cursor
+/// movement (`CSI A`/`B`/`C`/`D`/`H`), clearing (`CSI J`/`K`) and colors
+/// (`CSI m` with the 8 normal and 8 bright colors). Other sequences are
+/// dropped. Bytes outside printable ASCII are shown as `■`.
+///
+/// Lines scrolling off the top go into a scrollback buffer that can be
+/// brought back with [`TextOutput::scroll`]. While scrolled back, output
+/// still goes to the live screen and shows up when scrolling down again.
+///
+/// Text is drawn off screen and copied to the buffer on
+/// [`TextOutput::flush`], which the console does once per write, so another
+/// task never sees a line half written.
+pub struct VgaText {
+    buffer: *mut u16,
+    // What the screen shows when not scrolled back, the buffer only mirrors it
+    screen: Vec<u16>,
+    history: VecDeque<[u16; WIDTH]>,
+    history_limit: usize,
+    // How many lines the view is scrolled back into history
+    view: usize,
+    row: usize,
+    column: usize,
+    foreground: Color,
+    background: Color,
+    // Colors set by `set_color`, what `CSI 0 m` goes back to
+    default: (Color, Color),
+    parser: Parser,
+    // `screen` changed since it was last copied to the buffer
+    dirty: bool,
+}
+
+// The buffer is only touched through `&mut self`
+unsafe impl Send for VgaText {}
+
+impl VgaText {
+    /// Usual address of the text buffer on a PC
+    pub const ADDRESS: usize = 0xb8000;
+
+    /// Starts with a blank screen.
+    ///
+    /// # Safety
+    ///
+    /// `address` must point to an 80x25 text buffer that nothing else
+    /// writes to.
+    pub unsafe fn new(address: usize) -> Self {
+        let mut vga = VgaText {
+            buffer: address as *mut u16,
+            // Light gray spaces on black
+            screen: vec![0x0720; WIDTH * HEIGHT],
+            history: VecDeque::new(),
+            history_limit: DEFAULT_SCROLLBACK,
+            view: 0,
+            row: HEIGHT - 1,
+            column: 0,
+            foreground: Color::LightGray,
+            background: Color::Black,
+            default: (Color::LightGray, Color::Black),
+            parser: Parser::new(),
+            dirty: false,
+        };
+        vga.redraw();
+        vga
+    }
+
+    /// Colors for text written from now on, and for resets by escapes
+    pub fn set_color(&mut self, foreground: Color, background: Color) {
+        self.default = (foreground, background);
+        (self.foreground, self.background) = self.default;
+    }
+
+    /// Keep `lines` lines of scrollback, `0` turns it off
+    pub fn set_scrollback(&mut self, lines: usize) {
+        self.history_limit = lines;
+        while self.history.len() > lines {
+            self.history.pop_front();
+        }
+        self.view = self.view.min(lines);
+        self.redraw();
+    }
+
+    pub fn clear(&mut self) {
+        self.clear_cells(0, HEIGHT * WIDTH);
+        self.row = 0;
+        self.column = 0;
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        match self.parser.advance(byte) {
+            Some(Action::Print(byte)) => self.print(byte),
+            Some(Action::Control(b'\n')) => self.new_line(),
+            Some(Action::Control(b'\r')) => self.column = 0,
+            Some(Action::Control(0x08)) => self.column = self.column.saturating_sub(1),
+            Some(Action::Control(_)) => {}
+            Some(Action::Csi(csi)) => self.escape(csi),
+            None => {}
+        }
+    }
+
+    fn print(&mut self, byte: u8) {
+        if self.column == WIDTH {
+            self.new_line();
+        }
+        let byte = if byte.is_ascii() { byte } else { 0xfe };
+        self.set(self.row * WIDTH + self.column, self.cell(byte));
+        self.column += 1;
+    }
+
+    fn escape(&mut self, csi: Csi) {
+        let count = usize::from(csi.param(0, 1));
+        let cursor = self.row * WIDTH + self.column;
+        match csi.action {
+            b'A' => self.row = self.row.saturating_sub(count),
+            b'B' => self.row = (self.row + count).min(HEIGHT - 1),
+            b'C' => self.column = (self.column + count).min(WIDTH - 1),
+            b'D' => self.column = self.column.saturating_sub(count),
+            // 1-based row and column
+            b'H' | b'f' => {
+                self.row = usize::from(csi.param(0, 1) - 1).min(HEIGHT - 1);
+                self.column = usize::from(csi.param(1, 1) - 1).min(WIDTH - 1);
+            }
+            b'J' => match csi.params().first().copied().unwrap_or(0) {
+                0 => self.clear_cells(cursor, HEIGHT * WIDTH),
+                1 => self.clear_cells(0, cursor + 1),
+                _ => self.clear_cells(0, HEIGHT * WIDTH),
+            },
+            b'K' => {
+                let line = self.row * WIDTH;
+                match csi.params().first().copied().unwrap_or(0) {
+                    0 => self.clear_cells(cursor, line + WIDTH),
+                    1 => self.clear_cells(line, cursor + 1),
+                    _ => self.clear_cells(line, line + WIDTH),
+                }
+            }
+            b'm' => self.select_graphic_rendition(csi.params()),
+            _ => {}
+        }
+    }
+
+    fn select_graphic_rendition(&mut self, params: &[u16]) {
+        // `CSI m` alone is a reset
+        if params.is_empty() {
+            (self.foreground, self.background) = self.default;
+        }
+        for &param in params {
+            match param {
+                0 => (self.foreground, self.background) = self.default,
+                // No bold font, brighten the color instead like most consoles
+                1 => self.foreground = brighten(self.foreground),
+                30..=37 => self.foreground = ANSI_COLORS[usize::from(param - 30)],
+                39 => self.foreground = self.default.0,
+                40..=47 => self.background = ANSI_COLORS[usize::from(param - 40)],
+                49 => self.background = self.default.1,
+                90..=97 => self.foreground = brighten(ANSI_COLORS[usize::from(param - 90)]),
+                100..=107 => self.background = brighten(ANSI_COLORS[usize::from(param - 100)]),
+                _ => {}
+            }
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.column = 0;
+        if self.row < HEIGHT - 1 {
+            self.row += 1;
+            return;
+        }
+        if self.history_limit > 0 {
+            if self.history.len() == self.history_limit {
+                self.history.pop_front();
+            }
+            let mut line = [0; WIDTH];
+            line.copy_from_slice(&self.screen[..WIDTH]);
+            self.history.push_back(line);
+            // Keep a scrolled back view on the same lines
+            if self.view > 0 {
+                self.view = (self.view + 1).min(self.history.len());
+            }
+        }
+        self.screen.copy_within(WIDTH.., 0);
+        let blank = self.cell(b' ');
+        self.screen[(HEIGHT - 1) * WIDTH..].fill(blank);
+        self.dirty = true;
+    }
+
+    /// Copy what the view shows to the buffer
+    fn redraw(&mut self) {
+        // History lines from the top, then as much of the screen as fits
+        let first = self.history.len() - self.view;
+        for row in 0..HEIGHT {
+            let line = first + row;
+            let cells = match self.history.get(line) {
+                Some(cells) => &cells[..],
+                None => {
+                    let start = (line - self.history.len()) * WIDTH;
+                    &self.screen[start..start + WIDTH]
+                }
+            };
+            // Volatile so the compiler doesn't drop writes it never sees
+            // read back
+            for (column, &cell) in cells.iter().enumerate() {
+                unsafe { ptr::write_volatile(self.buffer.add(row * WIDTH + column), cell) }
+            }
+        }
+        self.dirty = false;
+    }
+
+    /// Blank cells `start..end`, counted from the top left
+    fn clear_cells(&mut self, start: usize, end: usize) {
+        for index in start..end.min(HEIGHT * WIDTH) {
+            self.set(index, self.cell(b' '));
+        }
+    }
+
+    fn cell(&self, byte: u8) -> u16 {
+        let attribute = (self.background as u8) << 4 | self.foreground as u8;
+        u16::from(attribute) << 8 | u16::from(byte)
+    }
+
+    fn set(&mut self, index: usize, cell: u16) {
+        self.screen[index] = cell;
+        self.dirty = true;
+    }
+}
+
+impl TextOutput for VgaText {
+    fn write_str(&mut self, text: &str) {
+        for byte in text.bytes() {
+            self.write_byte(byte);
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.dirty {
+            self.redraw();
+        }
+    }
+
+    fn scroll(&mut self, lines: isize) {
+        let view = self
+            .view
+            .saturating_add_signed(lines)
+            .min(self.history.len());
+        if view != self.view {
+            self.view = view;
+            self.redraw();
+        }
+    }
+
+    fn reset(&mut self) {
+        self.parser = Parser::new();
+        (self.foreground, self.background) = self.default;
+        self.history.clear();
+        self.view = 0;
+        self.clear();
+    }
+}
+
+const fn brighten(color: Color) -> Color {
+    match color {
+        Color::Black => Color::DarkGray,
+        Color::Blue => Color::LightBlue,
+        Color::Green => Color::LightGreen,
+        Color::Cyan => Color::LightCyan,
+        Color::Red => Color::LightRed,
+        Color::Magenta => Color::Pink,
+        Color::Brown => Color::Yellow,
+        Color::LightGray => Color::White,
+        bright => bright,
+    }
+}
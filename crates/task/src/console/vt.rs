@@ -0,0 +1,131 @@
+//!
+//! Virtual terminals
+//!
+//! [`COUNT`] consoles share the screen like Linux's VTs, one shown at a
+//! time. A task writes to the one it was spawned on with
+//! [`Task::with_vt`](crate::Task::with_vt), or the one of the task that
+//! created it, or else the first. Each keeps the tail of its output, which
+//! is replayed when [`switch`] brings it back on screen, so it also has a
+//! scrollback of its own.
+//!
+//! ```ignore
+//! for vt in 1..vt::COUNT {
+//!     executor::spawn_local(LocalTask::new(shell::run_shell(Tty::vt(vt))).with_vt(vt));
+//! }
+//! executor::spawn(Task::new(vt::switch_keys()).with_name("vt"));
+//! ```
+//!
+//! Keys and Ctrl+C go to the one on screen, each has a
+//! [`Foreground`](crate::signal::Foreground) of its own.
+//!
+
+use alloc::{collections::BTreeMap, string::String};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "keyboard")]
+use pc_keyboard::KeyCode;
+
+use crate::{TaskId, context};
+#[cfg(feature = "keyboard")]
+use crate::{
+    future::race,
+    keyboard::{Hotkey, on_hotkey},
+};
+
+/// Virtual terminals there are, numbered from 0
+pub const COUNT: usize = 4;
+
+/// Output each terminal keeps for replaying, a few screens' worth
+const SCROLLBACK_BYTES: usize = 16 * 1024;
+
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+// Tasks with a terminal of their own, the others write to the first
+static OWNERS: spin::Mutex<BTreeMap<TaskId, usize>> = spin::Mutex::new(BTreeMap::new());
+
+/// The terminal on screen
+pub fn active() -> usize {
+    ACTIVE.load(Ordering::Relaxed)
+}
+
+/// The terminal the task being polled writes to
+pub fn current() -> usize {
+    own().unwrap_or(0)
+}
+
+/// Bring `vt` on screen, replaying what it wrote while hidden. Panics if
+/// it isn't below [`COUNT`].
+pub async fn switch(vt: usize) {
+    assert!(vt < COUNT, "no virtual terminal {}", vt);
+    super::show(vt).await;
+}
+
+/// Switch terminals with Alt+F1 to Alt+F4, runs until the keyboard
+/// dispatcher stops
+#[cfg(feature = "keyboard")]
+pub async fn switch_keys() {
+    let key = |key, vt| on_hotkey(Hotkey::new(key).alt(), move || switch(vt));
+    race(
+        race(key(KeyCode::F1, 0), key(KeyCode::F2, 1)),
+        race(key(KeyCode::F3, 2), key(KeyCode::F4, 3)),
+    )
+    .await;
+}
+
+/// The task being polled's own terminal, which the tasks it creates get
+pub(crate) fn own() -> Option<usize> {
+    let (task, _) = context::current()?;
+    OWNERS.lock().get(&task).copied()
+}
+
+/// Called by the executor when `task` is spawned
+pub(crate) fn attach(task: TaskId, vt: Option<usize>) {
+    if let Some(vt) = vt {
+        OWNERS.lock().insert(task, vt);
+    }
+}
+
+/// `task` finished
+pub(crate) fn detach(task: TaskId) {
+    OWNERS.lock().remove(&task);
+}
+
+/// Called by the console once `vt` is on screen
+pub(super) fn set_active(vt: usize) {
+    ACTIVE.store(vt, Ordering::Relaxed);
+}
+
+/// The tail of one terminal's output
+pub(super) struct Transcript {
+    text: String,
+}
+
+impl Transcript {
+    pub(super) const fn new() -> Self {
+        Transcript {
+            text: String::new(),
+        }
+    }
+
+    pub(super) fn push(&mut self, text: &str) {
+        self.text.push_str(text);
+        if self.text.len() <= SCROLLBACK_BYTES {
+            return;
+        }
+        // Cut back to three quarters at a line start, not on every write
+        let mut cut = self.text.len() - SCROLLBACK_BYTES * 3 / 4;
+        match self.text.as_bytes()[cut..].iter().position(|&b| b == b'\n') {
+            Some(newline) => cut += newline + 1,
+            None => {
+                while !self.text.is_char_boundary(cut) {
+                    cut += 1;
+                }
+            }
+        }
+        self.text.drain(..cut);
+    }
+
+    pub(super) fn as_str(&self) -> &str {
+        &self.text
+    }
+}
@@ -0,0 +1,92 @@
+//!
+//! Which task is being polled right now, and whether an executor runs at
+//! all, so one isn't started from inside another
+//!
+
+use crate::{TaskId, priority::Priority};
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static CURRENT: core::cell::Cell<Option<(TaskId, Priority)>> = const { core::cell::Cell::new(None) };
+}
+
+// No threads without std, so no executors on other threads to keep apart
+#[cfg(not(feature = "std"))]
+static CURRENT: Current = Current(spin::Mutex::new(None));
+
+#[cfg(not(feature = "std"))]
+struct Current(spin::Mutex<Option<(TaskId, Priority)>>);
+
+#[cfg(not(feature = "std"))]
+impl Current {
+    fn replace(&self, current: Option<(TaskId, Priority)>) -> Option<(TaskId, Priority)> {
+        core::mem::replace(&mut self.0.lock(), current)
+    }
+
+    fn set(&self, current: Option<(TaskId, Priority)>) {
+        *self.0.lock() = current;
+    }
+
+    fn get(&self) -> Option<(TaskId, Priority)> {
+        *self.0.lock()
+    }
+}
+
+/// Marks a task as current until dropped, the executor holds one around `task.poll`
+pub(crate) struct Enter {
+    previous: Option<(TaskId, Priority)>,
+}
+
+pub(crate) fn enter(task: TaskId, priority: Priority) -> Enter {
+    Enter {
+        previous: CURRENT.replace(Some((task, priority))),
+    }
+}
+
+impl Drop for Enter {
+    fn drop(&mut self) {
+        CURRENT.set(self.previous);
+    }
+}
+
+/// Id and base priority of the task being polled, `None` outside the executor
+pub(crate) fn current() -> Option<(TaskId, Priority)> {
+    CURRENT.get()
+}
+
+// An executor or `block_on` is running on this thread
+#[cfg(feature = "std")]
+std::thread_local! {
+    static RUNNING: core::cell::Cell<bool> = const { core::cell::Cell::new(false) };
+}
+
+#[cfg(not(feature = "std"))]
+static RUNNING: Running = Running(core::sync::atomic::AtomicBool::new(false));
+
+#[cfg(not(feature = "std"))]
+struct Running(core::sync::atomic::AtomicBool);
+
+#[cfg(not(feature = "std"))]
+impl Running {
+    fn replace(&self, running: bool) -> bool {
+        self.0.swap(running, core::sync::atomic::Ordering::AcqRel)
+    }
+
+    fn set(&self, running: bool) {
+        self.0.store(running, core::sync::atomic::Ordering::Release);
+    }
+}
+
+/// Marks an executor as running until dropped, held around its loop
+pub(crate) struct Run(());
+
+/// Mark an executor as running, `None` if one already is on this thread
+pub(crate) fn run() -> Option<Run> {
+    (!RUNNING.replace(true)).then_some(Run(()))
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        RUNNING.set(false);
+    }
+}
@@ -0,0 +1,94 @@
+//! Tutorial demos, each chapter's in a registry the shell and the
+//! command line pick from.
+//!
+//! A demo is a name, a line saying what it shows and a function making its
+//! task. The crate's own chapters are there from the start, a program adds
+//! its own with [`register`]:
+//!
+//! ```ignore
+//! demo::register("hello", "prints a greeting", || {
+//!     LocalTask::new(async { log::info!("hello") })
+//! });
+//!
+//! demo::run("hello")?;
+//! ```
+//!
+//! `demo list` and `demo run NAME` in the shell do the same.
+
+mod builtins;
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{LocalTask, TaskId, executor};
+
+/// Makes a demo's task, named after the demo unless it has a name already
+pub type Entry = fn() -> LocalTask;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Demo {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub entry: Entry,
+}
+
+static DEMOS: spin::Mutex<BTreeMap<&'static str, Demo>> = spin::Mutex::new(BTreeMap::new());
+// The crate's own are added on first use, so they can be unregistered
+static BUILTINS: AtomicBool = AtomicBool::new(false);
+
+fn registry() -> spin::MutexGuard<'static, BTreeMap<&'static str, Demo>> {
+    let mut demos = DEMOS.lock();
+    if !BUILTINS.swap(true, Ordering::Relaxed) {
+        builtins::register(&mut demos);
+    }
+    demos
+}
+
+/// Add a demo, replacing one of the same name
+pub fn register(name: &'static str, description: &'static str, entry: Entry) {
+    registry().insert(
+        name,
+        Demo {
+            name,
+            description,
+            entry,
+        },
+    );
+}
+
+pub fn unregister(name: &str) {
+    registry().remove(name);
+}
+
+/// Every demo, by name
+pub fn demos() -> Vec<Demo> {
+    registry().values().copied().collect()
+}
+
+pub fn find(name: &str) -> Option<Demo> {
+    registry().get(name).copied()
+}
+
+/// Spawn the demo `name` on the executor polling the caller
+pub fn run(name: &str) -> Result<TaskId, UnknownDemo> {
+    let demo = find(name).ok_or(UnknownDemo)?;
+    let mut task = (demo.entry)();
+    if task.name().is_none() {
+        task = task.with_name(demo.name);
+    }
+    let id = task.id();
+    executor::spawn_local(task);
+    Ok(id)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownDemo;
+
+impl fmt::Display for UnknownDemo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("no such demo, `demo list` shows them")
+    }
+}
@@ -0,0 +1,121 @@
+use alloc::collections::BTreeMap;
+#[cfg(any(feature = "timers", feature = "channels"))]
+use core::str::FromStr;
+#[cfg(feature = "timers")]
+use core::time::Duration;
+
+use super::{Demo, Entry};
+#[cfg(feature = "keyboard")]
+use crate::keyboard;
+#[cfg(feature = "timers")]
+use crate::timer;
+use crate::{LocalTask, Task, executor, future::yield_now, log};
+#[cfg(feature = "channels")]
+use crate::{channel::mpmc, env};
+
+/// A chapter's demos, by the module they show, those of the features built
+pub(super) fn register(demos: &mut BTreeMap<&'static str, Demo>) {
+    let mut add = |name, description, entry: Entry| {
+        // Registered demos of the same name win
+        demos.entry(name).or_insert(Demo {
+            name,
+            description,
+            entry,
+        });
+    };
+    add(
+        "yield",
+        "executor: two tasks taking turns at every yield",
+        || LocalTask::new(yield_turns()),
+    );
+    #[cfg(feature = "timers")]
+    add(
+        "timer",
+        "timer: logs TIMER_TICKS ticks (5), TIMER_MS apart (1000)",
+        || LocalTask::new(timer_ticks()),
+    );
+    #[cfg(feature = "channels")]
+    add(
+        "ping-pong",
+        "channel: two tasks pass a counter PING_PONG_ROUNDS times (10)",
+        || LocalTask::new(ping_pong()),
+    );
+    #[cfg(feature = "keyboard")]
+    add("echo", "keyboard: echoes the keys typed", || {
+        LocalTask::new(keyboard::print_keypresses())
+    });
+}
+
+/// Environment variable `name`, `default` if it isn't set or there's no
+/// environment without the `channels` feature
+#[cfg(any(feature = "timers", feature = "channels"))]
+fn setting<T: FromStr>(name: &str, default: T) -> T {
+    #[cfg(feature = "channels")]
+    if let Some(value) = env::parse(name) {
+        return value;
+    }
+    let _ = name;
+    default
+}
+
+async fn yield_turns() {
+    let turns = |name: &'static str| async move {
+        for turn in 1..=3 {
+            log::info!("yield: {} takes turn {}", name, turn);
+            yield_now().await;
+        }
+    };
+    executor::spawn(Task::new(turns("second")).with_name("yield-second"));
+    turns("first").await;
+}
+
+#[cfg(feature = "timers")]
+async fn timer_ticks() {
+    let mut ticks = timer::interval(tick_period());
+    // A new TIMER_MS applies from the next tick
+    #[cfg(feature = "channels")]
+    let mut changes = env::watch();
+    for tick in 1..=setting("TIMER_TICKS", 5) {
+        ticks.tick().await;
+        log::info!("timer: tick {}", tick);
+        #[cfg(feature = "channels")]
+        if changes.has_changed() {
+            changes.borrow_and_update();
+            ticks = timer::interval(tick_period());
+        }
+    }
+}
+
+#[cfg(feature = "timers")]
+fn tick_period() -> Duration {
+    Duration::from_millis(setting("TIMER_MS", 1000).max(1))
+}
+
+#[cfg(feature = "channels")]
+async fn ping_pong() {
+    let (to_pong, mut pings) = mpmc::channel(1);
+    let (to_ping, mut pongs) = mpmc::channel(1);
+    executor::spawn(
+        Task::new(async move {
+            while let Some(count) = pings.recv().await {
+                log::info!("pong {}", count);
+                if to_ping.send(count + 1).await.is_err() {
+                    break;
+                }
+            }
+        })
+        .with_name("pong"),
+    );
+    let rounds = setting("PING_PONG_ROUNDS", 10);
+    let mut count = 0;
+    while count < rounds {
+        log::info!("ping {}", count);
+        if to_pong.send(count + 1).await.is_err() {
+            break;
+        }
+        match pongs.recv().await {
+            Some(next) => count = next,
+            None => break,
+        }
+    }
+}
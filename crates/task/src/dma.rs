@@ -0,0 +1,261 @@
+//!
+//! Memory for devices
+//!
+//! A device reading or writing memory by itself needs its physical address
+//! and has to find the memory in one piece. A [`DmaRegion`] is one such
+//! block, a [`DmaPool`] hands out equal buffers from one and lets tasks wait
+//! when they're all in use:
+//!
+//! ```ignore
+//! let pool = DmaPool::new(512, 32);
+//! let mut sector = pool.acquire().await;
+//! device.read_sector(lba, sector.physical_address());
+//! ```
+//!
+//! Memory comes from the heap, so it's contiguous as long as the kernel maps
+//! its heap to contiguous physical memory, and [`set_address_translation`]
+//! says where that is.
+//!
+
+use alloc::{
+    alloc::{Layout, alloc_zeroed, dealloc, handle_alloc_error},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    fmt,
+    ops::{Deref, DerefMut},
+    ptr::NonNull,
+    slice,
+};
+
+use crate::sync::Notify;
+
+pub const PAGE_SIZE: usize = 4096;
+
+/// Buffers of a [`DmaPool`] start on a multiple of this, a cache line
+const BUFFER_ALIGN: usize = 64;
+
+fn identity(address: usize) -> u64 {
+    address as u64
+}
+
+static TRANSLATE: spin::Mutex<fn(usize) -> u64> = spin::Mutex::new(identity);
+
+/// How to find the physical address devices need for a virtual one. Memory
+/// is taken to be identity mapped until this is called.
+pub fn set_address_translation(translate: fn(usize) -> u64) {
+    *TRANSLATE.lock() = translate;
+}
+
+pub fn physical_address(address: usize) -> u64 {
+    (TRANSLATE.lock())(address)
+}
+
+/// A zeroed block of memory for a device, freed on drop
+pub struct DmaRegion {
+    memory: NonNull<u8>,
+    layout: Layout,
+}
+
+// Plain memory, shared only through `&` and `&mut`
+unsafe impl Send for DmaRegion {}
+unsafe impl Sync for DmaRegion {}
+
+impl DmaRegion {
+    /// `size` bytes starting on a page, rounded up to whole pages
+    pub fn new(size: usize) -> DmaRegion {
+        DmaRegion::with_align(size.max(1).next_multiple_of(PAGE_SIZE), PAGE_SIZE)
+    }
+
+    /// `size` bytes aligned to `align`, a power of two
+    pub fn with_align(size: usize, align: usize) -> DmaRegion {
+        let layout = Layout::from_size_align(size.max(1), align).expect("bad DMA region layout");
+        // SAFETY: the layout isn't empty
+        let memory = NonNull::new(unsafe { alloc_zeroed(layout) })
+            .unwrap_or_else(|| handle_alloc_error(layout));
+        DmaRegion { memory, layout }
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.memory.as_ptr()
+    }
+
+    pub fn len(&self) -> usize {
+        self.layout.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Of the first byte, for the device
+    pub fn physical_address(&self) -> u64 {
+        physical_address(self.memory.as_ptr() as usize)
+    }
+}
+
+impl Deref for DmaRegion {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: the allocation is `len` initialized bytes
+        unsafe { slice::from_raw_parts(self.memory.as_ptr(), self.len()) }
+    }
+}
+
+impl DerefMut for DmaRegion {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // SAFETY: as above, and `&mut self` is the only way in
+        unsafe { slice::from_raw_parts_mut(self.memory.as_ptr(), self.len()) }
+    }
+}
+
+impl Drop for DmaRegion {
+    fn drop(&mut self) {
+        // SAFETY: allocated in `with_align` with the same layout
+        unsafe { dealloc(self.memory.as_ptr(), self.layout) }
+    }
+}
+
+impl fmt::Debug for DmaRegion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DmaRegion")
+            .field("address", &self.memory)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// Equal buffers for a device out of one [`DmaRegion`]. A buffer no bigger
+/// than a page doesn't cross into the next one.
+///
+/// Clones share the buffers.
+#[derive(Clone)]
+pub struct DmaPool {
+    inner: Arc<Pool>,
+}
+
+struct Pool {
+    region: DmaRegion,
+    buffer_size: usize,
+    stride: usize,
+    count: usize,
+    free: spin::Mutex<Vec<usize>>,
+    released: Notify,
+}
+
+impl DmaPool {
+    /// `count` buffers of `buffer_size` bytes each, zeroed to begin with
+    pub fn new(buffer_size: usize, count: usize) -> DmaPool {
+        assert!(buffer_size > 0, "DMA buffers need a size");
+        let mut stride = buffer_size.next_multiple_of(BUFFER_ALIGN);
+        // Buffers that would straddle a page boundary get their own pages
+        if stride <= PAGE_SIZE && !PAGE_SIZE.is_multiple_of(stride) {
+            stride = stride.next_power_of_two();
+        }
+        DmaPool {
+            inner: Arc::new(Pool {
+                region: DmaRegion::new(stride * count),
+                buffer_size,
+                stride,
+                count,
+                free: spin::Mutex::new((0..count).rev().collect()),
+                released: Notify::new(),
+            }),
+        }
+    }
+
+    pub fn buffer_size(&self) -> usize {
+        self.inner.buffer_size
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.count
+    }
+
+    /// Buffers not in use right now
+    pub fn available(&self) -> usize {
+        self.inner.free.lock().len()
+    }
+
+    /// A buffer, waiting for one to come back while they're all in use.
+    /// It holds what its last user left in it.
+    pub async fn acquire(&self) -> DmaBuffer {
+        loop {
+            if let Some(buffer) = self.try_acquire() {
+                return buffer;
+            }
+            self.inner.released.notified().await;
+        }
+    }
+
+    /// `None` if every buffer is in use
+    pub fn try_acquire(&self) -> Option<DmaBuffer> {
+        let index = self.inner.free.lock().pop()?;
+        Some(DmaBuffer {
+            pool: self.inner.clone(),
+            index,
+        })
+    }
+}
+
+impl fmt::Debug for DmaPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DmaPool")
+            .field("buffer_size", &self.buffer_size())
+            .field("capacity", &self.capacity())
+            .field("available", &self.available())
+            .finish()
+    }
+}
+
+/// A buffer from a [`DmaPool`], back in the pool on drop
+pub struct DmaBuffer {
+    pool: Arc<Pool>,
+    index: usize,
+}
+
+impl DmaBuffer {
+    fn start(&self) -> *mut u8 {
+        // SAFETY: `index` is below `count`, so this stays in the region
+        unsafe { self.pool.region.as_ptr().add(self.index * self.pool.stride) }
+    }
+
+    /// Of the first byte, for the device
+    pub fn physical_address(&self) -> u64 {
+        physical_address(self.start() as usize)
+    }
+}
+
+impl Deref for DmaBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: the buffer's bytes, which only this handle hands out
+        unsafe { slice::from_raw_parts(self.start(), self.pool.buffer_size) }
+    }
+}
+
+impl DerefMut for DmaBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // SAFETY: as above
+        unsafe { slice::from_raw_parts_mut(self.start(), self.pool.buffer_size) }
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        self.pool.free.lock().push(self.index);
+        self.pool.released.notify_one();
+    }
+}
+
+impl fmt::Debug for DmaBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DmaBuffer")
+            .field("index", &self.index)
+            .field("len", &self.pool.buffer_size)
+            .finish()
+    }
+}
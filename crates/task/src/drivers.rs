@@ -0,0 +1,180 @@
+//!
+//! Device drivers
+//!
+//! Drivers are [`register`]ed before the executor starts and brought up by
+//! the task running [`run`]: one after the other in registration order, so
+//! a driver can rely on the ones before it. Each one's [`Driver::run`]
+//! future is then driven inside that task until [`shutdown`].
+//!
+//! ```ignore
+//! drivers::register(KeyboardDriver);
+//! drivers::register(NetDriver::new(device));
+//! executor.spawn_local(LocalTask::new(drivers::run()).with_name("drivers"));
+//! ```
+//!
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::{
+    fmt,
+    future::Future,
+    mem,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    future::{Either, race},
+    join_set::JoinSet,
+    log,
+    sync::Notify,
+};
+
+/// Future returned by [`Driver::init`]
+pub type InitFuture<'a> = Pin<Box<dyn Future<Output = Result<(), String>> + 'a>>;
+
+/// Future returned by [`Driver::run`]
+pub type RunFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+pub trait Driver: Send {
+    /// Shown by [`drivers`]
+    fn name(&self) -> &'static str;
+
+    /// Set the device up, an error leaves the driver out
+    fn init(&mut self) -> InitFuture<'_>;
+
+    /// The driver's work once initialized, `None` if there is none. Driven
+    /// until it ends or the drivers are shut down.
+    fn run(&mut self) -> Option<RunFuture> {
+        None
+    }
+
+    /// Put the device to rest, after the [`run`](Driver::run) future is
+    /// dropped
+    fn shutdown(&mut self) {}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DriverState {
+    /// Waiting for [`run`] to get to it
+    Registered,
+    Initializing,
+    /// Initialized, with its future being driven if it has one
+    Running,
+    /// The [`run`](Driver::run) future ended by itself
+    Finished,
+    /// [`Driver::init`] failed with this error
+    Failed(String),
+    Stopped,
+}
+
+impl fmt::Display for DriverState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DriverState::Registered => f.write_str("registered"),
+            DriverState::Initializing => f.write_str("initializing"),
+            DriverState::Running => f.write_str("running"),
+            DriverState::Finished => f.write_str("finished"),
+            DriverState::Failed(err) => write!(f, "failed: {}", err),
+            DriverState::Stopped => f.write_str("stopped"),
+        }
+    }
+}
+
+/// A driver as reported by [`drivers`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DriverInfo {
+    pub name: &'static str,
+    pub state: DriverState,
+}
+
+// Drivers not picked up by `run` yet, with their index in `STATES`
+static PENDING: spin::Mutex<Vec<(usize, Box<dyn Driver>)>> = spin::Mutex::new(Vec::new());
+// Every registered driver, in registration order
+static STATES: spin::Mutex<Vec<DriverInfo>> = spin::Mutex::new(Vec::new());
+
+static SHUT_DOWN: AtomicBool = AtomicBool::new(false);
+// For a registration or the shutdown
+static WAKE: Notify = Notify::new();
+// While `run` drives the drivers, until it has shut them down
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static STOPPED: Notify = Notify::new();
+
+/// Add a driver, initialized after the ones registered before it. A
+/// driver registered while [`run`] is going is picked up once it's done
+/// with the others.
+pub fn register(driver: impl Driver + 'static) {
+    let mut states = STATES.lock();
+    states.push(DriverInfo {
+        name: driver.name(),
+        state: DriverState::Registered,
+    });
+    PENDING.lock().push((states.len() - 1, Box::new(driver)));
+    WAKE.notify_one();
+}
+
+/// Registered drivers and how they are doing, in registration order
+pub fn drivers() -> Vec<DriverInfo> {
+    STATES.lock().clone()
+}
+
+/// Make [`run`] stop every driver and return
+pub fn shutdown() {
+    SHUT_DOWN.store(true, Ordering::Release);
+    WAKE.notify_one();
+}
+
+/// Resolves once [`run`] has shut every driver down after a [`shutdown`],
+/// at once if it isn't running. For one waiter at a time.
+pub async fn stopped() {
+    while RUNNING.load(Ordering::Acquire) {
+        STOPPED.notified().await;
+    }
+}
+
+fn set_state(index: usize, state: DriverState) {
+    STATES.lock()[index].state = state;
+}
+
+/// Initialize every registered driver in order and drive them until
+/// [`shutdown`], then shut them down in reverse order
+pub async fn run() {
+    RUNNING.store(true, Ordering::Release);
+    let mut initialized = Vec::new();
+    let mut running = JoinSet::new();
+    while !SHUT_DOWN.load(Ordering::Acquire) {
+        let pending = mem::take(&mut *PENDING.lock());
+        for (index, mut driver) in pending {
+            let name = driver.name();
+            set_state(index, DriverState::Initializing);
+            if let Err(err) = driver.init().await {
+                log::error!("driver {}: {}", name, err);
+                set_state(index, DriverState::Failed(err));
+                continue;
+            }
+            log::info!("driver {} up", name);
+            set_state(index, DriverState::Running);
+            if let Some(future) = driver.run() {
+                running.spawn(async move {
+                    future.await;
+                    index
+                });
+            }
+            initialized.push((index, driver));
+        }
+
+        if running.is_empty() {
+            WAKE.notified().await;
+        } else if let Either::Left(Some(index)) = race(running.join_next(), WAKE.notified()).await {
+            set_state(index, DriverState::Finished);
+        }
+    }
+
+    running.clear();
+    for (index, mut driver) in initialized.into_iter().rev() {
+        driver.shutdown();
+        set_state(index, DriverState::Stopped);
+    }
+    RUNNING.store(false, Ordering::Release);
+    // Keeps a permit, for a waiter between its check and registering
+    STOPPED.notify_one();
+}
@@ -0,0 +1,343 @@
+//!
+//! Line editing
+//!
+//! A [`LineEditor`] is the state behind an input line: the text, the cursor,
+//! the history and completion. It takes keys and says what happened, the
+//! caller decides how to show it. [`Tty::edit_line`], the shell and
+//! [`InputField`] all go through one.
+//!
+//! | Key                          | Does                                  |
+//! |------------------------------|---------------------------------------|
+//! | Left, Right, Home, End       | Move the cursor                       |
+//! | Ctrl+A, Ctrl+E               | Start, end of line                    |
+//! | Backspace, Delete            | Delete before, at the cursor          |
+//! | Ctrl+U                       | Delete everything before the cursor   |
+//! | Ctrl+C, Ctrl+V               | Copy the line, paste at the cursor    |
+//! | Up, Down                     | Older, newer history entry            |
+//! | Tab                          | Complete the word before the cursor   |
+//! | Enter                        | Finish the line                       |
+//! | Ctrl+D                       | End of input on an empty line         |
+//!
+//! Copying and pasting go through the [`clipboard`](crate::clipboard).
+//!
+//! The completer is async, it may have a filesystem to look at: Tab comes
+//! back as [`Edit::Complete`] and the caller awaits
+//! [`LineEditor::complete`].
+//!
+//! [`Tty::edit_line`]: crate::tty::Tty::edit_line
+//! [`InputField`]: crate::tui::InputField
+//!
+
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::{fmt::Write as _, future::Future, pin::Pin};
+
+use pc_keyboard::{DecodedKey, KeyCode};
+
+use crate::clipboard;
+
+/// History entries kept by default
+pub const DEFAULT_HISTORY: usize = 100;
+
+const CTRL_A: char = '\u{1}';
+const CTRL_C: char = '\u{3}';
+const CTRL_D: char = '\u{4}';
+const CTRL_E: char = '\u{5}';
+const CTRL_U: char = '\u{15}';
+const CTRL_V: char = '\u{16}';
+const BACKSPACE: char = '\u{8}';
+/// What the Delete key decodes to
+const DELETE: char = '\u{7f}';
+
+/// Completion candidates for the text before the cursor. Each candidate is
+/// a whole word to replace the last word of the text with.
+pub type Completer = Box<dyn Fn(&str) -> CompletionFuture + Send>;
+
+/// Future returned by a [`Completer`]
+pub type CompletionFuture = Pin<Box<dyn Future<Output = Vec<String>> + Send>>;
+
+/// What a key did, see [`LineEditor::handle`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+    /// Nothing to redraw
+    Unchanged,
+    /// The text or the cursor changed
+    Changed,
+    /// Enter was pressed, the finished line. The editor is empty again.
+    Submit(String),
+    /// Ctrl+D on an empty line
+    EndOfInput,
+    /// Tab was pressed, await [`LineEditor::complete`] for what it did
+    Complete,
+    /// Completion found several words, the text was extended as far as they
+    /// agree. Show them and redraw.
+    Candidates(Vec<String>),
+}
+
+pub struct LineEditor {
+    text: Vec<char>,
+    cursor: usize,
+    history: Vec<String>,
+    history_limit: usize,
+    // Entry being shown while going through the history, and what was
+    // typed before starting to
+    browsing: Option<usize>,
+    draft: Vec<char>,
+    completer: Option<Completer>,
+    // Cursor position as last drawn by `redraw`
+    shown: usize,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        LineEditor {
+            text: Vec::new(),
+            cursor: 0,
+            history: Vec::new(),
+            history_limit: DEFAULT_HISTORY,
+            browsing: None,
+            draft: Vec::new(),
+            completer: None,
+            shown: 0,
+        }
+    }
+
+    /// Keep `entries` lines of history, `0` turns it off
+    pub fn set_history_limit(&mut self, entries: usize) {
+        self.history_limit = entries;
+        let excess = self.history.len().saturating_sub(entries);
+        self.history.drain(..excess);
+        self.browsing = None;
+    }
+
+    /// Complete words on Tab with `completer`
+    pub fn set_completer(&mut self, completer: impl Fn(&str) -> CompletionFuture + Send + 'static) {
+        self.completer = Some(Box::new(completer));
+    }
+
+    pub fn text(&self) -> String {
+        self.text.iter().collect()
+    }
+
+    /// In characters from the start of the line
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Replace the line, the cursor goes to the end
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.chars().collect();
+        self.cursor = self.text.len();
+    }
+
+    /// Put `text` in at the cursor, as if typed. Line breaks become spaces
+    /// and other control characters are left out.
+    pub fn insert(&mut self, text: &str) -> Edit {
+        let inserted: Vec<char> = text
+            .chars()
+            .map(|character| match character {
+                '\n' | '\r' | '\t' => ' ',
+                character => character,
+            })
+            .filter(|character| !character.is_control())
+            .collect();
+        if inserted.is_empty() {
+            return Edit::Unchanged;
+        }
+        self.text
+            .splice(self.cursor..self.cursor, inserted.iter().copied());
+        self.cursor += inserted.len();
+        Edit::Changed
+    }
+
+    /// Oldest first
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Remember `line` as the newest entry, unless it's empty or the same
+    /// as the newest one
+    pub fn add_history(&mut self, line: &str) {
+        if self.history_limit == 0
+            || line.trim().is_empty()
+            || self.history.last().is_some_and(|last| last == line)
+        {
+            return;
+        }
+        if self.history.len() == self.history_limit {
+            self.history.remove(0);
+        }
+        self.history.push(line.into());
+    }
+
+    pub fn handle(&mut self, key: DecodedKey) -> Edit {
+        match key {
+            DecodedKey::Unicode('\n' | '\r') => return self.submit(),
+            DecodedKey::Unicode(CTRL_D) if self.text.is_empty() => return Edit::EndOfInput,
+            DecodedKey::Unicode('\t') if self.completer.is_some() => return Edit::Complete,
+            DecodedKey::Unicode(BACKSPACE) | DecodedKey::RawKey(KeyCode::Backspace) => {
+                if self.cursor == 0 {
+                    return Edit::Unchanged;
+                }
+                self.cursor -= 1;
+                self.text.remove(self.cursor);
+            }
+            DecodedKey::Unicode(DELETE | CTRL_D) | DecodedKey::RawKey(KeyCode::Delete) => {
+                if self.cursor == self.text.len() {
+                    return Edit::Unchanged;
+                }
+                self.text.remove(self.cursor);
+            }
+            DecodedKey::Unicode(CTRL_U) => {
+                self.text.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            DecodedKey::Unicode(CTRL_C) => {
+                clipboard::copy(self.text());
+                return Edit::Unchanged;
+            }
+            DecodedKey::Unicode(CTRL_V) => return self.insert(&clipboard::paste()),
+            DecodedKey::RawKey(KeyCode::ArrowLeft) => {
+                return self.move_to(self.cursor.saturating_sub(1));
+            }
+            DecodedKey::RawKey(KeyCode::ArrowRight) => return self.move_to(self.cursor + 1),
+            DecodedKey::Unicode(CTRL_A) | DecodedKey::RawKey(KeyCode::Home) => {
+                return self.move_to(0);
+            }
+            DecodedKey::Unicode(CTRL_E) | DecodedKey::RawKey(KeyCode::End) => {
+                return self.move_to(self.text.len());
+            }
+            DecodedKey::RawKey(KeyCode::ArrowUp) => return self.older(),
+            DecodedKey::RawKey(KeyCode::ArrowDown) => return self.newer(),
+            DecodedKey::Unicode(character) if !character.is_control() => {
+                self.text.insert(self.cursor, character);
+                self.cursor += 1;
+            }
+            _ => return Edit::Unchanged,
+        }
+        Edit::Changed
+    }
+
+    /// Terminal output to bring the line as last drawn up to date, using
+    /// ANSI cursor movement only, so it works after any prompt.
+    ///
+    /// Call it after [`Edit::Changed`] and [`Edit::Candidates`]. After
+    /// anything else was written, call [`LineEditor::reset_display`] first.
+    pub fn redraw(&mut self) -> String {
+        let mut out = String::new();
+        if self.shown > 0 {
+            let _ = write!(out, "\x1b[{}D", self.shown);
+        }
+        out.extend(&self.text);
+        out.push_str("\x1b[K");
+        let back = self.text.len() - self.cursor;
+        if back > 0 {
+            let _ = write!(out, "\x1b[{}D", back);
+        }
+        self.shown = self.cursor;
+        out
+    }
+
+    /// The terminal cursor is at the start of a fresh line again, the next
+    /// [`LineEditor::redraw`] draws the whole text
+    pub fn reset_display(&mut self) {
+        self.shown = 0;
+    }
+
+    fn move_to(&mut self, cursor: usize) -> Edit {
+        let cursor = cursor.min(self.text.len());
+        if cursor == self.cursor {
+            return Edit::Unchanged;
+        }
+        self.cursor = cursor;
+        Edit::Changed
+    }
+
+    fn submit(&mut self) -> Edit {
+        let line = self.text();
+        self.add_history(&line);
+        self.text.clear();
+        self.cursor = 0;
+        self.browsing = None;
+        self.shown = 0;
+        Edit::Submit(line)
+    }
+
+    fn older(&mut self) -> Edit {
+        let index = match self.browsing {
+            Some(0) => return Edit::Unchanged,
+            Some(index) => index - 1,
+            None if self.history.is_empty() => return Edit::Unchanged,
+            None => {
+                self.draft = self.text.clone();
+                self.history.len() - 1
+            }
+        };
+        self.browsing = Some(index);
+        self.text = self.history[index].chars().collect();
+        self.cursor = self.text.len();
+        Edit::Changed
+    }
+
+    fn newer(&mut self) -> Edit {
+        let Some(index) = self.browsing else {
+            return Edit::Unchanged;
+        };
+        if index + 1 < self.history.len() {
+            self.browsing = Some(index + 1);
+            self.text = self.history[index + 1].chars().collect();
+        } else {
+            self.browsing = None;
+            self.text = core::mem::take(&mut self.draft);
+        }
+        self.cursor = self.text.len();
+        Edit::Changed
+    }
+
+    /// Complete the word before the cursor, after [`Edit::Complete`]. A
+    /// single candidate gets a space after it, unless it's a directory
+    /// ending in `/` to go on completing in.
+    pub async fn complete(&mut self) -> Edit {
+        let Some(completer) = &self.completer else {
+            return Edit::Unchanged;
+        };
+        let before: String = self.text[..self.cursor].iter().collect();
+        let mut candidates = completer(&before).await;
+        let start = self.text[..self.cursor]
+            .iter()
+            .rposition(|character| character.is_whitespace())
+            .map_or(0, |space| space + 1);
+
+        let replacement: Vec<char> = match candidates.as_slice() {
+            [] => return Edit::Unchanged,
+            [word] if word.ends_with('/') => word.chars().collect(),
+            [word] => word.chars().chain([' ']).collect(),
+            [first, rest @ ..] => {
+                let mut prefix: Vec<char> = first.chars().collect();
+                for word in rest {
+                    let common = prefix
+                        .iter()
+                        .zip(word.chars())
+                        .take_while(|(a, b)| **a == *b);
+                    prefix.truncate(common.count());
+                }
+                prefix
+            }
+        };
+        if replacement.len() > self.cursor - start || candidates.len() == 1 {
+            self.text
+                .splice(start..self.cursor, replacement.iter().copied());
+            self.cursor = start + replacement.len();
+        }
+        if candidates.len() == 1 {
+            return Edit::Changed;
+        }
+        candidates.sort();
+        Edit::Candidates(candidates)
+    }
+}
+
+impl Default for LineEditor {
+    fn default() -> Self {
+        LineEditor::new()
+    }
+}
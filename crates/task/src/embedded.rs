@@ -0,0 +1,76 @@
+//!
+//! The embedded ecosystem's async traits, with the `embedded-hal` feature
+//!
+//! Drivers written against `embedded-hal-async` and `embedded-io-async`
+//! run on this executor unchanged: [`Delay`] waits on the timers, and the
+//! halves of a [`Serial`](crate::serial::Serial) are embedded-io readers and
+//! writers.
+//!
+//! ```ignore
+//! let (reader, writer) = com1.split();
+//! let mut gps = Gps::new(reader, Delay);
+//! ```
+//!
+//! Delays are at least as long as asked, rounded up to the next timer
+//! check: a tick on bare metal, [`idle::NAP`](crate::idle::NAP) at most on
+//! the std build.
+//!
+
+use core::time::Duration;
+
+use embedded_hal_async::delay::DelayNs;
+
+use crate::timer;
+
+/// Delays on the executor's timers, any number of tasks can hold one
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Delay;
+
+impl DelayNs for Delay {
+    async fn delay_ns(&mut self, ns: u32) {
+        timer::sleep(Duration::from_nanos(ns.into())).await;
+    }
+
+    async fn delay_us(&mut self, us: u32) {
+        timer::sleep(Duration::from_micros(us.into())).await;
+    }
+
+    async fn delay_ms(&mut self, ms: u32) {
+        timer::sleep(Duration::from_millis(ms.into())).await;
+    }
+}
+
+#[cfg(feature = "std")]
+mod serial {
+    use core::{convert::Infallible, future::poll_fn};
+
+    use embedded_io_async::{ErrorType, Read, Write};
+
+    use crate::serial::{SerialReader, SerialWriter};
+
+    impl ErrorType for SerialReader {
+        type Error = Infallible;
+    }
+
+    /// `Ok(0)` once stdin has ended
+    impl Read for SerialReader {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+            Ok(SerialReader::read(self, buf).await)
+        }
+    }
+
+    impl ErrorType for SerialWriter {
+        type Error = Infallible;
+    }
+
+    impl Write for SerialWriter {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            Ok(poll_fn(|cx| self.poll_write(cx, buf)).await)
+        }
+
+        async fn write_all(&mut self, buf: &[u8]) -> Result<(), Infallible> {
+            SerialWriter::write(self, buf).await;
+            Ok(())
+        }
+    }
+}
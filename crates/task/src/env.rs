@@ -0,0 +1,165 @@
+//!
+//! Environment variables
+//!
+//! Names with string values that configure code at runtime instead of
+//! constants: how many rounds a demo plays, how often it ticks. A task sees
+//! the [`Env`] given with [`Task::with_env`](crate::Task::with_env), or the
+//! one of the task that created it, or else the [`global`] one. The free
+//! functions go to the current task's:
+//!
+//! ```ignore
+//! env::set("PING_PONG_ROUNDS", "3");
+//! let rounds = env::parse("PING_PONG_ROUNDS").unwrap_or(10);
+//! ```
+//!
+//! Clones of an `Env` are the same variables, so a change shows in every
+//! task sharing it, where [`Env::watch`] can wait for it. [`Env::fork`]
+//! makes a copy to change separately.
+//!
+//! The shell's `export` and `env` commands set and list them, and the
+//! shell's jobs share its environment.
+//!
+
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+};
+use core::{fmt, str::FromStr};
+
+use crate::{TaskId, channel::watch, context};
+
+/// Every variable of an environment, by name
+pub type Vars = BTreeMap<String, String>;
+
+/// A set of variables, clones share it
+#[derive(Clone)]
+pub struct Env {
+    vars: watch::Sender<Vars>,
+}
+
+impl Env {
+    /// An empty environment
+    pub fn new() -> Self {
+        Env::with_vars(Vars::new())
+    }
+
+    pub fn with_vars(vars: Vars) -> Self {
+        let (vars, _) = watch::channel(vars);
+        Env { vars }
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.vars.borrow().get(name).cloned()
+    }
+
+    /// Replace `name`'s value, tasks watching only hear of a change
+    pub fn set(&self, name: impl Into<String>, value: impl ToString) {
+        let (name, value) = (name.into(), value.to_string());
+        self.vars.send_if_modified(|vars| {
+            if vars.get(&name) == Some(&value) {
+                return false;
+            }
+            vars.insert(name, value);
+            true
+        });
+    }
+
+    pub fn remove(&self, name: &str) -> Option<String> {
+        let mut removed = None;
+        self.vars.send_if_modified(|vars| {
+            removed = vars.remove(name);
+            removed.is_some()
+        });
+        removed
+    }
+
+    /// A copy of every variable
+    pub fn vars(&self) -> Vars {
+        self.vars.borrow().clone()
+    }
+
+    /// Changes from now on, [`changed`](watch::Receiver::changed) waits for
+    /// the next one
+    pub fn watch(&self) -> watch::Receiver<Vars> {
+        self.vars.subscribe()
+    }
+
+    /// A separate environment starting with the same variables
+    pub fn fork(&self) -> Env {
+        Env::with_vars(self.vars())
+    }
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        Env::new()
+    }
+}
+
+impl fmt::Debug for Env {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.vars.borrow().iter()).finish()
+    }
+}
+
+static GLOBAL: spin::Once<Env> = spin::Once::new();
+
+/// The environment of tasks without one of their own, and of code outside
+/// any task
+pub fn global() -> &'static Env {
+    GLOBAL.call_once(Env::new)
+}
+
+static ENVS: spin::Mutex<BTreeMap<TaskId, Env>> = spin::Mutex::new(BTreeMap::new());
+
+/// The environment of the task being polled
+pub fn current() -> Env {
+    own().unwrap_or_else(|| global().clone())
+}
+
+/// The task being polled's own environment, which the tasks it creates get
+pub(crate) fn own() -> Option<Env> {
+    let (task, _) = context::current()?;
+    ENVS.lock().get(&task).cloned()
+}
+
+/// Called by the executor when `task` is spawned
+pub(crate) fn attach(task: TaskId, env: &Option<Env>) {
+    if let Some(env) = env {
+        ENVS.lock().insert(task, env.clone());
+    }
+}
+
+/// `task` finished, let go of its environment
+pub(crate) fn detach(task: TaskId) {
+    ENVS.lock().remove(&task);
+}
+
+/// `name` in the current task's environment
+pub fn get(name: &str) -> Option<String> {
+    current().get(name)
+}
+
+/// `name` in the current task's environment as a `T`, `None` if it isn't
+/// set or doesn't parse
+pub fn parse<T: FromStr>(name: &str) -> Option<T> {
+    get(name)?.trim().parse().ok()
+}
+
+pub fn set(name: impl Into<String>, value: impl ToString) {
+    current().set(name, value);
+}
+
+pub fn remove(name: &str) -> Option<String> {
+    current().remove(name)
+}
+
+/// Every variable of the current task's environment
+pub fn vars() -> Vars {
+    current().vars()
+}
+
+/// See [`Env::watch`]
+pub fn watch() -> watch::Receiver<Vars> {
+    current().watch()
+}
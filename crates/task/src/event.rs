@@ -0,0 +1,94 @@
+//! Event bus: one topic per event type, any task publishes on it and every
+//! subscriber gets a copy.
+//!
+//! Subsystems that don't know each other use it to react to one another,
+//! instead of a channel between every pair:
+//!
+//! ```ignore
+//! #[derive(Clone)]
+//! struct Docked(bool);
+//!
+//! event::publish(Docked(true));
+//!
+//! let mut link = event::subscribe::<net::LinkState>();
+//! while let Some(state) = link.next().await {
+//!     log::info!("link {:?}", state);
+//! }
+//! ```
+//!
+//! Events published before subscribing aren't seen. A subscriber more than
+//! [`CAPACITY`] events behind misses the oldest ones, counted in the
+//! channel's lag metric.
+//!
+//! The crate publishes [`net::LinkState`](crate::net::LinkState) and
+//! [`shutdown::Started`](crate::shutdown::Started).
+
+use alloc::{boxed::Box, collections::BTreeMap};
+use core::{
+    any::{Any, TypeId},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::{Stream, StreamExt};
+
+use crate::channel::broadcast::{self, RecvError};
+
+/// Events of one type kept for subscribers that haven't got to them yet
+pub const CAPACITY: usize = 32;
+
+// A `broadcast::Sender<E>` for every event type `E` used so far
+static TOPICS: spin::Mutex<BTreeMap<TypeId, Box<dyn Any + Send>>> =
+    spin::Mutex::new(BTreeMap::new());
+
+/// Run `f` on the topic of `E`, made on first use
+fn with_topic<E: Clone + Send + 'static, R>(f: impl FnOnce(&broadcast::Sender<E>) -> R) -> R {
+    let mut topics = TOPICS.lock();
+    let topic = topics
+        .entry(TypeId::of::<E>())
+        .or_insert_with(|| Box::new(broadcast::channel::<E>(CAPACITY).0));
+    f(topic
+        .downcast_ref()
+        .expect("topics are keyed by their event type"))
+}
+
+/// Hand `event` to every subscriber of its type, returns how many there are
+pub fn publish<E: Clone + Send + 'static>(event: E) -> usize {
+    // Fails only without subscribers, the event then goes nowhere
+    with_topic(|sender| sender.send(event).unwrap_or(0))
+}
+
+/// Events of type `E` published from now on
+pub fn subscribe<E: Clone + Send + 'static>() -> Subscription<E> {
+    Subscription {
+        receiver: with_topic(broadcast::Sender::subscribe),
+    }
+}
+
+/// Stream of the events of one type, see [`subscribe`]
+pub struct Subscription<E> {
+    receiver: broadcast::Receiver<E>,
+}
+
+impl<E: Clone> Subscription<E> {
+    /// Next event, skipping any missed for falling behind. Never `None`,
+    /// topics stay open.
+    pub async fn recv(&mut self) -> Option<E> {
+        self.next().await
+    }
+}
+
+impl<E: Clone> Stream for Subscription<E> {
+    type Item = E;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<E>> {
+        loop {
+            return match self.receiver.poll_recv_lagged(cx) {
+                Poll::Ready(Ok(event)) => Poll::Ready(Some(event)),
+                Poll::Ready(Err(RecvError::Lagged(_))) => continue,
+                Poll::Ready(Err(RecvError::Closed)) => Poll::Ready(None),
+                Poll::Pending => Poll::Pending,
+            };
+        }
+    }
+}
@@ -1,15 +1,545 @@
-use std::{
+use alloc::{
     collections::{BTreeMap, VecDeque},
-    sync::Arc,
-    task::{Context, Poll, RawWaker, RawWakerVTable, Wake, Waker},
+    string::{String, ToString},
+    task::Wake,
+    vec::Vec,
 };
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicUsize},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+mod builder;
+mod deferred;
+mod local_pool;
+mod pinned;
+// Both keep state in statics or real threads, which loom can't model
+#[cfg(not(loom))]
+mod static_tasks;
+#[cfg(all(feature = "std", not(loom)))]
+mod threaded;
+
+#[cfg(not(loom))]
+pub use crate::__static_task as static_task;
+pub use builder::{Spawn, TaskBuilder};
+pub use deferred::{defer, spawn_from_irq};
+pub use local_pool::{LocalPool, LocalSpawner};
+pub use pinned::block_on;
+#[cfg(not(loom))]
+pub use static_tasks::{
+    STORAGE_ALIGN, StaticExecutor, StaticTask, TaskFn, TaskStorage, future_size,
+};
+#[cfg(all(feature = "std", not(loom)))]
+pub use threaded::ThreadPool;
+
+use crate::{
+    LocalTask, Task, TaskId, TaskOptions, capability, context, idle, log,
+    memory::{self, OomPolicy, TaskMemory},
+    metrics::{Counter, Gauge, Histogram, Metric, Sample},
+    priority::{self, Priority},
+    profiler,
+    sync::loom::{self, Arc, ArrayQueue, AtomicU64, Ordering},
+    time,
+    trace::{self, EventKind},
+    watchdog,
+};
+
+static SPAWNED: Counter = Counter::new("executor_tasks_spawned_total", "Tasks spawned");
+static FINISHED: Counter = Counter::new(
+    "executor_tasks_finished_total",
+    "Tasks that completed or were aborted",
+);
+static LIVE: Gauge = Gauge::new("executor_tasks", "Tasks spawned and not finished yet");
+static POLLS: Counter = Counter::new("executor_polls_total", "Times a task was polled");
+static OOM_ABORTS: Counter = Counter::new(
+    "executor_oom_aborts_total",
+    "Tasks dropped for running out of memory",
+);
+static WAKES: Counter = Counter::new("executor_wakes_total", "Times a task was woken");
+static DEADLINE_MISSES: Counter = Counter::new(
+    "executor_deadline_misses_total",
+    "Polls later after the wake than the task's deadline",
+);
+
+/// Upper bounds of the wake latency histograms, in microseconds
+pub const LATENCY_BUCKETS: [u64; 11] = [
+    10, 50, 100, 500, 1_000, 5_000, 10_000, 50_000, 100_000, 500_000, 1_000_000,
+];
+
+static WAKE_LATENCY: Histogram<11> = Histogram::new(
+    "executor_wake_latency_microseconds",
+    "Time from a task being woken or spawned to its poll",
+    LATENCY_BUCKETS,
+);
+
+/// Where a task is between spawning and finishing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    /// Being polled on the thread asking
+    Running,
+    /// Woken or just spawned, waiting for its poll
+    Ready,
+    /// Waiting to be woken
+    Waiting,
+}
+
+/// What became of a task, from [`status`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskStatus {
+    /// Being polled on the thread asking
+    Running,
+    /// Spawned and not finished, waiting for a wake or its poll
+    Pending,
+    /// Its future returned
+    Completed,
+    /// Dropped before its future returned, by [`abort`] or for running out
+    /// of memory
+    Cancelled,
+}
+
+impl TaskStatus {
+    /// Completed or cancelled, it won't be polled again
+    pub fn is_finished(self) -> bool {
+        matches!(self, TaskStatus::Completed | TaskStatus::Cancelled)
+    }
+}
+
+/// A spawned task as reported by [`tasks`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskInfo {
+    pub id: TaskId,
+    pub name: Option<String>,
+    pub priority: Priority,
+    /// Tasks on a [`SimpleExecutor`] are always ready but only show that
+    /// until their first poll
+    pub state: TaskState,
+    /// Times the task has been polled
+    pub polls: u64,
+    /// Times its waker was called, always zero on a [`SimpleExecutor`],
+    /// which polls without one
+    pub wakes: u64,
+    /// Heap use while it was polled, see [`memory::task_usage`]
+    pub memory: Option<TaskMemory>,
+    /// Longest wait from a wake to the poll, see [`Task::with_deadline`]
+    pub deadline: Option<Duration>,
+    /// Polls that came later than the deadline
+    pub deadline_misses: u64,
+}
+
+/// Wakes of a task, shared with its waker, which can't take a lock: it may
+/// be called from an interrupt handler
+#[derive(Default)]
+struct WakeStats {
+    wakes: AtomicU64,
+    woken_at: WokenAt,
+}
+
+/// When a task was first woken since its last poll
+#[derive(Default)]
+pub(crate) struct WokenAt(
+    // Uptime in nanoseconds plus one, `0` if not woken
+    AtomicU64,
+);
+
+impl WokenAt {
+    #[cfg(not(loom))]
+    pub(crate) const fn new() -> Self {
+        WokenAt(AtomicU64::new(0))
+    }
+
+    fn is_woken(&self) -> bool {
+        self.0.load(Ordering::Relaxed) != 0
+    }
+
+    /// Later wakes before the poll don't move it
+    pub(crate) fn wake(&self) {
+        let now = time::uptime().as_nanos() as u64 + 1;
+        let _ = self
+            .0
+            .compare_exchange(0, now, Ordering::Relaxed, Ordering::Relaxed);
+    }
+
+    /// Microseconds since the wake, called when the task is polled
+    pub(crate) fn take_latency(&self) -> Option<u64> {
+        let woken = self.0.swap(0, Ordering::Relaxed).checked_sub(1)?;
+        let now = time::uptime().as_nanos() as u64;
+        Some(now.saturating_sub(woken) / 1000)
+    }
+
+    /// [`take_latency`](WokenAt::take_latency) into the global histogram
+    pub(crate) fn observe(&self) -> Option<u64> {
+        let latency = self.take_latency()?;
+        WAKE_LATENCY.observe(latency);
+        Some(latency)
+    }
+}
+
+struct Registered {
+    info: TaskInfo,
+    wake: Arc<WakeStats>,
+    // Not registered as a metric, `latency_samples` labels it instead
+    latency: Histogram<11>,
+}
+
+// Set by `stop`, cleared when a run starts
+static STOPPING: AtomicBool = AtomicBool::new(false);
+// Woken tasks an `Executor` made from now on holds at once
+static QUEUE_CAPACITY: AtomicUsize = AtomicUsize::new(100);
+// Every task spawned on any executor and not finished yet
+static REGISTRY: spin::Mutex<BTreeMap<TaskId, Registered>> = spin::Mutex::new(BTreeMap::new());
+// Tasks to drop, each executor takes out the ones it runs
+static ABORTED: spin::Mutex<Vec<TaskId>> = spin::Mutex::new(Vec::new());
+// How the last `ENDED_KEPT` finished tasks ended, oldest first
+static ENDED: spin::Mutex<VecDeque<(TaskId, TaskStatus)>> = spin::Mutex::new(VecDeque::new());
+
+/// Finished tasks [`status`] remembers
+pub const ENDED_KEPT: usize = 1024;
+
+/// Snapshot of all live tasks, ordered by id
+pub fn tasks() -> Vec<TaskInfo> {
+    snapshot(&REGISTRY.lock())
+}
+
+/// Where task `id` is at, on any executor. `None` if it was never spawned,
+/// or finished longer ago than the last [`ENDED_KEPT`] tasks.
+///
+/// A task polled on another thread right now shows as
+/// [`Pending`](TaskStatus::Pending).
+pub fn status(id: TaskId) -> Option<TaskStatus> {
+    if context::current().is_some_and(|(current, _)| current == id) {
+        return Some(TaskStatus::Running);
+    }
+    if REGISTRY.lock().contains_key(&id) || is_spawned(id) {
+        return Some(TaskStatus::Pending);
+    }
+    ENDED
+        .lock()
+        .iter()
+        .rev()
+        .find(|(ended, _)| *ended == id)
+        .map(|&(_, status)| status)
+}
+
+/// [`tasks`], or `None` if the registry is locked, for panic reports
+pub(crate) fn try_tasks() -> Option<Vec<TaskInfo>> {
+    REGISTRY.try_lock().map(|registry| snapshot(&registry))
+}
+
+fn snapshot(registry: &BTreeMap<TaskId, Registered>) -> Vec<TaskInfo> {
+    let current = context::current().map(|(id, _)| id);
+    registry
+        .values()
+        .map(|registered| TaskInfo {
+            state: if current == Some(registered.info.id) {
+                TaskState::Running
+            } else if registered.wake.woken_at.is_woken() {
+                TaskState::Ready
+            } else {
+                TaskState::Waiting
+            },
+            wakes: registered.wake.wakes.load(Ordering::Relaxed),
+            memory: memory::task_usage(registered.info.id),
+            ..registered.info.clone()
+        })
+        .collect()
+}
+
+/// Each task's wake latency histogram labelled with the task, for
+/// [`metrics::snapshot`](crate::metrics::snapshot)
+pub(crate) fn latency_samples() -> Vec<Sample> {
+    REGISTRY
+        .lock()
+        .values()
+        .filter(|registered| registered.latency.count() > 0)
+        .map(|registered| {
+            let mut sample = registered.latency.sample();
+            sample
+                .labels
+                .push(("task", registered.info.id.as_u64().to_string()));
+            if let Some(name) = &registered.info.name {
+                sample.labels.push(("name", name.clone()));
+            }
+            sample
+        })
+        .collect()
+}
+
+/// Room for woken tasks in each [`Executor`] made afterwards, 100 by
+/// default. Wakes with the queue full aren't lost, the executor then looks
+/// through all its tasks for the woken ones, which takes longer.
+pub fn set_queue_capacity(capacity: usize) {
+    QUEUE_CAPACITY.store(capacity.max(1), Ordering::Relaxed);
+}
+
+/// See [`set_queue_capacity`]
+pub fn queue_capacity() -> usize {
+    QUEUE_CAPACITY.load(Ordering::Relaxed)
+}
+
+/// Drop a task the next time its executor gets around to it, `false` if
+/// there is no such task
+pub fn abort(id: TaskId) -> bool {
+    if !REGISTRY.lock().contains_key(&id) {
+        // Spawned from a task and not picked up yet, or gone
+        return drop_spawned(id);
+    }
+    ABORTED.lock().push(id);
+    true
+}
+
+/// Why an executor wouldn't run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunError {
+    /// Called from inside this task. The executor polling it would wait on
+    /// the inner loop forever, so `.await` or [`spawn`] instead.
+    InTask(TaskId),
+    /// Called from a future [`block_on`] or [`Executor::run_until`] polls,
+    /// or another executor's loop on this thread
+    Nested,
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunError::InTask(task) => write!(
+                f,
+                "executor started from inside task {}, `.await` the future or spawn it instead",
+                task
+            ),
+            RunError::Nested => write!(
+                f,
+                "executor started while another runs on this thread, `.await` the future instead"
+            ),
+        }
+    }
+}
+
+/// Mark an executor as running on this thread for as long as the guard
+/// lives, unless one already is
+fn enter() -> Result<context::Run, RunError> {
+    if let Some((task, _)) = context::current() {
+        return Err(RunError::InTask(task));
+    }
+    let run = context::run().ok_or(RunError::Nested)?;
+    STOPPING.store(false, Ordering::Release);
+    Ok(run)
+}
+
+/// Make the executors running now return from `run` after the poll in
+/// progress, leaving their tasks unpolled. For shutting down, see
+/// [`shutdown`](crate::shutdown).
+pub fn stop() {
+    STOPPING.store(true, Ordering::Release);
+    idle::kick();
+}
+
+fn stopping() -> bool {
+    STOPPING.load(Ordering::Acquire)
+}
+
+/// Wake the tasks whose timers are due, nothing to do without `timers`
+fn wake_expired_timers() {
+    #[cfg(feature = "timers")]
+    crate::timer::wake_expired();
+}
+
+/// Spawn `task` from inside another, on the executor polling the caller,
+/// which picks it up before it next goes idle. For tasks starting others
+/// on an [`Executor`] or [`SimpleExecutor`]; elsewhere it waits for one on
+/// this thread.
+///
+/// From a [`ThreadPool`]'s worker it goes to the pool's injector, for any
+/// worker to take, unlike [`spawn_local`]. Other executors are one a
+/// thread, so it stays on the caller's.
+pub fn spawn(task: Task) -> TaskId {
+    #[cfg(all(feature = "std", not(loom)))]
+    return threaded::spawn(task, |task| spawn_local(task.into()));
+    #[cfg(not(all(feature = "std", not(loom))))]
+    spawn_local(task.into())
+}
+
+/// Spawn `task` from inside another, on the executor polling the caller
+/// and no other, see [`spawn`]
+pub fn spawn_local(task: LocalTask) -> TaskId {
+    let id = task.id();
+    #[cfg(feature = "std")]
+    SPAWNS.with(|spawns| spawns.borrow_mut().push(task));
+    #[cfg(not(feature = "std"))]
+    SPAWNS.0.lock().push(task);
+    idle::kick();
+    id
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static SPAWNS: core::cell::RefCell<Vec<LocalTask>> = const { core::cell::RefCell::new(Vec::new()) };
+}
+
+// No threads without std, so no other executor could take them
+#[cfg(not(feature = "std"))]
+static SPAWNS: Spawns = Spawns(spin::Mutex::new(Vec::new()));
+
+#[cfg(not(feature = "std"))]
+struct Spawns(spin::Mutex<Vec<LocalTask>>);
+
+// SAFETY: without std everything runs on the one thread
+#[cfg(not(feature = "std"))]
+unsafe impl Sync for Spawns {}
+
+/// Tasks [`spawn`]ed or [`spawn_local`]ed since the last call, and those
+/// interrupt handlers [`defer`]red
+fn take_spawned() -> Vec<LocalTask> {
+    #[cfg(feature = "std")]
+    let mut spawned = SPAWNS.with(|spawns| core::mem::take(&mut *spawns.borrow_mut()));
+    #[cfg(not(feature = "std"))]
+    let mut spawned = core::mem::take(&mut *SPAWNS.0.lock());
+    spawned.extend(deferred::take());
+    spawned
+}
+
+/// `id` is among the tasks waiting for [`take_spawned`]
+fn is_spawned(id: TaskId) -> bool {
+    let contains = |spawns: &Vec<LocalTask>| spawns.iter().any(|task| task.id() == id);
+    #[cfg(feature = "std")]
+    return SPAWNS.with(|spawns| contains(&spawns.borrow()));
+    #[cfg(not(feature = "std"))]
+    contains(&SPAWNS.0.lock())
+}
+
+/// Drop `id` if it's among the tasks waiting for [`take_spawned`]
+fn drop_spawned(id: TaskId) -> bool {
+    let remove = |spawns: &mut Vec<LocalTask>| {
+        let count = spawns.len();
+        spawns.retain(|task| task.id() != id);
+        spawns.len() < count
+    };
+    #[cfg(feature = "std")]
+    return SPAWNS.with(|spawns| remove(&mut spawns.borrow_mut()));
+    #[cfg(not(feature = "std"))]
+    remove(&mut SPAWNS.0.lock())
+}
+
+fn register(options: &TaskOptions) {
+    let info = TaskInfo {
+        id: options.id,
+        name: options.name.clone(),
+        priority: options.priority,
+        state: TaskState::Ready,
+        polls: 0,
+        wakes: 0,
+        memory: None,
+        deadline: options.deadline,
+        deadline_misses: 0,
+    };
+    let registered = Registered {
+        info,
+        wake: Arc::new(WakeStats::default()),
+        latency: Histogram::new(
+            "executor_task_wake_latency_microseconds",
+            "Time from the task being woken or spawned to its poll",
+            LATENCY_BUCKETS,
+        ),
+    };
+    // Spawning it is the first wake
+    registered.wake.woken_at.wake();
+    REGISTRY.lock().insert(options.id, registered);
+    trace::record(options.id, EventKind::Spawn);
+    memory::track(options.id);
+    capability::grant(options.id, &options.capabilities);
+    if let Some((limit, on_overrun)) = options.time_limit {
+        watchdog::limit(options.id, limit, on_overrun);
+    }
+    #[cfg(feature = "channels")]
+    crate::env::attach(options.id, &options.env);
+    crate::console::vt::attach(options.id, options.vt);
+    SPAWNED.inc();
+    LIVE.add(1);
+}
+
+/// Times `id` was polled, `None` if it isn't running
+pub(crate) fn polls(id: TaskId) -> Option<u64> {
+    REGISTRY
+        .lock()
+        .get(&id)
+        .map(|registered| registered.info.polls)
+}
+
+/// Count the poll about to happen and how long the task waited for it
+fn count_poll(id: TaskId) {
+    POLLS.inc();
+    if let Some(registered) = REGISTRY.lock().get_mut(&id) {
+        registered.info.polls += 1;
+        if let Some(latency) = registered.wake.woken_at.observe() {
+            registered.latency.record(latency);
+            if registered
+                .info
+                .deadline
+                .is_some_and(|deadline| latency > deadline.as_micros() as u64)
+            {
+                registered.info.deadline_misses += 1;
+                DEADLINE_MISSES.inc();
+            }
+        }
+    }
+}
+
+/// Take out the aborts for tasks `owns` says are ours, the rest stay
+fn take_aborted(mut owns: impl FnMut(TaskId) -> bool) -> Vec<TaskId> {
+    let mut aborted = Vec::new();
+    ABORTED.lock().retain(|&id| {
+        let ours = owns(id);
+        if ours {
+            aborted.push(id);
+        }
+        !ours
+    });
+    aborted
+}
 
-use crossbeam_queue::ArrayQueue;
+/// `id` ended as `status`, completed or cancelled
+fn finished(id: TaskId, status: TaskStatus) {
+    trace::record(id, EventKind::Complete);
+    if REGISTRY.lock().remove(&id).is_some() {
+        FINISHED.inc();
+        LIVE.add(-1);
+        let mut ended = ENDED.lock();
+        if ended.len() == ENDED_KEPT {
+            ended.pop_front();
+        }
+        ended.push_back((id, status));
+    }
+    priority::forget(id);
+    capability::forget(id);
+    memory::untrack(id);
+    watchdog::unlimit(id);
+    #[cfg(feature = "channels")]
+    crate::env::detach(id);
+    crate::console::vt::detach(id);
+}
 
-use crate::{Task, TaskId};
+/// `true` if `id` ran out of memory in the poll that just ended and should
+/// be dropped, see [`OomPolicy`]
+fn out_of_memory(id: TaskId) -> bool {
+    if !memory::take_out_of_memory(id) {
+        return false;
+    }
+    match memory::oom_policy() {
+        OomPolicy::Panic => panic!("task {} ran out of memory", id),
+        OomPolicy::AbortTask => {
+            log::error!("task {} ran out of memory, dropping it", id);
+            OOM_ABORTS.inc();
+            true
+        }
+        OomPolicy::Fail => false,
+    }
+}
 
 pub struct SimpleExecutor {
-    task_queue: VecDeque<Task>,
+    task_queue: VecDeque<LocalTask>,
 }
 
 impl SimpleExecutor {
@@ -20,59 +550,198 @@ impl SimpleExecutor {
         }
     }
 
-    pub fn spawn(&mut self, task: Task) {
+    pub fn spawn(&mut self, task: Task) -> TaskId {
+        self.spawn_local(task.into())
+    }
+
+    /// Spawn a task whose future isn't `Send`, it's polled here only
+    pub fn spawn_local(&mut self, task: LocalTask) -> TaskId {
+        let task_id = task.id();
+        register(&task.options);
         self.task_queue.push_back(task);
+        task_id
+    }
+
+    /// Spawn `future` once [`TaskBuilder`] has set its options
+    pub fn build_task(
+        &mut self,
+        future: impl Future<Output = ()> + Send + 'static,
+    ) -> TaskBuilder<'_, Self> {
+        TaskBuilder::new(self, future)
     }
 }
 
 impl SimpleExecutor {
-    /// Repeatedly poll all queued tasks
-    pub fn run(&mut self) {
-        while let Some(mut task) = self.task_queue.pop_front() {
-            let waker = dummy_waker();
-            let mut context = Context::from_waker(&waker);
-            match task.poll(&mut context) {
-                Poll::Ready(()) => {}
+    /// Repeatedly poll all queued tasks, until they've all finished or
+    /// [`stop`] is called
+    pub fn run(&mut self) -> Result<(), RunError> {
+        let _run = enter()?;
+        while !stopping() {
+            watchdog::check_time_limits();
+            for task in take_spawned() {
+                self.spawn_local(task);
+            }
+            let Some(mut task) = self.task_queue.pop_front() else {
+                break;
+            };
+            let queue = &self.task_queue;
+            let aborted = take_aborted(|id| id == task.id() || queue.iter().any(|t| t.id() == id));
+            if !aborted.is_empty() {
+                self.task_queue.retain(|t| !aborted.contains(&t.id()));
+                aborted
+                    .iter()
+                    .for_each(|&id| finished(id, TaskStatus::Cancelled));
+                if aborted.contains(&task.id()) {
+                    continue;
+                }
+            }
+
+            // Every task is polled each round, no need to be woken
+            let mut context = Context::from_waker(Waker::noop());
+            let _enter = context::enter(task.id(), task.priority());
+            let _memory = memory::enter(task.id());
+            count_poll(task.id());
+            watchdog::poll_started(task.id());
+            profiler::poll_started(task.id());
+            trace::record(task.id(), EventKind::Poll);
+            let poll = task.poll(&mut context);
+            profiler::poll_ended();
+            watchdog::poll_ended();
+            if poll.is_pending() {
+                trace::record(task.id(), EventKind::Pending);
+            }
+            match poll {
+                Poll::Ready(()) => finished(task.id(), TaskStatus::Completed),
+                Poll::Pending if out_of_memory(task.id()) => {
+                    finished(task.id(), TaskStatus::Cancelled)
+                }
                 Poll::Pending => self.task_queue.push_back(task),
             }
         }
+        Ok(())
     }
 }
 
-/// Using a task_queue and BTreeMap
+/// Using a task_queue and BTreeMap.
+///
+/// # Waking from interrupts
+///
+/// Its wakers can be called from interrupt handlers: waking never
+/// allocates, frees memory, takes a lock or panics. More wakes than
+/// [`set_queue_capacity`] has room for only cost the executor a look through
+/// its tasks. The same holds for [`StaticExecutor`] and
+/// [`block_on`], not for the std build's `ThreadPool`, whose wakers lock
+/// and allocate.
+///
+/// What the handlers call on the way holds to it too: the keyboard and mouse
+/// queues wake their readers through an [`IrqNotify`](crate::sync::IrqNotify),
+/// replies to i8042 commands through an `AtomicWaker`, and input boosts
+/// only bump a counter.
 pub struct Executor {
-    tasks: BTreeMap<TaskId, Task>,
+    tasks: BTreeMap<TaskId, LocalTask>,
     // Shared between executor and wakers
-    task_queue: Arc<ArrayQueue<TaskId>>,
+    task_queue: Arc<WakeQueue>,
+    // Spawned and woken tasks waiting for their turn, picked by priority
+    ready: VecDeque<TaskId>,
     // Caches waker of a task after creation
-    waker_cache: BTreeMap<TaskId, Waker>,
+    waker_cache: BTreeMap<TaskId, alloc::sync::Arc<TaskWaker>>,
+    // Wakers of finished tasks still held elsewhere, freed here once they
+    // aren't, rather than by whoever drops them last
+    retired: Vec<alloc::sync::Arc<TaskWaker>>,
 }
 
 impl Executor {
     pub fn new() -> Self {
+        // Registering takes a lock and allocates, not something to do in
+        // the first wake
+        WAKES.register();
         Executor {
             tasks: BTreeMap::new(),
-            task_queue: Arc::new(ArrayQueue::new(100)),
+            task_queue: Arc::new(WakeQueue {
+                ids: ArrayQueue::new(queue_capacity()),
+                overflowed: loom::AtomicBool::new(false),
+            }),
+            ready: VecDeque::new(),
             waker_cache: BTreeMap::new(),
+            retired: Vec::new(),
         }
     }
 
-    pub fn spawn(&mut self, task: Task) {
-        let task_id = task.id;
-        if self.tasks.insert(task.id, task).is_some() {
+    pub fn spawn(&mut self, task: Task) -> TaskId {
+        self.spawn_local(task.into())
+    }
+
+    /// Spawn a task whose future isn't `Send`, it's polled here only
+    pub fn spawn_local(&mut self, task: LocalTask) -> TaskId {
+        let task_id = task.id();
+        register(&task.options);
+        if self.tasks.insert(task.id(), task).is_some() {
             panic!("task with same ID already in tasks");
         }
-        self.task_queue.push(task_id).expect("queue full");
+        self.ready.push_back(task_id);
+        task_id
+    }
+
+    /// [`status`] of a task, spawned here or elsewhere
+    pub fn status(&self, id: TaskId) -> Option<TaskStatus> {
+        status(id)
+    }
+
+    /// Spawn `future` once [`TaskBuilder`] has set its options
+    pub fn build_task(
+        &mut self,
+        future: impl Future<Output = ()> + Send + 'static,
+    ) -> TaskBuilder<'_, Self> {
+        TaskBuilder::new(self, future)
     }
 
     fn run_ready_tasks(&mut self) {
         let Self {
             tasks,
             task_queue,
+            ready,
             waker_cache,
+            retired,
         } = self;
+        let mut retire = |waker_cache: &mut BTreeMap<TaskId, alloc::sync::Arc<TaskWaker>>,
+                          task_id| {
+            if let Some(waker) = waker_cache.remove(&task_id) {
+                retired.push(waker);
+            }
+        };
+
+        loop {
+            watchdog::check_time_limits();
+            for task in take_spawned() {
+                register(&task.options);
+                ready.push_back(task.id());
+                if tasks.insert(task.id(), task).is_some() {
+                    panic!("task with same ID already in tasks");
+                }
+            }
+            for task_id in take_aborted(|id| tasks.contains_key(&id)) {
+                tasks.remove(&task_id);
+                retire(waker_cache, task_id);
+                finished(task_id, TaskStatus::Cancelled);
+            }
+
+            // Move freshly woken tasks over, then pick the most important one.
+            // Wakes that happen while polling show up in the next round.
+            while let Some(task_id) = task_queue.ids.pop() {
+                ready.push_back(task_id);
+            }
+            if task_queue.overflowed.swap(false, Ordering::Acquire) {
+                // Some didn't fit in the queue, their flags tell
+                for (task_id, waker) in waker_cache.iter() {
+                    if waker.queued.load(Ordering::Acquire) && !ready.contains(task_id) {
+                        ready.push_back(*task_id);
+                    }
+                }
+            }
+            let Some(task_id) = next_ready(tasks, ready) else {
+                break;
+            };
 
-        while let Some(task_id) = task_queue.pop() {
             // Still polling this in a busy loop, if a task in the queue has a waker (ready)
             // poll the task with the waker wrapped in a context
             // If no waker is taken the task is effectively ignored by poll.
@@ -80,76 +749,282 @@ impl Executor {
                 Some(task) => task,
                 None => continue,
             };
-            let waker = waker_cache
-                .entry(task_id)
-                .or_insert_with(|| TaskWaker::new(task_id, task_queue.clone()));
-            let mut context = Context::from_waker(waker);
-            match task.poll(&mut context) {
-                Poll::Ready(()) => {
-                    tasks.remove(&task_id);
-                    waker_cache.remove(&task_id);
-                }
-                Poll::Pending => {}
+            let task_waker = waker_cache.entry(task_id).or_insert_with(|| {
+                TaskWaker::new(task_id, task_queue.clone(), wake_stats(task_id))
+            });
+            // Wakes from here on queue the task again
+            task_waker.queued.store(false, Ordering::Release);
+            // Additionally constructs vtable and raw waker
+            let waker = Waker::from(task_waker.clone());
+            let mut context = Context::from_waker(&waker);
+            let _enter = context::enter(task_id, task.priority());
+            let _memory = memory::enter(task_id);
+            let real_time = priority::effective(task_id, task.priority()) == Priority::RealTime;
+            let started = real_time.then(time::uptime);
+            priority::end_boost(task_id);
+            count_poll(task_id);
+            watchdog::poll_started(task_id);
+            profiler::poll_started(task_id);
+            trace::record(task_id, EventKind::Poll);
+            let poll = task.poll(&mut context);
+            profiler::poll_ended();
+            watchdog::poll_ended();
+            if let Some(started) = started {
+                priority::charge_rt(time::uptime().saturating_sub(started));
+            }
+            if poll.is_pending() {
+                trace::record(task_id, EventKind::Pending);
+            }
+            drop(waker);
+            let ended = if poll.is_ready() {
+                Some(TaskStatus::Completed)
+            } else {
+                out_of_memory(task_id).then_some(TaskStatus::Cancelled)
+            };
+            if let Some(status) = ended {
+                tasks.remove(&task_id);
+                retire(waker_cache, task_id);
+                finished(task_id, status);
             }
         }
+        retired.retain(|waker| alloc::sync::Arc::strong_count(waker) > 1);
     }
 
-    pub fn run(&mut self) -> ! {
+    /// Run spawned tasks until `future`, pinned on the caller's stack,
+    /// completes:
+    ///
+    /// ```ignore
+    /// let config = executor.run_until(pin!(load_config()))?;
+    /// ```
+    pub fn run_until<T>(
+        &mut self,
+        mut future: Pin<&mut dyn Future<Output = T>>,
+    ) -> Result<T, RunError> {
+        let _run = enter()?;
+        let waker = pinned::waker();
+        let mut context = Context::from_waker(&waker);
+        waker.wake_by_ref();
         loop {
+            if let Poll::Ready(output) = pinned::poll_if_woken(&mut future, &mut context) {
+                return Ok(output);
+            }
+            wake_expired_timers();
+            self.run_ready_tasks();
+            idle::wait(|| self.has_woken() || pinned::is_woken());
+        }
+    }
+
+    /// Poll tasks until [`stop`] is called, even with none left
+    pub fn run(&mut self) -> Result<(), RunError> {
+        let _run = enter()?;
+        while !stopping() {
+            wake_expired_timers();
             self.run_ready_tasks();
+            // Until the next interrupt, the timer's at the latest
+            idle::wait(|| self.has_woken() || stopping());
         }
+        Ok(())
+    }
+
+    /// A task is waiting for its turn
+    fn has_woken(&self) -> bool {
+        !self.ready.is_empty()
+            || !self.task_queue.ids.is_empty()
+            || self.task_queue.overflowed.load(Ordering::Acquire)
     }
 }
 
+/// Highest effective priority first, FIFO among equals.
+///
+/// Priorities are looked up at pick time rather than when the task is woken,
+/// so a priority inherited while the task sits in the queue takes effect.
+/// Linear in the number of ready tasks, which stays small here, with the
+/// priority locks taken once per pick.
+fn next_ready(tasks: &BTreeMap<TaskId, LocalTask>, ready: &mut VecDeque<TaskId>) -> Option<TaskId> {
+    let priorities = priority::lookup();
+    let mut best: Option<(usize, Priority)> = None;
+    for (index, task_id) in ready.iter().enumerate() {
+        // Stale ids of finished tasks are picked first so they get dropped
+        let priority = match tasks.get(task_id) {
+            Some(task) => priorities.effective(*task_id, task.priority()),
+            None => return ready.remove(index),
+        };
+        if best.is_none_or(|(_, p)| priority > p) {
+            best = Some((index, priority));
+        }
+    }
+    ready.remove(best?.0)
+}
+
+/// Wake statistics of `id`, detached ones if it isn't registered
+fn wake_stats(id: TaskId) -> Arc<WakeStats> {
+    REGISTRY
+        .lock()
+        .get(&id)
+        .map_or_else(Default::default, |registered| registered.wake.clone())
+}
+
+// Tasks woken since the executor last looked
+struct WakeQueue {
+    ids: ArrayQueue<TaskId>,
+    // A wake found `ids` full, the task's `queued` flag has it instead
+    overflowed: loom::AtomicBool,
+}
+
+/// Waker of an [`Executor`]'s task.
+///
+/// Waking is safe in an interrupt handler: it only touches atomics, the
+/// lock-free queue and the trace ring. A full queue sets a flag instead of
+/// failing, a task already queued isn't queued twice. The executor holds on
+/// to every waker of a finished task until it holds the last reference, so
+/// dropping one never frees memory either. The executor's counters are
+/// registered before its first wake.
 struct TaskWaker {
     task_id: TaskId,
-    task_queue: Arc<ArrayQueue<TaskId>>,
+    task_queue: Arc<WakeQueue>,
+    stats: Arc<WakeStats>,
+    // Woken and not polled since
+    queued: loom::AtomicBool,
 }
 
 impl TaskWaker {
+    fn new(
+        task_id: TaskId,
+        task_queue: Arc<WakeQueue>,
+        stats: Arc<WakeStats>,
+    ) -> alloc::sync::Arc<TaskWaker> {
+        alloc::sync::Arc::new(TaskWaker {
+            task_id,
+            task_queue,
+            stats,
+            queued: loom::AtomicBool::new(false),
+        })
+    }
+
     fn wake_task(&self) {
-        self.task_queue.push(self.task_id).expect("task queue full");
+        WAKES.inc();
+        self.stats.wakes.fetch_add(1, Ordering::Relaxed);
+        self.stats.woken_at.wake();
+        trace::record(self.task_id, EventKind::Wake);
+        if !self.queued.swap(true, Ordering::AcqRel)
+            && self.task_queue.ids.push(self.task_id).is_err()
+        {
+            self.task_queue.overflowed.store(true, Ordering::Release);
+        }
+        idle::kick();
     }
 }
 
 impl Wake for TaskWaker {
-    fn wake(self: Arc<Self>) {
+    fn wake(self: alloc::sync::Arc<Self>) {
         self.wake_task()
     }
 
-    fn wake_by_ref(self: &Arc<Self>) {
+    fn wake_by_ref(self: &alloc::sync::Arc<Self>) {
         self.wake_task()
     }
 }
 
-impl TaskWaker {
-    fn new(task_id: TaskId, task_queue: Arc<ArrayQueue<TaskId>>) -> Waker {
-        // Additionally constructs vtable and raw waker
-        Waker::from(Arc::new(TaskWaker {
-            task_id,
-            task_queue,
-        }))
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use core::{future::poll_fn, pin::pin};
+
+    use super::*;
+
+    /// Wakes past the queue's capacity set the overflow flag, and the
+    /// executor still polls every woken task once more
+    #[test]
+    fn wakes_past_a_full_queue_are_not_lost() {
+        let queue = Arc::new(WakeQueue {
+            ids: ArrayQueue::new(2),
+            overflowed: loom::AtomicBool::new(false),
+        });
+        let wakers: Vec<_> = (0..8)
+            .map(|_| TaskWaker::new(TaskId::new(), queue.clone(), Default::default()))
+            .collect();
+        for waker in wakers.iter().chain(&wakers) {
+            waker.wake_task();
+        }
+        assert_eq!(queue.ids.len(), 2);
+        assert!(queue.overflowed.load(Ordering::Acquire));
+        assert!(
+            wakers
+                .iter()
+                .all(|waker| waker.queued.load(Ordering::Acquire))
+        );
+
+        let mut executor = Executor::new();
+        executor.task_queue = Arc::new(WakeQueue {
+            ids: ArrayQueue::new(2),
+            overflowed: loom::AtomicBool::new(false),
+        });
+        let parked = Arc::new(spin::Mutex::new(Vec::<Waker>::new()));
+        let done = Arc::new(AtomicUsize::new(0));
+        for _ in 0..8 {
+            let (parked, done) = (parked.clone(), done.clone());
+            let mut polled = false;
+            executor.spawn(Task::new(poll_fn(move |cx| {
+                if polled {
+                    done.fetch_add(1, Ordering::Relaxed);
+                    return Poll::Ready(());
+                }
+                polled = true;
+                parked.lock().push(cx.waker().clone());
+                Poll::Pending
+            })));
+        }
+        let mut woken = false;
+        let all_done = executor.run_until(pin!(poll_fn(|cx| {
+            if !woken && parked.lock().len() == 8 {
+                for waker in parked.lock().drain(..) {
+                    waker.wake_by_ref();
+                    waker.wake();
+                }
+                woken = true;
+            }
+            if done.load(Ordering::Relaxed) == 8 {
+                return Poll::Ready(());
+            }
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        })));
+        assert_eq!(all_done, Ok(()));
     }
 }
 
-// Sleep CPU if no tasks are in the queue
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use ::loom::{model, thread};
 
-// Not recommended but as of the blog post around 2020 there is no way to initialize
-// a dummy Waker that does nothing
+    use super::*;
 
-// Vtable: Array of pointers used for dynamic dispatch
-fn dummy_raw_waker() -> RawWaker {
-    // Raw pointer to nothing
-    fn no_op(_: *const ()) {}
-    fn clone(_: *const ()) -> RawWaker {
-        dummy_raw_waker()
-    }
+    /// A wake racing the executor clearing `queued` before a poll is either
+    /// seen by that poll or queues the task again
+    #[test]
+    fn wake_during_poll_is_not_lost() {
+        model(|| {
+            let queue = Arc::new(WakeQueue {
+                ids: ArrayQueue::new(1),
+                overflowed: loom::AtomicBool::new(false),
+            });
+            let task_id = TaskId::new();
+            let waker = TaskWaker::new(task_id, queue.clone(), Arc::new(WakeStats::default()));
+            // What the task looks at when polled
+            let event = Arc::new(loom::AtomicBool::new(false));
 
-    let vtable = &RawWakerVTable::new(clone, no_op, no_op, no_op);
-    // return null pointer
-    RawWaker::new(0 as *const (), vtable)
-}
+            let waking = thread::spawn({
+                let (waker, event) = (waker.clone(), event.clone());
+                move || {
+                    event.store(true, Ordering::Release);
+                    waker.wake_task();
+                }
+            });
+            // The executor about to poll
+            waker.queued.store(false, Ordering::Release);
+            let seen = event.load(Ordering::Acquire);
+            waking.join().unwrap();
 
-fn dummy_waker() -> Waker {
-    unsafe { Waker::from_raw(dummy_raw_waker()) }
+            assert!(seen || queue.ids.pop() == Some(task_id));
+        });
+    }
 }
@@ -0,0 +1,85 @@
+//! Spawning with options, one method each instead of a `spawn_*` for every
+//! combination:
+//!
+//! ```ignore
+//! executor
+//!     .build_task(keyboard::print_keypresses())
+//!     .name("keyboard")
+//!     .priority(Priority::High)
+//!     .deadline(Duration::from_millis(5))
+//!     .spawn();
+//! ```
+
+use alloc::string::String;
+use core::{future::Future, time::Duration};
+
+use super::{Executor, SimpleExecutor};
+use crate::{Task, TaskId, capability::Capabilities, priority::Priority};
+
+/// An executor taking spawned tasks, what a [`TaskBuilder`] spawns on
+pub trait Spawn {
+    fn spawn(&mut self, task: Task);
+}
+
+impl Spawn for Executor {
+    fn spawn(&mut self, task: Task) {
+        Executor::spawn(self, task);
+    }
+}
+
+impl Spawn for SimpleExecutor {
+    fn spawn(&mut self, task: Task) {
+        SimpleExecutor::spawn(self, task);
+    }
+}
+
+#[cfg(all(feature = "std", not(loom)))]
+impl Spawn for super::ThreadPool {
+    fn spawn(&mut self, task: Task) {
+        super::ThreadPool::spawn(self, task);
+    }
+}
+
+/// A task being set up, from `build_task` on an executor
+#[must_use = "the task only runs once spawned"]
+pub struct TaskBuilder<'a, S: Spawn + ?Sized> {
+    executor: &'a mut S,
+    task: Task,
+}
+
+impl<'a, S: Spawn + ?Sized> TaskBuilder<'a, S> {
+    pub fn new(executor: &'a mut S, future: impl Future<Output = ()> + Send + 'static) -> Self {
+        TaskBuilder {
+            executor,
+            task: Task::new(future),
+        }
+    }
+
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.task = self.task.with_name(name);
+        self
+    }
+
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.task.options.priority = priority;
+        self
+    }
+
+    /// See [`Task::with_capabilities`]
+    pub fn capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.task = self.task.with_capabilities(capabilities);
+        self
+    }
+
+    /// See [`Task::with_deadline`]
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.task = self.task.with_deadline(deadline);
+        self
+    }
+
+    pub fn spawn(self) -> TaskId {
+        let id = self.task.id();
+        self.executor.spawn(self.task);
+        id
+    }
+}
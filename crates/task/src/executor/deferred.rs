@@ -0,0 +1,89 @@
+//! Spawning from interrupt handlers
+//!
+//! Spawning registers the task, which locks and allocates, so a handler
+//! can't do it. It hands the executor a task made beforehand with
+//! [`spawn_from_irq`], or just a function and a word of data with
+//! [`defer`], the bottom half of its driver. Either waits in a fixed queue
+//! until the executor's next round, which spawns it like any other:
+//!
+//! ```ignore
+//! fn nic_interrupt() {
+//!     let status = nic.read_status();
+//!     if !executor::defer(handle_nic_status, status as usize) {
+//!         // The queue was full, the status is counted as lost
+//!     }
+//! }
+//!
+//! fn handle_nic_status(status: usize) {
+//!     ..
+//! }
+//! ```
+//!
+//! An [`Executor`](super::Executor), a [`SimpleExecutor`](super::SimpleExecutor)
+//! or a `ThreadPool`'s worker picks them up. The queue is made in the first
+//! round of one, until then nothing fits. Pushing doesn't lock or allocate.
+
+use alloc::boxed::Box;
+
+use crossbeam_queue::ArrayQueue;
+
+use crate::{LocalTask, Task, TaskId, idle, metrics::Counter};
+
+/// Tasks and work items waiting for the executor
+const QUEUE_CAPACITY: usize = 64;
+
+static QUEUE: spin::Once<ArrayQueue<Deferred>> = spin::Once::new();
+static REJECTED: Counter = Counter::new(
+    "executor_deferred_rejected_total",
+    "Spawns from interrupt handlers that didn't fit in the queue",
+);
+
+enum Deferred {
+    Task(Box<Task>),
+    Work(fn(usize), usize),
+}
+
+fn push(deferred: Deferred) -> Result<(), Deferred> {
+    // Before the first round, when counting could still allocate
+    let Some(queue) = QUEUE.get() else {
+        return Err(deferred);
+    };
+    queue.push(deferred).inspect_err(|_| REJECTED.inc())?;
+    idle::kick();
+    Ok(())
+}
+
+/// Spawn `task` on the next round of the executor, from an interrupt
+/// handler. It's boxed beforehand so nothing is copied or allocated here.
+/// The task comes back if the queue is full, for the handler to keep;
+/// dropping it there would free its future.
+pub fn spawn_from_irq(task: Box<Task>) -> Result<TaskId, Box<Task>> {
+    let id = task.id();
+    match push(Deferred::Task(task)) {
+        Ok(()) => Ok(id),
+        Err(Deferred::Task(task)) => Err(task),
+        Err(Deferred::Work(..)) => unreachable!("pushed a task"),
+    }
+}
+
+/// Run `work(data)` as a task of its own on the next round of the
+/// executor, from an interrupt handler. `false` if the queue is full.
+pub fn defer(work: fn(usize), data: usize) -> bool {
+    push(Deferred::Work(work, data)).is_ok()
+}
+
+/// What interrupt handlers queued since the last call, as tasks. Makes the
+/// queue on the first call.
+pub(super) fn take() -> impl Iterator<Item = LocalTask> {
+    let queue = QUEUE.call_once(|| {
+        // Registering locks and allocates, not for the first rejection
+        REJECTED.register();
+        ArrayQueue::new(QUEUE_CAPACITY)
+    });
+    core::iter::from_fn(|| queue.pop()).map(|deferred| match deferred {
+        Deferred::Task(task) => (*task).into(),
+        Deferred::Work(work, data) => Task::new(async move { work(data) })
+            .with_name("deferred")
+            .into(),
+    })
+}
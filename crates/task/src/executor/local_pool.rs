@@ -0,0 +1,141 @@
+//! Futures a task drives itself, within its own polls.
+//!
+//! A [`LocalPool`] is an executor nested in a task, for a driver that keeps
+//! many small state machines: one per open port, per outstanding request.
+//! They needn't be `Send`, aren't tasks of their own and go away with the
+//! pool.
+//!
+//! ```ignore
+//! let mut pool = LocalPool::new();
+//! let spawner = pool.spawner();
+//! for port in ports {
+//!     pool.spawn(watch_port(port, spawner.clone()));
+//! }
+//! pool.run_until(shutdown.cancelled()).await;
+//! ```
+
+use alloc::{boxed::Box, rc::Rc, vec::Vec};
+use core::{
+    cell::RefCell,
+    future::{Future, poll_fn},
+    mem,
+    pin::{Pin, pin},
+    task::{Context, Poll, Waker},
+};
+
+use crate::join_set::JoinSet;
+
+type LocalFuture = Pin<Box<dyn Future<Output = ()>>>;
+
+/// Children polled by the task running the pool, see the [module](self)
+pub struct LocalPool {
+    children: JoinSet<()>,
+    spawned: Rc<RefCell<Spawned>>,
+}
+
+// Futures from a `LocalSpawner`, maybe a child being polled, for the pool
+// to pick up
+#[derive(Default)]
+struct Spawned {
+    futures: Vec<LocalFuture>,
+    // Of the task last polling the pool
+    waker: Option<Waker>,
+}
+
+impl LocalPool {
+    pub fn new() -> Self {
+        LocalPool {
+            children: JoinSet::new(),
+            spawned: Rc::default(),
+        }
+    }
+
+    pub fn spawn(&mut self, future: impl Future<Output = ()> + 'static) {
+        self.children.spawn(future);
+    }
+
+    /// For the children to spawn more
+    pub fn spawner(&self) -> LocalSpawner {
+        LocalSpawner {
+            spawned: self.spawned.clone(),
+        }
+    }
+
+    /// Children not finished yet
+    pub fn len(&self) -> usize {
+        self.children.len() + self.spawned.borrow().futures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Poll the children that were woken, `Ready` once they've all finished
+    pub fn poll_run(&mut self, cx: &mut Context) -> Poll<()> {
+        let mut spawned = self.spawned.borrow_mut();
+        if !spawned
+            .waker
+            .as_ref()
+            .is_some_and(|waker| waker.will_wake(cx.waker()))
+        {
+            spawned.waker = Some(cx.waker().clone());
+        }
+        drop(spawned);
+        loop {
+            let futures = mem::take(&mut self.spawned.borrow_mut().futures);
+            for future in futures {
+                self.children.spawn(future);
+            }
+            match self.children.poll_join_next(cx) {
+                Poll::Ready(Some(())) => continue,
+                // A child may have spawned another as it finished
+                _ if !self.spawned.borrow().futures.is_empty() => continue,
+                Poll::Ready(None) => return Poll::Ready(()),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    /// Wait for every child to finish, spawned ones included
+    pub async fn run(&mut self) {
+        poll_fn(|cx| self.poll_run(cx)).await
+    }
+
+    /// Drive the children while waiting for `future`, the ones not
+    /// finished by then stay in the pool
+    pub async fn run_until<F: Future>(&mut self, future: F) -> F::Output {
+        let mut future = pin!(future);
+        poll_fn(|cx| {
+            if let Poll::Ready(output) = future.as_mut().poll(cx) {
+                return Poll::Ready(output);
+            }
+            // Finishing doesn't matter here, only `future` does
+            let _ = self.poll_run(cx);
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+impl Default for LocalPool {
+    fn default() -> Self {
+        LocalPool::new()
+    }
+}
+
+/// Spawns children into a [`LocalPool`], from inside one of them for
+/// instance. They're picked up the next time the pool is polled.
+#[derive(Clone)]
+pub struct LocalSpawner {
+    spawned: Rc<RefCell<Spawned>>,
+}
+
+impl LocalSpawner {
+    pub fn spawn(&self, future: impl Future<Output = ()> + 'static) {
+        let mut spawned = self.spawned.borrow_mut();
+        spawned.futures.push(Box::pin(future));
+        if let Some(waker) = &spawned.waker {
+            waker.wake_by_ref();
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Futures pinned on the caller's stack instead of boxed into a task.
+//!
+//! They run outside any task, so nothing is counted against them and
+//! they're not restricted by capabilities.
+
+use core::{
+    future::Future,
+    pin::Pin,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
+
+use super::RunError;
+use crate::idle;
+
+// A pinned future was woken. Shared by every caller, which only costs a
+// spurious poll when several wait at once.
+static WOKEN: AtomicBool = AtomicBool::new(false);
+
+pub(super) fn waker() -> Waker {
+    fn clone(_: *const ()) -> RawWaker {
+        RawWaker::new(core::ptr::null(), &VTABLE)
+    }
+    fn wake(_: *const ()) {
+        WOKEN.store(true, Ordering::Release);
+        idle::kick();
+    }
+    fn drop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake, drop);
+
+    // SAFETY: the vtable ignores the data pointer
+    unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) }
+}
+
+/// Poll `future` if it was woken since the last poll
+pub(super) fn poll_if_woken<T>(
+    future: &mut Pin<&mut dyn Future<Output = T>>,
+    context: &mut Context,
+) -> Poll<T> {
+    if !WOKEN.swap(false, Ordering::AcqRel) {
+        return Poll::Pending;
+    }
+    future.as_mut().poll(context)
+}
+
+pub(super) fn is_woken() -> bool {
+    WOKEN.load(Ordering::Acquire)
+}
+
+/// Run `future` to completion on this thread, without spawning tasks or
+/// allocating. Pin it on the stack with [`pin!`](core::pin::pin):
+///
+/// ```ignore
+/// let line = executor::block_on(pin!(tty.read_line()))?;
+/// ```
+///
+/// Spawned tasks don't run meanwhile, see
+/// [`Executor::run_until`](super::Executor::run_until) for that. Inside a
+/// task, or a future already being blocked on, it's an error: `.await`
+/// there.
+pub fn block_on<T>(mut future: Pin<&mut dyn Future<Output = T>>) -> Result<T, RunError> {
+    let _run = super::enter()?;
+    let waker = waker();
+    let mut context = Context::from_waker(&waker);
+    WOKEN.store(true, Ordering::Release);
+    loop {
+        if let Poll::Ready(output) = poll_if_woken(&mut future, &mut context) {
+            return Ok(output);
+        }
+        super::wake_expired_timers();
+        // Until the next interrupt, the timer's at the latest
+        idle::wait(is_woken);
+    }
+}
@@ -0,0 +1,329 @@
+//! Tasks whose futures live in statics, for running without a heap.
+//!
+//! [`static_task!`](crate::executor::static_task) reserves storage sized
+//! for an `async fn`'s future at compile time and puts the future there, a
+//! [`StaticExecutor`] runs a fixed number of them:
+//!
+//! ```ignore
+//! async fn blink(port: u16) { ... }
+//!
+//! let mut executor = StaticExecutor::<4>::new();
+//! executor.spawn(static_task!(blink, 0x61).unwrap()).unwrap();
+//! executor.run()?;
+//! ```
+//!
+//! Each `static_task!` has room for one future: spawning it again before
+//! the task finished gives `None`.
+
+use core::{
+    cell::UnsafeCell,
+    fmt,
+    future::Future,
+    mem::{self, MaybeUninit},
+    pin::Pin,
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
+
+use super::{RunError, WokenAt};
+use crate::{
+    TaskId, context, idle, memory,
+    priority::Priority,
+    profiler,
+    trace::{self, EventKind},
+    watchdog,
+};
+
+/// Alignment of the storage, futures needing more don't fit
+pub const STORAGE_ALIGN: usize = 16;
+
+/// Implemented by functions returning a task's future, so
+/// [`future_size`] can name the future's type
+pub trait TaskFn<Args> {
+    type Future: Future<Output = ()>;
+}
+
+macro_rules! task_fn {
+    ($($arg:ident),*) => {
+        impl<F, Fut, $($arg),*> TaskFn<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> Fut,
+            Fut: Future<Output = ()>,
+        {
+            type Future = Fut;
+        }
+    };
+}
+
+task_fn!();
+task_fn!(A);
+task_fn!(A, B);
+task_fn!(A, B, C);
+task_fn!(A, B, C, D);
+
+/// Bytes the future of `function` takes, for sizing a [`TaskStorage`]
+pub const fn future_size<F: TaskFn<Args>, Args>(_function: &F) -> usize {
+    mem::size_of::<F::Future>()
+}
+
+// Flags shared by the storage, its task and the task's wakers
+struct Header {
+    spawned: AtomicBool,
+    woken: AtomicBool,
+    // Id of the task in the storage, for tracing its wakes
+    task: AtomicU64,
+    woken_at: WokenAt,
+}
+
+// Some task was woken, the executor doesn't need to look otherwise
+static WOKEN: AtomicBool = AtomicBool::new(false);
+
+#[repr(C, align(16))]
+struct Aligned<const SIZE: usize>([u8; SIZE]);
+
+/// Room for one task's future of up to `SIZE` bytes, usually declared by
+/// [`static_task!`](crate::executor::static_task)
+pub struct TaskStorage<const SIZE: usize> {
+    header: Header,
+    future: UnsafeCell<MaybeUninit<Aligned<SIZE>>>,
+}
+
+// The future is only touched by whoever holds the `StaticTask`
+unsafe impl<const SIZE: usize> Sync for TaskStorage<SIZE> {}
+
+impl<const SIZE: usize> TaskStorage<SIZE> {
+    pub const fn new() -> Self {
+        TaskStorage {
+            header: Header {
+                spawned: AtomicBool::new(false),
+                woken: AtomicBool::new(false),
+                task: AtomicU64::new(0),
+                woken_at: WokenAt::new(),
+            },
+            future: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Move `future` in and make it a task, `None` if the storage still
+    /// holds a task that hasn't finished
+    pub fn spawn<F>(&'static self, future: F) -> Option<StaticTask>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        const {
+            assert!(
+                mem::size_of::<F>() <= SIZE,
+                "future too large for its storage"
+            );
+            assert!(
+                mem::align_of::<F>() <= STORAGE_ALIGN,
+                "future aligned too strictly"
+            );
+        }
+        if self.header.spawned.swap(true, Ordering::Acquire) {
+            return None;
+        }
+        let slot = self.future.get().cast::<F>();
+        // SAFETY: fits as checked above, and nothing else uses the storage
+        // until the task is dropped and clears `spawned`
+        unsafe { slot.write(future) };
+        let future: *mut (dyn Future<Output = ()> + Send) = slot;
+        let id = TaskId::new();
+        self.header.task.store(id.as_u64(), Ordering::Relaxed);
+        self.header.woken.store(true, Ordering::Release);
+        self.header.woken_at.wake();
+        trace::record(id, EventKind::Spawn);
+        Some(StaticTask {
+            id,
+            name: None,
+            priority: Priority::Normal,
+            header: &self.header,
+            // SAFETY: points into a static
+            future: unsafe { NonNull::new_unchecked(future) },
+        })
+    }
+}
+
+impl<const SIZE: usize> Default for TaskStorage<SIZE> {
+    fn default() -> Self {
+        TaskStorage::new()
+    }
+}
+
+/// A task in a [`TaskStorage`], run by a [`StaticExecutor`]. Dropping it
+/// drops the future and frees the storage.
+pub struct StaticTask {
+    id: TaskId,
+    name: Option<&'static str>,
+    priority: Priority,
+    header: &'static Header,
+    future: NonNull<dyn Future<Output = ()> + Send>,
+}
+
+// The future is `Send` and only reachable through this
+unsafe impl Send for StaticTask {}
+
+impl StaticTask {
+    pub fn with_name(mut self, name: &'static str) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
+    pub fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    fn poll(&mut self, context: &mut Context) -> Poll<()> {
+        // SAFETY: the future stays in its static until dropped below
+        let future = unsafe { Pin::new_unchecked(self.future.as_mut()) };
+        future.poll(context)
+    }
+}
+
+impl fmt::Debug for StaticTask {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StaticTask")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("priority", &self.priority)
+            .finish()
+    }
+}
+
+impl Drop for StaticTask {
+    fn drop(&mut self) {
+        // SAFETY: written by `spawn` and not dropped yet
+        unsafe { self.future.as_ptr().drop_in_place() };
+        trace::record(self.id, EventKind::Complete);
+        self.header.spawned.store(false, Ordering::Release);
+    }
+}
+
+fn waker(header: &'static Header) -> Waker {
+    fn clone(data: *const ()) -> RawWaker {
+        RawWaker::new(data, &VTABLE)
+    }
+    fn wake(data: *const ()) {
+        // SAFETY: made from a `&'static Header` below
+        let header = unsafe { &*data.cast::<Header>() };
+        header.woken.store(true, Ordering::Release);
+        header.woken_at.wake();
+        WOKEN.store(true, Ordering::Release);
+        idle::kick();
+        trace::record(TaskId(header.task.load(Ordering::Relaxed)), EventKind::Wake);
+    }
+    fn drop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake, drop);
+
+    let data = (header as *const Header).cast();
+    // SAFETY: the vtable only reads the header, which lives forever
+    unsafe { Waker::from_raw(RawWaker::new(data, &VTABLE)) }
+}
+
+/// Runs up to `N` [`StaticTask`]s without allocating, apart from metrics
+/// registering themselves the first time. Tasks are polled in the order
+/// they were spawned when woken. Its wakers only set flags, safe to call
+/// from interrupt handlers.
+pub struct StaticExecutor<const N: usize> {
+    tasks: [Option<StaticTask>; N],
+}
+
+impl<const N: usize> StaticExecutor<N> {
+    pub const fn new() -> Self {
+        StaticExecutor {
+            tasks: [const { None }; N],
+        }
+    }
+
+    /// Hands the task back if all `N` places are taken
+    pub fn spawn(&mut self, task: StaticTask) -> Result<(), StaticTask> {
+        match self.tasks.iter_mut().find(|place| place.is_none()) {
+            Some(place) => {
+                *place = Some(task);
+                WOKEN.store(true, Ordering::Release);
+                Ok(())
+            }
+            None => Err(task),
+        }
+    }
+
+    /// Tasks spawned and not finished
+    pub fn len(&self) -> usize {
+        self.tasks.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Poll every woken task once
+    fn run_woken(&mut self) {
+        WOKEN.store(false, Ordering::Release);
+        for place in &mut self.tasks {
+            let Some(task) = place else {
+                continue;
+            };
+            if !task.header.woken.swap(false, Ordering::Acquire) {
+                continue;
+            }
+            let waker = waker(task.header);
+            let mut context = Context::from_waker(&waker);
+            let _enter = context::enter(task.id, task.priority);
+            let _memory = memory::enter(task.id);
+            task.header.woken_at.observe();
+            watchdog::poll_started(task.id);
+            profiler::poll_started(task.id);
+            trace::record(task.id, EventKind::Poll);
+            let poll = task.poll(&mut context);
+            profiler::poll_ended();
+            watchdog::poll_ended();
+            if poll.is_pending() {
+                trace::record(task.id, EventKind::Pending);
+            }
+            if poll.is_ready() {
+                *place = None;
+            }
+        }
+    }
+
+    /// Run until every task has finished
+    pub fn run(&mut self) -> Result<(), RunError> {
+        let _run = super::enter()?;
+        while !self.is_empty() && !super::stopping() {
+            super::wake_expired_timers();
+            self.run_woken();
+            // Until the next interrupt, the timer's at the latest
+            idle::wait(|| WOKEN.load(Ordering::Acquire) || super::stopping());
+        }
+        Ok(())
+    }
+}
+
+impl<const N: usize> Default for StaticExecutor<N> {
+    fn default() -> Self {
+        StaticExecutor::new()
+    }
+}
+
+/// Put the future of `function` called with the arguments in storage of
+/// its own, reserved at compile time. Gives an `Option<`[`StaticTask`]`>`,
+/// `None` if the last task spawned here hasn't finished.
+#[macro_export]
+macro_rules! __static_task {
+    ($function:path $(, $arg:expr)* $(,)?) => {{
+        static STORAGE: $crate::executor::TaskStorage<
+            { $crate::executor::future_size(&$function) },
+        > = $crate::executor::TaskStorage::new();
+        STORAGE.spawn($function($($arg),*))
+    }};
+}
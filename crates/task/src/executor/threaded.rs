@@ -0,0 +1,287 @@
+//! Tasks polled by several OS threads at once, on the std build.
+//!
+//! A [`ThreadPool`] runs its workers on threads of their own, sharing one
+//! injector queue. Spawned and woken tasks go in and whichever worker is
+//! free takes the next, so a task may be polled on another thread each
+//! time. Hence only [`Task`]s, whose futures are `Send`: application code
+//! can be tried under real parallelism here before it runs on several
+//! cores.
+//!
+//! ```ignore
+//! let mut pool = ThreadPool::new(4);
+//! pool.spawn(Task::new(net::run(loopback)).with_name("net"));
+//! pool.spawn(Task::new(rng::run_reseeder()).with_name("rng"));
+//! pool.run()?;
+//! ```
+//!
+//! [`executor::spawn`](super::spawn) from a task on a worker puts the new
+//! task in the injector too. [`spawn_local`](super::spawn_local) keeps it on
+//! that worker, which polls it between the shared ones like an
+//! [`Executor`] would.
+//!
+//! The injector is FIFO, priorities only order a worker's local tasks. The
+//! watchdog and profiler follow one poll at a time, with several workers
+//! the one started last.
+
+use alloc::{
+    collections::BTreeMap,
+    format,
+    sync::{Arc, Weak},
+    task::Wake,
+    vec::Vec,
+};
+use core::{
+    cell::RefCell,
+    future::Future,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Waker},
+};
+use std::thread::{self, Thread};
+
+use crossbeam_queue::SegQueue;
+
+use super::{
+    Executor, RunError, TaskBuilder, TaskStatus, WAKES, WakeStats, count_poll, finished,
+    out_of_memory, register, take_aborted, wake_stats,
+};
+use crate::{
+    Task, TaskId, context, idle, memory,
+    priority::Priority,
+    profiler,
+    trace::{self, EventKind},
+    watchdog,
+};
+
+std::thread_local! {
+    // The pool whose worker this thread is
+    static WORKER: RefCell<Option<Arc<Shared>>> = const { RefCell::new(None) };
+}
+
+/// Runs [`Task`]s on a number of worker threads, until they've all finished
+pub struct ThreadPool {
+    workers: usize,
+    shared: Arc<Shared>,
+}
+
+impl ThreadPool {
+    /// A pool of `workers` threads, started by [`run`](ThreadPool::run)
+    pub fn new(workers: usize) -> ThreadPool {
+        assert!(workers > 0, "a thread pool needs a worker");
+        ThreadPool {
+            workers,
+            shared: Arc::new(Shared {
+                injector: SegQueue::new(),
+                tasks: spin::Mutex::new(BTreeMap::new()),
+                sleepers: spin::Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    pub fn spawn(&mut self, task: Task) -> TaskId {
+        let id = task.id();
+        self.shared.insert(task);
+        id
+    }
+
+    /// Spawn `future` once [`TaskBuilder`] has set its options
+    pub fn build_task(
+        &mut self,
+        future: impl Future<Output = ()> + Send + 'static,
+    ) -> TaskBuilder<'_, Self> {
+        TaskBuilder::new(self, future)
+    }
+
+    /// Start the workers and wait for them, they stop once every task has
+    /// finished or [`stop`](super::stop) is called. A panicking task takes its worker down, and the panic
+    /// comes out here once the rest are done.
+    pub fn run(&mut self) -> Result<(), RunError> {
+        let _run = super::enter()?;
+        let shared = &self.shared;
+        thread::scope(|scope| {
+            for worker in 0..self.workers {
+                thread::Builder::new()
+                    .name(format!("worker-{}", worker))
+                    .spawn_scoped(scope, move || work(shared))
+                    .expect("spawning a worker thread");
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Put `task` in the injector of the pool the calling thread works for,
+/// off the pool `otherwise` spawns it
+pub(super) fn spawn(task: Task, otherwise: impl FnOnce(Task) -> TaskId) -> TaskId {
+    WORKER.with(|worker| match &*worker.borrow() {
+        Some(shared) => {
+            let id = task.id();
+            shared.insert(task);
+            id
+        }
+        None => otherwise(task),
+    })
+}
+
+// What the workers share
+struct Shared {
+    injector: SegQueue<Arc<Scheduled>>,
+    // Every task spawned on the pool and not finished yet
+    tasks: spin::Mutex<BTreeMap<TaskId, Arc<Scheduled>>>,
+    // Workers parked for lack of work
+    sleepers: spin::Mutex<Vec<Thread>>,
+}
+
+impl Shared {
+    fn insert(self: &Arc<Self>, task: Task) {
+        register(&task.options);
+        let id = task.id();
+        let scheduled = Arc::new(Scheduled {
+            id,
+            priority: task.priority(),
+            task: spin::Mutex::new(Some(task)),
+            queued: AtomicBool::new(false),
+            stats: wake_stats(id),
+            shared: Arc::downgrade(self),
+        });
+        self.tasks.lock().insert(id, scheduled.clone());
+        self.schedule(scheduled);
+    }
+
+    /// Queue `task` for a worker unless it's queued already
+    fn schedule(&self, task: Arc<Scheduled>) {
+        if task.queued.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        self.injector.push(task);
+        if let Some(sleeper) = self.sleepers.lock().pop() {
+            sleeper.unpark();
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.tasks.lock().is_empty()
+    }
+
+    /// Poll `task` if no other worker is at it
+    fn poll(&self, task: Arc<Scheduled>) {
+        let Some(mut slot) = task.task.try_lock() else {
+            // Woken while another worker polls it, which it'll be done with
+            // by the time this comes round again
+            self.injector.push(task);
+            return;
+        };
+        // Cleared first, so a wake during the poll queues it again
+        task.queued.store(false, Ordering::Release);
+        let Some(future) = slot.as_mut() else {
+            return;
+        };
+        let id = task.id;
+        let waker = Waker::from(task.clone());
+        let mut context = Context::from_waker(&waker);
+        let _enter = context::enter(id, task.priority);
+        let _memory = memory::enter(id);
+        count_poll(id);
+        watchdog::poll_started(id);
+        profiler::poll_started(id);
+        trace::record(id, EventKind::Poll);
+        let poll = future.future.poll(&mut context);
+        profiler::poll_ended();
+        watchdog::poll_ended();
+        if poll.is_pending() {
+            trace::record(id, EventKind::Pending);
+        }
+        let ended = if poll.is_ready() {
+            Some(TaskStatus::Completed)
+        } else {
+            out_of_memory(id).then_some(TaskStatus::Cancelled)
+        };
+        if let Some(status) = ended {
+            *slot = None;
+            drop(slot);
+            self.remove(id, status);
+        }
+    }
+
+    /// Drop the future of the pool's task `id` if it's still there
+    fn abort(&self, id: TaskId) {
+        let task = self.tasks.lock().get(&id).cloned();
+        if let Some(task) = task {
+            // Waits out a poll on another worker
+            task.task.lock().take();
+            self.remove(id, TaskStatus::Cancelled);
+        }
+    }
+
+    fn remove(&self, id: TaskId, status: TaskStatus) {
+        if self.tasks.lock().remove(&id).is_some() {
+            finished(id, status);
+        }
+    }
+
+    /// Sleep until a task is queued or `busy`, for at most [`idle::NAP`]
+    /// so the timers still fire
+    fn park(&self, busy: impl Fn() -> bool) {
+        let current = thread::current();
+        self.sleepers.lock().push(current.clone());
+        // Registered first, so a task queued after the check still unparks
+        if self.injector.is_empty() && !busy() {
+            thread::park_timeout(idle::NAP);
+        }
+        self.sleepers
+            .lock()
+            .retain(|sleeper| sleeper.id() != current.id());
+    }
+}
+
+// A task of the pool, queued by its own waker
+struct Scheduled {
+    id: TaskId,
+    priority: Priority,
+    // `None` once finished or aborted
+    task: spin::Mutex<Option<Task>>,
+    // In the injector, so wakes don't put it in twice
+    queued: AtomicBool,
+    stats: Arc<WakeStats>,
+    shared: Weak<Shared>,
+}
+
+impl Wake for Scheduled {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        WAKES.inc();
+        self.stats.wakes.fetch_add(1, Ordering::Relaxed);
+        self.stats.woken_at.wake();
+        trace::record(self.id, EventKind::Wake);
+        if let Some(shared) = self.shared.upgrade() {
+            shared.schedule(self.clone());
+        }
+    }
+}
+
+fn work(shared: &Arc<Shared>) {
+    // A fresh thread, nothing runs here yet. Not `enter`, which would undo
+    // a stop made before this worker got going
+    let _run = context::run();
+    WORKER.with(|worker| *worker.borrow_mut() = Some(shared.clone()));
+    // Tasks `spawn_local`ed here, which can't leave the thread
+    let mut local = Executor::new();
+    while !(super::stopping() || shared.is_done() && local.tasks.is_empty()) {
+        super::wake_expired_timers();
+        local.run_ready_tasks();
+        for id in take_aborted(|id| shared.tasks.lock().contains_key(&id)) {
+            shared.abort(id);
+        }
+        match shared.injector.pop() {
+            Some(task) => shared.poll(task),
+            None => shared.park(|| local.has_woken()),
+        }
+    }
+    WORKER.with(|worker| *worker.borrow_mut() = None);
+}
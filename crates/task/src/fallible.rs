@@ -0,0 +1,175 @@
+//!
+//! Tasks returning `Result`
+//!
+//! [`Task::fallible`] spawns a future returning `Result<(), E>` as it is,
+//! [`LocalTask::fallible`] one that isn't `Send`.
+//! An `Err` goes to the error handler set with [`set_handler`], which
+//! logs it by default and says what happens next:
+//!
+//! ```ignore
+//! fallible::set_handler(fallible::restart);
+//! executor.spawn_local(LocalTask::restartable(|| shell::serve_telnet(23)).with_name("telnetd"));
+//! ```
+//!
+//! Only a [`Task::restartable`] or [`LocalTask::restartable`] can start over, it makes a fresh future
+//! each time. A one-off task that's told to restart just ends.
+//!
+
+use alloc::string::{String, ToString};
+use core::{fmt, future::Future};
+
+use crate::{LocalTask, Task, TaskId, context, executor, future, log, metrics::Counter};
+
+static ERRORS: Counter = Counter::new(
+    "executor_task_errors_total",
+    "Tasks that finished with an error",
+);
+static RESTARTS: Counter = Counter::new(
+    "executor_task_error_restarts_total",
+    "Tasks started over after an error",
+);
+
+/// A task that finished with an error, for the handler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskError {
+    pub task: TaskId,
+    pub name: Option<String>,
+    /// The error, formatted
+    pub error: String,
+    /// Times the task was restarted before this error
+    pub restarts: u32,
+}
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "task {}", self.task)?;
+        if let Some(name) = &self.name {
+            write!(f, " ({})", name)?;
+        }
+        write!(f, " failed: {}", self.error)
+    }
+}
+
+/// What the handler wants done about an error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Let the task end
+    End,
+    /// Start a restartable task over, end any other
+    Restart,
+    /// End the task and abort every other one
+    AbortAll,
+}
+
+static HANDLER: spin::Mutex<fn(&TaskError) -> Action> = spin::Mutex::new(log_error);
+
+/// Have `handler` decide what happens to tasks that fail
+pub fn set_handler(handler: fn(&TaskError) -> Action) {
+    *HANDLER.lock() = handler;
+}
+
+/// Log the error and let the task end, the default handler
+pub fn log_error(error: &TaskError) -> Action {
+    log::error!("{}", error);
+    Action::End
+}
+
+/// Log the error and start the task over
+pub fn restart(error: &TaskError) -> Action {
+    log::error!("{}, restarting it", error);
+    Action::Restart
+}
+
+/// Log the error and abort every task
+pub fn abort_all(error: &TaskError) -> Action {
+    log::error!("{}, aborting all tasks", error);
+    Action::AbortAll
+}
+
+/// Run `future`, passing an `Err` to the handler. For a task built some
+/// other way than [`Task::fallible`], with a [`TaskBuilder`](crate::executor::TaskBuilder)
+/// say.
+pub async fn handle_errors<E: fmt::Display>(future: impl Future<Output = Result<(), E>>) {
+    if let Err(error) = future.await {
+        handle(&error, 0);
+    }
+}
+
+impl Task {
+    /// A task whose `Err` goes to the handler set with [`set_handler`]
+    pub fn fallible<E: fmt::Display + Send + 'static>(
+        future: impl Future<Output = Result<(), E>> + Send + 'static,
+    ) -> Task {
+        Task::new(handle_errors(future))
+    }
+
+    /// A task running the future `factory` makes, and another one whenever
+    /// the handler says [`Action::Restart`] to its `Err`
+    pub fn restartable<E, F>(factory: impl FnMut() -> F + Send + 'static) -> Task
+    where
+        E: fmt::Display + Send + 'static,
+        F: Future<Output = Result<(), E>> + Send + 'static,
+    {
+        Task::new(restarting(factory))
+    }
+}
+
+impl LocalTask {
+    /// [`Task::fallible`] for a future that isn't `Send`
+    pub fn fallible<E: fmt::Display + 'static>(
+        future: impl Future<Output = Result<(), E>> + 'static,
+    ) -> LocalTask {
+        LocalTask::new(handle_errors(future))
+    }
+
+    /// [`Task::restartable`] for futures that aren't `Send`
+    pub fn restartable<E, F>(factory: impl FnMut() -> F + 'static) -> LocalTask
+    where
+        E: fmt::Display + 'static,
+        F: Future<Output = Result<(), E>> + 'static,
+    {
+        LocalTask::new(restarting(factory))
+    }
+}
+
+async fn restarting<E, F>(mut factory: impl FnMut() -> F)
+where
+    E: fmt::Display,
+    F: Future<Output = Result<(), E>>,
+{
+    let mut restarts = 0;
+    while let Err(error) = factory().await {
+        if handle(&error, restarts) != Action::Restart {
+            break;
+        }
+        restarts += 1;
+        RESTARTS.inc();
+        // The next try may fail right away too, don't hog the executor
+        future::yield_now().await;
+    }
+}
+
+fn handle(error: &dyn fmt::Display, restarts: u32) -> Action {
+    ERRORS.inc();
+    let task = context::current().map_or(TaskId(0), |(id, _)| id);
+    let error = TaskError {
+        task,
+        name: executor::tasks()
+            .into_iter()
+            .find(|info| info.id == task)
+            .and_then(|info| info.name),
+        error: error.to_string(),
+        restarts,
+    };
+    // Copied out, the handler may set another
+    let handler = *HANDLER.lock();
+    let action = handler(&error);
+    if action == Action::AbortAll {
+        for info in executor::tasks() {
+            if info.id != task {
+                executor::abort(info.id);
+            }
+        }
+    }
+    action
+}
@@ -0,0 +1,344 @@
+//!
+//! Virtual filesystem
+//!
+//! Every [`FileSystem`] is mounted at some absolute path, and a path is
+//! served by the filesystem with the longest mount point in front of it.
+//! The functions here take absolute paths and find the filesystem, so
+//! callers don't care whether a file lives in memory, on a disk or is a
+//! device:
+//!
+//! ```ignore
+//! fs::mount("/", ramfs)?;
+//! fs::write("/motd", b"hello\n").await?;
+//! let text = fs::read_to_string("/motd").await?;
+//! for entry in fs::read_dir("/").await? {
+//!     println!("{}", entry.name);
+//! }
+//! ```
+//!
+//! A filesystem only sees paths relative to its own root with `.` and `..`
+//! already resolved, `""` being the root itself.
+//!
+
+mod fat;
+pub mod path;
+mod ramfs;
+
+use std::{fmt, future::Future, pin::Pin, sync::Arc};
+
+use crate::{block, io};
+
+pub use fat::FatFs;
+pub use ramfs::RamFs;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    NotFound,
+    AlreadyExists,
+    NotADirectory,
+    IsADirectory,
+    /// Only empty directories can be removed
+    DirectoryNotEmpty,
+    /// Not absolute, or a name a filesystem can't store
+    InvalidPath,
+    ReadOnly,
+    /// Writing a file opened for reading only
+    PermissionDenied,
+    /// Seeking before the start of a file
+    InvalidSeek,
+    /// The filesystem doesn't do that
+    Unsupported,
+    /// On-disk structures that make no sense
+    Corrupt,
+    Io(io::Error),
+    Device(block::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NotFound => f.write_str("no such file or directory"),
+            Error::AlreadyExists => f.write_str("already exists"),
+            Error::NotADirectory => f.write_str("not a directory"),
+            Error::IsADirectory => f.write_str("is a directory"),
+            Error::DirectoryNotEmpty => f.write_str("directory not empty"),
+            Error::InvalidPath => f.write_str("invalid path"),
+            Error::ReadOnly => f.write_str("read-only filesystem"),
+            Error::PermissionDenied => f.write_str("permission denied"),
+            Error::InvalidSeek => f.write_str("invalid seek"),
+            Error::Unsupported => f.write_str("operation not supported"),
+            Error::Corrupt => f.write_str("filesystem is corrupt"),
+            Error::Io(err) => err.fmt(f),
+            Error::Device(err) => err.fmt(f),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<block::Error> for Error {
+    fn from(err: block::Error) -> Self {
+        Error::Device(err)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Future returned by the [`FileSystem`], [`File`] and [`Dir`] methods
+pub type FsFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    File,
+    Directory,
+    /// Reads and writes go to a driver
+    Device,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Metadata {
+    pub file_type: FileType,
+    /// Bytes in a file, `0` for anything else
+    pub len: u64,
+}
+
+impl Metadata {
+    pub fn is_dir(&self) -> bool {
+        self.file_type == FileType::Directory
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub file_type: FileType,
+}
+
+/// How [`FileSystem::open`] opens a file, it's always readable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct OpenOptions {
+    pub write: bool,
+    /// Make the file if it doesn't exist
+    pub create: bool,
+    /// Empty the file first
+    pub truncate: bool,
+    /// Every write goes to the end
+    pub append: bool,
+}
+
+impl OpenOptions {
+    pub const READ: OpenOptions = OpenOptions {
+        write: false,
+        create: false,
+        truncate: false,
+        append: false,
+    };
+    /// A new or emptied file
+    pub const CREATE: OpenOptions = OpenOptions {
+        write: true,
+        create: true,
+        truncate: true,
+        append: false,
+    };
+    pub const APPEND: OpenOptions = OpenOptions {
+        write: true,
+        create: true,
+        truncate: false,
+        append: true,
+    };
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(u64),
+    End(i64),
+    Current(i64),
+}
+
+/// A tree of files and directories
+pub trait FileSystem: Send + Sync {
+    /// Shown by [`mounts`]
+    fn name(&self) -> &str;
+
+    fn open<'a>(&'a self, path: &'a str, options: OpenOptions) -> FsFuture<'a, Box<dyn File>>;
+
+    fn open_dir<'a>(&'a self, path: &'a str) -> FsFuture<'a, Box<dyn Dir>>;
+
+    fn metadata<'a>(&'a self, path: &'a str) -> FsFuture<'a, Metadata>;
+
+    fn create_dir<'a>(&'a self, path: &'a str) -> FsFuture<'a, ()>;
+
+    /// Remove a file or an empty directory
+    fn remove<'a>(&'a self, path: &'a str) -> FsFuture<'a, ()>;
+}
+
+/// An open file with a position that reads and writes move along
+pub trait File: Send {
+    /// Read from the position into `buf`, `0` at the end of the file
+    fn read<'a>(&'a mut self, buf: &'a mut [u8]) -> FsFuture<'a, usize>;
+
+    /// Write `bytes` at the position, returns how many were taken
+    fn write<'a>(&'a mut self, bytes: &'a [u8]) -> FsFuture<'a, usize>;
+
+    /// Move the position, returns where it ends up
+    fn seek(&mut self, pos: SeekFrom) -> FsFuture<'_, u64>;
+
+    fn metadata(&self) -> FsFuture<'_, Metadata>;
+
+    /// Make sure written bytes reached the filesystem's storage
+    fn flush(&mut self) -> FsFuture<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// The entries of a directory, one at a time
+pub trait Dir: Send {
+    /// The next entry, `None` after the last one. `.` and `..` aren't
+    /// entries.
+    fn next_entry(&mut self) -> FsFuture<'_, Option<DirEntry>>;
+}
+
+struct Mount {
+    path: String,
+    fs: Arc<dyn FileSystem>,
+}
+
+static MOUNTS: spin::Mutex<Vec<Mount>> = spin::Mutex::new(Vec::new());
+
+/// Serve `path` and everything under it from `fs`. The path doesn't need
+/// to exist, a mount hides what was there.
+pub fn mount(path: &str, fs: impl FileSystem + 'static) -> Result<()> {
+    let path = path::normalize(path)?;
+    let mut mounts = MOUNTS.lock();
+    if mounts.iter().any(|mount| mount.path == path) {
+        return Err(Error::AlreadyExists);
+    }
+    mounts.push(Mount {
+        path,
+        fs: Arc::new(fs),
+    });
+    Ok(())
+}
+
+/// Files still open keep the filesystem alive until they're dropped
+pub fn unmount(path: &str) -> Result<()> {
+    let path = path::normalize(path)?;
+    let mut mounts = MOUNTS.lock();
+    let index = mounts
+        .iter()
+        .position(|mount| mount.path == path)
+        .ok_or(Error::NotFound)?;
+    mounts.remove(index);
+    Ok(())
+}
+
+/// Mount points and the names of their filesystems, sorted by path
+pub fn mounts() -> Vec<(String, String)> {
+    let mut mounts: Vec<_> = MOUNTS
+        .lock()
+        .iter()
+        .map(|mount| (mount.path.clone(), mount.fs.name().to_string()))
+        .collect();
+    mounts.sort();
+    mounts
+}
+
+/// The filesystem serving `path` and the path inside it
+fn resolve(path: &str) -> Result<(Arc<dyn FileSystem>, String)> {
+    let path = path::normalize(path)?;
+    let mounts = MOUNTS.lock();
+    let mount = mounts
+        .iter()
+        .filter(|mount| match path.strip_prefix(mount.path.as_str()) {
+            Some(rest) => mount.path == "/" || rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        })
+        .max_by_key(|mount| mount.path.len())
+        .ok_or(Error::NotFound)?;
+    let inner = path[mount.path.len()..].trim_start_matches('/').to_string();
+    Ok((mount.fs.clone(), inner))
+}
+
+/// Open a file for reading
+pub async fn open(path: &str) -> Result<Box<dyn File>> {
+    open_with(path, OpenOptions::READ).await
+}
+
+/// Open a new or emptied file for writing
+pub async fn create(path: &str) -> Result<Box<dyn File>> {
+    open_with(path, OpenOptions::CREATE).await
+}
+
+pub async fn open_with(path: &str, options: OpenOptions) -> Result<Box<dyn File>> {
+    let (fs, path) = resolve(path)?;
+    fs.open(&path, options).await
+}
+
+pub async fn open_dir(path: &str) -> Result<Box<dyn Dir>> {
+    let (fs, path) = resolve(path)?;
+    fs.open_dir(&path).await
+}
+
+pub async fn metadata(path: &str) -> Result<Metadata> {
+    let (fs, path) = resolve(path)?;
+    fs.metadata(&path).await
+}
+
+pub async fn create_dir(path: &str) -> Result<()> {
+    let (fs, path) = resolve(path)?;
+    fs.create_dir(&path).await
+}
+
+/// Remove a file or an empty directory
+pub async fn remove(path: &str) -> Result<()> {
+    let (fs, path) = resolve(path)?;
+    fs.remove(&path).await
+}
+
+/// Every entry of a directory
+pub async fn read_dir(path: &str) -> Result<Vec<DirEntry>> {
+    let mut dir = open_dir(path).await?;
+    let mut entries = Vec::new();
+    while let Some(entry) = dir.next_entry().await? {
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// A whole file
+pub async fn read(path: &str) -> Result<Vec<u8>> {
+    let mut file = open(path).await?;
+    let mut contents = Vec::new();
+    let mut buf = [0; io::DEFAULT_BUF_SIZE];
+    loop {
+        let count = file.read(&mut buf).await?;
+        if count == 0 {
+            return Ok(contents);
+        }
+        contents.extend_from_slice(&buf[..count]);
+    }
+}
+
+/// A whole file, [`io::Error::InvalidData`] if it isn't UTF-8
+pub async fn read_to_string(path: &str) -> Result<String> {
+    let contents = read(path).await?;
+    String::from_utf8(contents).map_err(|_| Error::Io(io::Error::InvalidData))
+}
+
+/// Replace a file's contents, making it if needed
+pub async fn write(path: &str, contents: &[u8]) -> Result<()> {
+    let mut file = create(path).await?;
+    let mut rest = contents;
+    while !rest.is_empty() {
+        let count = file.write(rest).await?;
+        if count == 0 {
+            return Err(Error::Io(io::Error::WriteZero));
+        }
+        rest = &rest[count..];
+    }
+    file.flush().await
+}
@@ -0,0 +1,499 @@
+use std::sync::Arc;
+
+use super::{
+    Dir, DirEntry, Error, File, FileSystem, FileType, FsFuture, Metadata, OpenOptions, Result,
+    SeekFrom, path,
+};
+use crate::block::BlockDevice;
+
+const BOOT_SECTOR_SIZE: usize = 512;
+const DIR_ENTRY_SIZE: usize = 32;
+
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+/// All of read-only, hidden, system and volume id marks a long name part
+const ATTR_LONG_NAME: u8 = 0x0F;
+
+/// FAT entries at or above this end a chain
+const END_OF_CHAIN: u32 = 0x0FFF_FFF8;
+const BAD_CLUSTER: u32 = 0x0FFF_FFF7;
+
+/// Where the UTF-16 units of a long name part sit in its entry
+const LONG_NAME_OFFSETS: [usize; 13] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+/// A FAT32 volume, read-only.
+///
+/// Long file names are read, names are matched ignoring ASCII case like
+/// FAT does.
+pub struct FatFs {
+    volume: Arc<Volume>,
+}
+
+impl FatFs {
+    /// Check the boot sector at the start of `device` and read the volume's
+    /// layout from it. FAT12 and FAT16 are [`Error::Unsupported`].
+    ///
+    /// Directories are read again for every lookup, give it a
+    /// [`BlockCache`](crate::block::BlockCache) rather than a bare disk.
+    pub async fn open(device: Arc<dyn BlockDevice>) -> Result<FatFs> {
+        let mut boot = [0; BOOT_SECTOR_SIZE];
+        read_at(&*device, 0, &mut boot).await?;
+        if boot[510..512] != [0x55, 0xAA] {
+            return Err(Error::Corrupt);
+        }
+        let u16_at = |at: usize| u16::from_le_bytes([boot[at], boot[at + 1]]) as u64;
+        let u32_at =
+            |at: usize| u32::from_le_bytes([boot[at], boot[at + 1], boot[at + 2], boot[at + 3]]);
+
+        let sector_size = u16_at(11);
+        let sectors_per_cluster = boot[13] as u64;
+        let reserved_sectors = u16_at(14);
+        let fat_count = boot[16] as u64;
+        if !(512..=4096).contains(&sector_size)
+            || !sector_size.is_power_of_two()
+            || !sectors_per_cluster.is_power_of_two()
+            || fat_count == 0
+        {
+            return Err(Error::Corrupt);
+        }
+        // Only FAT12 and FAT16 have a root directory of their own and a
+        // 16 bit FAT size
+        let fat_size = u32_at(36) as u64;
+        if u16_at(17) != 0 || u16_at(22) != 0 || fat_size == 0 {
+            return Err(Error::Unsupported);
+        }
+        let total_sectors = match u16_at(19) {
+            0 => u32_at(32) as u64,
+            small => small,
+        };
+        let data_sector = reserved_sectors + fat_count * fat_size;
+        let data_sectors = total_sectors
+            .checked_sub(data_sector)
+            .ok_or(Error::Corrupt)?;
+
+        let volume = Volume {
+            device,
+            sector_size,
+            fat_start: reserved_sectors * sector_size,
+            data_start: data_sector * sector_size,
+            cluster_size: sectors_per_cluster * sector_size,
+            cluster_count: (data_sectors / sectors_per_cluster) as u32,
+            root_cluster: u32_at(44),
+            fat_cache: spin::Mutex::new(None),
+        };
+        volume.check_cluster(volume.root_cluster)?;
+        Ok(FatFs {
+            volume: Arc::new(volume),
+        })
+    }
+}
+
+struct Volume {
+    device: Arc<dyn BlockDevice>,
+    sector_size: u64,
+    /// Byte offsets of the first FAT and of cluster 2
+    fat_start: u64,
+    data_start: u64,
+    cluster_size: u64,
+    cluster_count: u32,
+    root_cluster: u32,
+    // The FAT sector looked at last, chains mostly run through one sector
+    fat_cache: spin::Mutex<Option<(u64, Vec<u8>)>>,
+}
+
+impl Volume {
+    /// Data clusters are numbered from 2
+    fn check_cluster(&self, cluster: u32) -> Result<()> {
+        if (2..self.cluster_count + 2).contains(&cluster) {
+            Ok(())
+        } else {
+            Err(Error::Corrupt)
+        }
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        self.data_start + (cluster as u64 - 2) * self.cluster_size
+    }
+
+    /// The cluster after `cluster` in its chain, `None` at the end
+    async fn next_cluster(&self, cluster: u32) -> Result<Option<u32>> {
+        let offset = self.fat_start + cluster as u64 * 4;
+        let sector = offset / self.sector_size;
+        let at = (offset % self.sector_size) as usize;
+        let entry_in = |bytes: &[u8]| {
+            u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+        };
+
+        let cached = match &*self.fat_cache.lock() {
+            Some((cached, bytes)) if *cached == sector => Some(entry_in(bytes)),
+            _ => None,
+        };
+        let entry = match cached {
+            Some(entry) => entry,
+            None => {
+                let mut bytes = vec![0; self.sector_size as usize];
+                read_at(&*self.device, sector * self.sector_size, &mut bytes).await?;
+                let entry = entry_in(&bytes);
+                *self.fat_cache.lock() = Some((sector, bytes));
+                entry
+            }
+        };
+        // The top four bits are reserved
+        match entry & 0x0FFF_FFFF {
+            next if next >= END_OF_CHAIN => Ok(None),
+            BAD_CLUSTER => Err(Error::Corrupt),
+            next => {
+                self.check_cluster(next)?;
+                Ok(Some(next))
+            }
+        }
+    }
+
+    /// Every cluster of a chain, one after the other
+    async fn read_chain(&self, first: u32) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut cluster = Some(first);
+        while let Some(current) = cluster {
+            self.check_cluster(current)?;
+            // A chain longer than the volume has a loop
+            if data.len() as u64 >= self.cluster_count as u64 * self.cluster_size {
+                return Err(Error::Corrupt);
+            }
+            let start = data.len();
+            data.resize(start + self.cluster_size as usize, 0);
+            read_at(
+                &*self.device,
+                self.cluster_offset(current),
+                &mut data[start..],
+            )
+            .await?;
+            cluster = self.next_cluster(current).await?;
+        }
+        Ok(data)
+    }
+
+    async fn read_dir(&self, cluster: u32) -> Result<Vec<Entry>> {
+        let data = self.read_chain(cluster).await?;
+        Ok(parse_dir(&data))
+    }
+
+    async fn lookup(&self, path: &str) -> Result<Entry> {
+        let mut entry = Entry {
+            name: String::new(),
+            directory: true,
+            cluster: self.root_cluster,
+            size: 0,
+        };
+        for name in path::components(path) {
+            if !entry.directory {
+                return Err(Error::NotADirectory);
+            }
+            entry = self
+                .read_dir(entry.cluster)
+                .await?
+                .into_iter()
+                .find(|entry| entry.name.eq_ignore_ascii_case(name))
+                .ok_or(Error::NotFound)?;
+        }
+        Ok(entry)
+    }
+}
+
+/// Read `buf.len()` bytes from `offset`, which needn't line up with blocks
+async fn read_at(device: &dyn BlockDevice, offset: u64, buf: &mut [u8]) -> Result<()> {
+    let block_size = device.block_size() as u64;
+    let first = offset / block_size;
+    let end = (offset + buf.len() as u64).div_ceil(block_size);
+    let mut blocks = vec![0; ((end - first) * block_size) as usize];
+    device.read_blocks(first, &mut blocks).await?;
+    let skip = (offset - first * block_size) as usize;
+    buf.copy_from_slice(&blocks[skip..skip + buf.len()]);
+    Ok(())
+}
+
+struct Entry {
+    name: String,
+    directory: bool,
+    cluster: u32,
+    size: u32,
+}
+
+impl Entry {
+    fn file_type(&self) -> FileType {
+        if self.directory {
+            FileType::Directory
+        } else {
+            FileType::File
+        }
+    }
+}
+
+/// The parts of a long name seen so far, they come last part first
+struct LongName {
+    units: Vec<u16>,
+    checksum: u8,
+    // Sequence number of the part expected next, `0` once complete
+    next: u8,
+}
+
+fn parse_dir(data: &[u8]) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut long_name: Option<LongName> = None;
+    for raw in data.chunks_exact(DIR_ENTRY_SIZE) {
+        match raw[0] {
+            // Nothing used after this one
+            0x00 => break,
+            // Deleted
+            0xE5 => {
+                long_name = None;
+                continue;
+            }
+            _ => {}
+        }
+        let attributes = raw[11];
+        if attributes & 0x3F == ATTR_LONG_NAME {
+            let sequence = raw[0] & 0x1F;
+            if raw[0] & 0x40 != 0 {
+                long_name = Some(LongName {
+                    units: Vec::new(),
+                    checksum: raw[13],
+                    next: sequence,
+                });
+            }
+            // A part out of order makes the whole name unusable
+            long_name = long_name
+                .take()
+                .filter(|long| sequence > 0 && long.next == sequence && long.checksum == raw[13])
+                .map(|mut long| {
+                    let mut units: Vec<u16> = LONG_NAME_OFFSETS
+                        .iter()
+                        .map(|&at| u16::from_le_bytes([raw[at], raw[at + 1]]))
+                        .collect();
+                    units.append(&mut long.units);
+                    long.units = units;
+                    long.next -= 1;
+                    long
+                });
+            continue;
+        }
+        if attributes & ATTR_VOLUME_ID != 0 {
+            long_name = None;
+            continue;
+        }
+
+        let short_name = &raw[..11];
+        let name = long_name
+            .take()
+            .filter(|long| long.next == 0 && long.checksum == checksum(short_name))
+            .and_then(|long| decode_long_name(&long.units))
+            .unwrap_or_else(|| decode_short_name(short_name, raw[12]));
+        if name == "." || name == ".." {
+            continue;
+        }
+        let cluster_high = u16::from_le_bytes([raw[20], raw[21]]) as u32;
+        let cluster_low = u16::from_le_bytes([raw[26], raw[27]]) as u32;
+        entries.push(Entry {
+            name,
+            directory: attributes & ATTR_DIRECTORY != 0,
+            cluster: cluster_high << 16 | cluster_low,
+            size: u32::from_le_bytes([raw[28], raw[29], raw[30], raw[31]]),
+        });
+    }
+    entries
+}
+
+/// Ties long name parts to their short entry
+fn checksum(short_name: &[u8]) -> u8 {
+    short_name
+        .iter()
+        .fold(0u8, |sum, &byte| sum.rotate_right(1).wrapping_add(byte))
+}
+
+/// Long names end in a NUL, the rest of the last part is padding
+fn decode_long_name(units: &[u16]) -> Option<String> {
+    let end = units
+        .iter()
+        .position(|&unit| unit == 0x0000 || unit == 0xFFFF)
+        .unwrap_or(units.len());
+    String::from_utf16(&units[..end]).ok()
+}
+
+/// `README  TXT` is `README.TXT`. Flag bits in byte 12 ask for a lowercase
+/// base or extension.
+fn decode_short_name(short_name: &[u8], flags: u8) -> String {
+    let part = |bytes: &[u8], lowercase: bool| -> String {
+        let text: String = bytes.iter().map(|&byte| byte as char).collect();
+        let text = text.trim_end_matches(' ');
+        if lowercase {
+            text.to_ascii_lowercase()
+        } else {
+            text.to_string()
+        }
+    };
+    let mut base_bytes = [0; 8];
+    base_bytes.copy_from_slice(&short_name[..8]);
+    // 0xE5 is a real first byte stored as 0x05, 0xE5 itself means deleted
+    if base_bytes[0] == 0x05 {
+        base_bytes[0] = 0xE5;
+    }
+    let base = part(&base_bytes, flags & 0x08 != 0);
+    let extension = part(&short_name[8..], flags & 0x10 != 0);
+    if extension.is_empty() {
+        base
+    } else {
+        format!("{}.{}", base, extension)
+    }
+}
+
+impl FileSystem for FatFs {
+    fn name(&self) -> &str {
+        "fat32"
+    }
+
+    fn open<'a>(&'a self, path: &'a str, options: OpenOptions) -> FsFuture<'a, Box<dyn File>> {
+        Box::pin(async move {
+            if options.write || options.create || options.truncate || options.append {
+                return Err(Error::ReadOnly);
+            }
+            let entry = self.volume.lookup(path).await?;
+            if entry.directory {
+                return Err(Error::IsADirectory);
+            }
+            if entry.size > 0 {
+                self.volume.check_cluster(entry.cluster)?;
+            }
+            Ok(Box::new(FatFile {
+                volume: self.volume.clone(),
+                first_cluster: entry.cluster,
+                size: entry.size as u64,
+                position: 0,
+                current: None,
+            }) as Box<dyn File>)
+        })
+    }
+
+    fn open_dir<'a>(&'a self, path: &'a str) -> FsFuture<'a, Box<dyn Dir>> {
+        Box::pin(async move {
+            let entry = self.volume.lookup(path).await?;
+            if !entry.directory {
+                return Err(Error::NotADirectory);
+            }
+            let entries: Vec<DirEntry> = self
+                .volume
+                .read_dir(entry.cluster)
+                .await?
+                .into_iter()
+                .map(|entry| DirEntry {
+                    file_type: entry.file_type(),
+                    name: entry.name,
+                })
+                .collect();
+            Ok(Box::new(FatDir {
+                entries: entries.into_iter(),
+            }) as Box<dyn Dir>)
+        })
+    }
+
+    fn metadata<'a>(&'a self, path: &'a str) -> FsFuture<'a, Metadata> {
+        Box::pin(async move {
+            let entry = self.volume.lookup(path).await?;
+            Ok(Metadata {
+                file_type: entry.file_type(),
+                len: if entry.directory {
+                    0
+                } else {
+                    entry.size as u64
+                },
+            })
+        })
+    }
+
+    fn create_dir<'a>(&'a self, _path: &'a str) -> FsFuture<'a, ()> {
+        Box::pin(async { Err(Error::ReadOnly) })
+    }
+
+    fn remove<'a>(&'a self, _path: &'a str) -> FsFuture<'a, ()> {
+        Box::pin(async { Err(Error::ReadOnly) })
+    }
+}
+
+struct FatFile {
+    volume: Arc<Volume>,
+    first_cluster: u32,
+    size: u64,
+    position: u64,
+    // The cluster read last and its index in the chain, so reading on
+    // doesn't walk the chain from the start again
+    current: Option<(u64, u32)>,
+}
+
+impl File for FatFile {
+    fn read<'a>(&'a mut self, buf: &'a mut [u8]) -> FsFuture<'a, usize> {
+        Box::pin(async move {
+            if self.position >= self.size || buf.is_empty() {
+                return Ok(0);
+            }
+            let cluster_size = self.volume.cluster_size;
+            let index = self.position / cluster_size;
+            let (mut at, mut cluster) = match self.current {
+                Some((at, cluster)) if at <= index => (at, cluster),
+                _ => (0, self.first_cluster),
+            };
+            while at < index {
+                cluster = self
+                    .volume
+                    .next_cluster(cluster)
+                    .await?
+                    .ok_or(Error::Corrupt)?;
+                at += 1;
+            }
+            self.current = Some((index, cluster));
+
+            let within = self.position % cluster_size;
+            let count = (buf.len() as u64)
+                .min(cluster_size - within)
+                .min(self.size - self.position) as usize;
+            let offset = self.volume.cluster_offset(cluster) + within;
+            read_at(&*self.volume.device, offset, &mut buf[..count]).await?;
+            self.position += count as u64;
+            Ok(count)
+        })
+    }
+
+    fn write<'a>(&'a mut self, _bytes: &'a [u8]) -> FsFuture<'a, usize> {
+        Box::pin(async { Err(Error::ReadOnly) })
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> FsFuture<'_, u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.size.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        Box::pin(async move {
+            self.position = position.ok_or(Error::InvalidSeek)?;
+            Ok(self.position)
+        })
+    }
+
+    fn metadata(&self) -> FsFuture<'_, Metadata> {
+        let len = self.size;
+        Box::pin(async move {
+            Ok(Metadata {
+                file_type: FileType::File,
+                len,
+            })
+        })
+    }
+}
+
+struct FatDir {
+    entries: std::vec::IntoIter<DirEntry>,
+}
+
+impl Dir for FatDir {
+    fn next_entry(&mut self) -> FsFuture<'_, Option<DirEntry>> {
+        let entry = self.entries.next();
+        Box::pin(async move { Ok(entry) })
+    }
+}
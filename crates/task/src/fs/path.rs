@@ -0,0 +1,43 @@
+use super::{Error, Result};
+
+/// `path` without `.`, `..`, repeated or trailing `/`s: `/a/./b//../c/`
+/// is `/a/c`. Only absolute paths, `..` at the root stays there.
+pub fn normalize(path: &str) -> Result<String> {
+    if !path.starts_with('/') {
+        return Err(Error::InvalidPath);
+    }
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    Ok(format!("/{}", parts.join("/")))
+}
+
+/// `path` looked up from `base`, for relative paths typed at a shell
+pub fn join(base: &str, path: &str) -> Result<String> {
+    if path.starts_with('/') {
+        normalize(path)
+    } else {
+        normalize(&format!("{}/{}", base, path))
+    }
+}
+
+/// The names in a path relative to a filesystem's root, `""` has none
+pub fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|part| !part.is_empty())
+}
+
+/// The directory holding `path` and its name in there: `a/b/c` is
+/// `("a/b", "c")`, `c` is `("", "c")`
+pub fn split_last(path: &str) -> (&str, &str) {
+    match path.rsplit_once('/') {
+        Some((parent, name)) => (parent, name),
+        None => ("", path),
+    }
+}
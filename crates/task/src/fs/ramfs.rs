@@ -0,0 +1,244 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use super::{
+    Dir, DirEntry, Error, File, FileSystem, FileType, FsFuture, Metadata, OpenOptions, Result,
+    SeekFrom, path,
+};
+use crate::sync::RwLock;
+
+type Contents = Arc<RwLock<Vec<u8>>>;
+type Directory = Arc<RwLock<BTreeMap<String, Node>>>;
+
+#[derive(Clone)]
+enum Node {
+    File(Contents),
+    Dir(Directory),
+}
+
+impl Node {
+    fn file_type(&self) -> FileType {
+        match self {
+            Node::File(_) => FileType::File,
+            Node::Dir(_) => FileType::Directory,
+        }
+    }
+}
+
+/// A filesystem kept in memory, gone on reboot.
+///
+/// Each file and directory has its own lock, so tasks working on different
+/// files don't wait for each other.
+pub struct RamFs {
+    root: Directory,
+}
+
+impl RamFs {
+    pub fn new() -> Self {
+        RamFs {
+            root: Directory::default(),
+        }
+    }
+
+    async fn lookup(&self, path: &str) -> Result<Node> {
+        let mut node = Node::Dir(self.root.clone());
+        for name in path::components(path) {
+            let Node::Dir(dir) = node else {
+                return Err(Error::NotADirectory);
+            };
+            node = dir.read().await.get(name).cloned().ok_or(Error::NotFound)?;
+        }
+        Ok(node)
+    }
+
+    /// The directory `path` is in and its name there
+    async fn parent<'a>(&self, path: &'a str) -> Result<(Directory, &'a str)> {
+        let (parent, name) = path::split_last(path);
+        if name.is_empty() {
+            return Err(Error::InvalidPath);
+        }
+        match self.lookup(parent).await? {
+            Node::Dir(dir) => Ok((dir, name)),
+            Node::File(_) => Err(Error::NotADirectory),
+        }
+    }
+}
+
+impl Default for RamFs {
+    fn default() -> Self {
+        RamFs::new()
+    }
+}
+
+impl FileSystem for RamFs {
+    fn name(&self) -> &str {
+        "ramfs"
+    }
+
+    fn open<'a>(&'a self, path: &'a str, options: OpenOptions) -> FsFuture<'a, Box<dyn File>> {
+        Box::pin(async move {
+            let node = if options.create && !path.is_empty() {
+                let (dir, name) = self.parent(path).await?;
+                let mut entries = dir.write().await;
+                entries
+                    .entry(name.to_string())
+                    .or_insert_with(|| Node::File(Contents::default()))
+                    .clone()
+            } else {
+                self.lookup(path).await?
+            };
+            let Node::File(contents) = node else {
+                return Err(Error::IsADirectory);
+            };
+            if options.write && options.truncate {
+                contents.write().await.clear();
+            }
+            Ok(Box::new(RamFile {
+                contents,
+                position: 0,
+                options,
+            }) as Box<dyn File>)
+        })
+    }
+
+    fn open_dir<'a>(&'a self, path: &'a str) -> FsFuture<'a, Box<dyn Dir>> {
+        Box::pin(async move {
+            let Node::Dir(dir) = self.lookup(path).await? else {
+                return Err(Error::NotADirectory);
+            };
+            // A snapshot, so a listing doesn't hold the directory locked
+            let entries: Vec<DirEntry> = dir
+                .read()
+                .await
+                .iter()
+                .map(|(name, node)| DirEntry {
+                    name: name.clone(),
+                    file_type: node.file_type(),
+                })
+                .collect();
+            Ok(Box::new(RamDir {
+                entries: entries.into_iter(),
+            }) as Box<dyn Dir>)
+        })
+    }
+
+    fn metadata<'a>(&'a self, path: &'a str) -> FsFuture<'a, Metadata> {
+        Box::pin(async move {
+            let node = self.lookup(path).await?;
+            let len = match &node {
+                Node::File(contents) => contents.read().await.len() as u64,
+                Node::Dir(_) => 0,
+            };
+            Ok(Metadata {
+                file_type: node.file_type(),
+                len,
+            })
+        })
+    }
+
+    fn create_dir<'a>(&'a self, path: &'a str) -> FsFuture<'a, ()> {
+        Box::pin(async move {
+            if path.is_empty() {
+                return Err(Error::AlreadyExists);
+            }
+            let (dir, name) = self.parent(path).await?;
+            let mut entries = dir.write().await;
+            if entries.contains_key(name) {
+                return Err(Error::AlreadyExists);
+            }
+            entries.insert(name.to_string(), Node::Dir(Directory::default()));
+            Ok(())
+        })
+    }
+
+    fn remove<'a>(&'a self, path: &'a str) -> FsFuture<'a, ()> {
+        Box::pin(async move {
+            let (dir, name) = self.parent(path).await?;
+            let mut entries = dir.write().await;
+            match entries.get(name) {
+                None => return Err(Error::NotFound),
+                Some(Node::Dir(child)) if !child.read().await.is_empty() => {
+                    return Err(Error::DirectoryNotEmpty);
+                }
+                Some(_) => {}
+            }
+            // Open files keep their contents until they're dropped
+            entries.remove(name);
+            Ok(())
+        })
+    }
+}
+
+struct RamFile {
+    contents: Contents,
+    position: u64,
+    options: OpenOptions,
+}
+
+impl File for RamFile {
+    fn read<'a>(&'a mut self, buf: &'a mut [u8]) -> FsFuture<'a, usize> {
+        Box::pin(async move {
+            let contents = self.contents.read().await;
+            let start = (self.position as usize).min(contents.len());
+            let count = buf.len().min(contents.len() - start);
+            buf[..count].copy_from_slice(&contents[start..start + count]);
+            self.position += count as u64;
+            Ok(count)
+        })
+    }
+
+    fn write<'a>(&'a mut self, bytes: &'a [u8]) -> FsFuture<'a, usize> {
+        Box::pin(async move {
+            if !self.options.write {
+                return Err(Error::PermissionDenied);
+            }
+            let mut contents = self.contents.write().await;
+            if self.options.append {
+                self.position = contents.len() as u64;
+            }
+            let start = self.position as usize;
+            let end = start + bytes.len();
+            // Writing past the end leaves a gap of zeros
+            if contents.len() < end {
+                contents.resize(end, 0);
+            }
+            contents[start..end].copy_from_slice(bytes);
+            self.position = end as u64;
+            Ok(bytes.len())
+        })
+    }
+
+    fn seek(&mut self, pos: SeekFrom) -> FsFuture<'_, u64> {
+        Box::pin(async move {
+            let position = match pos {
+                SeekFrom::Start(offset) => Some(offset),
+                SeekFrom::End(offset) => {
+                    let len = self.contents.read().await.len() as u64;
+                    len.checked_add_signed(offset)
+                }
+                SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            };
+            self.position = position.ok_or(Error::InvalidSeek)?;
+            Ok(self.position)
+        })
+    }
+
+    fn metadata(&self) -> FsFuture<'_, Metadata> {
+        Box::pin(async move {
+            Ok(Metadata {
+                file_type: FileType::File,
+                len: self.contents.read().await.len() as u64,
+            })
+        })
+    }
+}
+
+struct RamDir {
+    entries: std::vec::IntoIter<DirEntry>,
+}
+
+impl Dir for RamDir {
+    fn next_entry(&mut self) -> FsFuture<'_, Option<DirEntry>> {
+        let entry = self.entries.next();
+        Box::pin(async move { Ok(entry) })
+    }
+}
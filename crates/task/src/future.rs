@@ -0,0 +1,139 @@
+//!
+//! Future combinators
+//!
+//! [`pin!`] pins a future on the stack, for
+//! [`executor::block_on`](crate::executor::block_on) and other places
+//! taking a `Pin<&mut dyn Future>` instead of a boxed task.
+//!
+
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use pin_project_lite::pin_project;
+
+pub use core::pin::pin;
+
+/// Output of [`race`], tells which branch finished first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Either<A, B> {
+    Left(A),
+    Right(B),
+}
+
+impl<A, B> Either<A, B> {
+    pub fn left(self) -> Option<A> {
+        match self {
+            Either::Left(a) => Some(a),
+            Either::Right(_) => None,
+        }
+    }
+
+    pub fn right(self) -> Option<B> {
+        match self {
+            Either::Left(_) => None,
+            Either::Right(b) => Some(b),
+        }
+    }
+}
+
+impl<T> Either<T, T> {
+    pub fn into_inner(self) -> T {
+        match self {
+            Either::Left(t) | Either::Right(t) => t,
+        }
+    }
+}
+
+pin_project! {
+    /// Future returned by [`race`]
+    #[must_use = "futures do nothing unless polled"]
+    pub struct Race<A, B> {
+        #[pin]
+        a: A,
+        #[pin]
+        b: B,
+        left_first: bool,
+    }
+}
+
+/// Wait for whichever of two futures completes first, dropping the other.
+///
+/// Both futures are polled with the same context, so both register the
+/// task's waker and a wake from either side re-polls the race. Which side
+/// gets polled first alternates so a busy branch can't starve the other.
+///
+/// ```ignore
+/// match race(scancodes.next(), shutdown.notified()).await {
+///     Either::Left(Some(scancode)) => ...,
+///     _ => return,
+/// }
+/// ```
+pub fn race<A, B>(a: A, b: B) -> Race<A, B>
+where
+    A: Future,
+    B: Future,
+{
+    Race {
+        a,
+        b,
+        left_first: true,
+    }
+}
+
+impl<A, B> Future for Race<A, B>
+where
+    A: Future,
+    B: Future,
+{
+    type Output = Either<A::Output, B::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        let left_first = *this.left_first;
+        *this.left_first = !left_first;
+
+        if left_first {
+            if let Poll::Ready(a) = this.a.poll(cx) {
+                return Poll::Ready(Either::Left(a));
+            }
+            if let Poll::Ready(b) = this.b.poll(cx) {
+                return Poll::Ready(Either::Right(b));
+            }
+        } else {
+            if let Poll::Ready(b) = this.b.poll(cx) {
+                return Poll::Ready(Either::Right(b));
+            }
+            if let Poll::Ready(a) = this.a.poll(cx) {
+                return Poll::Ready(Either::Left(a));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+/// Future returned by [`yield_now`]
+#[must_use = "futures do nothing unless polled"]
+pub struct YieldNow {
+    yielded: bool,
+}
+
+/// Let the executor run other tasks before coming back
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
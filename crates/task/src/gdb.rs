@@ -0,0 +1,492 @@
+//!
+//! GDB stub
+//!
+//! Enough of GDB's remote serial protocol to debug the bare-metal kernel
+//! from the host: stop it, read registers and memory, write memory, set
+//! breakpoints and single step. The stub talks over a [`Connection`],
+//! usually a polled UART, and takes over when the CPU hits a breakpoint:
+//!
+//! ```ignore
+//! pc::init();
+//! gdb::install(pc::ComPort::init_polled(pc::COM1, 115_200).unwrap());
+//! // Waits here until gdb attaches and continues
+//! gdb::breakpoint();
+//! ```
+//!
+//! Then on the host, with QEMU's `-serial pty` or a cable:
+//!
+//! ```text
+//! gdb kernel -ex 'target remote /dev/pts/3'
+//! ```
+//!
+//! While stopped the whole machine is: interrupts stay off and the stub
+//! polls the connection. Ctrl+C in gdb stops the kernel at the next timer
+//! tick, inside the timer's interrupt handler. Only software breakpoints
+//! are supported, registers can't be written, and memory is only checked
+//! to be canonical and off the null page, reading unmapped memory faults.
+//!
+
+use alloc::boxed::Box;
+use core::{
+    fmt::{self, Write as _},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use x86_64::{
+    VirtAddr,
+    registers::control::{Cr0, Cr0Flags},
+};
+
+/// Where the stub reads gdb's packets and writes its replies
+pub trait Connection: Send {
+    /// Wait for the next byte
+    fn read_byte(&mut self) -> u8;
+    /// The next byte if one is there
+    fn try_read_byte(&mut self) -> Option<u8>;
+    fn write_byte(&mut self, byte: u8);
+}
+
+/// Largest packet either way, told to gdb in `qSupported`
+const PACKET_SIZE: usize = 4096;
+const MAX_BREAKPOINTS: usize = 32;
+
+const INT3: u8 = 0xcc;
+/// Gdb's Ctrl+C, sent while the kernel runs
+const INTERRUPT: u8 = 0x03;
+const TRAP_FLAG: u64 = 1 << 8;
+
+const DEBUG_VECTOR: u64 = 1;
+const BREAKPOINT_VECTOR: u64 = 3;
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+/// The interrupted code's registers, in the order the trap entry in
+/// [`pc`](crate::pc) pushes them, followed by the CPU's interrupt frame
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct TrapFrame {
+    rax: u64,
+    rbx: u64,
+    rcx: u64,
+    rdx: u64,
+    rsi: u64,
+    rdi: u64,
+    rbp: u64,
+    r8: u64,
+    r9: u64,
+    r10: u64,
+    r11: u64,
+    r12: u64,
+    r13: u64,
+    r14: u64,
+    r15: u64,
+    rip: u64,
+    cs: u64,
+    rflags: u64,
+    rsp: u64,
+    ss: u64,
+}
+
+#[derive(Clone, Copy)]
+struct Breakpoint {
+    address: u64,
+    // The byte `INT3` replaced
+    original: u8,
+}
+
+struct Breakpoints([Option<Breakpoint>; MAX_BREAKPOINTS]);
+
+impl Breakpoints {
+    fn at(&self, address: u64) -> Option<usize> {
+        self.0
+            .iter()
+            .position(|breakpoint| breakpoint.is_some_and(|b| b.address == address))
+    }
+
+    fn insert(&mut self, address: u64) -> bool {
+        if self.at(address).is_some() {
+            return true;
+        }
+        let Some(slot) = self.0.iter_mut().find(|slot| slot.is_none()) else {
+            return false;
+        };
+        if !accessible(address, 1) {
+            return false;
+        }
+        // SAFETY: as for any memory gdb reads
+        let original = unsafe { *(address as *const u8) };
+        write_memory(address, &[INT3]);
+        *slot = Some(Breakpoint { address, original });
+        true
+    }
+
+    fn remove(&mut self, address: u64) -> bool {
+        let Some(breakpoint) = self.at(address).and_then(|index| self.0[index].take()) else {
+            return false;
+        };
+        write_memory(breakpoint.address, &[breakpoint.original]);
+        true
+    }
+
+    fn remove_all(&mut self) {
+        for breakpoint in self.0.iter_mut().filter_map(Option::take) {
+            write_memory(breakpoint.address, &[breakpoint.original]);
+        }
+    }
+}
+
+struct Buffer {
+    bytes: [u8; PACKET_SIZE],
+    len: usize,
+}
+
+impl Buffer {
+    const fn new() -> Self {
+        Buffer {
+            bytes: [0; PACKET_SIZE],
+            len: 0,
+        }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    fn push(&mut self, byte: u8) -> bool {
+        let Some(slot) = self.bytes.get_mut(self.len) else {
+            return false;
+        };
+        *slot = byte;
+        self.len += 1;
+        true
+    }
+
+    fn hex(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            let _ = write!(self, "{:02x}", byte);
+        }
+    }
+}
+
+impl fmt::Write for Buffer {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        for &byte in text.as_bytes() {
+            if !self.push(byte) {
+                return Err(fmt::Error);
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Stub {
+    connection: Option<Box<dyn Connection>>,
+    breakpoints: Breakpoints,
+    // Gdb resumed the kernel and waits to hear it stopped
+    attached: bool,
+    // Kept off the stack, which may be small where the trap hit
+    packet: Buffer,
+    reply: Buffer,
+}
+
+static STUB: spin::Mutex<Stub> = spin::Mutex::new(Stub {
+    connection: None,
+    breakpoints: Breakpoints([None; MAX_BREAKPOINTS]),
+    attached: false,
+    packet: Buffer::new(),
+    reply: Buffer::new(),
+});
+
+// The next stop is gdb's Ctrl+C rather than a breakpoint
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Talk to gdb over `connection` from the next breakpoint on
+pub fn install(connection: impl Connection + 'static) {
+    STUB.lock().connection = Some(Box::new(connection));
+}
+
+pub fn is_installed() -> bool {
+    STUB.lock().connection.is_some()
+}
+
+/// Stop here and hand over to gdb, panics if no stub is installed
+pub fn breakpoint() {
+    x86_64::instructions::interrupts::int3();
+}
+
+/// Stop if gdb sent Ctrl+C, called from the timer interrupt
+pub(crate) fn poll_interrupt() {
+    let interrupt = STUB
+        .try_lock()
+        .and_then(|mut stub| stub.connection.as_mut()?.try_read_byte())
+        == Some(INTERRUPT);
+    if interrupt {
+        INTERRUPTED.store(true, Ordering::Relaxed);
+        breakpoint();
+    }
+}
+
+/// Entered on `int3` and after a single step, with interrupts off.
+/// Returns when gdb continues or steps.
+pub(crate) extern "C" fn trap(frame: &mut TrapFrame, vector: u64) {
+    let Some(mut stub) = STUB.try_lock().filter(|stub| stub.connection.is_some()) else {
+        panic!("breakpoint without a debugger\n{:#x?}", frame);
+    };
+    frame.rflags &= !TRAP_FLAG;
+    // `rip` is past the `int3` gdb had put there
+    if vector == BREAKPOINT_VECTOR && stub.breakpoints.at(frame.rip.wrapping_sub(1)).is_some() {
+        frame.rip -= 1;
+    }
+    let signal = if INTERRUPTED.swap(false, Ordering::Relaxed) {
+        SIGINT
+    } else {
+        debug_assert!(vector == BREAKPOINT_VECTOR || vector == DEBUG_VECTOR);
+        SIGTRAP
+    };
+    stub.session(frame, signal);
+}
+
+impl Stub {
+    /// Answer gdb's packets until it continues or steps
+    fn session(&mut self, frame: &mut TrapFrame, signal: u8) {
+        if self.attached {
+            self.reply.clear();
+            let _ = write!(self.reply, "S{:02x}", signal);
+            self.send();
+        }
+        loop {
+            self.receive();
+            self.reply.clear();
+            let packet = &self.packet.bytes[..self.packet.len];
+            let resume = match packet.split_first() {
+                Some((b'?', _)) => {
+                    let _ = write!(self.reply, "S{:02x}", signal);
+                    false
+                }
+                Some((b'g', _)) => {
+                    registers(frame, &mut self.reply);
+                    false
+                }
+                Some((b'm', arguments)) => {
+                    match address_and_len(arguments) {
+                        Some((address, len))
+                            if len <= PACKET_SIZE / 2 && accessible(address, len) =>
+                        {
+                            // SAFETY: only checked so far, see the module docs
+                            let memory =
+                                unsafe { core::slice::from_raw_parts(address as *const u8, len) };
+                            self.reply.hex(memory);
+                        }
+                        _ => {
+                            let _ = self.reply.write_str("E01");
+                        }
+                    }
+                    false
+                }
+                Some((b'M', arguments)) => {
+                    let written = write_memory_packet(arguments);
+                    let _ = self.reply.write_str(if written { "OK" } else { "E01" });
+                    false
+                }
+                Some((kind @ (b'Z' | b'z'), arguments)) => {
+                    // Hardware breakpoints and watchpoints aren't supported,
+                    // an empty reply says so
+                    if let Some((address, _)) =
+                        arguments.strip_prefix(b"0,").and_then(address_and_len)
+                    {
+                        let done = if *kind == b'Z' {
+                            self.breakpoints.insert(address)
+                        } else {
+                            self.breakpoints.remove(address)
+                        };
+                        let _ = self.reply.write_str(if done { "OK" } else { "E01" });
+                    }
+                    false
+                }
+                Some((b'c', address)) => {
+                    if let Some(address) = parse_hex(address) {
+                        frame.rip = address;
+                    }
+                    true
+                }
+                Some((b's', address)) => {
+                    if let Some(address) = parse_hex(address) {
+                        frame.rip = address;
+                    }
+                    frame.rflags |= TRAP_FLAG;
+                    true
+                }
+                Some((b'D', _)) => {
+                    self.breakpoints.remove_all();
+                    self.attached = false;
+                    let _ = self.reply.write_str("OK");
+                    self.send();
+                    return;
+                }
+                Some((b'k', _)) => crate::pc::reset(),
+                Some((b'H', _)) => {
+                    let _ = self.reply.write_str("OK");
+                    false
+                }
+                Some((b'q', query)) => {
+                    if query.starts_with(b"Supported") {
+                        let _ = write!(self.reply, "PacketSize={:x}", PACKET_SIZE);
+                    } else if query == b"Attached" {
+                        // Detaching leaves the kernel running
+                        let _ = self.reply.write_str("1");
+                    }
+                    false
+                }
+                _ => false,
+            };
+            if resume {
+                // Resuming has no reply until the next stop
+                self.attached = true;
+                return;
+            }
+            self.send();
+        }
+    }
+
+    /// Wait for a packet with a good checksum and acknowledge it
+    fn receive(&mut self) {
+        let connection = self.connection.as_mut().expect("checked in trap");
+        loop {
+            while connection.read_byte() != b'$' {}
+            self.packet.clear();
+            let mut sum = 0u8;
+            let mut fits = true;
+            let checksum = loop {
+                match connection.read_byte() {
+                    b'#' => {
+                        break hex_digit(connection.read_byte())
+                            .zip(hex_digit(connection.read_byte()))
+                            .map(|(high, low)| high << 4 | low);
+                    }
+                    byte => {
+                        sum = sum.wrapping_add(byte);
+                        fits &= self.packet.push(byte);
+                    }
+                }
+            };
+            if fits && checksum == Some(sum) {
+                connection.write_byte(b'+');
+                return;
+            }
+            connection.write_byte(b'-');
+        }
+    }
+
+    /// Send the reply until gdb acknowledges it
+    fn send(&mut self) {
+        let connection = self.connection.as_mut().expect("checked in trap");
+        let reply = self.reply.as_slice();
+        let sum = reply.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        loop {
+            connection.write_byte(b'$');
+            for &byte in reply {
+                connection.write_byte(byte);
+            }
+            connection.write_byte(b'#');
+            connection.write_byte(HEX_DIGITS[usize::from(sum >> 4)]);
+            connection.write_byte(HEX_DIGITS[usize::from(sum & 0xf)]);
+            loop {
+                match connection.read_byte() {
+                    b'+' => return,
+                    b'-' => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+/// The `g` reply: gdb's amd64 registers up to `gs`, the rest it takes as
+/// unavailable
+fn registers(frame: &TrapFrame, reply: &mut Buffer) {
+    for value in [
+        frame.rax, frame.rbx, frame.rcx, frame.rdx, frame.rsi, frame.rdi, frame.rbp, frame.rsp,
+        frame.r8, frame.r9, frame.r10, frame.r11, frame.r12, frame.r13, frame.r14, frame.r15,
+        frame.rip,
+    ] {
+        reply.hex(&value.to_le_bytes());
+    }
+    // eflags, cs, ss, then ds, es, fs and gs, which long mode ignores
+    for value in [frame.rflags, frame.cs, frame.ss, 0, 0, 0, 0] {
+        reply.hex(&(value as u32).to_le_bytes());
+    }
+}
+
+/// `M addr,len:bytes`
+fn write_memory_packet(arguments: &[u8]) -> bool {
+    let Some(colon) = arguments.iter().position(|&byte| byte == b':') else {
+        return false;
+    };
+    let (header, data) = (&arguments[..colon], &arguments[colon + 1..]);
+    let Some((address, len)) = address_and_len(header) else {
+        return false;
+    };
+    if data.len() / 2 != len || data.len() % 2 != 0 || !accessible(address, len) {
+        return false;
+    }
+    for (offset, pair) in data.chunks_exact(2).enumerate() {
+        let Some(byte) = hex_digit(pair[0])
+            .zip(hex_digit(pair[1]))
+            .map(|(high, low)| high << 4 | low)
+        else {
+            return false;
+        };
+        write_memory(address + offset as u64, &[byte]);
+    }
+    true
+}
+
+/// Write past the code pages' write protection, breakpoints go there
+fn write_memory(address: u64, bytes: &[u8]) {
+    let cr0 = Cr0::read();
+    // SAFETY: only the supervisor's write protection is lifted, and only
+    // for the copy, which `accessible` checked
+    unsafe {
+        Cr0::write(cr0 - Cr0Flags::WRITE_PROTECT);
+        core::ptr::copy_nonoverlapping(bytes.as_ptr(), address as *mut u8, bytes.len());
+        Cr0::write(cr0);
+    }
+}
+
+/// Canonical and off the null page, page tables aren't looked at
+fn accessible(address: u64, len: usize) -> bool {
+    let Some(end) = address.checked_add(len as u64) else {
+        return false;
+    };
+    address >= 0x1000
+        && VirtAddr::try_new(address).is_ok()
+        && (len == 0 || VirtAddr::try_new(end - 1).is_ok())
+}
+
+/// `addr,len` in hex
+fn address_and_len(arguments: &[u8]) -> Option<(u64, usize)> {
+    let comma = arguments.iter().position(|&byte| byte == b',')?;
+    let address = parse_hex(&arguments[..comma])?;
+    let len = parse_hex(&arguments[comma + 1..])?;
+    Some((address, usize::try_from(len).ok()?))
+}
+
+fn parse_hex(digits: &[u8]) -> Option<u64> {
+    if digits.is_empty() || digits.len() > 16 {
+        return None;
+    }
+    digits.iter().try_fold(0u64, |value, &digit| {
+        Some(value << 4 | u64::from(hex_digit(digit)?))
+    })
+}
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+fn hex_digit(digit: u8) -> Option<u8> {
+    (digit as char).to_digit(16).map(|value| value as u8)
+}
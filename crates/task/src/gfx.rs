@@ -0,0 +1,372 @@
+//!
+//! Framebuffer graphics
+//!
+//! A [`Framebuffer`] wraps a linear framebuffer, either the one the
+//! bootloader set up or plain memory on the hosted build that a frontend
+//! copies to a window. Install it with [`install`], after that tasks share
+//! it through [`lock`]:
+//!
+//! ```ignore
+//! let mut screen = gfx::lock().await.expect("no framebuffer");
+//! screen.fill_rect(Rect::new(10, 10, 100, 50), Rgb::new(0, 0, 128));
+//! screen.draw_text(14, 14, "hello", Rgb::WHITE, None);
+//! screen.present();
+//! ```
+//!
+//! With [`Framebuffer::double_buffered`] drawing goes to memory off screen
+//! and [`Framebuffer::present`] copies a finished frame over in one go, so
+//! nothing half drawn is ever shown.
+//!
+//! For animation, spawn [`render_loop`] and draw from [`on_draw`] callbacks.
+//!
+
+mod font;
+mod render;
+
+use std::{
+    ops::{Deref, DerefMut},
+    slice,
+};
+
+use crate::sync::{Mutex, MutexGuard};
+
+pub use render::{DrawId, Frame, on_draw, remove_draw, render_loop};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb::new(0, 0, 0);
+    pub const WHITE: Rgb = Rgb::new(255, 255, 255);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb { r, g, b }
+    }
+}
+
+/// Byte order of a pixel in memory, a fourth byte if any is left alone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgb,
+    Bgr,
+    /// One byte of brightness
+    Gray,
+}
+
+/// Layout of a framebuffer, as reported by the bootloader
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramebufferInfo {
+    pub width: usize,
+    pub height: usize,
+    /// Pixels from the start of one line to the next, at least `width`
+    pub stride: usize,
+    pub bytes_per_pixel: usize,
+    pub format: PixelFormat,
+}
+
+impl FramebufferInfo {
+    fn len(&self) -> usize {
+        self.stride * self.height * self.bytes_per_pixel
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub const fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Shrunk by `by` on every side
+    pub const fn inset(self, by: usize) -> Rect {
+        Rect::new(
+            self.x + by,
+            self.y + by,
+            self.width.saturating_sub(2 * by),
+            self.height.saturating_sub(2 * by),
+        )
+    }
+
+    /// The top `rows` and the rest
+    pub const fn split_top(self, rows: usize) -> (Rect, Rect) {
+        let rows = if rows < self.height {
+            rows
+        } else {
+            self.height
+        };
+        (
+            Rect::new(self.x, self.y, self.width, rows),
+            Rect::new(self.x, self.y + rows, self.width, self.height - rows),
+        )
+    }
+
+    /// The rest and the bottom `rows`
+    pub const fn split_bottom(self, rows: usize) -> (Rect, Rect) {
+        let rows = if rows < self.height {
+            rows
+        } else {
+            self.height
+        };
+        self.split_top(self.height - rows)
+    }
+
+    /// The left `columns` and the rest
+    pub const fn split_left(self, columns: usize) -> (Rect, Rect) {
+        let columns = if columns < self.width {
+            columns
+        } else {
+            self.width
+        };
+        (
+            Rect::new(self.x, self.y, columns, self.height),
+            Rect::new(self.x + columns, self.y, self.width - columns, self.height),
+        )
+    }
+}
+
+enum Memory {
+    Mapped(&'static mut [u8]),
+    Owned(Vec<u8>),
+}
+
+/// Drawing target over framebuffer memory.
+///
+/// Everything drawn is clipped to the screen, so drawing partly off screen is
+/// fine.
+pub struct Framebuffer {
+    memory: Memory,
+    // Drawn to instead of `memory` when double buffered
+    back: Option<Vec<u8>>,
+    info: FramebufferInfo,
+}
+
+impl Framebuffer {
+    /// # Safety
+    ///
+    /// `address` must point to framebuffer memory laid out as `info` says,
+    /// that nothing else uses.
+    pub unsafe fn from_raw(address: *mut u8, info: FramebufferInfo) -> Self {
+        let memory = unsafe { slice::from_raw_parts_mut(address, info.len()) };
+        Framebuffer {
+            memory: Memory::Mapped(memory),
+            back: None,
+            info,
+        }
+    }
+
+    /// Framebuffer in ordinary memory, 4 bytes per pixel in RGB order.
+    ///
+    /// For the hosted build, where a window frontend shows [`bytes`].
+    ///
+    /// [`bytes`]: Framebuffer::bytes
+    pub fn in_memory(width: usize, height: usize) -> Self {
+        let info = FramebufferInfo {
+            width,
+            height,
+            stride: width,
+            bytes_per_pixel: 4,
+            format: PixelFormat::Rgb,
+        };
+        Framebuffer {
+            memory: Memory::Owned(vec![0; info.len()]),
+            back: None,
+            info,
+        }
+    }
+
+    /// Draw to a back buffer from now on, shown by [`present`].
+    ///
+    /// [`present`]: Framebuffer::present
+    pub fn double_buffered(mut self) -> Self {
+        self.back = Some(self.front().to_vec());
+        self
+    }
+
+    pub fn is_double_buffered(&self) -> bool {
+        self.back.is_some()
+    }
+
+    /// Show everything drawn since the last call, does nothing unless
+    /// [`double_buffered`](Framebuffer::double_buffered)
+    pub fn present(&mut self) {
+        if let Some(back) = &self.back {
+            let front = match &mut self.memory {
+                Memory::Mapped(memory) => &mut memory[..],
+                Memory::Owned(memory) => &mut memory[..],
+            };
+            front.copy_from_slice(back);
+        }
+    }
+
+    pub fn info(&self) -> FramebufferInfo {
+        self.info
+    }
+
+    pub fn width(&self) -> usize {
+        self.info.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.info.height
+    }
+
+    /// Pixel memory being shown, laid out as [`info`](Framebuffer::info)
+    /// says
+    pub fn bytes(&self) -> &[u8] {
+        self.front()
+    }
+
+    fn front(&self) -> &[u8] {
+        match &self.memory {
+            Memory::Mapped(memory) => memory,
+            Memory::Owned(memory) => memory,
+        }
+    }
+
+    /// Where drawing goes
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        match (&mut self.back, &mut self.memory) {
+            (Some(back), _) => back,
+            (None, Memory::Mapped(memory)) => memory,
+            (None, Memory::Owned(memory)) => memory,
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Rgb) {
+        if x >= self.info.width || y >= self.info.height {
+            return;
+        }
+        let FramebufferInfo {
+            stride,
+            bytes_per_pixel,
+            format,
+            ..
+        } = self.info;
+        let offset = (y * stride + x) * bytes_per_pixel;
+        let pixel = &mut self.bytes_mut()[offset..offset + bytes_per_pixel];
+        match format {
+            PixelFormat::Rgb => pixel[..3].copy_from_slice(&[color.r, color.g, color.b]),
+            PixelFormat::Bgr => pixel[..3].copy_from_slice(&[color.b, color.g, color.r]),
+            PixelFormat::Gray => pixel[0] = gray(color),
+        }
+    }
+
+    pub fn clear(&mut self, color: Rgb) {
+        self.fill_rect(Rect::new(0, 0, self.info.width, self.info.height), color);
+    }
+
+    pub fn fill_rect(&mut self, rect: Rect, color: Rgb) {
+        let right = (rect.x + rect.width).min(self.info.width);
+        let bottom = (rect.y + rect.height).min(self.info.height);
+        for y in rect.y..bottom {
+            for x in rect.x..right {
+                self.set_pixel(x, y, color);
+            }
+        }
+    }
+
+    /// One pixel wide outline of `rect`
+    pub fn draw_rect(&mut self, rect: Rect, color: Rgb) {
+        if rect.width == 0 || rect.height == 0 {
+            return;
+        }
+        let Rect {
+            x,
+            y,
+            width,
+            height,
+        } = rect;
+        self.fill_rect(Rect::new(x, y, width, 1), color);
+        self.fill_rect(Rect::new(x, y + height - 1, width, 1), color);
+        self.fill_rect(Rect::new(x, y, 1, height), color);
+        self.fill_rect(Rect::new(x + width - 1, y, 1, height), color);
+    }
+
+    /// Draw `text` in an 8x8 font with its top left at (`x`, `y`).
+    ///
+    /// Without a `background` the pixels between strokes are left as they
+    /// are. Returns the x just past the text, to continue from.
+    pub fn draw_text(
+        &mut self,
+        x: usize,
+        y: usize,
+        text: &str,
+        foreground: Rgb,
+        background: Option<Rgb>,
+    ) -> usize {
+        let mut left = x;
+        for character in text.chars() {
+            let glyph = font::glyph(character);
+            for (row, bits) in glyph.iter().enumerate() {
+                for column in 0..font::WIDTH {
+                    let color = if bits & (1 << column) != 0 {
+                        foreground
+                    } else if let Some(background) = background {
+                        background
+                    } else {
+                        continue;
+                    };
+                    self.set_pixel(left + column, y + row, color);
+                }
+            }
+            left += font::WIDTH;
+        }
+        left
+    }
+}
+
+/// Perceived brightness, weights from ITU-R BT.601
+fn gray(color: Rgb) -> u8 {
+    let weighted = 77 * u32::from(color.r) + 150 * u32::from(color.g) + 29 * u32::from(color.b);
+    (weighted >> 8) as u8
+}
+
+static FRAMEBUFFER: Mutex<Option<Framebuffer>> = Mutex::new(None);
+
+/// Make `framebuffer` the screen, handing back the previous one
+pub async fn install(framebuffer: Framebuffer) -> Option<Framebuffer> {
+    FRAMEBUFFER.lock().await.replace(framebuffer)
+}
+
+/// Exclusive access to the screen, `None` if no framebuffer is installed.
+///
+/// Other tasks wait for their turn while the guard is held, so draw a whole
+/// frame and then let go.
+pub async fn lock() -> Option<Screen> {
+    let guard = FRAMEBUFFER.lock().await;
+    guard.is_some().then_some(Screen { guard })
+}
+
+/// Guard returned by [`lock`], derefs to the installed [`Framebuffer`]
+pub struct Screen {
+    guard: MutexGuard<'static, Option<Framebuffer>>,
+}
+
+impl Deref for Screen {
+    type Target = Framebuffer;
+
+    fn deref(&self) -> &Framebuffer {
+        self.guard.as_ref().expect("checked in lock()")
+    }
+}
+
+impl DerefMut for Screen {
+    fn deref_mut(&mut self) -> &mut Framebuffer {
+        self.guard.as_mut().expect("checked in lock()")
+    }
+}
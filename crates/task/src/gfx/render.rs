@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use super::Framebuffer;
+use crate::{
+    metrics::{Counter, Gauge},
+    time::Instant,
+    timer,
+};
+
+static FRAMES: Counter = Counter::new("gfx_frames_total", "Frames presented by the render loop");
+static LATE_FRAMES: Counter = Counter::new(
+    "gfx_frames_late_total",
+    "Frames that started after their deadline",
+);
+static FRAME_TIME: Gauge = Gauge::new(
+    "gfx_frame_time_us",
+    "Time to draw and present the last frame",
+);
+
+/// What a draw callback knows about the frame being drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// Counts up from 0
+    pub number: u64,
+    /// Since the previous frame started, for animating at a steady speed
+    pub delta: Duration,
+}
+
+type Draw = Box<dyn FnMut(&mut Framebuffer, Frame) + Send>;
+
+/// Identifies a registered callback, for [`remove_draw`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DrawId(u64);
+
+struct Changes {
+    next_id: u64,
+    added: Vec<(DrawId, Draw)>,
+    removed: Vec<DrawId>,
+}
+
+// The render loop owns the callbacks so none runs under this lock, changes
+// are picked up before the next frame
+static CHANGES: spin::Mutex<Changes> = spin::Mutex::new(Changes {
+    next_id: 0,
+    added: Vec::new(),
+    removed: Vec::new(),
+});
+
+/// Call `draw` every frame, after the callbacks registered before it
+pub fn on_draw(draw: impl FnMut(&mut Framebuffer, Frame) + Send + 'static) -> DrawId {
+    let mut changes = CHANGES.lock();
+    let id = DrawId(changes.next_id);
+    changes.next_id += 1;
+    changes.added.push((id, Box::new(draw)));
+    id
+}
+
+pub fn remove_draw(id: DrawId) {
+    CHANGES.lock().removed.push(id);
+}
+
+/// Draw and present a frame `fps` times a second, spawn it as a task.
+///
+/// Each frame locks the screen, runs the [`on_draw`] callbacks in order and
+/// presents. A frame that can't keep up is skipped rather than queued, so
+/// a slow frame lowers the frame rate instead of adding lag. Frame counts
+/// and times are kept in the `gfx_frame*` metrics.
+pub async fn render_loop(fps: u32) {
+    assert!(fps > 0, "render loop needs a non-zero frame rate");
+    let period = Duration::from_secs(1) / fps;
+    let mut ticks = timer::interval(period);
+    let mut draws: Vec<(DrawId, Draw)> = Vec::new();
+    let mut number = 0;
+    let mut previous: Option<Instant> = None;
+
+    loop {
+        let deadline = ticks.tick().await;
+        let start = Instant::now();
+        if start - deadline > period {
+            LATE_FRAMES.inc();
+        }
+
+        {
+            let mut changes = CHANGES.lock();
+            let Changes { added, removed, .. } = &mut *changes;
+            draws.retain(|(id, _)| !removed.contains(id));
+            removed.clear();
+            draws.append(added);
+        }
+        // Nothing to draw on yet, try again next frame
+        let Some(mut screen) = super::lock().await else {
+            continue;
+        };
+
+        let frame = Frame {
+            number,
+            delta: previous.map_or(Duration::ZERO, |previous| start - previous),
+        };
+        for (_, draw) in &mut draws {
+            draw(&mut screen, frame);
+        }
+        screen.present();
+        drop(screen);
+
+        number += 1;
+        previous = Some(start);
+        FRAMES.inc();
+        FRAME_TIME.set(start.elapsed().as_micros() as i64);
+    }
+}
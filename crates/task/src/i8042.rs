@@ -0,0 +1,263 @@
+//!
+//! i8042 PS/2 controller
+//!
+//! The controller behind the keyboard and mouse on a PC. Port access goes
+//! through [`Ports`] so the same code runs on bare metal and under test.
+//!
+//! Setup talks to the controller directly and synchronously, before
+//! interrupts are enabled. Commands for the devices behind it go through the
+//! async queue in [`command`], since their replies arrive by interrupt.
+//!
+
+use alloc::boxed::Box;
+use core::{
+    fmt,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+mod command;
+
+pub(crate) use command::take_reply;
+pub use command::{CommandError, command, enable_mouse, set_scancode_set};
+
+use crate::keyboard::{self, ScancodeSetKind};
+
+/// Raw access to the controller's I/O ports, `0x60` (data) and `0x64`
+/// (status when read, command when written)
+pub trait Ports: Send {
+    fn read_status(&mut self) -> u8;
+    fn read_data(&mut self) -> u8;
+    fn write_data(&mut self, byte: u8);
+    fn write_command(&mut self, byte: u8);
+}
+
+/// A byte is waiting in the data port
+pub const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+/// The controller hasn't taken the last byte written yet
+pub const STATUS_INPUT_FULL: u8 = 1 << 1;
+/// The waiting byte came from the second (mouse) port
+pub const STATUS_AUX_DATA: u8 = 1 << 5;
+
+// Controller commands
+const READ_CONFIG: u8 = 0x20;
+const WRITE_CONFIG: u8 = 0x60;
+const DISABLE_SECOND: u8 = 0xA7;
+const ENABLE_SECOND: u8 = 0xA8;
+const TEST_SECOND: u8 = 0xA9;
+const SELF_TEST: u8 = 0xAA;
+const TEST_FIRST: u8 = 0xAB;
+const DISABLE_FIRST: u8 = 0xAD;
+const ENABLE_FIRST: u8 = 0xAE;
+const WRITE_SECOND: u8 = 0xD4;
+
+const SELF_TEST_PASSED: u8 = 0x55;
+const PORT_TEST_PASSED: u8 = 0x00;
+
+// Configuration byte
+const CONFIG_FIRST_IRQ: u8 = 1 << 0;
+const CONFIG_SECOND_IRQ: u8 = 1 << 1;
+const CONFIG_SECOND_CLOCK_OFF: u8 = 1 << 5;
+
+/// Status polls before giving up on the controller
+const SPIN_LIMIT: usize = 100_000;
+
+/// The two device ports of the controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Port {
+    /// Usually the keyboard
+    First,
+    /// Usually the mouse
+    Second,
+}
+
+impl Port {
+    fn index(self) -> usize {
+        match self {
+            Port::First => 0,
+            Port::Second => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControllerError {
+    /// [`install`] wasn't called
+    NotInstalled,
+    /// The controller didn't answer
+    Timeout,
+    SelfTestFailed(u8),
+}
+
+impl fmt::Display for ControllerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ControllerError::NotInstalled => f.write_str("no i8042 ports installed"),
+            ControllerError::Timeout => f.write_str("i8042 controller did not respond"),
+            ControllerError::SelfTestFailed(reply) => {
+                write!(f, "i8042 self-test failed with {:#04x}", reply)
+            }
+        }
+    }
+}
+
+/// What [`initialize`] found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControllerInfo {
+    pub config: u8,
+    pub dual_channel: bool,
+    pub first_port_ok: bool,
+    pub second_port_ok: bool,
+}
+
+impl ControllerInfo {
+    /// Scancode set the keyboard's bytes arrive in
+    pub fn scancode_set(&self) -> ScancodeSetKind {
+        ScancodeSetKind::from_controller_config(self.config)
+    }
+}
+
+static PORTS: spin::Mutex<Option<Box<dyn Ports>>> = spin::Mutex::new(None);
+// Configuration byte as last written, read by the scancode set selection
+static CONFIG: AtomicU8 = AtomicU8::new(0);
+
+/// Use `ports` for every controller access from now on
+pub fn install(ports: impl Ports + 'static) {
+    *PORTS.lock() = Some(Box::new(ports));
+}
+
+fn with_ports<T>(f: impl FnOnce(&mut dyn Ports) -> T) -> Result<T, ControllerError> {
+    match PORTS.lock().as_mut() {
+        Some(ports) => Ok(f(&mut **ports)),
+        None => Err(ControllerError::NotInstalled),
+    }
+}
+
+fn wait_writable(ports: &mut dyn Ports) -> Result<(), ControllerError> {
+    for _ in 0..SPIN_LIMIT {
+        if ports.read_status() & STATUS_INPUT_FULL == 0 {
+            return Ok(());
+        }
+    }
+    Err(ControllerError::Timeout)
+}
+
+fn wait_readable(ports: &mut dyn Ports) -> Result<(), ControllerError> {
+    for _ in 0..SPIN_LIMIT {
+        if ports.read_status() & STATUS_OUTPUT_FULL != 0 {
+            return Ok(());
+        }
+    }
+    Err(ControllerError::Timeout)
+}
+
+fn send(ports: &mut dyn Ports, command: u8) -> Result<(), ControllerError> {
+    wait_writable(ports)?;
+    ports.write_command(command);
+    Ok(())
+}
+
+fn send_with_data(ports: &mut dyn Ports, command: u8, data: u8) -> Result<(), ControllerError> {
+    send(ports, command)?;
+    wait_writable(ports)?;
+    ports.write_data(data);
+    Ok(())
+}
+
+fn query(ports: &mut dyn Ports, command: u8) -> Result<u8, ControllerError> {
+    send(ports, command)?;
+    wait_readable(ports)?;
+    Ok(ports.read_data())
+}
+
+fn write_config(ports: &mut dyn Ports, config: u8) -> Result<(), ControllerError> {
+    send_with_data(ports, WRITE_CONFIG, config)?;
+    CONFIG.store(config, Ordering::Release);
+    Ok(())
+}
+
+/// Reset the controller into a known state: both ports tested, working ones
+/// enabled with their interrupts on. The keyboard decodes the scancode set
+/// the controller's translation leaves it with.
+///
+/// Call once during boot with interrupts still disabled, the replies are
+/// read by polling.
+pub fn initialize() -> Result<ControllerInfo, ControllerError> {
+    with_ports(|ports| {
+        // Quiet both devices and drop whatever they sent meanwhile
+        send(ports, DISABLE_FIRST)?;
+        send(ports, DISABLE_SECOND)?;
+        for _ in 0..SPIN_LIMIT {
+            if ports.read_status() & STATUS_OUTPUT_FULL == 0 {
+                break;
+            }
+            ports.read_data();
+        }
+
+        let mut config = query(ports, READ_CONFIG)?;
+        config &= !(CONFIG_FIRST_IRQ | CONFIG_SECOND_IRQ);
+        write_config(ports, config)?;
+
+        let reply = query(ports, SELF_TEST)?;
+        if reply != SELF_TEST_PASSED {
+            return Err(ControllerError::SelfTestFailed(reply));
+        }
+        // Some controllers reset themselves during the self-test
+        write_config(ports, config)?;
+
+        // The second clock only turns on if there is a second port
+        send(ports, ENABLE_SECOND)?;
+        let dual_channel = query(ports, READ_CONFIG)? & CONFIG_SECOND_CLOCK_OFF == 0;
+        send(ports, DISABLE_SECOND)?;
+
+        let first_port_ok = query(ports, TEST_FIRST)? == PORT_TEST_PASSED;
+        let second_port_ok = dual_channel && query(ports, TEST_SECOND)? == PORT_TEST_PASSED;
+
+        if first_port_ok {
+            send(ports, ENABLE_FIRST)?;
+            config |= CONFIG_FIRST_IRQ;
+        }
+        if second_port_ok {
+            send(ports, ENABLE_SECOND)?;
+            config |= CONFIG_SECOND_IRQ;
+        }
+        write_config(ports, config)?;
+        keyboard::set_scancode_set(ScancodeSetKind::from_controller_config(config));
+
+        Ok(ControllerInfo {
+            config,
+            dual_channel,
+            first_port_ok,
+            second_port_ok,
+        })
+    })?
+}
+
+pub fn enable_port(port: Port) -> Result<(), ControllerError> {
+    let command = match port {
+        Port::First => ENABLE_FIRST,
+        Port::Second => ENABLE_SECOND,
+    };
+    with_ports(|ports| send(ports, command))?
+}
+
+/// Stops the device's clock, it can't send anything until enabled again
+pub fn disable_port(port: Port) -> Result<(), ControllerError> {
+    let command = match port {
+        Port::First => DISABLE_FIRST,
+        Port::Second => DISABLE_SECOND,
+    };
+    with_ports(|ports| send(ports, command))?
+}
+
+/// Write a byte to the device on `port`
+fn write_device(port: Port, byte: u8) -> Result<(), ControllerError> {
+    with_ports(|ports| match port {
+        Port::First => {
+            wait_writable(ports)?;
+            ports.write_data(byte);
+            Ok(())
+        }
+        // Without the prefix the byte would go to the first port
+        Port::Second => send_with_data(ports, WRITE_SECOND, byte),
+    })?
+}
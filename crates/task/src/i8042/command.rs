@@ -0,0 +1,218 @@
+use alloc::vec::Vec;
+use core::{
+    fmt,
+    future::poll_fn,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::Poll,
+    time::Duration,
+};
+
+use crossbeam_queue::ArrayQueue;
+use futures_util::task::AtomicWaker;
+
+use super::{CONFIG, ControllerError, Port, write_device};
+use crate::{
+    keyboard::{self, ScancodeSetKind},
+    sync::Mutex,
+    timer,
+};
+
+const ACK: u8 = 0xFA;
+const RESEND: u8 = 0xFE;
+
+// Device commands
+const SET_SCANCODE_SET: u8 = 0xF0;
+const ENABLE_REPORTING: u8 = 0xF4;
+const SET_DEFAULTS: u8 = 0xF6;
+
+/// Tries per byte before giving up on a device asking for resends
+const ATTEMPTS: usize = 3;
+/// Devices answer within a few milliseconds
+const REPLY_TIMEOUT: Duration = Duration::from_millis(50);
+/// Longest reply we expect, identify sends two bytes
+const MAX_REPLY: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandError {
+    Controller(ControllerError),
+    /// The device didn't answer in time
+    Timeout,
+    /// The device kept asking for the byte to be sent again
+    Resend,
+    /// Neither an ACK nor a resend request
+    Unexpected(u8),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::Controller(err) => err.fmt(f),
+            CommandError::Timeout => f.write_str("device did not respond"),
+            CommandError::Resend => f.write_str("device kept requesting resend"),
+            CommandError::Unexpected(reply) => write!(f, "unexpected reply {:#04x}", reply),
+        }
+    }
+}
+
+impl From<ControllerError> for CommandError {
+    fn from(err: ControllerError) -> Self {
+        CommandError::Controller(err)
+    }
+}
+
+/// Replies of one device, filled from its interrupt without taking a lock
+struct Replies {
+    // One command at a time, their bytes and replies must not interleave
+    commands: Mutex<()>,
+    // Bytes sent and not acknowledged yet, then reply bytes still to come.
+    // Only those are taken, anything else the device sends is input.
+    unacked: AtomicUsize,
+    unanswered: AtomicUsize,
+    bytes: spin::Once<ArrayQueue<u8>>,
+    // The task running the command
+    ready: AtomicWaker,
+}
+
+impl Replies {
+    const fn new() -> Self {
+        Replies {
+            commands: Mutex::new(()),
+            unacked: AtomicUsize::new(0),
+            unanswered: AtomicUsize::new(0),
+            bytes: spin::Once::new(),
+            ready: AtomicWaker::new(),
+        }
+    }
+
+    /// Made by [`command`] before any byte is expected, so the interrupt
+    /// handler never allocates it
+    fn queue(&self) -> &ArrayQueue<u8> {
+        self.bytes.call_once(|| ArrayQueue::new(MAX_REPLY))
+    }
+
+    /// Whether `byte` belongs to the running command. The last ACK moves on
+    /// to the reply here, so reply bytes right after it aren't taken for input.
+    fn expects(&self, byte: u8) -> bool {
+        let take = |count: &AtomicUsize| {
+            count
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |left| {
+                    left.checked_sub(1)
+                })
+                .is_ok()
+        };
+        if self.unacked.load(Ordering::Acquire) > 0 {
+            match byte {
+                ACK => take(&self.unacked),
+                RESEND => true,
+                _ => false,
+            }
+        } else {
+            take(&self.unanswered)
+        }
+    }
+
+    async fn next(&self) -> u8 {
+        poll_fn(|cx| {
+            if let Some(byte) = self.queue().pop() {
+                return Poll::Ready(byte);
+            }
+            self.ready.register(cx.waker());
+            // Pushed before the waker was in place, that woke nobody
+            self.queue().pop().map_or(Poll::Pending, Poll::Ready)
+        })
+        .await
+    }
+}
+
+static REPLIES: [Replies; 2] = [Replies::new(), Replies::new()];
+
+/// Send a command and its arguments to the device on `port`, then collect
+/// `reply_len` bytes it answers with.
+///
+/// Every byte sent must be acknowledged. While sending only ACKs and resend
+/// requests count as replies, after that only the `reply_len` bytes
+/// expected; the rest goes on to the keyboard or mouse. Commands to the same device are
+/// queued and run one after the other.
+pub async fn command(port: Port, bytes: &[u8], reply_len: usize) -> Result<Vec<u8>, CommandError> {
+    let replies = &REPLIES[port.index()];
+    let _command = replies.commands.lock().await;
+
+    while replies.queue().pop().is_some() {}
+    replies.unanswered.store(reply_len, Ordering::Release);
+    replies.unacked.store(bytes.len(), Ordering::Release);
+    let result = exchange(port, replies, bytes, reply_len).await;
+    replies.unacked.store(0, Ordering::Release);
+    replies.unanswered.store(0, Ordering::Release);
+    result
+}
+
+async fn exchange(
+    port: Port,
+    replies: &Replies,
+    bytes: &[u8],
+    reply_len: usize,
+) -> Result<Vec<u8>, CommandError> {
+    for &byte in bytes {
+        send_byte(port, replies, byte).await?;
+    }
+    let mut reply = Vec::with_capacity(reply_len);
+    for _ in 0..reply_len {
+        match timer::timeout(REPLY_TIMEOUT, replies.next()).await {
+            Ok(byte) => reply.push(byte),
+            Err(_) => return Err(CommandError::Timeout),
+        }
+    }
+    Ok(reply)
+}
+
+async fn send_byte(port: Port, replies: &Replies, byte: u8) -> Result<(), CommandError> {
+    for _ in 0..ATTEMPTS {
+        write_device(port, byte)?;
+        match timer::timeout(REPLY_TIMEOUT, replies.next()).await {
+            Ok(ACK) => return Ok(()),
+            Ok(RESEND) => continue,
+            Ok(other) => return Err(CommandError::Unexpected(other)),
+            Err(_) => return Err(CommandError::Timeout),
+        }
+    }
+    Err(CommandError::Resend)
+}
+
+/// Called with every byte received from `port`, returns `true` if it was a
+/// reply to a command rather than input
+pub(crate) fn take_reply(port: Port, byte: u8) -> bool {
+    let replies = &REPLIES[port.index()];
+    if !replies.expects(byte) {
+        return false;
+    }
+    // A full queue means a confused device, the command will time out
+    let _ = replies.queue().push(byte);
+    replies.ready.wake();
+    true
+}
+
+/// Switch the keyboard to `set` and decode accordingly.
+///
+/// With translation on in the controller the bytes still arrive in set 1,
+/// the decoder is only switched when they don't.
+pub async fn set_scancode_set(set: ScancodeSetKind) -> Result<(), CommandError> {
+    let number = match set {
+        ScancodeSetKind::Set1 => 1,
+        ScancodeSetKind::Set2 => 2,
+    };
+    command(Port::First, &[SET_SCANCODE_SET, number], 0).await?;
+
+    let translated = ScancodeSetKind::from_controller_config(CONFIG.load(Ordering::Acquire));
+    keyboard::set_scancode_set(match translated {
+        ScancodeSetKind::Set1 => ScancodeSetKind::Set1,
+        ScancodeSetKind::Set2 => set,
+    });
+    Ok(())
+}
+
+/// Reset the mouse to its defaults and have it start sending packets
+pub async fn enable_mouse() -> Result<(), CommandError> {
+    command(Port::Second, &[SET_DEFAULTS], 0).await?;
+    command(Port::Second, &[ENABLE_REPORTING], 0).await?;
+    Ok(())
+}
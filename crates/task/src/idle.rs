@@ -0,0 +1,297 @@
+//!
+//! Idle strategy
+//!
+//! With nothing ready the executors call [`wait`], which goes deeper the
+//! longer nothing happens. Right after a wake it spins, checking for work,
+//! so a task woken a moment later runs without a round trip through an
+//! interrupt. Then it halts until the next interrupt, and once idle for
+//! [`Policy::halt`] it sleeps deeper:
+//!
+//! - with `bare-metal` every IRQ line but the [`WakeSource`]s is masked,
+//!   and where the CPU has `monitor`/`mwait` it waits in those, asking for
+//!   a deeper C-state. IRQs on masked lines are held by the PICs and come
+//!   in when the sleep ends.
+//! - on the std build the thread parks, for at most [`NAP`].
+//!
+//! Every wake of a task [`kick`]s, which ends the streak and wakes a sleep
+//! from another core or thread. `mwait` sees a kick at once, `hlt` only at
+//! the next interrupt, the timer's at the latest.
+//!
+//! Time spent in each state is counted, see [`time_spent`] and the
+//! `idle_*_microseconds_total` metrics.
+//!
+
+use alloc::vec::Vec;
+use core::{
+    sync::atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::{metrics::Counter, time};
+
+/// Longest park of a deep sleep on the std build, for the timers
+#[cfg(feature = "std")]
+pub const NAP: Duration = Duration::from_millis(1);
+
+/// How deep idling may go
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Depth {
+    /// Spin only, for the lowest latency
+    Spin,
+    /// Spin, then halt until the next interrupt
+    Halt,
+    /// Spin, halt, then sleep with only the wake sources on
+    Deep,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Policy {
+    /// Checks for work right after a wake before halting
+    pub spins: u32,
+    /// Idle time spent halting before sleeping deeper
+    pub halt: Duration,
+    pub deepest: Depth,
+}
+
+impl Policy {
+    pub const DEFAULT: Policy = Policy {
+        spins: 1_000,
+        halt: Duration::from_millis(50),
+        deepest: Depth::Deep,
+    };
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy::DEFAULT
+    }
+}
+
+/// What ends a deep sleep
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WakeSource {
+    /// IRQ 0, always on: the executor fires the timers
+    Timer,
+    /// IRQ 1
+    Keyboard,
+    /// Another IRQ line, 2 to 15
+    Irq(u8),
+    /// A [`kick`], from a wake on another core or thread. Always on.
+    Kick,
+}
+
+impl WakeSource {
+    fn line(self) -> Option<u8> {
+        match self {
+            WakeSource::Timer => Some(TIMER_LINE),
+            WakeSource::Keyboard => Some(KEYBOARD_LINE),
+            WakeSource::Irq(line) => Some(line),
+            WakeSource::Kick => None,
+        }
+    }
+}
+
+const TIMER_LINE: u8 = 0;
+const KEYBOARD_LINE: u8 = 1;
+const LINES: u8 = 16;
+
+static POLICY: spin::Mutex<Policy> = spin::Mutex::new(Policy::DEFAULT);
+// IRQ lines that are wake sources, a bit each
+static WAKE_LINES: AtomicU16 = AtomicU16::new(1 << TIMER_LINE | 1 << KEYBOARD_LINE);
+// A task was woken since the last `wait`. Watched by `mwait`.
+static KICKED: AtomicBool = AtomicBool::new(false);
+// Uptime in nanoseconds when the executor last went idle, `NOT_IDLE`
+// before the first time
+static IDLE_SINCE: AtomicU64 = AtomicU64::new(NOT_IDLE);
+const NOT_IDLE: u64 = u64::MAX;
+
+static SPIN_TIME: Counter = Counter::new(
+    "idle_spin_microseconds_total",
+    "Time spent spinning for work",
+);
+static HALT_TIME: Counter = Counter::new(
+    "idle_halt_microseconds_total",
+    "Time spent halted until the next interrupt",
+);
+static DEEP_TIME: Counter = Counter::new(
+    "idle_deep_microseconds_total",
+    "Time spent in deep sleep, woken by the wake sources only",
+);
+
+pub fn set_policy(policy: Policy) {
+    *POLICY.lock() = policy;
+}
+
+pub fn policy() -> Policy {
+    *POLICY.lock()
+}
+
+/// Let `source` end deep sleeps, `false` for a line past 15
+pub fn register(source: WakeSource) -> bool {
+    match source.line() {
+        Some(line) if line < LINES => {
+            WAKE_LINES.fetch_or(1 << line, Ordering::AcqRel);
+            true
+        }
+        Some(_) => false,
+        None => true,
+    }
+}
+
+/// Stop `source` from ending deep sleeps, `false` for the timer and kicks,
+/// which always do
+pub fn unregister(source: WakeSource) -> bool {
+    match source.line() {
+        Some(line) if line < LINES && line != TIMER_LINE => {
+            WAKE_LINES.fetch_and(!(1 << line), Ordering::AcqRel);
+            true
+        }
+        _ => false,
+    }
+}
+
+pub fn wake_sources() -> Vec<WakeSource> {
+    let lines = WAKE_LINES.load(Ordering::Acquire);
+    let mut sources: Vec<WakeSource> = (0..LINES)
+        .filter(|line| lines & 1 << line != 0)
+        .map(|line| match line {
+            TIMER_LINE => WakeSource::Timer,
+            KEYBOARD_LINE => WakeSource::Keyboard,
+            line => WakeSource::Irq(line),
+        })
+        .collect();
+    sources.push(WakeSource::Kick);
+    sources
+}
+
+/// There's work: end the idle streak and wake a sleeping executor. Doesn't
+/// lock on bare metal, so interrupt handlers can kick.
+pub fn kick() {
+    KICKED.store(true, Ordering::Release);
+    #[cfg(all(
+        feature = "std",
+        not(all(feature = "bare-metal", target_arch = "x86_64"))
+    ))]
+    if let Some(sleeper) = SLEEPER.lock().as_ref() {
+        sleeper.unpark();
+    }
+}
+
+/// Idle once, unless `busy` says there's work. Called in a loop by the
+/// executors, between firing timers and running what they woke.
+pub fn wait(busy: impl Fn() -> bool) {
+    let policy = policy();
+    let now = time::uptime();
+    let kicked = KICKED.swap(false, Ordering::AcqRel);
+    let since = IDLE_SINCE.load(Ordering::Acquire);
+    let fresh = kicked || since == NOT_IDLE;
+    let since = if fresh {
+        IDLE_SINCE.store(now.as_nanos() as u64, Ordering::Release);
+        now
+    } else {
+        Duration::from_nanos(since)
+    };
+
+    if fresh || policy.deepest == Depth::Spin {
+        let mut woken = false;
+        for _ in 0..policy.spins {
+            if busy() || KICKED.load(Ordering::Acquire) {
+                woken = true;
+                break;
+            }
+            core::hint::spin_loop();
+        }
+        SPIN_TIME.add(micros_since(now));
+        if woken || policy.deepest == Depth::Spin {
+            return;
+        }
+    }
+
+    let started = time::uptime();
+    if policy.deepest == Depth::Halt || now.saturating_sub(since) < policy.halt {
+        halt(&busy);
+        HALT_TIME.add(micros_since(started));
+    } else {
+        deep(&busy);
+        DEEP_TIME.add(micros_since(started));
+    }
+}
+
+/// Idle time so far in each state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IdleTime {
+    pub spin: Duration,
+    pub halt: Duration,
+    pub deep: Duration,
+}
+
+impl IdleTime {
+    pub fn total(&self) -> Duration {
+        self.spin + self.halt + self.deep
+    }
+}
+
+pub fn time_spent() -> IdleTime {
+    IdleTime {
+        spin: Duration::from_micros(SPIN_TIME.get()),
+        halt: Duration::from_micros(HALT_TIME.get()),
+        deep: Duration::from_micros(DEEP_TIME.get()),
+    }
+}
+
+fn micros_since(started: Duration) -> u64 {
+    time::uptime().saturating_sub(started).as_micros() as u64
+}
+
+#[cfg(all(feature = "bare-metal", target_arch = "x86_64"))]
+fn halt(busy: &dyn Fn() -> bool) {
+    crate::pc::halt_unless(busy);
+}
+
+#[cfg(all(feature = "bare-metal", target_arch = "x86_64"))]
+fn deep(busy: &dyn Fn() -> bool) {
+    let lines = WAKE_LINES.load(Ordering::Acquire);
+    crate::pc::sleep_unless(lines, &KICKED, || busy() || KICKED.load(Ordering::Acquire));
+}
+
+// The thread in a deep sleep, for `kick` to unpark
+#[cfg(all(
+    feature = "std",
+    not(all(feature = "bare-metal", target_arch = "x86_64"))
+))]
+static SLEEPER: spin::Mutex<Option<std::thread::Thread>> = spin::Mutex::new(None);
+
+#[cfg(all(
+    feature = "std",
+    not(all(feature = "bare-metal", target_arch = "x86_64"))
+))]
+fn halt(busy: &dyn Fn() -> bool) {
+    if !busy() {
+        std::thread::yield_now();
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    not(all(feature = "bare-metal", target_arch = "x86_64"))
+))]
+fn deep(busy: &dyn Fn() -> bool) {
+    // Registered first, so a kick after the check still unparks
+    *SLEEPER.lock() = Some(std::thread::current());
+    if !busy() && !KICKED.load(Ordering::Acquire) {
+        std::thread::park_timeout(NAP);
+    }
+    *SLEEPER.lock() = None;
+}
+
+#[cfg(not(any(feature = "std", all(feature = "bare-metal", target_arch = "x86_64"))))]
+fn halt(busy: &dyn Fn() -> bool) {
+    if !busy() {
+        core::hint::spin_loop();
+    }
+}
+
+#[cfg(not(any(feature = "std", all(feature = "bare-metal", target_arch = "x86_64"))))]
+fn deep(busy: &dyn Fn() -> bool) {
+    halt(busy);
+}
@@ -0,0 +1,115 @@
+//!
+//! Input multiplexer
+//!
+//! Merges every input source into one stream, so an application task can
+//! wait for "whatever the user does next" with a single `next().await`.
+//!
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::{Stream, StreamExt};
+use pc_keyboard::KeyEvent;
+
+use crate::{
+    keyboard::KeyEventStream,
+    mouse::{MouseEvent, MouseStream},
+    time::Instant,
+};
+
+mod record;
+
+pub(crate) use record::capture;
+pub use record::{
+    DecodeError, Device, RecordedByte, Recording, is_recording, replay, start_recording,
+    stop_recording,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    Key(KeyEvent),
+    Mouse(MouseEvent),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputEvent {
+    /// When the multiplexer received the event, not when the interrupt fired
+    pub timestamp: Instant,
+    pub input: Input,
+}
+
+type Source = Pin<Box<dyn Stream<Item = Input>>>;
+
+/// One stream of timestamped events from several input sources.
+///
+/// Sources are polled round-robin so a chatty mouse can't starve the
+/// keyboard. The stream ends once every source has ended.
+pub struct InputStream {
+    sources: Vec<Source>,
+    // Source polled first next time
+    next: usize,
+}
+
+impl InputStream {
+    /// Keyboard events from [`keyboard::dispatch`](crate::keyboard::dispatch)
+    /// and the mouse.
+    ///
+    /// This creates a [`MouseStream`], don't keep another one around or the
+    /// two will split the mouse bytes between them.
+    pub fn new() -> Self {
+        let mut stream = InputStream::empty();
+        stream.add_source(KeyEventStream::new().map(Input::Key));
+        stream.add_source(MouseStream::new().map(Input::Mouse));
+        stream
+    }
+
+    pub fn empty() -> Self {
+        InputStream {
+            sources: Vec::new(),
+            next: 0,
+        }
+    }
+
+    pub fn add_source(&mut self, source: impl Stream<Item = Input> + 'static) {
+        self.sources.push(Box::pin(source));
+    }
+}
+
+impl Default for InputStream {
+    fn default() -> Self {
+        InputStream::new()
+    }
+}
+
+impl Stream for InputStream {
+    type Item = InputEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<InputEvent>> {
+        let mut polled = 0;
+        while polled < self.sources.len() {
+            let index = (self.next + polled) % self.sources.len();
+            match self.sources[index].poll_next_unpin(cx) {
+                Poll::Ready(Some(input)) => {
+                    self.next = index + 1;
+                    return Poll::Ready(Some(InputEvent {
+                        timestamp: Instant::now(),
+                        input,
+                    }));
+                }
+                Poll::Ready(None) => {
+                    // The next source moves into this slot, poll it too
+                    drop(self.sources.swap_remove(index));
+                }
+                Poll::Pending => polled += 1,
+            }
+        }
+        if self.sources.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
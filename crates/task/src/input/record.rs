@@ -0,0 +1,150 @@
+use alloc::vec::Vec;
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use crossbeam_queue::ArrayQueue;
+
+use crate::{keyboard, mouse, time::Instant, timer};
+
+/// Bytes kept per recording, older ones are dropped once it is full
+const CAPACITY: usize = 4096;
+
+/// Which device a recorded byte came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Device {
+    Keyboard,
+    Mouse,
+}
+
+/// A raw input byte and when it arrived, relative to the recording start
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordedByte {
+    pub at: Duration,
+    pub device: Device,
+    pub byte: u8,
+}
+
+/// Input captured between [`start_recording`] and [`stop_recording`].
+///
+/// Raw bytes are recorded rather than decoded events, so a replay goes
+/// through decoding, hotkeys and every subscriber just like real input.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Recording {
+    pub bytes: Vec<RecordedByte>,
+}
+
+static RECORDING: AtomicBool = AtomicBool::new(false);
+static STARTED: spin::Mutex<Option<Instant>> = spin::Mutex::new(None);
+// Filled from interrupt handlers, so lock-free
+static CAPTURED: spin::Once<ArrayQueue<(Instant, Device, u8)>> = spin::Once::new();
+
+pub fn start_recording() {
+    let captured = CAPTURED.call_once(|| ArrayQueue::new(CAPACITY));
+    while captured.pop().is_some() {}
+    *STARTED.lock() = Some(Instant::now());
+    RECORDING.store(true, Ordering::Release);
+}
+
+pub fn is_recording() -> bool {
+    RECORDING.load(Ordering::Acquire)
+}
+
+/// Empty if no recording was started
+pub fn stop_recording() -> Recording {
+    RECORDING.store(false, Ordering::Release);
+    let Some(started) = STARTED.lock().take() else {
+        return Recording::default();
+    };
+    let mut recording = Recording::default();
+    if let Some(captured) = CAPTURED.get() {
+        while let Some((at, device, byte)) = captured.pop() {
+            recording.bytes.push(RecordedByte {
+                at: at.duration_since(started),
+                device,
+                byte,
+            });
+        }
+    }
+    recording
+}
+
+/// Called where raw input enters the pipeline
+pub(crate) fn capture(device: Device, byte: u8) {
+    if !is_recording() {
+        return;
+    }
+    if let Some(captured) = CAPTURED.get() {
+        captured.force_push((Instant::now(), device, byte));
+    }
+}
+
+/// Feed a recording back in with its original timing.
+///
+/// Bytes enter where the interrupt handlers deliver them, so the keyboard
+/// [`dispatch`](crate::keyboard::dispatch) task and any mouse stream need to
+/// be running.
+pub async fn replay(recording: &Recording) {
+    let started = Instant::now();
+    for recorded in &recording.bytes {
+        timer::sleep_until(started + recorded.at).await;
+        match recorded.device {
+            Device::Keyboard => keyboard::add_scancode(recorded.byte),
+            Device::Mouse => mouse::add_mouse_byte(recorded.byte),
+        }
+    }
+}
+
+/// Returned by [`Recording::from_bytes`] for malformed data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError;
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("malformed input recording")
+    }
+}
+
+/// Microsecond offset, device and byte
+const ENTRY_LEN: usize = 10;
+
+impl Recording {
+    /// Compact binary form for saving to a file
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.bytes.len() * ENTRY_LEN);
+        for recorded in &self.bytes {
+            out.extend_from_slice(&(recorded.at.as_micros() as u64).to_le_bytes());
+            out.push(match recorded.device {
+                Device::Keyboard => 0,
+                Device::Mouse => 1,
+            });
+            out.push(recorded.byte);
+        }
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Recording, DecodeError> {
+        if !data.len().is_multiple_of(ENTRY_LEN) {
+            return Err(DecodeError);
+        }
+        let bytes = data
+            .chunks_exact(ENTRY_LEN)
+            .map(|entry| {
+                let micros = u64::from_le_bytes(entry[..8].try_into().unwrap());
+                let device = match entry[8] {
+                    0 => Device::Keyboard,
+                    1 => Device::Mouse,
+                    _ => return Err(DecodeError),
+                };
+                Ok(RecordedByte {
+                    at: Duration::from_micros(micros),
+                    device,
+                    byte: entry[9],
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Recording { bytes })
+    }
+}
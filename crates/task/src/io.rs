@@ -0,0 +1,183 @@
+//!
+//! Async byte IO
+//!
+//! [`AsyncRead`] and [`AsyncWrite`] are what a serial port, a pipe and a
+//! [`Tty`](crate::tty::Tty) have in common, so code moving bytes around can
+//! be written once for all of them. They're poll based like [`Stream`], the
+//! [`AsyncReadExt`] and [`AsyncWriteExt`] methods turn them into futures:
+//!
+//! ```ignore
+//! let mut buf = [0; 64];
+//! let count = reader.read(&mut buf).await?;
+//! writer.write_all(&buf[..count]).await?;
+//! ```
+//!
+//! [`copy`] pumps a reader into a writer, the core of `cat` and friends.
+//! The [`codec`]s split a byte stream into messages.
+//!
+//! Wrap either in a [`BufReader`] or [`BufWriter`] when doing many small
+//! reads or writes, a UART costs an interrupt round trip per call.
+//!
+//! [`Stream`]: futures_util::Stream
+//!
+
+use std::{
+    fmt,
+    ops::DerefMut,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+mod buf;
+pub mod codec;
+mod ext;
+
+pub use buf::{BufReader, BufWriter, DEFAULT_BUF_SIZE};
+pub use ext::{AsyncReadExt, AsyncWriteExt, Flush, Read, ReadExact, Write, WriteAll};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The other end is gone, nothing written arrives anywhere
+    BrokenPipe,
+    /// Input ended before as many bytes as needed came
+    UnexpectedEof,
+    /// A write took none of the bytes
+    WriteZero,
+    /// Bytes that should have been text weren't UTF-8
+    InvalidData,
+    /// A frame over a codec's size limit
+    FrameTooLong,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::BrokenPipe => f.write_str("broken pipe"),
+            Error::UnexpectedEof => f.write_str("unexpected end of input"),
+            Error::WriteZero => f.write_str("write took no bytes"),
+            Error::InvalidData => f.write_str("invalid UTF-8"),
+            Error::FrameTooLong => f.write_str("frame too long"),
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Move everything from `reader` to `writer` until the input ends, then
+/// flush `writer`. Returns the number of bytes copied.
+pub async fn copy<R, W>(reader: &mut R, writer: &mut W) -> Result<u64>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let mut buf = [0; DEFAULT_BUF_SIZE];
+    let mut copied = 0;
+    loop {
+        let count = reader.read(&mut buf).await?;
+        if count == 0 {
+            break;
+        }
+        writer.write_all(&buf[..count]).await?;
+        copied += count as u64;
+    }
+    writer.flush().await?;
+    Ok(copied)
+}
+
+/// Source of bytes
+pub trait AsyncRead {
+    /// Move available bytes into `buf`, or register to be woken when there
+    /// are some. `Ok(0)` is the end of input, or an empty `buf`.
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<Result<usize>>;
+}
+
+/// Sink for bytes
+pub trait AsyncWrite {
+    /// Take as many of `bytes` as possible right now, or register to be
+    /// woken when there's room
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, bytes: &[u8]) -> Poll<Result<usize>>;
+
+    /// Push out anything buffered
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    /// Flush and tell the other end no more is coming
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl<T: AsyncRead + Unpin + ?Sized> AsyncRead for &mut T {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize>> {
+        Pin::new(&mut **self).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncRead + Unpin + ?Sized> AsyncRead for Box<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<Result<usize>> {
+        Pin::new(&mut **self).poll_read(cx, buf)
+    }
+}
+
+impl<P> AsyncRead for Pin<P>
+where
+    P: DerefMut<Target: AsyncRead> + Unpin,
+{
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<Result<usize>> {
+        self.get_mut().as_mut().poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin + ?Sized> AsyncWrite for &mut T {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context, bytes: &[u8]) -> Poll<Result<usize>> {
+        Pin::new(&mut **self).poll_write(cx, bytes)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        Pin::new(&mut **self).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        Pin::new(&mut **self).poll_close(cx)
+    }
+}
+
+impl<T: AsyncWrite + Unpin + ?Sized> AsyncWrite for Box<T> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context, bytes: &[u8]) -> Poll<Result<usize>> {
+        Pin::new(&mut **self).poll_write(cx, bytes)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        Pin::new(&mut **self).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        Pin::new(&mut **self).poll_close(cx)
+    }
+}
+
+impl<P> AsyncWrite for Pin<P>
+where
+    P: DerefMut<Target: AsyncWrite> + Unpin,
+{
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, bytes: &[u8]) -> Poll<Result<usize>> {
+        self.get_mut().as_mut().poll_write(cx, bytes)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        self.get_mut().as_mut().poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        self.get_mut().as_mut().poll_close(cx)
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    future::poll_fn,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::ready;
+
+use super::{AsyncRead, AsyncWrite, Error, Result};
+
+/// Buffer size of [`BufReader::new`] and [`BufWriter::new`]
+pub const DEFAULT_BUF_SIZE: usize = 1024;
+
+/// Reads from `R` a buffer at a time, so many small reads cost one read of
+/// the source. Adds [`BufReader::read_until`] and [`BufReader::read_line`].
+pub struct BufReader<R> {
+    inner: R,
+    buf: Box<[u8]>,
+    // `buf[pos..filled]` is read but not handed out
+    pos: usize,
+    filled: usize,
+}
+
+impl<R: AsyncRead + Unpin> BufReader<R> {
+    pub fn new(inner: R) -> Self {
+        BufReader::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    pub fn with_capacity(capacity: usize, inner: R) -> Self {
+        BufReader {
+            inner,
+            buf: vec![0; capacity].into_boxed_slice(),
+            pos: 0,
+            filled: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Reading from the source directly skips what's buffered
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Drops what's buffered
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Read but not handed out yet
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.pos..self.filled]
+    }
+
+    /// Mark `count` bytes of [`BufReader::buffer`] as used
+    pub fn consume(&mut self, count: usize) {
+        self.pos = (self.pos + count).min(self.filled);
+    }
+
+    /// Refill the buffer if it's empty, an empty buffer afterwards is the
+    /// end of input
+    pub fn poll_fill_buf(&mut self, cx: &mut Context) -> Poll<Result<&[u8]>> {
+        if self.pos == self.filled {
+            let count = ready!(Pin::new(&mut self.inner).poll_read(cx, &mut self.buf))?;
+            self.pos = 0;
+            self.filled = count;
+        }
+        Poll::Ready(Ok(self.buffer()))
+    }
+
+    /// Read up to and including `delimiter` into `buf`, or to the end of
+    /// input. Returns the number of bytes read, `0` at the end of input.
+    pub async fn read_until(&mut self, delimiter: u8, buf: &mut Vec<u8>) -> Result<usize> {
+        let mut read = 0;
+        loop {
+            let available = poll_fn(|cx| self.poll_fill_buf(cx).map_ok(<[u8]>::len)).await?;
+            if available == 0 {
+                return Ok(read);
+            }
+            let chunk = self.buffer();
+            let (used, done) = match chunk.iter().position(|&byte| byte == delimiter) {
+                Some(at) => (at + 1, true),
+                None => (chunk.len(), false),
+            };
+            buf.extend_from_slice(&chunk[..used]);
+            self.consume(used);
+            read += used;
+            if done {
+                return Ok(read);
+            }
+        }
+    }
+
+    /// Append the next line, newline included, to `line`. Returns the
+    /// number of bytes read, `0` at the end of input.
+    ///
+    /// [`Error::InvalidData`] if the line isn't UTF-8, `line` is left as it
+    /// was then.
+    pub async fn read_line(&mut self, line: &mut String) -> Result<usize> {
+        let mut bytes = Vec::new();
+        let count = self.read_until(b'\n', &mut bytes).await?;
+        let text = String::from_utf8(bytes).map_err(|_| Error::InvalidData)?;
+        line.push_str(&text);
+        Ok(count)
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for BufReader<R> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+        // Nothing gained from copying through the buffer
+        if this.pos == this.filled && buf.len() >= this.buf.len() {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+        let available = ready!(this.poll_fill_buf(cx))?;
+        let count = available.len().min(buf.len());
+        buf[..count].copy_from_slice(&available[..count]);
+        this.consume(count);
+        Poll::Ready(Ok(count))
+    }
+}
+
+/// Collects writes to `W` and passes them on a buffer at a time.
+///
+/// Bytes still buffered when it's dropped are lost, flush first.
+pub struct BufWriter<W> {
+    inner: W,
+    buf: Vec<u8>,
+    capacity: usize,
+    // `buf[..written]` already went to `inner`
+    written: usize,
+}
+
+impl<W: AsyncWrite + Unpin> BufWriter<W> {
+    pub fn new(inner: W) -> Self {
+        BufWriter::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    pub fn with_capacity(capacity: usize, inner: W) -> Self {
+        BufWriter {
+            inner,
+            buf: Vec::with_capacity(capacity),
+            capacity,
+            written: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Writing to the target directly goes ahead of what's buffered
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Written but not passed on yet
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.written..]
+    }
+
+    /// Pass the buffer on, without flushing the target
+    fn poll_write_buf(&mut self, cx: &mut Context) -> Poll<Result<()>> {
+        while self.written < self.buf.len() {
+            let bytes = &self.buf[self.written..];
+            let count = ready!(Pin::new(&mut self.inner).poll_write(cx, bytes))?;
+            if count == 0 {
+                return Poll::Ready(Err(Error::WriteZero));
+            }
+            self.written += count;
+        }
+        self.buf.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for BufWriter<W> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, bytes: &[u8]) -> Poll<Result<usize>> {
+        let this = self.get_mut();
+        if this.buf.len() + bytes.len() > this.capacity {
+            ready!(this.poll_write_buf(cx))?;
+        }
+        // Too big to be worth buffering
+        if bytes.len() >= this.capacity {
+            return Pin::new(&mut this.inner).poll_write(cx, bytes);
+        }
+        this.buf.extend_from_slice(bytes);
+        Poll::Ready(Ok(bytes.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buf(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buf(cx))?;
+        Pin::new(&mut this.inner).poll_close(cx)
+    }
+}
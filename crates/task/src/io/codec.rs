@@ -0,0 +1,342 @@
+//!
+//! Framing codecs
+//!
+//! A byte stream has no message boundaries, a codec adds them. A
+//! [`FramedRead`] buffers what an [`AsyncRead`] gives and yields it a frame
+//! at a time as a [`Stream`], a [`FramedWrite`] encodes frames and writes
+//! them out:
+//!
+//! ```ignore
+//! let (reader, writer) = serial.split();
+//! let mut requests = FramedRead::new(reader, LinesCodec::new());
+//! let mut replies = FramedWrite::new(writer, LinesCodec::new());
+//! while let Some(line) = requests.next().await {
+//!     replies.send(format!("echo: {}", line?)).await?;
+//! }
+//! ```
+//!
+
+use std::{
+    future::poll_fn,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::{Stream, ready};
+
+use super::{AsyncRead, AsyncWrite, Error, Result};
+
+/// Bytes asked from the reader at a time
+const READ_SIZE: usize = 256;
+
+/// Splits frames off the front of a buffer
+pub trait Decoder {
+    type Item;
+
+    /// Take the first frame out of `buf`, or `None` if it isn't complete
+    /// yet
+    fn decode(&mut self, buf: &mut Vec<u8>) -> Result<Option<Self::Item>>;
+
+    /// Like [`Decoder::decode`] once input has ended. Leftover bytes that
+    /// aren't a frame are [`Error::UnexpectedEof`].
+    fn decode_eof(&mut self, buf: &mut Vec<u8>) -> Result<Option<Self::Item>> {
+        match self.decode(buf)? {
+            Some(frame) => Ok(Some(frame)),
+            None if buf.is_empty() => Ok(None),
+            None => Err(Error::UnexpectedEof),
+        }
+    }
+}
+
+/// Turns frames into bytes
+pub trait Encoder<Item> {
+    /// Append `item` to `buf`
+    fn encode(&mut self, item: Item, buf: &mut Vec<u8>) -> Result<()>;
+}
+
+/// Lines of UTF-8 text ending in `\n`. Frames come without the newline and
+/// a `\r` before it, encoding adds a `\n`.
+#[derive(Debug, Clone, Default)]
+pub struct LinesCodec {
+    max_length: Option<usize>,
+    // Bytes of the buffer known to have no newline
+    searched: usize,
+}
+
+impl LinesCodec {
+    pub fn new() -> Self {
+        LinesCodec::default()
+    }
+
+    /// Lines longer than `max_length` bytes are [`Error::FrameTooLong`],
+    /// so a peer that never sends a newline can't fill up memory
+    pub fn with_max_length(max_length: usize) -> Self {
+        LinesCodec {
+            max_length: Some(max_length),
+            searched: 0,
+        }
+    }
+
+    fn take_line(&mut self, buf: &mut Vec<u8>, end: usize) -> Result<String> {
+        self.searched = 0;
+        let mut line: Vec<u8> = buf.drain(..end).collect();
+        if line.last() == Some(&b'\n') {
+            line.pop();
+        }
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+        String::from_utf8(line).map_err(|_| Error::InvalidData)
+    }
+}
+
+impl Decoder for LinesCodec {
+    type Item = String;
+
+    fn decode(&mut self, buf: &mut Vec<u8>) -> Result<Option<String>> {
+        let newline = buf[self.searched..].iter().position(|&byte| byte == b'\n');
+        match newline {
+            Some(at) => {
+                let end = self.searched + at + 1;
+                if self.max_length.is_some_and(|max| end - 1 > max) {
+                    return Err(Error::FrameTooLong);
+                }
+                self.take_line(buf, end).map(Some)
+            }
+            None if self.max_length.is_some_and(|max| buf.len() > max) => Err(Error::FrameTooLong),
+            None => {
+                self.searched = buf.len();
+                Ok(None)
+            }
+        }
+    }
+
+    /// The last line doesn't need a newline
+    fn decode_eof(&mut self, buf: &mut Vec<u8>) -> Result<Option<String>> {
+        match self.decode(buf)? {
+            Some(line) => Ok(Some(line)),
+            None if buf.is_empty() => Ok(None),
+            None => self.take_line(buf, buf.len()).map(Some),
+        }
+    }
+}
+
+impl<T: AsRef<str>> Encoder<T> for LinesCodec {
+    fn encode(&mut self, line: T, buf: &mut Vec<u8>) -> Result<()> {
+        buf.extend_from_slice(line.as_ref().as_bytes());
+        buf.push(b'\n');
+        Ok(())
+    }
+}
+
+/// Largest frame [`LengthDelimitedCodec::new`] accepts, 8 MiB
+pub const DEFAULT_MAX_FRAME: usize = 8 * 1024 * 1024;
+
+/// Frames of bytes, each after its length as a big-endian `u32`
+#[derive(Debug, Clone)]
+pub struct LengthDelimitedCodec {
+    max_frame: usize,
+}
+
+impl LengthDelimitedCodec {
+    pub fn new() -> Self {
+        LengthDelimitedCodec::with_max_frame(DEFAULT_MAX_FRAME)
+    }
+
+    /// Longer frames are [`Error::FrameTooLong`], both ways
+    pub fn with_max_frame(max_frame: usize) -> Self {
+        LengthDelimitedCodec { max_frame }
+    }
+}
+
+impl Default for LengthDelimitedCodec {
+    fn default() -> Self {
+        LengthDelimitedCodec::new()
+    }
+}
+
+const LENGTH_SIZE: usize = 4;
+
+impl Decoder for LengthDelimitedCodec {
+    type Item = Vec<u8>;
+
+    fn decode(&mut self, buf: &mut Vec<u8>) -> Result<Option<Vec<u8>>> {
+        let Some(length) = buf.first_chunk::<LENGTH_SIZE>() else {
+            return Ok(None);
+        };
+        let length = u32::from_be_bytes(*length) as usize;
+        if length > self.max_frame {
+            return Err(Error::FrameTooLong);
+        }
+        if buf.len() < LENGTH_SIZE + length {
+            buf.reserve(LENGTH_SIZE + length - buf.len());
+            return Ok(None);
+        }
+        let frame = buf[LENGTH_SIZE..LENGTH_SIZE + length].to_vec();
+        buf.drain(..LENGTH_SIZE + length);
+        Ok(Some(frame))
+    }
+}
+
+impl<T: AsRef<[u8]>> Encoder<T> for LengthDelimitedCodec {
+    fn encode(&mut self, frame: T, buf: &mut Vec<u8>) -> Result<()> {
+        let frame = frame.as_ref();
+        let length = u32::try_from(frame.len())
+            .ok()
+            .filter(|&length| length as usize <= self.max_frame)
+            .ok_or(Error::FrameTooLong)?;
+        buf.extend_from_slice(&length.to_be_bytes());
+        buf.extend_from_slice(frame);
+        Ok(())
+    }
+}
+
+/// Frames decoded from an [`AsyncRead`].
+///
+/// Ends after the input does, or after the first error.
+pub struct FramedRead<R, D> {
+    reader: R,
+    decoder: D,
+    buf: Vec<u8>,
+    eof: bool,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin, D: Decoder> FramedRead<R, D> {
+    pub fn new(reader: R, decoder: D) -> Self {
+        FramedRead {
+            reader,
+            decoder,
+            buf: Vec::new(),
+            eof: false,
+            done: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn decoder_mut(&mut self) -> &mut D {
+        &mut self.decoder
+    }
+
+    /// Drops bytes read but not decoded yet
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: AsyncRead + Unpin, D: Decoder + Unpin> Stream for FramedRead<R, D> {
+    type Item = Result<D::Item>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Result<D::Item>>> {
+        let this = self.get_mut();
+        loop {
+            if this.done {
+                return Poll::Ready(None);
+            }
+            let frame = if this.eof {
+                this.decoder.decode_eof(&mut this.buf)
+            } else {
+                this.decoder.decode(&mut this.buf)
+            };
+            match frame {
+                Ok(Some(frame)) => return Poll::Ready(Some(Ok(frame))),
+                Ok(None) if this.eof => {
+                    this.done = true;
+                    return Poll::Ready(None);
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(err)));
+                }
+            }
+
+            // Read straight into the buffer's end
+            let start = this.buf.len();
+            this.buf.resize(start + READ_SIZE, 0);
+            let read = Pin::new(&mut this.reader).poll_read(cx, &mut this.buf[start..]);
+            let count = match read {
+                Poll::Ready(Ok(count)) => count,
+                Poll::Ready(Err(err)) => {
+                    this.buf.truncate(start);
+                    this.done = true;
+                    return Poll::Ready(Some(Err(err)));
+                }
+                Poll::Pending => {
+                    this.buf.truncate(start);
+                    return Poll::Pending;
+                }
+            };
+            this.buf.truncate(start + count);
+            this.eof = count == 0;
+        }
+    }
+}
+
+/// Frames encoded onto an [`AsyncWrite`]
+pub struct FramedWrite<W, E> {
+    writer: W,
+    encoder: E,
+    buf: Vec<u8>,
+    // `buf[..written]` went out already
+    written: usize,
+}
+
+impl<W: AsyncWrite + Unpin, E> FramedWrite<W, E> {
+    pub fn new(writer: W, encoder: E) -> Self {
+        FramedWrite {
+            writer,
+            encoder,
+            buf: Vec::new(),
+            written: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    pub fn encoder_mut(&mut self) -> &mut E {
+        &mut self.encoder
+    }
+
+    /// Encode `item` and write it out
+    pub async fn send<I>(&mut self, item: I) -> Result<()>
+    where
+        E: Encoder<I>,
+    {
+        self.feed(item)?;
+        self.flush().await
+    }
+
+    /// Encode `item` without writing yet, for sending several frames in
+    /// one go with [`FramedWrite::flush`]
+    pub fn feed<I>(&mut self, item: I) -> Result<()>
+    where
+        E: Encoder<I>,
+    {
+        self.encoder.encode(item, &mut self.buf)
+    }
+
+    /// Write out every frame fed so far and flush the writer
+    pub async fn flush(&mut self) -> Result<()> {
+        poll_fn(|cx| self.poll_flush(cx)).await
+    }
+
+    pub fn poll_flush(&mut self, cx: &mut Context) -> Poll<Result<()>> {
+        while self.written < self.buf.len() {
+            let bytes = &self.buf[self.written..];
+            let count = ready!(Pin::new(&mut self.writer).poll_write(cx, bytes))?;
+            if count == 0 {
+                return Poll::Ready(Err(Error::WriteZero));
+            }
+            self.written += count;
+        }
+        self.buf.clear();
+        self.written = 0;
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+}
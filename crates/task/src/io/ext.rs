@@ -0,0 +1,176 @@
+use std::{
+    future::Future,
+    mem,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::ready;
+
+use super::{AsyncRead, AsyncWrite, Error, Result};
+
+/// Future-returning methods for every [`AsyncRead`]
+pub trait AsyncReadExt: AsyncRead {
+    /// Wait for input and read as much as fits into `buf`, `0` at the end
+    /// of input
+    fn read<'a>(&'a mut self, buf: &'a mut [u8]) -> Read<'a, Self>
+    where
+        Self: Unpin,
+    {
+        Read { reader: self, buf }
+    }
+
+    /// Fill all of `buf`, [`Error::UnexpectedEof`] if input ends first
+    fn read_exact<'a>(&'a mut self, buf: &'a mut [u8]) -> ReadExact<'a, Self>
+    where
+        Self: Unpin,
+    {
+        ReadExact { reader: self, buf }
+    }
+}
+
+impl<R: AsyncRead + ?Sized> AsyncReadExt for R {}
+
+/// Future-returning methods for every [`AsyncWrite`]
+pub trait AsyncWriteExt: AsyncWrite {
+    /// Write some of `bytes`, returns how many
+    fn write<'a>(&'a mut self, bytes: &'a [u8]) -> Write<'a, Self>
+    where
+        Self: Unpin,
+    {
+        Write {
+            writer: self,
+            bytes,
+        }
+    }
+
+    /// Write every byte of `bytes`
+    fn write_all<'a>(&'a mut self, bytes: &'a [u8]) -> WriteAll<'a, Self>
+    where
+        Self: Unpin,
+    {
+        WriteAll {
+            writer: self,
+            bytes,
+        }
+    }
+
+    fn flush(&mut self) -> Flush<'_, Self>
+    where
+        Self: Unpin,
+    {
+        Flush {
+            writer: self,
+            close: false,
+        }
+    }
+
+    /// Flush and end the output, see [`AsyncWrite::poll_close`]
+    fn close(&mut self) -> Flush<'_, Self>
+    where
+        Self: Unpin,
+    {
+        Flush {
+            writer: self,
+            close: true,
+        }
+    }
+}
+
+impl<W: AsyncWrite + ?Sized> AsyncWriteExt for W {}
+
+/// Future returned by [`AsyncReadExt::read`]
+pub struct Read<'a, R: ?Sized> {
+    reader: &'a mut R,
+    buf: &'a mut [u8],
+}
+
+impl<R: AsyncRead + Unpin + ?Sized> Future for Read<'_, R> {
+    type Output = Result<usize>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<usize>> {
+        let this = &mut *self;
+        Pin::new(&mut *this.reader).poll_read(cx, this.buf)
+    }
+}
+
+/// Future returned by [`AsyncReadExt::read_exact`]
+pub struct ReadExact<'a, R: ?Sized> {
+    reader: &'a mut R,
+    // What's still to fill
+    buf: &'a mut [u8],
+}
+
+impl<R: AsyncRead + Unpin + ?Sized> Future for ReadExact<'_, R> {
+    type Output = Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        let this = &mut *self;
+        while !this.buf.is_empty() {
+            let count = ready!(Pin::new(&mut *this.reader).poll_read(cx, this.buf))?;
+            if count == 0 {
+                return Poll::Ready(Err(Error::UnexpectedEof));
+            }
+            this.buf = &mut mem::take(&mut this.buf)[count..];
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Future returned by [`AsyncWriteExt::write`]
+pub struct Write<'a, W: ?Sized> {
+    writer: &'a mut W,
+    bytes: &'a [u8],
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> Future for Write<'_, W> {
+    type Output = Result<usize>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<usize>> {
+        let this = &mut *self;
+        Pin::new(&mut *this.writer).poll_write(cx, this.bytes)
+    }
+}
+
+/// Future returned by [`AsyncWriteExt::write_all`]
+pub struct WriteAll<'a, W: ?Sized> {
+    writer: &'a mut W,
+    // What's still to write
+    bytes: &'a [u8],
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> Future for WriteAll<'_, W> {
+    type Output = Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        let this = &mut *self;
+        while !this.bytes.is_empty() {
+            let count = ready!(Pin::new(&mut *this.writer).poll_write(cx, this.bytes))?;
+            if count == 0 {
+                return Poll::Ready(Err(Error::WriteZero));
+            }
+            this.bytes = &this.bytes[count..];
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Future returned by [`AsyncWriteExt::flush`] and [`AsyncWriteExt::close`]
+pub struct Flush<'a, W: ?Sized> {
+    writer: &'a mut W,
+    close: bool,
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> Future for Flush<'_, W> {
+    type Output = Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<()>> {
+        let this = &mut *self;
+        let writer = Pin::new(&mut *this.writer);
+        if this.close {
+            writer.poll_close(cx)
+        } else {
+            writer.poll_flush(cx)
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//!
+//! Interrupt lines as streams
+//!
+//! An interrupt handler does as little as it can: it calls [`notify`] with
+//! its line and returns. The driver's task waits on an [`IrqStream`] for the
+//! line and does the rest:
+//!
+//! ```ignore
+//! let mut irq = IrqStream::new(4)?;
+//! while let Some(_count) = irq.next().await {
+//!     while let Some(byte) = uart.read_byte() {
+//!         ..
+//!     }
+//! }
+//! ```
+//!
+//! Occurrences are counted, not queued, so a driver has to find out from
+//! the device what happened. Data that has to be read in the handler itself,
+//! like scancodes, needs a queue of its own.
+//!
+//! With the `bare-metal` feature the [`pc`](crate::pc) handlers notify every
+//! line, and a line no driver of the crate uses is unmasked while it has a
+//! stream.
+//!
+
+use core::{
+    fmt,
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+
+use futures_util::{Stream, task::AtomicWaker};
+
+/// Lines 0 to 15, as on the PC's two PICs
+pub const LINES: u8 = 16;
+
+struct Line {
+    taken: AtomicBool,
+    count: AtomicUsize,
+    waker: AtomicWaker,
+}
+
+static LINES_STATE: [Line; LINES as usize] = [const {
+    Line {
+        taken: AtomicBool::new(false),
+        count: AtomicUsize::new(0),
+        waker: AtomicWaker::new(),
+    }
+}; LINES as usize];
+
+/// Call from the interrupt handler for `line`. Doesn't lock or allocate,
+/// and does nothing while nobody has an [`IrqStream`] for the line.
+pub fn notify(line: u8) {
+    let Some(state) = LINES_STATE.get(line as usize) else {
+        return;
+    };
+    if state.taken.load(Ordering::Acquire) {
+        state.count.fetch_add(1, Ordering::AcqRel);
+        state.waker.wake();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    /// Not below [`LINES`]
+    NoSuchLine(u8),
+    /// Another [`IrqStream`] has the line
+    InUse(u8),
+}
+
+impl fmt::Display for IrqError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IrqError::NoSuchLine(line) => write!(f, "no interrupt line {}", line),
+            IrqError::InUse(line) => write!(f, "interrupt line {} already in use", line),
+        }
+    }
+}
+
+/// How often an interrupt line fired. Each item is the number of times
+/// since the previous one, at least 1. The stream never ends.
+///
+/// One stream per line at a time, dropping it frees the line.
+pub struct IrqStream {
+    line: u8,
+}
+
+impl IrqStream {
+    /// Start counting `line`, occurrences before this aren't seen
+    pub fn new(line: u8) -> Result<IrqStream, IrqError> {
+        let state = LINES_STATE
+            .get(line as usize)
+            .ok_or(IrqError::NoSuchLine(line))?;
+        if state.taken.swap(true, Ordering::AcqRel) {
+            return Err(IrqError::InUse(line));
+        }
+        state.count.store(0, Ordering::Release);
+        #[cfg(all(feature = "bare-metal", target_arch = "x86_64"))]
+        crate::pc::set_irq_wanted(line, true);
+        Ok(IrqStream { line })
+    }
+
+    pub fn line(&self) -> u8 {
+        self.line
+    }
+
+    fn state(&self) -> &'static Line {
+        &LINES_STATE[self.line as usize]
+    }
+}
+
+impl Stream for IrqStream {
+    type Item = usize;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<usize>> {
+        let state = self.state();
+        let count = state.count.swap(0, Ordering::AcqRel);
+        if count > 0 {
+            return Poll::Ready(Some(count));
+        }
+        state.waker.register(cx.waker());
+        // Fired between the swap and registering
+        match state.count.swap(0, Ordering::AcqRel) {
+            0 => Poll::Pending,
+            count => Poll::Ready(Some(count)),
+        }
+    }
+}
+
+impl Drop for IrqStream {
+    fn drop(&mut self) {
+        #[cfg(all(feature = "bare-metal", target_arch = "x86_64"))]
+        crate::pc::set_irq_wanted(self.line, false);
+        let state = self.state();
+        state.waker.take();
+        state.taken.store(false, Ordering::Release);
+    }
+}
@@ -0,0 +1,179 @@
+//!
+//! JoinSet: many child futures driven inside one task
+//!
+
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    task::Wake,
+    vec::Vec,
+};
+use core::{
+    future::{Future, poll_fn},
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use futures_util::Stream;
+
+use crate::sync::loom::{Arc, AtomicWaker, Mutex};
+
+/// Collection of futures polled concurrently by the task that owns it.
+///
+/// Works like a tiny executor nested in a task: every child gets a waker that
+/// pushes its id to a ready queue and wakes the owning task, so only children
+/// that were actually woken are polled again. Results come out in completion
+/// order.
+pub struct JoinSet<T> {
+    children: BTreeMap<u64, Child<T>>,
+    ready: Arc<ReadyQueue>,
+    next_id: u64,
+}
+
+struct Child<T> {
+    future: Pin<Box<dyn Future<Output = T>>>,
+    // Same idea as the executor's waker_cache
+    waker: Waker,
+}
+
+struct ReadyQueue {
+    ids: Mutex<VecDeque<u64>>,
+    // Waker of the task that owns the set
+    parent: AtomicWaker,
+}
+
+struct ChildWaker {
+    id: u64,
+    ready: Arc<ReadyQueue>,
+}
+
+impl Wake for ChildWaker {
+    fn wake(self: alloc::sync::Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &alloc::sync::Arc<Self>) {
+        self.ready.ids.lock().push_back(self.id);
+        self.ready.parent.wake();
+    }
+}
+
+impl<T> JoinSet<T> {
+    pub fn new() -> Self {
+        JoinSet {
+            children: BTreeMap::new(),
+            ready: Arc::new(ReadyQueue {
+                ids: Mutex::new(VecDeque::new()),
+                parent: AtomicWaker::new(),
+            }),
+            next_id: 0,
+        }
+    }
+
+    pub fn spawn(&mut self, future: impl Future<Output = T> + 'static) {
+        let id = self.next_id;
+        self.next_id += 1;
+        let waker = Waker::from(alloc::sync::Arc::new(ChildWaker {
+            id,
+            ready: self.ready.clone(),
+        }));
+        self.children.insert(
+            id,
+            Child {
+                future: Box::pin(future),
+                waker,
+            },
+        );
+        // Every child needs a first poll
+        self.ready.ids.lock().push_back(id);
+        self.ready.parent.wake();
+    }
+
+    pub fn len(&self) -> usize {
+        self.children.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// Drop all children without waiting for them
+    pub fn clear(&mut self) {
+        self.children.clear();
+        self.ready.ids.lock().clear();
+    }
+
+    /// `Ready(None)` once the set is empty
+    pub fn poll_join_next(&mut self, cx: &mut Context) -> Poll<Option<T>> {
+        self.ready.parent.register(cx.waker());
+
+        // Bound the work per call so a child that keeps waking itself
+        // can't keep us from yielding back to the executor
+        let budget = self.ready.ids.lock().len();
+        for _ in 0..budget {
+            let Some(id) = self.ready.ids.lock().pop_front() else {
+                break;
+            };
+            // Stale wake of a child that already finished
+            let Some(child) = self.children.get_mut(&id) else {
+                continue;
+            };
+            let mut context = Context::from_waker(&child.waker);
+            if let Poll::Ready(output) = child.future.as_mut().poll(&mut context) {
+                self.children.remove(&id);
+                return Poll::Ready(Some(output));
+            }
+        }
+
+        if self.children.is_empty() {
+            Poll::Ready(None)
+        } else {
+            if !self.ready.ids.lock().is_empty() {
+                cx.waker().wake_by_ref();
+            }
+            Poll::Pending
+        }
+    }
+
+    pub async fn join_next(&mut self) -> Option<T> {
+        poll_fn(|cx| self.poll_join_next(cx)).await
+    }
+
+    /// Wait for every child, results in completion order
+    pub async fn join_all(mut self) -> Vec<T> {
+        let mut results = Vec::with_capacity(self.len());
+        while let Some(output) = self.join_next().await {
+            results.push(output);
+        }
+        results
+    }
+}
+
+impl<T> Default for JoinSet<T> {
+    fn default() -> Self {
+        JoinSet::new()
+    }
+}
+
+impl<T> Stream for JoinSet<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        self.get_mut().poll_join_next(cx)
+    }
+}
+
+/// Run all futures concurrently in the current task, results in input order
+pub async fn join_all<I>(futures: I) -> Vec<<I::Item as Future>::Output>
+where
+    I: IntoIterator,
+    I::Item: Future + 'static,
+{
+    let mut set = JoinSet::new();
+    for (index, future) in futures.into_iter().enumerate() {
+        set.spawn(async move { (index, future.await) });
+    }
+    let mut results = set.join_all().await;
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, output)| output).collect()
+}
@@ -1,12 +1,67 @@
-use std::{
+use alloc::{collections::VecDeque, vec::Vec};
+use core::{
+    future::poll_fn,
     pin::Pin,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
     task::{Context, Poll},
 };
 
-use conquer_once::OnceCell;
-use crossbeam_queue::ArrayQueue;
-use futures_util::{Stream, StreamExt, task::AtomicWaker};
-use pc_keyboard::{DecodedKey, HandleControl, Keyboard, ScancodeSet1, layouts};
+use conquer_once::spin::OnceCell;
+use futures_util::{Stream, StreamExt, ready};
+use pc_keyboard::{DecodedKey, KeyCode, KeyEvent, KeyState};
+
+use crate::{
+    capability::{self, Capability, CapabilityError},
+    channel::broadcast,
+    console,
+    future::{Either, race},
+    i8042::{self, Port},
+    input, log, priority, rng, shutdown,
+    signal::{self, Signal},
+    sync::{IrqNotify, Listener, Notify},
+};
+
+mod ascii;
+mod compose;
+mod config;
+mod driver;
+mod hotkey;
+mod led;
+mod line;
+mod macros;
+mod middleware;
+mod modifiers;
+mod polling;
+mod queue;
+mod remap;
+mod repeat;
+mod source;
+
+pub(crate) use config::Decoder;
+pub use config::{
+    KeyboardConfig, Layout, ScancodeSetKind, config, set_config, set_dead_keys, set_layout,
+    set_repeat, set_scancode_set,
+};
+pub use driver::KeyboardDriver;
+pub use hotkey::{Hotkey, HotkeyListener, on_hotkey, register_hotkey};
+pub use led::{Leds, set_leds, sync_leds};
+pub use line::{LineReader, read_line};
+pub use macros::{MacroKeys, run_macros};
+pub use middleware::{
+    FnMiddleware, Middleware, MiddlewareId, ProcessFuture, add_middleware, middleware_fn,
+    remove_middleware,
+};
+pub use modifiers::{ModifierState, Modifiers, modifiers, watch_modifiers};
+pub use polling::{DEFAULT_POLL_PERIOD, PollingSource, polling_source};
+use queue::ScancodeQueue;
+pub use queue::{Overflow, QueueConfig, dropped_scancodes};
+pub use remap::{Keymap, KeymapError, keymap, reload_keymap, set_keymap, set_keymap_source};
+pub use repeat::KeyRepeat;
+#[cfg(feature = "std")]
+pub use source::TerminalSource;
+pub use source::{
+    Injector, IrqSource, ScancodeSink, ScancodeSource, inject, inject_str, set_source,
+};
 
 // Wake is used to handle futures. You can notify an executor to poll a future
 // using a wake when it is required.
@@ -16,33 +71,154 @@ use pc_keyboard::{DecodedKey, HandleControl, Keyboard, ScancodeSet1, layouts};
 ///
 /// OnceCell is a cell that can be written to only once
 ///
-/// Array queue is a bounded mpmc queue
-static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+/// Array queue is a bounded mpmc queue, or an unbounded one with
+/// [`Overflow::Grow`]
+static SCANCODE_QUEUE: OnceCell<ScancodeQueue> = OnceCell::uninit();
+
+/// Used when the queue is created by the first `ScancodeStream`
+static QUEUE_CONFIG: spin::Mutex<QueueConfig> = spin::Mutex::new(QueueConfig::new());
+
+/// Set the scancode queue's capacity and overflow policy.
+///
+/// The queue is created by the first [`ScancodeStream`] and kept for good,
+/// afterwards this fails and hands the config back.
+pub fn set_queue_config(config: QueueConfig) -> Result<(), QueueConfig> {
+    let mut current = QUEUE_CONFIG.lock();
+    if SCANCODE_QUEUE.is_initialized() {
+        return Err(config);
+    }
+    *current = config;
+    Ok(())
+}
+
+/// Wakes the streams from the interrupt handler. Without permits, they look
+/// at the queue again after registering so a scancode pushed in between
+/// isn't missed.
+static NOTIFY: IrqNotify = IrqNotify::new();
+
+/// Live `ScancodeStream` handles, scancodes are only queued while there is one
+static HANDLES: AtomicUsize = AtomicUsize::new(0);
 
-static WAKER: AtomicWaker = AtomicWaker::new();
+/// Scancodes that came with no stream to take them, counted by the
+/// interrupt handler and reported by [`dispatch`]
+static UNREAD: AtomicU64 = AtomicU64::new(0);
+/// Of those and the dropped ones, how many were logged
+static REPORTED_UNREAD: AtomicU64 = AtomicU64::new(0);
+static REPORTED_DROPPED: AtomicU64 = AtomicU64::new(0);
+
+/// Set by [`shutdown`], for good
+static SHUT_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Stop keyboard input for good, e.g. before powering off.
+///
+/// Scancode streams end once the queued scancodes are read, then
+/// [`dispatch`] returns and every subscriber stream, [`LineReader`] and
+/// hotkey listener ends too, so `while let Some(..)` loops exit.
+pub fn shutdown() {
+    SHUT_DOWN.store(true, Ordering::Release);
+    // A stream about to park checks the flag after registering
+    NOTIFY.notify();
+}
+
+pub fn is_shut_down() -> bool {
+    SHUT_DOWN.load(Ordering::Acquire)
+}
 
 pub(crate) fn add_scancode(scancode: u8) {
-    if let Ok(queue) = SCANCODE_QUEUE.try_get() {
-        if let Err(_) = queue.push(scancode) {
-            println!("WARNING: scancode queue full; dropping keyboard input");
-        } else {
-            WAKER.wake();
+    // Replies to keyboard commands come in with the scancodes
+    if i8042::take_reply(Port::First, scancode) || is_shut_down() {
+        return;
+    }
+    rng::add_interrupt_timing(scancode.into(), 1);
+    input::capture(input::Device::Keyboard, scancode);
+    // Logging allocates and locks, a task reports the losses instead
+    let queue = match SCANCODE_QUEUE.try_get() {
+        Ok(queue) if HANDLES.load(Ordering::Acquire) > 0 => queue,
+        _ => {
+            UNREAD.fetch_add(1, Ordering::Relaxed);
+            return;
         }
-    } else {
-        println!("WARNING: scancode queue uninitialized");
+    };
+    // Counted as dropped when full, dropping the oldest still queues it
+    queue.push(scancode);
+    NOTIFY.notify();
+    priority::input_arrived();
+}
+
+/// Log the scancodes lost since the last call, once for all of them
+fn report_lost_scancodes() {
+    let unread = UNREAD.load(Ordering::Relaxed);
+    let reported = REPORTED_UNREAD.swap(unread, Ordering::Relaxed);
+    if unread > reported {
+        log::warn!(
+            "no scancode reader; dropped {} scancodes of keyboard input",
+            unread - reported
+        );
+    }
+    let dropped = dropped_scancodes();
+    let reported = REPORTED_DROPPED.swap(dropped, Ordering::Relaxed);
+    if dropped > reported {
+        log::warn!(
+            "scancode queue full; dropped {} scancodes of keyboard input",
+            dropped - reported
+        );
     }
 }
 
+/// Handle to the scancode queue filled by the keyboard interrupt.
+///
+/// Handles can be cloned and dropped freely. Clones share the queue, so each
+/// scancode goes to only one of them; use [`dispatch`] and [`subscribe`] to
+/// give several tasks the same input. Once the last handle is dropped the
+/// queue is emptied and input is ignored until a new stream is created.
 pub struct ScancodeStream {
-    _private: (),
+    listener: Listener<'static>,
 }
 
 impl ScancodeStream {
+    /// Panics if the task lacks [`Capability::RawInput`], see
+    /// [`try_new`](ScancodeStream::try_new)
     pub fn new() -> Self {
-        SCANCODE_QUEUE
-            .try_init_once(|| ArrayQueue::new(100))
-            .expect("ScancodeStream::new should only be called once");
-        ScancodeStream { _private: () }
+        ScancodeStream::try_new().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_new() -> Result<Self, CapabilityError> {
+        capability::check(&Capability::RawInput)?;
+        // The queue itself is allocated once and reused by later streams
+        SCANCODE_QUEUE.init_once(|| {
+            let config = QUEUE_CONFIG.lock();
+            ScancodeQueue::new(*config)
+        });
+        HANDLES.fetch_add(1, Ordering::AcqRel);
+        Ok(ScancodeStream {
+            listener: NOTIFY.listener(),
+        })
+    }
+}
+
+impl Default for ScancodeStream {
+    fn default() -> Self {
+        ScancodeStream::new()
+    }
+}
+
+impl Clone for ScancodeStream {
+    fn clone(&self) -> Self {
+        HANDLES.fetch_add(1, Ordering::AcqRel);
+        ScancodeStream {
+            listener: self.listener.clone(),
+        }
+    }
+}
+
+impl Drop for ScancodeStream {
+    fn drop(&mut self) {
+        if HANDLES.fetch_sub(1, Ordering::AcqRel) == 1
+            && let Ok(queue) = SCANCODE_QUEUE.try_get()
+        {
+            // Don't hand stale input to the next stream
+            while queue.pop().is_some() {}
+        }
     }
 }
 
@@ -62,6 +238,60 @@ impl ScancodeStream {
 //     }
 // }
 
+impl ScancodeStream {
+    /// Next queued scancode without waiting, for tasks that sample input
+    /// once per frame
+    pub fn try_next(&mut self) -> Option<u8> {
+        SCANCODE_QUEUE.try_get().ok()?.pop()
+    }
+
+    /// Move up to `max` queued scancodes into `buf`, waiting only while
+    /// nothing is queued.
+    ///
+    /// Returns how many were added, `0` means the stream has ended. Bursts
+    /// of input are handled with one wake instead of one per byte.
+    pub async fn recv_many(&mut self, buf: &mut Vec<u8>, max: usize) -> usize {
+        poll_fn(|cx| self.poll_recv_many(cx, buf, max)).await
+    }
+
+    pub fn poll_recv_many(
+        &mut self,
+        cx: &mut Context,
+        buf: &mut Vec<u8>,
+        max: usize,
+    ) -> Poll<usize> {
+        assert!(max > 0, "recv_many needs room for at least one scancode");
+        let queue = SCANCODE_QUEUE
+            .try_get()
+            .expect("scancode queue not initialized");
+
+        let mut registered = false;
+        loop {
+            let before = buf.len();
+            while buf.len() - before < max
+                && let Some(scancode) = queue.pop()
+            {
+                buf.push(scancode);
+            }
+            if buf.len() > before {
+                return Poll::Ready(buf.len() - before);
+            }
+            if is_shut_down() {
+                return Poll::Ready(0);
+            }
+            if registered {
+                priority::waiting_for_input();
+                return Poll::Pending;
+            }
+
+            // Pause polling until add_scancode notifies. A scancode pushed
+            // before the waker was in place woke nobody, so look once more.
+            self.listener.register(cx.waker());
+            registered = true;
+        }
+    }
+}
+
 /// Use waker
 impl Stream for ScancodeStream {
     type Item = u8;
@@ -71,22 +301,20 @@ impl Stream for ScancodeStream {
             .try_get()
             .expect("scancode queue not initialized");
 
-        // Interrupt handler fills queue im mediately after check
-        if let Some(scancode) = queue.pop() {
-            return Poll::Ready(Some(scancode));
-        }
-
-        // Pause polling
-        WAKER.register(&cx.waker());
-        match queue.pop() {
-            Some(scancode) => {
-                // input is ready to be taken from queue.
-                // Pop and use waker to activate
-                WAKER.take();
-                Poll::Ready(Some(scancode))
+        let mut registered = false;
+        loop {
+            if let Some(scancode) = queue.pop() {
+                return Poll::Ready(Some(scancode));
             }
-            // Nothing to pop, poll once
-            None => Poll::Pending,
+            if is_shut_down() {
+                return Poll::Ready(None);
+            }
+            if registered {
+                priority::waiting_for_input();
+                return Poll::Pending;
+            }
+            self.listener.register(cx.waker());
+            registered = true;
         }
     }
 }
@@ -100,22 +328,260 @@ impl Stream for ScancodeStream {
 //     fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>>;
 // }
 
-pub async fn print_keypresses() {
+/// Key events buffered per subscriber before the slowest one starts missing some
+const EVENT_CAPACITY: usize = 64;
+
+/// Every subscriber gets its own receiver on this channel
+static KEY_EVENTS: spin::Once<broadcast::Sender<KeyEvent>> = spin::Once::new();
+
+fn key_events() -> &'static broadcast::Sender<KeyEvent> {
+    KEY_EVENTS.call_once(|| broadcast::channel(EVENT_CAPACITY).0)
+}
+
+/// Decode scancodes and publish the key events to every subscriber.
+///
+/// Scancodes go through the [`Middleware`] chain before decoding, decoded
+/// events are then remapped with the current [`Keymap`]. Presses of
+/// registered [`Hotkey`]s are routed to their listeners instead of the
+/// subscribers. Ctrl+Alt+Del starts a [`shutdown`](shutdown::shutdown).
+///
+/// This is the only reader of the scancode queue, spawn it exactly once.
+/// Without it running, [`KeyEventStream`]s never yield anything.
+pub async fn dispatch() {
     let mut scancodes = ScancodeStream::new();
-    let mut keyboard = Keyboard::new(
-        ScancodeSet1::new(),
-        layouts::Us104Key,
-        HandleControl::Ignore,
-    );
-
-    while let Some(scancode) = scancodes.next().await {
-        if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
-            if let Some(key) = keyboard.process_keyevent(key_event) {
-                match key {
-                    DecodedKey::Unicode(character) => print!("{}", character),
-                    DecodedKey::RawKey(key) => print!("{:?}", key),
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let mut dispatcher = Dispatcher {
+        decoder: Decoder::new(),
+        hotkeys: hotkey::Router::new(),
+        modifiers: ModifierState::default(),
+        repeater: repeat::Repeater::new(),
+        events: key_events(),
+    };
+    let mut middleware = middleware::Chain::default();
+
+    loop {
+        report_lost_scancodes();
+        let received = scancodes.recv_many(&mut batch, BATCH_SIZE);
+        let repeated = poll_fn(|cx| dispatcher.repeater.poll_repeat(cx));
+        match race(received, race(repeated, next_injected())).await {
+            Either::Left(0) => break,
+            Either::Left(_) => {
+                middleware.run(&mut batch).await;
+                for scancode in batch.drain(..) {
+                    dispatcher.scancode(scancode);
+                }
+            }
+            Either::Right(event) => dispatcher.publish(event.into_inner()),
+        }
+    }
+
+    // Shut down, let everyone waiting on us finish
+    dispatcher.events.close();
+    hotkey::close_all();
+    modifiers::sender().close();
+}
+
+static INJECTED: spin::Mutex<VecDeque<KeyEvent>> = spin::Mutex::new(VecDeque::new());
+static INJECTED_READY: Notify = Notify::new();
+
+/// Publish a synthetic key event as if it had just been typed.
+///
+/// It skips decoding, remapping and repeat but otherwise goes through
+/// [`dispatch`] like any other event, so it updates the modifier state and
+/// can trigger hotkeys.
+pub fn inject_key_event(event: KeyEvent) {
+    INJECTED.lock().push_back(event);
+    INJECTED_READY.notify_one();
+}
+
+async fn next_injected() -> KeyEvent {
+    loop {
+        if let Some(event) = INJECTED.lock().pop_front() {
+            return event;
+        }
+        INJECTED_READY.notified().await;
+    }
+}
+
+/// Scancodes taken off the queue per wake of the dispatcher
+const BATCH_SIZE: usize = 16;
+
+/// State of the [`dispatch`] task
+struct Dispatcher {
+    decoder: Decoder,
+    hotkeys: hotkey::Router,
+    modifiers: ModifierState,
+    repeater: repeat::Repeater,
+    events: &'static broadcast::Sender<KeyEvent>,
+}
+
+impl Dispatcher {
+    fn scancode(&mut self, scancode: u8) {
+        // Multi-byte scancodes only produce an event on their last byte
+        let Some(event) = self.decoder.add_byte(scancode) else {
+            return;
+        };
+        let event = remap::apply(event);
+        if self.repeater.key(&event, config().repeat) {
+            self.publish(event);
+        }
+    }
+
+    fn publish(&mut self, event: KeyEvent) {
+        if self.modifiers.update(&event) {
+            modifiers::sender().send_replace(self.modifiers);
+        }
+        if event.code == KeyCode::Delete
+            && event.state == KeyState::Down
+            && self.modifiers.ctrl()
+            && self.modifiers.alt()
+        {
+            shutdown::request();
+        }
+        // By key position, a layout moving C or Z still gets its signal
+        // from the console's line discipline
+        let signal = match event.code {
+            KeyCode::C => Some(Signal::Interrupt),
+            KeyCode::Z => Some(Signal::Suspend),
+            _ => None,
+        };
+        if event.state == KeyState::Down
+            && self.modifiers.ctrl()
+            && signal.is_some_and(|signal| signal::console().raise(signal))
+        {
+            // Taken by the job in the console's foreground
+            return;
+        }
+        if !self.hotkeys.route(&event, &self.modifiers) {
+            // Fails only when nobody is subscribed, the event is just dropped
+            let _ = self.events.send(event);
+            priority::input_arrived();
+        }
+    }
+}
+
+/// Subscribe to key events published by [`dispatch`]
+pub fn subscribe() -> KeyEventStream {
+    KeyEventStream::new()
+}
+
+/// Decoded key events, including key releases.
+///
+/// Unlike `print_keypresses` this doesn't map keys to characters, so
+/// applications like games can track which keys are held down. Each stream
+/// sees every event sent after it was created; a subscriber that falls too
+/// far behind silently skips the events it missed.
+pub struct KeyEventStream {
+    events: broadcast::Receiver<KeyEvent>,
+}
+
+impl KeyEventStream {
+    pub fn new() -> Self {
+        KeyEventStream {
+            events: key_events().subscribe(),
+        }
+    }
+
+    /// Next event without waiting, `None` if nothing arrived since the last
+    /// call.
+    ///
+    /// Call it in a loop each frame to catch up on everything that happened.
+    pub fn try_next(&mut self) -> Option<KeyEvent> {
+        loop {
+            match self.events.try_recv() {
+                Ok(event) => return Some(event),
+                Err(broadcast::TryRecvError::Lagged(_)) => continue,
+                Err(broadcast::TryRecvError::Empty | broadcast::TryRecvError::Closed) => {
+                    return None;
                 }
             }
         }
     }
 }
+
+impl Default for KeyEventStream {
+    fn default() -> Self {
+        KeyEventStream::new()
+    }
+}
+
+impl Stream for KeyEventStream {
+    type Item = KeyEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<KeyEvent>> {
+        let poll = self.events.poll_next_unpin(cx);
+        if poll.is_pending() {
+            priority::waiting_for_input();
+        }
+        poll
+    }
+}
+
+/// Keys decoded to characters with the current [`KeyboardConfig`].
+///
+/// Only presses produce keys. With [`KeyboardConfig::dead_keys`] on, accents
+/// are combined with the following letter here.
+pub struct DecodedKeyStream {
+    events: KeyEventStream,
+    decoder: Decoder,
+    composer: compose::Composer,
+    // A dead key that didn't combine comes out together with the next key
+    pending: VecDeque<DecodedKey>,
+}
+
+impl DecodedKeyStream {
+    pub fn new() -> Self {
+        DecodedKeyStream {
+            events: KeyEventStream::new(),
+            decoder: Decoder::new(),
+            composer: compose::Composer::default(),
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl Default for DecodedKeyStream {
+    fn default() -> Self {
+        DecodedKeyStream::new()
+    }
+}
+
+impl Stream for DecodedKeyStream {
+    type Item = DecodedKey;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<DecodedKey>> {
+        let this = &mut *self;
+        loop {
+            if let Some(key) = this.pending.pop_front() {
+                return Poll::Ready(Some(key));
+            }
+            let Some(event) = ready!(this.events.poll_next_unpin(cx)) else {
+                return Poll::Ready(None);
+            };
+            if let Some(key) = this.decoder.process_keyevent(event) {
+                let dead_keys = config().dead_keys;
+                this.composer.feed(key, dead_keys, &mut this.pending);
+            }
+        }
+    }
+}
+
+/// Echo typed characters, decoded with the current [`KeyboardConfig`].
+///
+/// Needs [`dispatch`] running alongside it.
+pub async fn print_keypresses() {
+    let mut keys = DecodedKeyStream::new();
+
+    while let Some(key) = keys.next().await {
+        match key {
+            DecodedKey::Unicode(character) => console::print!("{}", character).await,
+            DecodedKey::RawKey(key) => console::print!("{:?}", key).await,
+        }
+    }
+}
+
+/// Like [`print_keypresses`], switching to `config` first
+pub async fn print_keypresses_with(config: KeyboardConfig) {
+    set_config(config);
+    print_keypresses().await
+}
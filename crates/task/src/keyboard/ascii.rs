@@ -0,0 +1,50 @@
+//! Typing text as scancode set 1 on a US layout
+
+use alloc::vec::Vec;
+
+const LSHIFT: u8 = 0x2A;
+/// Set 1 marks a release by setting the top bit of the press
+const RELEASE: u8 = 0x80;
+
+/// Press and release scancodes typing `character`, `false` if it can't be
+/// typed
+pub(crate) fn type_char(character: char, out: &mut Vec<u8>) -> bool {
+    let Some((shift, code)) = make_code(character) else {
+        return false;
+    };
+    if shift {
+        out.push(LSHIFT);
+    }
+    out.push(code);
+    out.push(code | RELEASE);
+    if shift {
+        out.push(LSHIFT | RELEASE);
+    }
+    true
+}
+
+/// (needs shift, make code)
+fn make_code(character: char) -> Option<(bool, u8)> {
+    const ROWS: [(&str, &str, u8); 4] = [
+        ("1234567890-=", "!@#$%^&*()_+", 0x02),
+        ("qwertyuiop[]", "QWERTYUIOP{}", 0x10),
+        ("asdfghjkl;'`", "ASDFGHJKL:\"~", 0x1E),
+        ("\\zxcvbnm,./", "|ZXCVBNM<>?", 0x2B),
+    ];
+    match character {
+        '\u{8}' => return Some((false, 0x0E)),
+        '\t' => return Some((false, 0x0F)),
+        '\n' | '\r' => return Some((false, 0x1C)),
+        ' ' => return Some((false, 0x39)),
+        _ => {}
+    }
+    for (plain, shifted, first) in ROWS {
+        if let Some(i) = plain.chars().position(|c| c == character) {
+            return Some((false, first + i as u8));
+        }
+        if let Some(i) = shifted.chars().position(|c| c == character) {
+            return Some((true, first + i as u8));
+        }
+    }
+    None
+}
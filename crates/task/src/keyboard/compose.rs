@@ -0,0 +1,106 @@
+use alloc::collections::VecDeque;
+
+use pc_keyboard::DecodedKey;
+
+/// Characters that start a dead key sequence
+const DEAD_KEYS: &[char] = &['´', '`', '^', '¨', '~'];
+
+/// (dead key, base, composed)
+const COMPOSITIONS: &[(char, char, char)] = &[
+    ('´', 'a', 'á'),
+    ('´', 'e', 'é'),
+    ('´', 'i', 'í'),
+    ('´', 'o', 'ó'),
+    ('´', 'u', 'ú'),
+    ('´', 'y', 'ý'),
+    ('´', 'A', 'Á'),
+    ('´', 'E', 'É'),
+    ('´', 'I', 'Í'),
+    ('´', 'O', 'Ó'),
+    ('´', 'U', 'Ú'),
+    ('´', 'Y', 'Ý'),
+    ('`', 'a', 'à'),
+    ('`', 'e', 'è'),
+    ('`', 'i', 'ì'),
+    ('`', 'o', 'ò'),
+    ('`', 'u', 'ù'),
+    ('`', 'A', 'À'),
+    ('`', 'E', 'È'),
+    ('`', 'I', 'Ì'),
+    ('`', 'O', 'Ò'),
+    ('`', 'U', 'Ù'),
+    ('^', 'a', 'â'),
+    ('^', 'e', 'ê'),
+    ('^', 'i', 'î'),
+    ('^', 'o', 'ô'),
+    ('^', 'u', 'û'),
+    ('^', 'A', 'Â'),
+    ('^', 'E', 'Ê'),
+    ('^', 'I', 'Î'),
+    ('^', 'O', 'Ô'),
+    ('^', 'U', 'Û'),
+    ('¨', 'a', 'ä'),
+    ('¨', 'e', 'ë'),
+    ('¨', 'i', 'ï'),
+    ('¨', 'o', 'ö'),
+    ('¨', 'u', 'ü'),
+    ('¨', 'y', 'ÿ'),
+    ('¨', 'A', 'Ä'),
+    ('¨', 'E', 'Ë'),
+    ('¨', 'I', 'Ï'),
+    ('¨', 'O', 'Ö'),
+    ('¨', 'U', 'Ü'),
+    ('~', 'a', 'ã'),
+    ('~', 'n', 'ñ'),
+    ('~', 'o', 'õ'),
+    ('~', 'A', 'Ã'),
+    ('~', 'N', 'Ñ'),
+    ('~', 'O', 'Õ'),
+];
+
+/// Turns dead key sequences like ´ + e into é
+#[derive(Default)]
+pub(crate) struct Composer {
+    dead: Option<char>,
+}
+
+impl Composer {
+    /// Feed a decoded key, pushing zero, one or two keys to `out`
+    pub(crate) fn feed(&mut self, key: DecodedKey, enabled: bool, out: &mut VecDeque<DecodedKey>) {
+        if !enabled {
+            // Don't sit on a dead key from before compose was switched off
+            out.extend(self.dead.take().map(DecodedKey::Unicode));
+            out.push_back(key);
+            return;
+        }
+        let DecodedKey::Unicode(character) = key else {
+            // Keys like arrows cancel a pending dead key
+            self.dead = None;
+            out.push_back(key);
+            return;
+        };
+
+        match self.dead.take() {
+            None if DEAD_KEYS.contains(&character) => self.dead = Some(character),
+            None => out.push_back(key),
+            // Space or the dead key again types the accent itself
+            Some(dead) if character == ' ' || character == dead => {
+                out.push_back(DecodedKey::Unicode(dead))
+            }
+            Some(dead) => match compose(dead, character) {
+                Some(composed) => out.push_back(DecodedKey::Unicode(composed)),
+                None => {
+                    out.push_back(DecodedKey::Unicode(dead));
+                    out.push_back(key);
+                }
+            },
+        }
+    }
+}
+
+fn compose(dead: char, base: char) -> Option<char> {
+    COMPOSITIONS
+        .iter()
+        .find(|(d, b, _)| *d == dead && *b == base)
+        .map(|(_, _, composed)| *composed)
+}
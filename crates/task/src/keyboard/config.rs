@@ -0,0 +1,240 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use pc_keyboard::{
+    DecodedKey, HandleControl, KeyEvent, Keyboard, ScancodeSet, ScancodeSet1, ScancodeSet2, layouts,
+};
+
+use super::KeyRepeat;
+
+macro_rules! layouts {
+    ($($(#[$attr:meta])* $name:ident),* $(,)?) => {
+        /// Keyboard layouts shipped by `pc_keyboard`
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+        pub enum Layout {
+            $($(#[$attr])* $name),*
+        }
+
+        impl Layout {
+            pub const ALL: &[Layout] = &[$(Layout::$name),*];
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Layout::$name => stringify!($name)),*
+                }
+            }
+
+            /// The layout called `name`, ignoring case
+            pub fn from_name(name: &str) -> Option<Layout> {
+                Layout::ALL
+                    .iter()
+                    .copied()
+                    .find(|layout| layout.name().eq_ignore_ascii_case(name))
+            }
+        }
+
+        // pc_keyboard picks the layout through a type parameter, so there is
+        // one variant per layout to switch between them at runtime. The
+        // scancode set inside is unused, bytes are decoded by `Decoder`.
+        enum LayoutKeyboard {
+            $($name(Keyboard<layouts::$name, ScancodeSet1>)),*
+        }
+
+        impl LayoutKeyboard {
+            fn new(layout: Layout, handle_control: HandleControl) -> Self {
+                match layout {
+                    $(Layout::$name => LayoutKeyboard::$name(Keyboard::new(
+                        ScancodeSet1::new(),
+                        layouts::$name,
+                        handle_control,
+                    ))),*
+                }
+            }
+
+            fn process_keyevent(&mut self, event: KeyEvent) -> Option<DecodedKey> {
+                match self {
+                    $(LayoutKeyboard::$name(keyboard) => keyboard.process_keyevent(event)),*
+                }
+            }
+        }
+    };
+}
+
+layouts!(
+    #[default]
+    Us104Key,
+    Uk105Key,
+    De105Key,
+    Azerty,
+    Dvorak104Key,
+    DVP104Key,
+    Jis109Key,
+    Colemak,
+);
+
+/// Scancode set the bytes from the keyboard controller are in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScancodeSetKind {
+    /// What a PC controller with translation on delivers
+    #[default]
+    Set1,
+    /// What most keyboards send, seen raw when translation is off
+    Set2,
+}
+
+impl ScancodeSetKind {
+    /// Bit 6 of the i8042 configuration byte enables set 2 to set 1 translation
+    const TRANSLATION: u8 = 1 << 6;
+
+    /// Pick the set from the i8042 controller configuration byte, as read
+    /// with command `0x20`.
+    ///
+    /// Keyboards power up in set 2, so without translation that is what
+    /// arrives. Keyboards switched to another set by firmware aren't detected.
+    pub const fn from_controller_config(config: u8) -> Self {
+        if config & Self::TRANSLATION != 0 {
+            ScancodeSetKind::Set1
+        } else {
+            ScancodeSetKind::Set2
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyboardConfig {
+    pub layout: Layout,
+    pub handle_control: HandleControl,
+    pub scancode_set: ScancodeSetKind,
+    /// Software key repeat, `None` leaves repeating to the keyboard
+    pub repeat: Option<KeyRepeat>,
+    /// Treat ´ ` ^ ¨ ~ as dead keys combining with the next letter
+    pub dead_keys: bool,
+}
+
+impl KeyboardConfig {
+    pub const fn new() -> Self {
+        KeyboardConfig {
+            layout: Layout::Us104Key,
+            handle_control: HandleControl::Ignore,
+            scancode_set: ScancodeSetKind::Set1,
+            repeat: None,
+            dead_keys: false,
+        }
+    }
+}
+
+impl Default for KeyboardConfig {
+    fn default() -> Self {
+        KeyboardConfig::new()
+    }
+}
+
+static CONFIG: spin::Mutex<KeyboardConfig> = spin::Mutex::new(KeyboardConfig::new());
+// Bumped on every change so decoders notice without taking the lock
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+pub fn config() -> KeyboardConfig {
+    *CONFIG.lock()
+}
+
+/// Switch layout or decoding at runtime, running decoders pick it up with
+/// the next scancode
+pub fn set_config(config: KeyboardConfig) {
+    *CONFIG.lock() = config;
+    GENERATION.fetch_add(1, Ordering::Release);
+}
+
+pub fn set_layout(layout: Layout) {
+    let mut config = self::config();
+    config.layout = layout;
+    set_config(config);
+}
+
+pub fn set_scancode_set(scancode_set: ScancodeSetKind) {
+    let mut config = self::config();
+    config.scancode_set = scancode_set;
+    set_config(config);
+}
+
+pub fn set_dead_keys(dead_keys: bool) {
+    let mut config = self::config();
+    config.dead_keys = dead_keys;
+    set_config(config);
+}
+
+pub fn set_repeat(repeat: Option<KeyRepeat>) {
+    let mut config = self::config();
+    config.repeat = repeat;
+    set_config(config);
+}
+
+enum AnyScancodeSet {
+    Set1(ScancodeSet1),
+    Set2(ScancodeSet2),
+}
+
+impl AnyScancodeSet {
+    fn new(kind: ScancodeSetKind) -> Self {
+        match kind {
+            ScancodeSetKind::Set1 => AnyScancodeSet::Set1(ScancodeSet1::new()),
+            ScancodeSetKind::Set2 => AnyScancodeSet::Set2(ScancodeSet2::new()),
+        }
+    }
+
+    fn advance_state(&mut self, scancode: u8) -> Option<KeyEvent> {
+        let event = match self {
+            AnyScancodeSet::Set1(set) => set.advance_state(scancode),
+            AnyScancodeSet::Set2(set) => set.advance_state(scancode),
+        };
+        event.ok().flatten()
+    }
+}
+
+/// Scancode and layout decoding following the global [`KeyboardConfig`]
+pub(crate) struct Decoder {
+    generation: u64,
+    config: KeyboardConfig,
+    scancodes: AnyScancodeSet,
+    keyboard: LayoutKeyboard,
+}
+
+impl Decoder {
+    pub(crate) fn new() -> Self {
+        let config = config();
+        Decoder {
+            generation: GENERATION.load(Ordering::Acquire),
+            config,
+            scancodes: AnyScancodeSet::new(config.scancode_set),
+            keyboard: LayoutKeyboard::new(config.layout, config.handle_control),
+        }
+    }
+
+    fn refresh(&mut self) {
+        let generation = GENERATION.load(Ordering::Acquire);
+        if generation == self.generation {
+            return;
+        }
+        let config = config();
+        // Only rebuild what changed, so a layout switch doesn't lose a
+        // half-decoded multi-byte scancode
+        if config.scancode_set != self.config.scancode_set {
+            self.scancodes = AnyScancodeSet::new(config.scancode_set);
+        }
+        if config.layout != self.config.layout
+            || config.handle_control != self.config.handle_control
+        {
+            self.keyboard = LayoutKeyboard::new(config.layout, config.handle_control);
+        }
+        self.config = config;
+        self.generation = generation;
+    }
+
+    pub(crate) fn add_byte(&mut self, scancode: u8) -> Option<KeyEvent> {
+        self.refresh();
+        self.scancodes.advance_state(scancode)
+    }
+
+    pub(crate) fn process_keyevent(&mut self, event: KeyEvent) -> Option<DecodedKey> {
+        self.refresh();
+        self.keyboard.process_keyevent(event)
+    }
+}
@@ -0,0 +1,51 @@
+use alloc::boxed::Box;
+
+use super::{KeymapError, ScancodeSource, dispatch, reload_keymap, set_source, shutdown};
+use crate::{
+    drivers::{Driver, InitFuture, RunFuture},
+    log,
+};
+
+/// The keyboard as a [`Driver`]: switches to `source`, loads the keymap if
+/// there's a [`set_keymap_source`](super::set_keymap_source) and runs
+/// [`dispatch`]
+pub struct KeyboardDriver<S> {
+    source: Option<S>,
+}
+
+impl<S: ScancodeSource + 'static> KeyboardDriver<S> {
+    pub fn new(source: S) -> Self {
+        KeyboardDriver {
+            source: Some(source),
+        }
+    }
+}
+
+impl<S: ScancodeSource + 'static> Driver for KeyboardDriver<S> {
+    fn name(&self) -> &'static str {
+        "keyboard"
+    }
+
+    fn init(&mut self) -> InitFuture<'_> {
+        Box::pin(async move {
+            if let Some(source) = self.source.take() {
+                set_source(source);
+            }
+            // A broken keymap leaves the keys as they are, not the keyboard
+            // out
+            match reload_keymap() {
+                Ok(_) | Err(KeymapError::NoSource) => {}
+                Err(err) => log::warn!("keymap: {}", err),
+            }
+            Ok(())
+        })
+    }
+
+    fn run(&mut self) -> Option<RunFuture> {
+        Some(Box::pin(dispatch()))
+    }
+
+    fn shutdown(&mut self) {
+        shutdown();
+    }
+}
@@ -0,0 +1,150 @@
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::Stream;
+use pc_keyboard::{KeyCode, KeyEvent, KeyState};
+
+use super::{ModifierState, Modifiers};
+use crate::channel::{TrySendError, mpmc};
+
+/// Presses queued per listener before further ones are dropped
+const LISTENER_CAPACITY: usize = 8;
+
+/// A key combination such as Ctrl+C or Alt+F2.
+///
+/// Left and right modifiers are treated the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Hotkey {
+    pub modifiers: Modifiers,
+    pub key: KeyCode,
+}
+
+impl Hotkey {
+    pub const fn new(key: KeyCode) -> Self {
+        Hotkey {
+            modifiers: Modifiers::NONE,
+            key,
+        }
+    }
+
+    pub const fn shift(mut self) -> Self {
+        self.modifiers.shift = true;
+        self
+    }
+
+    pub const fn ctrl(mut self) -> Self {
+        self.modifiers.ctrl = true;
+        self
+    }
+
+    pub const fn alt(mut self) -> Self {
+        self.modifiers.alt = true;
+        self
+    }
+}
+
+static HOTKEYS: spin::Mutex<BTreeMap<Hotkey, Vec<mpmc::Sender<Hotkey>>>> =
+    spin::Mutex::new(BTreeMap::new());
+
+/// Get notified whenever `hotkey` is pressed.
+///
+/// While at least one listener for a combination is alive, its key presses
+/// are routed to the listeners only and never reach [`KeyEventStream`]s.
+///
+/// [`KeyEventStream`]: super::KeyEventStream
+pub fn register_hotkey(hotkey: Hotkey) -> HotkeyListener {
+    let (sender, receiver) = mpmc::channel(LISTENER_CAPACITY);
+    HOTKEYS.lock().entry(hotkey).or_default().push(sender);
+    HotkeyListener { receiver }
+}
+
+/// Call `callback` on every press of `hotkey`.
+///
+/// The returned future runs until the key stream ends, spawn it as a task.
+pub fn on_hotkey<F, Fut>(hotkey: Hotkey, mut callback: F) -> impl Future<Output = ()>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut listener = register_hotkey(hotkey);
+    async move {
+        while listener.recv().await.is_some() {
+            callback().await;
+        }
+    }
+}
+
+/// End every listener, called when the dispatcher stops
+pub(crate) fn close_all() {
+    HOTKEYS.lock().clear();
+}
+
+/// Presses of a registered [`Hotkey`], dropping it unregisters
+pub struct HotkeyListener {
+    receiver: mpmc::Receiver<Hotkey>,
+}
+
+impl HotkeyListener {
+    /// `None` once the keyboard has been shut down
+    pub async fn recv(&mut self) -> Option<Hotkey> {
+        self.receiver.recv().await
+    }
+}
+
+impl Stream for HotkeyListener {
+    type Item = Hotkey;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Hotkey>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+/// Hotkey matching done by the dispatcher before events are published
+pub(crate) struct Router {
+    // Keys whose press went to a hotkey, their release is swallowed as well
+    swallowed: Vec<KeyCode>,
+}
+
+impl Router {
+    pub(crate) fn new() -> Self {
+        Router {
+            swallowed: Vec::new(),
+        }
+    }
+
+    /// Returns `true` if the event was taken by a hotkey.
+    ///
+    /// `modifiers` must already include `event`.
+    pub(crate) fn route(&mut self, event: &KeyEvent, modifiers: &ModifierState) -> bool {
+        if event.state == KeyState::Up {
+            let swallowed = self.swallowed.iter().position(|code| *code == event.code);
+            return swallowed.map(|i| self.swallowed.swap_remove(i)).is_some();
+        }
+
+        let hotkey = Hotkey {
+            modifiers: modifiers.held(),
+            key: event.code,
+        };
+        let mut hotkeys = HOTKEYS.lock();
+        let Some(listeners) = hotkeys.get_mut(&hotkey) else {
+            return false;
+        };
+        listeners.retain(|listener| match listener.try_send(hotkey) {
+            Ok(()) | Err(TrySendError::Full(_)) => true,
+            Err(TrySendError::Closed(_)) => false,
+        });
+        if listeners.is_empty() {
+            hotkeys.remove(&hotkey);
+            return false;
+        }
+        // Auto-repeat sends more presses, only remember the key once
+        if !self.swallowed.contains(&event.code) {
+            self.swallowed.push(event.code);
+        }
+        true
+    }
+}
@@ -0,0 +1,58 @@
+use super::{ModifierState, watch_modifiers};
+use crate::{
+    i8042::{self, CommandError, Port},
+    log,
+};
+
+const SET_LEDS: u8 = 0xED;
+
+/// Lock indicator LEDs on the keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Leds {
+    pub scroll_lock: bool,
+    pub num_lock: bool,
+    pub caps_lock: bool,
+}
+
+impl Leds {
+    fn bits(&self) -> u8 {
+        (self.scroll_lock as u8) | (self.num_lock as u8) << 1 | (self.caps_lock as u8) << 2
+    }
+}
+
+impl From<&ModifierState> for Leds {
+    fn from(state: &ModifierState) -> Self {
+        Leds {
+            scroll_lock: state.scroll_lock(),
+            num_lock: state.num_lock(),
+            caps_lock: state.caps_lock(),
+        }
+    }
+}
+
+pub async fn set_leds(leds: Leds) -> Result<(), CommandError> {
+    i8042::command(Port::First, &[SET_LEDS, leds.bits()], 0).await?;
+    Ok(())
+}
+
+/// Keep the LEDs in sync with the lock state tracked by the dispatcher.
+///
+/// Spawn it next to [`dispatch`](super::dispatch), which maintains the lock
+/// state.
+pub async fn sync_leds() {
+    let mut modifiers = watch_modifiers();
+    let mut current = None;
+
+    loop {
+        let leds = Leds::from(&*modifiers.borrow_and_update());
+        if current != Some(leds) {
+            match set_leds(leds).await {
+                Ok(()) => current = Some(leds),
+                Err(err) => log::warn!("failed to set keyboard LEDs: {}", err),
+            }
+        }
+        if modifiers.changed().await.is_err() {
+            return;
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use alloc::string::String;
+
+use futures_util::StreamExt;
+
+use super::DecodedKeyStream;
+use crate::{
+    console,
+    editor::{Edit, LineEditor},
+};
+
+/// Reads whole lines from the keyboard, edited with a [`LineEditor`].
+///
+/// Keep one around between lines (a shell loop for instance), keys typed
+/// while the previous line is being handled are buffered by the subscription
+/// instead of being lost, and the history carries over.
+pub struct LineReader {
+    keys: DecodedKeyStream,
+    editor: LineEditor,
+}
+
+impl LineReader {
+    pub fn new() -> Self {
+        LineReader {
+            keys: DecodedKeyStream::new(),
+            editor: LineEditor::new(),
+        }
+    }
+
+    /// For setting up history and completion
+    pub fn editor(&mut self) -> &mut LineEditor {
+        &mut self.editor
+    }
+
+    /// Next line without the trailing newline.
+    ///
+    /// Returns what was typed so far if the key stream ends.
+    pub async fn read_line(&mut self) -> String {
+        self.editor.reset_display();
+        while let Some(key) = self.keys.next().await {
+            let edit = match self.editor.handle(key) {
+                Edit::Complete => self.editor.complete().await,
+                edit => edit,
+            };
+            match edit {
+                Edit::Submit(line) => {
+                    console::println!().await;
+                    return line;
+                }
+                Edit::Changed => console::write_string(self.editor.redraw()).await,
+                Edit::Candidates(words) => {
+                    self.editor.reset_display();
+                    console::print!("\n{}\n{}", words.join("  "), self.editor.redraw()).await;
+                }
+                Edit::Unchanged | Edit::EndOfInput | Edit::Complete => {}
+            }
+        }
+        let line = self.editor.text();
+        self.editor.set_text("");
+        line
+    }
+}
+
+impl Default for LineReader {
+    fn default() -> Self {
+        LineReader::new()
+    }
+}
+
+/// Read a single line, see [`LineReader`] for reading several
+pub async fn read_line() -> String {
+    LineReader::new().read_line().await
+}
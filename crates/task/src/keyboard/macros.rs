@@ -0,0 +1,114 @@
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use futures_util::StreamExt;
+use pc_keyboard::{KeyCode, KeyEvent, KeyState};
+
+use super::{
+    Hotkey, KeyEventStream, Modifiers, inject_key_event, register_hotkey, watch_modifiers,
+};
+use crate::{
+    future::{Either, race},
+    time::Instant,
+    timer,
+};
+
+/// Hotkeys controlling [`run_macros`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MacroKeys {
+    /// Start recording, or stop and keep what was recorded
+    pub record: Hotkey,
+    pub play: Hotkey,
+}
+
+impl Default for MacroKeys {
+    /// Ctrl+Alt+R and Ctrl+Alt+P
+    fn default() -> Self {
+        MacroKeys {
+            record: Hotkey::new(KeyCode::R).ctrl().alt(),
+            play: Hotkey::new(KeyCode::P).ctrl().alt(),
+        }
+    }
+}
+
+type Macro = Vec<(Duration, KeyEvent)>;
+
+/// Keyboard macros: record what is typed between two presses of
+/// `keys.record`, type it again with original timing on `keys.play`.
+///
+/// Spawn it next to [`dispatch`](super::dispatch). Playback injects the
+/// events into the dispatcher, so every subscriber sees them as typed keys.
+pub async fn run_macros(keys: MacroKeys) {
+    let mut record = register_hotkey(keys.record);
+    let mut play = register_hotkey(keys.play);
+    let mut recorded = Macro::new();
+    let mut recording: Option<(KeyEventStream, Instant)> = None;
+
+    loop {
+        let next_event = async {
+            match &mut recording {
+                Some((events, _)) => events.next().await,
+                None => core::future::pending().await,
+            }
+        };
+        match race(race(record.recv(), play.recv()), next_event).await {
+            Either::Left(Either::Left(None) | Either::Right(None)) => return,
+            Either::Left(Either::Left(Some(_))) => match recording.take() {
+                Some(_) => trim(&mut recorded),
+                None => {
+                    recorded.clear();
+                    recording = Some((KeyEventStream::new(), Instant::now()));
+                }
+            },
+            // Playing back while recording would record the playback
+            Either::Left(Either::Right(Some(_))) if recording.is_none() => {
+                play_back(&recorded).await;
+            }
+            Either::Left(Either::Right(Some(_))) => {}
+            Either::Right(Some(event)) => {
+                if let Some((_, started)) = &recording {
+                    recorded.push((started.elapsed(), event));
+                }
+            }
+            Either::Right(None) => return,
+        }
+    }
+}
+
+/// Drop the ends of the hotkey presses that started and stopped recording:
+/// releases of keys held before, and presses of keys still held at the end
+fn trim(recorded: &mut Macro) {
+    let mut down = Vec::new();
+    recorded.retain(|(_, event)| match event.state {
+        KeyState::Up => match down.iter().position(|code| *code == event.code) {
+            Some(i) => {
+                down.swap_remove(i);
+                true
+            }
+            None => false,
+        },
+        _ => {
+            if !down.contains(&event.code) {
+                down.push(event.code);
+            }
+            true
+        }
+    });
+    recorded.retain(|(_, event)| event.state == KeyState::Up || !down.contains(&event.code));
+}
+
+async fn play_back(recorded: &Macro) {
+    // The play hotkey's modifiers would change what the macro types
+    let mut modifiers = watch_modifiers();
+    while modifiers.borrow_and_update().held() != Modifiers::NONE {
+        if modifiers.changed().await.is_err() {
+            return;
+        }
+    }
+
+    let started = Instant::now();
+    for (at, event) in recorded {
+        timer::sleep_until(started + *at).await;
+        inject_key_event(event.clone());
+    }
+}
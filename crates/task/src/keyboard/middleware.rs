@@ -0,0 +1,96 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::{future::Future, mem, pin::Pin};
+
+/// Future returned by [`Middleware::process`]
+pub type ProcessFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+/// A stage between the scancode queue and decoding.
+///
+/// Stages see raw scancodes in the order they were registered, each one
+/// feeding the next. A stage can pass a scancode on, drop it or replace it
+/// with several, and may await (to log to a channel, say) while doing so.
+pub trait Middleware: Send {
+    /// Push whatever should go on to the next stage into `out`
+    fn process<'a>(&'a mut self, scancode: u8, out: &'a mut Vec<u8>) -> ProcessFuture<'a>;
+}
+
+/// Wraps a closure as a stage that doesn't need to await
+pub struct FnMiddleware<F>(F);
+
+pub fn middleware_fn<F>(f: F) -> FnMiddleware<F>
+where
+    F: FnMut(u8, &mut Vec<u8>) + Send,
+{
+    FnMiddleware(f)
+}
+
+impl<F> Middleware for FnMiddleware<F>
+where
+    F: FnMut(u8, &mut Vec<u8>) + Send,
+{
+    fn process<'a>(&'a mut self, scancode: u8, out: &'a mut Vec<u8>) -> ProcessFuture<'a> {
+        (self.0)(scancode, out);
+        Box::pin(async {})
+    }
+}
+
+/// Identifies a registered stage, for [`remove_middleware`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MiddlewareId(u64);
+
+struct Changes {
+    next_id: u64,
+    added: Vec<(MiddlewareId, Box<dyn Middleware>)>,
+    removed: Vec<MiddlewareId>,
+}
+
+// The dispatcher owns the chain so no lock is held while stages await,
+// changes are handed over here and applied before the next batch
+static CHANGES: spin::Mutex<Changes> = spin::Mutex::new(Changes {
+    next_id: 0,
+    added: Vec::new(),
+    removed: Vec::new(),
+});
+
+/// Append a stage to the end of the chain
+pub fn add_middleware(middleware: impl Middleware + 'static) -> MiddlewareId {
+    let mut changes = CHANGES.lock();
+    let id = MiddlewareId(changes.next_id);
+    changes.next_id += 1;
+    changes.added.push((id, Box::new(middleware)));
+    id
+}
+
+pub fn remove_middleware(id: MiddlewareId) {
+    CHANGES.lock().removed.push(id);
+}
+
+/// The chain as run by the dispatcher
+#[derive(Default)]
+pub(crate) struct Chain {
+    stages: Vec<(MiddlewareId, Box<dyn Middleware>)>,
+    // Spare buffer so a batch doesn't allocate per stage
+    scratch: Vec<u8>,
+}
+
+impl Chain {
+    /// Run `scancodes` through every stage, in place
+    pub(crate) async fn run(&mut self, scancodes: &mut Vec<u8>) {
+        self.apply_changes();
+        for (_, stage) in &mut self.stages {
+            self.scratch.clear();
+            for &scancode in scancodes.iter() {
+                stage.process(scancode, &mut self.scratch).await;
+            }
+            mem::swap(scancodes, &mut self.scratch);
+        }
+    }
+
+    fn apply_changes(&mut self) {
+        let mut changes = CHANGES.lock();
+        self.stages.append(&mut changes.added);
+        for id in changes.removed.drain(..) {
+            self.stages.retain(|(stage, _)| *stage != id);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use pc_keyboard::{KeyCode, KeyEvent, KeyState};
+
+use crate::channel::watch;
+
+/// Modifier keys that have to be held for a [`Hotkey`](super::Hotkey)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers {
+        shift: false,
+        ctrl: false,
+        alt: false,
+    };
+}
+
+/// Which modifier keys are held and which locks are on.
+///
+/// Maintained by [`dispatch`](super::dispatch) from the key events it
+/// decodes, so it reflects what subscribers are about to see.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ModifierState {
+    lshift: bool,
+    rshift: bool,
+    lctrl: bool,
+    rctrl: bool,
+    lalt: bool,
+    ralt: bool,
+    caps_lock: bool,
+    num_lock: bool,
+    scroll_lock: bool,
+    // Lock keys toggle on press, auto-repeat must not toggle them again
+    locks_held: [bool; 3],
+}
+
+impl ModifierState {
+    pub fn shift(&self) -> bool {
+        self.lshift || self.rshift
+    }
+
+    pub fn ctrl(&self) -> bool {
+        self.lctrl || self.rctrl
+    }
+
+    pub fn alt(&self) -> bool {
+        self.lalt || self.ralt
+    }
+
+    /// Right Alt, reported as AltGr on most layouts
+    pub fn alt_gr(&self) -> bool {
+        self.ralt
+    }
+
+    pub fn caps_lock(&self) -> bool {
+        self.caps_lock
+    }
+
+    pub fn num_lock(&self) -> bool {
+        self.num_lock
+    }
+
+    pub fn scroll_lock(&self) -> bool {
+        self.scroll_lock
+    }
+
+    /// Held modifiers, left and right merged
+    pub fn held(&self) -> Modifiers {
+        Modifiers {
+            shift: self.shift(),
+            ctrl: self.ctrl(),
+            alt: self.alt(),
+        }
+    }
+
+    /// Apply a key event, returns whether anything changed
+    pub(crate) fn update(&mut self, event: &KeyEvent) -> bool {
+        let before = *self;
+        let down = event.state != KeyState::Up;
+        match event.code {
+            KeyCode::LShift => self.lshift = down,
+            KeyCode::RShift => self.rshift = down,
+            KeyCode::LControl => self.lctrl = down,
+            KeyCode::RControl => self.rctrl = down,
+            KeyCode::LAlt => self.lalt = down,
+            KeyCode::RAltGr => self.ralt = down,
+            KeyCode::CapsLock => Self::toggle(&mut self.caps_lock, &mut self.locks_held[0], down),
+            KeyCode::NumpadLock => Self::toggle(&mut self.num_lock, &mut self.locks_held[1], down),
+            KeyCode::ScrollLock => {
+                Self::toggle(&mut self.scroll_lock, &mut self.locks_held[2], down)
+            }
+            _ => {}
+        }
+        *self != before
+    }
+
+    fn toggle(lock: &mut bool, held: &mut bool, down: bool) {
+        if down && !*held {
+            *lock = !*lock;
+        }
+        *held = down;
+    }
+}
+
+static MODIFIERS: spin::Once<watch::Sender<ModifierState>> = spin::Once::new();
+
+pub(crate) fn sender() -> &'static watch::Sender<ModifierState> {
+    MODIFIERS.call_once(|| watch::channel(ModifierState::default()).0)
+}
+
+/// Modifier state as of the last key event
+pub fn modifiers() -> ModifierState {
+    *sender().borrow()
+}
+
+/// Receiver for waiting on modifier changes with `changed().await`
+pub fn watch_modifiers() -> watch::Receiver<ModifierState> {
+    sender().subscribe()
+}
@@ -0,0 +1,70 @@
+use alloc::sync::Arc;
+use core::{future::Future, time::Duration};
+
+use super::{ScancodeSink, ScancodeSource};
+use crate::{
+    i8042::{Ports, STATUS_AUX_DATA, STATUS_OUTPUT_FULL},
+    mouse, timer,
+};
+
+/// Poll often enough that typing doesn't feel laggy
+pub const DEFAULT_POLL_PERIOD: Duration = Duration::from_millis(10);
+
+/// Bytes read per poll at most, a stuck status bit mustn't hang the task
+const MAX_BYTES_PER_POLL: usize = 32;
+
+/// Scancodes read by polling the controller instead of waiting for IRQ 1,
+/// for early boot or platforms without interrupt wiring.
+///
+/// Returns the source to pass to [`set_source`](super::set_source) and the
+/// polling task to spawn. The task ends once the source is replaced. Mouse
+/// bytes found along the way go to [`mouse`](crate::mouse).
+pub fn polling_source<P: Ports + 'static>(
+    ports: P,
+    period: Duration,
+) -> (PollingSource, impl Future<Output = ()>) {
+    let sink = Arc::new(spin::Mutex::new(None));
+    let source = PollingSource { sink: sink.clone() };
+    (source, poll(ports, period, sink))
+}
+
+pub struct PollingSource {
+    sink: Arc<spin::Mutex<Option<ScancodeSink>>>,
+}
+
+impl ScancodeSource for PollingSource {
+    fn start(&mut self, sink: ScancodeSink) {
+        *self.sink.lock() = Some(sink);
+    }
+}
+
+async fn poll<P: Ports>(
+    mut ports: P,
+    period: Duration,
+    sink: Arc<spin::Mutex<Option<ScancodeSink>>>,
+) {
+    let mut interval = timer::interval(period);
+    loop {
+        interval.tick().await;
+        // Not started yet
+        let Some(sink) = sink.lock().clone() else {
+            continue;
+        };
+        if !sink.is_active() {
+            return;
+        }
+
+        for _ in 0..MAX_BYTES_PER_POLL {
+            let status = ports.read_status();
+            if status & STATUS_OUTPUT_FULL == 0 {
+                break;
+            }
+            let byte = ports.read_data();
+            if status & STATUS_AUX_DATA != 0 {
+                mouse::add_mouse_byte(byte);
+            } else {
+                sink.push(byte);
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use crossbeam_queue::{ArrayQueue, SegQueue};
+
+use crate::metrics::Counter;
+
+static DROPPED: Counter = Counter::new(
+    "keyboard_scancodes_dropped_total",
+    "Scancodes lost because the scancode queue was full",
+);
+
+/// What to do with a scancode that arrives while the queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Keep what is queued and lose the new scancode
+    #[default]
+    DropNewest,
+    /// Make room by losing the oldest queued scancode
+    DropOldest,
+    /// Never drop, the queue allocates as needed. That's from the interrupt
+    /// handler, so only for hosted builds, where scancodes come from a task.
+    Grow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueConfig {
+    /// Ignored with [`Overflow::Grow`]
+    pub capacity: usize,
+    pub overflow: Overflow,
+}
+
+impl QueueConfig {
+    pub const fn new() -> Self {
+        QueueConfig {
+            capacity: 100,
+            overflow: Overflow::DropNewest,
+        }
+    }
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig::new()
+    }
+}
+
+/// Scancodes dropped on overflow since boot
+pub fn dropped_scancodes() -> u64 {
+    DROPPED.get()
+}
+
+/// Queue between the keyboard interrupt and `ScancodeStream`, neither side
+/// takes a lock
+pub(crate) enum ScancodeQueue {
+    Bounded(ArrayQueue<u8>, Overflow),
+    Unbounded(SegQueue<u8>),
+}
+
+impl ScancodeQueue {
+    pub(crate) fn new(config: QueueConfig) -> Self {
+        // Counted from the interrupt handler, which mustn't register it
+        DROPPED.register();
+        match config.overflow {
+            Overflow::Grow => ScancodeQueue::Unbounded(SegQueue::new()),
+            overflow => ScancodeQueue::Bounded(ArrayQueue::new(config.capacity), overflow),
+        }
+    }
+
+    /// Returns `false` if a scancode had to be dropped
+    pub(crate) fn push(&self, scancode: u8) -> bool {
+        let dropped = match self {
+            ScancodeQueue::Bounded(queue, Overflow::DropOldest) => {
+                queue.force_push(scancode).is_some()
+            }
+            ScancodeQueue::Bounded(queue, _) => queue.push(scancode).is_err(),
+            ScancodeQueue::Unbounded(queue) => {
+                queue.push(scancode);
+                false
+            }
+        };
+        if dropped {
+            DROPPED.inc();
+        }
+        !dropped
+    }
+
+    pub(crate) fn pop(&self) -> Option<u8> {
+        match self {
+            ScancodeQueue::Bounded(queue, _) => queue.pop(),
+            ScancodeQueue::Unbounded(queue) => queue.pop(),
+        }
+    }
+}
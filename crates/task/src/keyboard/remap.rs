@@ -0,0 +1,174 @@
+use alloc::{boxed::Box, collections::BTreeMap, format, string::String};
+use core::{fmt, str::FromStr};
+
+use pc_keyboard::{KeyCode, KeyEvent};
+
+/// Key remapping applied by the dispatcher before anything else sees an event.
+///
+/// Remapping is by physical key, so e.g. mapping `CapsLock` to `LControl`
+/// makes CapsLock act as Ctrl for hotkeys, modifier state and decoding alike.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Keymap {
+    map: BTreeMap<KeyCode, KeyCode>,
+}
+
+impl Keymap {
+    pub const fn new() -> Self {
+        Keymap {
+            map: BTreeMap::new(),
+        }
+    }
+
+    /// Make `from` act like `to`
+    pub fn remap(mut self, from: KeyCode, to: KeyCode) -> Self {
+        self.map.insert(from, to);
+        self
+    }
+
+    /// Exchange two keys
+    pub fn swap(self, a: KeyCode, b: KeyCode) -> Self {
+        self.remap(a, b).remap(b, a)
+    }
+
+    pub fn get(&self, code: KeyCode) -> KeyCode {
+        self.map.get(&code).copied().unwrap_or(code)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+/// Text form, one mapping per line:
+///
+/// ```text
+/// # CapsLock acts as Ctrl
+/// CapsLock = LControl
+/// # Exchange Y and Z
+/// Y <> Z
+/// ```
+///
+/// Keys are named like the [`KeyCode`] variants, ignoring case.
+impl FromStr for Keymap {
+    type Err = KeymapError;
+
+    fn from_str(text: &str) -> Result<Self, KeymapError> {
+        let mut keymap = Keymap::new();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (swap, (from, to)) = match line.split_once("<>") {
+                Some(pair) => (true, pair),
+                None => (
+                    false,
+                    line.split_once('=')
+                        .ok_or(KeymapError::Syntax(line_number))?,
+                ),
+            };
+            let key =
+                |name: &str| key_code(name.trim()).ok_or(KeymapError::UnknownKey(line_number));
+            let (from, to) = (key(from)?, key(to)?);
+            keymap = if swap {
+                keymap.swap(from, to)
+            } else {
+                keymap.remap(from, to)
+            };
+        }
+        Ok(keymap)
+    }
+}
+
+/// Writes the text form [`Keymap::from_str`] reads, one `from = to` per line
+impl fmt::Display for Keymap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (from, to) in &self.map {
+            writeln!(f, "{from:?} = {to:?}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeymapError {
+    /// No source set with [`set_keymap_source`], or it had nothing to give
+    NoSource,
+    /// Line isn't `from = to` or `a <> b`
+    Syntax(usize),
+    UnknownKey(usize),
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeymapError::NoSource => f.write_str("no keymap source"),
+            KeymapError::Syntax(line) => write!(f, "line {line}: expected `from = to` or `a <> b`"),
+            KeymapError::UnknownKey(line) => write!(f, "line {line}: unknown key"),
+        }
+    }
+}
+
+fn key_code(name: &str) -> Option<KeyCode> {
+    use KeyCode::*;
+    // Everything that can be remapped usefully, so not the controller's
+    // status codes
+    #[rustfmt::skip]
+    const KEYS: &[KeyCode] = &[
+        Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12, PrintScreen, SysRq,
+        ScrollLock, PauseBreak, Oem8, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+        OemMinus, OemPlus, Backspace, Insert, Home, PageUp, NumpadLock, NumpadDivide,
+        NumpadMultiply, NumpadSubtract, Tab, Q, W, E, R, T, Y, U, I, O, P, Oem4, Oem6, Oem5, Oem7,
+        Delete, End, PageDown, Numpad7, Numpad8, Numpad9, NumpadAdd, CapsLock, A, S, D, F, G, H,
+        J, K, L, Oem1, Oem3, Return, Numpad4, Numpad5, Numpad6, LShift, Z, X, C, V, B, N, M,
+        OemComma, OemPeriod, Oem2, RShift, ArrowUp, Numpad1, Numpad2, Numpad3, NumpadEnter,
+        LControl, LWin, LAlt, Spacebar, RAltGr, RWin, Apps, RControl, ArrowLeft, ArrowDown,
+        ArrowRight, Numpad0, NumpadPeriod, Oem9, Oem10, Oem11, Oem12, Oem13, PrevTrack,
+        NextTrack, Mute, Calculator, Play, Stop, VolumeDown, VolumeUp, WWWHome,
+    ];
+    KEYS.iter()
+        .copied()
+        .find(|code| format!("{code:?}").eq_ignore_ascii_case(name))
+}
+
+type KeymapSource = Box<dyn Fn() -> Option<String> + Send>;
+
+static SOURCE: spin::Mutex<Option<KeymapSource>> = spin::Mutex::new(None);
+
+/// Where [`reload_keymap`] reads the keymap text from, a file or a blob
+/// built into the kernel for instance
+pub fn set_keymap_source(source: impl Fn() -> Option<String> + Send + 'static) {
+    *SOURCE.lock() = Some(Box::new(source));
+}
+
+/// Read the keymap source again and switch to it, for a `loadkeys`-style
+/// command.
+///
+/// On error the current keymap stays.
+pub fn reload_keymap() -> Result<Keymap, KeymapError> {
+    let text = SOURCE
+        .lock()
+        .as_ref()
+        .and_then(|source| source())
+        .ok_or(KeymapError::NoSource)?;
+    let keymap: Keymap = text.parse()?;
+    set_keymap(keymap.clone());
+    Ok(keymap)
+}
+
+static KEYMAP: spin::Mutex<Keymap> = spin::Mutex::new(Keymap::new());
+
+pub fn keymap() -> Keymap {
+    KEYMAP.lock().clone()
+}
+
+/// Takes effect with the next key event
+pub fn set_keymap(keymap: Keymap) {
+    *KEYMAP.lock() = keymap;
+}
+
+pub(crate) fn apply(mut event: KeyEvent) -> KeyEvent {
+    event.code = KEYMAP.lock().get(event.code);
+    event
+}
@@ -0,0 +1,115 @@
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use pc_keyboard::{KeyCode, KeyEvent, KeyState};
+
+use crate::{
+    time::Instant,
+    timer::{self, Sleep},
+};
+
+/// Typematic repeat done in software with the timer subsystem.
+///
+/// A held key is pressed again after `delay`, then every `interval` until it
+/// is released. Hardware repeats are dropped while this is enabled so keys
+/// don't repeat twice as fast.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyRepeat {
+    pub delay: Duration,
+    pub interval: Duration,
+}
+
+impl KeyRepeat {
+    /// Half a second delay, then 30 presses a second
+    pub const DEFAULT: KeyRepeat = KeyRepeat {
+        delay: Duration::from_millis(500),
+        interval: Duration::from_millis(33),
+    };
+
+    pub const fn new(delay: Duration, interval: Duration) -> Self {
+        KeyRepeat { delay, interval }
+    }
+}
+
+impl Default for KeyRepeat {
+    fn default() -> Self {
+        KeyRepeat::DEFAULT
+    }
+}
+
+/// Modifiers and locks are only ever held, never repeated
+fn repeats(code: KeyCode) -> bool {
+    !matches!(
+        code,
+        KeyCode::LShift
+            | KeyCode::RShift
+            | KeyCode::LControl
+            | KeyCode::RControl
+            | KeyCode::LAlt
+            | KeyCode::RAltGr
+            | KeyCode::LWin
+            | KeyCode::RWin
+            | KeyCode::CapsLock
+            | KeyCode::NumpadLock
+            | KeyCode::ScrollLock
+    )
+}
+
+/// Repeat state kept by the dispatcher
+pub(crate) struct Repeater {
+    // Key being repeated and how
+    held: Option<(KeyEvent, KeyRepeat)>,
+    sleep: Sleep,
+}
+
+impl Repeater {
+    pub(crate) fn new() -> Self {
+        Repeater {
+            held: None,
+            sleep: timer::sleep_until(Instant::now()),
+        }
+    }
+
+    /// Track a decoded event, returns `false` for hardware repeats to drop
+    pub(crate) fn key(&mut self, event: &KeyEvent, repeat: Option<KeyRepeat>) -> bool {
+        let Some(repeat) = repeat else {
+            self.held = None;
+            return true;
+        };
+        let held = self.held.as_ref().map(|(held, _)| held.code);
+
+        match event.state {
+            KeyState::Down if held == Some(event.code) => false,
+            KeyState::Down if repeats(event.code) => {
+                self.held = Some((event.clone(), repeat));
+                self.sleep.reset(Instant::now() + repeat.delay);
+                true
+            }
+            KeyState::Up if held == Some(event.code) => {
+                self.held = None;
+                true
+            }
+            _ => true,
+        }
+    }
+
+    /// Ready with another press of the held key once it is due
+    pub(crate) fn poll_repeat(&mut self, cx: &mut Context) -> Poll<KeyEvent> {
+        // Only `key` can start a repeat and it runs on the same task, so
+        // there is nothing to wake us for while no key is held
+        let Some((event, repeat)) = &self.held else {
+            return Poll::Pending;
+        };
+        if Pin::new(&mut self.sleep).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        // Counted from now, a busy executor shouldn't cause a burst of presses
+        let event = event.clone();
+        self.sleep.reset(Instant::now() + repeat.interval);
+        Poll::Ready(event)
+    }
+}
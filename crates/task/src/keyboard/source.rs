@@ -0,0 +1,193 @@
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use super::{add_scancode, ascii};
+
+/// Where scancodes come from.
+///
+/// The active source pushes scancodes into the keyboard pipeline through the
+/// [`ScancodeSink`] it is started with. Pick one with [`set_source`], until
+/// then the [`IrqSource`] is active.
+pub trait ScancodeSource: Send {
+    /// Begin producing, every scancode goes to `sink`
+    fn start(&mut self, sink: ScancodeSink);
+
+    /// Called when another source replaces this one
+    fn stop(&mut self) {}
+}
+
+// Identifies the active source, sinks of replaced sources stop working
+static GENERATION: AtomicU64 = AtomicU64::new(IRQ_DEFAULT);
+static SOURCE: spin::Mutex<Option<Box<dyn ScancodeSource>>> = spin::Mutex::new(None);
+
+/// Generation of the implicit IRQ source active at boot
+const IRQ_DEFAULT: u64 = 1;
+
+/// Replace the active scancode source, stopping the previous one
+pub fn set_source(source: impl ScancodeSource + 'static) {
+    let mut current = SOURCE.lock();
+    let generation = GENERATION.fetch_add(1, Ordering::AcqRel) + 1;
+    IRQ_GENERATION.store(0, Ordering::Release);
+    if let Some(mut old) = current.take() {
+        old.stop();
+    }
+
+    let mut source: Box<dyn ScancodeSource> = Box::new(source);
+    source.start(ScancodeSink { generation });
+    *current = Some(source);
+}
+
+/// Handle for a source to deliver scancodes with
+#[derive(Debug, Clone)]
+pub struct ScancodeSink {
+    generation: u64,
+}
+
+impl ScancodeSink {
+    /// Returns `false` once the source has been replaced
+    pub fn push(&self, scancode: u8) -> bool {
+        if !self.is_active() {
+            return false;
+        }
+        add_scancode(scancode);
+        true
+    }
+
+    pub fn is_active(&self) -> bool {
+        GENERATION.load(Ordering::Acquire) == self.generation
+    }
+}
+
+// Generation while the IRQ source is active, 0 otherwise. An atomic since
+// it is read from the interrupt handler, which must not take a lock.
+static IRQ_GENERATION: AtomicU64 = AtomicU64::new(IRQ_DEFAULT);
+
+/// Scancodes read by the keyboard interrupt handler
+pub struct IrqSource;
+
+impl IrqSource {
+    /// Call from the keyboard interrupt handler with the byte read from the
+    /// controller's data port
+    pub fn handle_interrupt(scancode: u8) {
+        let generation = IRQ_GENERATION.load(Ordering::Acquire);
+        if generation != 0 {
+            ScancodeSink { generation }.push(scancode);
+        }
+    }
+}
+
+impl ScancodeSource for IrqSource {
+    fn start(&mut self, sink: ScancodeSink) {
+        IRQ_GENERATION.store(sink.generation, Ordering::Release);
+    }
+
+    fn stop(&mut self) {
+        IRQ_GENERATION.store(0, Ordering::Release);
+    }
+}
+
+/// Typed text from the host terminal, for running on a std build.
+///
+/// Characters are converted to scancode set 1 as if typed on a US keyboard,
+/// so use [`ScancodeSetKind::Set1`](super::ScancodeSetKind::Set1) and
+/// [`Layout::Us104Key`](super::Layout::Us104Key). The terminal is line
+/// buffered, so input arrives when Enter is pressed.
+#[cfg(feature = "std")]
+pub struct TerminalSource;
+
+#[cfg(feature = "std")]
+impl ScancodeSource for TerminalSource {
+    fn start(&mut self, sink: ScancodeSink) {
+        // Blocking reads get their own thread. After `stop` the thread
+        // exits with the next byte it reads.
+        std::thread::spawn(move || {
+            use std::io::Read;
+
+            let mut scancodes = Vec::new();
+            for byte in std::io::stdin().lock().bytes() {
+                let Ok(byte) = byte else {
+                    return;
+                };
+                scancodes.clear();
+                ascii::type_char(byte as char, &mut scancodes);
+                for &scancode in &scancodes {
+                    if !sink.push(scancode) {
+                        return;
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Feed a scancode into the keyboard pipeline as if the keyboard interrupt
+/// had read it.
+///
+/// Works whatever source is active, for automated tests of anything that
+/// reads the keyboard.
+pub fn inject(scancode: u8) {
+    super::add_scancode(scancode);
+}
+
+/// Type `text` through [`inject`] in scancode set 1 on a US layout.
+///
+/// Returns `false` if some characters couldn't be typed, the rest are typed
+/// anyway.
+pub fn inject_str(text: &str) -> bool {
+    let mut scancodes = Vec::new();
+    let mut all_typed = true;
+    for character in text.chars() {
+        all_typed &= ascii::type_char(character, &mut scancodes);
+    }
+    scancodes.into_iter().for_each(inject);
+    all_typed
+}
+
+/// Scancodes pushed by hand, for scripted input that replaces the keyboard.
+///
+/// For tests running alongside the real keyboard, [`inject`] is simpler.
+/// Keep a clone after handing it to [`set_source`]:
+///
+/// ```ignore
+/// let injector = Injector::new();
+/// keyboard::set_source(injector.clone());
+/// injector.inject_str("help\n");
+/// ```
+#[derive(Clone, Default)]
+pub struct Injector {
+    sink: Arc<spin::Mutex<Option<ScancodeSink>>>,
+}
+
+impl Injector {
+    pub fn new() -> Self {
+        Injector::default()
+    }
+
+    /// Returns `false` if this injector isn't the active source
+    pub fn inject(&self, scancode: u8) -> bool {
+        match &*self.sink.lock() {
+            Some(sink) => sink.push(scancode),
+            None => false,
+        }
+    }
+
+    /// Type `text` in scancode set 1 on a US layout, characters that can't
+    /// be typed are skipped
+    pub fn inject_str(&self, text: &str) -> bool {
+        let mut scancodes = Vec::new();
+        for character in text.chars() {
+            ascii::type_char(character, &mut scancodes);
+        }
+        scancodes.into_iter().all(|scancode| self.inject(scancode))
+    }
+}
+
+impl ScancodeSource for Injector {
+    fn start(&mut self, sink: ScancodeSink) {
+        *self.sink.lock() = Some(sink);
+    }
+
+    fn stop(&mut self) {
+        *self.sink.lock() = None;
+    }
+}
@@ -1,40 +1,371 @@
 //!
 //! Task
 //!
+//! Builds `no_std` + `alloc` without the default `std` feature, leaving out
+//! the modules that need an operating system underneath.
+//! The subsystems are features of their own, `timers`, `channels`,
+//! `keyboard`, `fs`, `net` and `shell`, so a kernel can leave out what it
+//! doesn't use.
+//!
+
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+#[cfg(feature = "channels")]
+pub mod actor;
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod block;
+pub mod bytes;
+pub mod capability;
+#[cfg(feature = "channels")]
+pub mod channel;
+#[cfg(feature = "keyboard")]
+pub mod clipboard;
+#[cfg(feature = "timers")]
+pub mod compat;
+pub mod config;
+pub mod console;
+mod context;
+pub mod demo;
+pub mod dma;
+pub mod drivers;
+#[cfg(feature = "keyboard")]
+pub mod editor;
+#[cfg(feature = "embedded-hal")]
+pub mod embedded;
+#[cfg(feature = "channels")]
+pub mod env;
+#[cfg(feature = "channels")]
+pub mod event;
 pub mod executor;
+pub mod fallible;
+#[cfg(feature = "fs")]
+pub mod fs;
+pub mod future;
+#[cfg(all(feature = "bare-metal", target_arch = "x86_64"))]
+pub mod gdb;
+#[cfg(feature = "std")]
+pub mod gfx;
+#[cfg(feature = "keyboard")]
+pub mod i8042;
+pub mod idle;
+#[cfg(feature = "keyboard")]
+pub mod input;
+#[cfg(feature = "std")]
+pub mod io;
+pub mod irq;
+pub mod join_set;
+#[cfg(feature = "keyboard")]
 pub mod keyboard;
+#[cfg(all(feature = "fs", target_os = "linux", target_arch = "x86_64"))]
+pub mod loader;
+pub mod log;
+pub mod memory;
+pub mod metrics;
+#[cfg(feature = "keyboard")]
+pub mod mouse;
+#[cfg(feature = "net")]
+pub mod net;
+pub mod panic;
+#[cfg(all(feature = "bare-metal", target_arch = "x86_64"))]
+pub mod pc;
+#[cfg(feature = "std")]
+pub mod pipe;
+#[cfg(all(feature = "timers", feature = "channels"))]
+pub mod power;
+pub mod priority;
+pub mod profiler;
+pub mod rng;
+#[cfg(feature = "std")]
+pub mod serial;
+#[cfg(feature = "channels")]
+pub mod services;
+#[cfg(feature = "shell")]
+pub mod shell;
+#[cfg(all(feature = "timers", feature = "channels"))]
+pub mod shutdown;
+#[cfg(feature = "keyboard")]
+pub mod signal;
+#[cfg(feature = "timers")]
+pub mod speaker;
+pub mod stream;
+#[cfg(feature = "std")]
+pub mod supervisor;
+pub mod sync;
+pub mod time;
+#[cfg(feature = "timers")]
+pub mod timer;
+pub mod trace;
+#[cfg(all(feature = "std", feature = "keyboard"))]
+pub mod tty;
+#[cfg(all(feature = "std", feature = "keyboard"))]
+pub mod tui;
+#[cfg(feature = "std")]
+pub mod virtio;
+pub mod waker;
+pub mod watchdog;
 
-use core::{future::Future, pin::Pin};
-use std::{
+use alloc::{boxed::Box, string::String};
+use capability::{Capabilities, Grant};
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
     sync::atomic::{AtomicU64, Ordering},
     task::{Context, Poll},
+    time::Duration,
 };
+use priority::Priority;
+
+/// For the crate's macros, which can't assume `extern crate alloc`
+#[doc(hidden)]
+pub mod __private {
+    pub use alloc::format;
+}
 
+/// A task whose future is `Send`, so whichever worker gets to it can poll
+/// it. Spawn one with `spawn`, a [`LocalTask`] with `spawn_local`.
 pub struct Task {
+    options: TaskOptions,
+    future: TaskFuture<dyn Future<Output = ()> + Send>,
+}
+
+/// A task whose future needs not be `Send`, polled by the worker it was
+/// spawned on. Every [`Task`] converts into one.
+pub struct LocalTask {
+    options: TaskOptions,
+    future: TaskFuture<dyn Future<Output = ()>>,
+}
+
+// Where a task's future lives, in a `Box` of its own unless spawned through
+// a `memory::Arena`
+enum TaskFuture<F: ?Sized> {
+    Boxed(Pin<Box<F>>),
+    Arena(memory::ArenaFuture<F>),
+}
+
+impl<F: ?Sized + Future> TaskFuture<F> {
+    fn poll(&mut self, context: &mut Context) -> Poll<F::Output> {
+        match self {
+            TaskFuture::Boxed(future) => future.as_mut().poll(context),
+            TaskFuture::Arena(future) => Pin::new(future).poll(context),
+        }
+    }
+}
+
+// What both kinds of task carry besides the future
+struct TaskOptions {
     id: TaskId,
-    future: Pin<Box<dyn Future<Output = ()>>>,
+    priority: Priority,
+    name: Option<String>,
+    capabilities: Grant,
+    deadline: Option<Duration>,
+    time_limit: Option<(Duration, watchdog::OnOverrun)>,
+    #[cfg(feature = "channels")]
+    env: Option<env::Env>,
+    vt: Option<usize>,
+}
+
+impl TaskOptions {
+    fn new(priority: Priority) -> Self {
+        TaskOptions {
+            id: TaskId::new(),
+            priority,
+            name: None,
+            // Whatever creates the task can't hand out more than it has
+            capabilities: capability::current(),
+            deadline: None,
+            time_limit: None,
+            // Shared with the task creating it, if that has one
+            #[cfg(feature = "channels")]
+            env: env::own(),
+            vt: console::vt::own(),
+        }
+    }
 }
 
 impl Task {
-    pub fn new(future: impl Future<Output = ()> + 'static) -> Task {
+    pub fn new(future: impl Future<Output = ()> + Send + 'static) -> Task {
+        Task::with_priority(future, Priority::Normal)
+    }
+
+    pub fn with_priority(
+        future: impl Future<Output = ()> + Send + 'static,
+        priority: Priority,
+    ) -> Task {
         Task {
-            id: TaskId::new(),
-            future: Box::pin(future),
+            options: TaskOptions::new(priority),
+            future: TaskFuture::Boxed(Box::pin(future)),
+        }
+    }
+
+    fn from_future(future: TaskFuture<dyn Future<Output = ()> + Send>) -> Task {
+        Task {
+            options: TaskOptions::new(Priority::Normal),
+            future,
+        }
+    }
+}
+
+impl LocalTask {
+    pub fn new(future: impl Future<Output = ()> + 'static) -> LocalTask {
+        LocalTask::with_priority(future, Priority::Normal)
+    }
+
+    pub fn with_priority(
+        future: impl Future<Output = ()> + 'static,
+        priority: Priority,
+    ) -> LocalTask {
+        LocalTask {
+            options: TaskOptions::new(priority),
+            future: TaskFuture::Boxed(Box::pin(future)),
+        }
+    }
+
+    fn from_future(future: TaskFuture<dyn Future<Output = ()>>) -> LocalTask {
+        LocalTask {
+            options: TaskOptions::new(Priority::Normal),
+            future,
         }
     }
 
     fn poll(&mut self, context: &mut Context) -> Poll<()> {
-        self.future.as_mut().poll(context)
+        self.future.poll(context)
+    }
+}
+
+impl From<Task> for LocalTask {
+    fn from(task: Task) -> LocalTask {
+        let future: TaskFuture<dyn Future<Output = ()>> = match task.future {
+            TaskFuture::Boxed(future) => TaskFuture::Boxed(future),
+            TaskFuture::Arena(future) => TaskFuture::Arena(future.into_local()),
+        };
+        LocalTask {
+            options: task.options,
+            future,
+        }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-struct TaskId(u64);
+/// The options both kinds of task take
+macro_rules! task_options {
+    ($task:ident) => {
+        impl $task {
+            /// Name shown by [`executor::tasks`]
+            pub fn with_name(mut self, name: impl Into<String>) -> $task {
+                self.options.name = Some(name.into());
+                self
+            }
+
+            /// Restrict the task to `capabilities`, less any the task creating it
+            /// doesn't have
+            pub fn with_capabilities(mut self, capabilities: Capabilities) -> $task {
+                self.options.capabilities =
+                    Some(capability::narrow(capabilities, &self.options.capabilities));
+                self
+            }
+
+            /// Expect every poll within `deadline` of the wake before it, later
+            /// ones are counted as misses
+            pub fn with_deadline(mut self, deadline: Duration) -> $task {
+                self.options.deadline = Some(deadline);
+                self
+            }
+
+            /// Expect the task to finish within `limit` of being spawned, see
+            /// [`watchdog`](crate::watchdog)
+            pub fn with_time_limit(
+                mut self,
+                limit: Duration,
+                on_overrun: watchdog::OnOverrun,
+            ) -> $task {
+                self.options.time_limit = Some((limit, on_overrun));
+                self
+            }
+
+            /// Run the task in `env` instead of its creator's, see
+            /// [`env`](crate::env)
+            #[cfg(feature = "channels")]
+            pub fn with_env(mut self, env: env::Env) -> $task {
+                self.options.env = Some(env);
+                self
+            }
+
+            /// Write the task's console output to virtual terminal `vt`
+            /// instead of its creator's, see [`console::vt`]. Panics if it
+            /// isn't below [`console::vt::COUNT`].
+            pub fn with_vt(mut self, vt: usize) -> $task {
+                assert!(vt < console::vt::COUNT, "no virtual terminal {}", vt);
+                self.options.vt = Some(vt);
+                self
+            }
+
+            pub fn id(&self) -> TaskId {
+                self.options.id
+            }
+
+            pub fn priority(&self) -> Priority {
+                self.options.priority
+            }
+
+            pub fn name(&self) -> Option<&str> {
+                self.options.name.as_deref()
+            }
+
+            pub fn deadline(&self) -> Option<Duration> {
+                self.options.deadline
+            }
+
+            pub fn time_limit(&self) -> Option<Duration> {
+                self.options.time_limit.map(|(limit, _)| limit)
+            }
+
+            /// `None` if the task uses the [global](crate::env::global) one
+            #[cfg(feature = "channels")]
+            pub fn env(&self) -> Option<&env::Env> {
+                self.options.env.as_ref()
+            }
+
+            /// `None` if the task writes to the first virtual terminal
+            pub fn vt(&self) -> Option<usize> {
+                self.options.vt
+            }
+
+            /// `None` if the task is unrestricted
+            pub fn capabilities(&self) -> Option<&Capabilities> {
+                self.options.capabilities.as_deref()
+            }
+        }
+    };
+}
+
+task_options!(Task);
+task_options!(LocalTask);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TaskId(u64);
 
 impl TaskId {
     fn new() -> Self {
         static NEXT_ID: AtomicU64 = AtomicU64::new(0);
         TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
     }
+
+    pub fn as_u64(self) -> u64 {
+        self.0
+    }
+
+    /// The id shown as `id`, by `ps` say, to look the task up with. No task
+    /// need have it.
+    pub const fn from_u64(id: u64) -> Self {
+        TaskId(id)
+    }
+}
+
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
 }
@@ -0,0 +1,333 @@
+//!
+//! Program loader
+//!
+//! Loads a static x86_64 ELF executable from the filesystem at the
+//! addresses it was linked for and runs it as a task of its own, with the
+//! capabilities it's given:
+//!
+//! ```ignore
+//! let program = loader::load("/bin/hello").await?;
+//! let caps = Capabilities::none().with(Capability::ConsoleWrite);
+//! let (task, exit_code) = loader::spawn(program, caps);
+//! executor.spawn_local(task);
+//! ```
+//!
+//! A program has no libc and talks to the OS only through the [`Api`] its
+//! entry point is called with:
+//!
+//! ```ignore
+//! #[unsafe(no_mangle)]
+//! extern "C" fn _start(api: &Api) -> i32 {
+//!     (api.write)(api.context, b"hello\n".as_ptr(), 6);
+//!     0
+//! }
+//! ```
+//!
+//! It runs to completion in one poll of its task, so a program that takes
+//! its time holds up the executor. What it writes reaches the console
+//! through the [`services`](crate::services) once it returns.
+//!
+//! Hosted on Linux the segments are mapped with `mmap`, which fails if the
+//! addresses are taken.
+//!
+
+mod elf;
+
+use alloc::{string::String, vec::Vec};
+use core::{ffi::c_void, fmt, mem, ptr, slice};
+
+use crate::{
+    LocalTask,
+    capability::{self, Capabilities, Capability},
+    channel::oneshot,
+    dma::PAGE_SIZE,
+    fs, log, services, time,
+};
+
+use elf::{Executable, PF_R, PF_W, PF_X};
+
+/// The [`Api`] layout programs are built against
+pub const API_VERSION: u32 = 1;
+
+/// Programs spanning more memory than this aren't loaded
+pub const MAX_IMAGE_SIZE: usize = 64 << 20;
+
+/// Handed to a program's entry point, its way to the OS
+#[repr(C)]
+#[derive(Debug)]
+pub struct Api {
+    /// [`API_VERSION`]
+    pub version: u32,
+    /// Passed back to the functions below
+    pub context: *mut c_void,
+    /// Write `len` bytes to the console. The number of bytes written, `-1`
+    /// if the program isn't allowed to.
+    pub write: extern "C" fn(context: *mut c_void, bytes: *const u8, len: usize) -> isize,
+    /// Milliseconds since boot
+    pub uptime_ms: extern "C" fn() -> u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadError {
+    Fs(fs::Error),
+    /// The file doesn't start like an ELF file
+    NotElf,
+    /// An ELF file, but not one this loads
+    Unsupported(&'static str),
+    /// An ELF file that makes no sense
+    Malformed(&'static str),
+    /// Spans more than [`MAX_IMAGE_SIZE`]
+    TooLarge,
+    /// Something is mapped where the program wants to be
+    AddressInUse,
+    /// Mapping the segments failed with this `errno`
+    Map(i32),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Fs(err) => err.fmt(f),
+            LoadError::NotElf => f.write_str("not an ELF file"),
+            LoadError::Unsupported(what) => write!(f, "unsupported executable: {}", what),
+            LoadError::Malformed(what) => write!(f, "malformed executable: {}", what),
+            LoadError::TooLarge => f.write_str("executable too large"),
+            LoadError::AddressInUse => f.write_str("load address in use"),
+            LoadError::Map(errno) => write!(f, "mapping segments failed: errno {}", errno),
+        }
+    }
+}
+
+impl From<fs::Error> for LoadError {
+    fn from(err: fs::Error) -> Self {
+        LoadError::Fs(err)
+    }
+}
+
+/// Memory the segments are mapped into, unmapped on drop
+struct Image {
+    start: *mut u8,
+    len: usize,
+}
+
+impl Image {
+    fn map(executable: &Executable, bytes: &[u8]) -> Result<Image, LoadError> {
+        let start = executable
+            .segments
+            .iter()
+            .map(|segment| segment.address)
+            .min()
+            .ok_or(LoadError::Malformed("nothing to load"))?
+            / PAGE_SIZE
+            * PAGE_SIZE;
+        let end = executable
+            .segments
+            .iter()
+            .map(|segment| segment.address + segment.memory_size)
+            .max()
+            .unwrap_or(start)
+            .next_multiple_of(PAGE_SIZE);
+        let len = end - start;
+        if len > MAX_IMAGE_SIZE {
+            return Err(LoadError::TooLarge);
+        }
+
+        // SAFETY: MAP_FIXED_NOREPLACE never replaces an existing mapping
+        let mapped = unsafe {
+            libc::mmap(
+                start as *mut c_void,
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_FIXED_NOREPLACE,
+                -1,
+                0,
+            )
+        };
+        if mapped == libc::MAP_FAILED {
+            return Err(match errno() {
+                libc::EEXIST => LoadError::AddressInUse,
+                errno => LoadError::Map(errno),
+            });
+        }
+        let image = Image {
+            start: mapped.cast(),
+            len,
+        };
+        // Kernels before 4.17 take the flag as a hint
+        if mapped as usize != start {
+            return Err(LoadError::AddressInUse);
+        }
+
+        // Zeroed already, the rest of each segment stays that way
+        for segment in &executable.segments {
+            // SAFETY: the segment is inside the mapping and the file
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    bytes[segment.offset..].as_ptr(),
+                    segment.address as *mut u8,
+                    segment.file_size,
+                );
+            }
+        }
+        image.protect(executable)?;
+        Ok(image)
+    }
+
+    /// Give each page what the segments in it need
+    fn protect(&self, executable: &Executable) -> Result<(), LoadError> {
+        let start = self.start as usize;
+        let mut pages = vec![0; self.len / PAGE_SIZE];
+        for segment in &executable.segments {
+            let first = (segment.address - start) / PAGE_SIZE;
+            let last = (segment.address + segment.memory_size - start).div_ceil(PAGE_SIZE);
+            for page in &mut pages[first..last] {
+                *page |= segment.flags;
+            }
+        }
+        for (index, run) in pages.chunk_by(|a, b| a == b).scan(0, |index, run| {
+            let first = *index;
+            *index += run.len();
+            Some((first, run))
+        }) {
+            let flags = run[0];
+            let mut protection = libc::PROT_NONE;
+            if flags & PF_R != 0 {
+                protection |= libc::PROT_READ;
+            }
+            if flags & PF_W != 0 {
+                protection |= libc::PROT_WRITE;
+            }
+            if flags & PF_X != 0 {
+                protection |= libc::PROT_EXEC;
+            }
+            // SAFETY: pages of our own mapping
+            let result = unsafe {
+                libc::mprotect(
+                    self.start.add(index * PAGE_SIZE).cast(),
+                    run.len() * PAGE_SIZE,
+                    protection,
+                )
+            };
+            if result != 0 {
+                return Err(LoadError::Map(errno()));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        // SAFETY: mapped in `map`, nothing points in once the program is done
+        unsafe { libc::munmap(self.start.cast(), self.len) };
+    }
+}
+
+fn errno() -> i32 {
+    std::io::Error::last_os_error().raw_os_error().unwrap_or(0)
+}
+
+/// A loaded program, ready to [`spawn`]
+pub struct Program {
+    name: String,
+    image: Image,
+    entry: usize,
+}
+
+impl Program {
+    /// The path it was loaded from
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Where it starts running
+    pub fn entry(&self) -> usize {
+        self.entry
+    }
+}
+
+impl fmt::Debug for Program {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Program")
+            .field("name", &self.name)
+            .field("start", &self.image.start)
+            .field("len", &self.image.len)
+            .field("entry", &(self.entry as *const u8))
+            .finish()
+    }
+}
+
+/// Read the executable at `path` and map it
+pub async fn load(path: &str) -> Result<Program, LoadError> {
+    let bytes = fs::read(path).await?;
+    load_bytes(path, &bytes)
+}
+
+/// Map the executable in `bytes`, `name` is for [`Program::name`]
+pub fn load_bytes(name: &str, bytes: &[u8]) -> Result<Program, LoadError> {
+    let executable = elf::parse(bytes)?;
+    let image = Image::map(&executable, bytes)?;
+    Ok(Program {
+        name: name.into(),
+        image,
+        entry: executable.entry,
+    })
+}
+
+/// The task running `program` with `capabilities`, named after it, and its
+/// exit code once it's done
+pub fn spawn(program: Program, capabilities: Capabilities) -> (LocalTask, oneshot::Receiver<i32>) {
+    let (exit, exit_code) = oneshot::channel();
+    let name = program.name.clone();
+    let task = LocalTask::new(async move {
+        let _ = exit.send(run(program).await);
+    })
+    .with_name(name)
+    .with_capabilities(capabilities);
+    (task, exit_code)
+}
+
+extern "C" fn write(context: *mut c_void, bytes: *const u8, len: usize) -> isize {
+    if capability::check(&Capability::ConsoleWrite).is_err() {
+        return -1;
+    }
+    // SAFETY: `context` is the output buffer `run` set up, `bytes` is the
+    // program's to vouch for
+    let (output, bytes) = unsafe {
+        (
+            &mut *context.cast::<Vec<u8>>(),
+            slice::from_raw_parts(bytes, len),
+        )
+    };
+    output.extend_from_slice(bytes);
+    len as isize
+}
+
+extern "C" fn uptime_ms() -> u64 {
+    time::uptime().as_millis() as u64
+}
+
+async fn run(program: Program) -> i32 {
+    let mut output = Vec::new();
+    let code = {
+        let api = Api {
+            version: API_VERSION,
+            context: (&mut output as *mut Vec<u8>).cast(),
+            write,
+            uptime_ms,
+        };
+        // SAFETY: the entry point of a program mapped as its headers say,
+        // which is all a loader can go by
+        let entry: extern "C" fn(&Api) -> i32 = unsafe { mem::transmute(program.entry) };
+        entry(&api)
+    };
+    drop(program);
+
+    if !output.is_empty() {
+        let text = String::from_utf8_lossy(&output).into_owned();
+        if let Err(err) = services::console::write(text).await {
+            log::warn!("loader: program output: {}", err);
+        }
+    }
+    code
+}
@@ -0,0 +1,119 @@
+//! Just enough of ELF64 to load a static x86_64 executable
+
+use alloc::vec::Vec;
+
+use super::LoadError;
+
+const MAGIC: [u8; 4] = *b"\x7fELF";
+const CLASS_64: u8 = 2;
+const LITTLE_ENDIAN: u8 = 1;
+const TYPE_EXEC: u16 = 2;
+const TYPE_DYN: u16 = 3;
+const MACHINE_X86_64: u16 = 62;
+
+const HEADER_SIZE: usize = 64;
+const PROGRAM_HEADER_SIZE: usize = 56;
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+const PT_INTERP: u32 = 3;
+
+pub(super) const PF_X: u32 = 1;
+pub(super) const PF_W: u32 = 2;
+pub(super) const PF_R: u32 = 4;
+
+/// A `PT_LOAD` program header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Segment {
+    pub(super) offset: usize,
+    pub(super) address: usize,
+    pub(super) file_size: usize,
+    pub(super) memory_size: usize,
+    pub(super) flags: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Executable {
+    pub(super) entry: usize,
+    pub(super) segments: Vec<Segment>,
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().expect("4 bytes"))
+}
+
+fn usize_at(bytes: &[u8], at: usize) -> Result<usize, LoadError> {
+    let value = u64::from_le_bytes(bytes[at..at + 8].try_into().expect("8 bytes"));
+    usize::try_from(value).map_err(|_| LoadError::Malformed("value out of range"))
+}
+
+pub(super) fn parse(bytes: &[u8]) -> Result<Executable, LoadError> {
+    if bytes.len() < HEADER_SIZE || bytes[..4] != MAGIC {
+        return Err(LoadError::NotElf);
+    }
+    if bytes[4] != CLASS_64 || bytes[5] != LITTLE_ENDIAN {
+        return Err(LoadError::Unsupported("not 64-bit little endian"));
+    }
+    if u16_at(bytes, 18) != MACHINE_X86_64 {
+        return Err(LoadError::Unsupported("not x86_64"));
+    }
+    match u16_at(bytes, 16) {
+        TYPE_EXEC => {}
+        TYPE_DYN => return Err(LoadError::Unsupported("position independent")),
+        _ => return Err(LoadError::Unsupported("not an executable")),
+    }
+    let entry = usize_at(bytes, 24)?;
+    let table = usize_at(bytes, 32)?;
+    let entry_size = u16_at(bytes, 54) as usize;
+    let count = u16_at(bytes, 56) as usize;
+    if entry_size < PROGRAM_HEADER_SIZE {
+        return Err(LoadError::Malformed("program header too small"));
+    }
+
+    let mut segments = Vec::new();
+    for index in 0..count {
+        let at = table
+            .checked_add(index * entry_size)
+            .filter(|at| {
+                at.checked_add(PROGRAM_HEADER_SIZE)
+                    .is_some_and(|end| end <= bytes.len())
+            })
+            .ok_or(LoadError::Malformed("program header past the end"))?;
+        match u32_at(bytes, at) {
+            PT_LOAD => {}
+            PT_DYNAMIC | PT_INTERP => return Err(LoadError::Unsupported("dynamically linked")),
+            _ => continue,
+        }
+        let segment = Segment {
+            flags: u32_at(bytes, at + 4),
+            offset: usize_at(bytes, at + 8)?,
+            address: usize_at(bytes, at + 16)?,
+            file_size: usize_at(bytes, at + 32)?,
+            memory_size: usize_at(bytes, at + 40)?,
+        };
+        let in_file = segment
+            .offset
+            .checked_add(segment.file_size)
+            .is_some_and(|end| end <= bytes.len());
+        if !in_file || segment.file_size > segment.memory_size {
+            return Err(LoadError::Malformed("segment past the end"));
+        }
+        if segment.address.checked_add(segment.memory_size).is_none() {
+            return Err(LoadError::Malformed("segment past the address space"));
+        }
+        segments.push(segment);
+    }
+
+    let in_code = segments.iter().any(|segment| {
+        segment.flags & PF_X != 0
+            && (segment.address..segment.address + segment.memory_size).contains(&entry)
+    });
+    if !in_code {
+        return Err(LoadError::Malformed("entry point outside the code"));
+    }
+    Ok(Executable { entry, segments })
+}
@@ -0,0 +1,268 @@
+//!
+//! Logging
+//!
+//! The macros queue a [`Record`] and return straight away, so they are fine
+//! to use anywhere, interrupt handlers included. [`run_logger`] drains the
+//! queue into every sink added with [`add_sink`]; until it runs records wait
+//! in the queue.
+//!
+//! ```ignore
+//! log::warn!("scancode queue full; dropping keyboard input");
+//! log::set_target_level("task::keyboard", Level::Debug);
+//! ```
+//!
+//! With a filesystem, a [`Journal`] keeps them in a file for reading back
+//! after a reboot:
+//!
+//! ```ignore
+//! log::add_sink(Journal::open(JOURNAL_PATH, JOURNAL_SIZE).await?);
+//! ```
+//!
+
+#[cfg(feature = "fs")]
+mod journal;
+mod sink;
+
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+};
+
+use crossbeam_queue::ArrayQueue;
+
+use crate::{
+    metrics::Counter,
+    sync::Notify,
+    time::{Instant, SystemTime},
+};
+
+#[cfg(feature = "fs")]
+pub use journal::{JOURNAL_PATH, JOURNAL_SIZE, Journal, JournalEntry, read_journal};
+#[cfg(feature = "std")]
+pub use sink::SerialSink;
+pub use sink::{ConsoleSink, RingBuffer, Sink, SinkFuture};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    /// `"warn"` for [`Level::Warn`] and so on, ignoring case
+    pub fn from_name(name: &str) -> Option<Level> {
+        [
+            Level::Error,
+            Level::Warn,
+            Level::Info,
+            Level::Debug,
+            Level::Trace,
+        ]
+        .into_iter()
+        .find(|level| level.to_string().eq_ignore_ascii_case(name))
+    }
+
+    fn from_u8(level: u8) -> Option<Level> {
+        [
+            Level::Error,
+            Level::Warn,
+            Level::Info,
+            Level::Debug,
+            Level::Trace,
+        ]
+        .into_iter()
+        .find(|l| *l as u8 == level)
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        };
+        // Padding applies to the name, for lining up records
+        f.pad(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub level: Level,
+    /// Module the record comes from
+    pub target: &'static str,
+    pub timestamp: Instant,
+    pub message: String,
+}
+
+/// `[   1.234567] WARN  task::keyboard: message`, or with
+/// [`set_wall_clock`] `[2026-10-16T09:30:00.234567Z] WARN  ...`
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if wall_clock() {
+            let time = SystemTime::from_instant(self.timestamp);
+            write!(f, "[{}]", time.rfc3339())?;
+        } else {
+            let since_boot = self.timestamp.since_boot();
+            write!(
+                f,
+                "[{:5}.{:06}]",
+                since_boot.as_secs(),
+                since_boot.subsec_micros()
+            )?;
+        }
+        write!(f, " {:<5} {}: {}", self.level, self.target, self.message)
+    }
+}
+
+/// Records waiting for the logger task
+const QUEUE_CAPACITY: usize = 256;
+
+static QUEUE: spin::Once<ArrayQueue<Record>> = spin::Once::new();
+static READY: Notify = Notify::new();
+static DROPPED: Counter = Counter::new(
+    "log_records_dropped_total",
+    "Log records dropped because the queue was full",
+);
+
+static WALL_CLOCK: AtomicBool = AtomicBool::new(false);
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+// Most specific prefix wins
+static TARGET_LEVELS: spin::Mutex<Vec<(String, Level)>> = spin::Mutex::new(Vec::new());
+
+fn queue() -> &'static ArrayQueue<Record> {
+    QUEUE.call_once(|| ArrayQueue::new(QUEUE_CAPACITY))
+}
+
+/// Stamp records with the date and time instead of the time since boot,
+/// once the wall clock is set
+pub fn set_wall_clock(on: bool) {
+    WALL_CLOCK.store(on, Ordering::Relaxed);
+}
+
+pub fn wall_clock() -> bool {
+    WALL_CLOCK.load(Ordering::Relaxed)
+}
+
+/// Records above `level` are dropped, unless a target level says otherwise
+pub fn set_max_level(level: Level) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn max_level() -> Level {
+    Level::from_u8(MAX_LEVEL.load(Ordering::Relaxed)).unwrap_or(Level::Info)
+}
+
+/// Log level for modules whose path starts with `prefix`, `task::keyboard`
+/// covers `task::keyboard::hotkey` too
+pub fn set_target_level(prefix: &str, level: Level) {
+    let mut levels = TARGET_LEVELS.lock();
+    match levels.iter_mut().find(|(p, _)| p == prefix) {
+        Some((_, l)) => *l = level,
+        None => levels.push((prefix.into(), level)),
+    }
+}
+
+/// Whether a record for `target` at `level` would be kept
+pub fn enabled(level: Level, target: &str) -> bool {
+    let levels = TARGET_LEVELS.lock();
+    let limit = levels
+        .iter()
+        .filter(|(prefix, _)| target.starts_with(prefix.as_str()))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map_or_else(max_level, |(_, level)| *level);
+    level <= limit
+}
+
+/// Used by the macros
+#[doc(hidden)]
+pub fn log(level: Level, target: &'static str, args: fmt::Arguments) {
+    if !enabled(level, target) {
+        return;
+    }
+    let record = Record {
+        level,
+        target,
+        timestamp: Instant::now(),
+        message: args.to_string(),
+    };
+    if queue().push(record).is_err() {
+        DROPPED.inc();
+    }
+    READY.notify_one();
+}
+
+type Sinks = Vec<Box<dyn Sink>>;
+
+// The logger task owns the sinks so none is locked while it awaits, new
+// ones are handed over here
+static ADDED: spin::Mutex<Sinks> = spin::Mutex::new(Vec::new());
+
+/// Send records to `sink` as well, from the next record on
+pub fn add_sink(sink: impl Sink + 'static) {
+    ADDED.lock().push(Box::new(sink));
+    READY.notify_one();
+}
+
+/// Write queued records to the sinks, spawn it as a task. Runs forever.
+pub async fn run_logger() {
+    let mut sinks = Sinks::new();
+    loop {
+        sinks.append(&mut ADDED.lock());
+        while let Some(record) = queue().pop() {
+            for sink in &mut sinks {
+                sink.write(&record).await;
+            }
+        }
+        READY.notified().await;
+    }
+}
+
+#[macro_export]
+macro_rules! __log {
+    ($level:expr, $($arg:tt)+) => {
+        $crate::log::log($level, ::core::module_path!(), ::core::format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! __log_error {
+    ($($arg:tt)+) => { $crate::log::log!($crate::log::Level::Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! __log_warn {
+    ($($arg:tt)+) => { $crate::log::log!($crate::log::Level::Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! __log_info {
+    ($($arg:tt)+) => { $crate::log::log!($crate::log::Level::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! __log_debug {
+    ($($arg:tt)+) => { $crate::log::log!($crate::log::Level::Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! __log_trace {
+    ($($arg:tt)+) => { $crate::log::log!($crate::log::Level::Trace, $($arg)+) };
+}
+
+pub use crate::{
+    __log as log, __log_debug as debug, __log_error as error, __log_info as info,
+    __log_trace as trace, __log_warn as warn,
+};
@@ -0,0 +1,306 @@
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::{fmt, time::Duration};
+
+use super::{Level, Record, Sink, SinkFuture, wall_clock};
+use crate::{
+    bytes::{Buf, BufMut},
+    fs::{self, File, OpenOptions, SeekFrom},
+    io,
+    metrics::Counter,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Where the shell's `logs` reads the journal from
+pub const JOURNAL_PATH: &str = "/var/log/journal";
+
+/// Size of a journal [`Journal::open`] makes, a few thousand records
+pub const JOURNAL_SIZE: u64 = 256 * 1024;
+
+/// `JRNL`, where a frame starts
+const MAGIC: u32 = 0x4A52_4E4C;
+/// Magic, checksum and the length of the rest
+const HEADER_LEN: usize = 10;
+/// Sequence number, boot, both timestamps, level and target length
+const FIXED_LEN: usize = 30;
+/// Longer messages are cut short
+const MAX_MESSAGE: usize = 4096;
+/// The largest frame, a journal has room for at least one
+const MAX_FRAME: usize = HEADER_LEN + FIXED_LEN + u8::MAX as usize + MAX_MESSAGE;
+
+static ERRORS: Counter = Counter::new(
+    "log_journal_errors_total",
+    "Log records the journal failed to write",
+);
+
+/// Log records kept in a file of fixed size, the oldest overwritten once
+/// it's full, to read back with [`read_journal`] after a reboot.
+///
+/// Each record is a frame with a checksum, written in one go and flushed,
+/// so one torn by a crash or a power cut is skipped when reading and
+/// overwritten by the next boot. Only a filesystem backed by a disk keeps
+/// it across reboots.
+pub struct Journal {
+    file: Box<dyn File>,
+    size: u64,
+    /// Where the next frame goes
+    head: u64,
+    seq: u64,
+    boot: u32,
+}
+
+impl Journal {
+    /// Open the journal at `path`, making it `size` bytes long if it
+    /// doesn't exist. An existing one keeps its size and records, what's
+    /// written from now on belongs to a new boot. Its directory has to
+    /// exist. Panics if `size` can't hold the largest record.
+    pub async fn open(path: &str, size: u64) -> fs::Result<Journal> {
+        let contents = match fs::read(path).await {
+            Ok(contents) => contents,
+            Err(fs::Error::NotFound) => Vec::new(),
+            Err(err) => return Err(err),
+        };
+        let options = OpenOptions {
+            write: true,
+            create: true,
+            truncate: false,
+            append: false,
+        };
+        let mut file = fs::open_with(path, options).await?;
+        if contents.is_empty() {
+            assert!(size >= MAX_FRAME as u64, "journal of {} bytes", size);
+            // All of it up front, a full disk shows now rather than later
+            write_all(&mut *file, &vec![0; size as usize]).await?;
+            file.flush().await?;
+            return Ok(Journal {
+                file,
+                size,
+                head: 0,
+                seq: 0,
+                boot: 0,
+            });
+        }
+        if contents.len() < MAX_FRAME {
+            return Err(fs::Error::Corrupt);
+        }
+        let last = scan(&contents)
+            .into_iter()
+            .max_by_key(|(_, entry)| entry.seq);
+        let (head, seq, boot) = match last {
+            Some((end, entry)) => (end as u64, entry.seq + 1, entry.boot + 1),
+            None => (0, 0, 0),
+        };
+        Ok(Journal {
+            file,
+            size: contents.len() as u64,
+            head,
+            seq,
+            boot,
+        })
+    }
+
+    /// The boot the records written now belong to, the journal's first
+    /// being 0
+    pub fn boot(&self) -> u32 {
+        self.boot
+    }
+
+    async fn append(&mut self, record: &Record) -> fs::Result<()> {
+        let frame = self.frame(record);
+        if self.head + frame.len() as u64 > self.size {
+            // The end is left as it is, older records that stay readable
+            self.head = 0;
+        }
+        self.file.seek(SeekFrom::Start(self.head)).await?;
+        write_all(&mut *self.file, &frame).await?;
+        self.file.flush().await?;
+        self.head += frame.len() as u64;
+        self.seq += 1;
+        Ok(())
+    }
+
+    fn frame(&self, record: &Record) -> Vec<u8> {
+        let target = truncated(record.target, u8::MAX.into());
+        let message = truncated(&record.message, MAX_MESSAGE);
+        let time = SystemTime::from_instant(record.timestamp);
+        let len = FIXED_LEN + target.len() + message.len();
+        let mut frame = Vec::with_capacity(HEADER_LEN + len);
+        frame.put_u32(MAGIC);
+        // The checksum, once the rest is there
+        frame.put_u32(0);
+        frame.put_u16(len as u16);
+        frame.put_u64(self.seq);
+        frame.put_u32(self.boot);
+        frame.put_u64(record.timestamp.since_boot().as_micros() as u64);
+        frame.put_u64(unix_micros(time));
+        frame.put_u8(record.level as u8);
+        frame.put_u8(target.len() as u8);
+        frame.put_slice(target.as_bytes());
+        frame.put_slice(message.as_bytes());
+        let checksum = crc32(&frame[8..]);
+        frame[4..8].copy_from_slice(&checksum.to_be_bytes());
+        frame
+    }
+}
+
+impl Sink for Journal {
+    fn write<'a>(&'a mut self, record: &'a Record) -> SinkFuture<'a> {
+        Box::pin(async move {
+            // Logging it would only make another record to fail on
+            if self.append(record).await.is_err() {
+                ERRORS.inc();
+            }
+        })
+    }
+}
+
+/// A record read back from a journal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+    /// Counts up across boots
+    pub seq: u64,
+    /// Counts up from the journal's first boot
+    pub boot: u32,
+    /// Time since that boot
+    pub uptime: Duration,
+    /// The wall clock then, which may not have been set yet
+    pub time: SystemTime,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+/// Like a [`Record`]
+impl fmt::Display for JournalEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if wall_clock() {
+            write!(f, "[{}]", self.time.rfc3339())?;
+        } else {
+            write!(
+                f,
+                "[{:5}.{:06}]",
+                self.uptime.as_secs(),
+                self.uptime.subsec_micros()
+            )?;
+        }
+        write!(f, " {:<5} {}: {}", self.level, self.target, self.message)
+    }
+}
+
+/// The intact records of the journal at `path`, oldest first
+pub async fn read_journal(path: &str) -> fs::Result<Vec<JournalEntry>> {
+    let contents = fs::read(path).await?;
+    let mut entries: Vec<_> = scan(&contents)
+        .into_iter()
+        .map(|(_, entry)| entry)
+        .collect();
+    entries.sort_by_key(|entry| entry.seq);
+    Ok(entries)
+}
+
+/// Every frame with a good checksum and where it ends. Looks for one at
+/// every byte, since the start of a torn or overwritten one says nothing
+/// about where the next begins.
+fn scan(contents: &[u8]) -> Vec<(usize, JournalEntry)> {
+    let mut entries = Vec::new();
+    let mut at = 0;
+    while at + HEADER_LEN <= contents.len() {
+        match parse(&contents[at..]) {
+            Some((len, entry)) => {
+                at += len;
+                entries.push((at, entry));
+            }
+            None => at += 1,
+        }
+    }
+    entries
+}
+
+/// The frame at the start of `bytes` and its length
+fn parse(bytes: &[u8]) -> Option<(usize, JournalEntry)> {
+    let mut header = &bytes[..HEADER_LEN];
+    if header.get_u32() != MAGIC {
+        return None;
+    }
+    let checksum = header.get_u32();
+    let len = header.get_u16() as usize;
+    let checked = bytes.get(8..HEADER_LEN + len)?;
+    if len < FIXED_LEN || crc32(checked) != checksum {
+        return None;
+    }
+    let mut body = &checked[2..];
+    let seq = body.get_u64();
+    let boot = body.get_u32();
+    let uptime = Duration::from_micros(body.get_u64());
+    let time = UNIX_EPOCH + Duration::from_micros(body.get_u64());
+    let level = Level::from_u8(body.get_u8())?;
+    let target_len = body.get_u8().into();
+    let (target, message) = body.split_at_checked(target_len)?;
+    let entry = JournalEntry {
+        seq,
+        boot,
+        uptime,
+        time,
+        level,
+        target: core::str::from_utf8(target).ok()?.to_string(),
+        message: core::str::from_utf8(message).ok()?.to_string(),
+    };
+    Some((HEADER_LEN + len, entry))
+}
+
+async fn write_all(file: &mut dyn File, mut bytes: &[u8]) -> fs::Result<()> {
+    while !bytes.is_empty() {
+        let count = file.write(bytes).await?;
+        if count == 0 {
+            return Err(fs::Error::Io(io::Error::WriteZero));
+        }
+        bytes = &bytes[count..];
+    }
+    Ok(())
+}
+
+/// At most `max` bytes of `text`, cut at a character
+fn truncated(text: &str, max: usize) -> &str {
+    let mut end = text.len().min(max);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+fn unix_micros(time: SystemTime) -> u64 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    since_epoch.as_micros() as u64
+}
+
+/// CRC-32 as Ethernet and zip have it
+fn crc32(bytes: &[u8]) -> u32 {
+    let crc = bytes.iter().fold(!0, |crc: u32, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    });
+    !crc
+}
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xEDB8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
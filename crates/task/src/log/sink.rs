@@ -0,0 +1,77 @@
+use alloc::{boxed::Box, collections::VecDeque, format, sync::Arc, vec::Vec};
+use core::{future::Future, pin::Pin};
+
+use super::Record;
+use crate::console;
+#[cfg(feature = "std")]
+use crate::serial::SerialWriter;
+
+/// Future returned by [`Sink::write`]
+pub type SinkFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
+
+/// Somewhere log records end up
+pub trait Sink: Send {
+    fn write<'a>(&'a mut self, record: &'a Record) -> SinkFuture<'a>;
+}
+
+/// One line per record on the console
+pub struct ConsoleSink;
+
+impl Sink for ConsoleSink {
+    fn write<'a>(&'a mut self, record: &'a Record) -> SinkFuture<'a> {
+        Box::pin(console::write_string(format!("{}\n", record)))
+    }
+}
+
+/// One line per record on a serial port, for reading logs on the host
+#[cfg(feature = "std")]
+pub struct SerialSink(pub SerialWriter);
+
+#[cfg(feature = "std")]
+impl Sink for SerialSink {
+    fn write<'a>(&'a mut self, record: &'a Record) -> SinkFuture<'a> {
+        Box::pin(async move {
+            self.0.write(format!("{}\r\n", record).as_bytes()).await;
+        })
+    }
+}
+
+/// The last `capacity` records, for a `dmesg`-style command.
+///
+/// Clones share the buffer, keep one after adding another as a sink.
+#[derive(Clone)]
+pub struct RingBuffer {
+    records: Arc<spin::Mutex<VecDeque<Record>>>,
+    capacity: usize,
+}
+
+impl RingBuffer {
+    pub fn new(capacity: usize) -> Self {
+        RingBuffer {
+            records: Arc::new(spin::Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Oldest first
+    pub fn records(&self) -> Vec<Record> {
+        self.records.lock().iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.records.lock().clear();
+    }
+}
+
+impl Sink for RingBuffer {
+    fn write<'a>(&'a mut self, record: &'a Record) -> SinkFuture<'a> {
+        let mut records = self.records.lock();
+        if records.len() == self.capacity {
+            records.pop_front();
+        }
+        if self.capacity > 0 {
+            records.push_back(record.clone());
+        }
+        Box::pin(async {})
+    }
+}
@@ -0,0 +1,277 @@
+//!
+//! Heap statistics
+//!
+//! Install [`CountingAllocator`] as the global allocator to have
+//! [`stats`] report heap use:
+//!
+//! ```ignore
+//! #[global_allocator]
+//! static ALLOCATOR: CountingAllocator = CountingAllocator::new(System);
+//! ```
+//!
+//! The same numbers and a histogram of allocation sizes show up in the
+//! [`metrics`](crate::metrics) as `heap_*`. [`reset_peak`] starts a new
+//! peak, to see what a stretch of work needs at most.
+//!
+//! Allocations and frees are also counted against the task being polled,
+//! see [`task_usage`].
+//!
+//! Spawning many short tasks through an [`Arena`] takes their futures off
+//! the heap, a chunk at a time.
+//!
+//! When the heap runs out the [`add_reclaimer`] hooks are asked to free
+//! memory, then the [`OomPolicy`] decides whether the allocation fails or
+//! the task that made it is dealt with:
+//!
+//! ```ignore
+//! memory::set_oom_policy(OomPolicy::AbortTask);
+//! let cache = Arc::downgrade(&block_cache);
+//! memory::add_reclaimer("block cache", move |_| {
+//!     cache.upgrade().map_or(0, |cache| cache.shrink())
+//! });
+//! ```
+//!
+
+mod arena;
+mod oom;
+mod tasks;
+
+use alloc::{vec, vec::Vec};
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ptr,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use crate::metrics::{Histogram, Kind, Metric, Sample};
+
+pub(crate) use arena::ArenaFuture;
+pub use arena::{Arena, CHUNK_ALIGN, CHUNK_SIZE};
+pub(crate) use oom::take_out_of_memory;
+pub use oom::{
+    OomPolicy, RESERVE_SIZE, ReclaimerId, add_reclaimer, oom_policy, reclaimed_bytes, reclaimers,
+    remove_reclaimer, set_oom_policy,
+};
+pub use tasks::{TRACKED_TASKS, TaskMemory, task_usage};
+pub(crate) use tasks::{enter, track, untrack};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoryStats {
+    /// Bytes allocated right now
+    pub in_use: usize,
+    /// Most bytes ever allocated at once
+    pub peak: usize,
+    pub allocations: usize,
+    pub frees: usize,
+    /// Allocations the heap couldn't satisfy, even after reclaiming
+    pub failures: usize,
+    /// Bytes ever allocated, freed or not
+    pub allocated_bytes: usize,
+}
+
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+static IN_USE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static FREES: AtomicUsize = AtomicUsize::new(0);
+static FAILURES: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// Upper bounds of the allocation size buckets, in bytes
+pub const SIZE_BUCKETS: [u64; 12] = [
+    16,
+    32,
+    64,
+    128,
+    256,
+    512,
+    1024,
+    4096,
+    16384,
+    65536,
+    1 << 20,
+    16 << 20,
+];
+
+static SIZES: Histogram<12> = Histogram::new(
+    "heap_allocation_size_bytes",
+    "Sizes of heap allocations",
+    SIZE_BUCKETS,
+);
+
+/// Heap use so far, `None` if [`CountingAllocator`] isn't the global
+/// allocator
+pub fn stats() -> Option<MemoryStats> {
+    if !is_counting() {
+        return None;
+    }
+    Some(MemoryStats {
+        in_use: IN_USE.load(Ordering::Relaxed),
+        peak: PEAK.load(Ordering::Relaxed),
+        allocations: ALLOCATIONS.load(Ordering::Relaxed),
+        frees: FREES.load(Ordering::Relaxed),
+        failures: FAILURES.load(Ordering::Relaxed),
+        allocated_bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+    })
+}
+
+/// Allocation sizes as upper bounds with the number of allocations up to
+/// each, see [`SIZE_BUCKETS`]. `None` like [`stats`].
+pub fn size_histogram() -> Option<Vec<(u64, u64)>> {
+    is_counting().then(|| SIZES.buckets())
+}
+
+fn is_counting() -> bool {
+    INSTALLED.load(Ordering::Relaxed)
+}
+
+/// Start the peak over from what's in use now
+pub fn reset_peak() {
+    PEAK.store(IN_USE.load(Ordering::Relaxed), Ordering::Relaxed);
+}
+
+/// The heap metrics, taken by [`metrics::snapshot`](crate::metrics::snapshot)
+pub(crate) fn samples() -> Vec<Sample> {
+    let Some(stats) = stats() else {
+        return Vec::new();
+    };
+    let sample = |name, help, kind, value: usize| Sample {
+        name,
+        help,
+        kind,
+        value: value as i64,
+        buckets: Vec::new(),
+        sum: 0,
+        labels: Vec::new(),
+    };
+    vec![
+        sample(
+            "heap_in_use_bytes",
+            "Bytes allocated right now",
+            Kind::Gauge,
+            stats.in_use,
+        ),
+        sample(
+            "heap_peak_bytes",
+            "Most bytes allocated at once",
+            Kind::Gauge,
+            stats.peak,
+        ),
+        sample(
+            "heap_allocations_total",
+            "Heap allocations",
+            Kind::Counter,
+            stats.allocations,
+        ),
+        sample("heap_frees_total", "Heap frees", Kind::Counter, stats.frees),
+        sample(
+            "heap_failures_total",
+            "Heap allocations that failed",
+            Kind::Counter,
+            stats.failures,
+        ),
+        sample(
+            "heap_reclaimed_bytes_total",
+            "Bytes freed by reclaimers when the heap ran out",
+            Kind::Counter,
+            reclaimed_bytes(),
+        ),
+        SIZES.sample(),
+    ]
+}
+
+/// Wraps another allocator, counting what goes through it
+pub struct CountingAllocator<A> {
+    inner: A,
+}
+
+impl<A> CountingAllocator<A> {
+    pub const fn new(inner: A) -> Self {
+        CountingAllocator { inner }
+    }
+}
+
+fn allocated(size: usize) {
+    INSTALLED.store(true, Ordering::Relaxed);
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    ALLOCATED_BYTES.fetch_add(size, Ordering::Relaxed);
+    let in_use = IN_USE.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK.fetch_max(in_use, Ordering::Relaxed);
+    SIZES.record(size as u64);
+    tasks::allocated(size);
+}
+
+fn failed() {
+    FAILURES.fetch_add(1, Ordering::Relaxed);
+}
+
+fn freed(size: usize) {
+    FREES.fetch_add(1, Ordering::Relaxed);
+    IN_USE.fetch_sub(size, Ordering::Relaxed);
+    tasks::freed(size);
+}
+
+impl<A: GlobalAlloc> CountingAllocator<A> {
+    /// Reclaiming and the reserve as the [`OomPolicy`] says if `alloc` fails
+    unsafe fn allocate(&self, layout: Layout, alloc: impl Fn() -> *mut u8) -> *mut u8 {
+        let mut ptr = alloc();
+        if ptr.is_null() && oom::reclaim(layout.size()) {
+            ptr = alloc();
+        }
+        if ptr.is_null() {
+            failed();
+            ptr = oom::from_reserve(layout);
+        }
+        if !ptr.is_null() {
+            allocated(layout.size());
+        }
+        ptr
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for CountingAllocator<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        unsafe { self.allocate(layout, || self.inner.alloc(layout)) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if oom::in_reserve(ptr) {
+            oom::release();
+        } else {
+            unsafe { self.inner.dealloc(ptr, layout) };
+        }
+        freed(layout.size());
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { self.allocate(layout, || self.inner.alloc_zeroed(layout)) };
+        // The reserve is reused
+        if oom::in_reserve(ptr) {
+            unsafe { ptr.write_bytes(0, layout.size()) };
+        }
+        ptr
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if !oom::in_reserve(ptr) {
+            let new_ptr = unsafe { self.inner.realloc(ptr, layout, new_size) };
+            if !new_ptr.is_null() {
+                // Counted as a free and an allocation
+                freed(layout.size());
+                allocated(new_size);
+                return new_ptr;
+            }
+        }
+        // Moved by hand, through reclaiming and the reserve if need be
+        // SAFETY: the caller guarantees `new_size` makes a valid layout
+        let new_layout = unsafe { Layout::from_size_align_unchecked(new_size, layout.align()) };
+        let new_ptr = unsafe { self.alloc(new_layout) };
+        if !new_ptr.is_null() {
+            unsafe {
+                ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+                self.dealloc(ptr, layout);
+            }
+        }
+        new_ptr
+    }
+}
@@ -0,0 +1,235 @@
+//! Task futures bump-allocated in chunks, for code spawning many short
+//! tasks.
+//!
+//! Spawning through an [`Arena`] puts the future at the end of the arena's
+//! current chunk instead of in a `Box` of its own. Nothing is freed future
+//! by future: a chunk is reused once every task in it has finished, or
+//! freed with its last task when the arena moved on to a new chunk.
+//!
+//! ```ignore
+//! let arena = Arena::new();
+//! for request in requests {
+//!     executor::spawn(arena.task(handle(request)));
+//! }
+//! ```
+
+use alloc::{
+    alloc::{alloc, dealloc, handle_alloc_error},
+    boxed::Box,
+    sync::Arc,
+};
+use core::{
+    alloc::Layout,
+    fmt,
+    future::Future,
+    mem::ManuallyDrop,
+    pin::Pin,
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+
+use crate::{LocalTask, Task, TaskFuture};
+
+/// Bytes in a chunk unless [`with_chunk_size`](Arena::with_chunk_size)
+/// says otherwise
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Alignment of a chunk, futures needing more are boxed instead
+pub const CHUNK_ALIGN: usize = 16;
+
+/// Hands out tasks whose futures live in its chunks, see the
+/// [module](self). Clones share the chunks.
+#[derive(Clone)]
+pub struct Arena {
+    bump: Arc<spin::Mutex<Bump>>,
+    chunk_size: usize,
+}
+
+// Where the next future goes
+struct Bump {
+    chunk: Arc<Chunk>,
+    offset: usize,
+}
+
+struct Chunk {
+    memory: NonNull<u8>,
+    layout: Layout,
+    // Futures in the chunk not dropped yet
+    live: AtomicUsize,
+}
+
+// Plain memory, the futures in it are reached through `ArenaFuture`s
+unsafe impl Send for Chunk {}
+unsafe impl Sync for Chunk {}
+
+impl Chunk {
+    fn new(size: usize) -> Arc<Chunk> {
+        let layout = Layout::from_size_align(size.max(1), CHUNK_ALIGN).expect("bad chunk size");
+        // SAFETY: the layout isn't empty
+        let memory =
+            NonNull::new(unsafe { alloc(layout) }).unwrap_or_else(|| handle_alloc_error(layout));
+        Arc::new(Chunk {
+            memory,
+            layout,
+            live: AtomicUsize::new(0),
+        })
+    }
+}
+
+impl Drop for Chunk {
+    fn drop(&mut self) {
+        // SAFETY: allocated in `new` with the same layout
+        unsafe { dealloc(self.memory.as_ptr(), self.layout) }
+    }
+}
+
+impl Arena {
+    pub fn new() -> Self {
+        Arena::with_chunk_size(CHUNK_SIZE)
+    }
+
+    /// Futures larger than `chunk_size` are boxed
+    pub fn with_chunk_size(chunk_size: usize) -> Self {
+        Arena {
+            bump: Arc::new(spin::Mutex::new(Bump {
+                chunk: Chunk::new(chunk_size),
+                offset: 0,
+            })),
+            chunk_size,
+        }
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    pub fn task(&self, future: impl Future<Output = ()> + Send + 'static) -> Task {
+        let future: TaskFuture<dyn Future<Output = ()> + Send> = match self.place(future) {
+            Ok(future) => TaskFuture::Arena(future.into_dyn_send()),
+            Err(future) => TaskFuture::Boxed(Box::pin(future)),
+        };
+        Task::from_future(future)
+    }
+
+    pub fn local_task(&self, future: impl Future<Output = ()> + 'static) -> LocalTask {
+        let future: TaskFuture<dyn Future<Output = ()>> = match self.place(future) {
+            Ok(future) => TaskFuture::Arena(future.into_dyn()),
+            Err(future) => TaskFuture::Boxed(Box::pin(future)),
+        };
+        LocalTask::from_future(future)
+    }
+
+    /// Move `future` into the current chunk, or a new one if it's full. The
+    /// future comes back if it can't go in a chunk at all.
+    fn place<F>(&self, future: F) -> Result<ArenaFuture<F>, F> {
+        let layout = Layout::new::<F>();
+        if layout.size() > self.chunk_size || layout.align() > CHUNK_ALIGN {
+            return Err(future);
+        }
+        let mut bump = self.bump.lock();
+        // Every task in the chunk has finished, start it over
+        if bump.chunk.live.load(Ordering::Acquire) == 0 {
+            bump.offset = 0;
+        }
+        let mut offset = bump.offset.next_multiple_of(layout.align());
+        if offset + layout.size() > self.chunk_size {
+            // The old chunk goes when its last future does
+            bump.chunk = Chunk::new(self.chunk_size);
+            offset = 0;
+        }
+        bump.offset = offset + layout.size();
+        bump.chunk.live.fetch_add(1, Ordering::Relaxed);
+        // SAFETY: in bounds as checked above
+        let slot = unsafe { bump.chunk.memory.add(offset) }.cast::<F>();
+        // SAFETY: aligned as the chunk is, and nothing else uses
+        // `offset..offset + size` until the chunk starts over, which needs
+        // this future dropped
+        unsafe { slot.write(future) };
+        Ok(ArenaFuture {
+            future: slot,
+            chunk: bump.chunk.clone(),
+        })
+    }
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Arena::new()
+    }
+}
+
+impl fmt::Debug for Arena {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bump = self.bump.lock();
+        f.debug_struct("Arena")
+            .field("chunk_size", &self.chunk_size)
+            .field("used", &bump.offset)
+            .field("live", &bump.chunk.live.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+/// A future in an arena chunk, dropped in place
+pub(crate) struct ArenaFuture<F: ?Sized> {
+    future: NonNull<F>,
+    chunk: Arc<Chunk>,
+}
+
+// Owns the future like a `Box` does
+unsafe impl<F: ?Sized + Send> Send for ArenaFuture<F> {}
+
+impl<F: ?Sized> Unpin for ArenaFuture<F> {}
+
+impl<F: ?Sized> ArenaFuture<F> {
+    /// The same future behind another pointer type, `ArenaFuture` can't
+    /// coerce to a trait object itself
+    fn cast<G: ?Sized>(self, cast: impl FnOnce(*mut F) -> *mut G) -> ArenaFuture<G> {
+        let this = ManuallyDrop::new(self);
+        // SAFETY: moved out of a value that's never dropped
+        let chunk = unsafe { ptr::read(&this.chunk) };
+        ArenaFuture {
+            // SAFETY: a cast of a `NonNull`
+            future: unsafe { NonNull::new_unchecked(cast(this.future.as_ptr())) },
+            chunk,
+        }
+    }
+}
+
+impl<F: Future<Output = ()> + 'static> ArenaFuture<F> {
+    fn into_dyn(self) -> ArenaFuture<dyn Future<Output = ()>> {
+        self.cast(|future| future as *mut dyn Future<Output = ()>)
+    }
+}
+
+impl<F: Future<Output = ()> + Send + 'static> ArenaFuture<F> {
+    fn into_dyn_send(self) -> ArenaFuture<dyn Future<Output = ()> + Send> {
+        self.cast(|future| future as *mut (dyn Future<Output = ()> + Send))
+    }
+}
+
+impl ArenaFuture<dyn Future<Output = ()> + Send> {
+    /// For turning a [`Task`] into a [`LocalTask`]
+    pub(crate) fn into_local(self) -> ArenaFuture<dyn Future<Output = ()>> {
+        self.cast(|future| future as *mut dyn Future<Output = ()>)
+    }
+}
+
+impl<F: ?Sized + Future> Future for ArenaFuture<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<F::Output> {
+        // SAFETY: the future never moves out of its chunk
+        unsafe { Pin::new_unchecked(self.future.as_mut()) }.poll(cx)
+    }
+}
+
+impl<F: ?Sized> Drop for ArenaFuture<F> {
+    fn drop(&mut self) {
+        // SAFETY: written by `place` and only dropped here
+        unsafe { self.future.drop_in_place() };
+        // Ordered after the drop, so the chunk starts over only once the
+        // future is gone
+        self.chunk.live.fetch_sub(1, Ordering::Release);
+    }
+}
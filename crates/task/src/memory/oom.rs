@@ -0,0 +1,187 @@
+//! What happens when the heap runs out.
+//!
+//! A failed allocation first asks the reclaimers to give memory back and
+//! tries again. If that doesn't help the [`OomPolicy`] decides: fail the
+//! allocation as Rust normally would, or get the allocating task through
+//! its poll on a small reserve and then drop it or panic. An allocation
+//! larger than what's left of the reserve fails either way.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    alloc::Layout,
+    cell::UnsafeCell,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicU8, AtomicU64, AtomicUsize, Ordering},
+};
+
+use crate::TaskId;
+
+/// Bytes set aside for tasks that ran out, enough to finish a poll
+pub const RESERVE_SIZE: usize = 256 * 1024;
+
+/// What to do once an allocation failed and reclaiming didn't help
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OomPolicy {
+    /// Return the failure, Rust's allocation error handler takes it from
+    /// there: the hosted build aborts, the bare one panics
+    #[default]
+    Fail,
+    /// Panic once the allocating task's poll returns
+    Panic,
+    /// Drop the allocating task once its poll returns, the rest carries on
+    AbortTask,
+}
+
+static POLICY: AtomicU8 = AtomicU8::new(0);
+
+pub fn set_oom_policy(policy: OomPolicy) {
+    POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+pub fn oom_policy() -> OomPolicy {
+    match POLICY.load(Ordering::Relaxed) {
+        1 => OomPolicy::Panic,
+        2 => OomPolicy::AbortTask,
+        _ => OomPolicy::Fail,
+    }
+}
+
+/// Handle to remove a reclaimer with [`remove_reclaimer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReclaimerId(u64);
+
+struct Reclaimer {
+    id: ReclaimerId,
+    name: &'static str,
+    // Given the bytes needed, returns the bytes it freed
+    reclaim: Box<dyn Fn(usize) -> usize + Send>,
+}
+
+static RECLAIMERS: spin::Mutex<Vec<Reclaimer>> = spin::Mutex::new(Vec::new());
+static NEXT_RECLAIMER: AtomicU64 = AtomicU64::new(0);
+// Set while reclaimers run, so their own failing allocations don't start
+// another round
+static RECLAIMING: AtomicBool = AtomicBool::new(false);
+static RECLAIMED: AtomicUsize = AtomicUsize::new(0);
+
+/// Have `reclaim` free what it can when an allocation fails, dropping
+/// caches or scrollback. It's told how many bytes are needed and returns
+/// how many it freed.
+///
+/// It runs inside the allocator, in whatever task was allocating, so it
+/// must not wait for a lock that task could hold: use `try_lock` and give
+/// up.
+pub fn add_reclaimer(
+    name: &'static str,
+    reclaim: impl Fn(usize) -> usize + Send + 'static,
+) -> ReclaimerId {
+    let id = ReclaimerId(NEXT_RECLAIMER.fetch_add(1, Ordering::Relaxed));
+    RECLAIMERS.lock().push(Reclaimer {
+        id,
+        name,
+        reclaim: Box::new(reclaim),
+    });
+    id
+}
+
+pub fn remove_reclaimer(id: ReclaimerId) {
+    RECLAIMERS.lock().retain(|reclaimer| reclaimer.id != id);
+}
+
+/// Names of the reclaimers, in the order they run
+pub fn reclaimers() -> Vec<&'static str> {
+    RECLAIMERS
+        .lock()
+        .iter()
+        .map(|reclaimer| reclaimer.name)
+        .collect()
+}
+
+/// Bytes the reclaimers freed so far
+pub fn reclaimed_bytes() -> usize {
+    RECLAIMED.load(Ordering::Relaxed)
+}
+
+/// Run reclaimers until `needed` bytes are free, `true` if any were
+pub(super) fn reclaim(needed: usize) -> bool {
+    if RECLAIMING.swap(true, Ordering::Acquire) {
+        return false;
+    }
+    let mut freed = 0;
+    // Taken if the failing allocation came from adding a reclaimer
+    if let Some(reclaimers) = RECLAIMERS.try_lock() {
+        for reclaimer in reclaimers.iter() {
+            freed += (reclaimer.reclaim)(needed);
+            if freed >= needed {
+                break;
+            }
+        }
+    }
+    RECLAIMING.store(false, Ordering::Release);
+    RECLAIMED.fetch_add(freed, Ordering::Relaxed);
+    freed > 0
+}
+
+#[repr(C, align(4096))]
+struct Reserve(UnsafeCell<[u8; RESERVE_SIZE]>);
+
+// Handed out in disjoint pieces under `BUMP`
+unsafe impl Sync for Reserve {}
+
+static RESERVE: Reserve = Reserve(UnsafeCell::new([0; RESERVE_SIZE]));
+
+struct Bump {
+    next: usize,
+    // Pieces handed out and not returned, the reserve starts over at none
+    live: usize,
+}
+
+static BUMP: spin::Mutex<Bump> = spin::Mutex::new(Bump { next: 0, live: 0 });
+// Id plus one of the task that ran out, `0` if none did
+static OUT_OF_MEMORY: AtomicU64 = AtomicU64::new(0);
+
+/// Memory for a task that ran out, null if the policy doesn't want it
+/// kept going or the reserve is used up
+pub(super) fn from_reserve(layout: Layout) -> *mut u8 {
+    if oom_policy() == OomPolicy::Fail {
+        return ptr::null_mut();
+    }
+    let Some(task) = super::tasks::current_task() else {
+        return ptr::null_mut();
+    };
+    let start = RESERVE.0.get().cast::<u8>();
+    let mut bump = BUMP.lock();
+    let offset = (start as usize + bump.next).next_multiple_of(layout.align()) - start as usize;
+    if offset + layout.size() > RESERVE_SIZE {
+        return ptr::null_mut();
+    }
+    bump.next = offset + layout.size();
+    bump.live += 1;
+    // The first task to run out is dealt with first
+    let _ =
+        OUT_OF_MEMORY.compare_exchange(0, task.as_u64() + 1, Ordering::AcqRel, Ordering::Relaxed);
+    // SAFETY: in bounds, checked above
+    unsafe { start.add(offset) }
+}
+
+pub(super) fn in_reserve(ptr: *mut u8) -> bool {
+    let start = RESERVE.0.get() as usize;
+    (start..start + RESERVE_SIZE).contains(&(ptr as usize))
+}
+
+/// Return a piece of the reserve
+pub(super) fn release() {
+    let mut bump = BUMP.lock();
+    bump.live -= 1;
+    if bump.live == 0 {
+        bump.next = 0;
+    }
+}
+
+/// `true` once if `task` ran out of memory during its last poll, called by
+/// the executor after each poll
+pub(crate) fn take_out_of_memory(task: TaskId) -> bool {
+    OUT_OF_MEMORY
+        .compare_exchange(task.as_u64() + 1, 0, Ordering::AcqRel, Ordering::Relaxed)
+        .is_ok()
+}
@@ -0,0 +1,205 @@
+//! Heap use by task, counted against whichever task is being polled.
+//!
+//! The allocator can't take locks or allocate, so each tracked task gets a
+//! slot of atomics in a fixed table and the executor marks the current one
+//! around `task.poll`.
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use crate::TaskId;
+
+/// Tasks accounted for at once, tasks spawned beyond that aren't
+pub const TRACKED_TASKS: usize = 128;
+
+/// What a task allocated and freed while it was polled. Freeing memory
+/// another task allocated counts too, so [`in_use`](TaskMemory::in_use)
+/// can go below zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TaskMemory {
+    pub allocations: usize,
+    pub frees: usize,
+    pub allocated_bytes: usize,
+    pub freed_bytes: usize,
+}
+
+impl TaskMemory {
+    /// Bytes allocated and not freed again
+    pub fn in_use(&self) -> isize {
+        self.allocated_bytes as isize - self.freed_bytes as isize
+    }
+}
+
+struct Slot {
+    // Id plus one, `0` while free
+    task: AtomicU64,
+    allocations: AtomicUsize,
+    frees: AtomicUsize,
+    allocated_bytes: AtomicUsize,
+    freed_bytes: AtomicUsize,
+}
+
+impl Slot {
+    const fn new() -> Self {
+        Slot {
+            task: AtomicU64::new(0),
+            allocations: AtomicUsize::new(0),
+            frees: AtomicUsize::new(0),
+            allocated_bytes: AtomicUsize::new(0),
+            freed_bytes: AtomicUsize::new(0),
+        }
+    }
+
+    fn usage(&self) -> TaskMemory {
+        TaskMemory {
+            allocations: self.allocations.load(Ordering::Relaxed),
+            frees: self.frees.load(Ordering::Relaxed),
+            allocated_bytes: self.allocated_bytes.load(Ordering::Relaxed),
+            freed_bytes: self.freed_bytes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+static SLOTS: [Slot; TRACKED_TASKS] = [const { Slot::new() }; TRACKED_TASKS];
+
+const NO_SLOT: usize = usize::MAX;
+
+// Slot index and id plus one of the task being polled, counted against
+// even when it has no slot
+#[derive(Clone, Copy)]
+struct Current {
+    slot: usize,
+    task: u64,
+}
+
+#[cfg(feature = "std")]
+const NONE: Current = Current {
+    slot: NO_SLOT,
+    task: 0,
+};
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static CURRENT: core::cell::Cell<Current> = const { core::cell::Cell::new(NONE) };
+}
+
+// No threads without std, one executor polls at a time
+#[cfg(not(feature = "std"))]
+static CURRENT_SLOT: AtomicUsize = AtomicUsize::new(NO_SLOT);
+#[cfg(not(feature = "std"))]
+static CURRENT_TASK: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(feature = "std")]
+fn current() -> Current {
+    // Gone while the thread shuts down, when it still frees memory
+    CURRENT.try_with(|current| current.get()).unwrap_or(NONE)
+}
+
+#[cfg(feature = "std")]
+fn set_current(task: Current) -> Current {
+    CURRENT
+        .try_with(|current| current.replace(task))
+        .unwrap_or(NONE)
+}
+
+#[cfg(not(feature = "std"))]
+fn current() -> Current {
+    Current {
+        slot: CURRENT_SLOT.load(Ordering::Relaxed),
+        task: CURRENT_TASK.load(Ordering::Relaxed),
+    }
+}
+
+#[cfg(not(feature = "std"))]
+fn set_current(task: Current) -> Current {
+    Current {
+        slot: CURRENT_SLOT.swap(task.slot, Ordering::Relaxed),
+        task: CURRENT_TASK.swap(task.task, Ordering::Relaxed),
+    }
+}
+
+/// The task being polled, for the allocator
+pub(super) fn current_task() -> Option<TaskId> {
+    current().task.checked_sub(1).map(TaskId)
+}
+
+fn slot_of(task: TaskId) -> Option<usize> {
+    let key = task.as_u64() + 1;
+    SLOTS
+        .iter()
+        .position(|slot| slot.task.load(Ordering::Acquire) == key)
+}
+
+/// Start accounting for `task`, called by the executor when it's spawned
+pub(crate) fn track(task: TaskId) {
+    let key = task.as_u64() + 1;
+    for slot in &SLOTS {
+        if slot.task.load(Ordering::Relaxed) != 0 {
+            continue;
+        }
+        // Nobody counts against a free slot, it can be cleared before
+        // it's claimed
+        slot.allocations.store(0, Ordering::Relaxed);
+        slot.frees.store(0, Ordering::Relaxed);
+        slot.allocated_bytes.store(0, Ordering::Relaxed);
+        slot.freed_bytes.store(0, Ordering::Relaxed);
+        if slot
+            .task
+            .compare_exchange(0, key, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+        {
+            return;
+        }
+    }
+}
+
+/// `task` finished, free its slot
+pub(crate) fn untrack(task: TaskId) {
+    if let Some(index) = slot_of(task) {
+        SLOTS[index].task.store(0, Ordering::Release);
+    }
+}
+
+/// Counts against `task` until dropped, the executor holds one around
+/// `task.poll`
+pub(crate) struct Enter {
+    previous: Current,
+}
+
+pub(crate) fn enter(task: TaskId) -> Enter {
+    let current = Current {
+        slot: slot_of(task).unwrap_or(NO_SLOT),
+        task: task.as_u64() + 1,
+    };
+    Enter {
+        previous: set_current(current),
+    }
+}
+
+impl Drop for Enter {
+    fn drop(&mut self) {
+        set_current(self.previous);
+    }
+}
+
+pub(super) fn allocated(size: usize) {
+    if let Some(slot) = SLOTS.get(current().slot) {
+        slot.allocations.fetch_add(1, Ordering::Relaxed);
+        slot.allocated_bytes.fetch_add(size, Ordering::Relaxed);
+    }
+}
+
+pub(super) fn freed(size: usize) {
+    if let Some(slot) = SLOTS.get(current().slot) {
+        slot.frees.fetch_add(1, Ordering::Relaxed);
+        slot.freed_bytes.fetch_add(size, Ordering::Relaxed);
+    }
+}
+
+/// Heap use of `task` so far, `None` if it isn't tracked or the allocator
+/// isn't counting
+pub fn task_usage(task: TaskId) -> Option<TaskMemory> {
+    if !super::is_counting() {
+        return None;
+    }
+    Some(SLOTS[slot_of(task)?].usage())
+}
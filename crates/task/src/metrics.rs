@@ -0,0 +1,329 @@
+//!
+//! Metrics
+//!
+//! Metrics are statics that register themselves in a global list the first
+//! time they are updated, so subsystems can declare them next to the code
+//! that uses them:
+//!
+//! ```ignore
+//! static RESTARTS: Counter = Counter::new("supervisor_restarts_total", "Child task restarts");
+//! RESTARTS.inc();
+//! ```
+//!
+//! Heap statistics from [`memory`](crate::memory) are included once its
+//! allocator is counting, and each task's wake latency as a histogram
+//! labelled with the task.
+//!
+
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    fmt::Write as _,
+    sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+};
+
+static REGISTRY: spin::Mutex<Vec<&'static dyn Metric>> = spin::Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+/// Point-in-time reading of a metric
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: Kind,
+    /// For a histogram the number of observations
+    pub value: i64,
+    /// Histograms only: upper bounds, each with the number of observations
+    /// up to it
+    pub buckets: Vec<(u64, u64)>,
+    /// Histograms only: all observations added up
+    pub sum: u64,
+    /// Tell apart samples of one metric, empty for most
+    pub labels: Vec<(&'static str, String)>,
+}
+
+impl Sample {
+    /// The name with the labels, as in `name{task="3"}`
+    pub fn series(&self) -> String {
+        let mut series = String::from(self.name);
+        series.push_str(&label_set(&self.labels, None));
+        series
+    }
+}
+
+/// `{key="value",...}` with `extra` last, empty without labels
+fn label_set(labels: &[(&'static str, String)], extra: Option<(&str, &str)>) -> String {
+    let mut set = String::new();
+    let labels = labels
+        .iter()
+        .map(|(key, value)| (*key, value.as_str()))
+        .chain(extra);
+    for (index, (key, value)) in labels.enumerate() {
+        set.push(if index == 0 { '{' } else { ',' });
+        let value = value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        let _ = write!(set, "{}=\"{}\"", key, value);
+    }
+    if !set.is_empty() {
+        set.push('}');
+    }
+    set
+}
+
+pub(crate) trait Metric: Sync {
+    fn sample(&self) -> Sample;
+}
+
+fn register(metric: &'static dyn Metric, registered: &AtomicBool) {
+    if !registered.swap(true, Ordering::AcqRel) {
+        REGISTRY.lock().push(metric);
+    }
+}
+
+/// Read every metric that has been touched so far
+pub fn snapshot() -> Vec<Sample> {
+    let mut samples: Vec<Sample> = REGISTRY
+        .lock()
+        .iter()
+        .map(|metric| metric.sample())
+        .collect();
+    // The allocator can't register anything, its own allocations would
+    // come back to the registry
+    samples.extend(crate::memory::samples());
+    samples.extend(crate::executor::latency_samples());
+    samples
+}
+
+/// Monotonically increasing count
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    value: AtomicU64,
+    registered: AtomicBool,
+}
+
+impl Counter {
+    pub const fn new(name: &'static str, help: &'static str) -> Self {
+        Counter {
+            name,
+            help,
+            value: AtomicU64::new(0),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    /// Counters are registered the first time they change, which locks the
+    /// registry and may allocate. One changed in interrupt handlers is
+    /// registered up front with this instead.
+    pub fn register(&'static self) {
+        register(self, &self.registered);
+    }
+
+    pub fn inc(&'static self) {
+        self.add(1);
+    }
+
+    pub fn add(&'static self, n: u64) {
+        register(self, &self.registered);
+        self.value.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+impl Metric for Counter {
+    fn sample(&self) -> Sample {
+        Sample {
+            name: self.name,
+            help: self.help,
+            kind: Kind::Counter,
+            value: self.get() as i64,
+            buckets: Vec::new(),
+            sum: 0,
+            labels: Vec::new(),
+        }
+    }
+}
+
+/// Value that can go up and down
+pub struct Gauge {
+    name: &'static str,
+    help: &'static str,
+    value: AtomicI64,
+    registered: AtomicBool,
+}
+
+impl Gauge {
+    pub const fn new(name: &'static str, help: &'static str) -> Self {
+        Gauge {
+            name,
+            help,
+            value: AtomicI64::new(0),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    pub fn set(&'static self, value: i64) {
+        register(self, &self.registered);
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    pub fn add(&'static self, n: i64) {
+        register(self, &self.registered);
+        self.value.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+impl Metric for Gauge {
+    fn sample(&self) -> Sample {
+        Sample {
+            name: self.name,
+            help: self.help,
+            kind: Kind::Gauge,
+            value: self.get(),
+            buckets: Vec::new(),
+            sum: 0,
+            labels: Vec::new(),
+        }
+    }
+}
+
+/// Observations counted in buckets by their size, `N` upper bounds in
+/// ascending order. Larger observations only count towards the total.
+pub struct Histogram<const N: usize> {
+    name: &'static str,
+    help: &'static str,
+    bounds: [u64; N],
+    // Observations in each bucket alone, summed up when sampled
+    counts: [AtomicU64; N],
+    count: AtomicU64,
+    sum: AtomicU64,
+    registered: AtomicBool,
+}
+
+impl<const N: usize> Histogram<N> {
+    pub const fn new(name: &'static str, help: &'static str, bounds: [u64; N]) -> Self {
+        Histogram {
+            name,
+            help,
+            bounds,
+            counts: [const { AtomicU64::new(0) }; N],
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+            registered: AtomicBool::new(false),
+        }
+    }
+
+    pub fn observe(&'static self, value: u64) {
+        register(self, &self.registered);
+        self.record(value);
+    }
+
+    /// [`observe`](Histogram::observe) without registering, for the
+    /// allocator
+    pub(crate) fn record(&self, value: u64) {
+        if let Some(bucket) = self.bounds.iter().position(|bound| value <= *bound) {
+            self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(value, Ordering::Relaxed);
+    }
+
+    /// Upper bounds, each with the number of observations up to it
+    pub fn buckets(&self) -> Vec<(u64, u64)> {
+        let mut total = 0;
+        self.bounds
+            .iter()
+            .zip(&self.counts)
+            .map(|(bound, count)| {
+                total += count.load(Ordering::Relaxed);
+                (*bound, total)
+            })
+            .collect()
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn sum(&self) -> u64 {
+        self.sum.load(Ordering::Relaxed)
+    }
+}
+
+impl<const N: usize> Metric for Histogram<N> {
+    fn sample(&self) -> Sample {
+        let buckets = self.buckets();
+        // Observations made while reading the buckets may be in them but
+        // not in an earlier count
+        let count = buckets.last().map_or(0, |(_, count)| *count);
+        Sample {
+            name: self.name,
+            help: self.help,
+            kind: Kind::Histogram,
+            value: self.count().max(count) as i64,
+            buckets,
+            sum: self.sum(),
+            labels: Vec::new(),
+        }
+    }
+}
+
+/// Every metric in the Prometheus text format, for scraping from the host
+pub fn prometheus() -> String {
+    let mut text = String::new();
+    let mut previous = "";
+    for sample in snapshot() {
+        let kind = match sample.kind {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+            Kind::Histogram => "histogram",
+        };
+        // Once for all the samples of a metric, which come one after the
+        // other
+        if sample.name != previous {
+            // Only these two would end the line early
+            let help = sample.help.replace('\\', "\\\\").replace('\n', "\\n");
+            let _ = write!(
+                text,
+                "# HELP {name} {help}\n# TYPE {name} {kind}\n",
+                name = sample.name,
+            );
+            previous = sample.name;
+        }
+        if sample.kind != Kind::Histogram {
+            let _ = writeln!(text, "{} {}", sample.series(), sample.value);
+            continue;
+        }
+        for (bound, count) in &sample.buckets {
+            let labels = label_set(&sample.labels, Some(("le", &bound.to_string())));
+            let _ = writeln!(text, "{}_bucket{} {}", sample.name, labels, count);
+        }
+        let _ = write!(
+            text,
+            "{name}_bucket{inf} {count}\n{name}_sum{labels} {sum}\n{name}_count{labels} {count}\n",
+            name = sample.name,
+            inf = label_set(&sample.labels, Some(("le", "+Inf"))),
+            labels = label_set(&sample.labels, None),
+            count = sample.value,
+            sum = sample.sum,
+        );
+    }
+    text
+}
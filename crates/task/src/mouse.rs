@@ -0,0 +1,201 @@
+//!
+//! PS/2 mouse
+//!
+//! Same shape as the keyboard: the interrupt handler pushes raw bytes with
+//! `add_mouse_byte` and a [`MouseStream`] turns them into events.
+//!
+
+use core::{
+    pin::Pin,
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    task::{Context, Poll},
+};
+
+use conquer_once::spin::OnceCell;
+use crossbeam_queue::ArrayQueue;
+use futures_util::Stream;
+
+use crate::{
+    i8042::{self, Port},
+    input, log, priority, rng,
+    sync::{IrqNotify, Listener},
+};
+
+/// A few dozen packets, the mouse is chattier than the keyboard
+const QUEUE_CAPACITY: usize = 256;
+
+static MOUSE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+static NOTIFY: IrqNotify = IrqNotify::new();
+
+/// Live `MouseStream`s, bytes are only queued while there is one
+static STREAMS: AtomicUsize = AtomicUsize::new(0);
+
+/// Bytes lost to a full queue, counted by the interrupt handler and
+/// reported by the streams
+static DROPPED: AtomicU64 = AtomicU64::new(0);
+static REPORTED: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn add_mouse_byte(byte: u8) {
+    if i8042::take_reply(Port::Second, byte) {
+        return;
+    }
+    rng::add_interrupt_timing(byte.into(), 1);
+    input::capture(input::Device::Mouse, byte);
+    if STREAMS.load(Ordering::Acquire) == 0 {
+        return;
+    }
+    // Made before the first stream counted itself
+    let Ok(queue) = MOUSE_QUEUE.try_get() else {
+        return;
+    };
+    if queue.push(byte).is_err() {
+        // Logging allocates and locks, a stream reports it instead
+        DROPPED.fetch_add(1, Ordering::Relaxed);
+    } else {
+        NOTIFY.notify();
+        priority::input_arrived();
+    }
+}
+
+/// Log the bytes lost since the last call, once for all of them
+fn report_dropped() {
+    let dropped = DROPPED.load(Ordering::Relaxed);
+    let reported = REPORTED.swap(dropped, Ordering::Relaxed);
+    if dropped > reported {
+        log::warn!(
+            "mouse queue full; dropped {} bytes of mouse input",
+            dropped - reported
+        );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MouseButtons {
+    pub left: bool,
+    pub right: bool,
+    pub middle: bool,
+}
+
+/// One packet from the mouse: movement since the last one and which buttons
+/// are held. `dy` is positive upwards, as the mouse reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MouseEvent {
+    pub dx: i16,
+    pub dy: i16,
+    pub buttons: MouseButtons,
+}
+
+// Bits of the first packet byte
+const LEFT: u8 = 1 << 0;
+const RIGHT: u8 = 1 << 1;
+const MIDDLE: u8 = 1 << 2;
+const ALWAYS_ONE: u8 = 1 << 3;
+const X_SIGN: u8 = 1 << 4;
+const Y_SIGN: u8 = 1 << 5;
+const X_OVERFLOW: u8 = 1 << 6;
+const Y_OVERFLOW: u8 = 1 << 7;
+
+/// Assembles standard 3-byte packets
+#[derive(Default)]
+struct PacketDecoder {
+    bytes: [u8; 3],
+    len: usize,
+}
+
+impl PacketDecoder {
+    fn add_byte(&mut self, byte: u8) -> Option<MouseEvent> {
+        // Bit 3 is always set in the first byte, skip bytes until we see it
+        // so a lost byte only costs one packet
+        if self.len == 0 && byte & ALWAYS_ONE == 0 {
+            return None;
+        }
+        self.bytes[self.len] = byte;
+        self.len += 1;
+        if self.len < 3 {
+            return None;
+        }
+        self.len = 0;
+
+        let [flags, x, y] = self.bytes;
+        Some(MouseEvent {
+            dx: Self::movement(x, flags & X_SIGN != 0, flags & X_OVERFLOW != 0),
+            dy: Self::movement(y, flags & Y_SIGN != 0, flags & Y_OVERFLOW != 0),
+            buttons: MouseButtons {
+                left: flags & LEFT != 0,
+                right: flags & RIGHT != 0,
+                middle: flags & MIDDLE != 0,
+            },
+        })
+    }
+
+    /// 9-bit two's complement, the sign bit lives in the first byte
+    fn movement(value: u8, negative: bool, overflow: bool) -> i16 {
+        if overflow {
+            return 0;
+        }
+        if negative {
+            value as i16 - 0x100
+        } else {
+            value as i16
+        }
+    }
+}
+
+/// Mouse events decoded from the bytes given to `add_mouse_byte`
+pub struct MouseStream {
+    listener: Listener<'static>,
+    decoder: PacketDecoder,
+}
+
+impl MouseStream {
+    pub fn new() -> Self {
+        MOUSE_QUEUE.init_once(|| ArrayQueue::new(QUEUE_CAPACITY));
+        STREAMS.fetch_add(1, Ordering::AcqRel);
+        MouseStream {
+            listener: NOTIFY.listener(),
+            decoder: PacketDecoder::default(),
+        }
+    }
+}
+
+impl Default for MouseStream {
+    fn default() -> Self {
+        MouseStream::new()
+    }
+}
+
+impl Drop for MouseStream {
+    fn drop(&mut self) {
+        if STREAMS.fetch_sub(1, Ordering::AcqRel) == 1
+            && let Ok(queue) = MOUSE_QUEUE.try_get()
+        {
+            // A new stream must start on a packet boundary
+            while queue.pop().is_some() {}
+        }
+    }
+}
+
+impl Stream for MouseStream {
+    type Item = MouseEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<MouseEvent>> {
+        let queue = MOUSE_QUEUE.try_get().expect("mouse queue not initialized");
+        report_dropped();
+
+        let mut registered = false;
+        loop {
+            while let Some(byte) = queue.pop() {
+                if let Some(event) = self.decoder.add_byte(byte) {
+                    return Poll::Ready(Some(event));
+                }
+            }
+            if registered {
+                priority::waiting_for_input();
+                return Poll::Pending;
+            }
+            // Bytes pushed before the waker was in place woke nobody
+            self.listener.register(cx.waker());
+            registered = true;
+        }
+    }
+}
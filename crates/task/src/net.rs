@@ -0,0 +1,331 @@
+//!
+//! Networking
+//!
+//! [smoltcp] implements the protocols, this module gives it a home: one
+//! [`Interface`] and the sockets on it, owned by a driver task that polls
+//! them whenever the device has frames or a protocol timer runs out.
+//! Sockets register their task's waker with smoltcp, so a task waiting for
+//! data sleeps until a poll actually delivers some:
+//!
+//! ```ignore
+//! net::init(&mut device, net::Config::ethernet(mac))?;
+//! executor.spawn(Task::new(net::run(device)).with_name("net"));
+//! ```
+//!
+//! A device driver calls [`wake`] from its receive interrupt, socket types
+//! call it after queueing data so the driver task sends it out.
+//!
+
+mod dhcp;
+mod dns;
+mod driver;
+pub mod http;
+mod loopback;
+mod neighbor;
+mod ping;
+mod ports;
+mod sntp;
+mod tcp;
+mod udp;
+mod virtio;
+
+use std::{
+    fmt,
+    future::poll_fn,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use smoltcp::{
+    iface::{self, Interface, SocketSet},
+    phy::Device,
+    socket::AnySocket,
+    wire::{HardwareAddress, IpCidr},
+};
+
+pub use dhcp::{Lease, lease, run_dhcp, watch_lease};
+pub use dns::{dns_servers, resolve, set_dns_servers};
+pub use driver::NetDriver;
+pub use loopback::Loopback;
+pub use neighbor::{
+    Neighbor, NeighborState, add_static_neighbor, flush_neighbors, neighbors, remove_neighbor,
+};
+pub use ping::{PING_INTERVAL, PingStats, Pinger, ping};
+pub use smoltcp::iface::SocketHandle;
+pub use smoltcp::wire::{
+    EthernetAddress, IpAddress, IpEndpoint, IpListenEndpoint, Ipv4Address, Ipv4Cidr,
+};
+pub use sntp::{SNTP_INTERVAL, SntpSample, run_sntp, sntp_query};
+pub use tcp::{Incoming, ListenOptions, TcpListener, TcpReader, TcpStream, TcpWriter};
+pub use udp::UdpSocket;
+pub use virtio::{VirtioNet, VirtioNetInterrupt};
+
+use crate::{
+    event,
+    future::race,
+    metrics::Counter,
+    rng,
+    sync::Notify,
+    time::{self, Instant},
+    timer,
+};
+
+// smoltcp asking to be polled again right away would otherwise keep the
+// driver task from ever yielding
+const MIN_POLL_DELAY: Duration = Duration::from_millis(1);
+
+static POLLS: Counter = Counter::new("net_polls_total", "Times the network interface was polled");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// [`init`] was called before
+    AlreadyInitialized,
+    /// There's no interface yet, see [`init`]
+    NotInitialized,
+    /// Another socket is bound to the port
+    AddrInUse,
+    /// An address or port is missing, or there's no route to it
+    Unaddressable,
+    /// More data than the socket's buffer can ever hold
+    TooLarge,
+    /// The host turned down the connection or didn't answer
+    ConnectionRefused,
+    /// Not a name DNS can look up
+    InvalidName,
+    /// DNS doesn't know the name
+    NotFound,
+    /// No DNS server to ask, see [`set_dns_servers`]
+    NoDnsServers,
+    /// The host didn't answer in time
+    TimedOut,
+    /// The host's answer was malformed or refused the request
+    BadResponse,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::AlreadyInitialized => f.write_str("network is already initialized"),
+            Error::NotInitialized => f.write_str("network is not initialized"),
+            Error::AddrInUse => f.write_str("address in use"),
+            Error::Unaddressable => f.write_str("address unreachable"),
+            Error::TooLarge => f.write_str("too large for the socket buffer"),
+            Error::ConnectionRefused => f.write_str("connection refused"),
+            Error::InvalidName => f.write_str("invalid host name"),
+            Error::NotFound => f.write_str("host not found"),
+            Error::NoDnsServers => f.write_str("no DNS servers"),
+            Error::TimedOut => f.write_str("timed out"),
+            Error::BadResponse => f.write_str("bad response"),
+        }
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// How the interface is set up
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+    /// `None` for devices carrying bare IP packets, such as a loopback
+    pub mac: Option<EthernetAddress>,
+    /// Static address, if there's no DHCP
+    pub address: Option<Ipv4Cidr>,
+    pub gateway: Option<Ipv4Address>,
+}
+
+impl Config {
+    /// An ethernet card without addresses yet
+    pub fn ethernet(mac: EthernetAddress) -> Self {
+        Config {
+            mac: Some(mac),
+            address: None,
+            gateway: None,
+        }
+    }
+
+    /// A device without a link layer
+    pub fn ip() -> Self {
+        Config {
+            mac: None,
+            address: None,
+            gateway: None,
+        }
+    }
+
+    /// A [`Loopback`] device at `127.0.0.1`
+    pub fn loopback() -> Self {
+        Config::ip().with_address(Ipv4Cidr::new(Ipv4Address::new(127, 0, 0, 1), 8))
+    }
+
+    pub fn with_address(mut self, address: Ipv4Cidr) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    pub fn with_gateway(mut self, gateway: Ipv4Address) -> Self {
+        self.gateway = Some(gateway);
+        self
+    }
+}
+
+struct Stack {
+    iface: Interface,
+    sockets: SocketSet<'static>,
+}
+
+static STACK: spin::Mutex<Option<Stack>> = spin::Mutex::new(None);
+
+// Woken when the device has frames or a socket has something to send
+static POLL: Notify = Notify::new();
+
+/// smoltcp keeps its own clock type, counted from boot like ours
+pub(crate) fn now() -> smoltcp::time::Instant {
+    smoltcp::time::Instant::from_micros(Instant::now().since_boot().as_micros() as i64)
+}
+
+/// Set up the interface for `device`, which [`run`] then drives
+pub fn init(device: &mut (impl Device + ?Sized), config: Config) -> Result<()> {
+    let mut stack = STACK.lock();
+    if stack.is_some() {
+        return Err(Error::AlreadyInitialized);
+    }
+    let hardware_address = match config.mac {
+        Some(mac) => HardwareAddress::Ethernet(mac),
+        None => HardwareAddress::Ip,
+    };
+    let mut iface_config = iface::Config::new(hardware_address);
+    // smoltcp picks DNS query IDs and TCP sequence numbers from it
+    iface_config.random_seed =
+        rng::try_next_u64().unwrap_or_else(|_| time::uptime().as_nanos() as u64);
+    let mut iface = Interface::new(iface_config, device, now());
+    if let Some(address) = config.address {
+        iface.update_ip_addrs(|addrs| {
+            addrs
+                .push(IpCidr::Ipv4(address))
+                .expect("an interface has room for an address");
+        });
+    }
+    if let Some(gateway) = config.gateway {
+        iface
+            .routes_mut()
+            .add_default_ipv4_route(gateway)
+            .expect("an interface has room for a route");
+    }
+    *stack = Some(Stack {
+        iface,
+        sockets: SocketSet::new(Vec::new()),
+    });
+    Ok(())
+}
+
+/// Have the driver task poll the interface soon
+pub fn wake() {
+    POLL.notify_one();
+}
+
+/// Published on the [`event`](crate::event) bus when [`run`] starts and
+/// stops driving the interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    Up,
+    Down,
+}
+
+// Publishes `LinkState::Down` however `run` ends, aborted included
+struct Link;
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        event::publish(LinkState::Down);
+    }
+}
+
+/// Drive the interface over `device`, spawn it as a task after [`init`].
+///
+/// Polls whenever [`wake`] is called and when smoltcp's next timer is due,
+/// retransmissions and the like, otherwise it sleeps.
+pub async fn run(device: impl Device) {
+    let mut device = neighbor::Snoop::new(device);
+    event::publish(LinkState::Up);
+    let _link = Link;
+    loop {
+        let delay = {
+            let mut stack = STACK.lock();
+            let Some(Stack { iface, sockets }) = stack.as_mut() else {
+                return;
+            };
+            let now = now();
+            iface.poll(now, &mut device, sockets);
+            tcp::reap(sockets);
+            POLLS.inc();
+            iface.poll_delay(now, sockets)
+        };
+        match delay {
+            Some(delay) => {
+                let delay = Duration::from_micros(delay.total_micros()).max(MIN_POLL_DELAY);
+                race(POLL.notified(), timer::sleep(delay)).await;
+            }
+            None => POLL.notified().await,
+        }
+    }
+}
+
+/// The interface's IPv4 address, if it has one
+pub fn ipv4_address() -> Option<Ipv4Cidr> {
+    let stack = STACK.lock();
+    stack
+        .as_ref()?
+        .iface
+        .ip_addrs()
+        .iter()
+        .map(|&IpCidr::Ipv4(cidr)| cidr)
+        .next()
+}
+
+/// Run `f` on the interface, for changing its addresses or routes
+pub fn with_interface<R>(f: impl FnOnce(&mut Interface) -> R) -> Result<R> {
+    let result = {
+        let mut stack = STACK.lock();
+        f(&mut stack.as_mut().ok_or(Error::NotInitialized)?.iface)
+    };
+    wake();
+    Ok(result)
+}
+
+/// Add `socket` to the interface. Socket types wrap the handle and
+/// remove it again when dropped.
+pub fn add_socket<T: AnySocket<'static>>(socket: T) -> Result<SocketHandle> {
+    let mut stack = STACK.lock();
+    let stack = stack.as_mut().ok_or(Error::NotInitialized)?;
+    Ok(stack.sockets.add(socket))
+}
+
+/// Drop the socket behind `handle`, its owner is gone
+pub fn remove_socket(handle: SocketHandle) {
+    if let Some(stack) = STACK.lock().as_mut() {
+        stack.sockets.remove(handle);
+    }
+    wake();
+}
+
+/// Run `f` on the socket behind `handle`
+pub fn with_socket<T: AnySocket<'static>, R>(
+    handle: SocketHandle,
+    f: impl FnOnce(&mut T, &mut Interface) -> R,
+) -> R {
+    let mut stack = STACK.lock();
+    let Stack { iface, sockets } = stack.as_mut().expect("sockets outlive the interface");
+    f(sockets.get_mut(handle), iface)
+}
+
+/// Wait until `f` is ready on the socket behind `handle`. `f` registers
+/// the context's waker with the socket when it isn't, the next poll that
+/// changes the socket wakes the task.
+pub async fn poll_socket<T: AnySocket<'static>, R>(
+    handle: SocketHandle,
+    mut f: impl FnMut(&mut T, &mut Context) -> Poll<R>,
+) -> R {
+    let ready = poll_fn(|cx| with_socket(handle, |socket, _| f(socket, cx))).await;
+    // Whatever `f` did may have left something to send
+    wake();
+    ready
+}
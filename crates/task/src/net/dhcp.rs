@@ -0,0 +1,106 @@
+use std::task::Poll;
+
+use smoltcp::{
+    iface::Interface,
+    socket::dhcpv4::{self, Event},
+    wire::{IpAddress, IpCidr, Ipv4Address, Ipv4Cidr},
+};
+
+use crate::{channel::watch, log};
+
+/// What a DHCP server assigned the interface
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    pub address: Ipv4Cidr,
+    pub gateway: Option<Ipv4Address>,
+    pub dns_servers: Vec<Ipv4Address>,
+}
+
+static LEASE: spin::Once<watch::Sender<Option<Lease>>> = spin::Once::new();
+
+fn sender() -> &'static watch::Sender<Option<Lease>> {
+    LEASE.call_once(|| watch::channel(None).0)
+}
+
+/// The current lease, `None` until [`run_dhcp`] gets one or after it ran
+/// out
+pub fn lease() -> Option<Lease> {
+    sender().borrow().clone()
+}
+
+/// Receiver for waiting on lease changes with `changed().await`
+pub fn watch_lease() -> watch::Receiver<Option<Lease>> {
+    sender().subscribe()
+}
+
+/// Configure the interface over DHCP, spawn it as a task after
+/// [`init`](super::init). Runs forever: smoltcp renews the lease before it
+/// expires and starts over if it's lost.
+pub async fn run_dhcp() {
+    let handle = match super::add_socket(dhcpv4::Socket::new()) {
+        Ok(handle) => handle,
+        Err(err) => {
+            log::warn!("dhcp: {}", err);
+            return;
+        }
+    };
+    loop {
+        let lease = super::poll_socket(handle, |socket: &mut dhcpv4::Socket, cx| {
+            match socket.poll() {
+                Some(Event::Configured(config)) => Poll::Ready(Some(Lease {
+                    address: config.address,
+                    gateway: config.router,
+                    dns_servers: config.dns_servers.iter().copied().collect(),
+                })),
+                Some(Event::Deconfigured) => Poll::Ready(None),
+                None => {
+                    socket.register_waker(cx.waker());
+                    Poll::Pending
+                }
+            }
+        })
+        .await;
+
+        let _ = super::with_interface(|iface| apply(iface, lease.as_ref()));
+        if let Some(lease) = &lease {
+            let servers: Vec<IpAddress> = lease
+                .dns_servers
+                .iter()
+                .map(|&server| server.into())
+                .collect();
+            super::set_dns_servers(&servers);
+        }
+        match &lease {
+            Some(Lease {
+                address,
+                gateway: Some(gateway),
+                ..
+            }) => log::info!("dhcp: got {} via {}", address, gateway),
+            Some(lease) => log::info!("dhcp: got {}", lease.address),
+            None => log::warn!("dhcp: lease lost"),
+        }
+        sender().send_replace(lease);
+    }
+}
+
+fn apply(iface: &mut Interface, lease: Option<&Lease>) {
+    iface.update_ip_addrs(|addrs| {
+        addrs.clear();
+        if let Some(lease) = lease {
+            addrs
+                .push(IpCidr::Ipv4(lease.address))
+                .expect("addresses were just cleared");
+        }
+    });
+    match lease.and_then(|lease| lease.gateway) {
+        Some(gateway) => {
+            iface
+                .routes_mut()
+                .add_default_ipv4_route(gateway)
+                .expect("the default route replaces the old one");
+        }
+        None => {
+            iface.routes_mut().remove_default_ipv4_route();
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::task::Poll;
+
+use smoltcp::{
+    socket::dns::{self, GetQueryResultError},
+    wire::{DnsQueryType, IpAddress},
+};
+
+use super::{Error, Result, SocketHandle};
+
+static SERVERS: spin::Mutex<Vec<IpAddress>> = spin::Mutex::new(Vec::new());
+
+/// Ask `servers` from now on, the DHCP client sets the ones it's told
+pub fn set_dns_servers(servers: &[IpAddress]) {
+    *SERVERS.lock() = servers.to_vec();
+}
+
+pub fn dns_servers() -> Vec<IpAddress> {
+    SERVERS.lock().clone()
+}
+
+// Removes the query's socket however `resolve` ends
+struct Query(SocketHandle);
+
+impl Drop for Query {
+    fn drop(&mut self) {
+        super::remove_socket(self.0);
+    }
+}
+
+/// The IPv4 addresses of `name`, asking the DNS servers. An address
+/// written out is returned as it is, `localhost` is the loopback address.
+pub async fn resolve(name: &str) -> Result<Vec<IpAddress>> {
+    if let Ok(address) = name.parse() {
+        return Ok(vec![address]);
+    }
+    if name.eq_ignore_ascii_case("localhost") {
+        return Ok(vec![IpAddress::v4(127, 0, 0, 1)]);
+    }
+    let servers = dns_servers();
+    if servers.is_empty() {
+        return Err(Error::NoDnsServers);
+    }
+    let socket = Query(super::add_socket(dns::Socket::new(&servers, vec![None]))?);
+    let query = super::with_socket(socket.0, |socket: &mut dns::Socket, iface| {
+        socket.start_query(iface.context(), name, DnsQueryType::A)
+    })
+    .map_err(|_| Error::InvalidName)?;
+    super::wake();
+
+    // smoltcp retries on its own and gives up after a while
+    super::poll_socket(socket.0, |socket: &mut dns::Socket, cx| {
+        match socket.get_query_result(query) {
+            Ok(addresses) if addresses.is_empty() => Poll::Ready(Err(Error::NotFound)),
+            Ok(addresses) => Poll::Ready(Ok(addresses.to_vec())),
+            Err(GetQueryResultError::Pending) => {
+                socket.register_query_waker(query, cx.waker());
+                Poll::Pending
+            }
+            Err(GetQueryResultError::Failed) => Poll::Ready(Err(Error::NotFound)),
+        }
+    })
+    .await
+}
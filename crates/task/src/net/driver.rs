@@ -0,0 +1,40 @@
+use smoltcp::phy::Device;
+
+use super::{Config, init, run};
+use crate::drivers::{Driver, InitFuture, RunFuture};
+
+/// The network stack on `device` as a [`Driver`]: [`init`] with the
+/// config, then [`run`]
+pub struct NetDriver<D> {
+    device: Option<D>,
+    config: Option<Config>,
+}
+
+impl<D: Device + Send + 'static> NetDriver<D> {
+    pub fn new(device: D, config: Config) -> Self {
+        NetDriver {
+            device: Some(device),
+            config: Some(config),
+        }
+    }
+}
+
+impl<D: Device + Send + 'static> Driver for NetDriver<D> {
+    fn name(&self) -> &'static str {
+        "net"
+    }
+
+    fn init(&mut self) -> InitFuture<'_> {
+        Box::pin(async move {
+            let (Some(device), Some(config)) = (self.device.as_mut(), self.config.take()) else {
+                return Err("already initialized".into());
+            };
+            init(device, config).map_err(|err| err.to_string())
+        })
+    }
+
+    fn run(&mut self) -> Option<RunFuture> {
+        let device = self.device.take()?;
+        Some(Box::pin(run(device)))
+    }
+}
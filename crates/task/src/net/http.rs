@@ -0,0 +1,323 @@
+//!
+//! HTTP/1.1
+//!
+//! Just enough of it to fetch a page, plain `http://` only:
+//!
+//! ```ignore
+//! let response = http::get("http://example.com/").await?;
+//! println!("{} {}", response.status, response.text()?);
+//! ```
+//!
+//! And to serve a few, see [`serve`].
+//!
+
+mod server;
+
+use std::{collections::BTreeMap, fmt, future::poll_fn};
+
+use super::{IpEndpoint, TcpStream};
+use crate::io::{self, AsyncReadExt, AsyncWriteExt, BufReader};
+
+pub use server::{Request, ResponseFuture, Router, metrics_page, serve, status_page};
+
+/// Header names are lowercased, HTTP doesn't care about their case
+pub type Headers = BTreeMap<String, String>;
+
+/// Longest status or header line accepted
+const MAX_LINE: usize = 8 * 1024;
+/// Most header lines accepted, trailers of a chunked body included
+const MAX_HEADERS: usize = 100;
+/// Most header bytes accepted, names and values together
+const MAX_HEADER_BYTES: usize = 64 * 1024;
+/// Largest response body [`get`] takes
+const MAX_RESPONSE_BODY: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// Not an `http://host[:port]/path` URL
+    InvalidUrl,
+    /// `https` and friends
+    UnsupportedScheme,
+    /// The other end sent something that isn't HTTP
+    Malformed,
+    /// Headers or a body over the limit
+    TooLarge,
+    Net(super::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidUrl => f.write_str("invalid URL"),
+            Error::UnsupportedScheme => f.write_str("only http:// is supported"),
+            Error::Malformed => f.write_str("malformed HTTP message"),
+            Error::TooLarge => f.write_str("message too large"),
+            Error::Net(err) => err.fmt(f),
+            Error::Io(err) => err.fmt(f),
+        }
+    }
+}
+
+impl From<super::Error> for Error {
+    fn from(err: super::Error) -> Self {
+        Error::Net(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// The parts of an `http://` URL a request needs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Url {
+    pub host: String,
+    pub port: u16,
+    /// With the query, `/` if the URL had none
+    pub path: String,
+}
+
+impl Url {
+    pub fn parse(url: &str) -> Result<Url> {
+        let (scheme, rest) = url.split_once("://").ok_or(Error::InvalidUrl)?;
+        if !scheme.eq_ignore_ascii_case("http") {
+            return Err(Error::UnsupportedScheme);
+        }
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(at) => rest.split_at(at),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| Error::InvalidUrl)?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(Error::InvalidUrl);
+        }
+        let path = if path.starts_with('?') {
+            format!("/{}", path)
+        } else {
+            path.into()
+        };
+        Ok(Url {
+            host: host.into(),
+            port,
+            path,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub reason: String,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Response {
+        Response {
+            status,
+            reason: reason(status).into(),
+            headers: Headers::new(),
+            body: body.into(),
+        }
+    }
+
+    /// `200 OK` with an HTML page
+    pub fn html(body: impl Into<String>) -> Response {
+        Response::new(200, body.into()).with_header("Content-Type", "text/html; charset=utf-8")
+    }
+
+    /// `200 OK` with plain text
+    pub fn plain(body: impl Into<String>) -> Response {
+        Response::new(200, body.into()).with_header("Content-Type", "text/plain; charset=utf-8")
+    }
+
+    pub fn with_header(mut self, name: &str, value: impl Into<String>) -> Response {
+        self.headers.insert(name.to_ascii_lowercase(), value.into());
+        self
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+
+    /// The body as text, [`io::Error::InvalidData`] if it isn't UTF-8
+    pub fn text(&self) -> Result<&str> {
+        std::str::from_utf8(&self.body).map_err(|_| Error::Io(io::Error::InvalidData))
+    }
+}
+
+/// Reason phrase of the common status codes
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Content Too Large",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+/// Fetch `url`, following no redirects. [`Error::TooLarge`] for a body over
+/// [`MAX_RESPONSE_BODY`].
+pub async fn get(url: &str) -> Result<Response> {
+    let url = Url::parse(url)?;
+    let address = super::resolve(&url.host).await?[0];
+    let mut stream = TcpStream::connect(IpEndpoint::new(address, url.port)).await?;
+
+    let host = match url.port {
+        80 => url.host.clone(),
+        port => format!("{}:{}", url.host, port),
+    };
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: async-os\r\nConnection: close\r\n\r\n",
+        url.path, host
+    );
+    stream.write_all(request.as_bytes()).await?;
+    stream.flush().await?;
+
+    let mut reader = BufReader::new(stream);
+    let status_line = read_line(&mut reader).await?;
+    let mut parts = status_line.splitn(3, ' ');
+    let (Some(version), Some(status)) = (parts.next(), parts.next()) else {
+        return Err(Error::Malformed);
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(Error::Malformed);
+    }
+    let status = status.parse().map_err(|_| Error::Malformed)?;
+    let reason = parts.next().unwrap_or("").into();
+    let headers = read_headers(&mut reader).await?;
+
+    let chunked = headers
+        .get("transfer-encoding")
+        .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"));
+    let body = if chunked {
+        read_chunked(&mut reader).await?
+    } else if let Some(length) = headers.get("content-length") {
+        let length = length.parse().map_err(|_| Error::Malformed)?;
+        if length > MAX_RESPONSE_BODY {
+            return Err(Error::TooLarge);
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).await?;
+        body
+    } else {
+        read_to_end(&mut reader).await?
+    };
+    Ok(Response {
+        status,
+        reason,
+        headers,
+        body,
+    })
+}
+
+/// The next line without its `\r\n`. [`Error::Malformed`] once more than
+/// [`MAX_LINE`] bytes came without one, the rest isn't read.
+async fn read_line(reader: &mut BufReader<TcpStream>) -> Result<String> {
+    let mut line = Vec::new();
+    loop {
+        let available = poll_fn(|cx| reader.poll_fill_buf(cx).map_ok(<[u8]>::len)).await?;
+        if available == 0 {
+            if line.is_empty() {
+                return Err(Error::Io(io::Error::UnexpectedEof));
+            }
+            break;
+        }
+        let chunk = reader.buffer();
+        let (used, done) = match chunk.iter().position(|&byte| byte == b'\n') {
+            Some(at) => (at + 1, true),
+            None => (chunk.len(), false),
+        };
+        if line.len() + used > MAX_LINE {
+            return Err(Error::Malformed);
+        }
+        line.extend_from_slice(&chunk[..used]);
+        reader.consume(used);
+        if done {
+            break;
+        }
+    }
+    let line = String::from_utf8(line).map_err(|_| Error::Malformed)?;
+    Ok(line.trim_end_matches(['\r', '\n']).into())
+}
+
+/// Header lines up to the empty one ending them, [`Error::TooLarge`] past
+/// [`MAX_HEADERS`] lines or [`MAX_HEADER_BYTES`]
+async fn read_headers(reader: &mut BufReader<TcpStream>) -> Result<Headers> {
+    let mut headers = Headers::new();
+    let mut bytes = 0;
+    for _ in 0..=MAX_HEADERS {
+        let line = read_line(reader).await?;
+        if line.is_empty() {
+            return Ok(headers);
+        }
+        bytes += line.len();
+        if bytes > MAX_HEADER_BYTES {
+            return Err(Error::TooLarge);
+        }
+        let (name, value) = line.split_once(':').ok_or(Error::Malformed)?;
+        headers.insert(name.trim().to_ascii_lowercase(), value.trim().into());
+    }
+    Err(Error::TooLarge)
+}
+
+/// A body sent as chunks, each after its length in hex, up to an empty one
+async fn read_chunked(reader: &mut BufReader<TcpStream>) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    loop {
+        let line = read_line(reader).await?;
+        // Chunk extensions after `;` mean nothing to us
+        let size = line.split(';').next().unwrap_or("").trim();
+        let size = usize::from_str_radix(size, 16).map_err(|_| Error::Malformed)?;
+        if size == 0 {
+            // Trailers, the same as headers
+            read_headers(reader).await?;
+            return Ok(body);
+        }
+        let start = body.len();
+        let end = start.checked_add(size).ok_or(Error::TooLarge)?;
+        if end > MAX_RESPONSE_BODY {
+            return Err(Error::TooLarge);
+        }
+        body.resize(end, 0);
+        reader.read_exact(&mut body[start..]).await?;
+        if !read_line(reader).await?.is_empty() {
+            return Err(Error::Malformed);
+        }
+    }
+}
+
+async fn read_to_end(reader: &mut BufReader<TcpStream>) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    let mut buf = [0; io::DEFAULT_BUF_SIZE];
+    loop {
+        let count = reader.read(&mut buf).await?;
+        if count == 0 {
+            return Ok(body);
+        }
+        if body.len() + count > MAX_RESPONSE_BODY {
+            return Err(Error::TooLarge);
+        }
+        body.extend_from_slice(&buf[..count]);
+    }
+}
@@ -0,0 +1,236 @@
+use std::{
+    collections::BTreeMap, fmt::Write as _, future::Future, pin::Pin, rc::Rc, time::Duration,
+};
+
+use super::{Error, Headers, Response, Result, read_headers, read_line};
+use crate::{
+    executor,
+    future::{Either, race},
+    io::{AsyncReadExt, AsyncWriteExt, BufReader},
+    join_set::JoinSet,
+    log, metrics,
+    net::{IpEndpoint, IpListenEndpoint, TcpListener, TcpStream},
+    time, timer,
+};
+
+/// How long a client gets to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Largest request body accepted
+const MAX_BODY: usize = 1024 * 1024;
+
+/// Future returned by a route's handler
+pub type ResponseFuture = Pin<Box<dyn Future<Output = Response>>>;
+
+type Handler = Box<dyn Fn(Request) -> ResponseFuture>;
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    /// Without the query
+    pub path: String,
+    /// After the `?`, empty if there's none
+    pub query: String,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+    pub peer: IpEndpoint,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+}
+
+/// Picks the handler for a request by its path, `404 Not Found` for paths
+/// without one
+#[derive(Default)]
+pub struct Router {
+    routes: BTreeMap<String, Handler>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Router::default()
+    }
+
+    /// Answer requests for `path` with `handler`, whatever their method
+    pub fn route<F>(mut self, path: &str, handler: impl Fn(Request) -> F + 'static) -> Self
+    where
+        F: Future<Output = Response> + 'static,
+    {
+        self.routes.insert(
+            path.into(),
+            Box::new(move |request| Box::pin(handler(request))),
+        );
+        self
+    }
+
+    pub async fn handle(&self, request: Request) -> Response {
+        match self.routes.get(&request.path) {
+            Some(handler) => handler(request).await,
+            None => Response::new(404, "not found\n"),
+        }
+    }
+}
+
+/// Serve `router` on `endpoint` until the listener fails. Each connection
+/// is handled concurrently in the calling task, one request per
+/// connection.
+///
+/// ```ignore
+/// let router = Router::new().route("/", http::status_page);
+/// executor.spawn_local(LocalTask::fallible(http::serve(80, router)));
+/// ```
+pub async fn serve(
+    endpoint: impl Into<IpListenEndpoint>,
+    router: Router,
+) -> core::result::Result<(), crate::net::Error> {
+    let listener = TcpListener::bind(endpoint)?;
+    let router = Rc::new(router);
+    let mut connections = JoinSet::new();
+    loop {
+        let accepted = if connections.is_empty() {
+            listener.accept().await
+        } else {
+            // Finished connections have to be collected as well
+            match race(listener.accept(), connections.join_next()).await {
+                Either::Left(accepted) => accepted,
+                Either::Right(_) => continue,
+            }
+        };
+        let (stream, peer) = accepted?;
+        connections.spawn(connection(stream, peer, router.clone()));
+    }
+}
+
+async fn connection(stream: TcpStream, peer: IpEndpoint, router: Rc<Router>) {
+    let mut reader = BufReader::new(stream);
+    let response = match timer::timeout(REQUEST_TIMEOUT, read_request(&mut reader, peer)).await {
+        Ok(Ok(request)) => router.handle(request).await,
+        Ok(Err(Error::Malformed)) => Response::new(400, "bad request\n"),
+        Ok(Err(Error::TooLarge)) => Response::new(413, "request too large\n"),
+        // The client is gone
+        Ok(Err(_)) => return,
+        Err(_) => Response::new(408, "request timeout\n"),
+    };
+    let mut stream = reader.into_inner();
+    if let Err(err) = write_response(&mut stream, &response).await {
+        log::debug!("http: {}: {}", peer, err);
+    }
+}
+
+async fn read_request(reader: &mut BufReader<TcpStream>, peer: IpEndpoint) -> Result<Request> {
+    let line = read_line(reader).await?;
+    let mut parts = line.split(' ');
+    let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(Error::Malformed);
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(Error::Malformed);
+    }
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let headers = read_headers(reader).await?;
+
+    let length = match headers.get("content-length") {
+        Some(length) => length.parse().map_err(|_| Error::Malformed)?,
+        None => 0,
+    };
+    if length > MAX_BODY {
+        return Err(Error::TooLarge);
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+    Ok(Request {
+        method: method.into(),
+        path: path.into(),
+        query: query.into(),
+        headers,
+        body,
+        peer,
+    })
+}
+
+async fn write_response(stream: &mut TcpStream, response: &Response) -> Result<()> {
+    let mut head = format!("HTTP/1.1 {} {}\r\n", response.status, response.reason);
+    for (name, value) in &response.headers {
+        let _ = write!(head, "{}: {}\r\n", name, value);
+    }
+    if response.header("date").is_none() {
+        let now = time::SystemTime::now().date_time();
+        let _ = write!(head, "date: {}\r\n", now.http_date());
+    }
+    let _ = write!(
+        head,
+        "Content-Length: {}\r\nConnection: close\r\n\r\n",
+        response.body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    stream.close().await?;
+    Ok(())
+}
+
+/// A page with the running tasks and every metric, for
+/// [`Router::route`]
+pub async fn status_page(_request: Request) -> Response {
+    let mut page = String::from(
+        "<!DOCTYPE html>\n<html><head><title>async-os</title></head><body>\n<h1>async-os</h1>\n",
+    );
+    let _ = writeln!(page, "<p>up {} s</p>", time::uptime().as_secs());
+
+    page.push_str("<h2>Tasks</h2>\n<table>\n");
+    page.push_str(
+        "<tr><th>ID</th><th>Priority</th><th>Polls</th><th>Wakes</th><th>Heap</th><th>Name</th></tr>\n",
+    );
+    for task in executor::tasks() {
+        let heap = task
+            .memory
+            .map_or("-".into(), |memory| memory.in_use().to_string());
+        let _ = writeln!(
+            page,
+            "<tr><td>{}</td><td>{:?}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            task.id,
+            task.priority,
+            task.polls,
+            task.wakes,
+            heap,
+            escape(task.name.as_deref().unwrap_or("-"))
+        );
+    }
+    page.push_str("</table>\n<h2>Metrics</h2>\n<table>\n");
+    for sample in metrics::snapshot() {
+        let _ = writeln!(
+            page,
+            "<tr><td title=\"{}\">{}</td><td>{}</td></tr>",
+            escape(sample.help),
+            escape(&sample.series()),
+            sample.value
+        );
+    }
+    page.push_str("</table>\n</body></html>\n");
+    Response::html(page)
+}
+
+/// Every metric in the Prometheus text format, for [`Router::route`] at
+/// `/metrics`
+pub async fn metrics_page(_request: Request) -> Response {
+    Response::new(200, metrics::prometheus())
+        .with_header("Content-Type", "text/plain; version=0.0.4; charset=utf-8")
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
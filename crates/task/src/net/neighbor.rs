@@ -0,0 +1,312 @@
+use std::{collections::BTreeMap, collections::VecDeque, time::Duration};
+
+use smoltcp::{
+    phy::{self, Device, DeviceCapabilities, Medium},
+    time::Instant as NetInstant,
+    wire::{
+        ArpOperation, ArpPacket, ArpRepr, EthernetAddress, EthernetFrame, EthernetProtocol,
+        EthernetRepr, Ipv4Address,
+    },
+};
+
+use crate::{metrics::Counter, time::Instant};
+
+/// How long smoltcp trusts an ARP reply, ours are shown until then too
+const ENTRY_LIFETIME: Duration = Duration::from_secs(60);
+
+static REQUESTS: Counter = Counter::new("net_arp_requests_total", "ARP requests sent");
+static UNANSWERED: Counter = Counter::new(
+    "net_arp_unanswered_total",
+    "ARP requests asked again because nobody replied",
+);
+
+/// How an address in [`neighbors`] got there
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NeighborState {
+    /// Learned from ARP traffic
+    Reachable,
+    /// Added with [`add_static_neighbor`]
+    Static,
+    /// Asked for, no reply yet
+    Incomplete,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Neighbor {
+    pub address: Ipv4Address,
+    /// `None` while [`NeighborState::Incomplete`]
+    pub mac: Option<EthernetAddress>,
+    pub state: NeighborState,
+    /// Since the entry was learned or asked for, `None` for static ones
+    pub age: Option<Duration>,
+}
+
+// smoltcp keeps its cache to itself, this one is rebuilt from the ARP
+// packets going through the device
+struct Table {
+    learned: BTreeMap<Ipv4Address, (EthernetAddress, Instant)>,
+    fixed: BTreeMap<Ipv4Address, EthernetAddress>,
+    asked: BTreeMap<Ipv4Address, Instant>,
+}
+
+static TABLE: spin::Mutex<Table> = spin::Mutex::new(Table {
+    learned: BTreeMap::new(),
+    fixed: BTreeMap::new(),
+    asked: BTreeMap::new(),
+});
+
+/// The neighbor cache: addresses on the local network and their MAC
+/// addresses, by address
+pub fn neighbors() -> Vec<Neighbor> {
+    let table = TABLE.lock();
+    let mut neighbors: BTreeMap<Ipv4Address, Neighbor> = BTreeMap::new();
+    for (&address, &asked) in &table.asked {
+        neighbors.insert(
+            address,
+            Neighbor {
+                address,
+                mac: None,
+                state: NeighborState::Incomplete,
+                age: Some(asked.elapsed()),
+            },
+        );
+    }
+    for (&address, &(mac, seen)) in &table.learned {
+        if seen.elapsed() < ENTRY_LIFETIME {
+            neighbors.insert(
+                address,
+                Neighbor {
+                    address,
+                    mac: Some(mac),
+                    state: NeighborState::Reachable,
+                    age: Some(seen.elapsed()),
+                },
+            );
+        }
+    }
+    for (&address, &mac) in &table.fixed {
+        neighbors.insert(
+            address,
+            Neighbor {
+                address,
+                mac: Some(mac),
+                state: NeighborState::Static,
+                age: None,
+            },
+        );
+    }
+    neighbors.into_values().collect()
+}
+
+/// Answer ARP requests for `address` with `mac` without asking the network,
+/// for hosts that don't reply
+pub fn add_static_neighbor(address: Ipv4Address, mac: EthernetAddress) {
+    TABLE.lock().fixed.insert(address, mac);
+    // Whatever smoltcp has learned for the address goes
+    flush_interface();
+}
+
+/// Forget `address`, static or learned. `false` if it wasn't known.
+pub fn remove_neighbor(address: Ipv4Address) -> bool {
+    let mut table = TABLE.lock();
+    let fixed = table.fixed.remove(&address).is_some();
+    let learned = table.learned.remove(&address).is_some();
+    let asked = table.asked.remove(&address).is_some();
+    drop(table);
+    flush_interface();
+    fixed || learned || asked
+}
+
+/// Forget every learned address, static ones stay. The next packet to
+/// each neighbor asks for it again.
+pub fn flush_neighbors() {
+    let mut table = TABLE.lock();
+    table.learned.clear();
+    table.asked.clear();
+    drop(table);
+    flush_interface();
+}
+
+/// smoltcp empties its own cache whenever the addresses change
+fn flush_interface() {
+    let _ = super::with_interface(|iface| iface.update_ip_addrs(|_| {}));
+}
+
+/// Note what an ARP packet in `frame` tells about the neighbors. For an
+/// outgoing request to a static neighbor, returns the reply to receive.
+fn snoop(frame: &[u8], outgoing: bool) -> Option<Vec<u8>> {
+    let ethernet = EthernetFrame::new_checked(frame).ok()?;
+    if ethernet.ethertype() != EthernetProtocol::Arp {
+        return None;
+    }
+    let packet = ArpPacket::new_checked(ethernet.payload()).ok()?;
+    let ArpRepr::EthernetIpv4 {
+        operation,
+        source_hardware_addr,
+        source_protocol_addr,
+        target_protocol_addr,
+        ..
+    } = ArpRepr::parse(&packet).ok()?
+    else {
+        return None;
+    };
+
+    let mut table = TABLE.lock();
+    if !outgoing {
+        // Requests tell about the asker as much as replies do
+        table
+            .learned
+            .insert(source_protocol_addr, (source_hardware_addr, Instant::now()));
+        table.asked.remove(&source_protocol_addr);
+        return None;
+    }
+    if operation != ArpOperation::Request {
+        return None;
+    }
+    REQUESTS.inc();
+    if table
+        .asked
+        .insert(target_protocol_addr, Instant::now())
+        .is_some()
+    {
+        UNANSWERED.inc();
+    }
+    let &mac = table.fixed.get(&target_protocol_addr)?;
+    table.asked.remove(&target_protocol_addr);
+
+    let reply = ArpRepr::EthernetIpv4 {
+        operation: ArpOperation::Reply,
+        source_hardware_addr: mac,
+        source_protocol_addr: target_protocol_addr,
+        target_hardware_addr: source_hardware_addr,
+        target_protocol_addr: source_protocol_addr,
+    };
+    let header = EthernetRepr {
+        src_addr: mac,
+        dst_addr: source_hardware_addr,
+        ethertype: EthernetProtocol::Arp,
+    };
+    let mut bytes = vec![0; header.buffer_len() + reply.buffer_len()];
+    let mut frame = EthernetFrame::new_unchecked(&mut bytes[..]);
+    header.emit(&mut frame);
+    reply.emit(&mut ArpPacket::new_unchecked(frame.payload_mut()));
+    Some(bytes)
+}
+
+/// Wraps the device [`run`](super::run) drives, to watch ARP go by and
+/// answer for static neighbors
+pub(super) struct Snoop<D> {
+    inner: D,
+    ethernet: bool,
+    // Replies for static neighbors, received before anything from `inner`
+    injected: VecDeque<Vec<u8>>,
+}
+
+impl<D: Device> Snoop<D> {
+    pub(super) fn new(inner: D) -> Self {
+        let ethernet = inner.capabilities().medium == Medium::Ethernet;
+        Snoop {
+            inner,
+            ethernet,
+            injected: VecDeque::new(),
+        }
+    }
+}
+
+impl<D: Device> Device for Snoop<D> {
+    type RxToken<'a>
+        = RxToken<D::RxToken<'a>>
+    where
+        Self: 'a;
+    type TxToken<'a>
+        = TxToken<'a, D::TxToken<'a>>
+    where
+        Self: 'a;
+
+    fn receive(&mut self, timestamp: NetInstant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        if let Some(frame) = self.injected.pop_front() {
+            let Some(tx) = self.inner.transmit(timestamp) else {
+                self.injected.push_front(frame);
+                return None;
+            };
+            let tx = TxToken {
+                inner: tx,
+                ethernet: self.ethernet,
+                injected: &mut self.injected,
+            };
+            return Some((RxToken::Injected(frame), tx));
+        }
+        let (rx, tx) = self.inner.receive(timestamp)?;
+        let rx = RxToken::Device {
+            inner: rx,
+            ethernet: self.ethernet,
+        };
+        let tx = TxToken {
+            inner: tx,
+            ethernet: self.ethernet,
+            injected: &mut self.injected,
+        };
+        Some((rx, tx))
+    }
+
+    fn transmit(&mut self, timestamp: NetInstant) -> Option<Self::TxToken<'_>> {
+        Some(TxToken {
+            inner: self.inner.transmit(timestamp)?,
+            ethernet: self.ethernet,
+            injected: &mut self.injected,
+        })
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        self.inner.capabilities()
+    }
+}
+
+pub(super) enum RxToken<T> {
+    Device { inner: T, ethernet: bool },
+    Injected(Vec<u8>),
+}
+
+impl<T: phy::RxToken> phy::RxToken for RxToken<T> {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&[u8]) -> R,
+    {
+        match self {
+            RxToken::Device { inner, ethernet } => inner.consume(|frame| {
+                if ethernet {
+                    snoop(frame, false);
+                }
+                f(frame)
+            }),
+            RxToken::Injected(frame) => f(&frame),
+        }
+    }
+}
+
+pub(super) struct TxToken<'a, T> {
+    inner: T,
+    ethernet: bool,
+    injected: &'a mut VecDeque<Vec<u8>>,
+}
+
+impl<T: phy::TxToken> phy::TxToken for TxToken<'_, T> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let Self {
+            inner,
+            ethernet,
+            injected,
+        } = self;
+        inner.consume(len, |frame| {
+            let result = f(frame);
+            if ethernet && let Some(reply) = snoop(frame, true) {
+                injected.push_back(reply);
+                super::wake();
+            }
+            result
+        })
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU16, Ordering},
+    task::Poll,
+    time::Duration,
+};
+
+use smoltcp::{
+    phy::ChecksumCapabilities,
+    socket::icmp::{self, PacketBuffer, PacketMetadata},
+    wire::{Icmpv4Packet, Icmpv4Repr, IpAddress},
+};
+
+use super::{Error, Result, SocketHandle};
+use crate::{
+    time::Instant,
+    timer::{self, Elapsed},
+};
+
+/// Payload of each echo request, as much as the usual `ping` sends
+const PAYLOAD: [u8; 56] = [0xA5; 56];
+
+const QUEUED_PACKETS: usize = 4;
+const BUFFER_SIZE: usize = 1024;
+
+/// Time between echo requests of [`ping`]
+pub const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+// Identifies a pinger's replies, every pinger gets its own
+static NEXT_IDENT: AtomicU16 = AtomicU16::new(1);
+
+/// Sends ICMP echo requests to one host and waits for the replies
+pub struct Pinger {
+    handle: SocketHandle,
+    ident: u16,
+    to: IpAddress,
+}
+
+impl Pinger {
+    pub fn new(to: impl Into<IpAddress>) -> Result<Pinger> {
+        let buffer = || {
+            PacketBuffer::new(
+                vec![PacketMetadata::EMPTY; QUEUED_PACKETS],
+                vec![0; BUFFER_SIZE],
+            )
+        };
+        let mut socket = icmp::Socket::new(buffer(), buffer());
+        let ident = NEXT_IDENT.fetch_add(1, Ordering::Relaxed);
+        socket
+            .bind(icmp::Endpoint::Ident(ident))
+            .map_err(|_| Error::Unaddressable)?;
+        Ok(Pinger {
+            handle: super::add_socket(socket)?,
+            ident,
+            to: to.into(),
+        })
+    }
+
+    pub fn target(&self) -> IpAddress {
+        self.to
+    }
+
+    /// Send echo request `seq_no` and wait up to `timeout` for its reply.
+    /// The round trip time, `None` if the reply didn't come in time.
+    pub async fn echo(&self, seq_no: u16, timeout: Duration) -> Result<Option<Duration>> {
+        let request = Icmpv4Repr::EchoRequest {
+            ident: self.ident,
+            seq_no,
+            data: &PAYLOAD,
+        };
+        super::poll_socket(self.handle, |socket: &mut icmp::Socket, cx| {
+            match socket.send(request.buffer_len(), self.to) {
+                Ok(buf) => {
+                    let mut packet = Icmpv4Packet::new_unchecked(buf);
+                    request.emit(&mut packet, &ChecksumCapabilities::default());
+                    Poll::Ready(Ok(()))
+                }
+                Err(icmp::SendError::BufferFull) => {
+                    socket.register_send_waker(cx.waker());
+                    Poll::Pending
+                }
+                Err(icmp::SendError::Unaddressable) => Poll::Ready(Err(Error::Unaddressable)),
+            }
+        })
+        .await?;
+        let sent = Instant::now();
+
+        match timer::timeout(timeout, self.reply(seq_no)).await {
+            Ok(()) => Ok(Some(sent.elapsed())),
+            Err(Elapsed) => Ok(None),
+        }
+    }
+
+    /// Wait for the reply to `seq_no`, dropping late replies to earlier ones
+    async fn reply(&self, seq_no: u16) {
+        super::poll_socket(self.handle, |socket: &mut icmp::Socket, cx| {
+            while let Ok((data, from)) = socket.recv() {
+                if from != self.to {
+                    continue;
+                }
+                let Ok(packet) = Icmpv4Packet::new_checked(data) else {
+                    continue;
+                };
+                let reply = Icmpv4Repr::parse(&packet, &ChecksumCapabilities::default());
+                if let Ok(Icmpv4Repr::EchoReply {
+                    ident,
+                    seq_no: replied,
+                    ..
+                }) = reply
+                    && ident == self.ident
+                    && replied == seq_no
+                {
+                    return Poll::Ready(());
+                }
+            }
+            socket.register_recv_waker(cx.waker());
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+impl Drop for Pinger {
+    fn drop(&mut self) {
+        super::remove_socket(self.handle);
+    }
+}
+
+/// Round trip times of a run of echo requests
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PingStats {
+    pub sent: u32,
+    pub received: u32,
+    pub min: Option<Duration>,
+    pub max: Option<Duration>,
+    total: Duration,
+}
+
+impl PingStats {
+    /// Count a request and its round trip time, `None` if it was lost
+    pub fn record(&mut self, rtt: Option<Duration>) {
+        self.sent += 1;
+        let Some(rtt) = rtt else {
+            return;
+        };
+        self.received += 1;
+        self.total += rtt;
+        self.min = Some(self.min.map_or(rtt, |min| min.min(rtt)));
+        self.max = Some(self.max.map_or(rtt, |max| max.max(rtt)));
+    }
+
+    pub fn average(&self) -> Option<Duration> {
+        (self.received > 0).then(|| self.total / self.received)
+    }
+
+    /// Percentage of requests without a reply
+    pub fn loss(&self) -> u32 {
+        match self.sent {
+            0 => 0,
+            sent => (sent - self.received) * 100 / sent,
+        }
+    }
+}
+
+impl fmt::Display for PingStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} sent, {} received, {}% loss",
+            self.sent,
+            self.received,
+            self.loss()
+        )?;
+        if let (Some(min), Some(average), Some(max)) = (self.min, self.average(), self.max) {
+            write!(
+                f,
+                ", rtt min/avg/max {:.3}/{:.3}/{:.3} ms",
+                min.as_secs_f64() * 1000.0,
+                average.as_secs_f64() * 1000.0,
+                max.as_secs_f64() * 1000.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Ping `to` `count` times, a [`PING_INTERVAL`] apart, waiting up to
+/// `timeout` for each reply
+pub async fn ping(to: impl Into<IpAddress>, count: u16, timeout: Duration) -> Result<PingStats> {
+    let pinger = Pinger::new(to)?;
+    let mut stats = PingStats::default();
+    let mut interval = timer::interval(PING_INTERVAL);
+    for seq_no in 0..count {
+        if seq_no > 0 {
+            interval.tick().await;
+        }
+        stats.record(pinger.echo(seq_no, timeout).await?);
+    }
+    Ok(stats)
+}
@@ -0,0 +1,56 @@
+use std::{collections::BTreeSet, ops::RangeInclusive};
+
+use super::{Error, Result};
+use crate::rng;
+
+/// Where port `0` is replaced with a free port
+const EPHEMERAL_PORTS: RangeInclusive<u16> = 49152..=65535;
+
+/// Local ports in use by one protocol. smoltcp doesn't check, it would
+/// hand each packet to whichever socket it finds first.
+pub(super) struct Ports {
+    bound: BTreeSet<u16>,
+    // `0` until the first one is picked at random
+    next_ephemeral: u16,
+}
+
+impl Ports {
+    pub(super) const fn new() -> Self {
+        Ports {
+            bound: BTreeSet::new(),
+            next_ephemeral: 0,
+        }
+    }
+
+    /// Reserve `port`, or a free ephemeral port if it's `0`
+    pub(super) fn take(&mut self, port: u16) -> Result<u16> {
+        if port != 0 {
+            if !self.bound.insert(port) {
+                return Err(Error::AddrInUse);
+            }
+            return Ok(port);
+        }
+        if self.next_ephemeral == 0 {
+            // So ports don't repeat from one boot to the next
+            let count = EPHEMERAL_PORTS.len() as u64;
+            let offset = rng::try_next_u64().map_or(0, |random| random % count);
+            self.next_ephemeral = EPHEMERAL_PORTS.start() + offset as u16;
+        }
+        for _ in EPHEMERAL_PORTS {
+            let port = self.next_ephemeral;
+            self.next_ephemeral = if port == *EPHEMERAL_PORTS.end() {
+                *EPHEMERAL_PORTS.start()
+            } else {
+                port + 1
+            };
+            if self.bound.insert(port) {
+                return Ok(port);
+            }
+        }
+        Err(Error::AddrInUse)
+    }
+
+    pub(super) fn release(&mut self, port: u16) {
+        self.bound.remove(&port);
+    }
+}
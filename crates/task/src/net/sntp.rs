@@ -0,0 +1,247 @@
+use std::{collections::VecDeque, time::Duration};
+
+use smoltcp::wire::IpAddress;
+
+use super::{Error, Result, UdpSocket, resolve};
+use crate::{
+    bytes::{Buf, BufMut, BytesMut},
+    log,
+    metrics::{Counter, Gauge},
+    time::{self, Instant, SystemTime, UNIX_EPOCH},
+    timer,
+};
+
+/// Time between queries of [`run_sntp`] while the server answers
+pub const SNTP_INTERVAL: Duration = Duration::from_secs(64);
+
+/// Time before [`run_sntp`] asks again after a failed query
+const RETRY_INTERVAL: Duration = Duration::from_secs(8);
+
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Offsets larger than this, in nanoseconds, step the clock instead of
+/// slewing it for minutes, as ntpd does
+const STEP_THRESHOLD: i64 = 128_000_000;
+
+/// Answers the filter picks the best of
+const FILTER_SAMPLES: usize = 8;
+
+const NTP_PORT: u16 = 123;
+const PACKET_LEN: usize = 48;
+const VERSION: u8 = 4;
+const MODE_CLIENT: u8 = 3;
+const MODE_SERVER: u8 = 4;
+/// Leap indicator of a server that isn't synchronized itself
+const LEAP_UNSYNCHRONIZED: u8 = 3;
+
+/// From 1900, NTP's epoch, to 1970
+const UNIX_OFFSET_SECS: i128 = 2_208_988_800;
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+static OFFSET: Gauge = Gauge::new(
+    "sntp_offset_microseconds",
+    "Correction last applied to the wall clock, ahead if positive",
+);
+static JITTER: Gauge = Gauge::new(
+    "sntp_jitter_microseconds",
+    "Spread of the filtered offsets around the one applied",
+);
+static DELAY: Gauge = Gauge::new(
+    "sntp_delay_microseconds",
+    "Round trip of the last query to the time server",
+);
+static STEPS: Counter = Counter::new(
+    "sntp_steps_total",
+    "Times the wall clock was off too far to slew",
+);
+static FAILURES: Counter = Counter::new(
+    "sntp_failures_total",
+    "Time server queries without a usable answer",
+);
+
+/// One answer of a time server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SntpSample {
+    /// Nanoseconds the server's clock is ahead of ours, behind if negative
+    pub offset: i64,
+    /// The round trip, less the time the server took to answer
+    pub delay: Duration,
+    /// 1 for a server with a reference clock, one more for each hop
+    pub stratum: u8,
+}
+
+/// Ask the time server at `server` once
+pub async fn sntp_query(server: impl Into<IpAddress>) -> Result<SntpSample> {
+    let server = server.into();
+    let socket = UdpSocket::bind(0)?;
+    let sent_at = SystemTime::now();
+    let started = Instant::now();
+    // The server echoes it back, which tells its answer from stale ones
+    let transmit = ntp_timestamp(sent_at);
+    let mut request = BytesMut::with_capacity(PACKET_LEN);
+    request.put_u8(VERSION << 3 | MODE_CLIENT);
+    request.resize(PACKET_LEN - 8, 0);
+    request.put_u64(transmit);
+    socket.send_to(&request, (server, NTP_PORT)).await?;
+
+    let answer = timer::timeout(QUERY_TIMEOUT, async {
+        loop {
+            let (reply, from) = socket.recv().await?;
+            if from.addr != server || reply.len() < PACKET_LEN {
+                continue;
+            }
+            let mut reply = &reply[..];
+            let flags = reply.get_u8();
+            let stratum = reply.get_u8();
+            // Poll, precision, root delay and dispersion, reference
+            reply.advance(22);
+            if reply.get_u64() != transmit {
+                continue;
+            }
+            let received = reply.get_u64();
+            let answered = reply.get_u64();
+            if flags & 0x7 != MODE_SERVER
+                || flags >> 6 == LEAP_UNSYNCHRONIZED
+                || !(1..16).contains(&stratum)
+            {
+                return Err(Error::BadResponse);
+            }
+            return Ok((stratum, received, answered));
+        }
+    })
+    .await;
+    let (stratum, received, answered) = answer.map_err(|_| Error::TimedOut)??;
+    let rtt = started.elapsed();
+
+    let sent = unix_nanos(sent_at);
+    let back = sent + rtt.as_nanos() as i128;
+    let (received, answered) = (from_ntp_timestamp(received), from_ntp_timestamp(answered));
+    let offset = ((received - sent) + (answered - back)) / 2;
+    let delay = (back - sent) - (answered - received);
+    Ok(SntpSample {
+        offset: offset.clamp(i64::MIN.into(), i64::MAX.into()) as i64,
+        delay: Duration::from_nanos(delay.clamp(0, u64::MAX.into()) as u64),
+        stratum,
+    })
+}
+
+/// Keep the wall clock in step with the time server `server`, a name or an
+/// address, spawn it as a task after [`init`](super::init). Runs forever,
+/// asking every [`SNTP_INTERVAL`].
+///
+/// Of the last few answers it goes by the one with the shortest round
+/// trip, the least skewed by queueing on the way. Small offsets are
+/// slewed out with [`time::slew_system_time`], large ones step the clock.
+pub async fn run_sntp(server: impl Into<String>) {
+    let server = server.into();
+    let mut filter = Filter::default();
+    loop {
+        let next = match sync(&server, &mut filter).await {
+            Ok(()) => SNTP_INTERVAL,
+            Err(err) => {
+                FAILURES.inc();
+                log::warn!("sntp: {}: {}", server, err);
+                RETRY_INTERVAL
+            }
+        };
+        timer::sleep(next).await;
+    }
+}
+
+async fn sync(server: &str, filter: &mut Filter) -> Result<()> {
+    let address = *resolve(server).await?.first().ok_or(Error::NotFound)?;
+    let sample = sntp_query(address).await?;
+    DELAY.set(sample.delay.as_micros() as i64);
+    log::debug!(
+        "sntp: {} offset {} us, delay {} us",
+        address,
+        sample.offset / 1_000,
+        sample.delay.as_micros()
+    );
+
+    // Against the clock as it will be once a slew still going is done
+    let offset = sample.offset.saturating_sub(time::pending_slew());
+    filter.push(offset, sample.delay);
+    let (correction, jitter) = filter.best();
+    OFFSET.set(correction / 1_000);
+    JITTER.set(jitter / 1_000);
+    if correction.abs() > STEP_THRESHOLD {
+        let now = SystemTime::now();
+        let nanos = time::pending_slew().saturating_add(correction);
+        let by = Duration::from_nanos(nanos.unsigned_abs());
+        let stepped = if nanos < 0 {
+            now.checked_sub(by).unwrap_or(UNIX_EPOCH)
+        } else {
+            now + by
+        };
+        time::set_system_time(stepped);
+        STEPS.inc();
+        log::info!("sntp: clock set to {}", stepped.rfc3339());
+    } else {
+        time::slew_system_time(correction);
+    }
+    filter.corrected(correction);
+    Ok(())
+}
+
+/// The last few offsets with their round trips
+#[derive(Default)]
+struct Filter {
+    samples: VecDeque<(i64, Duration)>,
+}
+
+impl Filter {
+    fn push(&mut self, offset: i64, delay: Duration) {
+        if self.samples.len() == FILTER_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((offset, delay));
+    }
+
+    /// The offset with the shortest round trip, and the root mean square of
+    /// the others' distance from it
+    fn best(&self) -> (i64, i64) {
+        let Some(&(best, _)) = self.samples.iter().min_by_key(|(_, delay)| *delay) else {
+            return (0, 0);
+        };
+        let squares: i128 = self
+            .samples
+            .iter()
+            .map(|&(offset, _)| (offset as i128 - best as i128).pow(2))
+            .sum();
+        let jitter = (squares as f64 / self.samples.len() as f64).sqrt();
+        (best, jitter as i64)
+    }
+
+    /// The clock was moved by `nanos`, the offsets measured before are off
+    /// by that much now
+    fn corrected(&mut self, nanos: i64) {
+        for (offset, _) in &mut self.samples {
+            *offset = offset.saturating_sub(nanos);
+        }
+    }
+}
+
+fn unix_nanos(time: SystemTime) -> i128 {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    since_epoch.as_nanos() as i128
+}
+
+/// Seconds since 1900 and the fraction in 32 bits each
+fn ntp_timestamp(time: SystemTime) -> u64 {
+    let nanos = unix_nanos(time) + UNIX_OFFSET_SECS * NANOS_PER_SEC;
+    let seconds = (nanos / NANOS_PER_SEC) as u32;
+    let fraction = ((nanos % NANOS_PER_SEC) << 32) / NANOS_PER_SEC;
+    (seconds as u64) << 32 | fraction as u64
+}
+
+/// Nanoseconds since 1970
+fn from_ntp_timestamp(timestamp: u64) -> i128 {
+    let mut seconds = (timestamp >> 32) as i128;
+    // The seconds wrap in 2036, small ones are from after that
+    if seconds < 1 << 31 {
+        seconds += 1 << 32;
+    }
+    let fraction = ((timestamp & 0xFFFF_FFFF) as i128 * NANOS_PER_SEC) >> 32;
+    (seconds - UNIX_OFFSET_SECS) * NANOS_PER_SEC + fraction
+}
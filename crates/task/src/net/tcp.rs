@@ -0,0 +1,418 @@
+use std::{
+    future::poll_fn,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures_util::{Stream, ready};
+
+use smoltcp::{
+    iface::SocketSet,
+    socket::tcp::{self, SocketBuffer, State},
+    wire::{IpEndpoint, IpListenEndpoint},
+};
+
+use super::{Error, Result, SocketHandle, ports::Ports};
+use crate::io::{self, AsyncRead, AsyncWrite};
+
+/// Bytes a connection buffers each way
+const BUFFER_SIZE: usize = 16 * 1024;
+
+static PORTS: spin::Mutex<Ports> = spin::Mutex::new(Ports::new());
+
+// Dropped connections still saying goodbye, with the port to give back
+static CLOSING: spin::Mutex<Vec<(SocketHandle, Option<u16>)>> = spin::Mutex::new(Vec::new());
+
+/// Remove closed connections from `sockets`, called by the driver task
+/// after each poll
+pub(super) fn reap(sockets: &mut SocketSet<'static>) {
+    CLOSING.lock().retain(|&(handle, port)| {
+        if sockets.get::<tcp::Socket>(handle).is_active() {
+            return true;
+        }
+        sockets.remove(handle);
+        if let Some(port) = port {
+            PORTS.lock().release(port);
+        }
+        false
+    });
+}
+
+fn socket(receive_buffer: usize, send_buffer: usize) -> tcp::Socket<'static> {
+    tcp::Socket::new(
+        SocketBuffer::new(vec![0; receive_buffer]),
+        SocketBuffer::new(vec![0; send_buffer]),
+    )
+}
+
+/// A TCP connection, read and written through [`AsyncRead`] and
+/// [`AsyncWrite`].
+///
+/// Dropping it closes the connection in the background, bytes already
+/// written still go out. [`AsyncWriteExt::close`](crate::io::AsyncWriteExt)
+/// waits for that.
+pub struct TcpStream {
+    handle: SocketHandle,
+    // Its own ephemeral port, accepted connections share their listener's
+    port: Option<u16>,
+}
+
+impl TcpStream {
+    /// Connect to `to`. Wrap it in [`timer::timeout`](crate::timer::timeout)
+    /// to give up on hosts that don't answer.
+    pub async fn connect(to: impl Into<IpEndpoint>) -> Result<TcpStream> {
+        let port = PORTS.lock().take(0)?;
+        let stream = match super::add_socket(socket(BUFFER_SIZE, BUFFER_SIZE)) {
+            Ok(handle) => TcpStream {
+                handle,
+                port: Some(port),
+            },
+            Err(err) => {
+                PORTS.lock().release(port);
+                return Err(err);
+            }
+        };
+        let to = to.into();
+        super::with_socket(stream.handle, |socket: &mut tcp::Socket, iface| {
+            socket.connect(iface.context(), to, port)
+        })
+        .map_err(|_| Error::Unaddressable)?;
+        super::wake();
+
+        super::poll_socket(stream.handle, |socket: &mut tcp::Socket, cx| {
+            match socket.state() {
+                State::SynSent | State::SynReceived => {
+                    socket.register_send_waker(cx.waker());
+                    Poll::Pending
+                }
+                State::Established => Poll::Ready(Ok(())),
+                _ => Poll::Ready(Err(Error::ConnectionRefused)),
+            }
+        })
+        .await?;
+        Ok(stream)
+    }
+
+    pub fn local_endpoint(&self) -> Option<IpEndpoint> {
+        super::with_socket(self.handle, |socket: &mut tcp::Socket, _| {
+            socket.local_endpoint()
+        })
+    }
+
+    pub fn peer_endpoint(&self) -> Option<IpEndpoint> {
+        super::with_socket(self.handle, |socket: &mut tcp::Socket, _| {
+            socket.remote_endpoint()
+        })
+    }
+}
+
+impl TcpStream {
+    /// Reading and writing halves, to use from different futures. The
+    /// connection closes once both are dropped.
+    pub fn split(self) -> (TcpReader, TcpWriter) {
+        let stream = Arc::new(self);
+        (TcpReader(stream.clone()), TcpWriter(stream))
+    }
+
+    fn read(&self, cx: &mut Context, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let read = super::with_socket(self.handle, |socket: &mut tcp::Socket, _| {
+            if buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+            match socket.recv_slice(buf) {
+                Ok(0) => {
+                    socket.register_recv_waker(cx.waker());
+                    Poll::Pending
+                }
+                Ok(count) => Poll::Ready(Ok(count)),
+                // The peer is done sending
+                Err(tcp::RecvError::Finished) => Poll::Ready(Ok(0)),
+                Err(tcp::RecvError::InvalidState) => Poll::Ready(Err(io::Error::BrokenPipe)),
+            }
+        });
+        if let Poll::Ready(Ok(1..)) = read {
+            // The receive window opened up, tell the peer
+            super::wake();
+        }
+        read
+    }
+
+    fn write(&self, cx: &mut Context, bytes: &[u8]) -> Poll<io::Result<usize>> {
+        let written = super::with_socket(self.handle, |socket: &mut tcp::Socket, _| {
+            if !socket.may_send() {
+                return Poll::Ready(Err(io::Error::BrokenPipe));
+            }
+            match socket.send_slice(bytes) {
+                Ok(0) if !bytes.is_empty() => {
+                    socket.register_send_waker(cx.waker());
+                    Poll::Pending
+                }
+                Ok(count) => Poll::Ready(Ok(count)),
+                Err(tcp::SendError::InvalidState) => Poll::Ready(Err(io::Error::BrokenPipe)),
+            }
+        });
+        if let Poll::Ready(Ok(1..)) = written {
+            super::wake();
+        }
+        written
+    }
+
+    /// Ready once the peer acknowledged everything written
+    fn flush(&self, cx: &mut Context) -> Poll<io::Result<()>> {
+        super::with_socket(self.handle, |socket: &mut tcp::Socket, _| {
+            if socket.send_queue() == 0 {
+                Poll::Ready(Ok(()))
+            } else if !socket.is_active() {
+                Poll::Ready(Err(io::Error::BrokenPipe))
+            } else {
+                socket.register_send_waker(cx.waker());
+                Poll::Pending
+            }
+        })
+    }
+
+    fn close(&self, cx: &mut Context) -> Poll<io::Result<()>> {
+        super::with_socket(self.handle, |socket: &mut tcp::Socket, _| socket.close());
+        super::wake();
+        self.flush(cx)
+    }
+}
+
+impl AsyncRead for TcpStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TcpStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, bytes: &[u8]) -> Poll<io::Result<usize>> {
+        self.write(cx, bytes)
+    }
+
+    /// Ready once the peer acknowledged everything written
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.close(cx)
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        super::with_socket(self.handle, |socket: &mut tcp::Socket, _| socket.close());
+        CLOSING.lock().push((self.handle, self.port));
+        super::wake();
+    }
+}
+
+/// Receiving half of a [`TcpStream`], see [`TcpStream::split`]
+pub struct TcpReader(Arc<TcpStream>);
+
+impl AsyncRead for TcpReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.0.read(cx, buf)
+    }
+}
+
+impl Stream for TcpReader {
+    type Item = u8;
+
+    /// `None` at the end of the stream and on errors
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<u8>> {
+        let mut byte = [0];
+        self.0
+            .read(cx, &mut byte)
+            .map(|read| matches!(read, Ok(1)).then_some(byte[0]))
+    }
+}
+
+/// Sending half of a [`TcpStream`], see [`TcpStream::split`]
+pub struct TcpWriter(Arc<TcpStream>);
+
+impl AsyncWrite for TcpWriter {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, bytes: &[u8]) -> Poll<io::Result<usize>> {
+        self.0.write(cx, bytes)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.0.flush(cx)
+    }
+
+    /// Closes the sending direction, the peer can still send
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.0.close(cx)
+    }
+}
+
+/// How a [`TcpListener`] listens, see [`TcpListener::bind_with`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListenOptions {
+    /// Connections that can wait to be accepted, at least one. smoltcp has
+    /// no backlog, each needs a listening socket and its buffers.
+    pub backlog: usize,
+    /// Bytes each accepted connection buffers from the peer
+    pub receive_buffer: usize,
+    /// Bytes each accepted connection buffers for the peer
+    pub send_buffer: usize,
+}
+
+impl ListenOptions {
+    pub const DEFAULT: ListenOptions = ListenOptions {
+        backlog: 4,
+        receive_buffer: BUFFER_SIZE,
+        send_buffer: BUFFER_SIZE,
+    };
+}
+
+impl Default for ListenOptions {
+    fn default() -> Self {
+        ListenOptions::DEFAULT
+    }
+}
+
+/// Accepts TCP connections on a port until dropped, one at a time with
+/// [`accept`](TcpListener::accept) or as a stream with
+/// [`incoming`](TcpListener::incoming):
+///
+/// ```ignore
+/// let listener = TcpListener::bind(7)?;
+/// let mut incoming = listener.incoming();
+/// while let Some(accepted) = incoming.next().await {
+///     let (stream, peer) = accepted?;
+///     connections.spawn(echo(stream, peer));
+/// }
+/// ```
+pub struct TcpListener {
+    endpoint: IpListenEndpoint,
+    options: ListenOptions,
+    backlog: spin::Mutex<Vec<SocketHandle>>,
+}
+
+impl TcpListener {
+    /// Listen on `endpoint`, a port or an address and port, with the
+    /// [default](ListenOptions::DEFAULT) backlog and buffers
+    pub fn bind(endpoint: impl Into<IpListenEndpoint>) -> Result<TcpListener> {
+        TcpListener::bind_with(endpoint, ListenOptions::DEFAULT)
+    }
+
+    /// Listen on `endpoint` with a backlog and buffers of its own, larger
+    /// for a busy server or smaller to save memory
+    pub fn bind_with(
+        endpoint: impl Into<IpListenEndpoint>,
+        options: ListenOptions,
+    ) -> Result<TcpListener> {
+        let mut endpoint = endpoint.into();
+        endpoint.port = PORTS.lock().take(endpoint.port)?;
+        // Dropping it on an error gives the port back
+        let listener = TcpListener {
+            endpoint,
+            options,
+            backlog: spin::Mutex::new(Vec::new()),
+        };
+        for _ in 0..options.backlog.max(1) {
+            let handle = listener.listen()?;
+            listener.backlog.lock().push(handle);
+        }
+        Ok(listener)
+    }
+
+    fn listen(&self) -> Result<SocketHandle> {
+        let mut socket = socket(self.options.receive_buffer, self.options.send_buffer);
+        socket
+            .listen(self.endpoint)
+            .map_err(|_| Error::Unaddressable)?;
+        super::add_socket(socket)
+    }
+
+    pub fn local_endpoint(&self) -> IpListenEndpoint {
+        self.endpoint
+    }
+
+    pub fn options(&self) -> ListenOptions {
+        self.options
+    }
+
+    /// Wait for a connection, returns it with the peer's address
+    pub async fn accept(&self) -> Result<(TcpStream, IpEndpoint)> {
+        poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    /// The connections as they come in, a stream that doesn't end. An
+    /// error leaves the connection waiting, the next poll tries again.
+    pub fn incoming(&self) -> Incoming<'_> {
+        Incoming { listener: self }
+    }
+
+    /// [`accept`](TcpListener::accept) for hand-written futures
+    pub fn poll_accept(&self, cx: &mut Context) -> Poll<Result<(TcpStream, IpEndpoint)>> {
+        let (at, peer) = ready!(self.poll_backlog(cx));
+        // Another connection can wait in its place
+        let handle = self.listen()?;
+        let accepted = std::mem::replace(&mut self.backlog.lock()[at], handle);
+        let stream = TcpStream {
+            handle: accepted,
+            port: None,
+        };
+        Poll::Ready(Ok((stream, peer)))
+    }
+
+    /// A connection in the backlog that's done with the handshake. Ones
+    /// reset before that listen again.
+    fn poll_backlog(&self, cx: &mut Context) -> Poll<(usize, IpEndpoint)> {
+        let backlog = self.backlog.lock();
+        for (at, &handle) in backlog.iter().enumerate() {
+            let accepted =
+                super::with_socket(handle, |socket: &mut tcp::Socket, _| match socket.state() {
+                    State::Listen | State::SynReceived => {
+                        socket.register_recv_waker(cx.waker());
+                        None
+                    }
+                    State::Closed => {
+                        let _ = socket.listen(self.endpoint);
+                        socket.register_recv_waker(cx.waker());
+                        None
+                    }
+                    _ => socket.remote_endpoint(),
+                });
+            if let Some(peer) = accepted {
+                return Poll::Ready((at, peer));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for TcpListener {
+    fn drop(&mut self) {
+        for handle in self.backlog.get_mut().drain(..) {
+            super::with_socket(handle, |socket: &mut tcp::Socket, _| socket.abort());
+            CLOSING.lock().push((handle, None));
+        }
+        PORTS.lock().release(self.endpoint.port);
+        super::wake();
+    }
+}
+
+/// Connections accepted by a [`TcpListener`], see [`TcpListener::incoming`]
+pub struct Incoming<'a> {
+    listener: &'a TcpListener,
+}
+
+impl Stream for Incoming<'_> {
+    type Item = Result<(TcpStream, IpEndpoint)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.listener.poll_accept(cx).map(Some)
+    }
+}
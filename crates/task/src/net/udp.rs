@@ -0,0 +1,121 @@
+use std::task::Poll;
+
+use smoltcp::{
+    socket::udp::{self, PacketBuffer, PacketMetadata},
+    wire::{IpEndpoint, IpListenEndpoint},
+};
+
+use super::{Error, Result, SocketHandle, ports::Ports};
+use crate::bytes::Bytes;
+
+/// Datagrams a socket queues each way
+const QUEUED_DATAGRAMS: usize = 16;
+/// Payload bytes a socket queues each way
+const BUFFER_SIZE: usize = 16 * 1024;
+
+static PORTS: spin::Mutex<Ports> = spin::Mutex::new(Ports::new());
+
+/// A UDP socket, bound to a local port until dropped.
+///
+/// ```ignore
+/// let socket = UdpSocket::bind(0)?;
+/// socket.send_to(b"ping", (server, 7)).await?;
+/// let (len, from) = socket.recv_from(&mut buf).await?;
+/// ```
+pub struct UdpSocket {
+    handle: SocketHandle,
+    endpoint: IpListenEndpoint,
+}
+
+impl UdpSocket {
+    /// Bind to `endpoint`, a port or an address and port. Port `0` picks a
+    /// free ephemeral port, [`UdpSocket::local_endpoint`] tells which.
+    pub fn bind(endpoint: impl Into<IpListenEndpoint>) -> Result<UdpSocket> {
+        let mut endpoint = endpoint.into();
+        endpoint.port = PORTS.lock().take(endpoint.port)?;
+
+        let buffer = || {
+            PacketBuffer::new(
+                vec![PacketMetadata::EMPTY; QUEUED_DATAGRAMS],
+                vec![0; BUFFER_SIZE],
+            )
+        };
+        let mut socket = udp::Socket::new(buffer(), buffer());
+        let bound = socket
+            .bind(endpoint)
+            .map_err(|_| Error::Unaddressable)
+            .and_then(|()| super::add_socket(socket));
+        match bound {
+            Ok(handle) => Ok(UdpSocket { handle, endpoint }),
+            Err(err) => {
+                PORTS.lock().release(endpoint.port);
+                Err(err)
+            }
+        }
+    }
+
+    pub fn local_endpoint(&self) -> IpListenEndpoint {
+        self.endpoint
+    }
+
+    /// Queue `buf` as one datagram to `to`, waiting for room in the send
+    /// buffer. Done once queued, the driver task sends it out.
+    pub async fn send_to(&self, buf: &[u8], to: impl Into<IpEndpoint>) -> Result<()> {
+        let to = to.into();
+        super::poll_socket(self.handle, |socket: &mut udp::Socket, cx| {
+            if buf.len() > socket.payload_send_capacity() {
+                return Poll::Ready(Err(Error::TooLarge));
+            }
+            match socket.send_slice(buf, to) {
+                Ok(()) => Poll::Ready(Ok(())),
+                Err(udp::SendError::BufferFull) => {
+                    socket.register_send_waker(cx.waker());
+                    Poll::Pending
+                }
+                Err(udp::SendError::Unaddressable) => Poll::Ready(Err(Error::Unaddressable)),
+            }
+        })
+        .await
+    }
+
+    /// Wait for a datagram, copy it into `buf` and return its length and
+    /// sender. What doesn't fit in `buf` is dropped, as with any UDP socket.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, IpEndpoint)> {
+        super::poll_socket(self.handle, |socket: &mut udp::Socket, cx| {
+            match socket.recv() {
+                Ok((data, meta)) => {
+                    let len = data.len().min(buf.len());
+                    buf[..len].copy_from_slice(&data[..len]);
+                    Poll::Ready(Ok((len, meta.endpoint)))
+                }
+                Err(_) => {
+                    socket.register_recv_waker(cx.waker());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Wait for a datagram and return it whole, in a buffer of its own to
+    /// pass on, with its sender
+    pub async fn recv(&self) -> Result<(Bytes, IpEndpoint)> {
+        super::poll_socket(self.handle, |socket: &mut udp::Socket, cx| {
+            match socket.recv() {
+                Ok((data, meta)) => Poll::Ready(Ok((Bytes::copy_from_slice(data), meta.endpoint))),
+                Err(_) => {
+                    socket.register_recv_waker(cx.waker());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+}
+
+impl Drop for UdpSocket {
+    fn drop(&mut self) {
+        super::remove_socket(self.handle);
+        PORTS.lock().release(self.endpoint.port);
+    }
+}
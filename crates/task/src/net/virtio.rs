@@ -0,0 +1,234 @@
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+};
+
+use smoltcp::{
+    phy::{self, DeviceCapabilities, Medium},
+    time::Instant,
+};
+
+use super::EthernetAddress;
+use crate::{
+    bytes::{Bytes, BytesMut},
+    metrics::Counter,
+    virtio::{self, Buffer, Request, Transport, VirtQueue},
+};
+
+const FEATURE_MAC: u64 = 1 << 5;
+
+const RECEIVE_QUEUE: u16 = 0;
+const TRANSMIT_QUEUE: u16 = 1;
+
+/// `virtio_net_hdr` of legacy devices, in a descriptor of its own before
+/// every frame. All zero: no checksum or segmentation offload.
+const HEADER_LEN: usize = 10;
+/// An ethernet frame without its checksum
+const MAX_FRAME: usize = 1514;
+
+/// Receive buffers kept with the device
+const RECEIVE_BUFFERS: usize = 16;
+
+/// Used when the device doesn't have a MAC address of its own, locally
+/// administered
+const DEFAULT_MAC: EthernetAddress = EthernetAddress([0x02, 0x00, 0x00, 0x12, 0x34, 0x56]);
+
+static TX_DROPPED: Counter = Counter::new(
+    "virtio_net_tx_dropped_total",
+    "Frames dropped because the transmit queue was full",
+);
+
+/// What the interrupt handler needs, shared with [`VirtioNet`]
+struct Shared {
+    transport: spin::Mutex<Box<dyn Transport>>,
+    receive: Arc<VirtQueue>,
+    transmit: Arc<VirtQueue>,
+}
+
+/// A virtio-net card, as QEMU gives with `-device virtio-net`.
+///
+/// It's a smoltcp [`Device`](phy::Device), hand it to
+/// [`net::init`](super::init) and [`net::run`](super::run). Received frames
+/// wake the driver task from the interrupt handler instead of it polling:
+///
+/// ```ignore
+/// let mut card = VirtioNet::new(transport)?;
+/// let interrupt = card.interrupt();
+/// // route the card's IRQ to `interrupt.handle_interrupt()`
+/// net::init(&mut card, net::Config::ethernet(card.mac()))?;
+/// executor.spawn(Task::new(net::run(card)).with_name("net"));
+/// ```
+pub struct VirtioNet {
+    shared: Arc<Shared>,
+    mac: EthernetAddress,
+    // Receive buffers with the device, oldest first
+    receiving: VecDeque<Request>,
+}
+
+impl VirtioNet {
+    /// Start the network card behind `transport`
+    pub fn new(mut transport: impl Transport + 'static) -> Result<Self, virtio::Error> {
+        let features = virtio::negotiate(&mut transport, virtio::DEVICE_NET, FEATURE_MAC)?;
+        let receive = VirtQueue::new(&mut transport, RECEIVE_QUEUE)?;
+        let transmit = VirtQueue::new(&mut transport, TRANSMIT_QUEUE)?;
+        // Sent frames are collected as the next ones go out, see `transmit`
+        transmit.set_interrupts(false);
+        virtio::start(&mut transport);
+
+        let mac = if features & FEATURE_MAC != 0 {
+            let mut mac = [0; 6];
+            transport.read_config(0, &mut mac);
+            EthernetAddress(mac)
+        } else {
+            DEFAULT_MAC
+        };
+        let mut net = VirtioNet {
+            shared: Arc::new(Shared {
+                transport: spin::Mutex::new(Box::new(transport)),
+                receive,
+                transmit,
+            }),
+            mac,
+            receiving: VecDeque::new(),
+        };
+        net.refill();
+        Ok(net)
+    }
+
+    pub fn mac(&self) -> EthernetAddress {
+        self.mac
+    }
+
+    /// Handle for the interrupt handler, the card itself moves into the
+    /// driver task
+    pub fn interrupt(&self) -> VirtioNetInterrupt {
+        VirtioNetInterrupt(self.shared.clone())
+    }
+
+    /// Give the device receive buffers until it has [`RECEIVE_BUFFERS`]
+    fn refill(&mut self) {
+        let mut added = false;
+        while self.receiving.len() < RECEIVE_BUFFERS {
+            let buffers = vec![Buffer::writable(HEADER_LEN), Buffer::writable(MAX_FRAME)];
+            match self.shared.receive.try_submit(buffers) {
+                Ok(request) => self.receiving.push_back(request),
+                Err(_) => break,
+            }
+            added = true;
+        }
+        if added {
+            self.shared
+                .receive
+                .notify(&mut **self.shared.transport.lock());
+        }
+    }
+
+    /// A frame the device received, if any, in the buffer it wrote
+    fn take_frame(&mut self) -> Option<Bytes> {
+        // Nothing to wake, the interrupt handler wakes the driver task
+        let mut cx = Context::from_waker(Waker::noop());
+        let (at, completed) = self
+            .receiving
+            .iter_mut()
+            .enumerate()
+            .find_map(|(at, request)| match Pin::new(request).poll(&mut cx) {
+                Poll::Ready(completed) => Some((at, completed)),
+                Poll::Pending => None,
+            })?;
+        self.receiving.remove(at);
+        self.refill();
+
+        let mut frame = completed.buffers.into_iter().nth(1)?.into_bytes();
+        frame.truncate((completed.written as usize).saturating_sub(HEADER_LEN));
+        Some(frame)
+    }
+}
+
+/// Lets the interrupt handler reach a [`VirtioNet`] owned by the driver
+/// task
+#[derive(Clone)]
+pub struct VirtioNetInterrupt(Arc<Shared>);
+
+impl VirtioNetInterrupt {
+    /// Call from the card's interrupt handler
+    pub fn handle_interrupt(&self) {
+        if self.0.transport.lock().acknowledge_interrupt() {
+            self.0.receive.handle_used();
+            self.0.transmit.handle_used();
+            super::wake();
+        }
+    }
+}
+
+impl phy::Device for VirtioNet {
+    type RxToken<'a> = RxToken;
+    type TxToken<'a> = TxToken<'a>;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(RxToken, TxToken<'_>)> {
+        let frame = self.take_frame()?;
+        Some((RxToken(frame), TxToken(&self.shared)))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<TxToken<'_>> {
+        // Sent frames free their descriptors here rather than with an
+        // interrupt each. Only with the queue full does the driver task
+        // need waking once there's room again.
+        let transmit = &self.shared.transmit;
+        transmit.handle_used();
+        if !transmit.has_room(2) {
+            // Looked at again, in case the room came before the interrupts
+            transmit.set_interrupts(true);
+            transmit.handle_used();
+        }
+        let room = transmit.has_room(2);
+        if room {
+            transmit.set_interrupts(false);
+        }
+        room.then_some(TxToken(&self.shared))
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut capabilities = DeviceCapabilities::default();
+        capabilities.medium = Medium::Ethernet;
+        capabilities.max_transmission_unit = MAX_FRAME;
+        capabilities.max_burst_size = Some(RECEIVE_BUFFERS);
+        capabilities
+    }
+}
+
+pub struct RxToken(Bytes);
+
+impl phy::RxToken for RxToken {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&[u8]) -> R,
+    {
+        f(&self.0)
+    }
+}
+
+pub struct TxToken<'a>(&'a Shared);
+
+impl phy::TxToken for TxToken<'_> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut frame = BytesMut::zeroed(len);
+        let result = f(&mut frame);
+        let buffers = vec![
+            Buffer::readable(vec![0; HEADER_LEN]),
+            Buffer::readable(frame),
+        ];
+        // The queue keeps the buffers until the device is done with them,
+        // nobody waits for that
+        match self.0.transmit.try_submit(buffers) {
+            Ok(_request) => self.0.transmit.notify(&mut **self.0.transport.lock()),
+            Err(_) => TX_DROPPED.inc(),
+        }
+        result
+    }
+}
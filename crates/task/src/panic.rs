@@ -0,0 +1,161 @@
+//!
+//! Panic reports
+//!
+//! A panic message alone doesn't say what the executor was doing. The
+//! report adds the task being polled, how often it was polled and woken,
+//! and the other tasks:
+//!
+//! ```text
+//! panicked at src/shell.rs:40:9:
+//! index out of bounds: the len is 3 but the index is 3
+//! in task 12 "shell", Normal priority, polled 340 times, woken 338 times, 2048 bytes of heap
+//! other tasks:
+//!     ID  PRIORITY      POLLS      WAKES       HEAP  NAME
+//!      1  Normal           40         39        512  logger
+//! ```
+//!
+//! With the [`trace`](crate::trace) on, the report ends with the last
+//! [`PANIC_EVENTS`] scheduler events.
+//!
+//! On the std build [`install_hook`] adds the task part after the usual
+//! message. With `bare-metal` and without std this is the panic handler:
+//! it writes the report to the console, or to COM1 while the console is
+//! held, and halts.
+//!
+
+use alloc::{format, vec::Vec};
+use core::{fmt, panic::PanicInfo};
+
+use crate::{TaskId, context, executor, executor::TaskInfo, trace};
+
+/// Trace events at the end of a report
+pub const PANIC_EVENTS: usize = 32;
+
+/// Write the panic's message and where it happened, then [`write_context`]
+pub fn write_report(out: &mut dyn fmt::Write, info: &PanicInfo) -> fmt::Result {
+    match info.location() {
+        Some(location) => writeln!(out, "panicked at {}:", location)?,
+        None => writeln!(out, "panicked:")?,
+    }
+    writeln!(out, "{}", info.message())?;
+    write_context(out)
+}
+
+/// Write the task being polled, the state of the others and the recent
+/// trace. Tasks are left out if the panic hit while their registry was
+/// locked.
+pub fn write_context(out: &mut dyn fmt::Write) -> fmt::Result {
+    let tasks = executor::try_tasks();
+    let current = context::current();
+    match current {
+        Some((id, priority)) => {
+            let info = tasks.iter().flatten().find(|task| task.id == id);
+            write!(out, "in task {}", id)?;
+            if let Some(name) = info.and_then(|task| task.name.as_deref()) {
+                write!(out, " {:?}", name)?;
+            }
+            write!(out, ", {:?} priority", priority)?;
+            if let Some(info) = info {
+                write!(
+                    out,
+                    ", polled {} times, woken {} times",
+                    info.polls, info.wakes
+                )?;
+                if let Some(memory) = info.memory {
+                    write!(out, ", {} bytes of heap", memory.in_use())?;
+                }
+            }
+            writeln!(out)?;
+        }
+        None => writeln!(out, "outside any task")?,
+    }
+
+    match tasks {
+        Some(tasks) => write_tasks(out, tasks, current.map(|(id, _)| id))?,
+        None => writeln!(out, "task list locked, not shown")?,
+    }
+    if trace::is_enabled() {
+        writeln!(out, "recent events:")?;
+        trace::write_recent(out, PANIC_EVENTS)?;
+    }
+    Ok(())
+}
+
+fn write_tasks(
+    out: &mut dyn fmt::Write,
+    tasks: Vec<TaskInfo>,
+    current: Option<TaskId>,
+) -> fmt::Result {
+    writeln!(out, "other tasks:")?;
+    writeln!(
+        out,
+        "{:>6}  {:<8} {:>10} {:>10} {:>10}  NAME",
+        "ID", "PRIORITY", "POLLS", "WAKES", "HEAP"
+    )?;
+    for task in tasks {
+        if Some(task.id) == current {
+            continue;
+        }
+        let priority = format!("{:?}", task.priority);
+        let heap = task
+            .memory
+            .map_or("-".into(), |memory| format!("{}", memory.in_use()));
+        writeln!(
+            out,
+            "{:>6}  {:<8} {:>10} {:>10} {:>10}  {}",
+            task.id.as_u64(),
+            priority,
+            task.polls,
+            task.wakes,
+            heap,
+            task.name.as_deref().unwrap_or("-")
+        )?;
+    }
+    Ok(())
+}
+
+/// Follow the default panic message on stderr with [`write_context`]
+#[cfg(feature = "std")]
+pub fn install_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(alloc::boxed::Box::new(move |info| {
+        previous(info);
+        let mut context = alloc::string::String::new();
+        let _ = write_context(&mut context);
+        eprint!("{}", context);
+    }));
+}
+
+#[cfg(all(not(feature = "std"), feature = "bare-metal", target_arch = "x86_64"))]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::{console, gdb::Connection, pc};
+
+    /// COM1, polled, for when the console is held
+    struct Com1(pc::ComPort);
+
+    impl fmt::Write for Com1 {
+        fn write_str(&mut self, text: &str) -> fmt::Result {
+            for byte in text.bytes() {
+                self.0.write_byte(byte);
+            }
+            Ok(())
+        }
+    }
+
+    // A panic while reporting one only halts
+    static PANICKING: AtomicBool = AtomicBool::new(false);
+
+    x86_64::instructions::interrupts::disable();
+    if !PANICKING.swap(true, Ordering::Relaxed) {
+        let _ = match console::try_lock() {
+            Some(mut console) => write_report(&mut console, info),
+            None => write_report(&mut Com1(pc::ComPort::new(pc::COM1)), info),
+        };
+    }
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
@@ -0,0 +1,211 @@
+//!
+//! PC platform
+//!
+//! What a kernel on x86_64 needs besides the executor: interrupt handlers,
+//! the 8259 PICs, the PIT as the clock and the i8042 controller's ports.
+//! Built with the `bare-metal` feature. A kernel entered by its bootloader
+//! with a GDT in place and a global allocator for its heap starts like:
+//!
+//! ```ignore
+//! fn kernel_main() -> ! {
+//!     pc::init();
+//!     i8042::install(pc::I8042Ports);
+//!     let _ = i8042::initialize();
+//!     let mut executor = Executor::new();
+//!     executor.spawn(Task::new(keyboard::print_keypresses()));
+//!     if let Err(err) = executor.run() {
+//!         panic!("{}", err);
+//!     }
+//!     // Stopped by a shutdown, nothing left to run
+//!     loop {
+//!         pc::halt_unless(|| false);
+//!     }
+//! }
+//! ```
+//!
+//! [`PcSpeaker`] plays the [`speaker`](crate::speaker)'s tones on PIT
+//! channel 2. A [`ComPort`] is a 16550 UART, for the
+//! [`serial`](crate::serial) driver or, polled, the [`gdb`](crate::gdb)
+//! stub.
+//!
+//! Every IRQ is passed on to [`irq::notify`](crate::irq::notify). Scancodes
+//! from IRQ 1 also go to the [`IrqSource`](crate::keyboard::IrqSource),
+//! IRQ 12 feeds the [`mouse`](crate::mouse). The timer interrupt only counts
+//! ticks, [`Executor::run`](crate::executor::Executor::run) wakes up from
+//! its [`idle`](crate::idle) wait on each one and fires the expired timers
+//! itself. It also runs the
+//! [`watchdog`](crate::watchdog) check, and stops for gdb if it sent
+//! Ctrl+C. Breakpoints and single steps go to the [`gdb`](crate::gdb)
+//! stub.
+//!
+
+mod interrupts;
+mod pic;
+mod pit;
+mod rtc;
+mod uart;
+
+use core::{
+    arch::{asm, x86_64::__cpuid},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use x86_64::{
+    VirtAddr,
+    instructions::{interrupts as cpu, port::Port, tables::lidt},
+    structures::DescriptorTablePointer,
+};
+
+use crate::{i8042::Ports, speaker::Speaker, time};
+
+pub use pit::{TICK_HZ, ticks, uptime};
+pub use rtc::read_rtc;
+pub use uart::{COM1, COM2, ComPort};
+
+/// Install the interrupt handlers, start the tick, set the wall clock from
+/// the RTC and enable interrupts. Call once, early.
+pub fn init() {
+    interrupts::load();
+    // SAFETY: the handlers for every vector the PICs raise are installed
+    unsafe { pic::init() };
+    pit::init();
+    MWAIT.store(__cpuid(1).ecx & CPUID_MONITOR != 0, Ordering::Relaxed);
+    #[cfg(not(feature = "std"))]
+    time::set_clock(pit::uptime);
+    if let Some(now) = read_rtc() {
+        time::set_system_time(now.into());
+    }
+    cpu::enable();
+}
+
+/// Halt until the next interrupt, unless `busy` says there's work. The
+/// check runs with interrupts off, so a wake-up can't slip in between it
+/// and the `hlt`.
+pub fn halt_unless(busy: impl FnOnce() -> bool) {
+    cpu::disable();
+    if busy() {
+        cpu::enable();
+    } else {
+        cpu::enable_and_hlt();
+    }
+}
+
+// The CPU has `monitor` and `mwait`
+static MWAIT: AtomicBool = AtomicBool::new(false);
+/// In CPUID leaf 1's ECX
+const CPUID_MONITOR: u32 = 1 << 3;
+/// C2, the CPU picks what it has if it doesn't have that
+const MWAIT_C2: u32 = 0x10;
+
+/// Sleep until an IRQ on one of `wake_lines` or a write to `monitor`,
+/// unless `busy` says there's work. The other lines are masked meanwhile,
+/// their IRQs come in once it's over. Without `mwait` a write to `monitor`
+/// is only seen at the next IRQ.
+pub(crate) fn sleep_unless(wake_lines: u16, monitor: &AtomicBool, busy: impl FnOnce() -> bool) {
+    cpu::disable();
+    let mask = pic::mask();
+    pic::set_mask(pic::wake_only(wake_lines));
+    if MWAIT.load(Ordering::Relaxed) {
+        // SAFETY: only arms the monitor on an address we hold a reference to
+        unsafe {
+            asm!("monitor", in("rax") monitor.as_ptr(), in("ecx") 0, in("edx") 0,
+                 options(nostack, preserves_flags));
+        }
+    }
+    if busy() {
+        cpu::enable();
+    } else if MWAIT.load(Ordering::Relaxed) {
+        // SAFETY: `sti` holds interrupts off for one more instruction, so
+        // one can't slip in before the `mwait` and go unnoticed
+        unsafe { asm!("sti", "mwait", in("eax") MWAIT_C2, in("ecx") 0, options(nostack)) };
+    } else {
+        cpu::enable_and_hlt();
+    }
+    cpu::without_interrupts(|| pic::set_mask(mask));
+}
+
+/// Reset the machine: pulse the reset line through the i8042 controller,
+/// or failing that triple fault
+pub fn reset() -> ! {
+    const PULSE_RESET: u8 = 0xfe;
+    cpu::disable();
+    // SAFETY: nothing is coming back from either
+    unsafe {
+        Port::new(I8042_STATUS).write(PULSE_RESET);
+        lidt(&DescriptorTablePointer {
+            limit: 0,
+            base: VirtAddr::zero(),
+        });
+    }
+    cpu::int3();
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+/// Turn the machine off through the ACPI sleep control port QEMU, Bochs
+/// or VirtualBox have, without reading the ACPI tables, or failing that
+/// QEMU's `isa-debug-exit` device. Halts if neither is there.
+pub fn power_off() -> ! {
+    // PM1a control ports and the value that puts each into S5
+    const ACPI_SLEEP: [(u16, u16); 3] = [(0x604, 0x2000), (0xb004, 0x2000), (0x4004, 0x3400)];
+    const ISA_DEBUG_EXIT: u16 = 0xf4;
+    cpu::disable();
+    // SAFETY: nothing is coming back, and where there's no such device the
+    // writes go nowhere
+    unsafe {
+        for (port, sleep) in ACPI_SLEEP {
+            Port::new(port).write(sleep);
+        }
+        Port::new(ISA_DEBUG_EXIT).write(0u32);
+    }
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+/// Unmask IRQ `line` while an [`IrqStream`](crate::irq::IrqStream) wants
+/// it, lines with a handler here are always on
+pub(crate) fn set_irq_wanted(line: u8, wanted: bool) {
+    pic::set_wanted(line, wanted);
+}
+
+/// The i8042 controller's I/O ports, for [`i8042::install`](crate::i8042::install)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct I8042Ports;
+
+const I8042_DATA: u16 = 0x60;
+const I8042_STATUS: u16 = 0x64;
+
+// SAFETY: for all four, the i8042 ports only talk to the controller
+impl Ports for I8042Ports {
+    fn read_status(&mut self) -> u8 {
+        unsafe { Port::new(I8042_STATUS).read() }
+    }
+
+    fn read_data(&mut self) -> u8 {
+        unsafe { Port::new(I8042_DATA).read() }
+    }
+
+    fn write_data(&mut self, byte: u8) {
+        unsafe { Port::new(I8042_DATA).write(byte) }
+    }
+
+    fn write_command(&mut self, byte: u8) {
+        unsafe { Port::new(I8042_STATUS).write(byte) }
+    }
+}
+
+/// The speaker on PIT channel 2, for [`speaker::install`](crate::speaker::install)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PcSpeaker;
+
+impl Speaker for PcSpeaker {
+    fn start(&mut self, frequency: u32) {
+        pit::start_tone(frequency);
+    }
+
+    fn stop(&mut self) {
+        pit::stop_tone();
+    }
+}
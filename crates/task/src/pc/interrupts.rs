@@ -0,0 +1,266 @@
+use core::arch::naked_asm;
+
+use x86_64::{VirtAddr, registers::control::Cr2, structures::idt::InterruptDescriptorTable};
+
+use super::{
+    I8042Ports,
+    pic::{self, IRQ_KEYBOARD, IRQ_MOUSE, IRQ_TIMER, vector},
+    pit,
+};
+use crate::{gdb, i8042::Ports, irq, keyboard::IrqSource, mouse, profiler, watchdog};
+
+// Without the unstable "x86-interrupt" ABI the entry points are written out:
+// save what a C function may clobber, call the handler, `iretq`. The CPU
+// aligns the stack before pushing its 5 words, the 9 pushed here make the
+// call aligned again.
+macro_rules! irq_entry {
+    ($name:ident => $handler:path) => {
+        #[unsafe(naked)]
+        extern "C" fn $name() {
+            naked_asm!(
+                "push rax",
+                "push rcx",
+                "push rdx",
+                "push rsi",
+                "push rdi",
+                "push r8",
+                "push r9",
+                "push r10",
+                "push r11",
+                "cld",
+                "call {handler}",
+                "pop r11",
+                "pop r10",
+                "pop r9",
+                "pop r8",
+                "pop rdi",
+                "pop rsi",
+                "pop rdx",
+                "pop rcx",
+                "pop rax",
+                "iretq",
+                handler = sym $handler,
+            )
+        }
+    };
+}
+
+// Faults don't return, so nothing is saved. With an error code the CPU
+// pushed one more word, which goes to `fault` instead of zero.
+macro_rules! fault_entry {
+    ($name:ident => $vector:expr) => {
+        #[unsafe(naked)]
+        extern "C" fn $name() {
+            naked_asm!(
+                "mov edi, {vector}",
+                "xor esi, esi",
+                "mov rdx, rsp",
+                "and rsp, -16",
+                "call {fault}",
+                "ud2",
+                vector = const $vector,
+                fault = sym fault,
+            )
+        }
+    };
+    ($name:ident => $vector:expr, error code) => {
+        #[unsafe(naked)]
+        extern "C" fn $name() {
+            naked_asm!(
+                "mov edi, {vector}",
+                "mov rsi, [rsp]",
+                "lea rdx, [rsp + 8]",
+                "and rsp, -16",
+                "call {fault}",
+                "ud2",
+                vector = const $vector,
+                fault = sym fault,
+            )
+        }
+    };
+}
+
+// Breakpoints and single steps resume where they stopped, so unlike an
+// IRQ everything is saved: the stub shows and changes the registers.
+// Fifteen words and the CPU's five keep the call aligned.
+macro_rules! trap_entry {
+    ($name:ident => $vector:expr) => {
+        #[unsafe(naked)]
+        extern "C" fn $name() {
+            naked_asm!(
+                "push r15",
+                "push r14",
+                "push r13",
+                "push r12",
+                "push r11",
+                "push r10",
+                "push r9",
+                "push r8",
+                "push rbp",
+                "push rdi",
+                "push rsi",
+                "push rdx",
+                "push rcx",
+                "push rbx",
+                "push rax",
+                "cld",
+                "mov rdi, rsp",
+                "mov esi, {vector}",
+                "call {trap}",
+                "pop rax",
+                "pop rbx",
+                "pop rcx",
+                "pop rdx",
+                "pop rsi",
+                "pop rdi",
+                "pop rbp",
+                "pop r8",
+                "pop r9",
+                "pop r10",
+                "pop r11",
+                "pop r12",
+                "pop r13",
+                "pop r14",
+                "pop r15",
+                "iretq",
+                vector = const $vector,
+                trap = sym gdb::trap,
+            )
+        }
+    };
+}
+
+const DIVIDE_ERROR: u8 = 0;
+const DEBUG: u8 = 1;
+const BREAKPOINT: u8 = 3;
+const INVALID_OPCODE: u8 = 6;
+const DOUBLE_FAULT: u8 = 8;
+const GENERAL_PROTECTION: u8 = 13;
+const PAGE_FAULT: u8 = 14;
+
+fault_entry!(divide_error_entry => DIVIDE_ERROR);
+fault_entry!(invalid_opcode_entry => INVALID_OPCODE);
+fault_entry!(double_fault_entry => DOUBLE_FAULT, error code);
+fault_entry!(general_protection_entry => GENERAL_PROTECTION, error code);
+fault_entry!(page_fault_entry => PAGE_FAULT, error code);
+
+trap_entry!(debug_entry => DEBUG);
+trap_entry!(breakpoint_entry => BREAKPOINT);
+
+irq_entry!(timer_entry => timer_irq);
+irq_entry!(keyboard_entry => keyboard_irq);
+irq_entry!(mouse_entry => mouse_irq);
+irq_entry!(line_3_entry => line_irq::<3>);
+irq_entry!(line_4_entry => line_irq::<4>);
+irq_entry!(line_5_entry => line_irq::<5>);
+irq_entry!(line_6_entry => line_irq::<6>);
+irq_entry!(line_7_entry => line_irq::<7>);
+irq_entry!(line_8_entry => line_irq::<8>);
+irq_entry!(line_9_entry => line_irq::<9>);
+irq_entry!(line_10_entry => line_irq::<10>);
+irq_entry!(line_11_entry => line_irq::<11>);
+irq_entry!(line_13_entry => line_irq::<13>);
+irq_entry!(line_14_entry => line_irq::<14>);
+irq_entry!(line_15_entry => line_irq::<15>);
+
+static IDT: spin::Once<InterruptDescriptorTable> = spin::Once::new();
+
+pub(super) fn load() {
+    IDT.call_once(|| {
+        let mut idt = InterruptDescriptorTable::new();
+        let entries: [(u8, extern "C" fn()); 22] = [
+            (DIVIDE_ERROR, divide_error_entry),
+            (DEBUG, debug_entry),
+            (BREAKPOINT, breakpoint_entry),
+            (INVALID_OPCODE, invalid_opcode_entry),
+            (DOUBLE_FAULT, double_fault_entry),
+            (GENERAL_PROTECTION, general_protection_entry),
+            (PAGE_FAULT, page_fault_entry),
+            (vector(IRQ_TIMER), timer_entry),
+            (vector(IRQ_KEYBOARD), keyboard_entry),
+            (vector(IRQ_MOUSE), mouse_entry),
+            (vector(3), line_3_entry),
+            (vector(4), line_4_entry),
+            (vector(5), line_5_entry),
+            (vector(6), line_6_entry),
+            (vector(7), line_7_entry),
+            (vector(8), line_8_entry),
+            (vector(9), line_9_entry),
+            (vector(10), line_10_entry),
+            (vector(11), line_11_entry),
+            (vector(13), line_13_entry),
+            (vector(14), line_14_entry),
+            (vector(15), line_15_entry),
+        ];
+        for (number, entry) in entries {
+            let address = VirtAddr::new(entry as usize as u64);
+            // SAFETY: the entry points above follow the interrupt calling
+            // convention, faults, traps and IRQs alike
+            unsafe { idt[number].set_handler_addr(address) };
+        }
+        idt
+    })
+    .load();
+}
+
+/// The words the CPU pushed, from where it was interrupted
+#[repr(C)]
+#[derive(Debug)]
+struct InterruptFrame {
+    instruction_pointer: u64,
+    code_segment: u64,
+    flags: u64,
+    stack_pointer: u64,
+    stack_segment: u64,
+}
+
+extern "C" fn fault(vector: u64, error_code: u64, frame: &InterruptFrame) -> ! {
+    let name = match vector as u8 {
+        DIVIDE_ERROR => "divide error",
+        INVALID_OPCODE => "invalid opcode",
+        // No separate stack for it, so a kernel stack overflow resets
+        // the machine before getting here
+        DOUBLE_FAULT => "double fault",
+        GENERAL_PROTECTION => "general protection fault",
+        PAGE_FAULT => {
+            panic!(
+                "page fault at {:#x}, error code {:#x}\n{:#x?}",
+                Cr2::read_raw(),
+                error_code,
+                frame
+            )
+        }
+        _ => "exception",
+    };
+    panic!("{}, error code {:#x}\n{:#x?}", name, error_code, frame)
+}
+
+extern "C" fn timer_irq() {
+    pit::tick();
+    watchdog::check_and_handle();
+    profiler::sample();
+    irq::notify(IRQ_TIMER);
+    pic::end_of_interrupt(IRQ_TIMER);
+    gdb::poll_interrupt();
+}
+
+extern "C" fn keyboard_irq() {
+    IrqSource::handle_interrupt(I8042Ports.read_data());
+    irq::notify(IRQ_KEYBOARD);
+    pic::end_of_interrupt(IRQ_KEYBOARD);
+}
+
+extern "C" fn mouse_irq() {
+    mouse::add_mouse_byte(I8042Ports.read_data());
+    irq::notify(IRQ_MOUSE);
+    pic::end_of_interrupt(IRQ_MOUSE);
+}
+
+/// Lines without a driver here, only [`irq::notify`]
+extern "C" fn line_irq<const LINE: u8>() {
+    if pic::is_spurious(LINE) {
+        return;
+    }
+    irq::notify(LINE);
+    pic::end_of_interrupt(LINE);
+}
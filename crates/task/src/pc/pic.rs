@@ -0,0 +1,148 @@
+use x86_64::instructions::{interrupts::without_interrupts, port::Port};
+
+/// Where IRQ 0 to 7 land in the IDT, past the CPU's exceptions
+const MASTER_OFFSET: u8 = 32;
+/// IRQ 8 to 15
+const SLAVE_OFFSET: u8 = MASTER_OFFSET + 8;
+
+pub(super) const IRQ_TIMER: u8 = 0;
+pub(super) const IRQ_KEYBOARD: u8 = 1;
+const IRQ_CASCADE: u8 = 2;
+pub(super) const IRQ_MOUSE: u8 = 12;
+
+const MASTER_COMMAND: u16 = 0x20;
+const MASTER_DATA: u16 = 0x21;
+const SLAVE_COMMAND: u16 = 0xa0;
+const SLAVE_DATA: u16 = 0xa1;
+
+const ICW1_INIT: u8 = 0x11;
+const ICW4_8086: u8 = 0x01;
+const END_OF_INTERRUPT: u8 = 0x20;
+/// The next read of the command port gives the in-service register
+const READ_IN_SERVICE: u8 = 0x0b;
+
+/// Unmasked, the rest stay off
+const ENABLED: u16 = 1 << IRQ_TIMER | 1 << IRQ_KEYBOARD | 1 << IRQ_CASCADE | 1 << IRQ_MOUSE;
+
+/// Move the IRQs off the exception vectors and unmask [`ENABLED`].
+///
+/// # Safety
+///
+/// The IDT must have handlers for all 16 IRQs at their [`vector`].
+pub(super) unsafe fn init() {
+    let steps = [
+        (MASTER_COMMAND, ICW1_INIT),
+        (SLAVE_COMMAND, ICW1_INIT),
+        (MASTER_DATA, MASTER_OFFSET),
+        (SLAVE_DATA, SLAVE_OFFSET),
+        // The slave hangs off the master's IRQ 2
+        (MASTER_DATA, 1 << IRQ_CASCADE),
+        (SLAVE_DATA, IRQ_CASCADE),
+        (MASTER_DATA, ICW4_8086),
+        (SLAVE_DATA, ICW4_8086),
+        (MASTER_DATA, !ENABLED as u8),
+        (SLAVE_DATA, !(ENABLED >> 8) as u8),
+    ];
+    for (port, byte) in steps {
+        // SAFETY: the remap sequence, old PICs need a moment between writes
+        unsafe {
+            Port::<u8>::new(port).write(byte);
+            wait();
+        }
+    }
+}
+
+/// The IDT vector `irq` is raised at
+pub(super) const fn vector(irq: u8) -> u8 {
+    MASTER_OFFSET + irq
+}
+
+/// Unmask `irq`, or mask it again. The IRQs in [`ENABLED`] stay on.
+pub(super) fn set_wanted(irq: u8, wanted: bool) {
+    if ENABLED & 1 << irq != 0 {
+        return;
+    }
+    let (port, bit) = if irq < 8 {
+        (MASTER_DATA, irq)
+    } else {
+        (SLAVE_DATA, irq - 8)
+    };
+    let mut port = Port::<u8>::new(port);
+    // SAFETY: only this bit of the mask changes, with no interrupt
+    // handler in between
+    without_interrupts(|| unsafe {
+        let mask = port.read();
+        port.write(if wanted {
+            mask & !(1 << bit)
+        } else {
+            mask | 1 << bit
+        });
+    });
+}
+
+/// Both PICs' masks, the slave's in the high byte, a set bit masks a line
+pub(super) fn mask() -> u16 {
+    // SAFETY: reading the masks changes nothing
+    unsafe {
+        u16::from_le_bytes([
+            Port::<u8>::new(MASTER_DATA).read(),
+            Port::<u8>::new(SLAVE_DATA).read(),
+        ])
+    }
+}
+
+/// Set both PICs' masks, as read by [`mask`]
+pub(super) fn set_mask(mask: u16) {
+    let [master, slave] = mask.to_le_bytes();
+    // SAFETY: masks lines, whose IRQs the PICs keep until unmasked
+    unsafe {
+        Port::<u8>::new(MASTER_DATA).write(master);
+        Port::<u8>::new(SLAVE_DATA).write(slave);
+    }
+}
+
+/// The mask with only `lines` on, and the cascade if any are the slave's
+pub(super) fn wake_only(lines: u16) -> u16 {
+    let cascade = if lines >> 8 != 0 { 1 << IRQ_CASCADE } else { 0 };
+    !(lines | cascade)
+}
+
+/// Tell the PICs `irq` is handled, so it can come again
+pub(super) fn end_of_interrupt(irq: u8) {
+    // SAFETY: only acknowledges, the PICs were set up by `init`
+    unsafe {
+        if irq >= 8 {
+            Port::<u8>::new(SLAVE_COMMAND).write(END_OF_INTERRUPT);
+        }
+        Port::<u8>::new(MASTER_COMMAND).write(END_OF_INTERRUPT);
+    }
+}
+
+/// Whether `irq` is a phantom: the lowest priority line of a PIC firing
+/// for a request that went away before it was acknowledged. It must not
+/// be acknowledged, but the master did see the slave's.
+pub(super) fn is_spurious(irq: u8) -> bool {
+    let (command, bit) = match irq {
+        7 => (MASTER_COMMAND, 7),
+        15 => (SLAVE_COMMAND, 7),
+        _ => return false,
+    };
+    let mut port = Port::<u8>::new(command);
+    // SAFETY: reading the in-service register changes nothing
+    let in_service = unsafe {
+        port.write(READ_IN_SERVICE);
+        port.read()
+    };
+    if in_service & 1 << bit != 0 {
+        return false;
+    }
+    if irq == 15 {
+        end_of_interrupt(IRQ_CASCADE);
+    }
+    true
+}
+
+/// A write to the POST port takes about a microsecond
+unsafe fn wait() {
+    unsafe { Port::<u8>::new(0x80).write(0) }
+}
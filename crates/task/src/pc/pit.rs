@@ -0,0 +1,84 @@
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use x86_64::instructions::port::Port;
+
+use crate::rng;
+
+/// Timer interrupts per second, more or less, see [`DIVISOR`]
+pub const TICK_HZ: u32 = 1000;
+
+/// The PIT's input clock
+const BASE_HZ: u64 = 1_193_182;
+/// Whole input cycles per tick, the actual rate is a little off [`TICK_HZ`]
+const DIVISOR: u16 = (BASE_HZ / TICK_HZ as u64) as u16;
+
+const CHANNEL_0: u16 = 0x40;
+const CHANNEL_2: u16 = 0x42;
+const MODE_COMMAND: u16 = 0x43;
+/// Channel 0, low byte then high byte, rate generator
+const RATE_GENERATOR: u8 = 0b0011_0100;
+/// Channel 2, low byte then high byte, square wave
+const SQUARE_WAVE: u8 = 0b1011_0110;
+
+/// Bit 0 gates channel 2, bit 1 connects it to the speaker
+const SPEAKER_CONTROL: u16 = 0x61;
+const SPEAKER_ON: u8 = 0b11;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+pub(super) fn init() {
+    let [low, high] = DIVISOR.to_le_bytes();
+    // SAFETY: programs channel 0, whose IRQ 0 only counts ticks
+    unsafe {
+        Port::<u8>::new(MODE_COMMAND).write(RATE_GENERATOR);
+        let mut channel = Port::<u8>::new(CHANNEL_0);
+        channel.write(low);
+        channel.write(high);
+    }
+}
+
+/// From the timer interrupt
+pub(super) fn tick() {
+    let tick = TICKS.fetch_add(1, Ordering::Relaxed);
+    rng::add_timer_tick(tick);
+}
+
+/// Timer interrupts since [`init`](super::init)
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Time since [`init`](super::init) by the tick count, the clock for
+/// [`time`](crate::time) without std
+pub fn uptime() -> Duration {
+    let cycles = ticks() as u128 * DIVISOR as u128;
+    Duration::from_nanos((cycles * 1_000_000_000 / BASE_HZ as u128) as u64)
+}
+
+/// Sound the speaker at `frequency` Hz, from channel 2
+pub(super) fn start_tone(frequency: u32) {
+    let divisor = (BASE_HZ / frequency.max(1) as u64).clamp(1, u16::MAX as u64) as u16;
+    let [low, high] = divisor.to_le_bytes();
+    // SAFETY: channel 2 only drives the speaker
+    unsafe {
+        Port::<u8>::new(MODE_COMMAND).write(SQUARE_WAVE);
+        let mut channel = Port::<u8>::new(CHANNEL_2);
+        channel.write(low);
+        channel.write(high);
+        let mut control = Port::<u8>::new(SPEAKER_CONTROL);
+        let value = control.read();
+        control.write(value | SPEAKER_ON);
+    }
+}
+
+pub(super) fn stop_tone() {
+    // SAFETY: as above, the other bits are left alone
+    unsafe {
+        let mut control = Port::<u8>::new(SPEAKER_CONTROL);
+        let value = control.read();
+        control.write(value & !SPEAKER_ON);
+    }
+}
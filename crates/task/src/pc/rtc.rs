@@ -0,0 +1,84 @@
+use x86_64::instructions::port::Port;
+
+use crate::time::DateTime;
+
+const CMOS_ADDRESS: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+
+const SECONDS: u8 = 0x00;
+const MINUTES: u8 = 0x02;
+const HOURS: u8 = 0x04;
+const DAY: u8 = 0x07;
+const MONTH: u8 = 0x08;
+const YEAR: u8 = 0x09;
+const STATUS_A: u8 = 0x0a;
+const STATUS_B: u8 = 0x0b;
+
+/// In status A, the registers are about to change
+const UPDATE_IN_PROGRESS: u8 = 1 << 7;
+/// In status B, otherwise the hour runs 1 to 12 with [`PM`]
+const HOURS_24: u8 = 1 << 1;
+/// In status B, otherwise the registers are BCD
+const BINARY: u8 = 1 << 2;
+/// In the hour register on a 12-hour clock
+const PM: u8 = 1 << 7;
+
+/// A port read takes about a microsecond
+const MAX_UPDATE_POLLS: usize = 100_000;
+
+/// The date and time the CMOS clock keeps, `None` if it doesn't make
+/// sense. The RTC is assumed to run on UTC in the 21st century, the
+/// century register isn't always there.
+pub fn read_rtc() -> Option<DateTime> {
+    // Until two reads agree, so an update in between isn't seen half done
+    let mut registers = read_registers()?;
+    loop {
+        let again = read_registers()?;
+        if again == registers {
+            break;
+        }
+        registers = again;
+    }
+    let [second, minute, hour, day, month, year] = registers;
+
+    let status = read(STATUS_B);
+    let decode = |value: u8| {
+        if status & BINARY != 0 {
+            value
+        } else {
+            (value >> 4) * 10 + (value & 0x0f)
+        }
+    };
+    let mut hour_of_day = decode(hour & !PM);
+    if status & HOURS_24 == 0 {
+        hour_of_day = hour_of_day % 12 + if hour & PM != 0 { 12 } else { 0 };
+    }
+    let date_time = DateTime {
+        year: 2000 + decode(year) as u16,
+        month: decode(month),
+        day: decode(day),
+        hour: hour_of_day,
+        minute: decode(minute),
+        second: decode(second),
+    };
+    date_time.is_valid().then_some(date_time)
+}
+
+/// `None` if an update takes much longer than the 2 ms it should, there's
+/// probably no RTC
+fn read_registers() -> Option<[u8; 6]> {
+    for _ in 0..MAX_UPDATE_POLLS {
+        if read(STATUS_A) & UPDATE_IN_PROGRESS == 0 {
+            return Some([SECONDS, MINUTES, HOURS, DAY, MONTH, YEAR].map(read));
+        }
+    }
+    None
+}
+
+fn read(register: u8) -> u8 {
+    // SAFETY: selects and reads a CMOS register, nothing else uses them
+    unsafe {
+        Port::<u8>::new(CMOS_ADDRESS).write(register);
+        Port::<u8>::new(CMOS_DATA).read()
+    }
+}
@@ -0,0 +1,103 @@
+use x86_64::instructions::port::Port;
+
+use crate::gdb::Connection;
+
+/// I/O base of the first serial port, on IRQ 4
+pub const COM1: u16 = 0x3f8;
+/// I/O base of the second serial port, on IRQ 3
+pub const COM2: u16 = 0x2f8;
+
+const DATA: u16 = 0;
+const INTERRUPT_ENABLE: u16 = 1;
+const FIFO_CONTROL: u16 = 2;
+const LINE_CONTROL: u16 = 3;
+const MODEM_CONTROL: u16 = 4;
+const LINE_STATUS: u16 = 5;
+
+const LCR_8N1: u8 = 0x03;
+const LCR_DIVISOR_LATCH: u8 = 0x80;
+/// Enable and clear both FIFOs
+const FCR_ENABLE: u8 = 0xc7;
+/// DTR and RTS, OUT2 stays off so the IRQ line does too
+const MCR_POLLED: u8 = 0x03;
+const LSR_DATA_READY: u8 = 1 << 0;
+const LSR_TRANSMIT_EMPTY: u8 = 1 << 5;
+
+const BASE_CLOCK: u32 = 115_200;
+
+/// A 16550 UART at one of the legacy I/O bases, [`COM1`] or [`COM2`]
+///
+/// With the `std` feature it's the [`Registers`](crate::serial::Registers)
+/// of an interrupt driven [`Serial`](crate::serial::Serial). After
+/// [`init_polled`](ComPort::init_polled) it's polled instead, which is how
+/// the [`gdb`](crate::gdb) stub uses it, with interrupts off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComPort {
+    base: u16,
+}
+
+impl ComPort {
+    pub const fn new(base: u16) -> ComPort {
+        ComPort { base }
+    }
+
+    /// Set up 8N1 at `baud` with the UART's interrupts off, `None` if the
+    /// divisor doesn't come out even
+    pub fn init_polled(base: u16, baud: u32) -> Option<ComPort> {
+        if baud == 0 || !BASE_CLOCK.is_multiple_of(baud) || BASE_CLOCK / baud > u32::from(u16::MAX)
+        {
+            return None;
+        }
+        let [low, high] = ((BASE_CLOCK / baud) as u16).to_le_bytes();
+        let port = ComPort::new(base);
+        port.write_register(INTERRUPT_ENABLE, 0);
+        port.write_register(LINE_CONTROL, LCR_DIVISOR_LATCH);
+        port.write_register(DATA, low);
+        port.write_register(INTERRUPT_ENABLE, high);
+        port.write_register(LINE_CONTROL, LCR_8N1);
+        port.write_register(FIFO_CONTROL, FCR_ENABLE);
+        port.write_register(MODEM_CONTROL, MCR_POLLED);
+        Some(port)
+    }
+
+    fn read_register(&self, register: u16) -> u8 {
+        // SAFETY: the UART's ports only talk to the UART
+        unsafe { Port::new(self.base + register).read() }
+    }
+
+    fn write_register(&self, register: u16, value: u8) {
+        // SAFETY: as above
+        unsafe { Port::new(self.base + register).write(value) }
+    }
+}
+
+impl Connection for ComPort {
+    fn read_byte(&mut self) -> u8 {
+        while self.read_register(LINE_STATUS) & LSR_DATA_READY == 0 {
+            core::hint::spin_loop();
+        }
+        self.read_register(DATA)
+    }
+
+    fn try_read_byte(&mut self) -> Option<u8> {
+        (self.read_register(LINE_STATUS) & LSR_DATA_READY != 0).then(|| self.read_register(DATA))
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        while self.read_register(LINE_STATUS) & LSR_TRANSMIT_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+        self.write_register(DATA, byte);
+    }
+}
+
+#[cfg(feature = "std")]
+impl crate::serial::Registers for ComPort {
+    fn read(&mut self, register: u8) -> u8 {
+        self.read_register(register.into())
+    }
+
+    fn write(&mut self, register: u8, value: u8) {
+        self.write_register(register.into(), value);
+    }
+}
@@ -0,0 +1,163 @@
+//!
+//! Pipes
+//!
+//! [`pipe`] connects two tasks with a bounded byte buffer: whatever goes
+//! into the [`PipeWriter`] comes out of the [`PipeReader`] in order. A
+//! writer that gets ahead waits until the reader has caught up, so a fast
+//! producer can't pile up memory behind a slow consumer.
+//!
+//! Dropping or closing the writer ends the reader's input once the buffer
+//! is drained. Dropping the reader makes further writes fail with
+//! [`Error::BrokenPipe`].
+//!
+//! ```ignore
+//! let (mut reader, mut writer) = pipe(DEFAULT_CAPACITY);
+//! executor.spawn(Task::new(async move {
+//!     let _ = writer.write_all(b"hello\n").await;
+//! }));
+//! let mut buf = [0; 64];
+//! let count = reader.read(&mut buf).await?;
+//! ```
+//!
+
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, Waker},
+};
+
+use futures_util::Stream;
+
+use crate::io::{AsyncRead, AsyncWrite, Error, Result};
+
+/// Bytes buffered by [`pipe`] users without a better idea, a page
+pub const DEFAULT_CAPACITY: usize = 4096;
+
+/// A pipe buffering up to `capacity` bytes
+pub fn pipe(capacity: usize) -> (PipeReader, PipeWriter) {
+    assert!(capacity > 0, "a pipe needs room for at least one byte");
+    let shared = Arc::new(spin::Mutex::new(State {
+        buffer: VecDeque::new(),
+        capacity,
+        reader: None,
+        writer: None,
+        reader_closed: false,
+        writer_closed: false,
+    }));
+    (
+        PipeReader {
+            shared: shared.clone(),
+        },
+        PipeWriter { shared },
+    )
+}
+
+struct State {
+    buffer: VecDeque<u8>,
+    capacity: usize,
+    // Each end parks at most one waker, the ends aren't cloneable
+    reader: Option<Waker>,
+    writer: Option<Waker>,
+    reader_closed: bool,
+    writer_closed: bool,
+}
+
+impl State {
+    fn close_writer(&mut self) {
+        self.writer_closed = true;
+        if let Some(reader) = self.reader.take() {
+            reader.wake();
+        }
+    }
+}
+
+/// Reading end of a [`pipe`]
+pub struct PipeReader {
+    shared: Arc<spin::Mutex<State>>,
+}
+
+impl AsyncRead for PipeReader {
+    /// `Ok(0)` once the writer is gone and everything was read
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context, buf: &mut [u8]) -> Poll<Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let mut state = self.shared.lock();
+        if state.buffer.is_empty() {
+            if state.writer_closed {
+                return Poll::Ready(Ok(0));
+            }
+            state.reader = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let count = buf.len().min(state.buffer.len());
+        for (slot, byte) in buf.iter_mut().zip(state.buffer.drain(..count)) {
+            *slot = byte;
+        }
+        // There's room again
+        if let Some(writer) = state.writer.take() {
+            writer.wake();
+        }
+        Poll::Ready(Ok(count))
+    }
+}
+
+impl Stream for PipeReader {
+    type Item = u8;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<u8>> {
+        let mut byte = [0];
+        AsyncRead::poll_read(self, cx, &mut byte).map(|count| (count == Ok(1)).then_some(byte[0]))
+    }
+}
+
+impl Drop for PipeReader {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.reader_closed = true;
+        state.buffer.clear();
+        if let Some(writer) = state.writer.take() {
+            writer.wake();
+        }
+    }
+}
+
+/// Writing end of a [`pipe`]
+pub struct PipeWriter {
+    shared: Arc<spin::Mutex<State>>,
+}
+
+impl AsyncWrite for PipeWriter {
+    /// Buffer as many bytes as there's room for, waiting for the reader
+    /// while the buffer is full
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, bytes: &[u8]) -> Poll<Result<usize>> {
+        let mut state = self.shared.lock();
+        if state.reader_closed || state.writer_closed {
+            return Poll::Ready(Err(Error::BrokenPipe));
+        }
+        let room = state.capacity - state.buffer.len();
+        if room == 0 {
+            state.writer = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let count = bytes.len().min(room);
+        state.buffer.extend(&bytes[..count]);
+        if let Some(reader) = state.reader.take() {
+            reader.wake();
+        }
+        Poll::Ready(Ok(count))
+    }
+
+    /// Ends the reader's input like dropping the writer does
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<()>> {
+        self.shared.lock().close_writer();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for PipeWriter {
+    fn drop(&mut self) {
+        self.shared.lock().close_writer();
+    }
+}
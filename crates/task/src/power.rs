@@ -0,0 +1,88 @@
+//!
+//! Turning the machine off and restarting it
+//!
+//! Both go through a [`shutdown`](crate::shutdown::shutdown) first, so
+//! subscribed tasks are told and tracked ones get to finish, then the
+//! machine goes off or restarts once the executors are stopped:
+//!
+//! ```ignore
+//! power::reboot().await;
+//! ```
+//!
+//! On a PC built with `bare-metal` that's the ACPI sleep port of QEMU,
+//! Bochs or VirtualBox, else QEMU's `isa-debug-exit` device, and
+//! [`pc::reset`](crate::pc::reset). Hosted, the process exits, with
+//! [`REBOOT_EXIT_CODE`] for a reboot so a wrapper script can start it
+//! again. Anywhere else the executors just stop, as after a plain
+//! shutdown.
+//!
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::shutdown as graceful;
+
+/// What the process exits with when rebooting hosted
+pub const REBOOT_EXIT_CODE: i32 = 3;
+
+const NOTHING: u8 = 0;
+const POWER_OFF: u8 = 1;
+const REBOOT: u8 = 2;
+
+// What to do once the shutdown under way is done
+static ACTION: AtomicU8 = AtomicU8::new(NOTHING);
+
+/// Shut down and turn the machine off. If a shutdown is under way already
+/// it powers off at its end.
+pub async fn shutdown() {
+    ACTION.store(POWER_OFF, Ordering::Release);
+    graceful::shutdown().await;
+}
+
+/// Shut down and restart the machine. If a shutdown is under way already
+/// it restarts at its end.
+pub async fn reboot() {
+    ACTION.store(REBOOT, Ordering::Release);
+    graceful::shutdown().await;
+}
+
+/// Called at the end of a shutdown, with the executors stopped. Returns
+/// only if nothing was asked for or there's no way to do it here.
+pub(crate) fn finish() {
+    match ACTION.load(Ordering::Acquire) {
+        POWER_OFF => power_off(),
+        REBOOT => restart(),
+        _ => {}
+    }
+}
+
+#[cfg(all(feature = "bare-metal", target_arch = "x86_64"))]
+fn power_off() {
+    crate::pc::power_off();
+}
+
+#[cfg(all(feature = "bare-metal", target_arch = "x86_64"))]
+fn restart() {
+    crate::pc::reset();
+}
+
+#[cfg(all(
+    feature = "std",
+    not(all(feature = "bare-metal", target_arch = "x86_64"))
+))]
+fn power_off() {
+    std::process::exit(0);
+}
+
+#[cfg(all(
+    feature = "std",
+    not(all(feature = "bare-metal", target_arch = "x86_64"))
+))]
+fn restart() {
+    std::process::exit(REBOOT_EXIT_CODE);
+}
+
+#[cfg(not(any(feature = "std", all(feature = "bare-metal", target_arch = "x86_64"))))]
+fn power_off() {}
+
+#[cfg(not(any(feature = "std", all(feature = "bare-metal", target_arch = "x86_64"))))]
+fn restart() {}
@@ -0,0 +1,272 @@
+//!
+//! Task priorities
+//!
+//! [`Priority::RealTime`] is a class of its own for latency-critical
+//! drivers, an audio tick or input: ready real-time tasks are always
+//! polled before any other. To keep a busy one from locking everything
+//! else out, [`set_rt_bandwidth`] caps the time they get per period:
+//!
+//! ```ignore
+//! // At most 950 ms in every second
+//! priority::set_rt_bandwidth(Some(RtBandwidth::new(
+//!     Duration::from_millis(950),
+//!     Duration::from_secs(1),
+//! )));
+//! ```
+//!
+//! Once the cap is reached, real-time tasks run as `Low` for the rest of
+//! the period.
+//!
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    vec::Vec,
+};
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+#[cfg(feature = "keyboard")]
+use crate::context;
+use crate::{TaskId, metrics::Counter, time};
+
+/// Scheduling priority of a task.
+///
+/// The executor always polls the ready task with the highest effective
+/// priority first, tasks of equal priority run in FIFO order. Tasks woken by
+/// keyboard or mouse input run as `High` for one poll, so echoing a key
+/// isn't held up by background work.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    /// Above everything else, within the [`RtBandwidth`] if one is set
+    RealTime,
+}
+
+static RT_THROTTLED: Counter = Counter::new(
+    "executor_rt_throttled_total",
+    "Periods in which real-time tasks used up their bandwidth",
+);
+
+/// Time real-time tasks may be polled in each period
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RtBandwidth {
+    pub runtime: Duration,
+    pub period: Duration,
+}
+
+impl RtBandwidth {
+    pub const fn new(runtime: Duration, period: Duration) -> Self {
+        RtBandwidth { runtime, period }
+    }
+}
+
+struct RtUsage {
+    bandwidth: Option<RtBandwidth>,
+    // Uptime the current period began at
+    period_start: Duration,
+    // Time real-time polls took in it
+    used: Duration,
+}
+
+static RT_USAGE: spin::Mutex<RtUsage> = spin::Mutex::new(RtUsage {
+    bandwidth: None,
+    period_start: Duration::ZERO,
+    used: Duration::ZERO,
+});
+
+impl RtUsage {
+    /// Start a new period if the current one is over
+    fn roll_over(&mut self, bandwidth: RtBandwidth) {
+        let now = time::uptime();
+        if now.saturating_sub(self.period_start) >= bandwidth.period {
+            self.period_start = now;
+            self.used = Duration::ZERO;
+        }
+    }
+}
+
+/// Cap the time real-time tasks get, `None` lets them run unchecked, which
+/// is the default
+pub fn set_rt_bandwidth(bandwidth: Option<RtBandwidth>) {
+    let mut usage = RT_USAGE.lock();
+    usage.bandwidth = bandwidth;
+    usage.period_start = time::uptime();
+    usage.used = Duration::ZERO;
+}
+
+pub fn rt_bandwidth() -> Option<RtBandwidth> {
+    RT_USAGE.lock().bandwidth
+}
+
+/// Real-time tasks used up the current period's runtime
+pub fn is_rt_throttled() -> bool {
+    let mut usage = RT_USAGE.lock();
+    let Some(bandwidth) = usage.bandwidth else {
+        return false;
+    };
+    usage.roll_over(bandwidth);
+    usage.used >= bandwidth.runtime
+}
+
+/// A real-time poll took `elapsed`, called by the executor
+pub(crate) fn charge_rt(elapsed: Duration) {
+    let mut usage = RT_USAGE.lock();
+    let Some(bandwidth) = usage.bandwidth else {
+        return;
+    };
+    usage.roll_over(bandwidth);
+    let before = usage.used;
+    usage.used += elapsed;
+    if before < bandwidth.runtime && usage.used >= bandwidth.runtime {
+        RT_THROTTLED.inc();
+    }
+}
+
+/// Priorities lent to tasks by the resources they hold, keyed by resource
+/// address. Used for priority inheritance in `sync::Mutex`.
+static INHERITED: spin::Mutex<BTreeMap<TaskId, Vec<(usize, Priority)>>> =
+    spin::Mutex::new(BTreeMap::new());
+
+/// Lend `priority` to `task` for as long as it holds `resource`
+pub(crate) fn inherit(task: TaskId, resource: usize, priority: Priority) {
+    let mut inherited = INHERITED.lock();
+    let lent = inherited.entry(task).or_default();
+    match lent.iter_mut().find(|(r, _)| *r == resource) {
+        Some((_, p)) => *p = priority,
+        None => lent.push((resource, priority)),
+    }
+}
+
+/// Give back the priority lent for `resource`
+pub(crate) fn release(task: TaskId, resource: usize) {
+    let mut inherited = INHERITED.lock();
+    if let Some(lent) = inherited.get_mut(&task) {
+        lent.retain(|(r, _)| *r != resource);
+        if lent.is_empty() {
+            inherited.remove(&task);
+        }
+    }
+}
+
+/// Bumped by interrupt handlers as input arrives, which only touch this
+static INPUT_EPOCH: AtomicU64 = AtomicU64::new(0);
+
+static BOOSTS: spin::Mutex<Boosts> = spin::Mutex::new(Boosts {
+    waiters: BTreeMap::new(),
+    boosted: BTreeSet::new(),
+    seen: 0,
+});
+
+struct Boosts {
+    /// Tasks parked on an input stream, with the epoch they parked in
+    waiters: BTreeMap<TaskId, u64>,
+    /// Tasks woken by input that haven't been polled yet
+    boosted: BTreeSet<TaskId>,
+    /// The epoch the waiters were last looked at in
+    seen: u64,
+}
+
+impl Boosts {
+    /// Boost the waiters that input arrived for since they parked
+    fn catch_up(&mut self) {
+        let epoch = INPUT_EPOCH.load(Ordering::Acquire);
+        if epoch == self.seen {
+            return;
+        }
+        self.seen = epoch;
+        let Boosts {
+            waiters, boosted, ..
+        } = self;
+        waiters.retain(|task, parked| {
+            let woken = *parked < epoch;
+            if woken {
+                boosted.insert(*task);
+            }
+            !woken
+        });
+    }
+}
+
+/// Called by input streams about to return `Pending`, from the waiting task
+#[cfg(feature = "keyboard")]
+pub(crate) fn waiting_for_input() {
+    if let Some((task, _)) = context::current() {
+        let mut boosts = BOOSTS.lock();
+        boosts.catch_up();
+        boosts
+            .waiters
+            .insert(task, INPUT_EPOCH.load(Ordering::Acquire));
+    }
+}
+
+/// Boost every task waiting for input, called next to waking them. Safe in
+/// an interrupt handler, the executor sorts out who that is.
+#[cfg(feature = "keyboard")]
+pub(crate) fn input_arrived() {
+    INPUT_EPOCH.fetch_add(1, Ordering::Release);
+}
+
+/// The executor polled `task`, its boost is used up
+pub(crate) fn end_boost(task: TaskId) {
+    BOOSTS.lock().boosted.remove(&task);
+}
+
+/// `task` finished, drop anything kept for it
+pub(crate) fn forget(task: TaskId) {
+    let mut boosts = BOOSTS.lock();
+    boosts.waiters.remove(&task);
+    boosts.boosted.remove(&task);
+}
+
+/// Base priority raised by anything the task currently inherits or an input
+/// boost, real-time ones lowered while throttled
+pub(crate) fn effective(task: TaskId, base: Priority) -> Priority {
+    lookup().effective(task, base)
+}
+
+/// Effective priorities of many tasks, taking the locks once rather than
+/// for each
+pub(crate) fn lookup() -> Lookup {
+    let throttled = is_rt_throttled();
+    let mut boosts = BOOSTS.lock();
+    boosts.catch_up();
+    Lookup {
+        boosts,
+        inherited: INHERITED.lock(),
+        throttled,
+    }
+}
+
+/// The boosts and inherited priorities as [`lookup`] found them, holding
+/// their locks
+pub(crate) struct Lookup {
+    boosts: spin::MutexGuard<'static, Boosts>,
+    inherited: spin::MutexGuard<'static, BTreeMap<TaskId, Vec<(usize, Priority)>>>,
+    throttled: bool,
+}
+
+impl Lookup {
+    /// Like [`effective`]
+    pub(crate) fn effective(&self, task: TaskId, base: Priority) -> Priority {
+        let base = if self.boosts.boosted.contains(&task) {
+            base.max(Priority::High)
+        } else {
+            base
+        };
+        let priority = self
+            .inherited
+            .get(&task)
+            .and_then(|lent| lent.iter().map(|(_, p)| *p).max())
+            .map_or(base, |inherited| inherited.max(base));
+        if priority == Priority::RealTime && self.throttled {
+            Priority::Low
+        } else {
+            priority
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//!
+//! Sampling profiler
+//!
+//! While it runs, every timer tick looks at which task is being polled and
+//! counts a sample for it, or for the executor when it's between polls or
+//! idle. Over a window the counts say where the CPU time went:
+//!
+//! ```ignore
+//! let profile = profiler::profile(Duration::from_secs(5)).await;
+//! console::print!("{}", profile).await;
+//! ```
+//!
+//! With the `bare-metal` feature the timer interrupt takes the samples,
+//! [`pc::TICK_HZ`](crate::pc::TICK_HZ) of them a second. The std build
+//! runs a host thread that samples every [`SAMPLE_PERIOD`] while the
+//! profiler is on. Either way sampling doesn't lock or allocate.
+//!
+//! Samples one executor, like the [`watchdog`](crate::watchdog), polls on
+//! executors in other threads overlap.
+//!
+
+use alloc::{string::String, vec::Vec};
+#[cfg(feature = "timers")]
+use core::time::Duration;
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+#[cfg(feature = "timers")]
+use crate::timer;
+use crate::{TaskId, executor};
+
+/// Tasks counted apart, samples for tasks beyond that are added up
+pub const PROFILED_TASKS: usize = 64;
+
+/// Time between samples of the std build's sampler thread
+#[cfg(feature = "std")]
+pub const SAMPLE_PERIOD: Duration = Duration::from_millis(1);
+
+struct Slot {
+    // Id plus one, `0` while free
+    task: AtomicU64,
+    samples: AtomicU64,
+}
+
+static SLOTS: [Slot; PROFILED_TASKS] = [const {
+    Slot {
+        task: AtomicU64::new(0),
+        samples: AtomicU64::new(0),
+    }
+}; PROFILED_TASKS];
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+// Bumped by `start`, so a sampler thread left from before stops
+#[cfg(feature = "std")]
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+// Id plus one of the task being polled, `0` between polls
+static POLLING: AtomicU64 = AtomicU64::new(0);
+// Samples outside any poll
+static EXECUTOR: AtomicU64 = AtomicU64::new(0);
+// Samples of tasks that found every slot taken
+static OTHER: AtomicU64 = AtomicU64::new(0);
+
+/// The executor is about to poll `task`
+pub(crate) fn poll_started(task: TaskId) {
+    POLLING.store(task.as_u64() + 1, Ordering::Relaxed);
+}
+
+pub(crate) fn poll_ended() {
+    POLLING.store(0, Ordering::Relaxed);
+}
+
+/// Count one sample, on every timer tick or from the sampling thread
+#[cfg(any(feature = "std", all(feature = "bare-metal", target_arch = "x86_64")))]
+pub(crate) fn sample() {
+    if !RUNNING.load(Ordering::Relaxed) {
+        return;
+    }
+    let key = POLLING.load(Ordering::Relaxed);
+    if key == 0 {
+        EXECUTOR.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    // Slots are only freed by `start`, so a task's comes before the first
+    // free one
+    for slot in &SLOTS {
+        let claimed = match slot
+            .task
+            .compare_exchange(0, key, Ordering::Relaxed, Ordering::Relaxed)
+        {
+            Ok(_) => true,
+            Err(task) => task == key,
+        };
+        if claimed {
+            slot.samples.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+    }
+    OTHER.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Clear the counts and start sampling, `false` if already running
+pub fn start() -> bool {
+    if RUNNING.load(Ordering::Acquire) {
+        return false;
+    }
+    for slot in &SLOTS {
+        slot.task.store(0, Ordering::Relaxed);
+        slot.samples.store(0, Ordering::Relaxed);
+    }
+    EXECUTOR.store(0, Ordering::Relaxed);
+    OTHER.store(0, Ordering::Relaxed);
+    if RUNNING.swap(true, Ordering::AcqRel) {
+        return false;
+    }
+    #[cfg(feature = "std")]
+    {
+        let generation = GENERATION.fetch_add(1, Ordering::AcqRel) + 1;
+        std::thread::Builder::new()
+            .name("profiler".into())
+            .spawn(move || {
+                while RUNNING.load(Ordering::Acquire)
+                    && GENERATION.load(Ordering::Acquire) == generation
+                {
+                    sample();
+                    std::thread::sleep(SAMPLE_PERIOD);
+                }
+            })
+            .expect("spawning the profiler thread");
+    }
+    true
+}
+
+/// Stop sampling and return what was counted since [`start`]
+pub fn stop() -> Profile {
+    RUNNING.store(false, Ordering::Release);
+    let names: Vec<(TaskId, Option<String>)> = executor::tasks()
+        .into_iter()
+        .map(|task| (task.id, task.name))
+        .collect();
+    let mut tasks: Vec<TaskSamples> = SLOTS
+        .iter()
+        .filter_map(|slot| {
+            let id = TaskId(slot.task.load(Ordering::Relaxed).checked_sub(1)?);
+            let name = names
+                .iter()
+                .find(|(task, _)| *task == id)
+                .and_then(|(_, name)| name.clone());
+            Some(TaskSamples {
+                id,
+                name,
+                samples: slot.samples.load(Ordering::Relaxed),
+            })
+        })
+        .collect();
+    tasks.sort_by(|a, b| b.samples.cmp(&a.samples).then(a.id.cmp(&b.id)));
+    Profile {
+        tasks,
+        executor: EXECUTOR.load(Ordering::Relaxed),
+        other: OTHER.load(Ordering::Relaxed),
+    }
+}
+
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::Acquire)
+}
+
+/// Sample for `window`, `None` if the profiler is already running
+#[cfg(feature = "timers")]
+pub async fn profile(window: Duration) -> Option<Profile> {
+    if !start() {
+        return None;
+    }
+    timer::sleep(window).await;
+    Some(stop())
+}
+
+/// Samples counted for one task
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskSamples {
+    pub id: TaskId,
+    /// `None` if it has none or has finished since
+    pub name: Option<String>,
+    pub samples: u64,
+}
+
+/// What the profiler counted, tasks with the most samples first
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Profile {
+    pub tasks: Vec<TaskSamples>,
+    /// Samples outside any poll: the executor itself, or idle
+    pub executor: u64,
+    /// Samples of tasks beyond [`PROFILED_TASKS`]
+    pub other: u64,
+}
+
+impl Profile {
+    pub fn total(&self) -> u64 {
+        self.tasks.iter().map(|task| task.samples).sum::<u64>() + self.executor + self.other
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let total = self.total().max(1);
+        let percent = |samples: u64| samples as f64 * 100.0 / total as f64;
+        writeln!(f, "{:>6} {:>10} {:>7}  NAME", "ID", "SAMPLES", "%")?;
+        for task in &self.tasks {
+            writeln!(
+                f,
+                "{:>6} {:>10} {:>6.1}%  {}",
+                task.id.as_u64(),
+                task.samples,
+                percent(task.samples),
+                task.name.as_deref().unwrap_or("-")
+            )?;
+        }
+        if self.other > 0 {
+            writeln!(
+                f,
+                "{:>6} {:>10} {:>6.1}%  (other tasks)",
+                "-",
+                self.other,
+                percent(self.other)
+            )?;
+        }
+        writeln!(
+            f,
+            "{:>6} {:>10} {:>6.1}%  (executor, idle)",
+            "-",
+            self.executor,
+            percent(self.executor)
+        )
+    }
+}
@@ -0,0 +1,231 @@
+//!
+//! Random numbers
+//!
+//! A ChaCha20 generator seeded from the hardware: the host's generator on
+//! the std build, `RDSEED` or `RDRAND` on x86_64 without it. It's seeded
+//! the first time it's used, [`run_reseeder`] mixes in a fresh seed every
+//! [`RESEED_INTERVAL`]:
+//!
+//! ```ignore
+//! executor.spawn(Task::new(rng::run_reseeder()).with_name("rng"));
+//! let mut id = [0; 2];
+//! rng::fill_bytes(&mut id).await;
+//! ```
+//!
+//! The timing of keyboard, mouse and timer interrupts goes into an entropy
+//! pool as well, mixed in with the hardware's seeds whenever it has enough.
+//! It's what seeds the generator when there's no hardware source, until
+//! then [`fill_bytes`] waits, as it does for a seed from [`add_seed`].
+//!
+//! [`SystemRng`] is the generator as a [`rand_core::RngCore`], for crates
+//! that take one.
+//!
+
+mod chacha;
+mod entropy;
+mod hardware;
+
+use core::{fmt, time::Duration};
+
+use rand_core::{CryptoRng, RngCore};
+
+use crate::{log, metrics::Counter, sync::Notify};
+#[cfg(feature = "timers")]
+use crate::{time::Instant, timer};
+
+#[cfg(all(feature = "bare-metal", target_arch = "x86_64"))]
+pub(crate) use entropy::add_timer_tick;
+pub use entropy::{add_interrupt_timing, entropy_estimate};
+pub use hardware::Source;
+
+pub const RESEED_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often [`run_reseeder`] looks whether the entropy pool is full
+#[cfg(feature = "timers")]
+const POOL_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Bytes taken from the source at a time, a ChaCha20 key
+const SEED_LEN: usize = 32;
+
+static RESEEDS: Counter = Counter::new("rng_reseeds_total", "Seeds mixed into the generator");
+
+struct Generator {
+    key: [u32; 8],
+    counter: u64,
+}
+
+impl Generator {
+    fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(64) {
+            let block = chacha::block(&self.key, self.counter);
+            self.counter += 1;
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+        // Whatever was handed out can't be worked out from the state after
+        self.rekey();
+    }
+
+    fn rekey(&mut self) {
+        let block = chacha::block(&self.key, self.counter);
+        for (word, bytes) in self.key.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_le_bytes(bytes.try_into().expect("4 bytes"));
+        }
+        self.counter = 0;
+    }
+
+    fn mix(&mut self, seed: &[u8]) {
+        for chunk in seed.chunks(SEED_LEN) {
+            for (word, bytes) in self.key.iter_mut().zip(chunk.chunks(4)) {
+                let mut padded = [0; 4];
+                padded[..bytes.len()].copy_from_slice(bytes);
+                *word ^= u32::from_le_bytes(padded);
+            }
+            self.rekey();
+        }
+    }
+}
+
+// `None` until the first seed
+static GENERATOR: spin::Mutex<Option<Generator>> = spin::Mutex::new(None);
+static SEEDED: Notify = Notify::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RngError {
+    /// No source and no [`add_seed`] yet
+    NotSeeded,
+}
+
+impl fmt::Display for RngError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RngError::NotSeeded => f.write_str("random number generator not seeded"),
+        }
+    }
+}
+
+/// Where the seeds come from, `None` if there's nowhere
+pub fn source() -> Option<Source> {
+    static SOURCE: spin::Once<Option<Source>> = spin::Once::new();
+    *SOURCE.call_once(hardware::detect)
+}
+
+pub fn is_seeded() -> bool {
+    GENERATOR.lock().is_some()
+}
+
+/// Mix `seed` into the generator, entropy gathered some other way. The
+/// first one seeds it.
+pub fn add_seed(seed: &[u8]) {
+    GENERATOR
+        .lock()
+        .get_or_insert(Generator {
+            key: [0; 8],
+            counter: 0,
+        })
+        .mix(seed);
+    RESEEDS.inc();
+    // The permit covers a task about to wait
+    SEEDED.notify_waiters();
+    SEEDED.notify_one();
+}
+
+/// Mix in a seed from [`source`] along with the entropy pool if it's full,
+/// `false` if there's neither to be had
+pub fn reseed() -> bool {
+    let pool = entropy::take();
+    let mut seed = [0; SEED_LEN];
+    let hardware = match source() {
+        Some(source) if hardware::read(source, &mut seed) => true,
+        Some(source) => {
+            log::warn!("rng: {} gave no seed", source);
+            false
+        }
+        None => false,
+    };
+    match (hardware, pool) {
+        (true, Some(pool)) => {
+            add_seed(&seed);
+            add_seed(&pool);
+        }
+        (true, None) => add_seed(&seed),
+        (false, Some(pool)) => add_seed(&pool),
+        (false, None) => return false,
+    }
+    true
+}
+
+/// Fill `buf` with random bytes, waiting for a seed if there's none yet
+pub async fn fill_bytes(buf: &mut [u8]) {
+    while try_fill_bytes(buf).is_err() {
+        SEEDED.notified().await;
+    }
+}
+
+/// [`fill_bytes`] without waiting
+pub fn try_fill_bytes(buf: &mut [u8]) -> Result<(), RngError> {
+    if !is_seeded() {
+        reseed();
+    }
+    GENERATOR
+        .lock()
+        .as_mut()
+        .ok_or(RngError::NotSeeded)?
+        .fill(buf);
+    Ok(())
+}
+
+pub fn try_next_u64() -> Result<u64, RngError> {
+    let mut bytes = [0; 8];
+    try_fill_bytes(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Reseed from [`source`] every [`RESEED_INTERVAL`] and from the entropy
+/// pool whenever it fills up, for good
+#[cfg(feature = "timers")]
+pub async fn run_reseeder() {
+    match source() {
+        Some(source) => log::info!("rng: seeding from {} and interrupt timing", source),
+        None => log::warn!("rng: no entropy source, seeding from interrupt timing alone"),
+    }
+    reseed();
+    let mut last_reseed = Instant::now();
+    let mut interval = timer::interval(POOL_CHECK_INTERVAL);
+    loop {
+        let now = interval.tick().await;
+        if now - last_reseed >= RESEED_INTERVAL {
+            reseed();
+            last_reseed = now;
+        } else if let Some(pool) = entropy::take() {
+            add_seed(&pool);
+        }
+    }
+}
+
+/// The generator for crates taking a [`RngCore`]. Filling bytes doesn't
+/// wait for a seed, it panics if there's none to be had: await
+/// [`fill_bytes`] once before handing this out where that could happen.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemRng;
+
+impl RngCore for SystemRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dst: &mut [u8]) {
+        if let Err(err) = try_fill_bytes(dst) {
+            panic!("SystemRng: {}", err);
+        }
+    }
+}
+
+impl CryptoRng for SystemRng {}
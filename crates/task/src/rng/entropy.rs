@@ -0,0 +1,70 @@
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+
+/// Bits credited before the pool is worth a seed
+pub(super) const SEED_BITS: u32 = 256;
+
+/// The pool doesn't count past this, it only holds so much
+const MAX_BITS: u32 = 512;
+
+/// Timer ticks come like clockwork, only the cycle counter drifts against
+/// them. One bit is credited for this many.
+#[cfg(all(feature = "bare-metal", target_arch = "x86_64"))]
+const TIMER_TICKS_PER_BIT: u64 = 64;
+
+const WORDS: usize = 8;
+
+static POOL: [AtomicU64; WORDS] = [const { AtomicU64::new(0) }; WORDS];
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+static BITS: AtomicU32 = AtomicU32::new(0);
+
+/// The finest clock there is, its low bits are what jitters
+#[cfg(target_arch = "x86_64")]
+fn timestamp() -> u64 {
+    // SAFETY: the time stamp counter is there on every x86_64
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn timestamp() -> u64 {
+    crate::time::uptime().as_nanos() as u64
+}
+
+/// Mix the moment of an interrupt and `data` it came with into the pool,
+/// crediting `bits` of entropy. Lock free, so it's fine in a handler.
+pub fn add_interrupt_timing(data: u64, bits: u32) {
+    let index = NEXT.fetch_add(1, Ordering::Relaxed);
+    let sample = (timestamp() ^ data.rotate_left(32)).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    // Spread consecutive samples over different bits of the words
+    POOL[index % WORDS].fetch_xor(sample.rotate_left(index as u32 * 7), Ordering::Relaxed);
+    if bits > 0 {
+        let _ = BITS.fetch_update(Ordering::Release, Ordering::Relaxed, |credit| {
+            Some(credit.saturating_add(bits).min(MAX_BITS))
+        });
+    }
+}
+
+/// A timer interrupt, `tick` being its count
+#[cfg(all(feature = "bare-metal", target_arch = "x86_64"))]
+pub(crate) fn add_timer_tick(tick: u64) {
+    let bits = u32::from(tick.is_multiple_of(TIMER_TICKS_PER_BIT));
+    add_interrupt_timing(tick, bits);
+}
+
+/// Bits of entropy credited since the pool was last taken
+pub fn entropy_estimate() -> u32 {
+    BITS.load(Ordering::Acquire)
+}
+
+/// The pool's contents once [`SEED_BITS`] have been credited, starting it
+/// over
+pub(super) fn take() -> Option<[u8; WORDS * 8]> {
+    BITS.fetch_update(Ordering::Acquire, Ordering::Relaxed, |credit| {
+        (credit >= SEED_BITS).then_some(0)
+    })
+    .ok()?;
+    let mut seed = [0; WORDS * 8];
+    for (word, bytes) in POOL.iter().zip(seed.chunks_exact_mut(8)) {
+        bytes.copy_from_slice(&word.swap(0, Ordering::Relaxed).to_le_bytes());
+    }
+    Some(seed)
+}
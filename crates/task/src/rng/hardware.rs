@@ -0,0 +1,114 @@
+use core::fmt;
+
+/// Where seeds come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// The host operating system's generator
+    Host,
+    /// The CPU's entropy source, `RDSEED`
+    Rdseed,
+    /// The CPU's own generator, `RDRAND`
+    Rdrand,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Host => f.write_str("host"),
+            Source::Rdseed => f.write_str("RDSEED"),
+            Source::Rdrand => f.write_str("RDRAND"),
+        }
+    }
+}
+
+/// The best source there is, the host's before the CPU's
+pub(super) fn detect() -> Option<Source> {
+    if cfg!(feature = "std") {
+        return Some(Source::Host);
+    }
+    cpu_source()
+}
+
+#[cfg(target_arch = "x86_64")]
+fn cpu_source() -> Option<Source> {
+    use core::arch::x86_64::__cpuid;
+
+    const RDRAND: u32 = 1 << 30;
+    const RDSEED: u32 = 1 << 18;
+
+    let max_leaf = __cpuid(0).eax;
+    if max_leaf >= 7 && __cpuid(7).ebx & RDSEED != 0 {
+        Some(Source::Rdseed)
+    } else if __cpuid(1).ecx & RDRAND != 0 {
+        Some(Source::Rdrand)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn cpu_source() -> Option<Source> {
+    None
+}
+
+/// Fill `seed` from `source`, `false` if it failed
+pub(super) fn read(source: Source, seed: &mut [u8]) -> bool {
+    match source {
+        #[cfg(feature = "std")]
+        Source::Host => getrandom::getrandom(seed).is_ok(),
+        #[cfg(target_arch = "x86_64")]
+        Source::Rdseed => fill_words(seed, || {
+            // SAFETY: only used once CPUID said RDSEED is there
+            unsafe { x86::rdseed() }
+        }),
+        #[cfg(target_arch = "x86_64")]
+        Source::Rdrand => fill_words(seed, || {
+            // SAFETY: as above, for RDRAND
+            unsafe { x86::rdrand() }
+        }),
+        #[allow(unreachable_patterns)]
+        _ => false,
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn fill_words(seed: &mut [u8], mut next: impl FnMut() -> Option<u64>) -> bool {
+    for chunk in seed.chunks_mut(8) {
+        let Some(word) = next() else {
+            return false;
+        };
+        chunk.copy_from_slice(&word.to_le_bytes()[..chunk.len()]);
+    }
+    true
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use core::arch::x86_64::{_rdrand64_step, _rdseed64_step};
+
+    /// Either instruction can come up empty for a moment
+    const RETRIES: usize = 100;
+
+    #[target_feature(enable = "rdseed")]
+    pub(super) fn rdseed() -> Option<u64> {
+        let mut value = 0;
+        for _ in 0..RETRIES {
+            // Safe to call with the feature enabled for this function
+            if _rdseed64_step(&mut value) == 1 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    #[target_feature(enable = "rdrand")]
+    pub(super) fn rdrand() -> Option<u64> {
+        let mut value = 0;
+        for _ in 0..RETRIES {
+            if _rdrand64_step(&mut value) == 1 {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,383 @@
+//!
+//! Serial ports
+//!
+//! A [`Serial`] is either a 16550 UART, driven by its interrupt, or the
+//! process's stdin/stdout on the hosted build. Either way [`Serial::split`]
+//! gives an async [`SerialReader`] and [`SerialWriter`], which plug into a
+//! [`Tty`](crate::tty::Tty) or carry bytes for anything else, in a
+//! [`BytesMut`] to pass on with [`SerialReader::read_buf`] and from any
+//! [`Buf`] with [`SerialWriter::write_buf`].
+//!
+//! For early debugging a `Serial` also works as a console output, writing
+//! synchronously:
+//!
+//! ```ignore
+//! let com1 = Serial::uart(pc::ComPort::new(pc::COM1), 115_200)?;
+//! console::set_output(com1.clone()).await;
+//! ```
+//!
+
+use std::{
+    fmt,
+    future::poll_fn,
+    io::{self, Read as _, Write as _},
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll},
+    thread,
+};
+
+use crossbeam_queue::ArrayQueue;
+use futures_util::{Stream, task::AtomicWaker};
+
+use crate::{
+    bytes::{Buf, BufMut, BytesMut},
+    console::TextOutput,
+    io::{AsyncRead, AsyncWrite},
+    metrics::Counter,
+};
+
+/// Access to a UART's eight registers, `register` is the offset from the
+/// base port (`0x3F8` for COM1)
+pub trait Registers: Send {
+    fn read(&mut self, register: u8) -> u8;
+    fn write(&mut self, register: u8, value: u8);
+}
+
+// Register offsets, the divisor latch overlays DATA and INTERRUPT_ENABLE
+const DATA: u8 = 0;
+const INTERRUPT_ENABLE: u8 = 1;
+const FIFO_CONTROL: u8 = 2;
+const LINE_CONTROL: u8 = 3;
+const MODEM_CONTROL: u8 = 4;
+const LINE_STATUS: u8 = 5;
+
+const IER_RECEIVED: u8 = 1 << 0;
+const IER_TRANSMIT_EMPTY: u8 = 1 << 1;
+const LCR_8N1: u8 = 0x03;
+const LCR_DIVISOR_LATCH: u8 = 0x80;
+/// Enable and clear both FIFOs, interrupt at 14 bytes received
+const FCR_ENABLE: u8 = 0xC7;
+/// DTR, RTS and OUT2, which gates the interrupt line on PCs
+const MCR_NORMAL: u8 = 0x0B;
+const MCR_LOOPBACK: u8 = 0x1E;
+const LSR_DATA_READY: u8 = 1 << 0;
+const LSR_TRANSMIT_EMPTY: u8 = 1 << 5;
+
+const BASE_CLOCK: u32 = 115_200;
+/// Bytes the transmit FIFO takes once it reports empty
+const FIFO_SIZE: usize = 16;
+const RX_CAPACITY: usize = 1024;
+
+static DROPPED: Counter = Counter::new(
+    "serial_bytes_dropped_total",
+    "Received serial bytes dropped because nobody read them in time",
+);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialError {
+    /// Bytes sent in loopback mode didn't come back, no working UART there
+    LoopbackFailed,
+    /// The baud rate doesn't divide the UART's clock
+    BadBaudRate(u32),
+}
+
+impl fmt::Display for SerialError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SerialError::LoopbackFailed => f.write_str("UART failed its loopback test"),
+            SerialError::BadBaudRate(baud) => write!(f, "unsupported baud rate {}", baud),
+        }
+    }
+}
+
+enum Backend {
+    Uart(Box<dyn Registers>),
+    Stdio,
+}
+
+struct Shared {
+    backend: spin::Mutex<Backend>,
+    received: ArrayQueue<u8>,
+    // One reader and one writer, as handed out by `split`
+    reader: AtomicWaker,
+    writer: AtomicWaker,
+    // Stdin reached its end
+    closed: AtomicBool,
+}
+
+/// A serial port, clones share it
+#[derive(Clone)]
+pub struct Serial {
+    shared: Arc<Shared>,
+}
+
+impl Serial {
+    /// Set up a 16550 for 8N1 at `baud` with FIFOs and the receive
+    /// interrupt on.
+    ///
+    /// Route the UART's IRQ (4 for COM1) to [`handle_interrupt`].
+    ///
+    /// [`handle_interrupt`]: Serial::handle_interrupt
+    pub fn uart(mut registers: impl Registers + 'static, baud: u32) -> Result<Self, SerialError> {
+        if baud == 0 || !BASE_CLOCK.is_multiple_of(baud) || BASE_CLOCK / baud > u32::from(u16::MAX)
+        {
+            return Err(SerialError::BadBaudRate(baud));
+        }
+        let [low, high] = ((BASE_CLOCK / baud) as u16).to_le_bytes();
+
+        registers.write(INTERRUPT_ENABLE, 0);
+        registers.write(LINE_CONTROL, LCR_DIVISOR_LATCH);
+        registers.write(DATA, low);
+        registers.write(INTERRUPT_ENABLE, high);
+        registers.write(LINE_CONTROL, LCR_8N1);
+        registers.write(FIFO_CONTROL, FCR_ENABLE);
+
+        registers.write(MODEM_CONTROL, MCR_LOOPBACK);
+        registers.write(DATA, 0xAE);
+        if registers.read(DATA) != 0xAE {
+            return Err(SerialError::LoopbackFailed);
+        }
+        registers.write(MODEM_CONTROL, MCR_NORMAL);
+        registers.write(INTERRUPT_ENABLE, IER_RECEIVED);
+
+        Ok(Serial::with_backend(Backend::Uart(Box::new(registers))))
+    }
+
+    /// Stdin and stdout of the hosted build, a pty when run under a
+    /// terminal emulator or QEMU's `-serial stdio`
+    pub fn stdio() -> Self {
+        let serial = Serial::with_backend(Backend::Stdio);
+        let shared = serial.shared.clone();
+        thread::spawn(move || {
+            for byte in io::stdin().lock().bytes() {
+                let Ok(byte) = byte else { break };
+                shared.receive(byte);
+                shared.reader.wake();
+            }
+            shared.closed.store(true, Ordering::Release);
+            shared.reader.wake();
+        });
+        serial
+    }
+
+    fn with_backend(backend: Backend) -> Self {
+        Serial {
+            shared: Arc::new(Shared {
+                backend: spin::Mutex::new(backend),
+                received: ArrayQueue::new(RX_CAPACITY),
+                reader: AtomicWaker::new(),
+                writer: AtomicWaker::new(),
+                closed: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Call from the UART's interrupt handler
+    pub fn handle_interrupt(&self) {
+        let mut backend = self.shared.backend.lock();
+        let Backend::Uart(registers) = &mut *backend else {
+            return;
+        };
+        let mut received = false;
+        loop {
+            let status = registers.read(LINE_STATUS);
+            if status & LSR_DATA_READY != 0 {
+                self.shared.receive(registers.read(DATA));
+                received = true;
+                continue;
+            }
+            if status & LSR_TRANSMIT_EMPTY != 0 {
+                // Only wanted while a writer waits for room
+                registers.write(INTERRUPT_ENABLE, IER_RECEIVED);
+                self.shared.writer.wake();
+            }
+            break;
+        }
+        if received {
+            self.shared.reader.wake();
+        }
+    }
+
+    /// Reading and writing halves. There should be one of each at a time,
+    /// a second reader or writer steals the first one's wakeups.
+    pub fn split(&self) -> (SerialReader, SerialWriter) {
+        (
+            SerialReader {
+                shared: self.shared.clone(),
+            },
+            SerialWriter {
+                shared: self.shared.clone(),
+            },
+        )
+    }
+
+    /// Write `bytes` spinning until the UART takes them, for debugging
+    /// output where nothing can await
+    pub fn write_blocking(&self, bytes: &[u8]) {
+        let mut backend = self.shared.backend.lock();
+        match &mut *backend {
+            Backend::Uart(registers) => {
+                for &byte in bytes {
+                    while registers.read(LINE_STATUS) & LSR_TRANSMIT_EMPTY == 0 {
+                        std::hint::spin_loop();
+                    }
+                    registers.write(DATA, byte);
+                }
+            }
+            Backend::Stdio => {
+                let mut stdout = io::stdout().lock();
+                let _ = stdout.write_all(bytes);
+                let _ = stdout.flush();
+            }
+        }
+    }
+}
+
+impl Shared {
+    fn receive(&self, byte: u8) {
+        if self.received.push(byte).is_err() {
+            DROPPED.inc();
+        }
+    }
+}
+
+impl TextOutput for Serial {
+    fn write_str(&mut self, text: &str) {
+        self.write_blocking(text.as_bytes());
+    }
+}
+
+/// Receiving half of a [`Serial`]
+pub struct SerialReader {
+    shared: Arc<Shared>,
+}
+
+impl SerialReader {
+    /// Wait for input and move as much as fits into `buf`, `0` once stdin
+    /// has ended
+    pub async fn read(&mut self, buf: &mut [u8]) -> usize {
+        poll_fn(|cx| self.poll_read(cx, buf)).await
+    }
+
+    /// Wait for input and append everything received so far to `buf`,
+    /// `0` once stdin has ended. Freeze `buf` to hand the bytes on.
+    pub async fn read_buf(&mut self, buf: &mut BytesMut) -> usize {
+        poll_fn(|cx| {
+            self.shared.reader.register(cx.waker());
+            let mut count = 0;
+            while let Some(byte) = self.shared.received.pop() {
+                buf.put_u8(byte);
+                count += 1;
+            }
+            if count > 0 || self.shared.closed.load(Ordering::Acquire) {
+                Poll::Ready(count)
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    pub fn poll_read(&mut self, cx: &mut Context, buf: &mut [u8]) -> Poll<usize> {
+        if buf.is_empty() {
+            return Poll::Ready(0);
+        }
+        // Register first so a byte arriving right after the check wakes us
+        self.shared.reader.register(cx.waker());
+        let mut count = 0;
+        while count < buf.len()
+            && let Some(byte) = self.shared.received.pop()
+        {
+            buf[count] = byte;
+            count += 1;
+        }
+        if count > 0 || self.shared.closed.load(Ordering::Acquire) {
+            Poll::Ready(count)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl AsyncRead for SerialReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<crate::io::Result<usize>> {
+        SerialReader::poll_read(self.get_mut(), cx, buf).map(Ok)
+    }
+}
+
+impl Stream for SerialReader {
+    type Item = u8;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<u8>> {
+        let mut byte = [0];
+        self.get_mut()
+            .poll_read(cx, &mut byte)
+            .map(|count| (count == 1).then_some(byte[0]))
+    }
+}
+
+/// Sending half of a [`Serial`]
+pub struct SerialWriter {
+    shared: Arc<Shared>,
+}
+
+impl SerialWriter {
+    /// Returns once the UART has taken every byte
+    pub async fn write(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let sent = poll_fn(|cx| self.poll_write(cx, bytes)).await;
+            bytes = &bytes[sent..];
+        }
+    }
+
+    /// Write everything left in `buf`, chunk by chunk
+    pub async fn write_buf(&mut self, mut buf: impl Buf) {
+        while buf.has_remaining() {
+            let sent = poll_fn(|cx| self.poll_write(cx, buf.chunk())).await;
+            buf.advance(sent);
+        }
+    }
+
+    /// Hand over as many bytes as the UART takes right now
+    pub fn poll_write(&mut self, cx: &mut Context, bytes: &[u8]) -> Poll<usize> {
+        let mut backend = self.shared.backend.lock();
+        let registers = match &mut *backend {
+            Backend::Uart(registers) => registers,
+            Backend::Stdio => {
+                let mut stdout = io::stdout().lock();
+                let _ = stdout.write_all(bytes);
+                let _ = stdout.flush();
+                return Poll::Ready(bytes.len());
+            }
+        };
+        self.shared.writer.register(cx.waker());
+        if registers.read(LINE_STATUS) & LSR_TRANSMIT_EMPTY == 0 {
+            // Interrupt us once the FIFO has drained
+            registers.write(INTERRUPT_ENABLE, IER_RECEIVED | IER_TRANSMIT_EMPTY);
+            return Poll::Pending;
+        }
+        let count = bytes.len().min(FIFO_SIZE);
+        for &byte in &bytes[..count] {
+            registers.write(DATA, byte);
+        }
+        Poll::Ready(count)
+    }
+}
+
+impl AsyncWrite for SerialWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        bytes: &[u8],
+    ) -> Poll<crate::io::Result<usize>> {
+        SerialWriter::poll_write(self.get_mut(), cx, bytes).map(Ok)
+    }
+}
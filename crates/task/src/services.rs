@@ -0,0 +1,241 @@
+//!
+//! Services
+//!
+//! A subsystem can be reached through typed requests and responses sent
+//! over channels instead of calls into it: a [`Protocol`] says what goes
+//! back and forth, a [`Service`] answers requests in its own task and a
+//! [`Client`] sends them. Any service speaking the protocol can stand in,
+//! a closure for a test for instance:
+//!
+//! ```ignore
+//! executor.spawn_local(services::serve::<fs::Fs>(fs::FsService));
+//! let motd = services::fs::read("/etc/motd").await?;
+//!
+//! // Every read now finds the same file
+//! executor.spawn_local(services::serve::<fs::Fs>(|_| async {
+//!     Ok(fs::FsResponse::Data(b"hello".to_vec()))
+//! }));
+//! ```
+//!
+//! The console, the filesystem and the network have a protocol each in the
+//! submodules, with functions wrapping the requests.
+//!
+//! A request only gets to the service if the task sending it has the
+//! [`Capability`] the protocol says it needs.
+//!
+
+pub mod console;
+#[cfg(feature = "fs")]
+pub mod fs;
+#[cfg(feature = "net")]
+pub mod net;
+
+use alloc::{boxed::Box, collections::BTreeMap, vec::Vec};
+use core::{
+    any::{Any, TypeId},
+    fmt,
+    future::Future,
+};
+
+use crate::{
+    LocalTask,
+    capability::{self, Capability, Grant},
+    channel::{mpmc, oneshot},
+};
+
+/// Requests queued before [`Client::call`] waits
+pub const QUEUE_CAPACITY: usize = 32;
+
+/// What a service is asked and what it answers
+pub trait Protocol: 'static {
+    /// Names the service's task and shows in [`services`]
+    const NAME: &'static str;
+    type Request: Send + 'static;
+    type Response: Send + 'static;
+
+    /// What the sender of `request` has to be allowed, checked before the
+    /// service sees it
+    fn required(request: &Self::Request) -> Option<Capability> {
+        let _ = request;
+        None
+    }
+}
+
+/// Answers the requests of protocol `P`, one at a time
+pub trait Service<P: Protocol>: 'static {
+    fn call(&mut self, request: P::Request) -> impl Future<Output = P::Response>;
+}
+
+impl<P, F, Fut> Service<P> for F
+where
+    P: Protocol,
+    F: FnMut(P::Request) -> Fut + 'static,
+    Fut: Future<Output = P::Response>,
+{
+    fn call(&mut self, request: P::Request) -> impl Future<Output = P::Response> {
+        self(request)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ServiceError {
+    /// Nothing was [`provide`]d for the protocol with this name
+    NotProvided(&'static str),
+    /// The service's task is gone
+    Stopped(&'static str),
+    /// The sending task lacks what [`Protocol::required`] asked for
+    Denied(Capability),
+}
+
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServiceError::NotProvided(name) => write!(f, "no {} service", name),
+            ServiceError::Stopped(name) => write!(f, "{} service stopped", name),
+            ServiceError::Denied(capability) => write!(f, "missing capability: {}", capability),
+        }
+    }
+}
+
+struct Envelope<P: Protocol> {
+    request: P::Request,
+    // Of the task that sent the request
+    caller: Grant,
+    reply: oneshot::Sender<Result<P::Response, ServiceError>>,
+}
+
+/// Sends requests to a service, cheap to clone
+pub struct Client<P: Protocol> {
+    requests: mpmc::Sender<Envelope<P>>,
+}
+
+impl<P: Protocol> Clone for Client<P> {
+    fn clone(&self) -> Self {
+        Client {
+            requests: self.requests.clone(),
+        }
+    }
+}
+
+impl<P: Protocol> Client<P> {
+    /// Send `request` and wait for the response, fails once the service has
+    /// stopped
+    pub async fn call(&self, request: P::Request) -> Result<P::Response, ServiceError> {
+        let (reply, response) = oneshot::channel();
+        let envelope = Envelope {
+            request,
+            caller: capability::current(),
+            reply,
+        };
+        self.requests
+            .send(envelope)
+            .await
+            .map_err(|_| ServiceError::Stopped(P::NAME))?;
+        response.await.ok_or(ServiceError::Stopped(P::NAME))?
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.requests.is_closed()
+    }
+}
+
+impl<P: Protocol> fmt::Debug for Client<P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("service", &P::NAME)
+            .field("running", &self.is_running())
+            .finish()
+    }
+}
+
+/// Create the task answering requests with `service`, without spawning
+/// it. It ends once every client is dropped.
+pub fn start<P: Protocol>(service: impl Service<P>) -> (Client<P>, LocalTask) {
+    let (requests, rx) = mpmc::channel(QUEUE_CAPACITY);
+    let task = LocalTask::new(answer::<P>(service, rx)).with_name(P::NAME);
+    (Client { requests }, task)
+}
+
+async fn answer<P: Protocol>(mut service: impl Service<P>, mut rx: mpmc::Receiver<Envelope<P>>) {
+    while let Some(Envelope {
+        request,
+        caller,
+        reply,
+    }) = rx.recv().await
+    {
+        let response = match P::required(&request) {
+            Some(needed) if capability::check_grant(&caller, &needed).is_err() => {
+                Err(ServiceError::Denied(needed))
+            }
+            _ => Ok(service.call(request).await),
+        };
+        // The caller may have given up waiting
+        let _ = reply.send(response);
+    }
+}
+
+struct Provided {
+    name: &'static str,
+    client: Box<dyn Any + Send>,
+    // `Client::is_running` for the type `client` has
+    is_running: fn(&(dyn Any + Send)) -> bool,
+}
+
+fn is_running<P: Protocol>(client: &(dyn Any + Send)) -> bool {
+    client
+        .downcast_ref::<Client<P>>()
+        .is_some_and(Client::is_running)
+}
+
+// Clients handed out by `client`, by the protocol's type
+static PROVIDED: spin::Mutex<BTreeMap<TypeId, Provided>> = spin::Mutex::new(BTreeMap::new());
+
+/// Make `client` the one [`client`] returns for `P`, replacing the previous
+/// one
+pub fn provide<P: Protocol>(client: Client<P>) {
+    let provided = Provided {
+        name: P::NAME,
+        client: Box::new(client),
+        is_running: is_running::<P>,
+    };
+    PROVIDED.lock().insert(TypeId::of::<P>(), provided);
+}
+
+/// [`start`] the service and [`provide`] its client, the task is left to
+/// spawn
+pub fn serve<P: Protocol>(service: impl Service<P>) -> LocalTask {
+    let (client, task) = start(service);
+    provide(client);
+    task
+}
+
+/// The client last [`provide`]d for `P`
+pub fn client<P: Protocol>() -> Result<Client<P>, ServiceError> {
+    PROVIDED
+        .lock()
+        .get(&TypeId::of::<P>())
+        .and_then(|provided| provided.client.downcast_ref::<Client<P>>())
+        .cloned()
+        .ok_or(ServiceError::NotProvided(P::NAME))
+}
+
+/// Send `request` to the service provided for `P`
+pub async fn call<P: Protocol>(request: P::Request) -> Result<P::Response, ServiceError> {
+    client::<P>()?.call(request).await
+}
+
+/// Names of the provided services and whether they're still running
+pub fn services() -> Vec<(&'static str, bool)> {
+    let mut services: Vec<_> = PROVIDED
+        .lock()
+        .values()
+        .map(|provided| {
+            (
+                provided.name,
+                (provided.is_running)(provided.client.as_ref()),
+            )
+        })
+        .collect();
+    services.sort();
+    services
+}
@@ -0,0 +1,49 @@
+//! The console as a service
+
+use alloc::string::String;
+
+use super::{Protocol, Service, ServiceError, call};
+use crate::capability::Capability;
+
+/// The [`Protocol`] of the console
+#[derive(Debug)]
+pub struct Console;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsoleRequest {
+    /// Write the text in one piece
+    Write(String),
+    /// Move the view back into scrollback, forward if negative
+    Scroll(isize),
+}
+
+impl Protocol for Console {
+    const NAME: &'static str = "console";
+    type Request = ConsoleRequest;
+    type Response = ();
+
+    fn required(_: &ConsoleRequest) -> Option<Capability> {
+        Some(Capability::ConsoleWrite)
+    }
+}
+
+/// Answers with [`crate::console`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConsoleService;
+
+impl Service<Console> for ConsoleService {
+    async fn call(&mut self, request: ConsoleRequest) {
+        match request {
+            ConsoleRequest::Write(text) => crate::console::write_str(&text).await,
+            ConsoleRequest::Scroll(lines) => crate::console::scroll(lines).await,
+        }
+    }
+}
+
+pub async fn write(text: impl Into<String>) -> Result<(), ServiceError> {
+    call::<Console>(ConsoleRequest::Write(text.into())).await
+}
+
+pub async fn scroll(lines: isize) -> Result<(), ServiceError> {
+    call::<Console>(ConsoleRequest::Scroll(lines)).await
+}
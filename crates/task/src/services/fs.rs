@@ -0,0 +1,131 @@
+//! The filesystem as a service
+//!
+//! The functions here return [`fs::Error`](crate::fs::Error) like the ones
+//! in [`crate::fs`], a service that isn't there is a broken pipe. Each
+//! request needs [`Capability::Fs`] for its path.
+
+use alloc::{string::String, vec::Vec};
+
+use super::{Protocol, Service, ServiceError, call};
+use crate::{
+    capability::Capability,
+    fs::{self, DirEntry, Error, Metadata},
+    io,
+};
+
+/// The [`Protocol`] of the filesystem
+#[derive(Debug)]
+pub struct Fs;
+
+/// Paths are absolute, as for [`crate::fs`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsRequest {
+    Read(String),
+    Write(String, Vec<u8>),
+    ReadDir(String),
+    Metadata(String),
+    CreateDir(String),
+    Remove(String),
+}
+
+/// The answer to an [`FsRequest`], the variant going with the request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsResponse {
+    /// To [`FsRequest::Read`]
+    Data(Vec<u8>),
+    /// To [`FsRequest::ReadDir`]
+    Entries(Vec<DirEntry>),
+    /// To [`FsRequest::Metadata`]
+    Metadata(Metadata),
+    /// To the rest
+    Done,
+}
+
+impl Protocol for Fs {
+    const NAME: &'static str = "fs";
+    type Request = FsRequest;
+    type Response = fs::Result<FsResponse>;
+
+    fn required(request: &FsRequest) -> Option<Capability> {
+        Some(Capability::Fs(request.path().into()))
+    }
+}
+
+impl FsRequest {
+    pub fn path(&self) -> &str {
+        match self {
+            FsRequest::Read(path)
+            | FsRequest::Write(path, _)
+            | FsRequest::ReadDir(path)
+            | FsRequest::Metadata(path)
+            | FsRequest::CreateDir(path)
+            | FsRequest::Remove(path) => path,
+        }
+    }
+}
+
+/// Answers with the mounted filesystems
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FsService;
+
+impl Service<Fs> for FsService {
+    async fn call(&mut self, request: FsRequest) -> fs::Result<FsResponse> {
+        match request {
+            FsRequest::Read(path) => fs::read(&path).await.map(FsResponse::Data),
+            FsRequest::Write(path, contents) => {
+                fs::write(&path, &contents).await.map(|()| FsResponse::Done)
+            }
+            FsRequest::ReadDir(path) => fs::read_dir(&path).await.map(FsResponse::Entries),
+            FsRequest::Metadata(path) => fs::metadata(&path).await.map(FsResponse::Metadata),
+            FsRequest::CreateDir(path) => fs::create_dir(&path).await.map(|()| FsResponse::Done),
+            FsRequest::Remove(path) => fs::remove(&path).await.map(|()| FsResponse::Done),
+        }
+    }
+}
+
+async fn request(request: FsRequest) -> fs::Result<FsResponse> {
+    match call::<Fs>(request).await {
+        Ok(response) => response,
+        Err(ServiceError::Denied(_)) => Err(Error::PermissionDenied),
+        Err(_) => Err(Error::Io(io::Error::BrokenPipe)),
+    }
+}
+
+fn mismatched(response: FsResponse) -> ! {
+    panic!("fs service answered {:?}", response)
+}
+
+pub async fn read(path: &str) -> fs::Result<Vec<u8>> {
+    match request(FsRequest::Read(path.into())).await? {
+        FsResponse::Data(contents) => Ok(contents),
+        response => mismatched(response),
+    }
+}
+
+pub async fn write(path: &str, contents: &[u8]) -> fs::Result<()> {
+    request(FsRequest::Write(path.into(), contents.into()))
+        .await
+        .map(drop)
+}
+
+pub async fn read_dir(path: &str) -> fs::Result<Vec<DirEntry>> {
+    match request(FsRequest::ReadDir(path.into())).await? {
+        FsResponse::Entries(entries) => Ok(entries),
+        response => mismatched(response),
+    }
+}
+
+pub async fn metadata(path: &str) -> fs::Result<Metadata> {
+    match request(FsRequest::Metadata(path.into())).await? {
+        FsResponse::Metadata(metadata) => Ok(metadata),
+        response => mismatched(response),
+    }
+}
+
+pub async fn create_dir(path: &str) -> fs::Result<()> {
+    request(FsRequest::CreateDir(path.into())).await.map(drop)
+}
+
+pub async fn remove(path: &str) -> fs::Result<()> {
+    request(FsRequest::Remove(path.into())).await.map(drop)
+}
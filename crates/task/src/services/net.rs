@@ -0,0 +1,95 @@
+//! The network as a service
+//!
+//! The functions here return [`net::Error`](crate::net::Error) like the
+//! ones in [`crate::net`], a service that isn't there is a network that
+//! isn't initialized.
+
+use alloc::{string::String, vec::Vec};
+use core::time::Duration;
+
+use super::{Protocol, Service, call};
+use crate::net::{self, Error, IpAddress, Ipv4Cidr, PingStats};
+
+/// The [`Protocol`] of the network
+#[derive(Debug)]
+pub struct Net;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetRequest {
+    /// Look a host name up
+    Resolve(String),
+    Ping {
+        to: IpAddress,
+        count: u16,
+        timeout: Duration,
+    },
+    /// The interface's IPv4 address
+    Address,
+}
+
+/// The answer to a [`NetRequest`], the variant going with the request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetResponse {
+    Addresses(Vec<IpAddress>),
+    Ping(PingStats),
+    Address(Option<Ipv4Cidr>),
+}
+
+impl Protocol for Net {
+    const NAME: &'static str = "net";
+    type Request = NetRequest;
+    type Response = net::Result<NetResponse>;
+}
+
+/// Answers with the interface [`net::init`] set up
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NetService;
+
+impl Service<Net> for NetService {
+    async fn call(&mut self, request: NetRequest) -> net::Result<NetResponse> {
+        match request {
+            NetRequest::Resolve(name) => net::resolve(&name).await.map(NetResponse::Addresses),
+            NetRequest::Ping { to, count, timeout } => {
+                net::ping(to, count, timeout).await.map(NetResponse::Ping)
+            }
+            NetRequest::Address => Ok(NetResponse::Address(net::ipv4_address())),
+        }
+    }
+}
+
+async fn request(request: NetRequest) -> net::Result<NetResponse> {
+    // The protocol needs no capability, so no `Denied` either
+    call::<Net>(request)
+        .await
+        .unwrap_or(Err(Error::NotInitialized))
+}
+
+fn mismatched(response: NetResponse) -> ! {
+    panic!("net service answered {:?}", response)
+}
+
+pub async fn resolve(name: &str) -> net::Result<Vec<IpAddress>> {
+    match request(NetRequest::Resolve(name.into())).await? {
+        NetResponse::Addresses(addresses) => Ok(addresses),
+        response => mismatched(response),
+    }
+}
+
+pub async fn ping(
+    to: impl Into<IpAddress>,
+    count: u16,
+    timeout: Duration,
+) -> net::Result<PingStats> {
+    let to = to.into();
+    match request(NetRequest::Ping { to, count, timeout }).await? {
+        NetResponse::Ping(stats) => Ok(stats),
+        response => mismatched(response),
+    }
+}
+
+pub async fn ipv4_address() -> net::Result<Option<Ipv4Cidr>> {
+    match request(NetRequest::Address).await? {
+        NetResponse::Address(address) => Ok(address),
+        response => mismatched(response),
+    }
+}
@@ -0,0 +1,390 @@
+//!
+//! Shell
+//!
+//! Reads a line from a [`Tty`], splits it into words and runs the command
+//! named by the first one. Commands are plain functions registered with
+//! [`register_command`]:
+//!
+//! ```ignore
+//! fn greet<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+//!     Box::pin(async move {
+//!         let name = args.first().ok_or("usage: greet NAME")?;
+//!         tty.write_str(&format!("hello, {}\n", name)).await;
+//!         Ok(())
+//!     })
+//! }
+//!
+//! shell::register_command("greet", "Say hello", greet);
+//! ```
+//!
+//! Commands joined by `|` run at the same time, each one's output going
+//! through a [`pipe`] to the next one's input: `ps | grep shell`. The first
+//! command gets no input, the last one's output goes to the shell's `Tty`.
+//!
+//! Each command line runs as a job, a task of its own with a `Tty` of its
+//! own. The shell waits on it in the foreground, where Ctrl+C drops it
+//! unless it takes its [`signals`](Tty::signals) and Ctrl+Z stops it. A
+//! line ending in `&` runs in the background instead: `top 30 &`. `jobs`
+//! lists them, `fg` waits on one and `bg` resumes a stopped one where it
+//! is.
+//!
+//! Up and Down go through the lines typed before, kept in
+//! [`HISTORY_PATH`] so they outlive the shell. Tab completes command names,
+//! and paths in the filesystem after them.
+//!
+//! `export NAME=VALUE` sets a variable of the [`env`](crate::env), which
+//! the jobs and the tasks they start share: `export TIMER_MS=200` speeds up
+//! a `demo run timer` already ticking.
+//!
+//! [`serve_telnet`] runs shells for clients connecting over the network.
+//!
+
+mod builtins;
+mod jobs;
+mod telnet;
+mod top;
+
+use std::{collections::BTreeMap, fmt, future::Future, mem, pin::Pin};
+
+use futures_util::{
+    future::{join, join_all},
+    stream,
+};
+
+use crate::{
+    editor::{CompletionFuture, LineEditor},
+    fs::{self, FileType},
+    io,
+    pipe::{self, PipeReader, pipe},
+    tty::{Tty, TtyInput, TtyMode},
+};
+use jobs::Jobs;
+
+pub use telnet::serve_telnet;
+
+/// Future returned by a [`Handler`], an error is printed after the
+/// command's name
+pub type CommandFuture<'a> = Pin<Box<dyn Future<Output = Result<(), String>> + Send + 'a>>;
+
+/// Runs a command, `args` doesn't include the command's name
+pub type Handler = for<'a> fn(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a>;
+
+pub(crate) struct Command {
+    help: &'static str,
+    handler: Handler,
+}
+
+static COMMANDS: spin::Mutex<BTreeMap<&'static str, Command>> = spin::Mutex::new(BTreeMap::new());
+
+/// Add a command to every shell, replacing one of the same name
+pub fn register_command(name: &'static str, help: &'static str, handler: Handler) {
+    COMMANDS.lock().insert(name, Command { help, handler });
+}
+
+pub fn unregister_command(name: &str) {
+    COMMANDS.lock().remove(name);
+}
+
+const PROMPT: &str = "> ";
+
+/// Where the shell keeps its history, one line each. Every shell reads it
+/// when it starts and writes its own back after each line.
+pub const HISTORY_PATH: &str = "/.shell_history";
+
+/// Run commands from `tty` until its input ends or `exit` is typed, its
+/// jobs are aborted then
+pub async fn run_shell(mut tty: Tty) {
+    builtins::register(&mut COMMANDS.lock());
+    let mut editor = LineEditor::new();
+    editor.set_completer(complete);
+    load_history(&mut editor).await;
+    let mut jobs = Jobs::new();
+    loop {
+        let done = jobs.reap();
+        tty.write_str(&done).await;
+        let Some(line) = tty.edit_line(&mut editor, PROMPT).await else {
+            break;
+        };
+        save_history(&editor).await;
+        let CommandLine {
+            commands,
+            background,
+        } = match parse_command_line(&line) {
+            Ok(line) => line,
+            Err(err) => {
+                tty.write_str(&format!("{}\n", err)).await;
+                continue;
+            }
+        };
+        match commands.as_slice() {
+            [words] if words.is_empty() => continue,
+            [words] if words[0] == "exit" => break,
+            [words] => {
+                if let Some(result) = jobs.run_builtin(&mut tty, words).await {
+                    if let Err(message) = result {
+                        tty.write_str(&format!("{}: {}\n", words[0], message)).await;
+                    }
+                    continue;
+                }
+            }
+            _ => {}
+        }
+
+        let count = commands.len();
+        // Copied out so the registry isn't locked while the commands run
+        let mut stages = Vec::new();
+        for mut words in commands {
+            let name = words.remove(0);
+            match COMMANDS.lock().get(name.as_str()) {
+                Some(command) => stages.push((name, command.handler, words)),
+                None => {
+                    tty.write_str(&format!("{}: command not found, try `help`\n", name))
+                        .await;
+                    break;
+                }
+            }
+        }
+        if stages.len() < count {
+            // One of the commands wasn't found
+            continue;
+        }
+        let line = line.trim().trim_end_matches('&').trim_end();
+        let number = jobs.start(&tty, line, stages);
+        if background {
+            let id = jobs.id(number).expect("just started");
+            tty.write_str(&format!("[{}] {}\n", number, id)).await;
+        } else {
+            jobs.wait(&mut tty, number).await;
+        }
+    }
+    jobs.abort_all();
+}
+
+/// Run a command line's commands, then print their errors
+async fn run_job(tty: &mut Tty, stages: Vec<(String, Handler, Vec<String>)>) {
+    if let [(name, handler, args)] = stages.as_slice() {
+        if let Err(message) = handler(tty, args).await {
+            tty.write_str(&format!("{}: {}\n", name, message)).await;
+        }
+    } else {
+        run_pipeline(tty, stages).await;
+    }
+}
+
+/// Lines from an earlier shell, if there's a filesystem with them
+async fn load_history(editor: &mut LineEditor) {
+    if let Ok(history) = fs::read_to_string(HISTORY_PATH).await {
+        for line in history.lines() {
+            editor.add_history(line);
+        }
+    }
+}
+
+async fn save_history(editor: &LineEditor) {
+    let mut history = String::new();
+    for line in editor.history() {
+        history.push_str(line);
+        history.push('\n');
+    }
+    // Without a filesystem it lasts as long as the shell
+    let _ = fs::write(HISTORY_PATH, history.as_bytes()).await;
+}
+
+/// Command names for the word before the cursor where a command goes,
+/// paths anywhere else
+fn complete(before: &str) -> CompletionFuture {
+    let command = before.rsplit(['|', '&']).next().unwrap_or(before);
+    let command = command.trim_start();
+    let word = before.rsplit(char::is_whitespace).next().unwrap_or(before);
+    if word == command {
+        let names = complete_command(command);
+        Box::pin(async move { names })
+    } else if command.contains(char::is_whitespace) {
+        let word = word.to_string();
+        Box::pin(async move { complete_path(&word).await })
+    } else {
+        // A name glued to a `|` the editor would replace along with it
+        Box::pin(async { Vec::new() })
+    }
+}
+
+/// Command names starting with `command`
+fn complete_command(command: &str) -> Vec<String> {
+    let commands = COMMANDS.lock();
+    let job_control = jobs::BUILTINS.iter().map(|(name, _)| *name);
+    let names = commands.keys().copied().chain(job_control).chain(["exit"]);
+    names
+        .filter(|name| name.starts_with(command))
+        .map(String::from)
+        .collect()
+}
+
+/// Paths starting with `word`, looked up from `/`, directories with a `/`
+/// after them
+async fn complete_path(word: &str) -> Vec<String> {
+    let (dir, name) = word.split_at(word.rfind('/').map_or(0, |slash| slash + 1));
+    let Ok(path) = fs::path::join("/", dir) else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(&path).await else {
+        return Vec::new();
+    };
+    entries
+        .into_iter()
+        .filter(|entry| entry.name.starts_with(name))
+        .map(|entry| {
+            let slash = if entry.file_type == FileType::Directory {
+                "/"
+            } else {
+                ""
+            };
+            format!("{}{}{}", dir, entry.name, slash)
+        })
+        .collect()
+}
+
+/// Bytes in flight between two commands of a pipeline
+const PIPE_CAPACITY: usize = pipe::DEFAULT_CAPACITY;
+
+/// Run `stages` connected by pipes, then print their errors
+async fn run_pipeline(tty: &mut Tty, stages: Vec<(String, Handler, Vec<String>)>) {
+    let mut previous: Option<PipeReader> = None;
+    let mut running = Vec::new();
+    for (name, handler, args) in stages {
+        let (reader, writer) = pipe(PIPE_CAPACITY);
+        let input: TtyInput = match previous.replace(reader) {
+            Some(reader) => Box::pin(reader),
+            None => Box::pin(stream::empty()),
+        };
+        let mut stage = Tty::new(input, writer);
+        stage.set_mode(TtyMode::RAW);
+        // Owns its `Tty`, so the next command's input ends as soon as this
+        // one returns
+        running.push(async move {
+            handler(&mut stage, &args)
+                .await
+                .map_err(|message| format!("{}: {}\n", name, message))
+        });
+    }
+
+    let mut last = previous.expect("a pipeline has commands");
+    // Output to a closed terminal is dropped like `Tty::write` does
+    let output = async {
+        let _ = io::copy(&mut last, tty).await;
+    };
+    let (results, ()) = join(join_all(running), output).await;
+    for message in results.into_iter().filter_map(Result::err) {
+        tty.write_str(&message).await;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenizeError {
+    UnterminatedQuote,
+    /// A backslash at the very end
+    TrailingEscape,
+    /// Nothing before or after a `|`, or before `&`
+    EmptyCommand,
+    /// Something after the `&` that puts a line in the background
+    MisplacedAmpersand,
+}
+
+impl fmt::Display for TokenizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TokenizeError::UnterminatedQuote => f.write_str("unterminated quote"),
+            TokenizeError::TrailingEscape => f.write_str("nothing after `\\`"),
+            TokenizeError::EmptyCommand => f.write_str("missing command next to `|` or `&`"),
+            TokenizeError::MisplacedAmpersand => f.write_str("`&` only goes at the end"),
+        }
+    }
+}
+
+/// Split `line` into words at whitespace, like a POSIX shell does.
+///
+/// Single quotes keep everything up to the closing quote as is, double
+/// quotes still let `\` escape the next character. A `|` is an ordinary
+/// character here, see [`parse_pipeline`].
+pub fn tokenize(line: &str) -> Result<Vec<String>, TokenizeError> {
+    split(line, false, false).map(|(mut commands, _)| commands.remove(0))
+}
+
+/// [`tokenize`] `line` into the commands of a pipeline, split at every `|`
+/// outside quotes.
+///
+/// A line without `|` is a single command, possibly without words.
+pub fn parse_pipeline(line: &str) -> Result<Vec<Vec<String>>, TokenizeError> {
+    let (commands, _) = split(line, true, false)?;
+    if commands.len() > 1 && commands.iter().any(Vec::is_empty) {
+        return Err(TokenizeError::EmptyCommand);
+    }
+    Ok(commands)
+}
+
+/// A line typed at the shell
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandLine {
+    pub commands: Vec<Vec<String>>,
+    /// It ended in `&`
+    pub background: bool,
+}
+
+/// [`parse_pipeline`] with an `&` at the end too, outside quotes, which
+/// runs the line as a background job
+pub fn parse_command_line(line: &str) -> Result<CommandLine, TokenizeError> {
+    let (commands, background) = split(line, true, true)?;
+    if (commands.len() > 1 || background) && commands.iter().any(Vec::is_empty) {
+        return Err(TokenizeError::EmptyCommand);
+    }
+    Ok(CommandLine {
+        commands,
+        background,
+    })
+}
+
+/// Words of the commands, split at `|` if `pipes`, and whether the line
+/// ended in `&` if `jobs`
+fn split(line: &str, pipes: bool, jobs: bool) -> Result<(Vec<Vec<String>>, bool), TokenizeError> {
+    let mut commands = Vec::new();
+    let mut words = Vec::new();
+    // `None` between words, so `''` still makes an empty word
+    let mut word: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut background = false;
+    let mut chars = line.chars();
+
+    while let Some(character) = chars.next() {
+        if background && !character.is_whitespace() {
+            return Err(TokenizeError::MisplacedAmpersand);
+        }
+        match (quote, character) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), _) => word.get_or_insert_default().push(character),
+            (_, '\\') => {
+                let escaped = chars.next().ok_or(TokenizeError::TrailingEscape)?;
+                word.get_or_insert_default().push(escaped);
+            }
+            (Some(_), _) => word.get_or_insert_default().push(character),
+            (None, '\'' | '"') => {
+                quote = Some(character);
+                word.get_or_insert_default();
+            }
+            (None, _) if character.is_whitespace() => words.extend(word.take()),
+            (None, '|') if pipes => {
+                words.extend(word.take());
+                commands.push(mem::take(&mut words));
+            }
+            (None, '&') if jobs => {
+                words.extend(word.take());
+                background = true;
+            }
+            (None, _) => word.get_or_insert_default().push(character),
+        }
+    }
+    if quote.is_some() {
+        return Err(TokenizeError::UnterminatedQuote);
+    }
+    words.extend(word);
+    commands.push(words);
+    Ok((commands, background))
+}
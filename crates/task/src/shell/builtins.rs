@@ -0,0 +1,783 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use super::{Command, CommandFuture, Handler, Tty, top::top};
+use crate::{
+    TaskId, console, demo, drivers, env, executor,
+    fs::{self, FileType},
+    io::{self, AsyncReadExt},
+    keyboard, log, memory, metrics,
+    net::{self, EthernetAddress, IpAddress, Ipv4Address, NeighborState, PingStats, Pinger, http},
+    power, profiler, shutdown, speaker, time, timer, trace,
+};
+
+/// How long `ping` waits for each reply
+const PING_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub(super) fn register(commands: &mut BTreeMap<&'static str, Command>) {
+    let builtins: [(&'static str, &'static str, Handler); 36] = [
+        ("help", "List commands", help),
+        ("echo", "Print the arguments", echo),
+        ("grep", "Print input lines containing TEXT: grep TEXT", grep),
+        ("loadkeys", "Reload the keymap from its source", loadkeys),
+        (
+            "inject",
+            "Type text on the keyboard, -n presses Enter after: inject [-n] [TEXT]",
+            inject,
+        ),
+        (
+            "sendkey",
+            "Feed scancodes to the keyboard: sendkey SCANCODE...",
+            sendkey,
+        ),
+        ("ps", "List running tasks", ps),
+        ("kill", "Abort a task: kill ID", kill),
+        ("status", "Whether a task still runs: status ID...", status),
+        ("demo", "Tutorial demos: demo list | demo run NAME", demo),
+        ("env", "Print environment variables: env [NAME...]", env),
+        (
+            "export",
+            "Set environment variables: export NAME=VALUE...",
+            export,
+        ),
+        (
+            "unset",
+            "Remove environment variables: unset NAME...",
+            unset,
+        ),
+        ("top", "Tasks refreshed every second: top [SCREENS]", top),
+        (
+            "chvt",
+            "Show virtual terminal N, numbered like Alt+F1 to F4: chvt [N]",
+            chvt,
+        ),
+        ("prof", "Sample where CPU time goes: prof [SECONDS]", prof),
+        (
+            "trace",
+            "Scheduler events: trace [on|off|clear|COUNT]",
+            trace,
+        ),
+        ("uptime", "Time since boot", uptime),
+        ("shutdown", "Stop every task and the executor", shutdown),
+        ("poweroff", "Shut down and turn the machine off", poweroff),
+        ("reboot", "Shut down and restart the machine", reboot),
+        (
+            "date",
+            "Current date and time, or set it: date [--rfc3339 | set DATETIME]",
+            date,
+        ),
+        ("beep", "Sound the speaker: beep [HZ [MS]]", beep),
+        (
+            "mem",
+            "Heap usage, reset starts a new peak: mem [reset]",
+            mem,
+        ),
+        (
+            "metrics",
+            "Print every metric in Prometheus format",
+            metrics,
+        ),
+        ("ls", "List a directory: ls [PATH]", ls),
+        ("cat", "Print files: cat PATH...", cat),
+        ("tee", "Copy input to a file and the output: tee PATH", tee),
+        ("mkdir", "Make a directory: mkdir PATH", mkdir),
+        ("rm", "Remove a file or empty directory: rm PATH", rm),
+        ("mount", "List mounted filesystems", mount),
+        (
+            "logs",
+            "Records kept in the journal, of one boot with -b, -1 the one before the last: logs [-b [BOOT]]",
+            logs,
+        ),
+        ("drivers", "List device drivers and their state", drivers),
+        (
+            "ping",
+            "Send ICMP echo requests: ping ADDRESS [COUNT]",
+            ping,
+        ),
+        ("fetch", "Print a page fetched over HTTP: fetch URL", fetch),
+        (
+            "arp",
+            "Show or change the neighbor cache: arp [-s IP MAC | -d IP | -f]",
+            arp,
+        ),
+    ];
+    for (name, help, handler) in builtins {
+        // Registered commands of the same name win
+        commands.entry(name).or_insert(Command { help, handler });
+    }
+}
+
+fn help<'a>(tty: &'a mut Tty, _args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let mut text = String::new();
+        for (name, command) in super::COMMANDS.lock().iter() {
+            text.push_str(&format!("  {:<12} {}\n", name, command.help));
+        }
+        for (name, help) in super::jobs::BUILTINS {
+            text.push_str(&format!("  {:<12} {}\n", name, help));
+        }
+        text.push_str(&format!("  {:<12} {}\n", "exit", "Leave the shell"));
+        tty.write_str(&text).await;
+        Ok(())
+    })
+}
+
+fn echo<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        tty.write_str(&format!("{}\n", args.join(" "))).await;
+        Ok(())
+    })
+}
+
+fn grep<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let [pattern] = args else {
+            return Err("usage: grep TEXT".into());
+        };
+        while let Some(line) = tty.read_line().await {
+            if line.contains(pattern.as_str()) {
+                tty.write_str(&format!("{}\n", line)).await;
+            }
+        }
+        Ok(())
+    })
+}
+
+fn loadkeys<'a>(tty: &'a mut Tty, _args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let keymap = keyboard::reload_keymap().map_err(|err| err.to_string())?;
+        tty.write_str(&keymap.to_string()).await;
+        Ok(())
+    })
+}
+
+fn inject<'a>(_tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let (enter, words) = match args {
+            [flag, words @ ..] if flag == "-n" => (true, words),
+            words => (false, words),
+        };
+        if words.is_empty() && !enter {
+            return Err("usage: inject [-n] TEXT".into());
+        }
+        let mut text = words.join(" ");
+        if enter {
+            text.push('\n');
+        }
+        if !keyboard::inject_str(&text) {
+            return Err("some characters have no key on a US keyboard, skipped".into());
+        }
+        Ok(())
+    })
+}
+
+fn sendkey<'a>(_tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        if args.is_empty() {
+            return Err("usage: sendkey SCANCODE...".into());
+        }
+        // All parsed first, a typo doesn't leave half a key sequence typed
+        let scancodes = args
+            .iter()
+            .map(|arg| {
+                let scancode = match arg.strip_prefix("0x") {
+                    Some(hex) => u8::from_str_radix(hex, 16),
+                    None => arg.parse(),
+                };
+                scancode.map_err(|_| format!("not a scancode: {}", arg))
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+        scancodes.into_iter().for_each(keyboard::inject);
+        Ok(())
+    })
+}
+
+fn ps<'a>(tty: &'a mut Tty, _args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let mut text = format!(
+            "{:>6}  {:<8} {:>10} {:>10} {:>10}  NAME\n",
+            "ID", "PRIORITY", "POLLS", "WAKES", "HEAP"
+        );
+        for task in executor::tasks() {
+            // Padding only applies to strings, not to Debug output
+            let priority = format!("{:?}", task.priority);
+            let heap = task
+                .memory
+                .map_or("-".into(), |memory| memory.in_use().to_string());
+            let name = task.name.as_deref().unwrap_or("-");
+            text.push_str(&format!(
+                "{:>6}  {:<8} {:>10} {:>10} {:>10}  {}\n",
+                task.id, priority, task.polls, task.wakes, heap, name
+            ));
+        }
+        tty.write_str(&text).await;
+        Ok(())
+    })
+}
+
+fn prof<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let seconds: u64 = match args {
+            [] => 1,
+            [seconds] => seconds
+                .parse()
+                .map_err(|_| format!("not a number of seconds: {}", seconds))?,
+            _ => return Err("usage: prof [SECONDS]".into()),
+        };
+        let profile = profiler::profile(Duration::from_secs(seconds))
+            .await
+            .ok_or("the profiler is already running")?;
+        tty.write_str(&profile.to_string()).await;
+        Ok(())
+    })
+}
+
+fn trace<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let count = match args.first().map(String::as_str) {
+            None => 50,
+            Some("on") => {
+                trace::enable();
+                return Ok(());
+            }
+            Some("off") => {
+                trace::disable();
+                return Ok(());
+            }
+            Some("clear") => {
+                trace::clear();
+                return Ok(());
+            }
+            Some(count) => count
+                .parse()
+                .map_err(|_| format!("unknown argument: {}", count))?,
+        };
+        if !trace::is_enabled() {
+            tty.write_str("tracing is off, trace on starts it\n").await;
+        }
+        let mut text = String::new();
+        // Writing to a String can't fail
+        let _ = trace::write_recent(&mut text, count);
+        tty.write_str(&text).await;
+        Ok(())
+    })
+}
+
+fn kill<'a>(_tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let [id] = args else {
+            return Err("usage: kill ID".into());
+        };
+        let id = parse_task_id(id)?;
+        if !executor::abort(id) {
+            return Err(format!("no task {}", id));
+        }
+        Ok(())
+    })
+}
+
+fn status<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        if args.is_empty() {
+            return Err("usage: status ID...".into());
+        }
+        let ids = args
+            .iter()
+            .map(|id| parse_task_id(id))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut text = String::new();
+        for id in ids {
+            let status = executor::status(id).map_or("unknown".into(), |status| {
+                format!("{:?}", status).to_lowercase()
+            });
+            text.push_str(&format!("{:>6}  {}\n", id, status));
+        }
+        tty.write_str(&text).await;
+        Ok(())
+    })
+}
+
+fn parse_task_id(id: &str) -> Result<TaskId, String> {
+    id.parse()
+        .map(TaskId::from_u64)
+        .map_err(|_| format!("not a task id: {}", id))
+}
+
+fn demo<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        match args {
+            [list] if list == "list" => {
+                let mut text = String::new();
+                for demo in demo::demos() {
+                    text.push_str(&format!("  {:<12} {}\n", demo.name, demo.description));
+                }
+                tty.write_str(&text).await;
+            }
+            [run, name] if run == "run" => {
+                let id = demo::run(name).map_err(|err| format!("{}: {}", name, err))?;
+                tty.write_str(&format!(
+                    "{} started as task {}, `kill {}` stops it\n",
+                    name, id, id
+                ))
+                .await;
+            }
+            _ => return Err("usage: demo list | demo run NAME".into()),
+        }
+        Ok(())
+    })
+}
+
+fn env<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let vars = env::vars();
+        let mut text = String::new();
+        for (name, value) in &vars {
+            if args.is_empty() || args.contains(name) {
+                text.push_str(&format!("{}={}\n", name, value));
+            }
+        }
+        tty.write_str(&text).await;
+        Ok(())
+    })
+}
+
+fn export<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        if args.is_empty() {
+            return env(tty, args).await;
+        }
+        let vars = args
+            .iter()
+            .map(|arg| {
+                arg.split_once('=')
+                    .filter(|(name, _)| !name.is_empty())
+                    .ok_or(format!("{}: not NAME=VALUE", arg))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        for (name, value) in vars {
+            env::set(name, value);
+        }
+        Ok(())
+    })
+}
+
+fn unset<'a>(_tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        if args.is_empty() {
+            return Err("usage: unset NAME...".into());
+        }
+        for name in args {
+            env::remove(name);
+        }
+        Ok(())
+    })
+}
+
+fn shutdown<'a>(tty: &'a mut Tty, _args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        tty.write_str("shutting down\n").await;
+        shutdown::shutdown().await;
+        Ok(())
+    })
+}
+
+fn poweroff<'a>(tty: &'a mut Tty, _args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        tty.write_str("powering off\n").await;
+        power::shutdown().await;
+        Ok(())
+    })
+}
+
+fn reboot<'a>(tty: &'a mut Tty, _args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        tty.write_str("rebooting\n").await;
+        power::reboot().await;
+        Ok(())
+    })
+}
+
+fn uptime<'a>(tty: &'a mut Tty, _args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let uptime = format_duration(time::uptime());
+        tty.write_str(&format!("up {}\n", uptime)).await;
+        Ok(())
+    })
+}
+
+fn date<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let now = time::SystemTime::now();
+        match args {
+            [] => tty.write_str(&format!("{}\n", now)).await,
+            [flag] if flag == "--rfc3339" => {
+                tty.write_str(&format!("{}\n", now.date_time().rfc3339()))
+                    .await
+            }
+            [set, date_time] if set == "set" => {
+                let date_time: time::DateTime = date_time
+                    .parse()
+                    .map_err(|error| format!("{}: {}", date_time, error))?;
+                time::set_system_time(date_time.into());
+                tty.write_str(&format!("{}\n", date_time)).await;
+            }
+            _ => return Err("usage: date [--rfc3339 | set DATETIME]".into()),
+        }
+        Ok(())
+    })
+}
+
+fn chvt<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let usage = || format!("usage: chvt [1-{}]", console::vt::COUNT);
+        match args {
+            [] => {
+                tty.write_str(&format!("{}\n", console::vt::active() + 1))
+                    .await
+            }
+            [number] => match number.parse::<usize>() {
+                Ok(vt @ 1..=console::vt::COUNT) => console::vt::switch(vt - 1).await,
+                _ => return Err(usage()),
+            },
+            _ => return Err(usage()),
+        }
+        Ok(())
+    })
+}
+
+fn beep<'a>(_tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let frequency = match args.first() {
+            Some(hz) => hz.parse().map_err(|_| format!("not a frequency: {}", hz))?,
+            None => 880,
+        };
+        let millis = match args.get(1) {
+            Some(ms) => ms.parse().map_err(|_| format!("not a duration: {}", ms))?,
+            None => 200,
+        };
+        speaker::beep(frequency, Duration::from_millis(millis)).await;
+        Ok(())
+    })
+}
+
+fn mem<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let stats = memory::stats().ok_or("no heap statistics, the allocator isn't counting")?;
+        match args.first().map(String::as_str) {
+            None => {}
+            Some("reset") => {
+                memory::reset_peak();
+                return Ok(());
+            }
+            Some(other) => return Err(format!("unknown argument: {}", other)),
+        }
+        let mut text = format!(
+            "in use: {} KiB\npeak:   {} KiB\nallocations: {}, frees: {}, failed: {}\n",
+            stats.in_use / 1024,
+            stats.peak / 1024,
+            stats.allocations,
+            stats.frees,
+            stats.failures
+        );
+        text.push_str(&format!(
+            "allocated: {} KiB in total\nsizes:\n",
+            stats.allocated_bytes / 1024
+        ));
+        let mut counted = 0;
+        for (bound, count) in memory::size_histogram().unwrap_or_default() {
+            text.push_str(&format!("  <= {:>8} B  {}\n", bound, count - counted));
+            counted = count;
+        }
+        let largest = memory::SIZE_BUCKETS[memory::SIZE_BUCKETS.len() - 1];
+        let above = (stats.allocations as u64).saturating_sub(counted);
+        text.push_str(&format!("  >  {:>8} B  {}\n", largest, above));
+        tty.write_str(&text).await;
+        Ok(())
+    })
+}
+
+fn metrics<'a>(tty: &'a mut Tty, _args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        tty.write_str(&metrics::prometheus()).await;
+        Ok(())
+    })
+}
+
+/// Paths typed without a leading `/` start at the root, there's no
+/// working directory
+fn absolute(path: &str) -> Result<String, String> {
+    fs::path::join("/", path).map_err(|err| format!("{}: {}", path, err))
+}
+
+fn ls<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let path = absolute(args.first().map_or("/", String::as_str))?;
+        let entries = fs::read_dir(&path)
+            .await
+            .map_err(|err| format!("{}: {}", path, err))?;
+        let mut text = String::new();
+        for entry in entries {
+            let suffix = if entry.file_type == FileType::Directory {
+                "/"
+            } else {
+                ""
+            };
+            text.push_str(&format!("{}{}\n", entry.name, suffix));
+        }
+        tty.write_str(&text).await;
+        Ok(())
+    })
+}
+
+fn cat<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        if args.is_empty() {
+            return Err("usage: cat PATH...".into());
+        }
+        for arg in args {
+            let path = absolute(arg)?;
+            let contents = fs::read(&path)
+                .await
+                .map_err(|err| format!("{}: {}", path, err))?;
+            tty.write(&contents).await;
+        }
+        Ok(())
+    })
+}
+
+fn tee<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let [arg] = args else {
+            return Err("usage: tee PATH".into());
+        };
+        let path = absolute(arg)?;
+        let failed = |err: fs::Error| format!("{}: {}", path, err);
+        let mut file = fs::create(&path).await.map_err(failed)?;
+        let mut buf = [0; io::DEFAULT_BUF_SIZE];
+        loop {
+            let count = tty.read(&mut buf).await.map_err(|err| err.to_string())?;
+            if count == 0 {
+                break;
+            }
+            let mut rest = &buf[..count];
+            while !rest.is_empty() {
+                let written = file.write(rest).await.map_err(failed)?;
+                if written == 0 {
+                    return Err(failed(fs::Error::Io(io::Error::WriteZero)));
+                }
+                rest = &rest[written..];
+            }
+            tty.write(&buf[..count]).await;
+        }
+        file.flush().await.map_err(failed)
+    })
+}
+
+fn mkdir<'a>(_tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let [arg] = args else {
+            return Err("usage: mkdir PATH".into());
+        };
+        let path = absolute(arg)?;
+        fs::create_dir(&path)
+            .await
+            .map_err(|err| format!("{}: {}", path, err))
+    })
+}
+
+fn rm<'a>(_tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let [arg] = args else {
+            return Err("usage: rm PATH".into());
+        };
+        let path = absolute(arg)?;
+        fs::remove(&path)
+            .await
+            .map_err(|err| format!("{}: {}", path, err))
+    })
+}
+
+fn mount<'a>(tty: &'a mut Tty, _args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let mut text = String::new();
+        for (path, name) in fs::mounts() {
+            text.push_str(&format!("{:<16} {}\n", path, name));
+        }
+        tty.write_str(&text).await;
+        Ok(())
+    })
+}
+
+fn logs<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let usage = || String::from("usage: logs [-b [BOOT]]");
+        // Relative to the last boot in the journal, like journalctl's
+        let offset = match args {
+            [] => None,
+            [flag] if flag == "-b" => Some(0),
+            [flag, offset] if flag == "-b" => match offset.parse::<i64>() {
+                Ok(offset @ ..=0) => Some(offset),
+                _ => return Err(usage()),
+            },
+            _ => return Err(usage()),
+        };
+        let entries = log::read_journal(log::JOURNAL_PATH)
+            .await
+            .map_err(|err| format!("{}: {}", log::JOURNAL_PATH, err))?;
+        let last = entries.last().map_or(0, |entry| entry.boot);
+        let boot = offset.map(|offset| i64::from(last) + offset);
+        let mut text = String::new();
+        let mut shown = None;
+        for entry in &entries {
+            if boot.is_some_and(|boot| boot != i64::from(entry.boot)) {
+                continue;
+            }
+            if shown != Some(entry.boot) {
+                text.push_str(&format!("-- boot {} --\n", entry.boot));
+                shown = Some(entry.boot);
+            }
+            text.push_str(&format!("{}\n", entry));
+        }
+        tty.write_str(&text).await;
+        Ok(())
+    })
+}
+
+fn drivers<'a>(tty: &'a mut Tty, _args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let mut text = String::new();
+        for driver in drivers::drivers() {
+            text.push_str(&format!("{:<16} {}\n", driver.name, driver.state));
+        }
+        tty.write_str(&text).await;
+        Ok(())
+    })
+}
+
+fn ping<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let (address, count) = match args {
+            [address] => (address, "4"),
+            [address, count] => (address, count.as_str()),
+            _ => return Err("usage: ping ADDRESS [COUNT]".into()),
+        };
+        let to: IpAddress = address
+            .parse()
+            .map_err(|_| format!("not an address: {}", address))?;
+        let count: u16 = count
+            .parse()
+            .map_err(|_| format!("not a count: {}", count))?;
+
+        let pinger = Pinger::new(to).map_err(|err| err.to_string())?;
+        let mut stats = PingStats::default();
+        let mut interval = timer::interval(net::PING_INTERVAL);
+        for seq_no in 0..count {
+            if seq_no > 0 {
+                interval.tick().await;
+            }
+            let rtt = pinger
+                .echo(seq_no, PING_TIMEOUT)
+                .await
+                .map_err(|err| err.to_string())?;
+            let line = match rtt {
+                Some(rtt) => format!(
+                    "reply from {}: seq={} time={:.3} ms\n",
+                    to,
+                    seq_no,
+                    rtt.as_secs_f64() * 1000.0
+                ),
+                None => format!("no reply from {}: seq={}\n", to, seq_no),
+            };
+            tty.write_str(&line).await;
+            stats.record(rtt);
+        }
+        tty.write_str(&format!("{}\n", stats)).await;
+        Ok(())
+    })
+}
+
+fn fetch<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let [url] = args else {
+            return Err("usage: fetch URL".into());
+        };
+        let response = http::get(url).await.map_err(|err| err.to_string())?;
+        if response.status != 200 {
+            tty.write_str(&format!("{} {}\n", response.status, response.reason))
+                .await;
+        }
+        tty.write_str(&String::from_utf8_lossy(&response.body))
+            .await;
+        Ok(())
+    })
+}
+
+fn arp<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let parse_ip = |ip: &str| {
+            ip.parse::<Ipv4Address>()
+                .map_err(|_| format!("not an address: {}", ip))
+        };
+        match args {
+            [] => {}
+            [flag, ip, mac] if flag == "-s" => {
+                let mac: EthernetAddress = mac
+                    .parse()
+                    .map_err(|_| format!("not a MAC address: {}", mac))?;
+                net::add_static_neighbor(parse_ip(ip)?, mac);
+                return Ok(());
+            }
+            [flag, ip] if flag == "-d" => {
+                return match net::remove_neighbor(parse_ip(ip)?) {
+                    true => Ok(()),
+                    false => Err(format!("no entry for {}", ip)),
+                };
+            }
+            [flag] if flag == "-f" => {
+                net::flush_neighbors();
+                return Ok(());
+            }
+            _ => return Err("usage: arp [-s IP MAC | -d IP | -f]".into()),
+        }
+
+        let mut text = format!(
+            "{:<16} {:<18} {:<11} {}\n",
+            "ADDRESS", "MAC", "STATE", "AGE"
+        );
+        for neighbor in net::neighbors() {
+            let mac = match neighbor.mac {
+                Some(mac) => mac.to_string(),
+                None => "-".into(),
+            };
+            let state = match neighbor.state {
+                NeighborState::Reachable => "reachable",
+                NeighborState::Static => "static",
+                NeighborState::Incomplete => "incomplete",
+            };
+            let age = match neighbor.age {
+                Some(age) => format_duration(age),
+                None => "-".into(),
+            };
+            text.push_str(&format!(
+                "{:<16} {:<18} {:<11} {}\n",
+                neighbor.address.to_string(),
+                mac,
+                state,
+                age
+            ));
+        }
+        tty.write_str(&text).await;
+        Ok(())
+    })
+}
+
+/// `1d 2h 3m 4s`, leaving out leading zero units
+pub(super) fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (days, hours, minutes) = (seconds / 86_400, seconds / 3600 % 24, seconds / 60 % 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{}s", seconds % 60),
+        (0, 0, _) => format!("{}m {}s", minutes, seconds % 60),
+        (0, _, _) => format!("{}h {}m {}s", hours, minutes, seconds % 60),
+        _ => format!("{}d {}h {}m {}s", days, hours, minutes, seconds % 60),
+    }
+}
@@ -0,0 +1,340 @@
+//! Command lines running as tasks of their own, in the foreground or not
+//!
+//! The shell spawns every command line as a job with a [`Tty`] of its own,
+//! writing to the shell's output and reading from a pipe. Waiting on a job
+//! in the foreground passes it what's typed and the signals raised: Ctrl+C
+//! goes to the job, Ctrl+Z too if it takes its signals and otherwise stops
+//! it until `fg` or `bg`.
+
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll, Waker},
+};
+
+use futures_util::task::AtomicWaker;
+use pin_project_lite::pin_project;
+
+use super::{Handler, run_job};
+use crate::{
+    Task, TaskId,
+    channel::oneshot,
+    executor,
+    future::{Either, race},
+    io::{AsyncWrite, AsyncWriteExt},
+    pipe::{PipeWriter, pipe},
+    signal::{Foreground, Signal},
+    tty::{Tty, TtyMode},
+};
+
+/// The shell's job control commands, with their help
+pub(super) const BUILTINS: [(&str, &str); 3] = [
+    ("jobs", "List the shell's jobs"),
+    ("fg", "Wait on a job, resuming it if stopped: fg [%JOB]"),
+    ("bg", "Resume a stopped job in the background: bg [%JOB]"),
+];
+
+/// Bytes typed ahead for a job that isn't reading them yet
+const INPUT_CAPACITY: usize = super::PIPE_CAPACITY;
+
+/// The jobs of one shell, by number
+pub(super) struct Jobs {
+    jobs: BTreeMap<usize, Job>,
+}
+
+struct Job {
+    /// The command line, without the `&`
+    line: String,
+    id: TaskId,
+    /// Dropped when the shell's input ends
+    input: Option<PipeWriter>,
+    /// The job's own terminal's, for relaying signals
+    foreground: Foreground,
+    pause: Arc<Pause>,
+    /// `true` if the command line finished, `false` if Ctrl+C dropped it
+    done: oneshot::Receiver<bool>,
+}
+
+impl Job {
+    fn state(&self) -> &'static str {
+        if self.done.is_closed() {
+            "Done"
+        } else if self.pause.is_stopped() {
+            "Stopped"
+        } else {
+            "Running"
+        }
+    }
+
+    /// Hand `byte` to the job, dropped if it has too many unread already
+    fn send(&mut self, byte: u8) {
+        if let Some(input) = &mut self.input {
+            let mut cx = Context::from_waker(Waker::noop());
+            let _ = Pin::new(input).poll_write(&mut cx, &[byte]);
+        }
+    }
+}
+
+/// How waiting on a job in the foreground ended
+enum Waited {
+    Finished,
+    Interrupted,
+    Stopped,
+}
+
+impl Jobs {
+    pub(super) fn new() -> Self {
+        Jobs {
+            jobs: BTreeMap::new(),
+        }
+    }
+
+    /// Spawn `stages` as a job reading from and writing to `tty`, its
+    /// number comes back
+    pub(super) fn start(
+        &mut self,
+        tty: &Tty,
+        line: &str,
+        stages: Vec<(String, Handler, Vec<String>)>,
+    ) -> usize {
+        let (reader, writer) = pipe(INPUT_CAPACITY);
+        let mut job_tty = tty.with_input(Box::pin(reader));
+        let foreground = job_tty.foreground().clone();
+        let pause = Arc::new(Pause::default());
+        let (sender, done) = oneshot::channel();
+        let job = {
+            let foreground = foreground.clone();
+            async move {
+                let finished = foreground.run(run_job(&mut job_tty, stages)).await;
+                // Echo and output still queued
+                let _ = job_tty.flush().await;
+                let _ = sender.send(finished.is_some());
+            }
+        };
+        let id = executor::spawn(Task::new(Pausable {
+            future: job,
+            pause: pause.clone(),
+        }));
+        let number = (1..).find(|number| !self.jobs.contains_key(number));
+        let number = number.expect("a free job number");
+        self.jobs.insert(
+            number,
+            Job {
+                line: line.into(),
+                id,
+                input: Some(writer),
+                foreground,
+                pause,
+                done,
+            },
+        );
+        number
+    }
+
+    pub(super) fn id(&self, number: usize) -> Option<TaskId> {
+        self.jobs.get(&number).map(|job| job.id)
+    }
+
+    /// Forget the jobs that ended in the background, with a line about each
+    pub(super) fn reap(&mut self) -> String {
+        let mut report = String::new();
+        self.jobs.retain(|number, job| {
+            if !job.done.is_closed() {
+                return true;
+            }
+            report.push_str(&format!("[{}]  Done     {}\n", number, job.line));
+            false
+        });
+        report
+    }
+
+    /// Run `jobs`, `fg` or `bg`, `None` if `words` is another command
+    pub(super) async fn run_builtin(
+        &mut self,
+        tty: &mut Tty,
+        words: &[String],
+    ) -> Option<Result<(), String>> {
+        let result = match words[0].as_str() {
+            "jobs" => {
+                let mut text = String::new();
+                for (number, job) in &self.jobs {
+                    text.push_str(&format!("[{}]  {:<8} {}\n", number, job.state(), job.line));
+                }
+                tty.write_str(&text).await;
+                Ok(())
+            }
+            "fg" => match self.find(words, |_| true) {
+                Ok(number) => {
+                    let job = &self.jobs[&number];
+                    tty.write_str(&format!("{}\n", job.line)).await;
+                    job.pause.resume();
+                    self.wait(tty, number).await;
+                    Ok(())
+                }
+                Err(message) => Err(message),
+            },
+            "bg" => match self.find(words, |job| job.pause.is_stopped()) {
+                Ok(number) => {
+                    let job = &self.jobs[&number];
+                    if !job.pause.is_stopped() {
+                        return Some(Err(format!("job {} is already running", number)));
+                    }
+                    job.pause.resume();
+                    tty.write_str(&format!("[{}]  {} &\n", number, job.line))
+                        .await;
+                    Ok(())
+                }
+                Err(message) => Err(message),
+            },
+            _ => return None,
+        };
+        Some(result)
+    }
+
+    /// The job a command's argument names as `%N` or `N`, else the last
+    /// one `fits`
+    fn find(&self, words: &[String], fits: impl Fn(&Job) -> bool) -> Result<usize, String> {
+        match &words[1..] {
+            [] => self
+                .jobs
+                .iter()
+                .rev()
+                .find(|(_, job)| fits(job))
+                .map(|(&number, _)| number)
+                .ok_or_else(|| "no such job".into()),
+            [arg] => {
+                let number = arg.strip_prefix('%').unwrap_or(arg);
+                number
+                    .parse()
+                    .ok()
+                    .filter(|number| self.jobs.contains_key(number))
+                    .ok_or_else(|| format!("{}: no such job", arg))
+            }
+            _ => Err(format!("usage: {} [%JOB]", words[0])),
+        }
+    }
+
+    /// Have job `number` in the foreground until it finishes or is stopped
+    pub(super) async fn wait(&mut self, tty: &mut Tty, number: usize) {
+        let Some(job) = self.jobs.get_mut(&number) else {
+            return;
+        };
+        // Every byte as typed, the job's terminal does the editing and echo
+        let mode = tty.mode();
+        tty.set_mode(TtyMode {
+            echo: false,
+            canonical: false,
+            ..mode
+        });
+        let foreground = tty.foreground().clone();
+        let waited = foreground.run(relay(tty, job)).await;
+        tty.set_mode(mode);
+        match waited.expect("the relay takes its signals") {
+            Waited::Finished => {
+                self.jobs.remove(&number);
+            }
+            Waited::Interrupted => {
+                self.jobs.remove(&number);
+                tty.write_str("^C\n").await;
+            }
+            Waited::Stopped => {
+                let line = &self.jobs[&number].line;
+                tty.write_str(&format!("^Z\n[{}]  Stopped  {}\n", number, line))
+                    .await;
+            }
+        }
+    }
+
+    /// Drop every job, when the shell leaves
+    pub(super) fn abort_all(&mut self) {
+        for (_, job) in std::mem::take(&mut self.jobs) {
+            executor::abort(job.id);
+        }
+    }
+}
+
+/// Pass what's typed on `tty` and the signals raised there to `job` until
+/// it ends or is stopped
+async fn relay(tty: &mut Tty, job: &mut Job) -> Waited {
+    let mut signals = tty.signals().expect("run in the foreground");
+    loop {
+        let open = job.input.is_some();
+        let typed = async {
+            if open {
+                tty.read_byte().await
+            } else {
+                std::future::pending().await
+            }
+        };
+        match race(&mut job.done, race(signals.recv(), typed)).await {
+            Either::Left(Some(true)) => return Waited::Finished,
+            Either::Left(_) => return Waited::Interrupted,
+            Either::Right(Either::Left(Some(Signal::Interrupt))) => {
+                job.foreground.raise(Signal::Interrupt);
+            }
+            Either::Right(Either::Left(Some(Signal::Suspend))) if job.foreground.is_handled() => {
+                job.foreground.raise(Signal::Suspend);
+            }
+            // Stopped by Ctrl+Z, the signals only end once out of the
+            // foreground
+            Either::Right(Either::Left(_)) => {
+                job.pause.stop();
+                return Waited::Stopped;
+            }
+            Either::Right(Either::Right(Some(byte))) => job.send(byte),
+            // End of the job's input too
+            Either::Right(Either::Right(None)) => job.input = None,
+        }
+    }
+}
+
+/// Stops a job's task from being polled, and lets it go again
+#[derive(Default)]
+struct Pause {
+    stopped: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl Pause {
+    fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+
+    fn stop(&self) {
+        self.stopped.store(true, Ordering::Release);
+    }
+
+    /// Polls the job again, whatever woke it while stopped
+    fn resume(&self) {
+        self.stopped.store(false, Ordering::Release);
+        self.waker.wake();
+    }
+}
+
+pin_project! {
+    // A job's future, not polled while it's stopped
+    struct Pausable<F> {
+        #[pin]
+        future: F,
+        pause: Arc<Pause>,
+    }
+}
+
+impl<F: Future> Future for Pausable<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<F::Output> {
+        let this = self.project();
+        this.pause.waker.register(cx.waker());
+        // Checked after registering, so a resume in between still wakes
+        if this.pause.is_stopped() {
+            return Poll::Pending;
+        }
+        this.future.poll(cx)
+    }
+}
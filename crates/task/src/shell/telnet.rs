@@ -0,0 +1,131 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::{Stream, StreamExt, ready};
+
+use super::run_shell;
+use crate::{
+    future::{Either, race},
+    io::AsyncWriteExt,
+    join_set::JoinSet,
+    log,
+    net::{self, IpEndpoint, IpListenEndpoint, ListenOptions, TcpListener, TcpReader, TcpStream},
+    tty::{Tty, TtyMode},
+};
+
+/// Interpret As Command, starts every telnet command
+const IAC: u8 = 255;
+const SB: u8 = 250;
+const SE: u8 = 240;
+const WILL: u8 = 251;
+const DONT: u8 = 254;
+const ECHO: u8 = 1;
+const SUPPRESS_GO_AHEAD: u8 = 3;
+
+/// The server echoes and the client sends every key as it's typed, so the
+/// line editor works as on the console
+const GREETING: [u8; 6] = [IAC, WILL, ECHO, IAC, WILL, SUPPRESS_GO_AHEAD];
+
+/// Sessions carry keystrokes and a screenful of output at a time, they
+/// don't need the default buffers
+const LISTEN_OPTIONS: ListenOptions = ListenOptions {
+    receive_buffer: 1024,
+    send_buffer: 4 * 1024,
+    ..ListenOptions::DEFAULT
+};
+
+/// Run a [`run_shell`] session for every telnet client connecting to
+/// `endpoint`, until the listener fails. The sessions are driven by the
+/// calling task.
+///
+/// There's no login, anyone who can connect gets a shell.
+pub async fn serve_telnet(endpoint: impl Into<IpListenEndpoint>) -> net::Result<()> {
+    let listener = TcpListener::bind_with(endpoint, LISTEN_OPTIONS)?;
+    let mut sessions = JoinSet::new();
+    loop {
+        let accepted = if sessions.is_empty() {
+            listener.accept().await
+        } else {
+            match race(listener.accept(), sessions.join_next()).await {
+                Either::Left(accepted) => accepted,
+                Either::Right(_) => continue,
+            }
+        };
+        let (stream, peer) = accepted?;
+        sessions.spawn(session(stream, peer));
+    }
+}
+
+async fn session(mut stream: TcpStream, peer: IpEndpoint) {
+    log::info!("telnet: {} connected", peer);
+    if stream.write_all(&GREETING).await.is_err() {
+        return;
+    }
+    let (reader, writer) = stream.split();
+    let input = TelnetInput {
+        reader,
+        state: State::Data,
+    };
+    let mut tty = Tty::new(Box::pin(input), writer);
+    tty.set_mode(TtyMode {
+        crlf: true,
+        ..TtyMode::COOKED
+    });
+    run_shell(tty).await;
+    log::info!("telnet: {} disconnected", peer);
+}
+
+/// Where [`TelnetInput`] is in the byte stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Data,
+    /// After a `\r`, which telnet follows with `\n` or NUL
+    Return,
+    /// After an [`IAC`]
+    Command,
+    /// After `WILL`, `WONT`, `DO` or `DONT`, the option comes next
+    Option,
+    /// Inside a subnegotiation, up to `IAC SE`
+    Sub,
+    SubCommand,
+}
+
+/// Bytes a client typed, without the telnet commands. Option requests
+/// aren't answered, [`GREETING`] is all the negotiation there is.
+struct TelnetInput {
+    reader: TcpReader,
+    state: State,
+}
+
+impl Stream for TelnetInput {
+    type Item = u8;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<u8>> {
+        let this = &mut *self;
+        loop {
+            let Some(byte) = ready!(this.reader.poll_next_unpin(cx)) else {
+                return Poll::Ready(None);
+            };
+            let (state, data) = match (this.state, byte) {
+                (State::Data | State::Return, IAC) => (State::Command, None),
+                (State::Return, b'\n' | 0) => (State::Data, None),
+                (State::Data | State::Return, b'\r') => (State::Return, Some(byte)),
+                (State::Data | State::Return, _) => (State::Data, Some(byte)),
+                // Doubled, a literal 255
+                (State::Command, IAC) => (State::Data, Some(IAC)),
+                (State::Command, WILL..=DONT) => (State::Option, None),
+                (State::Command, SB) => (State::Sub, None),
+                (State::Command | State::Option, _) => (State::Data, None),
+                (State::Sub, IAC) => (State::SubCommand, None),
+                (State::SubCommand, SE) => (State::Data, None),
+                (State::Sub | State::SubCommand, _) => (State::Sub, None),
+            };
+            this.state = state;
+            if let Some(byte) = data {
+                return Poll::Ready(Some(byte));
+            }
+        }
+    }
+}
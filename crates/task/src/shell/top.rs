@@ -0,0 +1,123 @@
+use std::{collections::BTreeMap, fmt::Write as _, time::Duration};
+
+use super::{CommandFuture, Tty, builtins::format_duration};
+use crate::{
+    TaskId,
+    executor::{self, TaskState},
+    future::{Either, race},
+    profiler::{self, Profile},
+    time, timer,
+    tty::TtyMode,
+};
+
+/// Time between screens
+const REFRESH: Duration = Duration::from_secs(1);
+
+const CLEAR_SCREEN: &str = "\x1b[H\x1b[2J";
+
+/// Tasks refreshed every second with their CPU share from the
+/// [`profiler`], polls per second and wakes, until `q` or Ctrl+C, which
+/// drops it
+pub(super) fn top<'a>(tty: &'a mut Tty, args: &'a [String]) -> CommandFuture<'a> {
+    Box::pin(async move {
+        let screens: Option<u64> = match args {
+            [] => None,
+            [count] => Some(
+                count
+                    .parse()
+                    .map_err(|_| format!("not a number of screens: {}", count))?,
+            ),
+            _ => return Err("usage: top [SCREENS]".into()),
+        };
+        let mode = tty.mode();
+        tty.set_mode(TtyMode {
+            echo: false,
+            canonical: false,
+            ..mode
+        });
+        let mut polls: BTreeMap<TaskId, u64> = executor::tasks()
+            .into_iter()
+            .map(|task| (task.id, task.polls))
+            .collect();
+        let mut shown = 0;
+        loop {
+            // Someone else's `prof` has the profiler, go without CPU shares
+            let profiling = profiler::start();
+            let key = race(tty.read_byte(), timer::sleep(REFRESH)).await;
+            let profile = profiling.then(profiler::stop);
+            if let Either::Left(key) = key
+                && matches!(key, None | Some(b'q' | b'Q'))
+            {
+                break;
+            }
+            tty.write_str(&screen(profile.as_ref(), &mut polls)).await;
+            shown += 1;
+            if screens.is_some_and(|screens| shown >= screens) {
+                break;
+            }
+        }
+        tty.set_mode(mode);
+        Ok(())
+    })
+}
+
+fn screen(profile: Option<&Profile>, previous_polls: &mut BTreeMap<TaskId, u64>) -> String {
+    let tasks = executor::tasks();
+    let ready = tasks
+        .iter()
+        .filter(|task| task.state == TaskState::Ready)
+        .count();
+    let mut text = String::from(CLEAR_SCREEN);
+    let _ = write!(
+        text,
+        "up {}, {} tasks, {} ready",
+        format_duration(time::uptime()),
+        tasks.len(),
+        ready
+    );
+    let total = profile.map_or(0, Profile::total).max(1) as f64;
+    if let Some(profile) = profile {
+        let _ = write!(
+            text,
+            ", {:.1}% in tasks",
+            100.0 - profile.executor as f64 * 100.0 / total
+        );
+    }
+    text.push_str("  (q quits)\n\n");
+    let _ = writeln!(
+        text,
+        "{:>6}  {:<8} {:<8} {:>6} {:>8} {:>10}  NAME",
+        "ID", "STATE", "PRIORITY", "CPU%", "POLLS/S", "WAKES"
+    );
+
+    let mut polls = BTreeMap::new();
+    for task in &tasks {
+        let cpu = profile.map_or("-".into(), |profile| {
+            let samples = profile
+                .tasks
+                .iter()
+                .find(|sampled| sampled.id == task.id)
+                .map_or(0, |sampled| sampled.samples);
+            format!("{:.1}", samples as f64 * 100.0 / total)
+        });
+        let since = previous_polls.get(&task.id).copied().unwrap_or(0);
+        let per_second = task.polls.saturating_sub(since) as f64 / REFRESH.as_secs_f64();
+        polls.insert(task.id, task.polls);
+        // Padding only applies to strings, not to Debug output
+        let state = format!("{:?}", task.state);
+        let priority = format!("{:?}", task.priority);
+        let _ = writeln!(
+            text,
+            "{:>6}  {:<8} {:<8} {:>6} {:>8.0} {:>10}  {}",
+            task.id.as_u64(),
+            state,
+            priority,
+            cpu,
+            per_second,
+            task.wakes,
+            task.name.as_deref().unwrap_or("-")
+        );
+    }
+    *previous_polls = polls;
+    text
+}
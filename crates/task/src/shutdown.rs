@@ -0,0 +1,119 @@
+//! Shutting down in order: subscribed tasks are told, the tracked ones get
+//! [`GRACE`] to finish, then the drivers and the executors stop.
+//!
+//! ```ignore
+//! let mut signal = shutdown::subscribe();
+//! executor::spawn(Task::new(shutdown::track(async move {
+//!     while !signal.is_set() {
+//!         match race(signal.recv(), connections.next()).await {
+//!             Either::Left(()) => break,
+//!             Either::Right(connection) => serve(connection).await,
+//!         }
+//!     }
+//!     flush().await;
+//! })));
+//! ```
+//!
+//! Ctrl+Alt+Del and the shell's `shutdown` start one, the
+//! [`power`](crate::power) functions start one and turn the machine off or
+//! restart it at its end.
+
+use core::{future::Future, time::Duration};
+
+use crate::{
+    Task,
+    channel::watch,
+    drivers, event, executor, log, power,
+    sync::{TaskTracker, TrackedFuture},
+    timer,
+};
+
+/// Published on the [`event`](crate::event) bus as a shutdown starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Started;
+
+/// How long tracked tasks have to finish once told
+pub const GRACE: Duration = Duration::from_secs(5);
+
+static SIGNAL: spin::Once<watch::Sender<bool>> = spin::Once::new();
+static TRACKER: spin::Once<TaskTracker> = spin::Once::new();
+
+fn signal() -> &'static watch::Sender<bool> {
+    SIGNAL.call_once(|| watch::channel(false).0)
+}
+
+/// Counts the tasks a shutdown waits for, see [`track`]
+pub fn tracker() -> &'static TaskTracker {
+    TRACKER.call_once(TaskTracker::new)
+}
+
+/// Have shutdowns wait up to [`GRACE`] for `future`
+pub fn track<F: Future>(future: F) -> TrackedFuture<F> {
+    tracker().track_future(future)
+}
+
+/// Hear about the shutdown, started before or after subscribing
+pub fn subscribe() -> Signal {
+    Signal {
+        receiver: signal().subscribe(),
+    }
+}
+
+pub fn is_shutting_down() -> bool {
+    *signal().borrow()
+}
+
+/// Told when a shutdown starts, see [`subscribe`]
+pub struct Signal {
+    receiver: watch::Receiver<bool>,
+}
+
+impl Signal {
+    /// Resolves once the shutdown has started
+    pub async fn recv(&mut self) {
+        while !*self.receiver.borrow_and_update() {
+            // The sender is a static, it doesn't go away
+            let _ = self.receiver.changed().await;
+        }
+    }
+
+    pub fn is_set(&self) -> bool {
+        *self.receiver.borrow()
+    }
+}
+
+/// Tell the subscribers, wait up to [`GRACE`] for the tracked tasks, then
+/// shut the drivers down and [`stop`](executor::stop) the executors. Then
+/// powers off or reboots if [`power`] asked for that, before or during it.
+///
+/// Returns at once if a shutdown is under way already.
+pub async fn shutdown() {
+    if signal().send_replace(true) {
+        return;
+    }
+    log::info!("shutdown: telling tasks");
+    event::publish(Started);
+    let tracker = tracker();
+    tracker.close();
+    if timer::timeout(GRACE, tracker.wait()).await.is_err() {
+        log::warn!(
+            "shutdown: {} tasks still running after {:?}, stopping anyway",
+            tracker.len(),
+            GRACE
+        );
+    }
+    drivers::shutdown();
+    if timer::timeout(GRACE, drivers::stopped()).await.is_err() {
+        log::warn!("shutdown: drivers still running after {:?}", GRACE);
+    }
+    executor::stop();
+    power::finish();
+}
+
+/// Start a [`shutdown`] in a task of its own, for code that can't wait on
+/// it like the keyboard dispatcher
+pub fn request() {
+    if !is_shutting_down() {
+        executor::spawn(Task::new(shutdown()).with_name("shutdown"));
+    }
+}
@@ -0,0 +1,305 @@
+//!
+//! Signals from a terminal to the job in its foreground
+//!
+//! Ctrl+C and Ctrl+Z don't reach the job as input. The shell runs each
+//! command line in the terminal's [`Foreground`], and the two keys raise a
+//! [`Signal`] for it instead: from the keyboard dispatcher on the console,
+//! from the line discipline on other terminals, there as the job reads its
+//! input. A job that doesn't take its
+//! [`Signals`] is dropped on Ctrl+C, one that does decides for itself:
+//!
+//! ```ignore
+//! let mut signals = tty.signals().expect("run by the shell");
+//! loop {
+//!     match race(signals.recv(), copy_block(&mut source, &mut target)).await {
+//!         Either::Left(Some(Signal::Interrupt)) => return Err("interrupted, target left half written".into()),
+//!         Either::Left(_) => {}
+//!         Either::Right(false) => return Ok(()),
+//!         Either::Right(true) => {}
+//!     }
+//! }
+//! ```
+//!
+//! Ctrl+Z does nothing to a job that doesn't take its signals. The shell
+//! stops its jobs on Ctrl+Z itself, until `fg` or `bg`.
+//!
+
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+};
+
+use futures_util::Stream;
+use pin_project_lite::pin_project;
+
+use crate::{console::vt, metrics::Counter};
+
+static RAISED: Counter = Counter::new(
+    "signals_raised_total",
+    "Signals raised for a foreground job",
+);
+
+/// Signals kept for a job that hasn't read them, further ones are dropped
+const PENDING_CAPACITY: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    /// Ctrl+C, stop what you're doing
+    Interrupt,
+    /// Ctrl+Z, pause
+    Suspend,
+}
+
+impl Signal {
+    /// The control character a terminal sends for it
+    pub fn from_byte(byte: u8) -> Option<Signal> {
+        match byte {
+            0x03 => Some(Signal::Interrupt),
+            0x1a => Some(Signal::Suspend),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Signal::Interrupt => f.write_str("^C"),
+            Signal::Suspend => f.write_str("^Z"),
+        }
+    }
+}
+
+/// The job a terminal's signals go to, one per terminal. Clones share it.
+#[derive(Clone, Default)]
+pub struct Foreground {
+    state: Arc<spin::Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    // Jobs in the foreground, the one running last on top. Empty at the
+    // prompt.
+    jobs: Vec<Job>,
+    next_job: u64,
+    // The top job's `InForeground` and the `Signals` waiting, which may be
+    // in another task
+    job_waker: Option<Waker>,
+    signals_waker: Option<Waker>,
+}
+
+struct Job {
+    id: u64,
+    // The job took its `Signals`
+    handled: bool,
+    pending: VecDeque<Signal>,
+}
+
+impl State {
+    fn job(&mut self, id: u64) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.job_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.signals_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+static CONSOLES: spin::Once<[Foreground; vt::COUNT]> = spin::Once::new();
+
+/// The foreground of the keyboard and the [virtual terminal](vt) on screen,
+/// which the keyboard dispatcher raises signals for
+pub fn console() -> &'static Foreground {
+    virtual_terminal(vt::active())
+}
+
+/// The foreground of virtual terminal `vt`. Panics if it isn't below
+/// [`vt::COUNT`].
+pub fn virtual_terminal(vt: usize) -> &'static Foreground {
+    &CONSOLES.call_once(|| core::array::from_fn(|_| Foreground::new()))[vt]
+}
+
+impl Foreground {
+    pub fn new() -> Self {
+        Foreground::default()
+    }
+
+    /// Run `job` in the foreground until it finishes, `None` if Ctrl+C
+    /// dropped it first. A job run inside another has the foreground until
+    /// it's done, then the outer one gets it back.
+    pub fn run<F: Future>(&self, job: F) -> InForeground<F> {
+        InForeground {
+            job,
+            foreground: self.clone(),
+            id: None,
+        }
+    }
+
+    /// Hand `signal` to the foreground job, `false` if there is none and
+    /// the key should be taken as input
+    pub fn raise(&self, signal: Signal) -> bool {
+        let mut state = self.state.lock();
+        let Some(job) = state.jobs.last_mut() else {
+            return false;
+        };
+        RAISED.inc();
+        if job.handled || signal == Signal::Interrupt {
+            if job.pending.len() < PENDING_CAPACITY {
+                job.pending.push_back(signal);
+            }
+            state.wake();
+        }
+        true
+    }
+
+    /// The foreground job's signals, for it to deal with instead of being
+    /// dropped on Ctrl+C. `None` outside a job, or if they were taken
+    /// already.
+    pub fn signals(&self) -> Option<Signals> {
+        let mut state = self.state.lock();
+        let job = state.jobs.last_mut()?;
+        if job.handled {
+            return None;
+        }
+        job.handled = true;
+        Some(Signals {
+            foreground: self.clone(),
+            job: job.id,
+        })
+    }
+
+    /// A job is running
+    pub fn is_busy(&self) -> bool {
+        !self.state.lock().jobs.is_empty()
+    }
+
+    /// The foreground job took its [`signals`](Foreground::signals)
+    pub fn is_handled(&self) -> bool {
+        self.state.lock().jobs.last().is_some_and(|job| job.handled)
+    }
+
+    /// Take job `id` out of the foreground, whichever job had it before
+    /// gets it back
+    fn leave(&self, id: u64) {
+        let mut state = self.state.lock();
+        state.jobs.retain(|job| job.id != id);
+        // Ends the job's `Signals`, or has the outer one's look again
+        state.wake();
+    }
+}
+
+impl fmt::Debug for Foreground {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state.lock();
+        let top = state.jobs.last();
+        f.debug_struct("Foreground")
+            .field("jobs", &state.jobs.len())
+            .field("handled", &top.is_some_and(|job| job.handled))
+            .field("pending", &top.map(|job| &job.pending))
+            .finish()
+    }
+}
+
+pin_project! {
+    /// Future returned by [`Foreground::run`]
+    #[must_use = "futures do nothing unless polled"]
+    pub struct InForeground<F> {
+        #[pin]
+        job: F,
+        foreground: Foreground,
+        // Set by the first poll, the job is in the foreground from then on
+        id: Option<u64>,
+    }
+
+    impl<F> PinnedDrop for InForeground<F> {
+        fn drop(this: Pin<&mut Self>) {
+            let this = this.project();
+            if let Some(id) = this.id.take() {
+                this.foreground.leave(id);
+            }
+        }
+    }
+}
+
+impl<F: Future> Future for InForeground<F> {
+    type Output = Option<F::Output>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<F::Output>> {
+        let this = self.project();
+        let mut state = this.foreground.state.lock();
+        let id = *this.id.get_or_insert_with(|| {
+            let id = state.next_job;
+            state.next_job += 1;
+            state.jobs.push(Job {
+                id,
+                handled: false,
+                pending: VecDeque::new(),
+            });
+            id
+        });
+        let interrupted = state
+            .job(id)
+            .is_some_and(|job| !job.handled && job.pending.contains(&Signal::Interrupt));
+        if !interrupted {
+            state.job_waker = Some(cx.waker().clone());
+        }
+        drop(state);
+
+        let output = if interrupted {
+            None
+        } else {
+            Some(core::task::ready!(this.job.poll(cx)))
+        };
+        // Dropped or done, either way out of the foreground now
+        if let Some(id) = this.id.take() {
+            this.foreground.leave(id);
+        }
+        Poll::Ready(output)
+    }
+}
+
+/// Signals raised for a job, from [`Foreground::signals`]. Ends when the
+/// job leaves the foreground.
+pub struct Signals {
+    foreground: Foreground,
+    job: u64,
+}
+
+impl Signals {
+    pub async fn recv(&mut self) -> Option<Signal> {
+        core::future::poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    pub fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<Signal>> {
+        let mut state = self.foreground.state.lock();
+        let top = state.jobs.last().map(|job| job.id);
+        let Some(job) = state.job(self.job) else {
+            // Finished
+            return Poll::Ready(None);
+        };
+        // Signals go to an inner job meanwhile, none are pending for this one
+        if top == Some(self.job)
+            && let Some(signal) = job.pending.pop_front()
+        {
+            return Poll::Ready(Some(signal));
+        }
+        state.signals_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Stream for Signals {
+    type Item = Signal;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Signal>> {
+        self.poll_recv(cx)
+    }
+}
@@ -0,0 +1,144 @@
+//!
+//! PC speaker
+//!
+//! Tones on whatever [`Speaker`] is [`install`]ed, timed with the
+//! [`timer`]. The PC's own is [`PcSpeaker`](crate::pc::PcSpeaker) on PIT
+//! channel 2, the std build rings the terminal's bell until another one is
+//! installed:
+//!
+//! ```ignore
+//! speaker::install(pc::PcSpeaker);
+//! speaker::beep(440, Duration::from_millis(200)).await;
+//! speaker::play(speaker::ALARM).await;
+//! ```
+//!
+//! Tasks take turns, a sequence plays to the end before the next one
+//! starts. Dropping the future stops the sound.
+//!
+
+use alloc::boxed::Box;
+use core::time::Duration;
+
+use crate::{sync::Mutex, timer};
+
+/// Lowest frequency the PIT's 16-bit divisor gets to
+pub const MIN_FREQUENCY: u32 = 19;
+pub const MAX_FREQUENCY: u32 = 20_000;
+
+/// Something that makes a sound
+pub trait Speaker: Send {
+    /// Sound `frequency` Hz until [`stop`](Speaker::stop)
+    fn start(&mut self, frequency: u32);
+    fn stop(&mut self);
+}
+
+/// The terminal's bell, it has one pitch
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TerminalBell;
+
+#[cfg(feature = "std")]
+impl Speaker for TerminalBell {
+    fn start(&mut self, _frequency: u32) {
+        use std::io::Write;
+        let mut stdout = std::io::stdout();
+        let _ = stdout.write_all(b"\x07");
+        let _ = stdout.flush();
+    }
+
+    fn stop(&mut self) {}
+}
+
+static SPEAKER: spin::Mutex<Option<Box<dyn Speaker>>> = spin::Mutex::new(None);
+// Held while a sequence plays
+static TURN: Mutex<()> = Mutex::new(());
+
+/// Play through `speaker` from now on
+pub fn install(speaker: impl Speaker + 'static) {
+    let mut installed = SPEAKER.lock();
+    if let Some(previous) = installed.as_mut() {
+        previous.stop();
+    }
+    *installed = Some(Box::new(speaker));
+}
+
+fn with_speaker(f: impl FnOnce(&mut dyn Speaker)) {
+    let mut speaker = SPEAKER.lock();
+    #[cfg(feature = "std")]
+    let speaker = speaker.get_or_insert_with(|| Box::new(TerminalBell));
+    #[cfg(not(feature = "std"))]
+    let Some(speaker) = speaker.as_mut() else {
+        return;
+    };
+    f(&mut **speaker);
+}
+
+/// A note, or a rest with no frequency
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tone {
+    /// In Hz, `0` for silence
+    pub frequency: u32,
+    pub duration: Duration,
+}
+
+impl Tone {
+    pub const fn new(frequency: u32, duration: Duration) -> Tone {
+        Tone {
+            frequency,
+            duration,
+        }
+    }
+
+    pub const fn rest(duration: Duration) -> Tone {
+        Tone::new(0, duration)
+    }
+}
+
+const fn ms(millis: u64) -> Duration {
+    Duration::from_millis(millis)
+}
+
+/// Three quick high beeps
+pub const ALARM: &[Tone] = &[
+    Tone::new(1760, ms(120)),
+    Tone::rest(ms(80)),
+    Tone::new(1760, ms(120)),
+    Tone::rest(ms(80)),
+    Tone::new(1760, ms(120)),
+];
+
+/// Rising, for something that worked
+pub const SUCCESS: &[Tone] = &[Tone::new(880, ms(80)), Tone::new(1319, ms(120))];
+
+/// Falling, for something that didn't
+pub const FAILURE: &[Tone] = &[Tone::new(440, ms(150)), Tone::new(220, ms(250))];
+
+/// Silences the speaker when the sequence ends or is dropped
+struct Silence;
+
+impl Drop for Silence {
+    fn drop(&mut self) {
+        with_speaker(|speaker| speaker.stop());
+    }
+}
+
+/// Sound `frequency` Hz for `duration`
+pub async fn beep(frequency: u32, duration: Duration) {
+    play(&[Tone::new(frequency, duration)]).await;
+}
+
+/// Play `tones` one after the other, frequencies are kept to what the
+/// speaker can do
+pub async fn play(tones: &[Tone]) {
+    let _turn = TURN.lock().await;
+    let _silence = Silence;
+    for tone in tones {
+        if tone.frequency == 0 {
+            with_speaker(|speaker| speaker.stop());
+        } else {
+            let frequency = tone.frequency.clamp(MIN_FREQUENCY, MAX_FREQUENCY);
+            with_speaker(|speaker| speaker.start(frequency));
+        }
+        timer::sleep(tone.duration).await;
+    }
+}
@@ -0,0 +1,355 @@
+//!
+//! Stream combinators
+//!
+//! [`merge`] reads two streams in one loop, what [`race`](crate::future::race)
+//! is to futures:
+//!
+//! ```ignore
+//! let mut inputs = stream::merge(keyboard::KeyEventStream::new(), timer::interval(BLINK));
+//! while let Some(input) = inputs.next().await {
+//!     match input {
+//!         Either::Left(key) => editor.handle(key),
+//!         Either::Right(_) => cursor.blink(),
+//!     }
+//! }
+//! ```
+//!
+//! [`zip`] pairs up the items of two streams instead.
+//!
+//! [`ready_chunks`] hands over a fast stream's items in batches, all those
+//! ready at once, so a task reading replayed input or packets is polled
+//! once per wake rather than once per item. [`chunks`] waits for full
+//! batches.
+//!
+
+use alloc::vec::Vec;
+use core::{
+    mem,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_util::Stream;
+use pin_project_lite::pin_project;
+
+use crate::future::Either;
+
+pin_project! {
+    /// Stream returned by [`merge`]
+    #[must_use = "streams do nothing unless polled"]
+    pub struct Merge<A, B> {
+        #[pin]
+        a: A,
+        #[pin]
+        b: B,
+        a_done: bool,
+        b_done: bool,
+        left_first: bool,
+    }
+}
+
+/// Items of both streams as they come, tagged with the side they came from,
+/// until both have ended. Streams of the same item type take
+/// [`Either::into_inner`] to drop the tag.
+///
+/// Both streams are polled with the same context, so a wake from either
+/// side re-polls the merge. Which side gets polled first alternates so a
+/// busy stream can't starve the other.
+pub fn merge<A, B>(a: A, b: B) -> Merge<A, B>
+where
+    A: Stream,
+    B: Stream,
+{
+    Merge {
+        a,
+        b,
+        a_done: false,
+        b_done: false,
+        left_first: true,
+    }
+}
+
+impl<A, B> Stream for Merge<A, B>
+where
+    A: Stream,
+    B: Stream,
+{
+    type Item = Either<A::Item, B::Item>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        let left_first = *this.left_first;
+        *this.left_first = !left_first;
+
+        for left in [left_first, !left_first] {
+            if left && !*this.a_done {
+                match this.a.as_mut().poll_next(cx) {
+                    Poll::Ready(Some(a)) => return Poll::Ready(Some(Either::Left(a))),
+                    Poll::Ready(None) => *this.a_done = true,
+                    Poll::Pending => {}
+                }
+            } else if !left && !*this.b_done {
+                match this.b.as_mut().poll_next(cx) {
+                    Poll::Ready(Some(b)) => return Poll::Ready(Some(Either::Right(b))),
+                    Poll::Ready(None) => *this.b_done = true,
+                    Poll::Pending => {}
+                }
+            }
+        }
+        if *this.a_done && *this.b_done {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let a = if self.a_done {
+            (0, Some(0))
+        } else {
+            self.a.size_hint()
+        };
+        let b = if self.b_done {
+            (0, Some(0))
+        } else {
+            self.b.size_hint()
+        };
+        let upper = a.1.zip(b.1).and_then(|(a, b)| a.checked_add(b));
+        (a.0.saturating_add(b.0), upper)
+    }
+}
+
+pin_project! {
+    /// Stream returned by [`zip`]
+    #[must_use = "streams do nothing unless polled"]
+    pub struct Zip<A: Stream, B: Stream> {
+        #[pin]
+        a: A,
+        #[pin]
+        b: B,
+        // An item already taken from one side, waiting for the other's
+        a_item: Option<A::Item>,
+        b_item: Option<B::Item>,
+        done: bool,
+    }
+}
+
+/// Pairs of the streams' items, the first of each, then the second of each
+/// and so on. Ends with the shorter stream, an item the other side got
+/// meanwhile is dropped.
+///
+/// Both streams are polled with the same context until each has an item
+/// ready, the one that's quicker is held back until the other catches up.
+pub fn zip<A, B>(a: A, b: B) -> Zip<A, B>
+where
+    A: Stream,
+    B: Stream,
+{
+    Zip {
+        a,
+        b,
+        a_item: None,
+        b_item: None,
+        done: false,
+    }
+}
+
+impl<A, B> Stream for Zip<A, B>
+where
+    A: Stream,
+    B: Stream,
+{
+    type Item = (A::Item, B::Item);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        if *this.done {
+            return Poll::Ready(None);
+        }
+        if this.a_item.is_none() {
+            match this.a.as_mut().poll_next(cx) {
+                Poll::Ready(Some(a)) => *this.a_item = Some(a),
+                Poll::Ready(None) => *this.done = true,
+                Poll::Pending => {}
+            }
+        }
+        if !*this.done && this.b_item.is_none() {
+            match this.b.as_mut().poll_next(cx) {
+                Poll::Ready(Some(b)) => *this.b_item = Some(b),
+                Poll::Ready(None) => *this.done = true,
+                Poll::Pending => {}
+            }
+        }
+        if *this.done {
+            *this.a_item = None;
+            *this.b_item = None;
+            return Poll::Ready(None);
+        }
+        match (this.a_item.take(), this.b_item.take()) {
+            (Some(a), Some(b)) => Poll::Ready(Some((a, b))),
+            (a, b) => {
+                *this.a_item = a;
+                *this.b_item = b;
+                Poll::Pending
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            return (0, Some(0));
+        }
+        let held = |item: bool, (lower, upper): (usize, Option<usize>)| {
+            let item = usize::from(item);
+            (
+                lower.saturating_add(item),
+                upper.and_then(|upper| upper.checked_add(item)),
+            )
+        };
+        let a = held(self.a_item.is_some(), self.a.size_hint());
+        let b = held(self.b_item.is_some(), self.b.size_hint());
+        let upper = match (a.1, b.1) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        (a.0.min(b.0), upper)
+    }
+}
+
+pin_project! {
+    /// Stream returned by [`chunks`]
+    #[must_use = "streams do nothing unless polled"]
+    pub struct Chunks<S: Stream> {
+        #[pin]
+        stream: S,
+        items: Vec<S::Item>,
+        capacity: usize,
+        done: bool,
+    }
+}
+
+/// The stream's items `capacity` at a time, the last batch shorter if the
+/// stream ends in the middle of one.
+///
+/// # Panics
+///
+/// If `capacity` is 0
+pub fn chunks<S: Stream>(stream: S, capacity: usize) -> Chunks<S> {
+    assert!(capacity > 0, "chunks of no items");
+    Chunks {
+        stream,
+        items: Vec::with_capacity(capacity),
+        capacity,
+        done: false,
+    }
+}
+
+impl<S: Stream> Stream for Chunks<S> {
+    type Item = Vec<S::Item>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Vec<S::Item>>> {
+        let mut this = self.project();
+        while !*this.done {
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => {
+                    this.items.push(item);
+                    if this.items.len() == *this.capacity {
+                        let full = mem::replace(this.items, Vec::with_capacity(*this.capacity));
+                        return Poll::Ready(Some(full));
+                    }
+                }
+                Poll::Ready(None) => *this.done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        if this.items.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Ready(Some(mem::take(this.items)))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        batches(self.items.len(), self.capacity, self.done, &self.stream)
+    }
+}
+
+pin_project! {
+    /// Stream returned by [`ready_chunks`]
+    #[must_use = "streams do nothing unless polled"]
+    pub struct ReadyChunks<S: Stream> {
+        #[pin]
+        stream: S,
+        capacity: usize,
+        done: bool,
+    }
+}
+
+/// The stream's items in batches of those ready at once, up to `capacity`
+/// of them. A batch is handed over as soon as the stream would wait, so
+/// none is ever empty and no item waits for more to come.
+///
+/// # Panics
+///
+/// If `capacity` is 0
+pub fn ready_chunks<S: Stream>(stream: S, capacity: usize) -> ReadyChunks<S> {
+    assert!(capacity > 0, "chunks of no items");
+    ReadyChunks {
+        stream,
+        capacity,
+        done: false,
+    }
+}
+
+impl<S: Stream> Stream for ReadyChunks<S> {
+    type Item = Vec<S::Item>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Vec<S::Item>>> {
+        let mut this = self.project();
+        let mut items = Vec::new();
+        while !*this.done && items.len() < *this.capacity {
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(item)) => items.push(item),
+                Poll::Ready(None) => *this.done = true,
+                Poll::Pending if items.is_empty() => return Poll::Pending,
+                Poll::Pending => break,
+            }
+        }
+        if items.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Ready(Some(items))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, _) = batches(0, self.capacity, self.done, &self.stream);
+        // Items may come one per wake, a batch each
+        let upper = if self.done {
+            Some(0)
+        } else {
+            self.stream.size_hint().1
+        };
+        (lower, upper)
+    }
+}
+
+/// Batches of `capacity` left in a stream with `held` items taken already
+fn batches<S: Stream>(
+    held: usize,
+    capacity: usize,
+    done: bool,
+    stream: &S,
+) -> (usize, Option<usize>) {
+    let (lower, upper) = if done {
+        (0, Some(0))
+    } else {
+        stream.size_hint()
+    };
+    let lower = lower.saturating_add(held);
+    let upper = upper.and_then(|upper| upper.checked_add(held));
+    (
+        lower.div_ceil(capacity),
+        upper.map(|upper| upper.div_ceil(capacity)),
+    )
+}
@@ -0,0 +1,292 @@
+//!
+//! Supervisor restarting failed child tasks
+//!
+
+use std::{
+    any::Any,
+    future::Future,
+    panic::{AssertUnwindSafe, catch_unwind},
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use pin_project_lite::pin_project;
+
+use crate::{
+    LocalTask,
+    future::{Either, race},
+    join_set::JoinSet,
+    log,
+    metrics::Counter,
+    sync::CancellationToken,
+    timer,
+};
+
+static RESTARTS: Counter = Counter::new(
+    "supervisor_restarts_total",
+    "Child tasks restarted by a supervisor",
+);
+static GIVE_UPS: Counter = Counter::new(
+    "supervisor_give_ups_total",
+    "Children that exhausted their restart budget",
+);
+
+/// How a child task ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exit {
+    Completed,
+    Panicked,
+    /// Still running at the end of its [`time_limit`](RestartPolicy::time_limit)
+    TimedOut,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Restart {
+    /// Restart whenever the child ends
+    Always,
+    /// Restart only if the child panicked or ran out of time
+    OnPanic,
+    Never,
+}
+
+/// When and how often a child is restarted
+#[derive(Debug, Clone, Copy)]
+pub struct RestartPolicy {
+    pub restart: Restart,
+    /// Give up after this many restarts
+    pub max_restarts: Option<u32>,
+    /// Delay before the first restart, doubled for every further one
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    /// Dropped and counted as [`Exit::TimedOut`] if still running this
+    /// long after starting
+    pub time_limit: Option<Duration>,
+}
+
+impl RestartPolicy {
+    pub const fn always() -> Self {
+        RestartPolicy {
+            restart: Restart::Always,
+            max_restarts: None,
+            backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+            time_limit: None,
+        }
+    }
+
+    pub const fn on_panic() -> Self {
+        RestartPolicy {
+            restart: Restart::OnPanic,
+            ..RestartPolicy::always()
+        }
+    }
+
+    pub const fn never() -> Self {
+        RestartPolicy {
+            restart: Restart::Never,
+            ..RestartPolicy::always()
+        }
+    }
+
+    pub const fn max_restarts(mut self, max: u32) -> Self {
+        self.max_restarts = Some(max);
+        self
+    }
+
+    pub const fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// For children that can hang, like a driver probing for hardware
+    /// that isn't there
+    pub const fn time_limit(mut self, limit: Duration) -> Self {
+        self.time_limit = Some(limit);
+        self
+    }
+
+    fn should_restart(&self, exit: Exit) -> bool {
+        match self.restart {
+            Restart::Always => true,
+            Restart::OnPanic => exit != Exit::Completed,
+            Restart::Never => false,
+        }
+    }
+
+    /// Delay before restart number `restart` (starting at 1)
+    fn delay(&self, restart: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(restart.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff))
+    }
+}
+
+type Factory = Box<dyn Fn() -> Pin<Box<dyn Future<Output = ()>>>>;
+
+struct Child {
+    name: &'static str,
+    policy: RestartPolicy,
+    factory: Factory,
+    restarts: u32,
+}
+
+/// Owns a set of child tasks and restarts them according to their policy.
+///
+/// Children are driven inside the supervisor's own task, so spawning the
+/// supervisor is enough. Each restart creates a fresh future from the
+/// child's factory.
+///
+/// ```ignore
+/// let supervisor = Supervisor::new()
+///     .child("keyboard", RestartPolicy::on_panic().max_restarts(3), keyboard::print_keypresses);
+/// executor.spawn_local(supervisor.into_task());
+/// ```
+pub struct Supervisor {
+    children: Vec<Child>,
+    token: CancellationToken,
+}
+
+impl Supervisor {
+    pub fn new() -> Self {
+        Supervisor {
+            children: Vec::new(),
+            token: CancellationToken::new(),
+        }
+    }
+
+    /// Stop all children and return from `run` once `token` is cancelled
+    pub fn with_token(mut self, token: CancellationToken) -> Self {
+        self.token = token;
+        self
+    }
+
+    pub fn child<F, Fut>(mut self, name: &'static str, policy: RestartPolicy, factory: F) -> Self
+    where
+        F: Fn() -> Fut + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        self.children.push(Child {
+            name,
+            policy,
+            factory: Box::new(move || Box::pin(factory())),
+            restarts: 0,
+        });
+        self
+    }
+
+    pub fn into_task(self) -> LocalTask {
+        LocalTask::new(self.run())
+    }
+
+    /// Runs until every child is done for good or the token is cancelled
+    pub async fn run(mut self) {
+        let mut running = JoinSet::new();
+        for (index, child) in self.children.iter().enumerate() {
+            running.spawn(run_child(
+                index,
+                (child.factory)(),
+                Duration::ZERO,
+                child.policy.time_limit,
+            ));
+        }
+
+        loop {
+            let (index, exit) = match race(running.join_next(), self.token.cancelled()).await {
+                Either::Left(Some(exited)) => exited,
+                // All children finished, or we were told to stop
+                Either::Left(None) | Either::Right(()) => return,
+            };
+
+            let child = &mut self.children[index];
+            if !child.policy.should_restart(exit) {
+                continue;
+            }
+            if child
+                .policy
+                .max_restarts
+                .is_some_and(|max| child.restarts >= max)
+            {
+                GIVE_UPS.inc();
+                log::error!(
+                    "{} exited ({:?}) after {} restarts, giving up",
+                    child.name,
+                    exit,
+                    child.restarts
+                );
+                continue;
+            }
+
+            child.restarts += 1;
+            RESTARTS.inc();
+            let delay = child.policy.delay(child.restarts);
+            log::warn!(
+                "{} exited ({:?}), restart #{} in {:?}",
+                child.name,
+                exit,
+                child.restarts,
+                delay
+            );
+            running.spawn(run_child(
+                index,
+                (child.factory)(),
+                delay,
+                child.policy.time_limit,
+            ));
+        }
+    }
+}
+
+impl Default for Supervisor {
+    fn default() -> Self {
+        Supervisor::new()
+    }
+}
+
+async fn run_child(
+    index: usize,
+    future: Pin<Box<dyn Future<Output = ()>>>,
+    delay: Duration,
+    time_limit: Option<Duration>,
+) -> (usize, Exit) {
+    if !delay.is_zero() {
+        timer::sleep(delay).await;
+    }
+    let run = CatchUnwind { future };
+    let exited = match time_limit {
+        Some(limit) => match timer::timeout(limit, run).await {
+            Ok(exited) => exited,
+            Err(_) => return (index, Exit::TimedOut),
+        },
+        None => run.await,
+    };
+    match exited {
+        Ok(()) => (index, Exit::Completed),
+        Err(_) => (index, Exit::Panicked),
+    }
+}
+
+pin_project! {
+    /// Turns a panic inside `poll` into an `Err`
+    struct CatchUnwind<F> {
+        #[pin]
+        future: F,
+    }
+}
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, Box<dyn Any + Send>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let future = self.project().future;
+        match catch_unwind(AssertUnwindSafe(|| future.poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(output)) => Poll::Ready(Ok(output)),
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    }
+}
@@ -0,0 +1,28 @@
+//!
+//! Async synchronization primitives
+//!
+//! Waiting tasks are parked through their waker instead of spinning, so a
+//! contended lock doesn't keep the executor busy.
+//!
+
+mod barrier;
+pub mod cancellation;
+mod irq_notify;
+pub(crate) mod loom;
+mod mutex;
+mod notify;
+mod once_cell;
+mod rwlock;
+mod semaphore;
+mod task_tracker;
+pub(crate) mod waiters;
+
+pub use barrier::{Barrier, BarrierWait, BarrierWaitResult};
+pub use cancellation::{CancellationToken, Cancelled, DropGuard};
+pub use irq_notify::{IRQ_NOTIFY_SLOTS, IrqNotify, Listener};
+pub use mutex::{Lock, Mutex, MutexGuard};
+pub use notify::{Notified, Notify};
+pub use once_cell::OnceCell;
+pub use rwlock::{Read, RwLock, RwLockReadGuard, RwLockWriteGuard, Write};
+pub use semaphore::{Acquire, OwnedSemaphorePermit, Semaphore, SemaphorePermit};
+pub use task_tracker::{TaskTracker, TaskTrackerToken, TrackedFuture, TrackerWait};
@@ -0,0 +1,102 @@
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::waiters::WaitList;
+
+/// Lets `n` tasks wait for each other, e.g. for phased driver startup.
+///
+/// The barrier resets after releasing a group, so it can be reused for the
+/// next phase.
+pub struct Barrier {
+    n: usize,
+    state: spin::Mutex<State>,
+}
+
+struct State {
+    arrived: usize,
+    waiters: WaitList,
+}
+
+impl Barrier {
+    pub const fn new(n: usize) -> Self {
+        Barrier {
+            n,
+            state: spin::Mutex::new(State {
+                arrived: 0,
+                waiters: WaitList::new(),
+            }),
+        }
+    }
+
+    pub fn wait(&self) -> BarrierWait<'_> {
+        BarrierWait {
+            barrier: self,
+            key: None,
+        }
+    }
+}
+
+/// Future returned by [`Barrier::wait`]
+pub struct BarrierWait<'a> {
+    barrier: &'a Barrier,
+    key: Option<u64>,
+}
+
+impl Future for BarrierWait<'_> {
+    type Output = BarrierWaitResult;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<BarrierWaitResult> {
+        let barrier = self.barrier;
+        let mut state = barrier.state.lock();
+
+        match self.key {
+            None => {
+                state.arrived += 1;
+                if state.arrived >= barrier.n {
+                    // Last one in releases the group and resets for the next round
+                    state.arrived = 0;
+                    state.waiters.notify_all();
+                    return Poll::Ready(BarrierWaitResult { leader: true });
+                }
+                self.key = Some(state.waiters.push(cx.waker()));
+                Poll::Pending
+            }
+            Some(key) => {
+                if state.waiters.is_notified(key) {
+                    state.waiters.remove(key);
+                    self.key = None;
+                    return Poll::Ready(BarrierWaitResult { leader: false });
+                }
+                state.waiters.update(key, cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for BarrierWait<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            let mut state = self.barrier.state.lock();
+            // Leaving before the group was released, we no longer count as arrived
+            if state.waiters.remove(key) == Some(false) {
+                state.arrived -= 1;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BarrierWaitResult {
+    leader: bool,
+}
+
+impl BarrierWaitResult {
+    /// Exactly one task per round is the leader, the one that arrived last
+    pub fn is_leader(&self) -> bool {
+        self.leader
+    }
+}
@@ -0,0 +1,284 @@
+//! Cancellation tokens, and points where a long running future notices
+//! its token was cancelled:
+//!
+//! ```ignore
+//! let token = CancellationToken::new();
+//! executor::spawn(Task::new(with_cancel(token.child_token(), async {
+//!     for block in blocks {
+//!         checksum.update(block);
+//!         // Yields, and gives up here once `token` is cancelled
+//!         checkpoint().await?;
+//!     }
+//!     Ok::<_, CancelledError>(checksum)
+//! })));
+//! ```
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use pin_project_lite::pin_project;
+
+use super::waiters::WaitList;
+
+/// Cooperative cancellation signal shared between tasks.
+///
+/// Cancelling a token also cancels every token derived from it with
+/// [`child_token`](CancellationToken::child_token), but not the other way
+/// round, so a subsystem can be stopped on its own or as part of the whole.
+#[derive(Clone)]
+pub struct CancellationToken {
+    node: Arc<spin::Mutex<Node>>,
+}
+
+struct Node {
+    cancelled: bool,
+    waiters: WaitList,
+    children: Vec<Weak<spin::Mutex<Node>>>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken {
+            node: Arc::new(spin::Mutex::new(Node {
+                cancelled: false,
+                waiters: WaitList::new(),
+                children: Vec::new(),
+            })),
+        }
+    }
+
+    pub fn child_token(&self) -> CancellationToken {
+        let child = CancellationToken::new();
+        let mut node = self.node.lock();
+        if node.cancelled {
+            child.node.lock().cancelled = true;
+        } else {
+            // Forget children that are already gone
+            node.children.retain(|c| c.strong_count() > 0);
+            node.children.push(Arc::downgrade(&child.node));
+        }
+        child
+    }
+
+    pub fn cancel(&self) {
+        let children = {
+            let mut node = self.node.lock();
+            if node.cancelled {
+                return;
+            }
+            node.cancelled = true;
+            node.waiters.notify_all();
+            core::mem::take(&mut node.children)
+        };
+        // Outside our lock, children lock their own nodes
+        for child in children.iter().filter_map(Weak::upgrade) {
+            CancellationToken { node: child }.cancel();
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.node.lock().cancelled
+    }
+
+    pub fn cancelled(&self) -> Cancelled<'_> {
+        Cancelled {
+            token: self,
+            key: None,
+        }
+    }
+
+    /// Cancels the token when dropped, including during a panic unwind
+    pub fn drop_guard(self) -> DropGuard {
+        DropGuard { token: Some(self) }
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> Self {
+        CancellationToken::new()
+    }
+}
+
+/// Future returned by [`CancellationToken::cancelled`]
+pub struct Cancelled<'a> {
+    token: &'a CancellationToken,
+    key: Option<u64>,
+}
+
+impl Future for Cancelled<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let token = self.token;
+        let mut node = token.node.lock();
+        if node.cancelled {
+            if let Some(key) = self.key.take() {
+                node.waiters.remove(key);
+            }
+            return Poll::Ready(());
+        }
+        node.waiters.register(&mut self.key, cx.waker());
+        Poll::Pending
+    }
+}
+
+impl Drop for Cancelled<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            self.token.node.lock().waiters.remove(key);
+        }
+    }
+}
+
+pub struct DropGuard {
+    token: Option<CancellationToken>,
+}
+
+impl DropGuard {
+    /// Keep the token alive without cancelling it
+    pub fn disarm(mut self) -> CancellationToken {
+        self.token.take().expect("guard already disarmed")
+    }
+}
+
+impl Drop for DropGuard {
+    fn drop(&mut self) {
+        if let Some(token) = self.token.take() {
+            token.cancel();
+        }
+    }
+}
+
+// Token of the innermost `with_cancel` being polled
+#[cfg(feature = "std")]
+std::thread_local! {
+    static CURRENT: core::cell::RefCell<Option<CancellationToken>> = const { core::cell::RefCell::new(None) };
+}
+
+// No threads without std, one poll at a time
+#[cfg(not(feature = "std"))]
+static CURRENT: spin::Mutex<Option<CancellationToken>> = spin::Mutex::new(None);
+
+fn replace_current(token: Option<CancellationToken>) -> Option<CancellationToken> {
+    #[cfg(feature = "std")]
+    return CURRENT.with(|current| current.replace(token));
+    #[cfg(not(feature = "std"))]
+    return core::mem::replace(&mut CURRENT.lock(), token);
+}
+
+fn current_is_cancelled() -> bool {
+    #[cfg(feature = "std")]
+    return CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+    });
+    #[cfg(not(feature = "std"))]
+    return CURRENT
+        .lock()
+        .as_ref()
+        .is_some_and(CancellationToken::is_cancelled);
+}
+
+/// What [`with_cancel`] and [`checkpoint`] give once cancelled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CancelledError;
+
+impl fmt::Display for CancelledError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("cancelled")
+    }
+}
+
+/// Run `future` until `token` is cancelled. It's not polled again after
+/// that, the wrapper resolves to [`CancelledError`] right away even if
+/// `future` is waiting on something else.
+///
+/// [`checkpoint`]s inside `future` see `token`.
+pub fn with_cancel<F: Future>(token: CancellationToken, future: F) -> WithCancel<F> {
+    WithCancel {
+        future,
+        token,
+        key: None,
+    }
+}
+
+pin_project! {
+    /// Future returned by [`with_cancel`]
+    pub struct WithCancel<F> {
+        #[pin]
+        future: F,
+        token: CancellationToken,
+        key: Option<u64>,
+    }
+
+    impl<F> PinnedDrop for WithCancel<F> {
+        fn drop(this: Pin<&mut Self>) {
+            let this = this.project();
+            if let Some(key) = this.key.take() {
+                this.token.node.lock().waiters.remove(key);
+            }
+        }
+    }
+}
+
+impl<F: Future> Future for WithCancel<F> {
+    type Output = Result<F::Output, CancelledError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        {
+            let mut node = this.token.node.lock();
+            if node.cancelled {
+                if let Some(key) = this.key.take() {
+                    node.waiters.remove(key);
+                }
+                return Poll::Ready(Err(CancelledError));
+            }
+            node.waiters.register(this.key, cx.waker());
+        }
+        let previous = replace_current(Some(this.token.clone()));
+        let poll = this.future.poll(cx);
+        replace_current(previous);
+        poll.map(Ok)
+    }
+}
+
+/// Let other tasks run, then carry on unless the token of the innermost
+/// [`with_cancel`] around the caller was cancelled. Put one in every
+/// round of a loop that would otherwise never wait.
+///
+/// Outside `with_cancel` it only yields.
+pub fn checkpoint() -> Checkpoint {
+    Checkpoint { yielded: false }
+}
+
+/// Future returned by [`checkpoint`]
+pub struct Checkpoint {
+    yielded: bool,
+}
+
+impl Future for Checkpoint {
+    type Output = Result<(), CancelledError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if current_is_cancelled() {
+            return Poll::Ready(Err(CancelledError));
+        }
+        if self.yielded {
+            return Poll::Ready(Ok(()));
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
@@ -0,0 +1,163 @@
+use core::{
+    sync::atomic::{AtomicUsize, Ordering},
+    task::Waker,
+};
+
+use futures_util::task::AtomicWaker;
+
+/// Listeners with a waker of their own, later ones share them
+pub const IRQ_NOTIFY_SLOTS: usize = 4;
+
+/// Wakes the tasks reading what an interrupt handler queues. Unlike
+/// [`Notify`](super::Notify), notifying only touches atomics, it never
+/// locks or allocates, so the handler can do it.
+///
+/// Each reader holds a [`Listener`], registers its waker before parking and
+/// looks at the queue once more after, as with an `AtomicWaker`. There are
+/// no permits:
+///
+/// ```ignore
+/// if let Some(byte) = queue.pop() {
+///     return Poll::Ready(byte);
+/// }
+/// listener.register(cx.waker());
+/// // Pushed before registering, that woke nobody
+/// match queue.pop() {
+///     Some(byte) => Poll::Ready(byte),
+///     None => Poll::Pending,
+/// }
+/// ```
+///
+/// [`notify`](IrqNotify::notify) wakes every listener, they race for the
+/// input. Past [`IRQ_NOTIFY_SLOTS`] listeners share slots, of those only
+/// the last to register is woken.
+pub struct IrqNotify {
+    slots: [Slot; IRQ_NOTIFY_SLOTS],
+    // Where a listener goes once every slot is taken
+    next: AtomicUsize,
+}
+
+struct Slot {
+    listeners: AtomicUsize,
+    waker: AtomicWaker,
+}
+
+impl IrqNotify {
+    pub const fn new() -> Self {
+        IrqNotify {
+            slots: [const {
+                Slot {
+                    listeners: AtomicUsize::new(0),
+                    waker: AtomicWaker::new(),
+                }
+            }; IRQ_NOTIFY_SLOTS],
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// A free slot, or a shared one if none is
+    pub fn listener(&self) -> Listener<'_> {
+        let free = self.slots.iter().position(|slot| {
+            slot.listeners
+                .compare_exchange(0, 1, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        });
+        let slot = free.unwrap_or_else(|| {
+            let slot = self.next.fetch_add(1, Ordering::Relaxed) % IRQ_NOTIFY_SLOTS;
+            self.slots[slot].listeners.fetch_add(1, Ordering::AcqRel);
+            slot
+        });
+        Listener { notify: self, slot }
+    }
+
+    /// Wake every listener that registered since it was last woken
+    pub fn notify(&self) {
+        for slot in &self.slots {
+            if slot.listeners.load(Ordering::Acquire) > 0 {
+                slot.waker.wake();
+            }
+        }
+    }
+}
+
+impl Default for IrqNotify {
+    fn default() -> Self {
+        IrqNotify::new()
+    }
+}
+
+/// A reader's slot of an [`IrqNotify`]
+pub struct Listener<'a> {
+    notify: &'a IrqNotify,
+    slot: usize,
+}
+
+impl Listener<'_> {
+    /// Have the next [`notify`](IrqNotify::notify) wake `waker`
+    pub fn register(&self, waker: &Waker) {
+        self.notify.slots[self.slot].waker.register(waker);
+    }
+}
+
+impl Clone for Listener<'_> {
+    fn clone(&self) -> Self {
+        self.notify.listener()
+    }
+}
+
+impl Drop for Listener<'_> {
+    fn drop(&mut self) {
+        // A waker left behind only costs its task a spurious wake
+        self.notify.slots[self.slot]
+            .listeners
+            .fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::*;
+    use crate::waker::TestWaker;
+
+    #[test]
+    fn notify_wakes_each_registered_listener_once() {
+        let notify = IrqNotify::new();
+        let listeners: Vec<_> = (0..IRQ_NOTIFY_SLOTS).map(|_| notify.listener()).collect();
+        let wakers: Vec<_> = listeners
+            .iter()
+            .map(|listener| {
+                let waker = TestWaker::new();
+                listener.register(&waker.waker());
+                waker
+            })
+            .collect();
+        notify.notify();
+        notify.notify();
+        assert!(wakers.iter().all(|waker| waker.wakes() == 1));
+
+        // Registering again after the wake, as a stream that found nothing
+        listeners[0].register(&wakers[0].waker());
+        notify.notify();
+        assert_eq!(wakers[0].wakes(), 2);
+        assert_eq!(wakers[1].wakes(), 1);
+    }
+
+    #[test]
+    fn listeners_past_the_slots_share_them() {
+        let notify = IrqNotify::new();
+        let listeners: Vec<_> = (0..IRQ_NOTIFY_SLOTS * 2)
+            .map(|_| notify.listener())
+            .collect();
+        let waker = TestWaker::new();
+        listeners.last().unwrap().register(&waker.waker());
+        notify.notify();
+        assert_eq!(waker.wakes(), 1);
+
+        // Dropped listeners free their slots for new ones
+        drop(listeners);
+        let listener = notify.listener();
+        assert_eq!(listener.slot, 0);
+    }
+}
@@ -0,0 +1,89 @@
+//! The atomics, locks and queues of the wake path and the channels, from
+//! [loom](https://docs.rs/loom) when built with `--cfg loom` so their
+//! orderings can be model-checked:
+//!
+//! ```text
+//! RUSTFLAGS="--cfg loom" cargo test -p task --release
+//! ```
+//!
+//! Without it they're the usual `core` atomics, `spin` locks and crossbeam
+//! queues. State in statics, like the idle flags and metrics, stays as it is,
+//! loom can't reset it between runs.
+
+#[cfg(not(loom))]
+pub(crate) use alloc::sync::Arc;
+#[cfg(not(loom))]
+pub(crate) use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+#[cfg(not(loom))]
+pub(crate) use crossbeam_queue::ArrayQueue;
+#[cfg(not(loom))]
+pub(crate) use futures_util::task::AtomicWaker;
+#[cfg(not(loom))]
+pub(crate) use spin::Mutex;
+#[cfg(all(not(loom), feature = "channels"))]
+pub(crate) use spin::MutexGuard;
+
+#[cfg(loom)]
+pub(crate) use ::loom::{
+    future::AtomicWaker,
+    sync::{
+        Arc, MutexGuard,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+};
+
+/// loom's mutex with the `spin` one's methods
+#[cfg(loom)]
+#[derive(Debug, Default)]
+pub(crate) struct Mutex<T>(::loom::sync::Mutex<T>);
+
+#[cfg(loom)]
+impl<T> Mutex<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Mutex(::loom::sync::Mutex::new(value))
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, T> {
+        self.0.lock().unwrap()
+    }
+}
+
+/// Bounded queue with crossbeam's `ArrayQueue` methods, on a loom mutex:
+/// what's checked is how the executor uses it, not crossbeam's insides
+#[cfg(loom)]
+#[derive(Debug)]
+pub(crate) struct ArrayQueue<T> {
+    capacity: usize,
+    items: Mutex<alloc::collections::VecDeque<T>>,
+}
+
+#[cfg(loom)]
+impl<T> ArrayQueue<T> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        ArrayQueue {
+            capacity,
+            items: Mutex::new(alloc::collections::VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub(crate) fn push(&self, value: T) -> Result<(), T> {
+        let mut items = self.items.lock();
+        if items.len() == self.capacity {
+            return Err(value);
+        }
+        items.push_back(value);
+        Ok(())
+    }
+
+    pub(crate) fn pop(&self) -> Option<T> {
+        self.items.lock().pop_front()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.items.lock().len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,246 @@
+use alloc::collections::BTreeMap;
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::waiters::WaitList;
+use crate::{
+    TaskId, context,
+    priority::{self, Priority},
+};
+
+/// Mutual exclusion where `lock()` is a future.
+///
+/// Waiting tasks are parked in FIFO order. On unlock the lock is handed
+/// directly to the oldest waiter, so a task that keeps re-locking can't
+/// starve the others.
+///
+/// A mutex built with [`Mutex::with_priority_inheritance`] additionally lends
+/// the priority of its most important waiter to the task holding it, so a
+/// low-priority holder can't keep a high-priority waiter blocked while
+/// medium-priority tasks run.
+pub struct Mutex<T: ?Sized> {
+    state: spin::Mutex<State>,
+    inherit: bool,
+    value: UnsafeCell<T>,
+}
+
+struct State {
+    locked: bool,
+    waiters: WaitList,
+    // Only tracked with priority inheritance
+    holder: Option<TaskId>,
+    waiter_priorities: BTreeMap<u64, Priority>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Mutex::build(value, false)
+    }
+
+    pub const fn with_priority_inheritance(value: T) -> Self {
+        Mutex::build(value, true)
+    }
+
+    const fn build(value: T, inherit: bool) -> Self {
+        Mutex {
+            state: spin::Mutex::new(State {
+                locked: false,
+                waiters: WaitList::new(),
+                holder: None,
+                waiter_priorities: BTreeMap::new(),
+            }),
+            inherit,
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    pub fn lock(&self) -> Lock<'_, T> {
+        Lock {
+            mutex: self,
+            key: None,
+        }
+    }
+
+    /// Take the lock only if nobody holds it right now
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        let mut state = self.state.lock();
+        if state.locked {
+            return None;
+        }
+        state.locked = true;
+        self.acquired(&mut state);
+        Some(MutexGuard::new(self))
+    }
+
+    /// No locking needed, `&mut self` proves there are no other users
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.state.lock().locked
+    }
+
+    fn unlock(&self) {
+        let mut state = self.state.lock();
+        if self.inherit
+            && let Some(holder) = state.holder.take()
+        {
+            priority::release(holder, self.resource());
+        }
+        // Hand off to the next waiter, the lock stays taken
+        if !state.waiters.notify_one() {
+            state.locked = false;
+        }
+    }
+
+    fn resource(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+
+    /// Called in the context of the task that just took the lock
+    fn acquired(&self, state: &mut State) {
+        if self.inherit {
+            state.holder = context::current().map(|(task, _)| task);
+            self.lend_priority(state);
+        }
+    }
+
+    fn park(&self, state: &mut State, cx: &mut Context) -> u64 {
+        let key = state.waiters.push(cx.waker());
+        if self.inherit
+            && let Some((task, base)) = context::current()
+        {
+            let waiter_priority = priority::effective(task, base);
+            state.waiter_priorities.insert(key, waiter_priority);
+            self.lend_priority(state);
+        }
+        key
+    }
+
+    fn unpark(&self, state: &mut State, key: u64) -> Option<bool> {
+        if self.inherit && state.waiter_priorities.remove(&key).is_some() {
+            // The leaving waiter may have been the one we inherited from
+            if let Some(holder) = state.holder {
+                priority::release(holder, self.resource());
+            }
+            self.lend_priority(state);
+        }
+        state.waiters.remove(key)
+    }
+
+    /// Raise the holder to the priority of the most important waiter
+    fn lend_priority(&self, state: &State) {
+        let (Some(holder), Some(highest)) = (state.holder, state.waiter_priorities.values().max())
+        else {
+            return;
+        };
+        priority::inherit(holder, self.resource(), *highest);
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Mutex::new(T::default())
+    }
+}
+
+/// Future returned by [`Mutex::lock`]
+pub struct Lock<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+    // Position in the wait queue once we had to park
+    key: Option<u64>,
+}
+
+impl<'a, T: ?Sized> Future for Lock<'a, T> {
+    type Output = MutexGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<MutexGuard<'a, T>> {
+        let mutex = self.mutex;
+        let mut state = mutex.state.lock();
+
+        match self.key {
+            None => {
+                if !state.locked {
+                    state.locked = true;
+                    mutex.acquired(&mut state);
+                    return Poll::Ready(MutexGuard::new(mutex));
+                }
+                self.key = Some(mutex.park(&mut state, cx));
+                Poll::Pending
+            }
+            Some(key) => {
+                if state.waiters.is_notified(key) {
+                    // Lock was handed to us by unlock()
+                    mutex.unpark(&mut state, key);
+                    self.key = None;
+                    mutex.acquired(&mut state);
+                    return Poll::Ready(MutexGuard::new(mutex));
+                }
+                state.waiters.update(key, cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for Lock<'_, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            let mut state = self.mutex.state.lock();
+            // Cancelled after being handed the lock, pass it on
+            if self.mutex.unpark(&mut state, key) == Some(true) && !state.waiters.notify_one() {
+                state.locked = false;
+            }
+        }
+    }
+}
+
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+    _marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T: ?Sized> MutexGuard<'a, T> {
+    fn new(mutex: &'a Mutex<T>) -> Self {
+        MutexGuard {
+            mutex,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
@@ -0,0 +1,105 @@
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::waiters::WaitList;
+
+/// Wakes tasks waiting on an event, without carrying any data.
+///
+/// `notify_one` wakes the oldest waiter, or stores a single permit if nobody
+/// is waiting so the next `notified().await` completes immediately. This
+/// closes the "check, then register" race that hand-written futures around
+/// an `AtomicWaker` have to deal with themselves.
+pub struct Notify {
+    state: spin::Mutex<State>,
+}
+
+struct State {
+    permit: bool,
+    waiters: WaitList,
+}
+
+impl Notify {
+    pub const fn new() -> Self {
+        Notify {
+            state: spin::Mutex::new(State {
+                permit: false,
+                waiters: WaitList::new(),
+            }),
+        }
+    }
+
+    pub fn notified(&self) -> Notified<'_> {
+        Notified {
+            notify: self,
+            key: None,
+        }
+    }
+
+    pub fn notify_one(&self) {
+        let mut state = self.state.lock();
+        if !state.waiters.notify_one() {
+            state.permit = true;
+        }
+    }
+
+    /// Wake every task currently waiting, no permit is stored
+    pub fn notify_waiters(&self) {
+        self.state.lock().waiters.notify_all();
+    }
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Notify::new()
+    }
+}
+
+/// Future returned by [`Notify::notified`]
+pub struct Notified<'a> {
+    notify: &'a Notify,
+    key: Option<u64>,
+}
+
+impl Future for Notified<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let notify = self.notify;
+        let mut state = notify.state.lock();
+
+        match self.key {
+            None => {
+                if state.permit {
+                    state.permit = false;
+                    return Poll::Ready(());
+                }
+                self.key = Some(state.waiters.push(cx.waker()));
+                Poll::Pending
+            }
+            Some(key) => {
+                if state.waiters.is_notified(key) {
+                    state.waiters.remove(key);
+                    self.key = None;
+                    return Poll::Ready(());
+                }
+                state.waiters.update(key, cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Notified<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            let mut state = self.notify.state.lock();
+            // Don't swallow a notification we never observed
+            if state.waiters.remove(key) == Some(true) && !state.waiters.notify_one() {
+                state.permit = true;
+            }
+        }
+    }
+}
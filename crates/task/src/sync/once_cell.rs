@@ -0,0 +1,88 @@
+use core::future::Future;
+
+use super::Mutex;
+
+/// Cell written at most once, where initialization may await.
+///
+/// Unlike `conquer_once::OnceCell`, concurrent callers of `get_or_init` don't
+/// race or fail: the first one runs its init future and the rest park on an
+/// async lock until the value is there. If the running init future is
+/// dropped, the next waiter gets to try.
+pub struct OnceCell<T> {
+    value: spin::Once<T>,
+    init: Mutex<()>,
+}
+
+impl<T> OnceCell<T> {
+    pub const fn new() -> Self {
+        OnceCell {
+            value: spin::Once::new(),
+            init: Mutex::new(()),
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        self.value.get()
+    }
+
+    pub fn initialized(&self) -> bool {
+        self.value.is_completed()
+    }
+
+    /// Fails if the cell is already set or an initialization is in progress
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let Some(_guard) = self.init.try_lock() else {
+            return Err(value);
+        };
+        if self.value.is_completed() {
+            return Err(value);
+        }
+        self.value.call_once(|| value);
+        Ok(())
+    }
+
+    pub async fn get_or_init<F, Fut>(&self, init: F) -> &T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        if let Some(value) = self.value.get() {
+            return value;
+        }
+
+        let _guard = self.init.lock().await;
+        // Someone else may have finished while we were parked
+        if let Some(value) = self.value.get() {
+            return value;
+        }
+        let value = init().await;
+        self.value.call_once(|| value)
+    }
+
+    pub async fn get_or_try_init<F, Fut, E>(&self, init: F) -> Result<&T, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        if let Some(value) = self.value.get() {
+            return Ok(value);
+        }
+
+        let _guard = self.init.lock().await;
+        if let Some(value) = self.value.get() {
+            return Ok(value);
+        }
+        let value = init().await?;
+        Ok(self.value.call_once(|| value))
+    }
+
+    pub fn into_inner(self) -> Option<T> {
+        self.value.try_into_inner()
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        OnceCell::new()
+    }
+}
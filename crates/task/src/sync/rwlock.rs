@@ -0,0 +1,275 @@
+use core::{
+    cell::UnsafeCell,
+    future::Future,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::waiters::WaitList;
+
+/// Reader-writer lock with async acquisition.
+///
+/// Once a writer is queued, new readers queue up behind it instead of
+/// joining the current readers, so a steady stream of readers can't starve
+/// writers. When a writer releases, every reader that queued meanwhile is let
+/// in before the next writer, so writers can't starve readers either.
+pub struct RwLock<T: ?Sized> {
+    state: spin::Mutex<State>,
+    value: UnsafeCell<T>,
+}
+
+struct State {
+    readers: usize,
+    writer: bool,
+    read_waiters: WaitList,
+    write_waiters: WaitList,
+}
+
+impl State {
+    fn release_read(&mut self) {
+        self.readers -= 1;
+        if self.readers == 0 && self.write_waiters.notify_one() {
+            self.writer = true;
+        }
+    }
+
+    fn release_write(&mut self) {
+        self.writer = false;
+        // Hand off to all queued readers first, then to the next writer
+        let readers = self.read_waiters.notify_all();
+        if readers > 0 {
+            self.readers += readers;
+        } else if self.write_waiters.notify_one() {
+            self.writer = true;
+        }
+    }
+}
+
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    pub const fn new(value: T) -> Self {
+        RwLock {
+            state: spin::Mutex::new(State {
+                readers: 0,
+                writer: false,
+                read_waiters: WaitList::new(),
+                write_waiters: WaitList::new(),
+            }),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> RwLock<T> {
+    pub fn read(&self) -> Read<'_, T> {
+        Read {
+            lock: self,
+            key: None,
+        }
+    }
+
+    pub fn write(&self) -> Write<'_, T> {
+        Write {
+            lock: self,
+            key: None,
+        }
+    }
+
+    pub fn try_read(&self) -> Option<RwLockReadGuard<'_, T>> {
+        let mut state = self.state.lock();
+        if state.writer || state.write_waiters.pending() > 0 {
+            return None;
+        }
+        state.readers += 1;
+        Some(RwLockReadGuard::new(self))
+    }
+
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<'_, T>> {
+        let mut state = self.state.lock();
+        if state.writer || state.readers > 0 {
+            return None;
+        }
+        state.writer = true;
+        Some(RwLockWriteGuard::new(self))
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        RwLock::new(T::default())
+    }
+}
+
+/// Future returned by [`RwLock::read`]
+pub struct Read<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    key: Option<u64>,
+}
+
+impl<'a, T: ?Sized> Future for Read<'a, T> {
+    type Output = RwLockReadGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<RwLockReadGuard<'a, T>> {
+        let lock = self.lock;
+        let mut state = lock.state.lock();
+
+        match self.key {
+            None => {
+                // Queued writers go first
+                if !state.writer && state.write_waiters.pending() == 0 {
+                    state.readers += 1;
+                    return Poll::Ready(RwLockReadGuard::new(lock));
+                }
+                self.key = Some(state.read_waiters.push(cx.waker()));
+                Poll::Pending
+            }
+            Some(key) => {
+                // release_write() already counted us as a reader
+                if state.read_waiters.is_notified(key) {
+                    state.read_waiters.remove(key);
+                    self.key = None;
+                    return Poll::Ready(RwLockReadGuard::new(lock));
+                }
+                state.read_waiters.update(key, cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for Read<'_, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            let mut state = self.lock.state.lock();
+            if state.read_waiters.remove(key) == Some(true) {
+                state.release_read();
+            }
+        }
+    }
+}
+
+/// Future returned by [`RwLock::write`]
+pub struct Write<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    key: Option<u64>,
+}
+
+impl<'a, T: ?Sized> Future for Write<'a, T> {
+    type Output = RwLockWriteGuard<'a, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<RwLockWriteGuard<'a, T>> {
+        let lock = self.lock;
+        let mut state = lock.state.lock();
+
+        match self.key {
+            None => {
+                if !state.writer && state.readers == 0 {
+                    state.writer = true;
+                    return Poll::Ready(RwLockWriteGuard::new(lock));
+                }
+                self.key = Some(state.write_waiters.push(cx.waker()));
+                Poll::Pending
+            }
+            Some(key) => {
+                if state.write_waiters.is_notified(key) {
+                    state.write_waiters.remove(key);
+                    self.key = None;
+                    return Poll::Ready(RwLockWriteGuard::new(lock));
+                }
+                state.write_waiters.update(key, cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for Write<'_, T> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            let mut state = self.lock.state.lock();
+            match state.write_waiters.remove(key) {
+                Some(true) => state.release_write(),
+                // Readers may have queued only because we were waiting
+                Some(false) if !state.writer && state.write_waiters.pending() == 0 => {
+                    let readers = state.read_waiters.notify_all();
+                    state.readers += readers;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    _marker: PhantomData<&'a T>,
+}
+
+impl<'a, T: ?Sized> RwLockReadGuard<'a, T> {
+    fn new(lock: &'a RwLock<T>) -> Self {
+        RwLockReadGuard {
+            lock,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.lock().release_read();
+    }
+}
+
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    _marker: PhantomData<&'a mut T>,
+}
+
+impl<'a, T: ?Sized> RwLockWriteGuard<'a, T> {
+    fn new(lock: &'a RwLock<T>) -> Self {
+        RwLockWriteGuard {
+            lock,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.lock().release_write();
+    }
+}
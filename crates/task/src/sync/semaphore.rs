@@ -0,0 +1,158 @@
+use alloc::sync::Arc;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use super::waiters::WaitList;
+
+/// Counting semaphore for bounding concurrency.
+///
+/// Like [`Mutex`](super::Mutex), released permits are handed straight to the
+/// oldest waiter.
+pub struct Semaphore {
+    state: spin::Mutex<State>,
+}
+
+struct State {
+    permits: usize,
+    waiters: WaitList,
+}
+
+impl State {
+    fn release(&mut self, permits: usize) {
+        for _ in 0..permits {
+            if !self.waiters.notify_one() {
+                self.permits += 1;
+            }
+        }
+    }
+}
+
+impl Semaphore {
+    pub const fn new(permits: usize) -> Self {
+        Semaphore {
+            state: spin::Mutex::new(State {
+                permits,
+                waiters: WaitList::new(),
+            }),
+        }
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.state.lock().permits
+    }
+
+    pub fn add_permits(&self, permits: usize) {
+        self.state.lock().release(permits);
+    }
+
+    pub fn acquire(&self) -> Acquire<'_> {
+        Acquire {
+            semaphore: self,
+            key: None,
+        }
+    }
+
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        let mut state = self.state.lock();
+        // Don't jump the queue of parked tasks
+        if state.permits == 0 || state.waiters.pending() > 0 {
+            return None;
+        }
+        state.permits -= 1;
+        Some(SemaphorePermit { semaphore: self })
+    }
+
+    /// Permit that keeps the semaphore alive, so it can be moved into a spawned task
+    pub async fn acquire_owned(self: Arc<Self>) -> OwnedSemaphorePermit {
+        self.acquire().await.forget();
+        OwnedSemaphorePermit { semaphore: self }
+    }
+
+    pub fn try_acquire_owned(self: Arc<Self>) -> Option<OwnedSemaphorePermit> {
+        self.try_acquire()?.forget();
+        Some(OwnedSemaphorePermit { semaphore: self })
+    }
+}
+
+/// Future returned by [`Semaphore::acquire`]
+pub struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+    key: Option<u64>,
+}
+
+impl<'a> Future for Acquire<'a> {
+    type Output = SemaphorePermit<'a>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<SemaphorePermit<'a>> {
+        let semaphore = self.semaphore;
+        let mut state = semaphore.state.lock();
+
+        match self.key {
+            None => {
+                if state.permits > 0 {
+                    state.permits -= 1;
+                    return Poll::Ready(SemaphorePermit { semaphore });
+                }
+                self.key = Some(state.waiters.push(cx.waker()));
+                Poll::Pending
+            }
+            Some(key) => {
+                if state.waiters.is_notified(key) {
+                    state.waiters.remove(key);
+                    self.key = None;
+                    return Poll::Ready(SemaphorePermit { semaphore });
+                }
+                state.waiters.update(key, cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Acquire<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            let mut state = self.semaphore.state.lock();
+            if state.waiters.remove(key) == Some(true) {
+                state.release(1);
+            }
+        }
+    }
+}
+
+/// Returns its permit to the semaphore when dropped
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl SemaphorePermit<'_> {
+    /// Drop the permit without returning it, shrinking the semaphore
+    pub fn forget(self) {
+        core::mem::forget(self);
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        self.semaphore.add_permits(1);
+    }
+}
+
+pub struct OwnedSemaphorePermit {
+    semaphore: Arc<Semaphore>,
+}
+
+impl OwnedSemaphorePermit {
+    pub fn semaphore(&self) -> &Arc<Semaphore> {
+        &self.semaphore
+    }
+}
+
+impl Drop for OwnedSemaphorePermit {
+    fn drop(&mut self) {
+        self.semaphore.add_permits(1);
+    }
+}
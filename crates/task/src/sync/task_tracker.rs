@@ -0,0 +1,176 @@
+use alloc::sync::Arc;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use pin_project_lite::pin_project;
+
+use super::waiters::WaitList;
+
+/// Counts running tasks so `main` can wait for all of them on shutdown.
+///
+/// `wait()` resolves once the tracker is closed and every token has been
+/// dropped. Closing first means tasks spawned before `wait()` is called are
+/// not mistaken for "all done" while the count briefly touches zero.
+///
+/// ```ignore
+/// let tracker = TaskTracker::new();
+/// executor.spawn(Task::new(tracker.track_future(keyboard::print_keypresses())));
+/// // ... broadcast stop ...
+/// tracker.close();
+/// tracker.wait().await;
+/// ```
+#[derive(Clone)]
+pub struct TaskTracker {
+    inner: Arc<spin::Mutex<State>>,
+}
+
+struct State {
+    tasks: usize,
+    closed: bool,
+    waiters: WaitList,
+}
+
+impl State {
+    fn is_done(&self) -> bool {
+        self.closed && self.tasks == 0
+    }
+}
+
+impl TaskTracker {
+    pub fn new() -> Self {
+        TaskTracker {
+            inner: Arc::new(spin::Mutex::new(State {
+                tasks: 0,
+                closed: false,
+                waiters: WaitList::new(),
+            })),
+        }
+    }
+
+    /// Count one task until the returned token is dropped
+    pub fn token(&self) -> TaskTrackerToken {
+        self.inner.lock().tasks += 1;
+        TaskTrackerToken {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Wrap a future so it is tracked until it completes or is dropped
+    pub fn track_future<F: Future>(&self, future: F) -> TrackedFuture<F> {
+        TrackedFuture {
+            future,
+            _token: self.token(),
+        }
+    }
+
+    pub fn close(&self) {
+        let mut state = self.inner.lock();
+        state.closed = true;
+        if state.is_done() {
+            state.waiters.notify_all();
+        }
+    }
+
+    pub fn reopen(&self) {
+        self.inner.lock().closed = false;
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.lock().closed
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().tasks
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn wait(&self) -> TrackerWait {
+        TrackerWait {
+            inner: self.inner.clone(),
+            key: None,
+        }
+    }
+}
+
+impl Default for TaskTracker {
+    fn default() -> Self {
+        TaskTracker::new()
+    }
+}
+
+pub struct TaskTrackerToken {
+    inner: Arc<spin::Mutex<State>>,
+}
+
+impl Drop for TaskTrackerToken {
+    fn drop(&mut self) {
+        let mut state = self.inner.lock();
+        state.tasks -= 1;
+        if state.is_done() {
+            state.waiters.notify_all();
+        }
+    }
+}
+
+pin_project! {
+    /// Future returned by [`TaskTracker::track_future`]
+    pub struct TrackedFuture<F> {
+        #[pin]
+        future: F,
+        _token: TaskTrackerToken,
+    }
+}
+
+impl<F: Future> Future for TrackedFuture<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<F::Output> {
+        self.project().future.poll(cx)
+    }
+}
+
+/// Future returned by [`TaskTracker::wait`]
+pub struct TrackerWait {
+    inner: Arc<spin::Mutex<State>>,
+    key: Option<u64>,
+}
+
+impl Future for TrackerWait {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let inner = self.inner.clone();
+        let mut state = inner.lock();
+        if state.is_done() {
+            if let Some(key) = self.key.take() {
+                state.waiters.remove(key);
+            }
+            return Poll::Ready(());
+        }
+        match self.key {
+            Some(key) if !state.waiters.is_notified(key) => state.waiters.update(key, cx.waker()),
+            // Woken, but the tracker was reopened in between: queue up again
+            key => {
+                if let Some(key) = key {
+                    state.waiters.remove(key);
+                }
+                self.key = Some(state.waiters.push(cx.waker()));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for TrackerWait {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            self.inner.lock().waiters.remove(key);
+        }
+    }
+}
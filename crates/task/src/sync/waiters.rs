@@ -0,0 +1,109 @@
+use alloc::collections::VecDeque;
+use core::task::Waker;
+
+/// FIFO list of parked tasks shared by the sync primitives.
+///
+/// Each waiting future gets a key on its first poll. Notifying a waiter marks
+/// its entry instead of removing it, so the future can tell on its next poll
+/// that it was picked (and a dropped future can pass the notification on).
+pub(crate) struct WaitList {
+    next_key: u64,
+    entries: VecDeque<Entry>,
+}
+
+struct Entry {
+    key: u64,
+    waker: Waker,
+    notified: bool,
+}
+
+impl WaitList {
+    pub(crate) const fn new() -> Self {
+        WaitList {
+            next_key: 0,
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// Park a new waiter at the back of the queue
+    pub(crate) fn push(&mut self, waker: &Waker) -> u64 {
+        let key = self.next_key;
+        self.next_key += 1;
+        self.entries.push_back(Entry {
+            key,
+            waker: waker.clone(),
+            notified: false,
+        });
+        key
+    }
+
+    /// Refresh the stored waker, the task may have been polled with a new one
+    pub(crate) fn update(&mut self, key: u64, waker: &Waker) {
+        if let Some(entry) = self.entries.iter_mut().find(|e| e.key == key)
+            && !entry.waker.will_wake(waker)
+        {
+            entry.waker = waker.clone();
+        }
+    }
+
+    /// Park under `key`, or stay parked keeping our place in the queue.
+    ///
+    /// For primitives where a woken task re-checks its condition: a waiter that
+    /// was notified but found nothing to do queues up again at the back.
+    pub(crate) fn register(&mut self, key: &mut Option<u64>, waker: &Waker) {
+        if let Some(k) = *key
+            && let Some(entry) = self.entries.iter_mut().find(|e| e.key == k && !e.notified)
+        {
+            if !entry.waker.will_wake(waker) {
+                entry.waker = waker.clone();
+            }
+            return;
+        }
+        if let Some(k) = key.take() {
+            self.remove(k);
+        }
+        *key = Some(self.push(waker));
+    }
+
+    pub(crate) fn is_notified(&self, key: u64) -> bool {
+        self.entries.iter().any(|e| e.key == key && e.notified)
+    }
+
+    /// Remove a waiter, returning whether it had already been notified
+    pub(crate) fn remove(&mut self, key: u64) -> Option<bool> {
+        let index = self.entries.iter().position(|e| e.key == key)?;
+        self.entries.remove(index).map(|e| e.notified)
+    }
+
+    /// Wake the oldest waiter that has not been notified yet
+    pub(crate) fn notify_one(&mut self) -> bool {
+        match self.entries.iter_mut().find(|e| !e.notified) {
+            Some(entry) => {
+                entry.notified = true;
+                entry.waker.wake_by_ref();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn notify_all(&mut self) -> usize {
+        let mut count = 0;
+        for entry in self.entries.iter_mut().filter(|e| !e.notified) {
+            entry.notified = true;
+            entry.waker.wake_by_ref();
+            count += 1;
+        }
+        count
+    }
+
+    /// Number of waiters still waiting to be notified
+    pub(crate) fn pending(&self) -> usize {
+        self.entries.iter().filter(|e| !e.notified).count()
+    }
+
+    /// No waiters, notified or not
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
@@ -0,0 +1,97 @@
+//!
+//! Monotonic clock
+//!
+//! The std build reads the host's clock. Without std the clock is whatever
+//! was handed to [`set_clock`], a timer interrupt's tick count for instance.
+//!
+//! [`SystemTime`] is the time of day on top of it, set from the host's clock
+//! or the RTC, and kept right over SNTP with the network. [`DateTime`] is a
+//! point of it in the calendar, for log timestamps, the shell's `date` and
+//! HTTP's `Date` header, written and read as RFC 3339:
+//! `2026-10-16T09:30:00Z`.
+//!
+
+mod date;
+mod system;
+
+use core::{
+    ops::{Add, AddAssign, Sub},
+    time::Duration,
+};
+
+pub use date::{DateTime, HttpDate, ParseDateTimeError, Rfc3339, Weekday};
+pub use system::{SystemTime, UNIX_EPOCH, pending_slew, set_system_time, slew_system_time};
+
+/// Point in time measured from boot.
+///
+/// On the std build "boot" is the first time the clock is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Instant(Duration);
+
+impl Instant {
+    pub fn now() -> Instant {
+        Instant(uptime())
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+
+    /// Zero if `earlier` is actually later
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        self.0.saturating_sub(earlier.0)
+    }
+
+    pub fn since_boot(&self) -> Duration {
+        self.0
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        self.0.checked_add(duration).map(Instant)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, rhs: Duration) -> Instant {
+        Instant(self.0 + rhs)
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, rhs: Duration) {
+        self.0 += rhs;
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Duration {
+        self.duration_since(rhs)
+    }
+}
+
+/// Time since boot
+#[cfg(feature = "std")]
+pub fn uptime() -> Duration {
+    static BOOT: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    BOOT.get_or_init(std::time::Instant::now).elapsed()
+}
+
+#[cfg(not(feature = "std"))]
+static CLOCK: spin::Once<fn() -> Duration> = spin::Once::new();
+
+/// Where [`uptime`] comes from, set it once at boot. Until then the time
+/// stands still at zero and no timer expires.
+#[cfg(not(feature = "std"))]
+pub fn set_clock(clock: fn() -> Duration) {
+    CLOCK.call_once(|| clock);
+}
+
+/// Time since boot, zero until [`set_clock`] is called
+#[cfg(not(feature = "std"))]
+pub fn uptime() -> Duration {
+    CLOCK.get().map_or(Duration::ZERO, |clock| clock())
+}
@@ -0,0 +1,352 @@
+use core::{
+    fmt,
+    ops::{Add, Sub},
+    str::FromStr,
+    time::Duration,
+};
+
+const SECONDS_PER_DAY: u64 = 86_400;
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Calendar date and time of day in UTC, fields in the usual ranges:
+/// month 1 to 12, day 1 to 31, hour 0 to 23
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// From seconds since 1970-01-01 00:00:00
+    pub fn from_unix(seconds: u64) -> DateTime {
+        let (year, month, day) = civil_from_days(seconds / SECONDS_PER_DAY);
+        let time = seconds % SECONDS_PER_DAY;
+        DateTime {
+            year,
+            month,
+            day,
+            hour: (time / 3600) as u8,
+            minute: (time / 60 % 60) as u8,
+            second: (time % 60) as u8,
+        }
+    }
+
+    /// Seconds since 1970-01-01 00:00:00, zero for earlier dates
+    pub fn to_unix(&self) -> u64 {
+        let days = days_from_civil(self.year, self.month, self.day);
+        let time = self.hour as u64 * 3600 + self.minute as u64 * 60 + self.second as u64;
+        days.saturating_mul(SECONDS_PER_DAY).saturating_add(time)
+    }
+
+    /// Whether every field is in range, the day for its month too
+    pub fn is_valid(&self) -> bool {
+        (1..=12).contains(&self.month)
+            && (1..=days_in_month(self.year, self.month)).contains(&self.day)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+
+    pub fn weekday(&self) -> Weekday {
+        let days = days_from_civil(self.year, self.month, self.day);
+        // 1970-01-01 was a Thursday
+        Weekday::ALL[(days as usize + 3) % 7]
+    }
+
+    /// `None` past the year 65535
+    pub fn checked_add(&self, duration: Duration) -> Option<DateTime> {
+        let seconds = self.to_unix().checked_add(duration.as_secs())?;
+        let date_time = DateTime::from_unix(seconds);
+        // Not if the year wrapped around
+        (date_time.to_unix() == seconds).then_some(date_time)
+    }
+
+    /// `None` before 1970
+    pub fn checked_sub(&self, duration: Duration) -> Option<DateTime> {
+        let seconds = self.to_unix().checked_sub(duration.as_secs())?;
+        Some(DateTime::from_unix(seconds))
+    }
+
+    /// `2026-10-16T09:30:00Z`
+    pub fn rfc3339(&self) -> Rfc3339 {
+        Rfc3339 {
+            date_time: *self,
+            micros: None,
+        }
+    }
+
+    /// `Fri, 16 Oct 2026 09:30:00 GMT`, for HTTP's `Date` header
+    pub fn http_date(&self) -> HttpDate {
+        HttpDate(*self)
+    }
+}
+
+/// Whole seconds, the rest of `rhs` is dropped
+impl Add<Duration> for DateTime {
+    type Output = DateTime;
+
+    fn add(self, rhs: Duration) -> DateTime {
+        self.checked_add(rhs).expect("date past the year 65535")
+    }
+}
+
+impl Sub<Duration> for DateTime {
+    type Output = DateTime;
+
+    fn sub(self, rhs: Duration) -> DateTime {
+        self.checked_sub(rhs).expect("date before 1970")
+    }
+}
+
+/// Zero if `rhs` is actually later
+impl Sub for DateTime {
+    type Output = Duration;
+
+    fn sub(self, rhs: DateTime) -> Duration {
+        Duration::from_secs(self.to_unix().saturating_sub(rhs.to_unix()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    const ALL: [Weekday; 7] = [
+        Weekday::Monday,
+        Weekday::Tuesday,
+        Weekday::Wednesday,
+        Weekday::Thursday,
+        Weekday::Friday,
+        Weekday::Saturday,
+        Weekday::Sunday,
+    ];
+
+    /// `Mon`, `Tue` and so on
+    pub fn short_name(&self) -> &'static str {
+        match self {
+            Weekday::Monday => "Mon",
+            Weekday::Tuesday => "Tue",
+            Weekday::Wednesday => "Wed",
+            Weekday::Thursday => "Thu",
+            Weekday::Friday => "Fri",
+            Weekday::Saturday => "Sat",
+            Weekday::Sunday => "Sun",
+        }
+    }
+}
+
+/// Shown by [`DateTime::rfc3339`] and
+/// [`SystemTime::rfc3339`](super::SystemTime::rfc3339)
+#[derive(Debug, Clone, Copy)]
+pub struct Rfc3339 {
+    date_time: DateTime,
+    // With a fraction of a second if set
+    micros: Option<u32>,
+}
+
+impl Rfc3339 {
+    pub(super) fn with_micros(date_time: DateTime, micros: u32) -> Rfc3339 {
+        Rfc3339 {
+            date_time,
+            micros: Some(micros),
+        }
+    }
+}
+
+impl fmt::Display for Rfc3339 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+        } = self.date_time;
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year, month, day, hour, minute, second
+        )?;
+        if let Some(micros) = self.micros {
+            write!(f, ".{:06}", micros)?;
+        }
+        f.write_str("Z")
+    }
+}
+
+/// Shown by [`DateTime::http_date`]
+#[derive(Debug, Clone, Copy)]
+pub struct HttpDate(DateTime);
+
+impl fmt::Display for HttpDate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let date_time = &self.0;
+        write!(
+            f,
+            "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+            date_time.weekday().short_name(),
+            date_time.day,
+            MONTHS[usize::from(date_time.month.clamp(1, 12)) - 1],
+            date_time.year,
+            date_time.hour,
+            date_time.minute,
+            date_time.second
+        )
+    }
+}
+
+/// `2026-10-16T09:30:00Z` and the like: a `T` or a space between date and
+/// time, the fraction of a second dropped, then `Z`, ` UTC`, an offset like
+/// `+02:00` or nothing for UTC. The offset is taken off.
+impl FromStr for DateTime {
+    type Err = ParseDateTimeError;
+
+    fn from_str(text: &str) -> Result<DateTime, ParseDateTimeError> {
+        let text = text.trim();
+        let (date, time) = text.split_once(['T', 't', ' ']).ok_or(ParseDateTimeError)?;
+        let [year, month, day] = fields(date, '-')?;
+        // Where the seconds end and the offset starts
+        let end = time
+            .find(|character: char| !character.is_ascii_digit() && character != ':')
+            .unwrap_or(time.len());
+        let [hour, minute, second] = fields(&time[..end], ':')?;
+        let mut rest = &time[end..];
+        if let Some(fraction) = rest.strip_prefix('.') {
+            let digits = fraction
+                .find(|character: char| !character.is_ascii_digit())
+                .unwrap_or(fraction.len());
+            if digits == 0 {
+                return Err(ParseDateTimeError);
+            }
+            rest = &fraction[digits..];
+        }
+        let offset = match rest {
+            "" | "Z" | "z" | " UTC" => 0,
+            offset => {
+                let (sign, offset) = match offset.split_at_checked(1) {
+                    Some(("+", offset)) => (1, offset),
+                    Some(("-", offset)) => (-1, offset),
+                    _ => return Err(ParseDateTimeError),
+                };
+                let [hours, minutes] = fields(offset, ':')?;
+                if hours > 23 || minutes > 59 {
+                    return Err(ParseDateTimeError);
+                }
+                sign * i64::from(hours * 3600 + minutes * 60)
+            }
+        };
+        let local = DateTime {
+            year: year.try_into().map_err(|_| ParseDateTimeError)?,
+            month: month.try_into().map_err(|_| ParseDateTimeError)?,
+            day: day.try_into().map_err(|_| ParseDateTimeError)?,
+            hour: hour.try_into().map_err(|_| ParseDateTimeError)?,
+            minute: minute.try_into().map_err(|_| ParseDateTimeError)?,
+            second: second.try_into().map_err(|_| ParseDateTimeError)?,
+        };
+        if !local.is_valid() {
+            return Err(ParseDateTimeError);
+        }
+        let utc = local.to_unix() as i64 - offset;
+        let utc = u64::try_from(utc).map_err(|_| ParseDateTimeError)?;
+        Ok(DateTime::from_unix(utc))
+    }
+}
+
+/// The `N` numbers in `text` between `separator`s
+fn fields<const N: usize>(text: &str, separator: char) -> Result<[u32; N], ParseDateTimeError> {
+    let mut fields = [0; N];
+    let mut parts = text.split(separator);
+    for field in &mut fields {
+        let part = parts.next().ok_or(ParseDateTimeError)?;
+        if part.is_empty() || !part.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(ParseDateTimeError);
+        }
+        *field = part.parse().map_err(|_| ParseDateTimeError)?;
+    }
+    match parts.next() {
+        Some(_) => Err(ParseDateTimeError),
+        None => Ok(fields),
+    }
+}
+
+/// Returned by parsing a [`DateTime`] that isn't RFC 3339
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseDateTimeError;
+
+impl fmt::Display for ParseDateTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("not a date and time like 2026-10-16T09:30:00Z")
+    }
+}
+
+/// `2026-10-16 09:30:00 UTC`
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+fn is_leap_year(year: u16) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Howard Hinnant's algorithms, with years starting in March so the leap
+// day comes last
+
+fn days_from_civil(year: u16, month: u8, day: u8) -> u64 {
+    let year = year as i64 - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month_from_march = (month as i64 + 9) % 12;
+    let day_of_year = (153 * month_from_march + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    // 719468 days from 0000-03-01 to 1970-01-01
+    (era * 146_097 + day_of_era - 719_468).max(0) as u64
+}
+
+fn civil_from_days(days: u64) -> (u16, u8, u8) {
+    let days = days as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = era * 400 + year_of_era + (month <= 2) as i64;
+    (year as u16, month as u8, day as u8)
+}
@@ -0,0 +1,182 @@
+use core::{
+    fmt,
+    ops::{Add, Sub},
+    time::Duration,
+};
+
+use super::{DateTime, Instant, date::Rfc3339, uptime};
+
+/// Wall-clock time, measured from 1970-01-01 00:00:00 UTC like
+/// `std::time::SystemTime`.
+///
+/// It runs with [`uptime`] from what [`set_system_time`] was last told, so
+/// unlike [`Instant`](super::Instant) it can jump. [`slew_system_time`]
+/// corrects it without jumping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SystemTime(Duration);
+
+pub const UNIX_EPOCH: SystemTime = SystemTime(Duration::ZERO);
+
+impl SystemTime {
+    pub const UNIX_EPOCH: SystemTime = UNIX_EPOCH;
+
+    pub fn now() -> SystemTime {
+        SystemTime(wall_time(uptime()))
+    }
+
+    /// The wall-clock time at `instant`, going by the clock as set now
+    pub fn from_instant(instant: Instant) -> SystemTime {
+        SystemTime(wall_time(instant.since_boot()))
+    }
+
+    /// `Err` with the difference if `earlier` is actually later
+    pub fn duration_since(&self, earlier: SystemTime) -> Result<Duration, Duration> {
+        self.0
+            .checked_sub(earlier.0)
+            .ok_or_else(|| earlier.0 - self.0)
+    }
+
+    /// `Err` if the clock was set back past `self` since
+    pub fn elapsed(&self) -> Result<Duration, Duration> {
+        SystemTime::now().duration_since(*self)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<SystemTime> {
+        self.0.checked_add(duration).map(SystemTime)
+    }
+
+    /// `None` before the epoch
+    pub fn checked_sub(&self, duration: Duration) -> Option<SystemTime> {
+        self.0.checked_sub(duration).map(SystemTime)
+    }
+
+    /// Down to the second
+    pub fn date_time(&self) -> DateTime {
+        DateTime::from_unix(self.0.as_secs())
+    }
+
+    /// `2026-10-16T09:30:00.250000Z`
+    pub fn rfc3339(&self) -> Rfc3339 {
+        Rfc3339::with_micros(self.date_time(), self.0.subsec_micros())
+    }
+}
+
+impl Add<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn add(self, rhs: Duration) -> SystemTime {
+        SystemTime(self.0 + rhs)
+    }
+}
+
+impl Sub<Duration> for SystemTime {
+    type Output = SystemTime;
+
+    fn sub(self, rhs: Duration) -> SystemTime {
+        SystemTime(self.0 - rhs)
+    }
+}
+
+impl From<DateTime> for SystemTime {
+    fn from(date_time: DateTime) -> SystemTime {
+        SystemTime(Duration::from_secs(date_time.to_unix()))
+    }
+}
+
+impl fmt::Display for SystemTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.date_time().fmt(f)
+    }
+}
+
+/// How fast [`slew_system_time`] moves the clock: half a millisecond a
+/// second, as `adjtime` does
+const SLEW_RATE_PPM: u128 = 500;
+
+struct Clock {
+    /// Wall-clock time when `uptime` was zero, before the slew
+    boot_time: Duration,
+    /// Uptime the slew started at
+    slew_since: Duration,
+    /// Nanoseconds the slew moves the clock in all, ahead if positive
+    slew: i64,
+}
+
+impl Clock {
+    /// Nanoseconds of the slew done by `uptime`
+    fn slewed(&self, uptime: Duration) -> i64 {
+        let most = uptime.saturating_sub(self.slew_since).as_nanos() * SLEW_RATE_PPM / 1_000_000;
+        let most = i64::try_from(most).unwrap_or(i64::MAX);
+        self.slew.clamp(-most, most)
+    }
+}
+
+// `None` until first needed
+static CLOCK: spin::Mutex<Option<Clock>> = spin::Mutex::new(None);
+
+fn with_clock<R>(f: impl FnOnce(&mut Clock) -> R) -> R {
+    f(CLOCK.lock().get_or_insert_with(|| Clock {
+        boot_time: initial_boot_time(),
+        slew_since: Duration::ZERO,
+        slew: 0,
+    }))
+}
+
+fn wall_time(uptime: Duration) -> Duration {
+    with_clock(|clock| offset_by(clock.boot_time + uptime, clock.slewed(uptime)))
+}
+
+fn offset_by(time: Duration, nanos: i64) -> Duration {
+    let by = Duration::from_nanos(nanos.unsigned_abs());
+    if nanos < 0 {
+        time.saturating_sub(by)
+    } else {
+        time + by
+    }
+}
+
+/// Correct the wall clock, with the RTC's reading at boot or a time
+/// server's answer later. [`Instant`](super::Instant)s and timers don't
+/// notice. A slew still going is called off.
+pub fn set_system_time(now: SystemTime) {
+    let uptime = uptime();
+    with_clock(|clock| {
+        clock.boot_time = now.0.saturating_sub(uptime);
+        clock.slew = 0;
+    });
+}
+
+/// Move the wall clock by `nanos`, ahead if positive, a little at a time
+/// instead of jumping: it runs half a millisecond a second fast or slow
+/// until it's there, so it never goes backwards. Adds to a slew still
+/// going.
+pub fn slew_system_time(nanos: i64) {
+    let uptime = uptime();
+    with_clock(|clock| {
+        let slewed = clock.slewed(uptime);
+        clock.boot_time = offset_by(clock.boot_time, slewed);
+        clock.slew_since = uptime;
+        clock.slew = (clock.slew - slewed).saturating_add(nanos);
+    });
+}
+
+/// Nanoseconds [`slew_system_time`] still has to move the clock by
+pub fn pending_slew() -> i64 {
+    let uptime = uptime();
+    with_clock(|clock| clock.slew - clock.slewed(uptime))
+}
+
+/// The host's clock
+#[cfg(feature = "std")]
+fn initial_boot_time() -> Duration {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    now.saturating_sub(uptime())
+}
+
+/// The epoch, until [`set_system_time`] knows better
+#[cfg(not(feature = "std"))]
+fn initial_boot_time() -> Duration {
+    Duration::ZERO
+}
@@ -0,0 +1,215 @@
+//!
+//! Timers: sleep, interval and timeout
+//!
+//! Pending timers are kept in one global queue ordered by deadline. The
+//! executor calls [`wake_expired`] every time around its run loop, which
+//! wakes every task whose deadline has passed.
+//!
+
+use alloc::collections::BTreeMap;
+use core::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use futures_util::Stream;
+use pin_project_lite::pin_project;
+
+use crate::{
+    metrics::{Counter, Gauge},
+    time::Instant,
+};
+
+static FIRED: Counter = Counter::new("timer_fired_total", "Timers woken at their deadline");
+static PENDING: Gauge = Gauge::new("timer_pending", "Timers waiting for their deadline");
+
+struct TimerQueue {
+    // Keyed by deadline first so the earliest timer is the first entry
+    timers: BTreeMap<(Instant, u64), Waker>,
+    next_id: u64,
+}
+
+static TIMERS: spin::Mutex<TimerQueue> = spin::Mutex::new(TimerQueue {
+    timers: BTreeMap::new(),
+    next_id: 0,
+});
+
+/// Wake all expired timers, returns the next deadline if any
+pub fn wake_expired() -> Option<Instant> {
+    let now = Instant::now();
+    let mut queue = TIMERS.lock();
+    while let Some(entry) = queue.timers.first_entry() {
+        if entry.key().0 > now {
+            break;
+        }
+        entry.remove().wake();
+        FIRED.inc();
+    }
+    PENDING.set(queue.timers.len() as i64);
+    queue.timers.first_key_value().map(|(key, _)| key.0)
+}
+
+/// Deadline of the earliest pending timer
+pub fn next_deadline() -> Option<Instant> {
+    TIMERS.lock().timers.first_key_value().map(|(key, _)| key.0)
+}
+
+pub fn sleep(duration: Duration) -> Sleep {
+    sleep_until(Instant::now() + duration)
+}
+
+pub fn sleep_until(deadline: Instant) -> Sleep {
+    Sleep { deadline, id: None }
+}
+
+/// Future returned by [`sleep`] and [`sleep_until`]
+pub struct Sleep {
+    deadline: Instant,
+    // Registration in the timer queue
+    id: Option<u64>,
+}
+
+impl Sleep {
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    pub fn is_elapsed(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+
+    /// Move the deadline, dropping the old registration
+    pub fn reset(&mut self, deadline: Instant) {
+        self.deregister();
+        self.deadline = deadline;
+    }
+
+    fn deregister(&mut self) {
+        if let Some(id) = self.id.take() {
+            TIMERS.lock().timers.remove(&(self.deadline, id));
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.is_elapsed() {
+            self.deregister();
+            return Poll::Ready(());
+        }
+
+        let mut queue = TIMERS.lock();
+        let id = match self.id {
+            Some(id) => id,
+            None => {
+                let id = queue.next_id;
+                queue.next_id += 1;
+                id
+            }
+        };
+        // (Re-)insert, the entry may already have fired and been removed
+        queue.timers.insert((self.deadline, id), cx.waker().clone());
+        self.id = Some(id);
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        self.deregister();
+    }
+}
+
+/// Stream of ticks every `period`, the first one after `period`
+pub fn interval(period: Duration) -> Interval {
+    assert!(!period.is_zero(), "interval period must be non-zero");
+    Interval {
+        period,
+        sleep: sleep(period),
+    }
+}
+
+/// Stream returned by [`interval`].
+///
+/// Ticks missed because the task was busy are skipped rather than fired in
+/// a burst.
+pub struct Interval {
+    period: Duration,
+    sleep: Sleep,
+}
+
+impl Interval {
+    pub async fn tick(&mut self) -> Instant {
+        core::future::poll_fn(|cx| self.poll_tick(cx)).await
+    }
+
+    pub fn poll_tick(&mut self, cx: &mut Context) -> Poll<Instant> {
+        if Pin::new(&mut self.sleep).poll(cx).is_pending() {
+            return Poll::Pending;
+        }
+        let tick = self.sleep.deadline();
+        let now = Instant::now();
+        let mut next = tick + self.period;
+        if next <= now {
+            next = now + self.period;
+        }
+        self.sleep.reset(next);
+        Poll::Ready(tick)
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+}
+
+impl Stream for Interval {
+    type Item = Instant;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Instant>> {
+        self.get_mut().poll_tick(cx).map(Some)
+    }
+}
+
+/// Error of a [`timeout`] that ran out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed;
+
+impl fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("deadline elapsed")
+    }
+}
+
+/// Run `future` but give up after `duration`
+pub fn timeout<F: Future>(duration: Duration, future: F) -> Timeout<F> {
+    Timeout {
+        future,
+        sleep: sleep(duration),
+    }
+}
+
+pin_project! {
+    /// Future returned by [`timeout`]
+    pub struct Timeout<F> {
+        #[pin]
+        future: F,
+        sleep: Sleep,
+    }
+}
+
+impl<F: Future> Future for Timeout<F> {
+    type Output = Result<F::Output, Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.project();
+        if let Poll::Ready(output) = this.future.poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+        Pin::new(this.sleep).poll(cx).map(|()| Err(Elapsed))
+    }
+}
@@ -0,0 +1,177 @@
+//!
+//! Scheduler event trace
+//!
+//! Once [`enable`]d the executors record what they do to their tasks, with
+//! the uptime, in a ring of the last [`CAPACITY`] events: spawns, wakes,
+//! the start and end of each poll and completion. Dump it when something
+//! looks off, the `trace` shell command does, or read it after a panic,
+//! whose report ends with the most recent events:
+//!
+//! ```text
+//!    12.304511s  task 4  wake
+//!    12.304530s  task 4  poll
+//!    12.304602s  task 4  pending
+//!    12.305113s  task 7  complete
+//! ```
+//!
+//! Recording doesn't lock or allocate, wakes from interrupt handlers are
+//! traced too. An event being overwritten while it's read is left out.
+//!
+
+use alloc::vec::Vec;
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering, fence},
+    time::Duration,
+};
+
+use crate::{TaskId, time};
+
+/// Events kept, older ones are overwritten
+pub const CAPACITY: usize = 1024;
+
+/// What happened to a task
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Spawn,
+    Wake,
+    /// A poll started
+    Poll,
+    /// The poll returned `Pending`
+    Pending,
+    /// The task finished or was dropped
+    Complete,
+}
+
+impl EventKind {
+    fn from_u8(kind: u8) -> Option<EventKind> {
+        Some(match kind {
+            0 => EventKind::Spawn,
+            1 => EventKind::Wake,
+            2 => EventKind::Poll,
+            3 => EventKind::Pending,
+            4 => EventKind::Complete,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            EventKind::Spawn => "spawn",
+            EventKind::Wake => "wake",
+            EventKind::Poll => "poll",
+            EventKind::Pending => "pending",
+            EventKind::Complete => "complete",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    /// Uptime when it happened
+    pub at: Duration,
+    pub task: TaskId,
+    pub kind: EventKind,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:>6}.{:06}s  task {}  {}",
+            self.at.as_secs(),
+            self.at.subsec_micros(),
+            self.task,
+            self.kind
+        )
+    }
+}
+
+struct Slot {
+    // Number of the event plus one, `0` while it's being written
+    sequence: AtomicU64,
+    at: AtomicU64,
+    task: AtomicU64,
+    kind: AtomicU8,
+}
+
+static SLOTS: [Slot; CAPACITY] = [const {
+    Slot {
+        sequence: AtomicU64::new(0),
+        at: AtomicU64::new(0),
+        task: AtomicU64::new(0),
+        kind: AtomicU8::new(0),
+    }
+}; CAPACITY];
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+// Events recorded so far, the next one's number
+static NEXT: AtomicU64 = AtomicU64::new(0);
+
+/// Start recording
+pub fn enable() {
+    ENABLED.store(true, Ordering::Release);
+}
+
+/// Stop recording, what's in the ring stays
+pub fn disable() {
+    ENABLED.store(false, Ordering::Release);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+/// Empty the ring
+pub fn clear() {
+    for slot in &SLOTS {
+        slot.sequence.store(0, Ordering::Release);
+    }
+}
+
+pub(crate) fn record(task: TaskId, kind: EventKind) {
+    if !is_enabled() {
+        return;
+    }
+    let number = NEXT.fetch_add(1, Ordering::Relaxed);
+    let slot = &SLOTS[number as usize % CAPACITY];
+    slot.sequence.store(0, Ordering::Relaxed);
+    fence(Ordering::Release);
+    slot.at
+        .store(time::uptime().as_nanos() as u64, Ordering::Relaxed);
+    slot.task.store(task.as_u64(), Ordering::Relaxed);
+    slot.kind.store(kind as u8, Ordering::Relaxed);
+    slot.sequence.store(number + 1, Ordering::Release);
+}
+
+/// The events in the ring, oldest first
+pub fn events() -> Vec<Event> {
+    let mut events: Vec<(u64, Event)> = SLOTS
+        .iter()
+        .filter_map(|slot| {
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            let event = Event {
+                at: Duration::from_nanos(slot.at.load(Ordering::Relaxed)),
+                task: TaskId(slot.task.load(Ordering::Relaxed)),
+                kind: EventKind::from_u8(slot.kind.load(Ordering::Relaxed))?,
+            };
+            fence(Ordering::Acquire);
+            // Rewritten in between otherwise
+            let unchanged = slot.sequence.load(Ordering::Relaxed) == sequence;
+            (sequence != 0 && unchanged).then_some((sequence, event))
+        })
+        .collect();
+    events.sort_unstable_by_key(|(sequence, _)| *sequence);
+    events.into_iter().map(|(_, event)| event).collect()
+}
+
+/// Write the last `count` events, one a line
+pub fn write_recent(out: &mut dyn fmt::Write, count: usize) -> fmt::Result {
+    let events = events();
+    for event in &events[events.len().saturating_sub(count)..] {
+        writeln!(out, "{}", event)?;
+    }
+    Ok(())
+}
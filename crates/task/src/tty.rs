@@ -0,0 +1,544 @@
+//!
+//! Terminals
+//!
+//! A [`Tty`] is a byte stream in, a writer out and the line discipline
+//! between them: echo, line editing in cooked mode, CR/LF translation. The
+//! shell and anything else interactive talk to a `Tty` and don't care
+//! whether it's the keyboard and screen or a serial line. Several can run
+//! at once, [`Tty::console`] next to a [`Tty::serial`] session for headless
+//! runs under QEMU.
+//!
+//! A `Tty` is an [`AsyncRead`] and [`AsyncWrite`] itself, with the line
+//! discipline applied, so generic IO code works on terminals too.
+//!
+//! Ctrl+C and Ctrl+Z typed while a job is in the terminal's
+//! [`Foreground`] raise a [`Signal`] for it instead of being input.
+//!
+
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    future::{Future, poll_fn},
+    pin::Pin,
+    str,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures_util::{Stream, StreamExt, ready};
+use pc_keyboard::{DecodedKey, KeyCode};
+
+use crate::{
+    console::{self, Console},
+    editor::{Edit, LineEditor},
+    io::{self, AsyncRead, AsyncWrite},
+    keyboard::DecodedKeyStream,
+    serial::Serial,
+    signal::{self, Foreground, Signal, Signals},
+};
+
+/// Input bytes of a terminal
+pub type TtyInput = Pin<Box<dyn Stream<Item = u8> + Send>>;
+
+/// Where a terminal's output goes
+pub type TtyOutput = Pin<Box<dyn AsyncWrite + Send>>;
+
+/// Line discipline settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TtyMode {
+    /// Write typed characters back
+    pub echo: bool,
+    /// Cooked mode: input is edited a line at a time and only handed out
+    /// once Enter is pressed. Off is raw mode, every byte as it comes.
+    pub canonical: bool,
+    /// Turn `\r` into `\n` on input and `\n` into `\r\n` on output, for
+    /// serial terminals
+    pub crlf: bool,
+}
+
+impl TtyMode {
+    pub const COOKED: TtyMode = TtyMode {
+        echo: true,
+        canonical: true,
+        crlf: false,
+    };
+    pub const RAW: TtyMode = TtyMode {
+        echo: false,
+        canonical: false,
+        crlf: false,
+    };
+}
+
+impl Default for TtyMode {
+    fn default() -> Self {
+        TtyMode::COOKED
+    }
+}
+
+/// End of input typed at the start of a line
+const CTRL_D: u8 = 0x04;
+const BACKSPACE: u8 = 0x08;
+const ESCAPE: u8 = 0x1b;
+const DELETE: u8 = 0x7f;
+
+pub struct Tty {
+    input: TtyInput,
+    // Shared with the terminals of jobs run from this one
+    output: Arc<spin::Mutex<TtyOutput>>,
+    mode: TtyMode,
+    // Cooked mode: the line being edited, then the finished line
+    line: Vec<u8>,
+    ready: VecDeque<u8>,
+    // Translated output and echo the output hasn't taken yet
+    outgoing: VecDeque<u8>,
+    foreground: Foreground,
+}
+
+impl Tty {
+    pub fn new(input: TtyInput, output: impl AsyncWrite + Send + 'static) -> Self {
+        Tty {
+            input,
+            output: Arc::new(spin::Mutex::new(Box::pin(output))),
+            mode: TtyMode::default(),
+            line: Vec::new(),
+            ready: VecDeque::new(),
+            outgoing: VecDeque::new(),
+            foreground: Foreground::new(),
+        }
+    }
+
+    /// The keyboard and the console, on the current task's
+    /// [virtual terminal](console::vt).
+    ///
+    /// Keys come out as UTF-8 and the arrow, Home/End and Delete keys as the
+    /// escape sequences a VT100 sends, needs [`keyboard::dispatch`] running.
+    ///
+    /// [`keyboard::dispatch`]: crate::keyboard::dispatch
+    pub fn console() -> Self {
+        Tty::vt(console::vt::current())
+    }
+
+    /// The keyboard and virtual terminal `vt`, like [`Tty::console`]. Keys
+    /// typed while another terminal is on screen don't reach it. Panics if
+    /// `vt` isn't below [`console::vt::COUNT`].
+    pub fn vt(vt: usize) -> Self {
+        // Shared with the keyboard dispatcher, which raises the signals
+        let foreground = signal::virtual_terminal(vt).clone();
+        let input = KeyboardInput {
+            vt,
+            keys: DecodedKeyStream::new(),
+            pending: VecDeque::new(),
+        };
+        let mut tty = Tty::new(Box::pin(input), ConsoleOutput { vt, locking: None });
+        tty.foreground = foreground;
+        tty
+    }
+
+    /// A terminal on the other end of a serial line, in cooked mode with
+    /// CR/LF translation.
+    ///
+    /// Takes the port's reading and writing halves, so one session per port.
+    pub fn serial(serial: &Serial) -> Self {
+        let (reader, writer) = serial.split();
+        let mut tty = Tty::new(Box::pin(reader), writer);
+        tty.mode.crlf = true;
+        tty
+    }
+
+    /// A terminal reading `input` and writing to this one's output, in the
+    /// same mode. For a job of the shell, with a foreground of its own.
+    pub fn with_input(&self, input: TtyInput) -> Self {
+        Tty {
+            input,
+            output: self.output.clone(),
+            mode: self.mode,
+            line: Vec::new(),
+            ready: VecDeque::new(),
+            outgoing: VecDeque::new(),
+            foreground: Foreground::new(),
+        }
+    }
+
+    pub fn mode(&self) -> TtyMode {
+        self.mode
+    }
+
+    /// Where this terminal's signals go, the shell runs its commands here
+    pub fn foreground(&self) -> &Foreground {
+        &self.foreground
+    }
+
+    /// See [`Foreground::signals`]
+    pub fn signals(&self) -> Option<Signals> {
+        self.foreground.signals()
+    }
+
+    /// Takes effect with the next read, a half typed line is kept
+    pub fn set_mode(&mut self, mode: TtyMode) {
+        if !mode.canonical {
+            // Hand out what was typed so far as is
+            self.ready.extend(self.line.drain(..));
+        }
+        self.mode = mode;
+    }
+
+    /// Next input byte, `None` at end of input
+    pub async fn read_byte(&mut self) -> Option<u8> {
+        poll_fn(|cx| self.poll_read_byte(cx)).await
+    }
+
+    fn poll_read_byte(&mut self, cx: &mut Context) -> Poll<Option<u8>> {
+        loop {
+            // Echo goes out before the byte is handed over
+            if self.poll_drain(cx).is_pending() {
+                return Poll::Pending;
+            }
+            if let Some(byte) = self.ready.pop_front() {
+                return Poll::Ready(Some(byte));
+            }
+            let Some(mut byte) = ready!(self.input.poll_next_unpin(cx)) else {
+                return Poll::Ready(None);
+            };
+            if self.mode.crlf && byte == b'\r' {
+                byte = b'\n';
+            }
+            if Signal::from_byte(byte).is_some_and(|signal| self.foreground.raise(signal)) {
+                continue;
+            }
+            if !self.mode.canonical {
+                self.echo(&[byte]);
+                self.ready.push_back(byte);
+            } else if !self.edit(byte) {
+                return Poll::Ready(None);
+            }
+        }
+    }
+
+    /// Next line without the newline, `None` at end of input.
+    ///
+    /// In raw mode no editing happens, the line is the bytes up to `\n`.
+    pub async fn read_line(&mut self) -> Option<String> {
+        let mut line = Vec::new();
+        loop {
+            match self.read_byte().await {
+                Some(b'\n') => break,
+                Some(byte) => line.push(byte),
+                None if line.is_empty() => return None,
+                None => break,
+            }
+        }
+        Some(String::from_utf8_lossy(&line).into_owned())
+    }
+
+    /// Next line edited with `editor`, after showing `prompt`. `None` at end
+    /// of input.
+    ///
+    /// Cooked mode editing is off meanwhile, the editor takes every key. With
+    /// echo off nothing but the prompt is drawn, for a terminal on the other
+    /// end that shows what's typed itself.
+    pub async fn edit_line(&mut self, editor: &mut LineEditor, prompt: &str) -> Option<String> {
+        let mode = self.mode;
+        self.set_mode(TtyMode {
+            echo: false,
+            canonical: false,
+            ..mode
+        });
+        let line = self.run_editor(editor, prompt, mode.echo).await;
+        self.set_mode(mode);
+        line
+    }
+
+    async fn run_editor(
+        &mut self,
+        editor: &mut LineEditor,
+        prompt: &str,
+        echo: bool,
+    ) -> Option<String> {
+        self.write_str(prompt).await;
+        editor.reset_display();
+        loop {
+            let key = self.read_key().await?;
+            let edit = match editor.handle(key) {
+                Edit::Complete => editor.complete().await,
+                edit => edit,
+            };
+            let output = match edit {
+                Edit::Submit(line) => {
+                    if echo {
+                        self.write(b"\n").await;
+                    }
+                    return Some(line);
+                }
+                Edit::EndOfInput => return None,
+                Edit::Unchanged | Edit::Complete => continue,
+                Edit::Changed => editor.redraw(),
+                Edit::Candidates(words) => {
+                    editor.reset_display();
+                    format!("\n{}\n{}{}", words.join("  "), prompt, editor.redraw())
+                }
+            };
+            if echo {
+                self.write_str(&output).await;
+            }
+        }
+    }
+
+    /// Next input as a key, with UTF-8 and the escape sequences
+    /// [`Tty::console`] sends decoded
+    async fn read_key(&mut self) -> Option<DecodedKey> {
+        let byte = self.read_byte().await?;
+        Some(match byte {
+            ESCAPE => self.read_escape().await?,
+            // Terminals send DEL for Backspace
+            BACKSPACE | DELETE => DecodedKey::RawKey(KeyCode::Backspace),
+            0x80.. => {
+                let len = match byte {
+                    0xc0..=0xdf => 2,
+                    0xe0..=0xef => 3,
+                    0xf0..=0xf7 => 4,
+                    _ => 1,
+                };
+                let mut buf = [byte, 0, 0, 0];
+                for slot in &mut buf[1..len] {
+                    *slot = self.read_byte().await?;
+                }
+                let character = str::from_utf8(&buf[..len])
+                    .ok()
+                    .and_then(|s| s.chars().next());
+                DecodedKey::Unicode(character.unwrap_or(char::REPLACEMENT_CHARACTER))
+            }
+            byte => DecodedKey::Unicode(char::from(byte)),
+        })
+    }
+
+    /// The rest of a `CSI` sequence, anything unknown comes out as Escape
+    async fn read_escape(&mut self) -> Option<DecodedKey> {
+        if self.read_byte().await? != b'[' {
+            return Some(DecodedKey::RawKey(KeyCode::Escape));
+        }
+        let mut param = 0u16;
+        let action = loop {
+            match self.read_byte().await? {
+                digit @ b'0'..=b'9' => {
+                    param = param
+                        .saturating_mul(10)
+                        .saturating_add(u16::from(digit - b'0'));
+                }
+                // Final byte
+                byte @ 0x40..=0x7e => break byte,
+                _ => {}
+            }
+        };
+        let code = match (action, param) {
+            (b'A', _) => KeyCode::ArrowUp,
+            (b'B', _) => KeyCode::ArrowDown,
+            (b'C', _) => KeyCode::ArrowRight,
+            (b'D', _) => KeyCode::ArrowLeft,
+            (b'H', _) | (b'~', 1 | 7) => KeyCode::Home,
+            (b'F', _) | (b'~', 4 | 8) => KeyCode::End,
+            (b'~', 3) => KeyCode::Delete,
+            _ => KeyCode::Escape,
+        };
+        Some(DecodedKey::RawKey(code))
+    }
+
+    /// Write `bytes` translating newlines as the mode says, returns once
+    /// the output has taken them.
+    ///
+    /// Output to a closed terminal is dropped, [`AsyncWrite`] reports it.
+    pub async fn write(&mut self, bytes: &[u8]) {
+        self.queue(bytes);
+        let _ = poll_fn(|cx| self.poll_drain(cx)).await;
+    }
+
+    pub async fn write_str(&mut self, text: &str) {
+        self.write(text.as_bytes()).await;
+    }
+
+    /// Add `bytes` to the output translating newlines as the mode says
+    fn queue(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if self.mode.crlf && byte == b'\n' {
+                self.outgoing.push_back(b'\r');
+            }
+            self.outgoing.push_back(byte);
+        }
+    }
+
+    /// Hand everything queued to the output
+    fn poll_drain(&mut self, cx: &mut Context) -> Poll<io::Result<()>> {
+        while !self.outgoing.is_empty() {
+            // In one piece so a UTF-8 character isn't split
+            let bytes = self.outgoing.make_contiguous();
+            match ready!(self.output.lock().as_mut().poll_write(cx, bytes)) {
+                Ok(0) | Err(_) => {
+                    // Nobody is listening anymore
+                    self.outgoing.clear();
+                    return Poll::Ready(Err(io::Error::BrokenPipe));
+                }
+                Ok(count) => drop(self.outgoing.drain(..count)),
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Cooked mode line editing, `false` at end of input
+    fn edit(&mut self, byte: u8) -> bool {
+        match byte {
+            b'\n' => {
+                self.line.push(b'\n');
+                self.ready.extend(self.line.drain(..));
+                self.echo(b"\n");
+            }
+            BACKSPACE | DELETE => {
+                // Whole UTF-8 characters, continuation bytes are 0b10xxxxxx
+                while let Some(last) = self.line.pop() {
+                    if last & 0xc0 != 0x80 {
+                        // Step back, blank the character and step back again
+                        self.echo(b"\x08 \x08");
+                        break;
+                    }
+                }
+            }
+            // Like a closed input if nothing was typed, else ends the line
+            // without a newline
+            CTRL_D if self.line.is_empty() => return false,
+            CTRL_D => self.ready.extend(self.line.drain(..)),
+            // Other control bytes, escape sequences included, aren't edited
+            byte if byte < 0x20 => {}
+            byte => {
+                self.line.push(byte);
+                self.echo(&[byte]);
+            }
+        }
+        true
+    }
+
+    fn echo(&mut self, bytes: &[u8]) {
+        if self.mode.echo {
+            self.queue(bytes);
+        }
+    }
+}
+
+impl AsyncRead for Tty {
+    /// Whatever input is ready, a line at a time in cooked mode
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let mut count = 0;
+        while count < buf.len() {
+            match this.poll_read_byte(cx) {
+                Poll::Ready(Some(byte)) => {
+                    buf[count] = byte;
+                    count += 1;
+                }
+                Poll::Ready(None) => break,
+                Poll::Pending if count == 0 => return Poll::Pending,
+                Poll::Pending => break,
+            }
+        }
+        Poll::Ready(Ok(count))
+    }
+}
+
+impl AsyncWrite for Tty {
+    /// Takes all of `bytes` once earlier output is out, poll
+    /// [`AsyncWrite::poll_flush`] to see them out too
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, bytes: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        this.queue(bytes);
+        // Get things going, the rest goes with the next write or flush
+        let _ = this.poll_drain(cx);
+        Poll::Ready(Ok(bytes.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        this.output.lock().as_mut().poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        this.output.lock().as_mut().poll_close(cx)
+    }
+}
+
+/// [`Tty::console`] output
+struct ConsoleOutput {
+    vt: usize,
+    // Waiting for the console between polls
+    locking: Option<Pin<Box<dyn Future<Output = Console> + Send>>>,
+}
+
+impl AsyncWrite for ConsoleOutput {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context, bytes: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let locking = this
+            .locking
+            .get_or_insert_with(|| Box::pin(console::lock_vt(this.vt)));
+        let mut console = ready!(locking.as_mut().poll(cx));
+        this.locking = None;
+        // A character cut off at the end waits for the next write
+        let count = match str::from_utf8(bytes) {
+            Err(err) if err.error_len().is_none() && err.valid_up_to() > 0 => err.valid_up_to(),
+            _ => bytes.len(),
+        };
+        // Shown when the guard is dropped
+        let _ = console.write_str(&String::from_utf8_lossy(&bytes[..count]));
+        Poll::Ready(Ok(count))
+    }
+}
+
+/// [`Tty::console`] input, decoded keys as terminal bytes
+struct KeyboardInput {
+    // Keys typed on the others are dropped
+    vt: usize,
+    keys: DecodedKeyStream,
+    pending: VecDeque<u8>,
+}
+
+impl Stream for KeyboardInput {
+    type Item = u8;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<u8>> {
+        let this = &mut *self;
+        loop {
+            if let Some(byte) = this.pending.pop_front() {
+                return Poll::Ready(Some(byte));
+            }
+            let Some(key) = ready!(this.keys.poll_next_unpin(cx)) else {
+                return Poll::Ready(None);
+            };
+            if console::vt::active() != this.vt {
+                continue;
+            }
+            let sequence: &[u8] = match key {
+                // The Delete key decodes to DEL, which a terminal sends
+                // for Backspace
+                DecodedKey::Unicode('\u{7f}') | DecodedKey::RawKey(KeyCode::Delete) => b"\x1b[3~",
+                DecodedKey::Unicode(character) => {
+                    let mut buf = [0; 4];
+                    this.pending
+                        .extend(character.encode_utf8(&mut buf).as_bytes());
+                    continue;
+                }
+                DecodedKey::RawKey(KeyCode::ArrowUp) => b"\x1b[A",
+                DecodedKey::RawKey(KeyCode::ArrowDown) => b"\x1b[B",
+                DecodedKey::RawKey(KeyCode::ArrowRight) => b"\x1b[C",
+                DecodedKey::RawKey(KeyCode::ArrowLeft) => b"\x1b[D",
+                DecodedKey::RawKey(KeyCode::Home) => b"\x1b[H",
+                DecodedKey::RawKey(KeyCode::End) => b"\x1b[F",
+                DecodedKey::RawKey(KeyCode::Backspace) => &[BACKSPACE],
+                DecodedKey::RawKey(_) => continue,
+            };
+            this.pending.extend(sequence);
+        }
+    }
+}
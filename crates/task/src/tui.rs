@@ -0,0 +1,406 @@
+//!
+//! Terminal UI
+//!
+//! Full-screen text interfaces on the ANSI console. A [`Ui`] owns a
+//! [`Screen`] of character cells and a set of [`Widget`]s, each drawn into
+//! its own [`Rect`]. Drawing only touches the cell buffer, [`Ui::draw`] then
+//! sends the cells that changed since the last frame to the console.
+//!
+//! Input comes from an [`InputStream`] and goes to the focused widget. Tab
+//! moves the focus between widgets that take input, unless the focused one
+//! uses it. Ctrl+C and Ctrl+V copy from and paste into the focused widget
+//! through the [`clipboard`](crate::clipboard).
+//!
+//! ```ignore
+//! let area = Rect::new(0, 0, 80, 25);
+//! let (main, status) = area.split_bottom(1);
+//! let mut ui = Ui::new(area);
+//! let log = ui.add(main, Pane::new("log", TextArea::new(100)));
+//! ui.add(status, StatusBar::new("Tab: focus  Esc: quit"));
+//!
+//! let mut input = InputStream::new();
+//! loop {
+//!     ui.draw().await;
+//!     let Some(event) = input.next().await else { break };
+//!     if let Input::Key(KeyEvent { code: KeyCode::Escape, .. }) = event.input {
+//!         break;
+//!     }
+//!     ui.handle(&event);
+//! }
+//! ```
+//!
+//! [`InputStream`]: crate::input::InputStream
+//!
+
+mod widgets;
+
+use std::{any::Any, fmt::Write as _};
+
+use pc_keyboard::{KeyCode, KeyState};
+
+use crate::{
+    clipboard, console,
+    input::{Input, InputEvent},
+    keyboard::{self, Hotkey},
+};
+
+pub use crate::gfx::Rect;
+pub use widgets::{InputField, Pane, StatusBar, TextArea};
+
+/// The eight ANSI colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Black = 0,
+    Red = 1,
+    Green = 2,
+    Yellow = 3,
+    Blue = 4,
+    Magenta = 5,
+    Cyan = 6,
+    White = 7,
+}
+
+/// How a cell is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    pub foreground: Color,
+    pub background: Color,
+    /// The bright variant of the foreground color
+    pub bright: bool,
+}
+
+impl Style {
+    /// White on black
+    pub const NORMAL: Style = Style::new(Color::White, Color::Black);
+
+    pub const fn new(foreground: Color, background: Color) -> Self {
+        Style {
+            foreground,
+            background,
+            bright: false,
+        }
+    }
+
+    pub const fn bright(mut self) -> Self {
+        self.bright = true;
+        self
+    }
+
+    fn write_escape(self, out: &mut String) {
+        // Reset first so nothing carries over from the previous style
+        let foreground = self.foreground as u8 + if self.bright { 90 } else { 30 };
+        let background = self.background as u8 + 40;
+        let _ = write!(out, "\x1b[0;{};{}m", foreground, background);
+    }
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Style::NORMAL
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    character: char,
+    style: Style,
+}
+
+const BLANK: Cell = Cell {
+    character: ' ',
+    style: Style::NORMAL,
+};
+
+/// A grid of character cells, drawn on the console by [`Screen::render`].
+///
+/// Everything drawn is clipped to the screen.
+pub struct Screen {
+    area: Rect,
+    cells: Vec<Cell>,
+    // What the console shows, `None` until the first render
+    shown: Option<Vec<Cell>>,
+}
+
+impl Screen {
+    /// Covering `area` of the console, in cells
+    pub fn new(area: Rect) -> Self {
+        Screen {
+            area,
+            cells: vec![BLANK; area.width * area.height],
+            shown: None,
+        }
+    }
+
+    pub fn area(&self) -> Rect {
+        self.area
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.fill(BLANK);
+    }
+
+    /// Set the cell at column `x` and row `y`, both counted from the top
+    /// left of the console
+    pub fn set(&mut self, x: usize, y: usize, character: char, style: Style) {
+        if let Some(index) = self.index(x, y) {
+            self.cells[index] = Cell { character, style };
+        }
+    }
+
+    /// Write `text` on one row starting at `x`, cut off after `width` cells.
+    /// Returns the cells used.
+    pub fn print(&mut self, x: usize, y: usize, text: &str, width: usize, style: Style) -> usize {
+        let mut used = 0;
+        for character in text.chars().take(width) {
+            self.set(x + used, y, character, style);
+            used += 1;
+        }
+        used
+    }
+
+    pub fn fill(&mut self, area: Rect, character: char, style: Style) {
+        for y in area.y..area.y + area.height {
+            for x in area.x..area.x + area.width {
+                self.set(x, y, character, style);
+            }
+        }
+    }
+
+    /// Draw a frame of ASCII lines along the edges of `area`, with `title`
+    /// in the top edge
+    pub fn frame(&mut self, area: Rect, title: &str, style: Style) {
+        if area.width < 2 || area.height < 2 {
+            return;
+        }
+        let (right, bottom) = (area.x + area.width - 1, area.y + area.height - 1);
+        for x in area.x + 1..right {
+            self.set(x, area.y, '-', style);
+            self.set(x, bottom, '-', style);
+        }
+        for y in area.y + 1..bottom {
+            self.set(area.x, y, '|', style);
+            self.set(right, y, '|', style);
+        }
+        for (x, y) in [
+            (area.x, area.y),
+            (right, area.y),
+            (area.x, bottom),
+            (right, bottom),
+        ] {
+            self.set(x, y, '+', style);
+        }
+        if !title.is_empty() {
+            self.print(
+                area.x + 2,
+                area.y,
+                title,
+                area.width.saturating_sub(4),
+                style,
+            );
+        }
+    }
+
+    /// Redraw everything on the next render, after something else wrote to
+    /// the console
+    pub fn invalidate(&mut self) {
+        self.shown = None;
+    }
+
+    /// Send the cells that changed since the last render to the console
+    pub async fn render(&mut self) {
+        let mut out = String::new();
+        let mut style = None;
+        for row in 0..self.area.height {
+            // Cursor position after the last cell written, if on this row
+            let mut cursor = None;
+            for column in 0..self.area.width {
+                let index = row * self.area.width + column;
+                let cell = self.cells[index];
+                if self
+                    .shown
+                    .as_ref()
+                    .is_some_and(|shown| shown[index] == cell)
+                {
+                    continue;
+                }
+                if cursor != Some(column) {
+                    // 1-based
+                    let (x, y) = (self.area.x + column + 1, self.area.y + row + 1);
+                    let _ = write!(out, "\x1b[{};{}H", y, x);
+                }
+                if style != Some(cell.style) {
+                    cell.style.write_escape(&mut out);
+                    style = Some(cell.style);
+                }
+                out.push(cell.character);
+                cursor = Some(column + 1);
+            }
+        }
+        if out.is_empty() {
+            return;
+        }
+        out.push_str("\x1b[0m");
+        console::write_str(&out).await;
+        self.shown = Some(self.cells.clone());
+    }
+
+    fn index(&self, x: usize, y: usize) -> Option<usize> {
+        let (column, row) = (x.checked_sub(self.area.x)?, y.checked_sub(self.area.y)?);
+        (column < self.area.width && row < self.area.height)
+            .then_some(row * self.area.width + column)
+    }
+}
+
+/// Part of a [`Ui`]
+pub trait Widget: Any + Send {
+    /// Draw into `area` of `screen`, `focused` if input goes here
+    fn draw(&self, screen: &mut Screen, area: Rect, focused: bool);
+
+    /// React to input while focused, `true` if it was used
+    fn handle_input(&mut self, _input: &Input) -> bool {
+        false
+    }
+
+    /// Whether Tab stops here
+    fn focusable(&self) -> bool {
+        false
+    }
+
+    /// Text for [`clipboard::COPY`], `None` if there's nothing to copy
+    fn copy(&self) -> Option<String> {
+        None
+    }
+
+    /// Take `text` from [`clipboard::PASTE`], `true` if it was used
+    fn paste(&mut self, _text: &str) -> bool {
+        false
+    }
+}
+
+/// Handle to a widget added with [`Ui::add`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WidgetId(usize);
+
+/// Widgets laid out on a [`Screen`], with one of them focused
+pub struct Ui {
+    screen: Screen,
+    widgets: Vec<(Rect, Box<dyn Widget>)>,
+    focus: Option<usize>,
+}
+
+impl Ui {
+    pub fn new(area: Rect) -> Self {
+        Ui {
+            screen: Screen::new(area),
+            widgets: Vec::new(),
+            focus: None,
+        }
+    }
+
+    pub fn area(&self) -> Rect {
+        self.screen.area()
+    }
+
+    /// Show `widget` in `area`. The first focusable widget gets the focus.
+    pub fn add(&mut self, area: Rect, widget: impl Widget) -> WidgetId {
+        let id = self.widgets.len();
+        if self.focus.is_none() && widget.focusable() {
+            self.focus = Some(id);
+        }
+        self.widgets.push((area, Box::new(widget)));
+        WidgetId(id)
+    }
+
+    /// The widget `id` if it's a `W`
+    pub fn get<W: Widget>(&self, id: WidgetId) -> Option<&W> {
+        let widget: &dyn Any = self.widgets.get(id.0)?.1.as_ref();
+        widget.downcast_ref()
+    }
+
+    /// The widget `id` if it's a `W`, to change what it shows
+    pub fn get_mut<W: Widget>(&mut self, id: WidgetId) -> Option<&mut W> {
+        let widget: &mut dyn Any = self.widgets.get_mut(id.0)?.1.as_mut();
+        widget.downcast_mut()
+    }
+
+    pub fn focused(&self) -> Option<WidgetId> {
+        self.focus.map(WidgetId)
+    }
+
+    /// Focus `id` if it takes input
+    pub fn focus(&mut self, id: WidgetId) {
+        if self.widgets.get(id.0).is_some_and(|(_, w)| w.focusable()) {
+            self.focus = Some(id.0);
+        }
+    }
+
+    /// Move the focus to the next focusable widget, wrapping around
+    pub fn focus_next(&mut self) {
+        let count = self.widgets.len();
+        let start = self.focus.map_or(0, |focus| focus + 1);
+        self.focus = (0..count)
+            .map(|offset| (start + offset) % count)
+            .find(|&index| self.widgets[index].1.focusable())
+            .or(self.focus);
+    }
+
+    /// Pass `event` to the focused widget, `true` if something used it.
+    ///
+    /// A Tab the widget doesn't use moves the focus. The clipboard hotkeys
+    /// go to [`Widget::copy`] and [`Widget::paste`] first.
+    pub fn handle(&mut self, event: &InputEvent) -> bool {
+        if let Some(focus) = self.focus {
+            let widget = &mut self.widgets[focus].1;
+            if Self::clipboard(widget.as_mut(), &event.input) || widget.handle_input(&event.input) {
+                return true;
+            }
+        }
+        match &event.input {
+            Input::Key(key) if key.code == KeyCode::Tab => {
+                if key.state == KeyState::Down {
+                    self.focus_next();
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn clipboard(widget: &mut dyn Widget, input: &Input) -> bool {
+        let Input::Key(key) = input else {
+            return false;
+        };
+        if key.state != KeyState::Down {
+            return false;
+        }
+        let hotkey = Hotkey {
+            modifiers: keyboard::modifiers().held(),
+            key: key.code,
+        };
+        if hotkey == clipboard::COPY {
+            let Some(text) = widget.copy() else {
+                return false;
+            };
+            clipboard::copy(text);
+            true
+        } else if hotkey == clipboard::PASTE {
+            widget.paste(&clipboard::paste())
+        } else {
+            false
+        }
+    }
+
+    /// Draw every widget and show the result
+    pub async fn draw(&mut self) {
+        self.screen.clear();
+        for (index, (area, widget)) in self.widgets.iter().enumerate() {
+            widget.draw(&mut self.screen, *area, self.focus == Some(index));
+        }
+        self.screen.render().await;
+    }
+
+    /// Redraw the whole screen next time, see [`Screen::invalidate`]
+    pub fn invalidate(&mut self) {
+        self.screen.invalidate();
+    }
+}
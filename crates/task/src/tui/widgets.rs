@@ -0,0 +1,303 @@
+use std::collections::VecDeque;
+
+use futures_util::FutureExt;
+use pc_keyboard::{KeyCode, KeyState};
+
+use super::{Color, Rect, Screen, Style, Widget};
+use crate::{
+    editor::{Edit, LineEditor},
+    input::Input,
+    keyboard::Decoder,
+};
+
+const BORDER: Style = Style::NORMAL;
+const BORDER_FOCUSED: Style = Style::new(Color::Yellow, Color::Black).bright();
+
+/// A frame with a title around another widget, which it passes focus and
+/// input on to
+pub struct Pane<W> {
+    title: String,
+    content: W,
+}
+
+impl<W: Widget> Pane<W> {
+    pub fn new(title: impl Into<String>, content: W) -> Self {
+        Pane {
+            title: title.into(),
+            content,
+        }
+    }
+
+    pub fn set_title(&mut self, title: impl Into<String>) {
+        self.title = title.into();
+    }
+
+    pub fn content(&self) -> &W {
+        &self.content
+    }
+
+    pub fn content_mut(&mut self) -> &mut W {
+        &mut self.content
+    }
+}
+
+impl<W: Widget> Widget for Pane<W> {
+    fn draw(&self, screen: &mut Screen, area: Rect, focused: bool) {
+        let style = if focused { BORDER_FOCUSED } else { BORDER };
+        screen.frame(area, &self.title, style);
+        self.content.draw(screen, area.inset(1), focused);
+    }
+
+    fn handle_input(&mut self, input: &Input) -> bool {
+        self.content.handle_input(input)
+    }
+
+    fn focusable(&self) -> bool {
+        self.content.focusable()
+    }
+
+    fn copy(&self) -> Option<String> {
+        self.content.copy()
+    }
+
+    fn paste(&mut self, text: &str) -> bool {
+        self.content.paste(text)
+    }
+}
+
+/// Rows moved by PageUp and PageDown
+const PAGE: usize = 10;
+
+/// Lines of text, the newest at the bottom.
+///
+/// Follows new lines as they come. While focused the arrow keys, PageUp and
+/// PageDown scroll back, End goes back to following. Copying takes every
+/// line up to the bottom of the view.
+pub struct TextArea {
+    lines: VecDeque<String>,
+    limit: usize,
+    // Lines scrolled back from the bottom, 0 follows new lines
+    offset: usize,
+}
+
+impl TextArea {
+    /// Keeping the last `limit` lines
+    pub fn new(limit: usize) -> Self {
+        TextArea {
+            lines: VecDeque::new(),
+            limit,
+            offset: 0,
+        }
+    }
+
+    pub fn push_line(&mut self, line: impl Into<String>) {
+        if self.lines.len() == self.limit {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line.into());
+        // Keep a scrolled back view on the same lines
+        if self.offset > 0 {
+            self.offset = (self.offset + 1).min(self.lines.len());
+        }
+    }
+
+    /// Replace every line, for content that's redrawn as a whole
+    pub fn set_lines<I>(&mut self, lines: I)
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.lines.clear();
+        for line in lines {
+            self.push_line(line);
+        }
+        self.offset = self.offset.min(self.lines.len());
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.offset = 0;
+    }
+
+    fn scroll(&mut self, back: isize) {
+        self.offset = self
+            .offset
+            .saturating_add_signed(back)
+            .min(self.lines.len().saturating_sub(1));
+    }
+}
+
+impl Widget for TextArea {
+    fn draw(&self, screen: &mut Screen, area: Rect, _focused: bool) {
+        let end = self.lines.len() - self.offset;
+        let start = end.saturating_sub(area.height);
+        for (row, line) in self.lines.range(start..end).enumerate() {
+            screen.print(area.x, area.y + row, line, area.width, Style::NORMAL);
+        }
+    }
+
+    fn handle_input(&mut self, input: &Input) -> bool {
+        let Input::Key(key) = input else {
+            return false;
+        };
+        if key.state != KeyState::Down {
+            return false;
+        }
+        match key.code {
+            KeyCode::ArrowUp => self.scroll(1),
+            KeyCode::ArrowDown => self.scroll(-1),
+            KeyCode::PageUp => self.scroll(PAGE as isize),
+            KeyCode::PageDown => self.scroll(-(PAGE as isize)),
+            KeyCode::Home => self.offset = self.lines.len().saturating_sub(1),
+            KeyCode::End => self.offset = 0,
+            _ => return false,
+        }
+        true
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+
+    fn copy(&self) -> Option<String> {
+        let end = self.lines.len() - self.offset;
+        if end == 0 {
+            return None;
+        }
+        let lines: Vec<&str> = self.lines.range(..end).map(String::as_str).collect();
+        Some(lines.join("\n"))
+    }
+}
+
+const CURSOR: Style = Style::new(Color::Black, Color::White);
+
+/// A line of input edited with a [`LineEditor`], pick up finished lines
+/// with [`InputField::take_line`].
+///
+/// Tab completes if the editor has a completer with an answer straight
+/// away, otherwise it still moves the focus.
+pub struct InputField {
+    prompt: String,
+    editor: LineEditor,
+    // Keys arrive as events, the editor wants characters
+    decoder: Decoder,
+    submitted: VecDeque<String>,
+}
+
+impl InputField {
+    pub fn new(prompt: impl Into<String>) -> Self {
+        InputField {
+            prompt: prompt.into(),
+            editor: LineEditor::new(),
+            decoder: Decoder::new(),
+            submitted: VecDeque::new(),
+        }
+    }
+
+    /// For setting up history and completion
+    pub fn editor(&mut self) -> &mut LineEditor {
+        &mut self.editor
+    }
+
+    /// The oldest line entered and not taken yet
+    pub fn take_line(&mut self) -> Option<String> {
+        self.submitted.pop_front()
+    }
+}
+
+impl Widget for InputField {
+    fn draw(&self, screen: &mut Screen, area: Rect, focused: bool) {
+        let (line, _) = area.split_top(1);
+        let used = screen.print(line.x, line.y, &self.prompt, line.width, Style::NORMAL);
+        let width = line.width - used;
+        if width == 0 {
+            return;
+        }
+        // Scrolled sideways to keep the cursor in view
+        let text: Vec<char> = self.editor.text().chars().collect();
+        let cursor = self.editor.cursor();
+        let start = (cursor + 1).saturating_sub(width);
+        let visible: String = text[start..].iter().collect();
+        screen.print(line.x + used, line.y, &visible, width, Style::NORMAL);
+        if focused {
+            let under = text.get(cursor).copied().unwrap_or(' ');
+            screen.set(line.x + used + cursor - start, line.y, under, CURSOR);
+        }
+    }
+
+    fn handle_input(&mut self, input: &Input) -> bool {
+        let Input::Key(event) = input else {
+            return false;
+        };
+        let Some(key) = self.decoder.process_keyevent(event.clone()) else {
+            return false;
+        };
+        let edit = match self.editor.handle(key) {
+            // Input is handled synchronously, a completer that has to wait
+            // is given up on
+            Edit::Complete => self.editor.complete().now_or_never(),
+            edit => Some(edit),
+        };
+        match edit {
+            Some(Edit::Submit(line)) => self.submitted.push_back(line),
+            Some(Edit::Unchanged | Edit::EndOfInput | Edit::Complete) | None => return false,
+            // Nowhere to list candidates, the common part was filled in
+            Some(Edit::Changed | Edit::Candidates(_)) => {}
+        }
+        true
+    }
+
+    fn focusable(&self) -> bool {
+        true
+    }
+
+    fn copy(&self) -> Option<String> {
+        Some(self.editor.text())
+    }
+
+    fn paste(&mut self, text: &str) -> bool {
+        self.editor.insert(text);
+        true
+    }
+}
+
+const STATUS: Style = Style::new(Color::Black, Color::White);
+
+/// One line of text on the left and one on the right, in inverted colors
+pub struct StatusBar {
+    left: String,
+    right: String,
+}
+
+impl StatusBar {
+    pub fn new(left: impl Into<String>) -> Self {
+        StatusBar {
+            left: left.into(),
+            right: String::new(),
+        }
+    }
+
+    pub fn set_left(&mut self, text: impl Into<String>) {
+        self.left = text.into();
+    }
+
+    pub fn set_right(&mut self, text: impl Into<String>) {
+        self.right = text.into();
+    }
+}
+
+impl Widget for StatusBar {
+    fn draw(&self, screen: &mut Screen, area: Rect, _focused: bool) {
+        let (line, _) = area.split_top(1);
+        if line.width == 0 {
+            return;
+        }
+        screen.fill(line, ' ', STATUS);
+        let used = screen.print(line.x + 1, line.y, &self.left, line.width - 1, STATUS);
+        // The right text gives way to the left one
+        let room = line.width.saturating_sub(used + 2);
+        let right = self.right.chars().count().min(room);
+        let x = line.x + line.width - 1 - right;
+        screen.print(x, line.y, &self.right, right, STATUS);
+    }
+}
@@ -0,0 +1,123 @@
+//!
+//! VirtIO
+//!
+//! VirtIO devices are what QEMU and KVM offer guests instead of emulating
+//! real hardware. Requests go through a [`VirtQueue`] in shared memory and
+//! the device interrupts once it's done with them, so a driver submits a
+//! request and awaits its completion:
+//!
+//! ```ignore
+//! let request = queue.submit(vec![Buffer::readable(header), Buffer::writable(512)]).await?;
+//! queue.notify(&mut transport);
+//! let completed = request.await;
+//! ```
+//!
+//! Several requests can go in before one [`VirtQueue::notify`], which
+//! leaves the doorbell alone while the device is still busy with the
+//! queue, and one interrupt completes whatever the device finished. A
+//! queue whose completions nobody waits for can do without interrupts, see
+//! [`VirtQueue::set_interrupts`].
+//!
+//! How registers are reached, PCI I/O ports or MMIO, is up to a
+//! [`Transport`].
+//!
+
+mod queue;
+
+use std::fmt;
+
+pub use crate::dma::set_address_translation;
+pub use queue::{Buffer, Completed, Request, VirtQueue};
+
+/// Device type of a network card
+pub const DEVICE_NET: u32 = 1;
+/// Device type of a block device
+pub const DEVICE_BLOCK: u32 = 2;
+
+// Device status bits, set in this order while starting a device
+const STATUS_ACKNOWLEDGE: u8 = 1;
+const STATUS_DRIVER: u8 = 2;
+const STATUS_FEATURES_OK: u8 = 8;
+const STATUS_DRIVER_OK: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    /// The transport leads to a different kind of device
+    WrongDevice(u32),
+    /// The device didn't accept the features the driver picked
+    FeaturesRejected,
+    /// The device has no queue with that index
+    NoQueue(u16),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::WrongDevice(device) => write!(f, "unexpected virtio device type {}", device),
+            Error::FeaturesRejected => f.write_str("device rejected the driver's features"),
+            Error::NoQueue(index) => write!(f, "device has no queue {}", index),
+        }
+    }
+}
+
+/// The registers of one VirtIO device
+pub trait Transport: Send {
+    /// [`DEVICE_NET`], [`DEVICE_BLOCK`] and so on
+    fn device_type(&self) -> u32;
+
+    fn device_features(&mut self) -> u64;
+
+    fn set_driver_features(&mut self, features: u64);
+
+    fn status(&mut self) -> u8;
+
+    /// `0` resets the device
+    fn set_status(&mut self, status: u8);
+
+    /// Entries the device allows in queue `index`, `0` if there's no such
+    /// queue
+    fn queue_size(&mut self, index: u16) -> u16;
+
+    /// Hand queue `index` to the device. The three parts are laid out one
+    /// after the other as legacy devices expect, `descriptors` page aligned.
+    fn set_queue(&mut self, index: u16, size: u16, descriptors: u64, available: u64, used: u64);
+
+    /// Tell the device there are new requests in queue `index`
+    fn notify(&mut self, index: u16);
+
+    /// Acknowledge an interrupt, `false` if it wasn't this device's
+    fn acknowledge_interrupt(&mut self) -> bool;
+
+    /// Bytes of the device specific configuration
+    fn read_config(&mut self, offset: usize, buf: &mut [u8]);
+}
+
+/// Reset the device and agree on features: the ones in `supported` that
+/// the device offers. Queues are set up after this and before [`start`].
+pub fn negotiate(
+    transport: &mut dyn Transport,
+    device_type: u32,
+    supported: u64,
+) -> Result<u64, Error> {
+    let found = transport.device_type();
+    if found != device_type {
+        return Err(Error::WrongDevice(found));
+    }
+    transport.set_status(0);
+    transport.set_status(STATUS_ACKNOWLEDGE);
+    transport.set_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+    let features = transport.device_features() & supported;
+    transport.set_driver_features(features);
+    transport.set_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK);
+    if transport.status() & STATUS_FEATURES_OK == 0 {
+        transport.set_status(0);
+        return Err(Error::FeaturesRejected);
+    }
+    Ok(features)
+}
+
+/// Let the device go once its queues are set up
+pub fn start(transport: &mut dyn Transport) {
+    transport
+        .set_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK | STATUS_DRIVER_OK);
+}
@@ -0,0 +1,383 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    ops::Deref,
+    pin::Pin,
+    ptr,
+    sync::{
+        Arc,
+        atomic::{Ordering, fence},
+    },
+    task::{Context, Poll, Waker},
+};
+
+use super::{Error, Transport};
+use crate::{
+    bytes::{Bytes, BytesMut},
+    dma::{DmaRegion, PAGE_SIZE, physical_address},
+    metrics::Counter,
+    sync::Notify,
+};
+
+const DESCRIPTOR_SIZE: usize = 16;
+
+const DESCRIPTOR_NEXT: u16 = 1;
+const DESCRIPTOR_WRITE: u16 = 2;
+
+/// In the available ring's flags: no interrupt needed when done
+const AVAILABLE_NO_INTERRUPT: u16 = 1;
+/// In the used ring's flags: the device is still going through the queue
+const USED_NO_NOTIFY: u16 = 1;
+
+static DOORBELLS: Counter = Counter::new(
+    "virtio_doorbells_total",
+    "Times a driver told a device about new requests",
+);
+static DOORBELLS_SKIPPED: Counter = Counter::new(
+    "virtio_doorbells_skipped_total",
+    "Doorbells left out because the device was working through its queue",
+);
+
+/// One part of a request, read or written by the device. The device
+/// reaches the bytes where they are, nothing is copied in or out.
+pub enum Buffer {
+    /// Sent to the device
+    Readable(Bytes),
+    /// For the device to fill in
+    Writable(BytesMut),
+}
+
+impl Buffer {
+    pub fn readable(data: impl Into<Bytes>) -> Self {
+        Buffer::Readable(data.into())
+    }
+
+    /// `len` bytes for the device to fill in
+    pub fn writable(len: usize) -> Self {
+        Buffer::Writable(BytesMut::zeroed(len))
+    }
+
+    fn device_writes(&self) -> bool {
+        matches!(self, Buffer::Writable(_))
+    }
+
+    /// What was sent, or what the device wrote
+    pub fn into_bytes(self) -> Bytes {
+        match self {
+            Buffer::Readable(bytes) => bytes,
+            Buffer::Writable(bytes) => bytes.freeze(),
+        }
+    }
+}
+
+impl Deref for Buffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Buffer::Readable(bytes) => bytes,
+            Buffer::Writable(bytes) => bytes,
+        }
+    }
+}
+
+/// A request the device is done with
+pub struct Completed {
+    /// As submitted, with what the device wrote
+    pub buffers: Vec<Buffer>,
+    /// Bytes the device wrote into the writable buffers
+    pub written: u32,
+}
+
+/// A split virtqueue: descriptors, the ring of requests made available to
+/// the device and the ring of requests it used.
+///
+/// The queue owns a request's buffers until the device is done with them,
+/// so dropping a [`Request`] early can't leave the device writing into
+/// freed memory.
+pub struct VirtQueue {
+    index: u16,
+    size: u16,
+    memory: DmaRegion,
+    available_offset: usize,
+    used_offset: usize,
+    state: spin::Mutex<State>,
+    // Woken as requests complete and free their descriptors
+    space: Notify,
+}
+
+// The rings are only touched with `state` locked
+unsafe impl Send for VirtQueue {}
+unsafe impl Sync for VirtQueue {}
+
+struct State {
+    free: Vec<u16>,
+    next_available: u16,
+    last_used: u16,
+    // By first descriptor
+    requests: BTreeMap<u16, Slot>,
+}
+
+struct Slot {
+    buffers: Vec<Buffer>,
+    written: Option<u32>,
+    waker: Option<Waker>,
+    // Its `Request` was dropped, throw the buffers away on completion
+    abandoned: bool,
+}
+
+impl VirtQueue {
+    /// Allocate queue `index` at the size the device asks for and hand it
+    /// over. Reset the device before dropping the queue.
+    pub fn new(transport: &mut dyn Transport, index: u16) -> Result<Arc<VirtQueue>, Error> {
+        let size = transport.queue_size(index);
+        if size == 0 {
+            return Err(Error::NoQueue(index));
+        }
+        let entries = size as usize;
+        let available_offset = DESCRIPTOR_SIZE * entries;
+        // Legacy devices want the used ring on a page of its own
+        let used_offset = (available_offset + 6 + 2 * entries).next_multiple_of(PAGE_SIZE);
+        let memory = DmaRegion::new(used_offset + 6 + 8 * entries);
+
+        let base = memory.as_ptr() as usize;
+        transport.set_queue(
+            index,
+            size,
+            memory.physical_address(),
+            physical_address(base + available_offset),
+            physical_address(base + used_offset),
+        );
+        Ok(Arc::new(VirtQueue {
+            index,
+            size,
+            memory,
+            available_offset,
+            used_offset,
+            state: spin::Mutex::new(State {
+                free: (0..size).rev().collect(),
+                next_available: 0,
+                last_used: 0,
+                requests: BTreeMap::new(),
+            }),
+            space: Notify::new(),
+        }))
+    }
+
+    pub fn size(&self) -> u16 {
+        self.size
+    }
+
+    /// Tell the device about requests made available since, unless it
+    /// said it's still going through the queue and will see them anyway.
+    /// Once after a batch of requests is enough.
+    pub fn notify(&self, transport: &mut dyn Transport) {
+        // The index written by `push` before the device's flags are read
+        fence(Ordering::SeqCst);
+        if self.read::<u16>(self.used_offset) & USED_NO_NOTIFY != 0 {
+            DOORBELLS_SKIPPED.inc();
+            return;
+        }
+        DOORBELLS.inc();
+        transport.notify(self.index);
+    }
+
+    /// Whether the device should interrupt as it completes requests. Off,
+    /// the driver calls [`handle_used`](VirtQueue::handle_used) itself. A
+    /// hint only, the device may interrupt anyway.
+    pub fn set_interrupts(&self, on: bool) {
+        let flags = if on { 0 } else { AVAILABLE_NO_INTERRUPT };
+        self.write(self.available_offset, flags);
+        fence(Ordering::SeqCst);
+    }
+
+    fn read<T: Copy>(&self, offset: usize) -> T {
+        // SAFETY: offsets are within the allocation and aligned for `T`,
+        // the device writes concurrently so reads are volatile
+        unsafe { ptr::read_volatile(self.memory.as_ptr().add(offset).cast()) }
+    }
+
+    fn write<T>(&self, offset: usize, value: T) {
+        // SAFETY: as for `read`
+        unsafe { ptr::write_volatile(self.memory.as_ptr().add(offset).cast(), value) }
+    }
+
+    /// Make a request of `buffers` available to the device, waiting for
+    /// free descriptors if needed. [`Notify`](VirtQueue::notify) the device
+    /// afterwards.
+    pub async fn submit(self: &Arc<Self>, mut buffers: Vec<Buffer>) -> Request {
+        assert!(
+            !buffers.is_empty() && buffers.len() <= self.size as usize,
+            "a request needs between one buffer and the queue size"
+        );
+        loop {
+            match self.try_submit(buffers) {
+                Ok(request) => return request,
+                Err(returned) => buffers = returned,
+            }
+            self.space.notified().await;
+        }
+    }
+
+    /// Like [`VirtQueue::submit`], handing `buffers` back if there aren't
+    /// enough free descriptors right now. For drivers that can't wait, such
+    /// as a network device polled by smoltcp.
+    pub fn try_submit(self: &Arc<Self>, buffers: Vec<Buffer>) -> Result<Request, Vec<Buffer>> {
+        let head = self.push(buffers)?;
+        Ok(Request {
+            queue: self.clone(),
+            head,
+            finished: false,
+        })
+    }
+
+    /// Whether a request of `buffers` buffers fits in right now
+    pub fn has_room(&self, buffers: usize) -> bool {
+        self.state.lock().free.len() >= buffers
+    }
+
+    fn push(&self, buffers: Vec<Buffer>) -> Result<u16, Vec<Buffer>> {
+        let mut state = self.state.lock();
+        if state.free.len() < buffers.len() {
+            return Err(buffers);
+        }
+        let split = state.free.len() - buffers.len();
+        let descriptors = state.free.split_off(split);
+        for (at, (buffer, &descriptor)) in buffers.iter().zip(&descriptors).enumerate() {
+            let offset = descriptor as usize * DESCRIPTOR_SIZE;
+            let next = descriptors.get(at + 1);
+            let mut flags = if buffer.device_writes() {
+                DESCRIPTOR_WRITE
+            } else {
+                0
+            };
+            if next.is_some() {
+                flags |= DESCRIPTOR_NEXT;
+            }
+            self.write(offset, physical_address(buffer.as_ptr() as usize));
+            self.write(offset + 8, buffer.len() as u32);
+            self.write(offset + 12, flags);
+            self.write(offset + 14, next.copied().unwrap_or(0));
+        }
+        let head = descriptors[0];
+        state.requests.insert(
+            head,
+            Slot {
+                buffers,
+                written: None,
+                waker: None,
+                abandoned: false,
+            },
+        );
+
+        let ring_at = self.available_offset + 4 + 2 * (state.next_available % self.size) as usize;
+        self.write(ring_at, head);
+        state.next_available = state.next_available.wrapping_add(1);
+        // The device must see the ring entry before the index moving past it
+        fence(Ordering::SeqCst);
+        self.write(self.available_offset + 2, state.next_available);
+        fence(Ordering::SeqCst);
+        Ok(head)
+    }
+
+    /// Collect requests the device finished and wake whoever waits on them.
+    /// Call from the device's interrupt handler.
+    pub fn handle_used(&self) {
+        let mut completed = 0;
+        let mut state = self.state.lock();
+        loop {
+            let used: u16 = self.read(self.used_offset + 2);
+            if used == state.last_used {
+                break;
+            }
+            fence(Ordering::SeqCst);
+            let ring_at = self.used_offset + 4 + 8 * (state.last_used % self.size) as usize;
+            let head = self.read::<u32>(ring_at) as u16;
+            let written: u32 = self.read(ring_at + 4);
+            state.last_used = state.last_used.wrapping_add(1);
+
+            let mut descriptor = head;
+            loop {
+                state.free.push(descriptor);
+                let offset = descriptor as usize * DESCRIPTOR_SIZE;
+                if self.read::<u16>(offset + 12) & DESCRIPTOR_NEXT == 0 {
+                    break;
+                }
+                descriptor = self.read(offset + 14);
+            }
+            completed += 1;
+
+            let abandoned = match state.requests.get_mut(&head) {
+                Some(slot) if !slot.abandoned => {
+                    slot.written = Some(written);
+                    if let Some(waker) = slot.waker.take() {
+                        waker.wake();
+                    }
+                    false
+                }
+                _ => true,
+            };
+            if abandoned {
+                state.requests.remove(&head);
+            }
+        }
+        drop(state);
+        for _ in 0..completed {
+            self.space.notify_one();
+        }
+    }
+}
+
+/// Future returned by [`VirtQueue::submit`], ready once the device is done
+pub struct Request {
+    queue: Arc<VirtQueue>,
+    head: u16,
+    finished: bool,
+}
+
+impl Future for Request {
+    type Output = Completed;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Completed> {
+        let head = self.head;
+        let mut state = self.queue.state.lock();
+        let slot = state
+            .requests
+            .get_mut(&head)
+            .expect("request polled after completion");
+        match slot.written {
+            Some(written) => {
+                let slot = state.requests.remove(&head).expect("just looked it up");
+                drop(state);
+                self.finished = true;
+                Poll::Ready(Completed {
+                    buffers: slot.buffers,
+                    written,
+                })
+            }
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Drop for Request {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        let mut state = self.queue.state.lock();
+        let done = match state.requests.get_mut(&self.head) {
+            Some(slot) if slot.written.is_none() => {
+                slot.abandoned = true;
+                false
+            }
+            _ => true,
+        };
+        if done {
+            state.requests.remove(&self.head);
+        }
+    }
+}
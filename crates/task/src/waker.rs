@@ -0,0 +1,62 @@
+//!
+//! Wakers belonging to no task
+//!
+//! A [`SimpleExecutor`](crate::executor::SimpleExecutor) polls every task
+//! each round, so it hands them [`Waker::noop`]. A [`TestWaker`] counts its
+//! wakes instead, for checking a future or stream by polling it by hand:
+//!
+//! ```ignore
+//! let waker = TestWaker::new();
+//! let mut receiving = pin!(receiver.recv());
+//! assert!(receiving.as_mut().poll(&mut Context::from_waker(&waker.waker())).is_pending());
+//! sender.try_send(1).unwrap();
+//! assert_eq!(waker.wakes(), 1);
+//! ```
+//!
+
+use alloc::{sync::Arc, task::Wake};
+use core::{
+    sync::atomic::{AtomicUsize, Ordering},
+    task::Waker,
+};
+
+/// Counts the wakes of the wakers it makes
+#[derive(Debug, Default)]
+pub struct TestWaker {
+    wakes: AtomicUsize,
+}
+
+impl TestWaker {
+    pub fn new() -> Arc<TestWaker> {
+        Arc::new(TestWaker::default())
+    }
+
+    /// A waker counting its wakes, and its clones', here
+    pub fn waker(self: &Arc<Self>) -> Waker {
+        Waker::from(self.clone())
+    }
+
+    /// Wakes so far, by value or by reference
+    pub fn wakes(&self) -> usize {
+        self.wakes.load(Ordering::Acquire)
+    }
+
+    pub fn is_woken(&self) -> bool {
+        self.wakes() > 0
+    }
+
+    /// The wakes so far, counting from zero again
+    pub fn take_wakes(&self) -> usize {
+        self.wakes.swap(0, Ordering::AcqRel)
+    }
+}
+
+impl Wake for TestWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.wakes.fetch_add(1, Ordering::AcqRel);
+    }
+}
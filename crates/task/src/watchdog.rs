@@ -0,0 +1,318 @@
+//!
+//! Watchdog for a hung executor
+//!
+//! A task that blocks in `poll`, spinning on a lock or calling something
+//! synchronous that never returns, holds up every other task with it. The
+//! executor stamps the start of each poll, a checker outside the executor
+//! sees when one has been going on for longer than the timeout and calls
+//! the handler:
+//!
+//! ```ignore
+//! watchdog::enable(Duration::from_secs(5), watchdog::report);
+//! watchdog::spawn_checker();
+//! ```
+//!
+//! On the std build the checker is a host thread from [`spawn_checker`].
+//! With the `bare-metal` feature the timer interrupt checks on every tick,
+//! so there the handler must not allocate or take a lock. Idle time, the
+//! executor waiting for a wake-up, doesn't count.
+//!
+//! Watches one executor, polls on executors in other threads overlap.
+//!
+//! A task that yields properly but never gets anywhere, a driver waiting
+//! for hardware that isn't there, doesn't block anything. Giving it a time
+//! limit has the executors check it finished in time instead, and deal with
+//! it per its [`OnOverrun`]:
+//!
+//! ```ignore
+//! executor.spawn(Task::new(ahci::init()).with_time_limit(Duration::from_secs(2), OnOverrun::Abort));
+//! ```
+//!
+//! A supervisor's child gets restarted instead, see
+//! [`RestartPolicy::time_limit`](crate::supervisor::RestartPolicy::time_limit).
+//!
+
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use crate::{TaskId, executor, log, metrics::Counter, time};
+
+static STALLS: Counter = Counter::new(
+    "watchdog_stalls_total",
+    "Polls that ran past the watchdog timeout",
+);
+static OVERRUNS: Counter = Counter::new(
+    "watchdog_overruns_total",
+    "Tasks still running at the end of their time limit",
+);
+
+// In nanoseconds, zero while disabled
+static TIMEOUT: AtomicU64 = AtomicU64::new(0);
+// Uptime in nanoseconds plus one at the start of the poll going on, zero
+// between polls
+static POLL_STARTED: AtomicU64 = AtomicU64::new(0);
+static POLL_TASK: AtomicU64 = AtomicU64::new(0);
+// `POLL_STARTED` of the poll the handler was called for
+static REPORTED: AtomicU64 = AtomicU64::new(0);
+
+static HANDLER: spin::Mutex<fn(&Stall)> = spin::Mutex::new(report);
+
+/// A poll that has gone on for too long
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stall {
+    /// The task being polled
+    pub task: TaskId,
+    /// How long the poll has been going on
+    pub stalled_for: Duration,
+}
+
+impl fmt::Display for Stall {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "task {} has blocked the executor for {:?}",
+            self.task, self.stalled_for
+        )
+    }
+}
+
+/// Call `on_stall` once for every poll longer than `timeout`
+pub fn enable(timeout: Duration, on_stall: fn(&Stall)) {
+    *HANDLER.lock() = on_stall;
+    TIMEOUT.store((timeout.as_nanos() as u64).max(1), Ordering::Release);
+}
+
+pub fn disable() {
+    TIMEOUT.store(0, Ordering::Release);
+}
+
+pub fn is_enabled() -> bool {
+    TIMEOUT.load(Ordering::Acquire) != 0
+}
+
+fn now() -> u64 {
+    time::uptime().as_nanos() as u64 + 1
+}
+
+/// The executor is about to poll `task`
+pub(crate) fn poll_started(task: TaskId) {
+    if !is_enabled() {
+        return;
+    }
+    POLL_TASK.store(task.as_u64(), Ordering::Relaxed);
+    POLL_STARTED.store(now(), Ordering::Release);
+}
+
+/// The poll [`poll_started`] stamped returned
+pub(crate) fn poll_ended() {
+    let started = POLL_STARTED.swap(0, Ordering::AcqRel);
+    if started != 0 && REPORTED.load(Ordering::Acquire) == started {
+        STALLS.inc();
+        log::warn!(
+            "watchdog: task {} blocked the executor for {:?}",
+            POLL_TASK.load(Ordering::Relaxed),
+            Duration::from_nanos(now() - started)
+        );
+    }
+}
+
+/// The poll going on if it has run past the timeout, once per poll. The
+/// checker calls this and the handler with what it returns.
+pub fn check() -> Option<Stall> {
+    let timeout = TIMEOUT.load(Ordering::Acquire);
+    let started = POLL_STARTED.load(Ordering::Acquire);
+    if timeout == 0 || started == 0 || REPORTED.load(Ordering::Acquire) == started {
+        return None;
+    }
+    let stalled_for = now().saturating_sub(started);
+    if stalled_for < timeout {
+        return None;
+    }
+    let task = POLL_TASK.load(Ordering::Relaxed);
+    // The poll might have ended and another one started meanwhile
+    if POLL_STARTED.load(Ordering::Acquire) != started {
+        return None;
+    }
+    REPORTED.store(started, Ordering::Release);
+    Some(Stall {
+        task: TaskId(task),
+        stalled_for: Duration::from_nanos(stalled_for),
+    })
+}
+
+/// [`check`] and call the handler for a stall, from the timer interrupt or
+/// the checker thread
+#[cfg(any(feature = "std", all(feature = "bare-metal", target_arch = "x86_64")))]
+pub(crate) fn check_and_handle() {
+    // Skipped while `enable` is changing the handler, next time then
+    let Some(handler) = HANDLER.try_lock().map(|handler| *handler) else {
+        return;
+    };
+    if let Some(stall) = check() {
+        handler(&stall);
+    }
+}
+
+/// Say which task is stuck. The std build prints to stderr at once, the
+/// logger being a task that's held up too. Without std it's logged once
+/// the poll returns.
+pub fn report(stall: &Stall) {
+    #[cfg(feature = "std")]
+    {
+        let name = crate::executor::tasks()
+            .into_iter()
+            .find(|info| info.id == stall.task)
+            .and_then(|info| info.name);
+        match name {
+            Some(name) => eprintln!("watchdog: {} ({})", stall, name),
+            None => eprintln!("watchdog: {}", stall),
+        }
+    }
+    #[cfg(not(feature = "std"))]
+    let _ = stall;
+}
+
+/// Report the stall and start over: abort the process on the std build,
+/// reset the machine with `bare-metal`
+pub fn reset(stall: &Stall) {
+    report(stall);
+    #[cfg(feature = "std")]
+    std::process::abort();
+    #[cfg(all(not(feature = "std"), feature = "bare-metal", target_arch = "x86_64"))]
+    crate::pc::reset();
+    #[cfg(all(
+        not(feature = "std"),
+        not(all(feature = "bare-metal", target_arch = "x86_64"))
+    ))]
+    panic!("watchdog: {}", stall);
+}
+
+/// Check a few times per timeout on a host thread, for good
+#[cfg(feature = "std")]
+pub fn spawn_checker() -> std::thread::JoinHandle<()> {
+    const MIN_PERIOD: Duration = Duration::from_millis(10);
+    std::thread::Builder::new()
+        .name("watchdog".into())
+        .spawn(|| {
+            loop {
+                let timeout = Duration::from_nanos(TIMEOUT.load(Ordering::Acquire));
+                std::thread::sleep((timeout / 4).max(MIN_PERIOD));
+                check_and_handle();
+            }
+        })
+        .expect("spawning the watchdog thread")
+}
+
+/// What to do with a task still running at the end of its time limit
+#[derive(Debug, Clone, Copy)]
+pub enum OnOverrun {
+    /// Log a warning and let it go on
+    Warn,
+    /// Log an error and [`abort`](executor::abort) it
+    Abort,
+    /// Leave it to a handler, run by the executor between polls
+    Call(fn(&Overrun)),
+}
+
+/// A task that didn't finish within its time limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overrun {
+    pub task: TaskId,
+    pub limit: Duration,
+    /// Times it was polled, zero if it never got to run
+    pub polls: u64,
+}
+
+impl fmt::Display for Overrun {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "task {} still running after {:?}", self.task, self.limit)?;
+        if self.polls == 0 {
+            write!(f, ", never polled")?;
+        }
+        Ok(())
+    }
+}
+
+struct Limit {
+    limit: Duration,
+    // Uptime in nanoseconds it must have finished by
+    due: u64,
+    on_overrun: OnOverrun,
+}
+
+// Tasks with a time limit, not finished and not overrun yet
+static LIMITS: spin::Mutex<BTreeMap<TaskId, Limit>> = spin::Mutex::new(BTreeMap::new());
+// Earliest `due` in `LIMITS`, `u64::MAX` if none, so checks are cheap
+// while nothing's due
+static NEXT_DUE: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// Start the clock on `task`, which was just spawned
+pub(crate) fn limit(task: TaskId, limit: Duration, on_overrun: OnOverrun) {
+    let due = now().saturating_add(limit.as_nanos() as u64);
+    LIMITS.lock().insert(
+        task,
+        Limit {
+            limit,
+            due,
+            on_overrun,
+        },
+    );
+    NEXT_DUE.fetch_min(due, Ordering::AcqRel);
+}
+
+/// `task` finished, in time or not
+pub(crate) fn unlimit(task: TaskId) {
+    if NEXT_DUE.load(Ordering::Acquire) == u64::MAX {
+        return;
+    }
+    let mut limits = LIMITS.lock();
+    if limits.remove(&task).is_some() {
+        update_next_due(&limits);
+    }
+}
+
+fn update_next_due(limits: &BTreeMap<TaskId, Limit>) {
+    let next = limits.values().map(|limit| limit.due).min();
+    NEXT_DUE.store(next.unwrap_or(u64::MAX), Ordering::Release);
+}
+
+/// Deal with the tasks past their time limit, each once. The executors
+/// call this between polls.
+pub(crate) fn check_time_limits() {
+    let now = now();
+    if now < NEXT_DUE.load(Ordering::Acquire) {
+        return;
+    }
+    let mut overrun = Vec::new();
+    {
+        let mut limits = LIMITS.lock();
+        limits.retain(|&task, limit| {
+            if limit.due > now {
+                return true;
+            }
+            overrun.push((task, limit.limit, limit.on_overrun));
+            false
+        });
+        update_next_due(&limits);
+    }
+    for (task, limit, on_overrun) in overrun {
+        OVERRUNS.inc();
+        let overrun = Overrun {
+            task,
+            limit,
+            polls: executor::polls(task).unwrap_or(0),
+        };
+        match on_overrun {
+            OnOverrun::Warn => log::warn!("watchdog: {}", overrun),
+            OnOverrun::Abort => {
+                log::error!("watchdog: {}, aborting it", overrun);
+                executor::abort(task);
+            }
+            OnOverrun::Call(handler) => handler(&overrun),
+        }
+    }
+}